@@ -1,12 +1,16 @@
 //! [BackendBusService] implementation for Axis.
 
-use std::sync::{Arc, OnceLock};
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex, OnceLock},
+	time::SystemTime,
+};
 
 use fabricia_backend::{
 	Result,
 	bus::{
 		BACKEND_BUS_C2A_CHANNEL, BACKEND_BUS_CHANNEL, BackendBusFactory, BackendBusMessage,
-		BackendBusService, BoxedBusService, C2ABusMessage,
+		BackendBusService, BoxedBusService, C2ABusMessage, LocalBusMessage,
 	},
 	redis::{RedisError, RedisService},
 };
@@ -15,7 +19,8 @@ use futures::{
 	future::{BoxFuture, ready},
 };
 use redis::AsyncCommands;
-use tracing::{debug, error, info};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error, info, warn};
 
 use crate::AxisServices;
 
@@ -33,7 +38,7 @@ impl BackendBusService for AxisBusService {
 				.redis
 				.get()
 				.await?
-				.publish(BACKEND_BUS_CHANNEL, message.as_str())
+				.publish(self.redis.key(BACKEND_BUS_CHANNEL), message.as_str())
 				.await
 				.map_err(RedisError::RedisError)?;
 			Ok(())
@@ -54,6 +59,42 @@ impl BackendBusService for AxisBusService {
 	}
 }
 
+/// Log of recently processed C2A bus messages.
+#[derive(Debug, Default)]
+pub struct C2ALog {
+	entries: Mutex<VecDeque<C2ALogEntry>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct C2ALogEntry {
+	pub received_at: SystemTime,
+	pub message: C2ABusMessage,
+	/// Error message if the message has failed to be processed.
+	pub error: Option<String>,
+}
+
+impl C2ALog {
+	/// The maximum count of kept entries.
+	const CAPACITY: usize = 64;
+
+	fn push(&self, message: C2ABusMessage, error: Option<String>) {
+		let mut entries = self.entries.lock().unwrap();
+		if entries.len() >= Self::CAPACITY {
+			entries.pop_front();
+		}
+		entries.push_back(C2ALogEntry {
+			received_at: SystemTime::now(),
+			message,
+			error,
+		});
+	}
+
+	/// Returns recently processed messages, from the oldest to the newest.
+	pub fn entries(&self) -> Vec<C2ALogEntry> {
+		self.entries.lock().unwrap().iter().cloned().collect()
+	}
+}
+
 pub struct AxisBusFactory(pub Arc<OnceLock<AxisServices>>);
 
 impl BackendBusFactory for AxisBusFactory {
@@ -67,10 +108,37 @@ impl BackendBusFactory for AxisBusFactory {
 }
 
 pub async fn handle_bus_message(services: AxisServices) {
-	let client = services.backend.redis.make_client().await.unwrap();
+	if let Some(mut receiver) = services.backend.bus.subscribe_local() {
+		info!("subscribed to in-process backend bus");
+		loop {
+			let result = match receiver.recv().await {
+				Ok(LocalBusMessage::Backend(message)) => {
+					process_backend_bus_message(message, &services).await
+				}
+				Ok(LocalBusMessage::C2A(message)) => process_c2a_message(message, &services).await,
+				Err(RecvError::Lagged(count)) => {
+					warn!(count, "skipped lagged in-process bus messages");
+					continue;
+				}
+				Err(RecvError::Closed) => break,
+			};
+			if let Err(error) = result {
+				error!(%error, "failed to handle in-process bus message");
+			}
+		}
+		return;
+	}
+
+	let Some(redis) = &services.backend.redis else {
+		error!("neither in-process bus nor Redis is available");
+		return;
+	};
+	let client = redis.make_client().await.unwrap();
 	let mut pubsub = client.get_async_pubsub().await.unwrap();
-	pubsub.subscribe(BACKEND_BUS_CHANNEL).await.unwrap();
-	pubsub.subscribe(BACKEND_BUS_C2A_CHANNEL).await.unwrap();
+	let backend_channel = redis.key(BACKEND_BUS_CHANNEL);
+	let c2a_channel = redis.key(BACKEND_BUS_C2A_CHANNEL);
+	pubsub.subscribe(&backend_channel).await.unwrap();
+	pubsub.subscribe(&c2a_channel).await.unwrap();
 	info!("subscribed to backend bus channel");
 	while let Some(msg) = pubsub.on_message().next().await {
 		let channel = msg.get_channel_name();
@@ -83,13 +151,13 @@ pub async fn handle_bus_message(services: AxisServices) {
 			}
 		};
 		match channel {
-			BACKEND_BUS_CHANNEL => {
+			channel if channel == backend_channel => {
 				let result = handle_backend_bus_message(payload, &services).await;
 				if let Err(error) = result {
 					error!(channel, %error, "failed to handle backend bus message");
 				}
 			}
-			BACKEND_BUS_C2A_CHANNEL => {
+			channel if channel == c2a_channel => {
 				let result = handle_c2a_bus_message(payload, &services).await;
 				if let Err(error) = result {
 					error!(channel, %error, "failed to handle C2A bus message");
@@ -104,10 +172,22 @@ pub async fn handle_bus_message(services: AxisServices) {
 
 async fn handle_backend_bus_message(
 	message: String,
-	_services: &AxisServices,
+	services: &AxisServices,
 ) -> anyhow::Result<()> {
 	let message = serde_json::from_str::<BackendBusMessage>(&message)?;
+	process_backend_bus_message(message, services).await
+}
+
+async fn process_backend_bus_message(
+	message: BackendBusMessage,
+	services: &AxisServices,
+) -> anyhow::Result<()> {
 	debug!(?message, "received backend bus message");
+	match message {
+		BackendBusMessage::JobQueuePaused(false) => services.runner.notify_all(),
+		BackendBusMessage::JobQueuePaused(true) => {}
+		BackendBusMessage::JobsCancelled(jobs) => services.runner.abort(&jobs),
+	}
 	Ok(())
 }
 
@@ -121,8 +201,22 @@ async fn process_c2a_message(
 	services: &AxisServices,
 ) -> anyhow::Result<()> {
 	debug!(?message, "processing C2A bus message");
-	match message {
-		C2ABusMessage::ResumeJobRunner => services.runner.notify_one(),
-	}
-	Ok(())
+	let result = match &message {
+		C2ABusMessage::ResumeJobRunner => {
+			services.runner.notify_one();
+			Ok(())
+		}
+		C2ABusMessage::ResizeJobRunners(size) => {
+			services.supervisor.resize(*size);
+			Ok(())
+		}
+	};
+	services.c2a_log.push(
+		message,
+		result
+			.as_ref()
+			.err()
+			.map(|error: &anyhow::Error| error.to_string()),
+	);
+	result
 }
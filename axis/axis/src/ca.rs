@@ -0,0 +1,169 @@
+//! Built-in CA of the worker port.
+//!
+//! Certificates of workers and of Axis itself are issued by `fabricia-axis ca`.
+//! Workers are identified by a URI in the subject alternative names of their
+//! certificates, see [`worker_identity`].
+
+use std::{
+	fs::{self, OpenOptions},
+	io::Write,
+	os::unix::fs::OpenOptionsExt,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use rcgen::{
+	BasicConstraints, CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa,
+	KeyPair, KeyUsagePurpose, SanType,
+};
+use time::{Duration, OffsetDateTime};
+use tracing::info;
+
+/// Prefix of identities of workers in their certificates.
+pub const WORKER_IDENTITY_PREFIX: &str = "urn:fabricia:worker:";
+
+/// Returns the identity in certificates of a worker.
+pub fn worker_identity(name: &str) -> String {
+	format!("{WORKER_IDENTITY_PREFIX}{name}")
+}
+
+/// A certificate and its private key in PEM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertifiedPem {
+	pub cert: String,
+	pub key: String,
+}
+
+/// Usage of an issued certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertUsage {
+	/// Client certificate of a worker, whose name is its identity.
+	Worker,
+	/// Server certificate of the worker port, whose name is a DNS name or an IP address.
+	Server,
+}
+
+fn validity(params: &mut CertificateParams, days: u32) {
+	let now = OffsetDateTime::now_utc();
+	params.not_before = now;
+	params.not_after = now + Duration::days(days.into());
+}
+
+/// Creates a self-signed CA certificate.
+pub fn create_ca(days: u32) -> Result<CertifiedPem> {
+	let key = KeyPair::generate()?;
+	let mut params = CertificateParams::default();
+	params.distinguished_name = DistinguishedName::new();
+	params
+		.distinguished_name
+		.push(DnType::CommonName, "Fabricia worker CA");
+	params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+	params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+	validity(&mut params, days);
+	let cert = params.self_signed(&key)?;
+	Ok(CertifiedPem {
+		cert: cert.pem(),
+		key: key.serialize_pem(),
+	})
+}
+
+/// Issues a certificate signed by a CA.
+pub fn issue(ca: &CertifiedPem, name: &str, usage: CertUsage, days: u32) -> Result<CertifiedPem> {
+	let ca_key = KeyPair::from_pem(&ca.key)?;
+	let ca_cert = CertificateParams::from_ca_cert_pem(&ca.cert)?.self_signed(&ca_key)?;
+
+	let key = KeyPair::generate()?;
+	let mut params = CertificateParams::default();
+	params.distinguished_name = DistinguishedName::new();
+	params.distinguished_name.push(DnType::CommonName, name);
+	params.use_authority_key_identifier_extension = true;
+	params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+	match usage {
+		CertUsage::Worker => {
+			params.subject_alt_names = vec![SanType::URI(worker_identity(name).try_into()?)];
+			params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+		}
+		CertUsage::Server => {
+			params.subject_alt_names =
+				CertificateParams::new(vec![name.to_string()])?.subject_alt_names;
+			params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+		}
+	}
+	validity(&mut params, days);
+	let cert = params.signed_by(&key, &ca_cert, &ca_key)?;
+	Ok(CertifiedPem {
+		cert: cert.pem(),
+		key: key.serialize_pem(),
+	})
+}
+
+/// Paths of the certificate and the private key named `name` in a directory.
+fn pem_paths(dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+	(
+		dir.join(format!("{name}.pem")),
+		dir.join(format!("{name}.key")),
+	)
+}
+
+/// Writes a certificate and its private key, which is only readable by the owner.
+fn write_pem(dir: &Path, name: &str, pem: &CertifiedPem) -> Result<()> {
+	let (cert_path, key_path) = pem_paths(dir, name);
+	fs::create_dir_all(dir)?;
+	OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.mode(0o600)
+		.open(&key_path)
+		.with_context(|| format!("failed to create {key_path:?}"))?
+		.write_all(pem.key.as_bytes())?;
+	fs::write(&cert_path, &pem.cert)?;
+	info!("written {:?} and {:?}", cert_path, key_path);
+	Ok(())
+}
+
+/// Creates a CA as `ca.pem` and `ca.key` in a directory.
+pub fn init_dir(dir: &Path, days: u32) -> Result<()> {
+	let (cert_path, _) = pem_paths(dir, "ca");
+	if cert_path.exists() {
+		bail!("CA already exists in {dir:?}");
+	}
+	write_pem(dir, "ca", &create_ca(days)?)
+}
+
+/// Issues a certificate with the CA in `ca_dir`, as `{name}.pem` and `{name}.key` in `out`.
+pub fn issue_to_dir(
+	ca_dir: &Path,
+	out: &Path,
+	name: &str,
+	usage: CertUsage,
+	days: u32,
+) -> Result<()> {
+	let (cert_path, key_path) = pem_paths(ca_dir, "ca");
+	let ca = CertifiedPem {
+		cert: fs::read_to_string(&cert_path)
+			.with_context(|| format!("failed to read {cert_path:?}"))?,
+		key: fs::read_to_string(&key_path)
+			.with_context(|| format!("failed to read {key_path:?}"))?,
+	};
+	write_pem(out, name, &issue(&ca, name, usage, days)?)
+}
+
+#[cfg(test)]
+mod test {
+	use rustls::pki_types::{CertificateDer, pem::PemObject};
+
+	use super::{CertUsage, create_ca, issue, worker_identity};
+	use crate::worker::peer_identities;
+
+	#[test]
+	fn test_issue_worker() {
+		let ca = create_ca(1).unwrap();
+		let worker = issue(&ca, "runner-1", CertUsage::Worker, 1).unwrap();
+		let cert = CertificateDer::from_pem_slice(worker.cert.as_bytes()).unwrap();
+		assert_eq!(peer_identities(&cert), [worker_identity("runner-1")]);
+
+		let server = issue(&ca, "axis.example.com", CertUsage::Server, 1).unwrap();
+		let cert = CertificateDer::from_pem_slice(server.cert.as_bytes()).unwrap();
+		assert!(peer_identities(&cert).is_empty());
+	}
+}
@@ -1,15 +1,79 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use fabricia_axis_jobrunner::supervisor::RunnersConfig;
 use fabricia_backend::{
-	config::BackendConfig, db::service::DatabaseConfig, redis::RedisConfig, target::TargetConfig,
+	artifact_diff::ArtifactStoreConfig,
+	branch_scan::BranchScanConfig,
+	builder::BuilderConfig,
+	bus::BusConfig,
+	config::BackendConfig,
+	db::service::DatabaseConfig,
+	evaluator::EvaluatorConfig,
+	job_queue::JobQueueConfig,
+	lint::LintConfig,
+	mirror::MirrorConfig,
+	redis::RedisConfig,
+	repository::RepositoryConfig,
+	repro::ReproConfig,
+	secrets::SecretError,
+	security::SecurityConfig,
+	target::{TargetConfig, TargetGroupConfig},
+	upstream::UpstreamConfig,
 };
+use fabricia_common_server::listen::UnixSocketConfig;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash, Deserialize, Serialize)]
 pub struct AxisConfig {
 	pub http: HttpConfig,
 	pub database: DatabaseConfig,
-	pub redis: RedisConfig,
+	#[serde(default)]
+	pub redis: Option<RedisConfig>,
 	pub target: Vec<TargetConfig>,
-	pub runners: usize,
+	#[serde(default)]
+	pub target_group: Vec<TargetGroupConfig>,
+	#[serde(default)]
+	pub repository: Vec<RepositoryConfig>,
+	#[serde(default)]
+	pub job_queue: JobQueueConfig,
+	#[serde(default)]
+	pub lint: LintConfig,
+	#[serde(default)]
+	pub security: SecurityConfig,
+	#[serde(default)]
+	pub upstream: Option<UpstreamConfig>,
+	#[serde(default)]
+	pub mirror: Option<MirrorConfig>,
+	#[serde(default)]
+	pub evaluator: Option<EvaluatorConfig>,
+	#[serde(default)]
+	pub builder: Option<BuilderConfig>,
+	#[serde(default)]
+	pub repro: Option<ReproConfig>,
+	#[serde(default)]
+	pub artifact_store: Option<ArtifactStoreConfig>,
+	#[serde(default)]
+	pub branch_scan: Option<BranchScanConfig>,
+	#[serde(default)]
+	pub bus: BusConfig,
+	pub runners: RunnersConfig,
+	/// Port for remote workers, disabled if not set.
+	#[serde(default)]
+	pub worker: Option<WorkerPortConfig>,
+}
+
+impl AxisConfig {
+	/// Resolves secrets referenced by options, see [`fabricia_backend::secrets`].
+	pub async fn resolve_secrets(&mut self) -> Result<(), SecretError> {
+		self.database.resolve_secrets().await?;
+		if let Some(redis) = &mut self.redis {
+			redis.resolve_secrets().await?;
+		}
+		if let Some(mirror) = &mut self.mirror {
+			mirror.resolve_secrets().await?;
+		}
+		Ok(())
+	}
 }
 
 impl TryFrom<AxisConfig> for BackendConfig {
@@ -20,6 +84,19 @@ impl TryFrom<AxisConfig> for BackendConfig {
 			database: config.database,
 			redis: config.redis,
 			target: config.target,
+			target_group: config.target_group,
+			repository: config.repository,
+			job_queue: config.job_queue,
+			lint: config.lint,
+			security: config.security,
+			upstream: config.upstream,
+			mirror: config.mirror,
+			evaluator: config.evaluator,
+			builder: config.builder,
+			repro: config.repro,
+			artifact_store: config.artifact_store,
+			branch_scan: config.branch_scan,
+			bus: config.bus,
 		})
 	}
 }
@@ -31,5 +108,46 @@ pub struct HttpConfig {
 	/// Examples:
 	/// - `unix://crayon.socket`
 	/// - `tcp://127.0.0.1:8000`
+	/// - `systemd://`, for sockets passed by systemd socket activation
 	pub listen: String,
+	/// Options for listening on Unix domain sockets.
+	#[serde(flatten)]
+	pub socket: UnixSocketConfig,
+	/// Bearer token for the admin API.
+	///
+	/// The admin API is disabled if this is not set.
+	/// This option takes effect on reloading.
+	#[serde(default)]
+	pub admin_token: Option<String>,
+}
+
+/// Worker port, serving remote workers over mutual TLS, see [`crate::worker`].
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Deserialize, Serialize)]
+pub struct WorkerPortConfig {
+	/// TCP address to listen on, e.g. `0.0.0.0:8443`.
+	pub listen: SocketAddr,
+	/// PEM file of the CA certificate, which client certificates must be issued by.
+	///
+	/// Created by `fabricia-axis ca init`.
+	pub ca_cert: PathBuf,
+	/// PEM file of the server certificate, issued by `fabricia-axis ca issue --server`.
+	pub cert: PathBuf,
+	/// PEM file of the private key of the server certificate.
+	pub key: PathBuf,
+	/// Workers allowed to connect.
+	#[serde(default)]
+	pub workers: Vec<RegisteredWorker>,
+}
+
+/// A worker allowed to connect to the worker port.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Deserialize, Serialize)]
+pub struct RegisteredWorker {
+	/// Name of the worker, which is its runner name.
+	pub name: String,
+	/// Identity in the certificate of the worker.
+	///
+	/// Defaults to the identity of certificates issued by `fabricia-axis ca issue`,
+	/// see [`crate::ca::worker_identity`].
+	#[serde(default)]
+	pub identity: Option<String>,
 }
@@ -1,26 +1,77 @@
 use std::{
 	fs,
 	path::PathBuf,
-	sync::{Arc, OnceLock},
+	sync::{Arc, OnceLock, RwLock},
 };
 
-use anyhow::{Result, bail};
-use bus::AxisBusFactory;
+use anyhow::Result;
+use bus::{AxisBusFactory, C2ALog};
 use clap::Parser;
 use config::AxisConfig;
-use fabricia_axis_jobrunner::JobRunner;
-use fabricia_backend::BackendServices;
-use tokio::net::{TcpListener, UnixListener};
-use tracing::info;
+use fabricia_axis_jobrunner::{JobRunner, supervisor::RunnerSupervisor};
+use fabricia_backend::{
+	BackendServices,
+	instance::{InstanceInfo, InstanceRole},
+	preflight,
+};
+use fabricia_common_server::listen;
+use tracing::{error, info};
+use worker::{WorkerListener, WorkerPeer};
 
 mod bus;
+mod ca;
 mod config;
 mod routes;
+mod worker;
 
 #[derive(clap::Parser)]
 struct Args {
 	#[arg(short, long, default_value = "axis.toml")]
 	config: PathBuf,
+	/// Checks the configuration, connectivity and migrations, and exits.
+	///
+	/// Exits with a non-zero status if any check fails.
+	#[arg(long)]
+	check: bool,
+	#[command(subcommand)]
+	command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+	/// Manages the built-in CA of the worker port.
+	#[command(subcommand)]
+	Ca(CaCommand),
+}
+
+#[derive(clap::Subcommand)]
+enum CaCommand {
+	/// Creates a CA certificate and its private key, as `ca.pem` and `ca.key`.
+	Init {
+		/// Directory of the CA.
+		#[arg(long, default_value = "ca")]
+		dir: PathBuf,
+		/// Validity in days.
+		#[arg(long, default_value_t = 3650)]
+		days: u32,
+	},
+	/// Issues a certificate with the CA, as `{name}.pem` and `{name}.key`.
+	Issue {
+		/// Name of the worker, or the DNS name or IP address of Axis with `--server`.
+		name: String,
+		/// Issues a server certificate for the worker port.
+		#[arg(long)]
+		server: bool,
+		/// Directory of the CA.
+		#[arg(long, default_value = "ca")]
+		dir: PathBuf,
+		/// Directory to write the certificate to.
+		#[arg(long, default_value = ".")]
+		out: PathBuf,
+		/// Validity in days.
+		#[arg(long, default_value_t = 365)]
+		days: u32,
+	},
 }
 
 #[tokio::main]
@@ -33,10 +84,39 @@ async fn main() -> Result<()> {
 			.finish(),
 	)?;
 
+	if let Some(Command::Ca(command)) = args.command {
+		return match command {
+			CaCommand::Init { dir, days } => ca::init_dir(&dir, days),
+			CaCommand::Issue {
+				name,
+				server,
+				dir,
+				out,
+				days,
+			} => {
+				let usage = match server {
+					true => ca::CertUsage::Server,
+					false => ca::CertUsage::Worker,
+				};
+				ca::issue_to_dir(&dir, &out, &name, usage, days)
+			}
+		};
+	}
+
 	let config_path = &args.config;
-	let config = toml::from_str::<AxisConfig>(&fs::read_to_string(config_path)?)?;
+	let mut config = toml::from_str::<AxisConfig>(&fs::read_to_string(config_path)?)?;
+	config.resolve_secrets().await?;
 	info!("loaded configuration from file: {:?}", config_path);
 
+	if args.check {
+		let report = preflight::check(&config.clone().try_into()?).await;
+		print!("{report}");
+		if !report.passed() {
+			std::process::exit(1);
+		}
+		return Ok(());
+	}
+
 	info!("initializing backend services ...");
 	let services_ref = Arc::new(OnceLock::new());
 	let backend_services = Arc::new(
@@ -47,44 +127,57 @@ async fn main() -> Result<()> {
 		.await?,
 	);
 	info!("initializing runner service ...");
-	let runner = JobRunner::new(backend_services.clone())?;
+	let runner = Arc::new(JobRunner::new(backend_services.clone())?);
+	let supervisor = RunnerSupervisor::new(runner.clone());
+	let config = Arc::new(config);
 	let services = AxisServices {
-		config: Arc::new(config),
+		config: config.clone(),
+		config_path: Arc::new(config_path.to_owned()),
+		live_config: Arc::new(RwLock::new(config)),
 		backend: backend_services,
-		runner: Arc::new(runner),
+		runner,
+		supervisor: Arc::new(supervisor),
+		c2a_log: Arc::new(C2ALog::default()),
 	};
 	services_ref.set(services.clone()).unwrap();
 
 	tokio::spawn(bus::handle_bus_message(services.clone()));
-	for i in 0..=services.config.runners {
-		tokio::spawn(services.runner.clone().run(i));
+	let instance = InstanceInfo::new(InstanceRole::Axis, env!("CARGO_PKG_VERSION"));
+	tokio::spawn(services.backend.instance.clone().run_heartbeat(instance));
+	tokio::spawn(services.backend.upstream.clone().run_scheduler());
+	tokio::spawn(services.backend.branch_scan.clone().run_scheduler());
+	services.supervisor.start(&services.config.runners);
+
+	if let Some(worker) = &services.config.worker {
+		let listener = WorkerListener::bind(worker).await?;
+		let router = routes::make_worker_router(services.clone())
+			.into_make_service_with_connect_info::<WorkerPeer>();
+		tokio::spawn(async move {
+			if let Err(error) = axum::serve(listener, router).await {
+				error!(?error, "worker port error");
+			}
+		});
 	}
-	tokio::spawn(services.runner.clone().run_watcher(services.config.runners));
 
-	let listen_addr = services.config.http.listen.clone();
+	let listener = listen::bind(&services.config.http.listen, &services.config.http.socket)?;
 	let router = routes::make_router(services)?;
-	if let Some(path) = listen_addr.strip_prefix("unix://") {
-		let path = PathBuf::from(path);
-		_ = fs::remove_file(&path);
-		fs::create_dir_all(path.parent().unwrap())?;
-
-		let listener = UnixListener::bind(&path)?;
-		info!("listening on UDS: {:?}", path);
-		axum::serve(listener, router).await?;
-	} else if let Some(addr) = listen_addr.strip_prefix("tcp://") {
-		let listener = TcpListener::bind(addr).await?;
-		info!("listening on TCP {}", listener.local_addr()?);
-		axum::serve(listener, router).await.unwrap();
-	} else {
-		bail!("unsupported http.listen schema")
-	}
+	listen::serve(listener, router).await?;
 
 	Ok(())
 }
 
 #[derive(Debug, Clone)]
 pub struct AxisServices {
+	/// Configuration loaded on startup.
 	pub config: Arc<AxisConfig>,
+	/// Path to the configuration file.
+	pub config_path: Arc<PathBuf>,
+	/// Configuration reloaded at runtime.
+	///
+	/// Only options not bound to constructed services take effect on reloading.
+	pub live_config: Arc<RwLock<Arc<AxisConfig>>>,
 	pub backend: Arc<BackendServices>,
 	pub runner: Arc<JobRunner>,
+	pub supervisor: Arc<RunnerSupervisor>,
+	pub c2a_log: Arc<C2ALog>,
 }
@@ -0,0 +1,310 @@
+//! Admin API of Axis.
+
+use std::{collections::BTreeMap, fs, sync::Arc, time::UNIX_EPOCH};
+
+use axum::{
+	Json,
+	extract::{FromRequestParts, State},
+	http::{StatusCode, header, request::Parts},
+};
+use fabricia_axis_jobrunner::supervisor::RunnersConfig;
+use fabricia_backend::{instance::StopMode, job_queue::JobRef};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{info, warn};
+
+use crate::{AxisServices, config::AxisConfig};
+
+type AdminResult<T> = Result<T, (StatusCode, String)>;
+
+/// Extractor requiring the admin bearer token.
+pub struct AdminAuth;
+
+impl FromRequestParts<AxisServices> for AdminAuth {
+	type Rejection = (StatusCode, &'static str);
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		services: &AxisServices,
+	) -> Result<Self, Self::Rejection> {
+		let config = services.live_config.read().unwrap().clone();
+		let Some(expected) = &config.http.admin_token else {
+			return Err((StatusCode::FORBIDDEN, "admin API is disabled"));
+		};
+		let token = parts
+			.headers
+			.get(header::AUTHORIZATION)
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| value.strip_prefix("Bearer "));
+		if token.is_some_and(|token| token_matches(token, expected)) {
+			Ok(Self)
+		} else {
+			Err((StatusCode::UNAUTHORIZED, "authentication is required"))
+		}
+	}
+}
+
+/// Compares tokens by MACs, so that the time does not leak the expected token.
+fn token_matches(token: &str, expected: &str) -> bool {
+	let mac = |key: &str| {
+		Hmac::<Sha256>::new_from_slice(key.as_bytes()).map(|mac| mac.chain_update(b"admin"))
+	};
+	match (mac(token), mac(expected)) {
+		(Ok(mac), Ok(expected)) => mac.verify_slice(&expected.finalize().into_bytes()).is_ok(),
+		_ => false,
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunnerInfo {
+	/// Kind of jobs which the runner is dedicated to, or [`None`] for default runners.
+	pub kind: Option<String>,
+	pub job: Option<RunnerJobInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunnerJobInfo {
+	pub id: JobRef,
+	pub kind: String,
+	pub running_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunnersInfo {
+	pub draining: bool,
+	/// Mode of stopping, if the instance is being stopped.
+	pub stop_mode: Option<StopMode>,
+	/// Target count of runners.
+	pub size: usize,
+	/// Configuration of the pool in effect, including resizes at runtime.
+	pub config: RunnersConfig,
+	/// Count of panics caught in runners since started.
+	pub panics: u64,
+	pub runners: BTreeMap<usize, RunnerInfo>,
+}
+
+pub async fn list_runners(
+	AdminAuth: AdminAuth,
+	State(services): State<AxisServices>,
+) -> Json<RunnersInfo> {
+	let set = services.runner.set();
+	let config = set.config();
+	let runners = set
+		.status()
+		.into_iter()
+		.map(|(index, status)| {
+			let job = status.job.map(|job| RunnerJobInfo {
+				id: job.id,
+				kind: job
+					.command
+					.serialize()
+					.map(|(kind, _)| kind.to_string())
+					.unwrap_or_default(),
+				running_secs: status
+					.since
+					.map(|since| since.elapsed().as_secs())
+					.unwrap_or_default(),
+			});
+			let kind = config.pool(index).kind;
+			(index, RunnerInfo { kind, job })
+		})
+		.collect();
+	Json(RunnersInfo {
+		draining: services.runner.is_draining(),
+		stop_mode: services.runner.stop_mode(),
+		size: config.total(),
+		config,
+		panics: services.runner.panics(),
+		runners,
+	})
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResizeRunners {
+	/// Count of default runners.
+	pub size: usize,
+}
+
+pub async fn resize_runners(
+	AdminAuth: AdminAuth,
+	State(services): State<AxisServices>,
+	Json(request): Json<ResizeRunners>,
+) -> (StatusCode, &'static str) {
+	services.supervisor.resize(request.size);
+	(StatusCode::ACCEPTED, "runners resized")
+}
+
+pub async fn notify_runners(
+	AdminAuth: AdminAuth,
+	State(services): State<AxisServices>,
+) -> (StatusCode, &'static str) {
+	services.runner.notify_all();
+	(StatusCode::ACCEPTED, "runners notified")
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReloadResult {
+	/// Configuration sections which have been changed,
+	/// but only take effect after restarting.
+	pub restart_required: Vec<&'static str>,
+}
+
+pub async fn reload_config(
+	AdminAuth: AdminAuth,
+	State(services): State<AxisServices>,
+) -> AdminResult<Json<ReloadResult>> {
+	let path = services.config_path.as_ref();
+	let config = fs::read_to_string(path)
+		.map_err(|error| (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+	let mut config = toml::from_str::<AxisConfig>(&config)
+		.map_err(|error| (StatusCode::UNPROCESSABLE_ENTITY, error.to_string()))?;
+	config
+		.resolve_secrets()
+		.await
+		.map_err(|error| (StatusCode::UNPROCESSABLE_ENTITY, error.to_string()))?;
+
+	let current = &services.config;
+	let mut restart_required = vec![];
+	if config.http.listen != current.http.listen || config.http.socket != current.http.socket {
+		restart_required.push("http");
+	}
+	if config.database != current.database {
+		restart_required.push("database");
+	}
+	if config.redis != current.redis {
+		restart_required.push("redis");
+	}
+	if config.target != current.target
+		|| config.target_group != current.target_group
+		|| config.repository != current.repository
+	{
+		restart_required.push("target");
+	}
+	if config.job_queue != current.job_queue {
+		restart_required.push("job_queue");
+	}
+	if config.lint != current.lint {
+		restart_required.push("lint");
+	}
+	if config.security != current.security {
+		restart_required.push("security");
+	}
+	if config.upstream != current.upstream {
+		restart_required.push("upstream");
+	}
+	if config.bus != current.bus {
+		restart_required.push("bus");
+	}
+	// runners resized at runtime are kept, unless the configured pool is changed
+	let live = services.live_config.read().unwrap().clone();
+	if config.runners != live.runners {
+		services.supervisor.configure(&config.runners);
+	}
+	if !restart_required.is_empty() {
+		warn!(?restart_required, "reloaded configuration requires restart");
+	}
+
+	*services.live_config.write().unwrap() = Arc::new(config);
+	info!("reloaded configuration from file: {:?}", path);
+	Ok(Json(ReloadResult { restart_required }))
+}
+
+pub async fn drain(
+	AdminAuth: AdminAuth,
+	State(services): State<AxisServices>,
+) -> AdminResult<(StatusCode, &'static str)> {
+	services
+		.runner
+		.drain()
+		.await
+		.map_err(|error| (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+	Ok((StatusCode::ACCEPTED, "instance draining"))
+}
+
+pub async fn undrain(
+	AdminAuth: AdminAuth,
+	State(services): State<AxisServices>,
+) -> (StatusCode, &'static str) {
+	services.runner.undrain();
+	(StatusCode::ACCEPTED, "instance resumed")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StopRequest {
+	pub mode: StopMode,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StopResult {
+	pub mode: StopMode,
+	/// Jobs released back to the queue, which are fetched but not started by
+	/// runners, and also running ones for hard stops.
+	pub released: Vec<JobRef>,
+}
+
+/// Stops the instance from taking new jobs, before it is shut down.
+///
+/// Draining finishes running jobs, while hard stops abort and release them
+/// to other instances immediately. Jobs fetched but not started by runners
+/// are released in both modes.
+pub async fn stop(
+	AdminAuth: AdminAuth,
+	State(services): State<AxisServices>,
+	Json(request): Json<StopRequest>,
+) -> AdminResult<(StatusCode, Json<StopResult>)> {
+	let released = match request.mode {
+		StopMode::Drain => services.runner.drain().await,
+		StopMode::HardStop => services.runner.hard_stop().await,
+	}
+	.map_err(|error| (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+	Ok((
+		StatusCode::ACCEPTED,
+		Json(StopResult {
+			mode: request.mode,
+			released,
+		}),
+	))
+}
+
+#[derive(Debug, Serialize)]
+pub struct C2ALogInfo {
+	/// Received time in seconds since UNIX epoch.
+	pub received_at: u64,
+	pub message: String,
+	pub error: Option<String>,
+}
+
+pub async fn list_c2a_messages(
+	AdminAuth: AdminAuth,
+	State(services): State<AxisServices>,
+) -> Json<Vec<C2ALogInfo>> {
+	let entries = services
+		.c2a_log
+		.entries()
+		.into_iter()
+		.map(|entry| C2ALogInfo {
+			received_at: entry
+				.received_at
+				.duration_since(UNIX_EPOCH)
+				.map(|time| time.as_secs())
+				.unwrap_or_default(),
+			message: format!("{:?}", entry.message),
+			error: entry.error,
+		})
+		.collect();
+	Json(entries)
+}
+
+#[cfg(test)]
+mod test {
+	use super::token_matches;
+
+	#[test]
+	fn test_token_matches() {
+		assert!(token_matches("secret", "secret"));
+		assert!(!token_matches("secret2", "secret"));
+		assert!(!token_matches("Secret", "secret"));
+		assert!(!token_matches("", "secret"));
+	}
+}
@@ -1,14 +1,41 @@
 use anyhow::Result;
-use axum::{Router, routing::get};
+use axum::{
+	Router,
+	routing::{get, post},
+};
 
 use crate::AxisServices;
 
+mod admin;
+mod worker;
+
 pub fn make_router(services: AxisServices) -> Result<Router> {
-	let router = Router::new().route("/", get(handler)).with_state(services);
+	let router = Router::new()
+		.route("/", get(handler))
+		.route(
+			"/admin/runners",
+			get(admin::list_runners).put(admin::resize_runners),
+		)
+		.route("/admin/runners/notify", post(admin::notify_runners))
+		.route("/admin/reload", post(admin::reload_config))
+		.route("/admin/drain", post(admin::drain).delete(admin::undrain))
+		.route("/admin/stop", post(admin::stop).delete(admin::undrain))
+		.route("/admin/c2a", get(admin::list_c2a_messages))
+		.with_state(services);
 
 	Ok(router)
 }
 
+/// Creates the router of the worker port.
+///
+/// Requests are served only for registered workers, see [`crate::worker::WorkerPeer`].
+pub fn make_worker_router(services: AxisServices) -> Router {
+	Router::new()
+		.route("/", get(handler))
+		.route("/worker/v0/identity", get(worker::get_identity))
+		.with_state(services)
+}
+
 async fn handler() -> &'static str {
 	concat!("Fabricia Axis ", env!("CARGO_PKG_VERSION"))
 }
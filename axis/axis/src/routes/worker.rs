@@ -0,0 +1,17 @@
+//! API of the worker port, see [`crate::worker`].
+
+use axum::{Json, extract::ConnectInfo};
+use serde::Serialize;
+
+use crate::worker::WorkerPeer;
+
+#[derive(Debug, Serialize)]
+pub struct WorkerIdentityInfo {
+	/// Name of the registered worker of the client certificate.
+	pub name: String,
+}
+
+/// Returns the registered worker which the client is authenticated as.
+pub async fn get_identity(ConnectInfo(peer): ConnectInfo<WorkerPeer>) -> Json<WorkerIdentityInfo> {
+	Json(WorkerIdentityInfo { name: peer.name })
+}
@@ -0,0 +1,343 @@
+//! Worker port of Axis, serving remote workers over mutual TLS.
+//!
+//! Workers present client certificates issued by the [built-in CA](crate::ca),
+//! and are identified by [`WorkerPeer`]s. Connections from certificates not
+//! mapped to registered workers are closed after handshakes.
+
+use std::{
+	collections::HashMap,
+	io::{self, ErrorKind},
+	net::SocketAddr,
+	sync::Arc,
+	time::Duration,
+};
+
+use anyhow::{Context, Result};
+use axum::{extract::connect_info::Connected, serve::IncomingStream};
+use rustls::{
+	RootCertStore, ServerConfig,
+	crypto::ring::default_provider,
+	pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+	server::WebPkiClientVerifier,
+};
+use tokio::{
+	io::{AsyncRead, AsyncWrite},
+	net::{TcpListener, TcpStream},
+	sync::mpsc,
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+use tracing::{debug, info, warn};
+use webpki::EndEntityCert;
+
+use crate::{
+	ca::worker_identity,
+	config::{RegisteredWorker, WorkerPortConfig},
+};
+
+/// Timeout of TLS handshakes.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Count of connections handshaked but not served yet.
+const ACCEPT_BACKLOG: usize = 64;
+
+/// Creates the TLS configuration of the worker port.
+///
+/// Client certificates are required, and verified with the CA certificate.
+pub fn server_config(config: &WorkerPortConfig) -> Result<ServerConfig> {
+	let provider = Arc::new(default_provider());
+	let mut roots = RootCertStore::empty();
+	for cert in CertificateDer::pem_file_iter(&config.ca_cert)
+		.with_context(|| format!("failed to read {:?}", config.ca_cert))?
+	{
+		roots.add(cert?)?;
+	}
+	let verifier =
+		WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build()?;
+	let certs = CertificateDer::pem_file_iter(&config.cert)
+		.with_context(|| format!("failed to read {:?}", config.cert))?
+		.collect::<Result<Vec<_>, _>>()?;
+	let key = PrivateKeyDer::from_pem_file(&config.key)
+		.with_context(|| format!("failed to read {:?}", config.key))?;
+	let mut tls = ServerConfig::builder_with_provider(provider)
+		.with_safe_default_protocol_versions()?
+		.with_client_cert_verifier(verifier)
+		.with_single_cert(certs, key)?;
+	tls.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+	Ok(tls)
+}
+
+/// Returns identities of a worker in its certificate, see [`worker_identity`].
+pub fn peer_identities(cert: &CertificateDer) -> Vec<String> {
+	let Ok(cert) = EndEntityCert::try_from(cert) else {
+		return vec![];
+	};
+	cert.valid_uri_names()
+		.filter(|uri| uri.starts_with(crate::ca::WORKER_IDENTITY_PREFIX))
+		.map(str::to_string)
+		.collect()
+}
+
+/// Mapping from certificate identities to registered workers.
+#[derive(Debug, Clone, Default)]
+pub struct WorkerRegistry {
+	workers: HashMap<String, String>,
+}
+
+impl WorkerRegistry {
+	pub fn new(workers: &[RegisteredWorker]) -> Self {
+		let workers = workers
+			.iter()
+			.map(|worker| {
+				let identity = worker
+					.identity
+					.clone()
+					.unwrap_or_else(|| worker_identity(&worker.name));
+				(identity, worker.name.clone())
+			})
+			.collect();
+		Self { workers }
+	}
+
+	/// Returns the name of the registered worker with any of `identities`.
+	pub fn resolve(&self, identities: &[String]) -> Option<&str> {
+		identities
+			.iter()
+			.find_map(|identity| self.workers.get(identity))
+			.map(String::as_str)
+	}
+}
+
+/// A connected worker, available to handlers as [`ConnectInfo`](axum::extract::ConnectInfo).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerPeer {
+	pub addr: SocketAddr,
+	/// Name of the registered worker.
+	pub name: String,
+}
+
+impl Connected<IncomingStream<'_, WorkerListener>> for WorkerPeer {
+	fn connect_info(stream: IncomingStream<'_, WorkerListener>) -> Self {
+		stream.remote_addr().clone()
+	}
+}
+
+/// Listener of the worker port, accepting connections of registered workers.
+///
+/// Handshakes are done in background tasks, so that slow clients do not block
+/// other connections.
+#[derive(Debug)]
+pub struct WorkerListener {
+	incoming: mpsc::Receiver<(TlsStream<TcpStream>, WorkerPeer)>,
+}
+
+impl WorkerListener {
+	pub async fn bind(config: &WorkerPortConfig) -> Result<Self> {
+		let acceptor = TlsAcceptor::from(Arc::new(server_config(config)?));
+		let registry = Arc::new(WorkerRegistry::new(&config.workers));
+		let listener = TcpListener::bind(config.listen).await?;
+		info!("worker port listening on {}", listener.local_addr()?);
+		let (sender, incoming) = mpsc::channel(ACCEPT_BACKLOG);
+		tokio::spawn(async move {
+			loop {
+				let (stream, addr) = match listener.accept().await {
+					Ok(accepted) => accepted,
+					Err(error) => {
+						warn!(?error, "failed to accept worker connection");
+						tokio::time::sleep(Duration::from_secs(1)).await;
+						continue;
+					}
+				};
+				let acceptor = acceptor.clone();
+				let registry = registry.clone();
+				let sender = sender.clone();
+				tokio::spawn(async move {
+					match handshake(acceptor, &registry, stream, addr).await {
+						Ok(accepted) => {
+							let _ = sender.send(accepted).await;
+						}
+						Err(error) => debug!(%addr, %error, "rejected worker connection"),
+					}
+				});
+			}
+		});
+		Ok(Self { incoming })
+	}
+}
+
+/// Handshakes with a worker, and resolves the registered worker of its certificate.
+async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+	acceptor: TlsAcceptor,
+	registry: &WorkerRegistry,
+	stream: S,
+	addr: SocketAddr,
+) -> io::Result<(TlsStream<S>, WorkerPeer)> {
+	let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+		.await
+		.map_err(|_| io::Error::new(ErrorKind::TimedOut, "handshake has timed out"))??;
+	let identities = stream
+		.get_ref()
+		.1
+		.peer_certificates()
+		.and_then(|certs| certs.first())
+		.map(peer_identities)
+		.unwrap_or_default();
+	let Some(name) = registry.resolve(&identities) else {
+		return Err(io::Error::new(
+			ErrorKind::PermissionDenied,
+			format!("unregistered worker identities {identities:?}"),
+		));
+	};
+	info!(%addr, worker = name, "worker connected");
+	let peer = WorkerPeer {
+		addr,
+		name: name.to_string(),
+	};
+	Ok((stream, peer))
+}
+
+impl axum::serve::Listener for WorkerListener {
+	type Io = TlsStream<TcpStream>;
+	type Addr = WorkerPeer;
+
+	async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+		match self.incoming.recv().await {
+			Some(accepted) => accepted,
+			// the accepting task never exits
+			None => std::future::pending().await,
+		}
+	}
+
+	/// Not supported, as addresses of this listener are connected workers.
+	fn local_addr(&self) -> io::Result<Self::Addr> {
+		Err(io::Error::new(
+			ErrorKind::Unsupported,
+			"worker port has no local worker",
+		))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{io::ErrorKind, path::Path, sync::Arc};
+
+	use rustls::{
+		ClientConfig, RootCertStore,
+		crypto::ring::default_provider,
+		pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+	};
+	use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+	use crate::{
+		ca::{CertUsage, CertifiedPem, create_ca, issue, worker_identity},
+		config::{RegisteredWorker, WorkerPortConfig},
+		worker::{WorkerRegistry, handshake, server_config},
+	};
+
+	fn registered(name: &str) -> RegisteredWorker {
+		RegisteredWorker {
+			name: name.to_string(),
+			identity: None,
+		}
+	}
+
+	#[test]
+	fn test_registry() {
+		let registry = WorkerRegistry::new(&[
+			registered("runner-1"),
+			RegisteredWorker {
+				name: "runner-2".to_string(),
+				identity: Some("urn:fabricia:worker:legacy".to_string()),
+			},
+		]);
+		assert_eq!(
+			registry.resolve(&[worker_identity("runner-1")]),
+			Some("runner-1")
+		);
+		assert_eq!(
+			registry.resolve(&[
+				worker_identity("unknown"),
+				"urn:fabricia:worker:legacy".to_string()
+			]),
+			Some("runner-2")
+		);
+		assert_eq!(registry.resolve(&[worker_identity("runner-2")]), None);
+		assert_eq!(registry.resolve(&[]), None);
+	}
+
+	/// Connects with a client certificate, and returns the result of the server.
+	async fn connect(
+		acceptor: TlsAcceptor,
+		registry: &WorkerRegistry,
+		ca: &CertifiedPem,
+		client: Option<&CertifiedPem>,
+	) -> std::io::Result<String> {
+		let provider = Arc::new(default_provider());
+		let mut roots = RootCertStore::empty();
+		roots
+			.add(CertificateDer::from_pem_slice(ca.cert.as_bytes()).unwrap())
+			.unwrap();
+		let builder = ClientConfig::builder_with_provider(provider)
+			.with_safe_default_protocol_versions()
+			.unwrap()
+			.with_root_certificates(roots);
+		let config = match client {
+			Some(client) => builder
+				.with_client_auth_cert(
+					vec![CertificateDer::from_pem_slice(client.cert.as_bytes()).unwrap()],
+					PrivateKeyDer::from_pem_slice(client.key.as_bytes()).unwrap(),
+				)
+				.unwrap(),
+			None => builder.with_no_client_auth(),
+		};
+		let connector = TlsConnector::from(Arc::new(config));
+
+		let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+		let addr = "127.0.0.1:1".parse().unwrap();
+		let name = ServerName::try_from("localhost").unwrap();
+		let (server, _) = tokio::join!(
+			handshake(acceptor, registry, server_io, addr),
+			connector.connect(name, client_io),
+		);
+		server.map(|(_, peer)| peer.name)
+	}
+
+	#[tokio::test]
+	async fn test_handshake() {
+		let dir = std::env::temp_dir().join(format!("fabricia-test-worker-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let write = |path: &Path, data: &str| std::fs::write(path, data).unwrap();
+		let ca = create_ca(1).unwrap();
+		let server = issue(&ca, "localhost", CertUsage::Server, 1).unwrap();
+		write(&dir.join("ca.pem"), &ca.cert);
+		write(&dir.join("server.pem"), &server.cert);
+		write(&dir.join("server.key"), &server.key);
+		let config = WorkerPortConfig {
+			listen: "127.0.0.1:0".parse().unwrap(),
+			ca_cert: dir.join("ca.pem"),
+			cert: dir.join("server.pem"),
+			key: dir.join("server.key"),
+			workers: vec![registered("runner-1")],
+		};
+		let acceptor = TlsAcceptor::from(Arc::new(server_config(&config).unwrap()));
+		std::fs::remove_dir_all(&dir).unwrap();
+		let registry = WorkerRegistry::new(&config.workers);
+
+		let worker = issue(&ca, "runner-1", CertUsage::Worker, 1).unwrap();
+		let result = connect(acceptor.clone(), &registry, &ca, Some(&worker)).await;
+		assert_eq!(result.unwrap(), "runner-1");
+
+		// not registered
+		let worker = issue(&ca, "runner-2", CertUsage::Worker, 1).unwrap();
+		let result = connect(acceptor.clone(), &registry, &ca, Some(&worker)).await;
+		assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+
+		// issued by another CA
+		let other = issue(&create_ca(1).unwrap(), "runner-1", CertUsage::Worker, 1).unwrap();
+		let result = connect(acceptor.clone(), &registry, &ca, Some(&other)).await;
+		assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+
+		// without certificates
+		let result = connect(acceptor, &registry, &ca, None).await;
+		assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+	}
+}
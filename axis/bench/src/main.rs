@@ -0,0 +1,181 @@
+//! Load test of the job queue.
+//!
+//! Synthetic jobs are enqueued, claimed and finished by concurrent workers, and
+//! latencies of each phase are reported, to compare database and dispatcher
+//! backends under contention. The database must be dedicated to the benchmark.
+
+use std::{
+	fs::File,
+	io::{self, Write},
+	path::PathBuf,
+	sync::Arc,
+};
+
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use fabricia_backend::{
+	db::service::{DatabaseConfig, DatabaseService},
+	job_history::JobHistoryService,
+	job_queue::{JobQueue, JobQueueBackend, JobQueueConfig, SchedulingMode},
+	lock::LockService,
+	redis::{RedisConfig, RedisService},
+};
+use report::Report;
+use workload::{Bench, Workload};
+
+mod report;
+mod workload;
+
+#[derive(clap::Parser)]
+struct Args {
+	/// URL to the database, e.g. `sqlite://bench.db` or `postgres://host/bench`.
+	#[arg(short, long, env = "FABRICIA_BENCH_DATABASE")]
+	database: String,
+	/// URL to the Redis server, dispatching jobs with Redis Streams if set.
+	#[arg(long, env = "FABRICIA_BENCH_REDIS")]
+	redis: Option<String>,
+	/// Count of enqueued jobs.
+	#[arg(short, long, default_value_t = 1000)]
+	jobs: usize,
+	/// Count of concurrent workers, each enqueueing and claiming jobs.
+	#[arg(short, long, default_value_t = 8)]
+	workers: usize,
+	/// Count of jobs claimed in each fetch.
+	#[arg(short, long, default_value_t = 1)]
+	batch: usize,
+	/// Count of subject branches which jobs are spread over.
+	#[arg(long, default_value_t = 16)]
+	branches: i64,
+	#[arg(long, value_enum, default_value_t = Scheduling::Priority)]
+	scheduling: Scheduling,
+	#[arg(short, long, value_enum, default_value_t = Format::Csv)]
+	format: Format,
+	/// Omits the header of CSV output, for appending to results of other runs.
+	#[arg(long)]
+	no_header: bool,
+	/// File to write results to, the standard output if not set.
+	#[arg(short, long)]
+	output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Scheduling {
+	Priority,
+	Fair,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+	Csv,
+	Json,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	let args = Args::parse();
+	anyhow::ensure!(args.workers > 0, "at least one worker is required");
+
+	// the queue logs each job at the info level
+	tracing::subscriber::set_global_default(
+		tracing_subscriber::FmtSubscriber::builder()
+			.with_max_level(tracing::Level::WARN)
+			.with_writer(io::stderr)
+			.finish(),
+	)?;
+
+	let database = DatabaseConfig {
+		// in-memory databases must have exactly one connection
+		max_connections: if args.database.ends_with(":memory:") {
+			1
+		} else {
+			args.workers + 1
+		},
+		url: args.database.clone(),
+		url_file: None,
+		expand_only: false,
+	};
+	let redis = match &args.redis {
+		Some(url) => Some(Arc::new(
+			RedisService::new(&RedisConfig {
+				url: url.clone(),
+				url_file: None,
+				max_connections: args.workers + 1,
+				key_prefix: "fabricia-bench:".to_string(),
+				lock_max_wait: None,
+			})
+			.await?,
+		)),
+		None => None,
+	};
+	let lock = LockService::new(redis.clone());
+	let db = Arc::new(DatabaseService::new(&database, &lock).await?);
+	let config = JobQueueConfig {
+		backend: if redis.is_some() {
+			JobQueueBackend::RedisStreams
+		} else {
+			JobQueueBackend::Sql
+		},
+		scheduling: match args.scheduling {
+			Scheduling::Priority => SchedulingMode::Priority,
+			Scheduling::Fair => SchedulingMode::Fair,
+		},
+		..Default::default()
+	};
+	let job_queue = Arc::new(JobQueue::new(
+		db.clone(),
+		redis,
+		Arc::new(JobHistoryService::new(db.clone())),
+		&config,
+	));
+	let bench = Bench { db, job_queue };
+	bench.ensure_empty().await?;
+
+	let workload = Workload {
+		jobs: args.jobs,
+		workers: args.workers,
+		batch: args.batch.max(1),
+		branches: args.branches,
+	};
+	let (enqueue, enqueued) = bench.enqueue(workload).await?;
+	let [claim, finish] = bench.drain(workload, enqueued).await?;
+
+	let report = Report {
+		database: args
+			.database
+			.split_once("://")
+			.map_or("unknown", |(scheme, _)| scheme)
+			.to_string(),
+		backend: match config.backend {
+			JobQueueBackend::Sql => "sql",
+			JobQueueBackend::RedisStreams => "redis-streams",
+		}
+		.to_string(),
+		scheduling: format!("{:?}", args.scheduling).to_lowercase(),
+		jobs: args.jobs,
+		workers: args.workers,
+		batch: workload.batch,
+		phases: vec![enqueue, claim, finish],
+	};
+	let mut out: Box<dyn Write> = match &args.output {
+		Some(path) => Box::new(File::create(path)?),
+		None => Box::new(io::stdout()),
+	};
+	match args.format {
+		Format::Csv => report.write_csv(&mut out, !args.no_header)?,
+		Format::Json => {
+			serde_json::to_writer_pretty(&mut out, &report)?;
+			writeln!(out)?;
+		}
+	}
+
+	let duplicates = report
+		.phases
+		.iter()
+		.map(|phase| phase.duplicates)
+		.sum::<u64>();
+	anyhow::ensure!(
+		duplicates == 0,
+		"{duplicates} jobs have been claimed more than once"
+	);
+	Ok(())
+}
@@ -0,0 +1,177 @@
+use std::{io::Write, time::Duration};
+
+use serde::Serialize;
+
+/// Measurements of operations of a phase, merged from all workers.
+#[derive(Debug, Default)]
+pub struct PhaseStats {
+	pub latencies: Vec<Duration>,
+	pub errors: u64,
+	/// Fetches returning no jobs while jobs remained unclaimed.
+	pub empty_fetches: u64,
+	/// Jobs claimed by more than one worker, which must never happen.
+	pub duplicates: u64,
+}
+
+impl PhaseStats {
+	pub fn merge(&mut self, other: PhaseStats) {
+		self.latencies.extend(other.latencies);
+		self.errors += other.errors;
+		self.empty_fetches += other.empty_fetches;
+		self.duplicates += other.duplicates;
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PhaseReport {
+	pub phase: &'static str,
+	/// Count of succeeded operations.
+	pub count: usize,
+	pub errors: u64,
+	pub empty_fetches: u64,
+	pub duplicates: u64,
+	/// Wall time of the phase in seconds.
+	pub seconds: f64,
+	/// Succeeded operations per second.
+	pub throughput: f64,
+	pub p50_ms: f64,
+	pub p90_ms: f64,
+	pub p99_ms: f64,
+	pub max_ms: f64,
+}
+
+impl PhaseReport {
+	pub fn new(phase: &'static str, mut stats: PhaseStats, elapsed: Duration) -> Self {
+		stats.latencies.sort_unstable();
+		let latencies = &stats.latencies;
+		let seconds = elapsed.as_secs_f64();
+		Self {
+			phase,
+			count: latencies.len(),
+			errors: stats.errors,
+			empty_fetches: stats.empty_fetches,
+			duplicates: stats.duplicates,
+			seconds,
+			throughput: if seconds > 0.0 {
+				latencies.len() as f64 / seconds
+			} else {
+				0.0
+			},
+			p50_ms: millis(percentile(latencies, 50)),
+			p90_ms: millis(percentile(latencies, 90)),
+			p99_ms: millis(percentile(latencies, 99)),
+			max_ms: millis(latencies.last().copied().unwrap_or_default()),
+		}
+	}
+}
+
+/// Returns the nearest-rank percentile of sorted durations.
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+	if sorted.is_empty() {
+		return Duration::ZERO;
+	}
+	let rank = (sorted.len() * p).div_ceil(100).max(1);
+	sorted[rank - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+	duration.as_secs_f64() * 1000.0
+}
+
+/// Results of a run, with the parameters of the workload.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+	/// Kind of the database, e.g. `sqlite`.
+	pub database: String,
+	/// Implementation dispatching jobs, e.g. `redis-streams`.
+	pub backend: String,
+	pub scheduling: String,
+	pub jobs: usize,
+	pub workers: usize,
+	pub batch: usize,
+	pub phases: Vec<PhaseReport>,
+}
+
+impl Report {
+	/// Writes one row for each phase, with the parameters repeated in each row,
+	/// so that rows of multiple runs can be concatenated.
+	pub fn write_csv(&self, mut out: impl Write, header: bool) -> std::io::Result<()> {
+		if header {
+			writeln!(
+				out,
+				"database,backend,scheduling,jobs,workers,batch,phase,count,errors,\
+				empty_fetches,duplicates,seconds,throughput,p50_ms,p90_ms,p99_ms,max_ms"
+			)?;
+		}
+		for phase in &self.phases {
+			writeln!(
+				out,
+				"{},{},{},{},{},{},{},{},{},{},{},{:.3},{:.1},{:.3},{:.3},{:.3},{:.3}",
+				self.database,
+				self.backend,
+				self.scheduling,
+				self.jobs,
+				self.workers,
+				self.batch,
+				phase.phase,
+				phase.count,
+				phase.errors,
+				phase.empty_fetches,
+				phase.duplicates,
+				phase.seconds,
+				phase.throughput,
+				phase.p50_ms,
+				phase.p90_ms,
+				phase.p99_ms,
+				phase.max_ms,
+			)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use crate::report::{PhaseReport, PhaseStats, Report};
+
+	#[test]
+	fn test_phase_report() {
+		let stats = PhaseStats {
+			latencies: (1..=100).rev().map(Duration::from_millis).collect(),
+			errors: 2,
+			..Default::default()
+		};
+		let report = PhaseReport::new("claim", stats, Duration::from_secs(4));
+		assert_eq!(report.count, 100);
+		assert_eq!(report.throughput, 25.0);
+		assert_eq!(
+			[report.p50_ms, report.p90_ms, report.p99_ms, report.max_ms],
+			[50.0, 90.0, 99.0, 100.0]
+		);
+
+		let empty = PhaseReport::new("claim", PhaseStats::default(), Duration::ZERO);
+		assert_eq!((empty.count, empty.throughput, empty.max_ms), (0, 0.0, 0.0));
+
+		let report = Report {
+			database: "sqlite".to_string(),
+			backend: "sql".to_string(),
+			scheduling: "priority".to_string(),
+			jobs: 100,
+			workers: 4,
+			batch: 1,
+			phases: vec![report],
+		};
+		let mut csv = Vec::new();
+		report.write_csv(&mut csv, true).unwrap();
+		let csv = String::from_utf8(csv).unwrap();
+		let lines = csv.lines().collect::<Vec<_>>();
+		assert_eq!(lines.len(), 2);
+		assert_eq!(
+			lines[1],
+			"sqlite,sql,priority,100,4,1,claim,100,2,0,0,4.000,25.0,50.000,90.000,99.000,100.000"
+		);
+		assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+	}
+}
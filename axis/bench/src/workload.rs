@@ -0,0 +1,175 @@
+use std::{
+	collections::HashSet,
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicUsize, Ordering},
+	},
+	time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use fabricia_backend::{
+	db::service::DatabaseService,
+	job_queue::{JobCommand, JobQueue, JobRef, KindFilter},
+};
+use tokio::task::JoinSet;
+use tracing::warn;
+
+use crate::report::{PhaseReport, PhaseStats};
+
+/// Consecutive empty or failed fetches after which a worker gives up on remaining jobs.
+const MAX_EMPTY_FETCHES: u32 = 1000;
+
+/// Parameters of a synthetic workload.
+#[derive(Debug, Clone, Copy)]
+pub struct Workload {
+	pub jobs: usize,
+	pub workers: usize,
+	/// Count of jobs claimed in each fetch.
+	pub batch: usize,
+	/// Count of subject branches which jobs are spread over.
+	pub branches: i64,
+}
+
+/// Job queue under benchmark.
+#[derive(Debug, Clone)]
+pub struct Bench {
+	pub db: Arc<DatabaseService>,
+	pub job_queue: Arc<JobQueue>,
+}
+
+impl Bench {
+	/// Fails if the queue has jobs, which would be claimed and finished by the benchmark.
+	pub async fn ensure_empty(&self) -> Result<()> {
+		let depth = self.job_queue.depth().await?;
+		if depth.iter().any(|depth| depth.pending + depth.running > 0) {
+			bail!("job queue is not empty, run the benchmark on a dedicated database");
+		}
+		Ok(())
+	}
+
+	/// Enqueues jobs of the workload from all workers concurrently.
+	///
+	/// Returns the report of the phase, and the count of enqueued jobs.
+	pub async fn enqueue(&self, workload: Workload) -> Result<(PhaseReport, usize)> {
+		let start = Instant::now();
+		let mut tasks = JoinSet::new();
+		for worker in 0..workload.workers {
+			let bench = self.clone();
+			tasks.spawn(async move {
+				let mut stats = PhaseStats::default();
+				for n in (worker..workload.jobs).step_by(workload.workers) {
+					let branch = n as i64 % workload.branches.max(1) + 1;
+					let start = Instant::now();
+					let result = async {
+						let mut conn = bench.db.get().await?;
+						bench
+							.job_queue
+							.enqueue(&mut conn, JobCommand::SyncBranch(branch))
+							.await
+					}
+					.await;
+					match result {
+						Ok(_) => stats.latencies.push(start.elapsed()),
+						Err(error) => {
+							warn!(%error, "failed to enqueue job");
+							stats.errors += 1;
+						}
+					}
+				}
+				stats
+			});
+		}
+		let mut stats = PhaseStats::default();
+		while let Some(result) = tasks.join_next().await {
+			stats.merge(result?);
+		}
+		let enqueued = stats.latencies.len();
+		Ok((
+			PhaseReport::new("enqueue", stats, start.elapsed()),
+			enqueued,
+		))
+	}
+
+	/// Claims and finishes `total` jobs from all workers concurrently.
+	///
+	/// Returns reports of the claim phase and the finish phase, which overlap.
+	pub async fn drain(&self, workload: Workload, total: usize) -> Result<[PhaseReport; 2]> {
+		let start = Instant::now();
+		let claimed = Arc::new(AtomicUsize::new(0));
+		let seen = Arc::new(Mutex::new(HashSet::<JobRef>::with_capacity(total)));
+		let mut tasks = JoinSet::new();
+		for _ in 0..workload.workers {
+			let bench = self.clone();
+			let claimed = claimed.clone();
+			let seen = seen.clone();
+			tasks.spawn(async move {
+				let mut claim = PhaseStats::default();
+				let mut finish = PhaseStats::default();
+				let mut empty = 0;
+				while claimed.load(Ordering::SeqCst) < total && empty < MAX_EMPTY_FETCHES {
+					let start = Instant::now();
+					let jobs = match bench
+						.job_queue
+						.fetch_and_start_many(workload.batch, &KindFilter::Any)
+						.await
+					{
+						Ok(jobs) => jobs,
+						Err(error) => {
+							warn!(%error, "failed to fetch jobs");
+							claim.errors += 1;
+							Vec::new()
+						}
+					};
+					if jobs.is_empty() {
+						empty += 1;
+						if claimed.load(Ordering::SeqCst) < total {
+							claim.empty_fetches += 1;
+						}
+						tokio::time::sleep(Duration::from_millis(1)).await;
+						continue;
+					}
+					empty = 0;
+					let latency = start.elapsed();
+					claimed.fetch_add(jobs.len(), Ordering::SeqCst);
+					for job in jobs {
+						claim.latencies.push(latency);
+						if !seen.lock().unwrap().insert(job.id) {
+							claim.duplicates += 1;
+						}
+						let start = Instant::now();
+						let result = async {
+							let mut conn = bench.db.get().await?;
+							bench.job_queue.finish_job(&mut conn, job.id).await
+						}
+						.await;
+						match result {
+							Ok(()) => finish.latencies.push(start.elapsed()),
+							Err(error) => {
+								warn!(%error, id = %job.id, "failed to finish job");
+								finish.errors += 1;
+							}
+						}
+					}
+				}
+				(claim, finish)
+			});
+		}
+		let mut claim = PhaseStats::default();
+		let mut finish = PhaseStats::default();
+		while let Some(result) = tasks.join_next().await {
+			let (worker_claim, worker_finish) = result?;
+			claim.merge(worker_claim);
+			finish.merge(worker_finish);
+		}
+		let elapsed = start.elapsed();
+		let unclaimed = total.saturating_sub(claimed.load(Ordering::SeqCst));
+		if unclaimed > 0 {
+			warn!(unclaimed, "gave up on unclaimed jobs");
+		}
+		Ok([
+			PhaseReport::new("claim", claim, elapsed),
+			PhaseReport::new("finish", finish, elapsed),
+		])
+	}
+}
@@ -1,9 +1,35 @@
-use std::sync::Arc;
+use std::{
+	backtrace::BacktraceStatus,
+	collections::{BTreeMap, HashMap, VecDeque},
+	panic::AssertUnwindSafe,
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicU64, Ordering},
+	},
+	time::{Duration, Instant},
+};
 
-use anyhow::Result;
-use fabricia_backend::{BackendServices, job_queue::JobCommand};
-use tokio::sync::Notify;
-use tracing::{Instrument, debug, error, info, info_span};
+use anyhow::{Result, anyhow};
+use fabricia_backend::{
+	BackendError, BackendServices,
+	instance::StopMode,
+	job_queue::{FailureClass, FailureOutcome, Job, JobCommand, JobError, JobRef},
+	package::StatusActor,
+	trace::TraceContext,
+};
+use futures::FutureExt;
+use set::RunnerSet;
+use supervisor::{RunnerPool, RunnersConfig};
+use thiserror::Error;
+use tokio::{
+	sync::Notify,
+	task::{AbortHandle, JoinError},
+};
+use tracing::{Instrument, debug, error, info, info_span, warn};
+use uuid::Uuid;
+
+pub mod set;
+pub mod supervisor;
 
 #[derive(Debug)]
 pub struct JobRunner {
@@ -11,6 +37,26 @@ pub struct JobRunner {
 	notifier: Notify,
 	/// Backend services
 	backend: Arc<BackendServices>,
+	/// Started runners and their count.
+	set: RunnerSet,
+	/// Mode of stopping, if runners should stop fetching new jobs.
+	stop_mode: Mutex<Option<StopMode>>,
+	/// Jobs fetched in batch and not picked by a runner yet,
+	/// by dedicated kinds of pools.
+	fetched: Mutex<BTreeMap<Option<String>, VecDeque<Job>>>,
+	/// Count of panics caught in runners.
+	panics: AtomicU64,
+	/// Tasks of running jobs, by job ID.
+	tasks: Mutex<HashMap<JobRef, AbortHandle>>,
+}
+
+/// Status of a runner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunnerStatus {
+	/// The running job.
+	pub job: Option<Job>,
+	/// Time when the running job has been started.
+	pub since: Option<Instant>,
 }
 
 impl JobRunner {
@@ -18,39 +64,318 @@ impl JobRunner {
 		Ok(Self {
 			notifier: Notify::const_new(),
 			backend,
+			set: RunnerSet::default(),
+			stop_mode: Mutex::new(None),
+			fetched: Mutex::new(BTreeMap::new()),
+			panics: AtomicU64::new(0),
+			tasks: Mutex::new(HashMap::new()),
 		})
 	}
 
+	/// Returns the set of runners.
+	pub fn set(&self) -> &RunnerSet {
+		&self.set
+	}
+
+	/// Sets the stop mode, and publishes it with the instance heartbeat.
+	fn set_stop_mode(&self, mode: Option<StopMode>) {
+		*self.stop_mode.lock().unwrap() = mode;
+		self.backend.instance.set_stop_mode(mode);
+	}
+
+	/// Stops fetching new jobs. Running jobs are finished.
+	///
+	/// Jobs fetched but not picked by a runner yet are released back to the queue,
+	/// so that other instances can take them immediately. Returns the released jobs.
+	pub async fn drain(&self) -> Result<Vec<JobRef>> {
+		self.set_stop_mode(Some(StopMode::Drain));
+		let released = self.release(self.take_fetched()).await?;
+		info!(released = released.len(), "draining job runners");
+		Ok(released)
+	}
+
+	/// Stops fetching new jobs, and aborts running jobs.
+	///
+	/// Aborted jobs and jobs fetched but not picked by a runner yet are released
+	/// back to the queue, so that other instances can take them immediately.
+	/// Returns the released jobs.
+	pub async fn hard_stop(&self) -> Result<Vec<JobRef>> {
+		self.set_stop_mode(Some(StopMode::HardStop));
+		let mut jobs = self.take_fetched();
+		for (id, task) in self.tasks.lock().unwrap().iter() {
+			info!(job = %id, "aborting job on hard stop");
+			task.abort();
+			jobs.push(*id);
+		}
+		let released = self.release(jobs).await?;
+		info!(released = released.len(), "hard stopped job runners");
+		Ok(released)
+	}
+
+	/// Removes jobs fetched but not picked by a runner yet, and returns their IDs.
+	fn take_fetched(&self) -> Vec<JobRef> {
+		self.fetched
+			.lock()
+			.unwrap()
+			.values_mut()
+			.flat_map(|jobs| jobs.drain(..))
+			.map(|job| job.id)
+			.collect()
+	}
+
+	/// Releases started jobs back to the queue, and returns the released ones.
+	async fn release(&self, jobs: Vec<JobRef>) -> Result<Vec<JobRef>> {
+		if jobs.is_empty() {
+			return Ok(jobs);
+		}
+		let mut db = self.backend.database.get().await?;
+		let mut released = Vec::with_capacity(jobs.len());
+		for id in jobs {
+			// jobs finished before being aborted are not released
+			if self.backend.job_queue.release_job(&mut db, id).await? {
+				released.push(id);
+			}
+		}
+		Ok(released)
+	}
+
+	/// Resumes fetching new jobs after [`JobRunner::drain`] or [`JobRunner::hard_stop`].
+	pub fn undrain(&self) {
+		self.set_stop_mode(None);
+		info!("resumed draining job runners");
+		self.notify_all();
+	}
+
+	pub fn is_draining(&self) -> bool {
+		self.stop_mode().is_some()
+	}
+
+	pub fn stop_mode(&self) -> Option<StopMode> {
+		*self.stop_mode.lock().unwrap()
+	}
+
+	/// Moves fetched jobs of pools removed from the configuration to default runners.
+	fn reassign_fetched(&self, config: &RunnersConfig) {
+		let mut fetched = self.fetched.lock().unwrap();
+		let removed = fetched
+			.keys()
+			.flatten()
+			.filter(|kind| config.kind.get(*kind).is_none_or(|pool| pool.count == 0))
+			.cloned()
+			.collect::<Vec<_>>();
+		for kind in removed {
+			let jobs = fetched.remove(&Some(kind)).unwrap_or_default();
+			fetched.entry(None).or_default().extend(jobs);
+		}
+	}
+
+	/// Aborts running jobs which have been cancelled, e.g. of untracked branches.
+	///
+	/// Cancelled jobs have been removed from the queue, so they are neither
+	/// finished nor failed by their runners.
+	pub fn abort(&self, jobs: &[JobRef]) {
+		let tasks = self.tasks.lock().unwrap();
+		for id in jobs {
+			if let Some(task) = tasks.get(id) {
+				info!(job = %id, "aborting cancelled job");
+				task.abort();
+			}
+		}
+	}
+
+	/// Returns count of panics caught in runners since started.
+	pub fn panics(&self) -> u64 {
+		self.panics.load(Ordering::Relaxed)
+	}
+
 	#[tracing::instrument(level = "info", name = "jobrunner", skip(self))]
 	pub async fn run(self: Arc<Self>, index: usize) {
 		info!("job runner started");
 		loop {
+			if self.set.retire(index) {
+				info!("job runner stopped");
+				return;
+			}
 			self.notifier.notified().await;
 			debug!("notified to resume");
 
-			let result = async {
-				while let Some(job) = self.backend.job_queue.fetch_and_start().await? {
+			let result = AssertUnwindSafe(async {
+				while !self.set.is_retiring(index) {
+					let pool = self.set.pool(index);
+					// jobs fetched by batches in flight when draining have been started,
+					// so run them even when draining
+					let fetched = self
+						.fetched
+						.lock()
+						.unwrap()
+						.get_mut(&pool.kind)
+						.and_then(VecDeque::pop_front);
+					let job = match fetched {
+						Some(job) => job,
+						None if self.is_draining() => break,
+						None => match self.fetch_batch(&pool).await? {
+							Some(job) => job,
+							None => break,
+						},
+					};
+					self.set.set_status(index, Some(job.clone()));
+					// jobs are executed in their own tasks, so that panics only fail the job
+					let runner = self.clone();
+					let id = job.id;
+					let command = job.command.clone();
+					// continue the trace of the request which has enqueued the job
+					let trace = job
+						.trace
+						.map(|trace| trace.child())
+						.unwrap_or_else(TraceContext::new_root);
+					let span = info_span!(
+						"execute job",
+						job = %job.id,
+						trace_id = trace.trace_id_hex(),
+					);
+					let mut task = tokio::spawn(
+						trace
+							.scope(async move { runner.exec(id, command).await })
+							.instrument(span),
+					);
+					self.tasks
+						.lock()
+						.unwrap()
+						.insert(job.id, task.abort_handle());
+					let timeout = job
+						.command
+						.serialize()
+						.ok()
+						.and_then(|(kind, _)| self.backend.config.job_queue.timeout(&kind));
+					let joined = match timeout {
+						Some(timeout) => match tokio::time::timeout(timeout, &mut task).await {
+							Ok(joined) => Some(joined),
+							Err(_) => {
+								task.abort();
+								None
+							}
+						},
+						None => Some(task.await),
+					};
+					self.tasks.lock().unwrap().remove(&job.id);
+					let result = match joined {
+						None => Err(JobTimeout(timeout.unwrap_or_default()).into()),
+						Some(Ok(result)) => result,
+						Some(Err(error)) if error.is_cancelled() => {
+							info!(job = %job.id, "job aborted");
+							self.set.set_status(index, None);
+							continue;
+						}
+						Some(Err(error)) => {
+							self.panics.fetch_add(1, Ordering::Relaxed);
+							let message = panic_message(error);
+							error!(job = %job.id, message, "job panicked");
+							Err(anyhow!("job panicked: {message}"))
+						}
+					};
 					let mut db = self.backend.database.get().await?;
-					self.exec(job.command)
-						.instrument(info_span!("execute job", job = %job.id))
-						.await?;
-					self.backend.job_queue.finish_job(&mut db, job.id).await?;
+					match result {
+						Ok(()) => self.backend.job_queue.finish_job(&mut db, job.id).await?,
+						Err(error) => {
+							let class = classify_error(&error);
+							let job_error = job_error(&error, class);
+							warn!(
+								job = %job.id,
+								?class,
+								backtrace = ?job_error.backtrace,
+								?error,
+								"job failed"
+							);
+							let outcome = self
+								.backend
+								.job_queue
+								.fail_job(&mut db, job.id, &job_error)
+								.await?;
+							if outcome == FailureOutcome::Dropped {
+								self.on_failed(job.id, job.command, &error).await?;
+							}
+						}
+					}
+					self.set.set_status(index, None);
 				}
 				Ok::<_, anyhow::Error>(())
-			}
+			})
+			.catch_unwind()
 			.await;
-			if let Err(error) = result {
-				error!(?error, "job runner error")
+			self.set.set_status(index, None);
+			match result {
+				Ok(Ok(())) => {}
+				Ok(Err(error)) => error!(?error, "job runner error"),
+				Err(_) => {
+					// the panic has been reported by the panic hook
+					self.panics.fetch_add(1, Ordering::Relaxed);
+					error!("job runner panicked, restarting");
+				}
+			}
+		}
+	}
+
+	/// Fetches jobs for all idle runners of a pool, and returns one for the current runner.
+	///
+	/// Other runners are notified to pick the rest. Runners dedicated to a kind
+	/// without pending jobs may take a job of another kind,
+	/// see [`supervisor::StealPolicy`].
+	async fn fetch_batch(&self, pool: &RunnerPool) -> Result<Option<Job>> {
+		let (n, kinds) = self.set.batch(pool);
+		let mut jobs = self
+			.backend
+			.job_queue
+			.fetch_and_start_many(n, &kinds)
+			.await?;
+		let steal = match jobs.is_empty() {
+			true => self.set.steal(pool),
+			false => None,
+		};
+		if let Some(kinds) = steal {
+			jobs = self
+				.backend
+				.job_queue
+				.fetch_and_start_many(1, &kinds)
+				.await?;
+			if let Some(job) = jobs.first() {
+				debug!(job = %job.id, kind = ?pool.kind, "taken job of another kind");
 			}
 		}
+		let mut jobs = jobs.into_iter();
+		let job = jobs.next();
+		let rest = jobs.collect::<Vec<_>>();
+		if !rest.is_empty() {
+			debug!(count = rest.len(), kind = ?pool.kind, "fetched jobs for other runners");
+			let count = rest.len();
+			self.fetched
+				.lock()
+				.unwrap()
+				.entry(pool.kind.clone())
+				.or_default()
+				.extend(rest);
+			let config = self.set.config();
+			if config.total() == config.default {
+				for _ in 0..count {
+					self.notify_one();
+				}
+			} else {
+				// runners of other pools may be notified, so wake all of them
+				self.notify_all();
+			}
+		}
+		Ok(job)
 	}
 
 	#[tracing::instrument(level = "debug", name = "job_watcher", skip(self))]
-	pub async fn run_watcher(self: Arc<Self>, runners: usize) {
+	pub async fn run_watcher(self: Arc<Self>) {
 		info!("job watcher started");
 		loop {
 			let result = async {
-				let count = self.backend.job_queue.count_pending(runners).await?;
+				let count = self
+					.backend
+					.job_queue
+					.count_pending(self.set.size())
+					.await?;
 				for _ in 0..count {
 					self.notify_one();
 				}
@@ -74,10 +399,337 @@ impl JobRunner {
 	}
 
 	/// Runs a job command.
-	async fn exec(&self, job: JobCommand) -> Result<()> {
+	async fn exec(&self, id: JobRef, job: JobCommand) -> Result<()> {
 		match job {
 			JobCommand::SyncBranch(branch) => todo!(),
+			JobCommand::LintPackage { package, .. } => {
+				self.backend.lint.run(package).await?;
+			}
+			JobCommand::IngestAdvisories => {
+				self.backend.security.ingest().await?;
+				self.backend.security.flag_all().await?;
+			}
+			JobCommand::RefreshUpstream => {
+				self.backend.upstream.refresh().await?;
+			}
+			JobCommand::ScanBranches => {
+				self.backend.branch_scan.scan().await?;
+			}
+			JobCommand::PrefetchSources { package, .. } => {
+				self.backend.mirror.prefetch(package).await?;
+			}
+			JobCommand::VerifyBuild { build, builder, .. } => {
+				let runner = self.backend.config.job_queue.runner.as_deref();
+				// fail before building on the runner of the verified build
+				let runner = self.backend.repro.check_runner(&builder, runner)?;
+				let hashes = self.backend.builder.run(id, &build).await?;
+				self.backend
+					.repro
+					.verify(&build, id, runner, &hashes)
+					.await?;
+			}
+			JobCommand::DiffArtifacts { build, against } => {
+				self.backend.artifact_diff.run(build, against).await?;
+			}
+			JobCommand::BuildPackage { build, .. } => {
+				let hashes = self.backend.builder.run(id, &build).await?;
+				let artifacts = hashes.keys().cloned().collect();
+				self.backend
+					.build_cache
+					.record(&build, id, artifacts)
+					.await?;
+				let runner = self.backend.config.job_queue.runner.as_deref();
+				self.backend
+					.repro
+					.record_artifacts(&build, runner, hashes)
+					.await?;
+			}
 		}
 		Ok(())
 	}
+
+	/// Handles a job which has failed permanently.
+	async fn on_failed(&self, id: JobRef, job: JobCommand, error: &anyhow::Error) -> Result<()> {
+		match job {
+			JobCommand::SyncBranch(branch) => {
+				self.backend
+					.branch
+					.mark_error(
+						branch,
+						&format!("sync failed: {error}"),
+						&StatusActor::Job(id),
+					)
+					.await?
+			}
+			// results of the last successful run are kept
+			JobCommand::LintPackage { .. }
+			| JobCommand::IngestAdvisories
+			| JobCommand::RefreshUpstream
+			| JobCommand::ScanBranches => {}
+			// builds fall back to fetching sources from upstream
+			JobCommand::PrefetchSources { .. } => {}
+			JobCommand::VerifyBuild { build, .. } => self.backend.repro.cancel(&build).await?,
+			JobCommand::DiffArtifacts { build, against } => {
+				self.backend
+					.artifact_diff
+					.record(build, against, Err(format!("comparison failed: {error}")))
+					.await?
+			}
+			JobCommand::BuildPackage { build, .. } => {
+				self.backend.build_cache.record_failure(&build, id).await?
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Returns the message of a panicked job task.
+fn panic_message(error: JoinError) -> String {
+	if !error.is_panic() {
+		return error.to_string();
+	}
+	let panic = error.into_panic();
+	if let Some(message) = panic.downcast_ref::<&str>() {
+		message.to_string()
+	} else if let Some(message) = panic.downcast_ref::<String>() {
+		message.clone()
+	} else {
+		"unknown panic".to_string()
+	}
+}
+
+/// Error of a job running longer than its timeout.
+#[derive(Debug, Error)]
+#[error("job timed out after {0:?}")]
+struct JobTimeout(Duration);
+
+/// Converts an error returned by a job into a [`JobError`].
+///
+/// If a backtrace has been captured, it is logged with a new ID
+/// which is referred by the job error.
+fn job_error(error: &anyhow::Error, class: FailureClass) -> JobError {
+	let mut job_error = JobError::new(class, format!("{error:#}"));
+	let backtrace = error.backtrace();
+	if backtrace.status() == BacktraceStatus::Captured {
+		let id = Uuid::now_v7();
+		error!(%id, %backtrace, "backtrace of job error");
+		job_error.backtrace = Some(id);
+	}
+	job_error
+}
+
+/// Classifies an error returned by a job.
+///
+/// Errors not known to be transient are considered as permanent.
+fn classify_error(error: &anyhow::Error) -> FailureClass {
+	for cause in error.chain() {
+		if cause.is::<JobTimeout>() {
+			return FailureClass::Timeout;
+		}
+		if let Some(error) = cause.downcast_ref::<BackendError>() {
+			return error.failure_class();
+		}
+		if let Some(error) = cause.downcast_ref::<std::io::Error>() {
+			use std::io::ErrorKind;
+			if matches!(
+				error.kind(),
+				ErrorKind::ConnectionRefused
+					| ErrorKind::ConnectionReset
+					| ErrorKind::ConnectionAborted
+					| ErrorKind::NotConnected
+					| ErrorKind::BrokenPipe
+					| ErrorKind::TimedOut
+					| ErrorKind::Interrupted
+					| ErrorKind::OutOfMemory
+			) {
+				return FailureClass::Transient;
+			}
+		}
+	}
+	FailureClass::Permanent
+}
+
+#[cfg(test)]
+mod test {
+	use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+	use fabricia_backend::{
+		instance::StopMode,
+		job_queue::{Job, JobCommand, JobRef, KindFilter},
+		test::test_env,
+	};
+
+	use crate::{
+		JobRunner,
+		supervisor::{KindRunnersConfig, RunnerSupervisor, RunnersConfig, StealPolicy},
+	};
+
+	/// Creates a runner on backend services for tests, without starting runners.
+	async fn test_runner() -> Arc<JobRunner> {
+		Arc::new(JobRunner::new(Arc::new(test_env().await)).unwrap())
+	}
+
+	async fn enqueue(runner: &JobRunner, job: JobCommand) -> JobRef {
+		let mut db = runner.backend.database.get().await.unwrap();
+		runner
+			.backend
+			.job_queue
+			.enqueue(&mut db, job)
+			.await
+			.unwrap()
+	}
+
+	/// Fetches and starts pending jobs, as if fetched by a batch.
+	async fn start(runner: &JobRunner, n: usize) -> Vec<Job> {
+		runner
+			.backend
+			.job_queue
+			.fetch_and_start_many(n, &KindFilter::Any)
+			.await
+			.unwrap()
+	}
+
+	fn sorted_ids<'a>(jobs: impl IntoIterator<Item = &'a Job>) -> Vec<JobRef> {
+		let mut ids = jobs.into_iter().map(|job| job.id).collect::<Vec<_>>();
+		ids.sort();
+		ids
+	}
+
+	/// Waits until runners with indices beyond the target count have stopped.
+	async fn wait_retired(runner: &JobRunner) {
+		for _ in 0..100 {
+			if runner.set.status().len() == runner.set.size() {
+				return;
+			}
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+		panic!("runners have not stopped");
+	}
+
+	fn indices(runner: &JobRunner) -> Vec<usize> {
+		runner.set.status().into_keys().collect()
+	}
+
+	#[tokio::test]
+	async fn test_resize() {
+		let runner = test_runner().await;
+		let supervisor = RunnerSupervisor::new(runner.clone());
+		supervisor.configure(&RunnersConfig {
+			default: 2,
+			..Default::default()
+		});
+		// exactly the configured count of runners is started
+		assert_eq!(supervisor.size(), 2);
+		assert_eq!(indices(&runner), [0, 1]);
+
+		supervisor.resize(4);
+		assert_eq!(supervisor.size(), 4);
+		assert_eq!(indices(&runner), [0, 1, 2, 3]);
+
+		supervisor.resize(1);
+		assert_eq!(supervisor.size(), 1);
+		wait_retired(&runner).await;
+		assert_eq!(indices(&runner), [0]);
+	}
+
+	#[tokio::test]
+	async fn test_steal() {
+		let runner = test_runner().await;
+		let mut config = RunnersConfig::default();
+		config.kind.insert(
+			"SyncBranch".to_string(),
+			KindRunnersConfig {
+				count: 1,
+				weight: None,
+				steal: StealPolicy::Never,
+			},
+		);
+		// runners are registered, but not started
+		runner.set.configure(&config);
+		let pool = runner.set.pool(0);
+		assert_eq!(pool.kind.as_deref(), Some("SyncBranch"));
+
+		let job = enqueue(&runner, JobCommand::ScanBranches).await;
+		assert!(runner.fetch_batch(&pool).await.unwrap().is_none());
+
+		config.kind.get_mut("SyncBranch").unwrap().steal =
+			StealPolicy::Only(vec!["RefreshUpstream".to_string()]);
+		runner.set.configure(&config);
+		assert!(runner.fetch_batch(&pool).await.unwrap().is_none());
+
+		config.kind.get_mut("SyncBranch").unwrap().steal = StealPolicy::Any;
+		runner.set.configure(&config);
+		let taken = runner.fetch_batch(&pool).await.unwrap().unwrap();
+		assert_eq!(taken.id, job);
+		// only one job is taken at a time
+		assert!(runner.fetched.lock().unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_drain() {
+		let runner = test_runner().await;
+		enqueue(&runner, JobCommand::ScanBranches).await;
+		enqueue(&runner, JobCommand::RefreshUpstream).await;
+		// the first job is running, and the other is waiting for a runner
+		let mut jobs = start(&runner, 2).await;
+		let fetched = jobs.pop().unwrap();
+		runner
+			.fetched
+			.lock()
+			.unwrap()
+			.entry(None)
+			.or_default()
+			.push_back(fetched.clone());
+
+		assert_eq!(runner.drain().await.unwrap(), [fetched.id]);
+		assert_eq!(runner.stop_mode(), Some(StopMode::Drain));
+		assert!(
+			runner
+				.fetched
+				.lock()
+				.unwrap()
+				.values()
+				.all(VecDeque::is_empty)
+		);
+		// the running job is left to its runner
+		assert_eq!(sorted_ids(&start(&runner, 2).await), [fetched.id]);
+	}
+
+	#[tokio::test]
+	async fn test_hard_stop() {
+		let runner = test_runner().await;
+		enqueue(&runner, JobCommand::ScanBranches).await;
+		enqueue(&runner, JobCommand::RefreshUpstream).await;
+		let jobs = start(&runner, 2).await;
+		let (running, fetched) = (&jobs[0], &jobs[1]);
+		runner
+			.fetched
+			.lock()
+			.unwrap()
+			.entry(None)
+			.or_default()
+			.push_back(fetched.clone());
+		let task = tokio::spawn(std::future::pending::<()>());
+		runner
+			.tasks
+			.lock()
+			.unwrap()
+			.insert(running.id, task.abort_handle());
+
+		let mut released = runner.hard_stop().await.unwrap();
+		released.sort();
+		assert_eq!(released, sorted_ids(&jobs));
+		assert_eq!(runner.stop_mode(), Some(StopMode::HardStop));
+		assert!(task.await.unwrap_err().is_cancelled());
+		assert!(
+			runner
+				.fetched
+				.lock()
+				.unwrap()
+				.values()
+				.all(VecDeque::is_empty)
+		);
+		// both jobs are pending again
+		assert_eq!(sorted_ids(&start(&runner, 2).await), sorted_ids(&jobs));
+	}
 }
@@ -0,0 +1,146 @@
+//! Set of runners of an instance.
+
+use std::{collections::BTreeMap, sync::Mutex, time::Instant};
+
+use fabricia_backend::job_queue::{Job, KindFilter};
+
+use crate::{
+	RunnerStatus,
+	supervisor::{RunnerPool, RunnersConfig},
+};
+
+/// Runners of an instance, by index.
+///
+/// The set is the only owner of the count of runners. Runners are started for
+/// indices below [`RunnerSet::size`], and stop after finishing their jobs when
+/// their indices are no longer below it.
+#[derive(Debug, Default)]
+pub struct RunnerSet {
+	inner: Mutex<RunnerSetInner>,
+}
+
+#[derive(Debug, Default)]
+struct RunnerSetInner {
+	config: RunnersConfig,
+	/// Status of started runners, by runner index.
+	runners: BTreeMap<usize, RunnerStatus>,
+}
+
+impl RunnerSet {
+	/// Returns the configuration in effect, including resizes at runtime.
+	pub fn config(&self) -> RunnersConfig {
+		self.inner.lock().unwrap().config.clone()
+	}
+
+	/// Returns the target count of runners.
+	pub fn size(&self) -> usize {
+		self.inner.lock().unwrap().config.total()
+	}
+
+	/// Returns the pool of the runner of an index.
+	pub fn pool(&self, index: usize) -> RunnerPool {
+		self.inner.lock().unwrap().config.pool(index)
+	}
+
+	/// Returns status of all started runners, by runner index.
+	pub fn status(&self) -> BTreeMap<usize, RunnerStatus> {
+		self.inner.lock().unwrap().runners.clone()
+	}
+
+	pub(crate) fn set_status(&self, index: usize, job: Option<Job>) {
+		let since = job.as_ref().map(|_| Instant::now());
+		self.inner
+			.lock()
+			.unwrap()
+			.runners
+			.insert(index, RunnerStatus { job, since });
+	}
+
+	/// Returns whether a runner should stop.
+	pub(crate) fn is_retiring(&self, index: usize) -> bool {
+		index >= self.size()
+	}
+
+	/// Removes a runner if it should stop.
+	///
+	/// This is checked with the lock held, so that
+	/// [`RunnerSet::configure`] never misses a stopping runner.
+	pub(crate) fn retire(&self, index: usize) -> bool {
+		let mut inner = self.inner.lock().unwrap();
+		if index >= inner.config.total() {
+			inner.runners.remove(&index);
+			true
+		} else {
+			false
+		}
+	}
+
+	/// Replaces the configuration, and returns indices of runners to be started.
+	pub(crate) fn configure(&self, config: &RunnersConfig) -> Vec<usize> {
+		let mut inner = self.inner.lock().unwrap();
+		inner.config = config.clone();
+		inner.register()
+	}
+
+	/// Sets the count of default runners, and returns indices of runners to be started.
+	pub(crate) fn resize(&self, size: usize) -> Vec<usize> {
+		let mut inner = self.inner.lock().unwrap();
+		inner.config.default = size;
+		inner.register()
+	}
+
+	/// Returns the count of jobs to be fetched for idle runners of a pool,
+	/// and the kinds which they may run.
+	pub(crate) fn batch(&self, pool: &RunnerPool) -> (usize, KindFilter) {
+		let inner = self.inner.lock().unwrap();
+		let runners = inner.runners.range(pool.indices.clone());
+		let idle = runners
+			.clone()
+			.filter(|(_, status)| status.job.is_none())
+			.count()
+			.max(1);
+		match &pool.kind {
+			Some(kind) => (idle, KindFilter::Only(vec![kind.clone()])),
+			None => {
+				let mut running = BTreeMap::new();
+				for job in runners.filter_map(|(_, status)| status.job.as_ref()) {
+					if let Ok((kind, _)) = job.command.serialize() {
+						*running.entry(kind.to_string()).or_default() += 1;
+					}
+				}
+				inner.config.default_kinds(&running, idle)
+			}
+		}
+	}
+
+	/// Returns the kinds of jobs which idle runners of a pool may take,
+	/// when no jobs of their own kind are pending,
+	/// see [`crate::supervisor::StealPolicy`].
+	///
+	/// Default runners run jobs of all kinds allowed to them, so they never take others.
+	pub(crate) fn steal(&self, pool: &RunnerPool) -> Option<KindFilter> {
+		let kind = pool.kind.as_ref()?;
+		let inner = self.inner.lock().unwrap();
+		inner.config.kind.get(kind)?.steal.kinds()
+	}
+}
+
+impl RunnerSetInner {
+	/// Registers idle runners for missing indices below the target count,
+	/// and returns their indices.
+	fn register(&mut self) -> Vec<usize> {
+		let missing = (0..self.config.total())
+			.filter(|index| !self.runners.contains_key(index))
+			.collect::<Vec<_>>();
+		for index in &missing {
+			self.runners.insert(
+				*index,
+				RunnerStatus {
+					job: None,
+					since: None,
+				},
+			);
+		}
+		missing
+	}
+}
@@ -0,0 +1,430 @@
+//! Supervision of the runner pool.
+
+use std::{collections::BTreeMap, ops::Range, sync::Arc};
+
+use fabricia_backend::job_queue::KindFilter;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::JobRunner;
+
+/// Configuration of the runner pool.
+///
+/// Runners dedicated to job kinds are indexed first, followed by default runners.
+/// A count of default runners is accepted for compatibility, e.g. `runners = 4`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", from = "RunnersConfigRepr")]
+pub struct RunnersConfig {
+	/// Count of runners of jobs of any kind without dedicated runners.
+	pub default: usize,
+	/// Dedicated runners and weights, by job kind, e.g. `SyncBranch`.
+	pub kind: BTreeMap<String, KindRunnersConfig>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct KindRunnersConfig {
+	/// Count of runners dedicated to jobs of this kind.
+	///
+	/// Jobs of kinds with dedicated runners are not run by default runners.
+	#[serde(default)]
+	pub count: usize,
+	/// Weight of this kind in sharing default runners.
+	///
+	/// Jobs of a kind with a weight occupy at most its proportion of default
+	/// runners among all weights, and a weight of `0` keeps them from default
+	/// runners. Jobs of kinds without weights may occupy all default runners.
+	#[serde(default)]
+	pub weight: Option<u32>,
+	/// Jobs of other kinds which idle runners of this kind may take, when no jobs
+	/// of this kind are pending.
+	#[serde(default)]
+	pub steal: StealPolicy,
+}
+
+/// Policy of dedicated runners taking jobs of other kinds, e.g. `steal = "any"`
+/// or `steal = { only = ["LintPackage"] }`.
+///
+/// Jobs are taken one at a time, so that runners return to their own kind as
+/// soon as they finish a taken job.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StealPolicy {
+	/// Runners only run jobs of their own kind.
+	#[default]
+	Never,
+	/// Runners may take jobs of any kind.
+	Any,
+	/// Runners may take jobs of these kinds.
+	Only(Vec<String>),
+}
+
+impl StealPolicy {
+	/// Returns the kinds of jobs which may be taken, or [`None`] if none may be.
+	pub(crate) fn kinds(&self) -> Option<KindFilter> {
+		match self {
+			StealPolicy::Never => None,
+			StealPolicy::Any => Some(KindFilter::Any),
+			StealPolicy::Only(kinds) if kinds.is_empty() => None,
+			StealPolicy::Only(kinds) => Some(KindFilter::Only(kinds.clone())),
+		}
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RunnersConfigRepr {
+	Count(usize),
+	#[serde(rename_all = "kebab-case")]
+	Pools {
+		#[serde(default)]
+		default: usize,
+		#[serde(default)]
+		kind: BTreeMap<String, KindRunnersConfig>,
+	},
+}
+
+impl From<RunnersConfigRepr> for RunnersConfig {
+	fn from(repr: RunnersConfigRepr) -> Self {
+		match repr {
+			RunnersConfigRepr::Count(default) => Self {
+				default,
+				kind: BTreeMap::new(),
+			},
+			RunnersConfigRepr::Pools { default, kind } => Self { default, kind },
+		}
+	}
+}
+
+/// Runners sharing jobs of the same kinds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunnerPool {
+	/// Kind of jobs which the runners are dedicated to, or [`None`] for default runners.
+	pub kind: Option<String>,
+	/// Indices of the runners.
+	pub indices: Range<usize>,
+}
+
+impl RunnersConfig {
+	/// Returns the count of runners dedicated to job kinds.
+	fn dedicated(&self) -> usize {
+		self.kind.values().map(|config| config.count).sum()
+	}
+
+	/// Returns the total count of runners.
+	pub fn total(&self) -> usize {
+		self.dedicated() + self.default
+	}
+
+	/// Returns the pool of the runner of an index.
+	pub fn pool(&self, index: usize) -> RunnerPool {
+		let mut start = 0;
+		for (kind, config) in &self.kind {
+			let end = start + config.count;
+			if index < end {
+				return RunnerPool {
+					kind: Some(kind.clone()),
+					indices: start..end,
+				};
+			}
+			start = end;
+		}
+		RunnerPool {
+			kind: None,
+			indices: start..self.total(),
+		}
+	}
+
+	/// Returns the count of jobs to be fetched for `idle` default runners,
+	/// and the kinds which they may run.
+	///
+	/// `running` is the count of jobs running on default runners by kind.
+	pub(crate) fn default_kinds(
+		&self,
+		running: &BTreeMap<String, usize>,
+		idle: usize,
+	) -> (usize, KindFilter) {
+		let mut excluded = self
+			.kind
+			.iter()
+			.filter(|(_, config)| config.count != 0)
+			.map(|(kind, _)| kind.clone())
+			.collect::<Vec<_>>();
+		let weighted = self
+			.kind
+			.iter()
+			.filter(|(_, config)| config.count == 0)
+			.filter_map(|(kind, config)| Some((kind, u64::from(config.weight?))))
+			.collect::<Vec<_>>();
+		let total_weight = weighted.iter().map(|(_, weight)| weight).sum::<u64>();
+		let mut n = idle;
+		for (kind, weight) in weighted {
+			let share = if weight == 0 {
+				0
+			} else {
+				(self.default as u64 * weight).div_ceil(total_weight) as usize
+			};
+			let left = share.saturating_sub(running.get(kind).copied().unwrap_or_default());
+			if left == 0 {
+				excluded.push(kind.clone());
+			} else {
+				// no kind can exceed its share in a batch
+				n = n.min(left);
+			}
+		}
+		(n, KindFilter::Except(excluded))
+	}
+}
+
+/// Supervisor which grows or shrinks the pool of runners at runtime.
+///
+/// Shrinking the pool does not interrupt running jobs. Runners removed
+/// from the pool stop after finishing their current jobs.
+#[derive(Debug)]
+pub struct RunnerSupervisor {
+	runner: Arc<JobRunner>,
+}
+
+impl RunnerSupervisor {
+	pub fn new(runner: Arc<JobRunner>) -> Self {
+		Self { runner }
+	}
+
+	/// Starts runners of the configuration and the job watcher.
+	pub fn start(&self, config: &RunnersConfig) {
+		self.configure(config);
+		tokio::spawn(self.runner.clone().run_watcher());
+	}
+
+	/// Returns the target count of runners.
+	pub fn size(&self) -> usize {
+		self.runner.set().size()
+	}
+
+	/// Replaces the configuration of the pool, and resizes it accordingly.
+	///
+	/// Runners are reassigned by their indices, after finishing their current jobs.
+	pub fn configure(&self, config: &RunnersConfig) {
+		let previous = self.size();
+		let started = self.runner.set().configure(config);
+		self.apply(previous, started);
+	}
+
+	/// Sets the count of default runners.
+	///
+	/// Runners are started for missing indices below the total count,
+	/// and runners with greater indices are asked to stop.
+	pub fn resize(&self, size: usize) {
+		let previous = self.size();
+		let started = self.runner.set().resize(size);
+		self.apply(previous, started);
+	}
+
+	fn apply(&self, previous: usize, started: Vec<usize>) {
+		for index in started {
+			tokio::spawn(self.runner.clone().run(index));
+		}
+		let config = self.runner.set().config();
+		self.runner.reassign_fetched(&config);
+		info!(
+			previous,
+			size = config.total(),
+			default = config.default,
+			"resized job runners"
+		);
+		// wake idle runners to stop them, or to pick pending jobs
+		self.runner.notify_all();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::BTreeMap;
+
+	use fabricia_backend::job_queue::KindFilter;
+	use serde::Deserialize;
+
+	use crate::{
+		set::RunnerSet,
+		supervisor::{KindRunnersConfig, RunnerPool, RunnersConfig, StealPolicy},
+	};
+
+	#[derive(Debug, Deserialize)]
+	struct Config {
+		runners: RunnersConfig,
+	}
+
+	fn parse(config: &str) -> RunnersConfig {
+		toml::from_str::<Config>(config).unwrap().runners
+	}
+
+	fn kind(count: usize, weight: Option<u32>, steal: StealPolicy) -> KindRunnersConfig {
+		KindRunnersConfig {
+			count,
+			weight,
+			steal,
+		}
+	}
+
+	fn test_config() -> RunnersConfig {
+		RunnersConfig {
+			default: 4,
+			kind: BTreeMap::from([
+				(
+					"IngestAdvisories".to_string(),
+					kind(0, Some(0), StealPolicy::Never),
+				),
+				(
+					"LintPackage".to_string(),
+					kind(0, Some(1), StealPolicy::Never),
+				),
+				(
+					"PrefetchSources".to_string(),
+					kind(0, Some(3), StealPolicy::Never),
+				),
+				("SyncBranch".to_string(), kind(1, None, StealPolicy::Any)),
+			]),
+		}
+	}
+
+	#[test]
+	fn test_deserialize_count() {
+		assert_eq!(
+			parse("runners = 4"),
+			RunnersConfig {
+				default: 4,
+				kind: BTreeMap::new(),
+			}
+		);
+	}
+
+	#[test]
+	fn test_deserialize_pools() {
+		let config = parse(
+			r#"
+			[runners]
+			default = 2
+
+			[runners.kind.SyncBranch]
+			count = 1
+			steal = "any"
+
+			[runners.kind.LintPackage]
+			count = 2
+			steal = { only = ["PrefetchSources"] }
+
+			[runners.kind.IngestAdvisories]
+			weight = 0
+			"#,
+		);
+		assert_eq!(
+			config,
+			RunnersConfig {
+				default: 2,
+				kind: BTreeMap::from([
+					(
+						"IngestAdvisories".to_string(),
+						kind(0, Some(0), StealPolicy::Never)
+					),
+					(
+						"LintPackage".to_string(),
+						kind(
+							2,
+							None,
+							StealPolicy::Only(vec!["PrefetchSources".to_string()])
+						)
+					),
+					("SyncBranch".to_string(), kind(1, None, StealPolicy::Any)),
+				]),
+			}
+		);
+		assert_eq!(config.total(), 5);
+		assert_eq!(parse("[runners]"), RunnersConfig::default());
+	}
+
+	#[test]
+	fn test_pool() {
+		let config = test_config();
+		assert_eq!(config.total(), 5);
+		assert_eq!(
+			config.pool(0),
+			RunnerPool {
+				kind: Some("SyncBranch".to_string()),
+				indices: 0..1,
+			}
+		);
+		for index in 1..5 {
+			assert_eq!(
+				config.pool(index),
+				RunnerPool {
+					kind: None,
+					indices: 1..5,
+				}
+			);
+		}
+	}
+
+	#[test]
+	fn test_default_kinds() {
+		let config = test_config();
+		let except = |kinds: &[&str]| {
+			KindFilter::Except(kinds.iter().map(|kind| kind.to_string()).collect())
+		};
+
+		// LintPackage has a share of 1 default runner, and PrefetchSources of 3
+		assert_eq!(
+			config.default_kinds(&BTreeMap::new(), 4),
+			(1, except(&["SyncBranch", "IngestAdvisories"]))
+		);
+		let running = BTreeMap::from([("LintPackage".to_string(), 1)]);
+		assert_eq!(
+			config.default_kinds(&running, 3),
+			(
+				3,
+				except(&["SyncBranch", "IngestAdvisories", "LintPackage"])
+			)
+		);
+		let running = BTreeMap::from([
+			("LintPackage".to_string(), 1),
+			("PrefetchSources".to_string(), 2),
+		]);
+		assert_eq!(
+			config.default_kinds(&running, 1),
+			(
+				1,
+				except(&["SyncBranch", "IngestAdvisories", "LintPackage"])
+			)
+		);
+
+		// kinds without weights may occupy all default runners
+		let config = RunnersConfig {
+			default: 4,
+			kind: BTreeMap::new(),
+		};
+		assert_eq!(config.default_kinds(&BTreeMap::new(), 4), (4, except(&[])));
+	}
+
+	#[test]
+	fn test_steal() {
+		let set = RunnerSet::default();
+		assert_eq!(set.configure(&test_config()), [0, 1, 2, 3, 4]);
+		assert_eq!(set.steal(&set.pool(0)), Some(KindFilter::Any));
+		// default runners never steal
+		assert_eq!(set.steal(&set.pool(1)), None);
+
+		let mut config = test_config();
+		config.kind.get_mut("SyncBranch").unwrap().steal =
+			StealPolicy::Only(vec!["LintPackage".to_string()]);
+		set.configure(&config);
+		assert_eq!(
+			set.steal(&set.pool(0)),
+			Some(KindFilter::Only(vec!["LintPackage".to_string()]))
+		);
+		config.kind.get_mut("SyncBranch").unwrap().steal = StealPolicy::Only(vec![]);
+		set.configure(&config);
+		assert_eq!(set.steal(&set.pool(0)), None);
+		config.kind.get_mut("SyncBranch").unwrap().steal = StealPolicy::Never;
+		set.configure(&config);
+		assert_eq!(set.steal(&set.pool(0)), None);
+	}
+}
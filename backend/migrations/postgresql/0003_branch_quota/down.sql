@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS "job_queue_subject_br";
+ALTER TABLE "job_queue" DROP COLUMN "subject_branch";
+ALTER TABLE "branch" DROP COLUMN "max_queued_jobs";
+ALTER TABLE "branch" DROP COLUMN "max_running_jobs";
@@ -0,0 +1,4 @@
+ALTER TABLE "branch" ADD COLUMN "max_running_jobs" INT NULL DEFAULT NULL;
+ALTER TABLE "branch" ADD COLUMN "max_queued_jobs" INT NULL DEFAULT NULL;
+ALTER TABLE "job_queue" ADD COLUMN "subject_branch" BIGINT NULL DEFAULT NULL;
+CREATE INDEX "job_queue_subject_br" ON "job_queue" ("subject_branch", ("started_at" IS NULL));
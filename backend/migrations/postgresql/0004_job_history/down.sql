@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS "job_duration";
+DROP TABLE IF EXISTS "job_history";
+DROP INDEX IF EXISTS "job_queue_poll";
+CREATE INDEX "job_queue_poll" ON "job_queue" ("kind", ("started_at" IS NULL), "priority" DESC);
+ALTER TABLE "job_queue" DROP COLUMN "estimated_ms";
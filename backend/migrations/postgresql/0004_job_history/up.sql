@@ -0,0 +1,25 @@
+ALTER TABLE "job_queue" ADD COLUMN "estimated_ms" BIGINT NOT NULL DEFAULT 0;
+DROP INDEX IF EXISTS "job_queue_poll";
+CREATE INDEX "job_queue_poll" ON "job_queue" (("started_at" IS NULL), "priority" DESC, "estimated_ms" DESC);
+-- Job History
+CREATE TABLE "job_history"(
+	"id" UUID NOT NULL PRIMARY KEY,
+	"kind" VARCHAR NOT NULL,
+	"data" JSONB NOT NULL,
+	"subject_branch" BIGINT NULL DEFAULT NULL,
+	"subject" VARCHAR NOT NULL,
+	"outcome" SMALLINT NOT NULL,
+	"started_at" TIMESTAMP NOT NULL,
+	"finished_at" TIMESTAMP NOT NULL,
+	"duration_ms" BIGINT NOT NULL
+);
+CREATE INDEX "job_history_subject" ON "job_history" ("kind", "subject", "finished_at" DESC);
+CREATE INDEX "job_history_finished" ON "job_history" ("finished_at" DESC);
+-- Moving Averages of Job Durations
+CREATE TABLE "job_duration"(
+	"kind" VARCHAR NOT NULL,
+	"subject" VARCHAR NOT NULL,
+	"avg_ms" BIGINT NOT NULL,
+	"samples" INT NOT NULL,
+	PRIMARY KEY ("kind", "subject")
+);
@@ -0,0 +1 @@
+ALTER TABLE "branch" ADD COLUMN "version" BIGINT NOT NULL DEFAULT 0;
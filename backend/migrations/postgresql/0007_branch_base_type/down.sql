@@ -0,0 +1 @@
+ALTER TABLE "branch" ALTER COLUMN "base" TYPE INTEGER;
@@ -0,0 +1,2 @@
+ALTER TABLE "job_history" ADD COLUMN "error_retryable" BOOLEAN NULL DEFAULT NULL;
+ALTER TABLE "job_history" ADD COLUMN "error_backtrace" UUID NULL DEFAULT NULL;
@@ -0,0 +1 @@
+ALTER TABLE "job_queue" DROP COLUMN "traceparent";
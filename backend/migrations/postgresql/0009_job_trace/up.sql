@@ -0,0 +1 @@
+ALTER TABLE "job_queue" ADD COLUMN "traceparent" VARCHAR NULL DEFAULT NULL;
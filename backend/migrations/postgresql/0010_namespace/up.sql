@@ -0,0 +1,15 @@
+-- Namespace
+CREATE TABLE "namespace"(
+	"id" BIGSERIAL NOT NULL PRIMARY KEY,
+	"name" VARCHAR(32) NOT NULL,
+	"token_hash" VARCHAR(64) NULL DEFAULT NULL,
+	"max_branches" INT NULL DEFAULT NULL,
+	"max_queued_jobs" INT NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX "namespace_name" ON "namespace" ("name");
+-- the default namespace, with ID 1
+INSERT INTO "namespace" ("name") VALUES ('default');
+-- Branch
+ALTER TABLE "branch" ADD COLUMN "namespace" BIGINT NOT NULL DEFAULT 1;
+DROP INDEX IF EXISTS "branch_name";
+CREATE UNIQUE INDEX "branch_name" ON "branch" ("namespace", "name");
@@ -0,0 +1,2 @@
+ALTER TABLE "branch" DROP COLUMN "repository";
+DROP TABLE IF EXISTS "repository";
@@ -0,0 +1 @@
+ALTER TABLE "job_queue" ADD COLUMN "target_arch" VARCHAR(32) NULL DEFAULT NULL;
@@ -0,0 +1,6 @@
+-- Upstream Version
+CREATE TABLE "upstream_version"(
+	"name" VARCHAR(32) NOT NULL PRIMARY KEY,
+	"version" VARCHAR(64) NULL DEFAULT NULL,
+	"checked_at" TIMESTAMP NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Build Cache
+CREATE TABLE "build_cache"(
+	"key" VARCHAR(64) NOT NULL PRIMARY KEY,
+	"target" BIGINT NOT NULL,
+	"build" UUID NOT NULL,
+	"artifacts" JSONB NOT NULL,
+	"created_at" TIMESTAMP NOT NULL
+);
@@ -0,0 +1,10 @@
+-- Branch Event
+CREATE TABLE "branch_event"(
+	"id" BIGSERIAL NOT NULL PRIMARY KEY,
+	"branch" BIGINT NOT NULL,
+	"status" SMALLINT NOT NULL,
+	"message" VARCHAR NULL DEFAULT NULL,
+	"actor" VARCHAR(64) NOT NULL,
+	"created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "branch_event_br" ON "branch_event" ("branch", "id");
@@ -0,0 +1,6 @@
+ALTER TABLE "branch" DROP COLUMN "created_at";
+ALTER TABLE "branch" DROP COLUMN "updated_at";
+ALTER TABLE "pkg" DROP COLUMN "created_at";
+ALTER TABLE "pkg" DROP COLUMN "updated_at";
+ALTER TABLE "pkg_target" DROP COLUMN "created_at";
+ALTER TABLE "pkg_target" DROP COLUMN "updated_at";
@@ -0,0 +1,14 @@
+-- Creation and Modification Times
+ALTER TABLE "branch" ADD COLUMN "created_at" TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC');
+ALTER TABLE "branch" ADD COLUMN "updated_at" TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC');
+ALTER TABLE "pkg" ADD COLUMN "created_at" TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC');
+ALTER TABLE "pkg" ADD COLUMN "updated_at" TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC');
+ALTER TABLE "pkg_target" ADD COLUMN "created_at" TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC');
+ALTER TABLE "pkg_target" ADD COLUMN "updated_at" TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC');
+-- times are maintained by the service layer
+ALTER TABLE "branch" ALTER COLUMN "created_at" DROP DEFAULT;
+ALTER TABLE "branch" ALTER COLUMN "updated_at" DROP DEFAULT;
+ALTER TABLE "pkg" ALTER COLUMN "created_at" DROP DEFAULT;
+ALTER TABLE "pkg" ALTER COLUMN "updated_at" DROP DEFAULT;
+ALTER TABLE "pkg_target" ALTER COLUMN "created_at" DROP DEFAULT;
+ALTER TABLE "pkg_target" ALTER COLUMN "updated_at" DROP DEFAULT;
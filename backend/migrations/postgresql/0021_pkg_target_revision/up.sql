@@ -0,0 +1,2 @@
+-- Package Target Revision
+ALTER TABLE "pkg_target" ADD COLUMN "revision" BIGINT NOT NULL DEFAULT 0;
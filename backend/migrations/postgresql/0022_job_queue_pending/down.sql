@@ -0,0 +1 @@
+DROP INDEX IF EXISTS "job_queue_pending";
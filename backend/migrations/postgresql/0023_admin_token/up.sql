@@ -0,0 +1,9 @@
+-- Admin Token
+CREATE TABLE "admin_token"(
+	"id" BIGSERIAL NOT NULL PRIMARY KEY,
+	"name" VARCHAR(32) NOT NULL,
+	"token_hash" VARCHAR(64) NOT NULL,
+	"created_at" TIMESTAMP NOT NULL
+);
+CREATE UNIQUE INDEX "admin_token_name" ON "admin_token" ("name");
+CREATE INDEX "admin_token_hash" ON "admin_token" ("token_hash");
@@ -0,0 +1 @@
+ALTER TABLE "job_history" ADD COLUMN "wait_ms" BIGINT NULL DEFAULT NULL;
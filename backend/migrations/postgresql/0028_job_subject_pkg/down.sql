@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS "job_queue_subject_pkg";
+ALTER TABLE "job_queue" DROP COLUMN "subject_pkg";
@@ -0,0 +1,2 @@
+ALTER TABLE "job_queue" ADD COLUMN "subject_pkg" UUID NULL DEFAULT NULL;
+CREATE INDEX "job_queue_subject_pkg" ON "job_queue" ("subject_pkg");
@@ -0,0 +1,6 @@
+-- Application Schema Version
+CREATE TABLE "app_schema"(
+	"id" INT NOT NULL PRIMARY KEY,
+	"version" INT NOT NULL,
+	"min_compatible" INT NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Phases of Applied Migrations
+CREATE TABLE "migration_phase"(
+	"version" VARCHAR(64) NOT NULL PRIMARY KEY,
+	"phase" SMALLINT NOT NULL,
+	"applied_at" TIMESTAMP NOT NULL
+);
@@ -0,0 +1,4 @@
+ALTER TABLE "job_history" ADD COLUMN "runner" VARCHAR NULL DEFAULT NULL;
+CREATE INDEX "job_history_branch" ON "job_history" ("subject_branch", "finished_at" DESC);
+CREATE INDEX "job_history_outcome" ON "job_history" ("outcome", "finished_at" DESC);
+CREATE INDEX "job_history_runner" ON "job_history" ("runner", "finished_at" DESC);
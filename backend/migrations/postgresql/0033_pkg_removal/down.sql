@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS "pkg_archive";
+ALTER TABLE "branch" DROP COLUMN "removal_policy";
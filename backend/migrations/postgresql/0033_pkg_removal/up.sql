@@ -0,0 +1,11 @@
+ALTER TABLE "branch" ADD COLUMN "removal_policy" SMALLINT NOT NULL DEFAULT 0;
+-- Archived Packages
+CREATE TABLE "pkg_archive"(
+	"id" UUID NOT NULL PRIMARY KEY,
+	"branch" BIGINT NOT NULL,
+	"name" VARCHAR NOT NULL,
+	"data" JSONB NOT NULL,
+	"targets" JSONB NOT NULL,
+	"archived_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "pkg_archive_branch" ON "pkg_archive" ("branch", "name");
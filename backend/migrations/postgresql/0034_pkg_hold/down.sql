@@ -0,0 +1 @@
+ALTER TABLE "pkg" DROP COLUMN "hold_reason";
@@ -0,0 +1,10 @@
+-- Audit Log
+CREATE TABLE "audit_log"(
+	"id" BIGSERIAL NOT NULL PRIMARY KEY,
+	"actor" VARCHAR(64) NOT NULL,
+	"action" VARCHAR(64) NOT NULL,
+	"subject" VARCHAR NOT NULL,
+	"detail" JSONB NOT NULL,
+	"created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "audit_log_subject" ON "audit_log" ("subject", "id");
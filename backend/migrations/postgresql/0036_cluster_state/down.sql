@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS "instance";
+DROP TABLE IF EXISTS "cluster_flag";
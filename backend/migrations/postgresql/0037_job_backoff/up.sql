@@ -0,0 +1 @@
+ALTER TABLE "job_queue" ADD COLUMN "scheduled_at" TIMESTAMP NULL DEFAULT NULL;
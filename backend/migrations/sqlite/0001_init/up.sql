@@ -1,6 +1,6 @@
 -- Branch
 CREATE TABLE `branch`(
-	`id` BIGINT NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
 	`name` VARCHAR(32) NOT NULL,
 	`base` BIGINT NULL DEFAULT NULL,
 	`status` SMALLINT NOT NULL DEFAULT 0,
@@ -28,7 +28,7 @@ CREATE UNIQUE INDEX `pkg_id` ON `pkg` (`id`);
 CREATE INDEX `pkg_branch` ON `pkg` (`branch`);
 CREATE UNIQUE INDEX `pkg_br_name` ON `pkg` (`branch`, `name`);
 CREATE INDEX `pkg_status` ON `pkg` (`status`);
-CREATE INDEX `pkg_br_status` ON `pkg` (`branch`, `status`)
+CREATE INDEX `pkg_br_status` ON `pkg` (`branch`, `status`);
 -- Package + Target
 CREATE TABLE `pkg_target`(
 	`id` UUID NOT NULL PRIMARY KEY,
@@ -0,0 +1 @@
+ALTER TABLE `job_queue` ADD COLUMN `attempts` SMALLINT NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS `operation_job`;
+DROP TABLE IF EXISTS `operation`;
+ALTER TABLE `job_history` DROP COLUMN `error`;
@@ -0,0 +1,13 @@
+ALTER TABLE `job_history` ADD COLUMN `error` VARCHAR NULL DEFAULT NULL;
+-- Operations
+CREATE TABLE `operation`(
+	`id` UUID NOT NULL PRIMARY KEY,
+	`kind` VARCHAR NOT NULL,
+	`branch` VARCHAR NULL DEFAULT NULL,
+	`created_at` TIMESTAMP NOT NULL
+);
+CREATE TABLE `operation_job`(
+	`operation` UUID NOT NULL REFERENCES `operation`(`id`) ON DELETE CASCADE,
+	`job` UUID NOT NULL,
+	PRIMARY KEY (`operation`, `job`)
+);
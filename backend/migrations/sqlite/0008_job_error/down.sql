@@ -0,0 +1,2 @@
+ALTER TABLE `job_history` DROP COLUMN `error_backtrace`;
+ALTER TABLE `job_history` DROP COLUMN `error_retryable`;
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS `branch_name`;
+DELETE FROM `branch` WHERE `namespace` <> 1;
+ALTER TABLE `branch` DROP COLUMN `namespace`;
+CREATE UNIQUE INDEX `branch_name` ON `branch` (`name`);
+DROP TABLE IF EXISTS `namespace`;
@@ -0,0 +1,9 @@
+-- Repository
+CREATE TABLE `repository`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name` VARCHAR(32) NOT NULL,
+	`url` VARCHAR(256) NOT NULL
+);
+CREATE UNIQUE INDEX `repository_name` ON `repository` (`name`);
+-- Branch
+ALTER TABLE `branch` ADD COLUMN `repository` BIGINT NULL DEFAULT NULL;
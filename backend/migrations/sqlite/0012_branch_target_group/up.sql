@@ -0,0 +1 @@
+ALTER TABLE `branch` ADD COLUMN `target_group` VARCHAR(32) NULL DEFAULT NULL;
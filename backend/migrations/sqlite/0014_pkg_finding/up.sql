@@ -0,0 +1,11 @@
+-- Package Finding
+CREATE TABLE `pkg_finding`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`branch` BIGINT NOT NULL,
+	`package` UUID NOT NULL,
+	`kind` VARCHAR(32) NOT NULL,
+	`severity` SMALLINT NOT NULL,
+	`message` VARCHAR(256) NOT NULL
+);
+CREATE INDEX `pkg_finding_br` ON `pkg_finding` (`branch`);
+CREATE INDEX `pkg_finding_pkg` ON `pkg_finding` (`package`);
@@ -0,0 +1,11 @@
+-- Advisory
+CREATE TABLE `advisory`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`source` VARCHAR(32) NOT NULL,
+	`name` VARCHAR(64) NOT NULL,
+	`package` VARCHAR(128) NOT NULL,
+	`summary` VARCHAR NOT NULL,
+	`affected` JSONB NOT NULL
+);
+CREATE INDEX `advisory_source` ON `advisory` (`source`);
+CREATE INDEX `advisory_package` ON `advisory` (`package`);
@@ -0,0 +1,10 @@
+-- Package Status Event
+CREATE TABLE `status_event`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`package` UUID NOT NULL,
+	`status` SMALLINT NOT NULL,
+	`message` VARCHAR NULL DEFAULT NULL,
+	`actor` VARCHAR(64) NOT NULL,
+	`created_at` TIMESTAMP NOT NULL
+);
+CREATE INDEX `status_event_pkg` ON `status_event` (`package`, `id`);
@@ -0,0 +1,12 @@
+-- Creation and Modification Times
+-- SQLite does not accept non-constant defaults of new columns, and times are
+-- maintained by the service layer anyway.
+ALTER TABLE `branch` ADD COLUMN `created_at` TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE `branch` ADD COLUMN `updated_at` TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE `pkg` ADD COLUMN `created_at` TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE `pkg` ADD COLUMN `updated_at` TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE `pkg_target` ADD COLUMN `created_at` TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE `pkg_target` ADD COLUMN `updated_at` TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+UPDATE `branch` SET `created_at` = datetime('now'), `updated_at` = datetime('now');
+UPDATE `pkg` SET `created_at` = datetime('now'), `updated_at` = datetime('now');
+UPDATE `pkg_target` SET `created_at` = datetime('now'), `updated_at` = datetime('now');
@@ -0,0 +1 @@
+ALTER TABLE `pkg_target` DROP COLUMN `revision`;
@@ -0,0 +1,2 @@
+-- Pending Job Index
+CREATE INDEX `job_queue_pending` ON `job_queue` (`priority` DESC) WHERE `started_at` IS NULL;
@@ -0,0 +1,6 @@
+-- Branch ACL
+CREATE TABLE `branch_acl`(
+	`branch` BIGINT NOT NULL,
+	`principal` VARCHAR(128) NOT NULL,
+	PRIMARY KEY (`branch`, `principal`)
+);
@@ -0,0 +1,9 @@
+-- Artifact Diff
+CREATE TABLE `artifact_diff`(
+	`build` UUID NOT NULL,
+	`against` UUID NOT NULL,
+	`result` JSONB,
+	`error` VARCHAR,
+	`created_at` TIMESTAMP NOT NULL,
+	PRIMARY KEY (`build`, `against`)
+);
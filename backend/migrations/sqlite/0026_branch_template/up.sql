@@ -0,0 +1,6 @@
+-- Branch Template
+CREATE TABLE `branch_template`(
+	`name` VARCHAR(64) NOT NULL PRIMARY KEY,
+	`config` JSONB NOT NULL,
+	`updated_at` TIMESTAMP NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE `job_history` DROP COLUMN `wait_ms`;
@@ -0,0 +1 @@
+ALTER TABLE `branch` ADD COLUMN `pinned_commit` BLOB NULL DEFAULT NULL;
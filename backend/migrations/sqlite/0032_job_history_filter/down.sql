@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS `job_history_runner`;
+DROP INDEX IF EXISTS `job_history_outcome`;
+DROP INDEX IF EXISTS `job_history_branch`;
+ALTER TABLE `job_history` DROP COLUMN `runner`;
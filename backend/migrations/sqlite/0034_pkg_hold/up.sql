@@ -0,0 +1 @@
+ALTER TABLE `pkg` ADD COLUMN `hold_reason` VARCHAR NULL DEFAULT NULL;
@@ -0,0 +1,11 @@
+-- Cluster Flag
+CREATE TABLE `cluster_flag`(
+	`name` VARCHAR(64) NOT NULL PRIMARY KEY,
+	`value` VARCHAR NOT NULL
+);
+-- Instance
+CREATE TABLE `instance`(
+	`id` UUID NOT NULL PRIMARY KEY,
+	`info` VARCHAR NOT NULL,
+	`heartbeat_at` BIGINT NOT NULL
+);
@@ -0,0 +1,158 @@
+//! Bearer tokens of administrators.
+//!
+//! Admin routes are closed until the first admin token is created explicitly,
+//! usually by bootstrapping a new deployment, see [`crate::bootstrap`].
+
+use std::{fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+use diesel::{
+	ExpressionMethods, OptionalExtension, QueryDsl, insert_into, result::DatabaseErrorKind,
+};
+use kstring::KString;
+use thiserror::Error;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::info;
+
+use crate::{
+	Result,
+	db::{schema::admin_token::dsl, service::DatabaseService},
+	namespace::{generate_token, hash_token},
+};
+
+pub type AdminTokenRef = i64;
+
+/// Operations on admin tokens used by API handlers.
+///
+/// Implemented by [`AdminTokenService`], and mocked by `MockAdminTokenApi`
+/// with the `mock` feature.
+#[cfg_attr(feature = "mock", mockall::automock)]
+#[async_trait]
+pub trait AdminTokenApi: Send + Sync + Debug {
+	/// See [`AdminTokenService::authorize`].
+	async fn authorize<'a>(&self, token: Option<&'a str>) -> Result<bool>;
+	/// See [`AdminTokenService::find_name`].
+	async fn find_name(&self, token: &str) -> Result<Option<String>>;
+}
+
+/// A newly generated admin token.
+///
+/// Only the hash is stored, so the token cannot be read again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminToken {
+	pub id: AdminTokenRef,
+	pub token: String,
+}
+
+#[derive(Debug)]
+pub struct AdminTokenService {
+	db: Arc<DatabaseService>,
+}
+
+impl AdminTokenService {
+	pub fn new(db: Arc<DatabaseService>) -> Self {
+		Self { db }
+	}
+
+	/// Returns whether any admin token has been created.
+	pub async fn exists(&self) -> Result<bool> {
+		let mut conn = self.db.get().await?;
+		let id = conn
+			.get_result::<_, AdminTokenRef>(dsl::admin_token.select(dsl::id).limit(1))
+			.await
+			.optional()?;
+		Ok(id.is_some())
+	}
+
+	/// Creates an admin token with a unique name.
+	pub async fn create(&self, name: &str) -> Result<AdminToken> {
+		let mut conn = self.db.get().await?;
+		let token = generate_token();
+		let time = OffsetDateTime::now_utc();
+		let id = conn
+			.get_result::<_, AdminTokenRef>(
+				insert_into(dsl::admin_token)
+					.values((
+						dsl::name.eq(name),
+						dsl::token_hash.eq(hash_token(&token)),
+						dsl::created_at.eq(PrimitiveDateTime::new(time.date(), time.time())),
+					))
+					.returning(dsl::id),
+			)
+			.await
+			.map_err(|error| match error {
+				diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+					AdminTokenError::AlreadyExists(KString::from_ref(name)).into()
+				}
+				error => crate::BackendError::from(error),
+			})?;
+		info!(id, name, "created admin token");
+		Ok(AdminToken { id, token })
+	}
+
+	/// Returns whether the given bearer token grants access to admin routes.
+	///
+	/// No token is accepted before the first admin token is created.
+	pub async fn authorize(&self, token: Option<&str>) -> Result<bool> {
+		match token {
+			Some(token) => Ok(self.find_name(token).await?.is_some()),
+			None => Ok(false),
+		}
+	}
+
+	/// Returns the name of an admin token.
+	pub async fn find_name(&self, token: &str) -> Result<Option<String>> {
+		let mut conn = self.db.get().await?;
+		Ok(conn
+			.get_result::<_, String>(
+				dsl::admin_token
+					.filter(dsl::token_hash.eq(hash_token(token)))
+					.select(dsl::name)
+					.limit(1),
+			)
+			.await
+			.optional()?)
+	}
+}
+
+#[async_trait]
+impl AdminTokenApi for AdminTokenService {
+	async fn authorize<'a>(&self, token: Option<&'a str>) -> Result<bool> {
+		AdminTokenService::authorize(self, token).await
+	}
+
+	async fn find_name(&self, token: &str) -> Result<Option<String>> {
+		AdminTokenService::find_name(self, token).await
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum AdminTokenError {
+	#[error("admin token {0} has already existed")]
+	AlreadyExists(KString),
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{BackendError, admin_token::AdminTokenError, test::test_env};
+
+	#[tokio::test]
+	async fn test_admin_token() {
+		let env = test_env().await;
+		assert!(!env.admin_token.exists().await.unwrap());
+		assert!(!env.admin_token.authorize(None).await.unwrap());
+		assert!(!env.admin_token.authorize(Some("any")).await.unwrap());
+
+		let token = env.admin_token.create("admin").await.unwrap();
+		assert!(env.admin_token.exists().await.unwrap());
+		assert!(env.admin_token.authorize(Some(&token.token)).await.unwrap());
+		assert!(!env.admin_token.authorize(Some("wrong")).await.unwrap());
+		assert!(!env.admin_token.authorize(None).await.unwrap());
+		assert!(matches!(
+			env.admin_token.create("admin").await,
+			Err(BackendError::AdminTokenError(
+				AdminTokenError::AlreadyExists(_)
+			))
+		));
+	}
+}
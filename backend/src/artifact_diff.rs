@@ -0,0 +1,526 @@
+//! Comparisons of artifacts of two builds.
+//!
+//! Artifacts are downloaded from the artifact store and compared by
+//! [`JobCommand::DiffArtifacts`] jobs, since archives may be large. Results are
+//! cached in `artifact_diff`, as artifacts of a build never change.
+//!
+//! Files are listed from `.deb` packages and tarballs, compressed with xz or zstd.
+//! Other artifacts are compared as single files.
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	io::{self, Read},
+	sync::Arc,
+};
+
+use diesel::{
+	ExpressionMethods, OptionalExtension, QueryDsl, insert_into,
+	result::{DatabaseErrorKind, Error as DieselError},
+	update,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tokio::task::spawn_blocking;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	db::{
+		schema::{artifact_diff::dsl, build_cache::dsl as cache_dsl},
+		service::{DatabaseError, DatabaseService},
+		utils::{XJsonVal, XUuidVal},
+	},
+	job_queue::{JobCommand, JobQueue},
+	model::{ArtifactDiffRow, BuildCacheRow},
+};
+
+/// Configuration of the artifact store.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ArtifactStoreConfig {
+	/// Base URL of the artifact store.
+	///
+	/// Artifacts of a build are served at `{url}/{build}/{name}`.
+	pub url: String,
+}
+
+/// A file in artifacts of a build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactFile {
+	pub size: u64,
+	/// SHA-256 digest of the content, or of the target of symbolic links.
+	pub sha256: [u8; 32],
+}
+
+/// Lists files in an artifact, by path.
+pub fn list_files(name: &str, data: &[u8]) -> io::Result<BTreeMap<String, ArtifactFile>> {
+	if name.ends_with(".deb") {
+		let members = ar_members(data)?;
+		return match members
+			.into_iter()
+			.find(|(member, _)| member.starts_with("data.tar"))
+		{
+			Some((member, data)) => list_tar(decoder(member, data)?),
+			None => Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("{name} has no data member"),
+			)),
+		};
+	}
+	if name.contains(".tar") {
+		return list_tar(decoder(name, data)?);
+	}
+	Ok(BTreeMap::from([(
+		name.to_string(),
+		ArtifactFile {
+			size: data.len() as u64,
+			sha256: Sha256::digest(data).into(),
+		},
+	)]))
+}
+
+/// Returns a reader decompressing an archive by its file name.
+fn decoder<'a>(name: &str, data: &'a [u8]) -> io::Result<Box<dyn Read + 'a>> {
+	if name.ends_with(".xz") {
+		Ok(Box::new(xz2::read::XzDecoder::new(data)))
+	} else if name.ends_with(".zst") {
+		Ok(Box::new(zstd::stream::read::Decoder::new(data)?))
+	} else {
+		Ok(Box::new(data))
+	}
+}
+
+/// Splits an `ar` archive, e.g. a `.deb` package, into named members.
+fn ar_members(mut data: &[u8]) -> io::Result<Vec<(&str, &[u8])>> {
+	let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid ar archive");
+	data = data.strip_prefix(b"!<arch>\n").ok_or_else(invalid)?;
+	let mut members = Vec::new();
+	while !data.is_empty() {
+		let header = data.get(..60).ok_or_else(invalid)?;
+		let name = std::str::from_utf8(&header[..16]).map_err(|_| invalid())?;
+		let size = std::str::from_utf8(&header[48..58])
+			.ok()
+			.and_then(|size| size.trim().parse::<usize>().ok())
+			.ok_or_else(invalid)?;
+		let content = data.get(60..60 + size).ok_or_else(invalid)?;
+		members.push((name.trim_end().trim_end_matches('/'), content));
+		// members are aligned to even offsets
+		data = data.get(60 + size + size % 2..).unwrap_or_default();
+	}
+	Ok(members)
+}
+
+fn list_tar(reader: impl Read) -> io::Result<BTreeMap<String, ArtifactFile>> {
+	let mut files = BTreeMap::new();
+	let mut archive = tar::Archive::new(reader);
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		let kind = entry.header().entry_type();
+		let path = entry.path()?.to_string_lossy().to_string();
+		let path = path.trim_start_matches("./").to_string();
+		let file = if kind.is_file() {
+			let mut hasher = Sha256::new();
+			let size = io::copy(&mut entry, &mut hasher)?;
+			ArtifactFile {
+				size,
+				sha256: hasher.finalize().into(),
+			}
+		} else if kind.is_symlink() {
+			let target = entry.link_name()?.unwrap_or_default();
+			ArtifactFile {
+				size: 0,
+				sha256: Sha256::digest(target.to_string_lossy().as_bytes()).into(),
+			}
+		} else {
+			continue;
+		};
+		files.insert(path, file);
+	}
+	Ok(files)
+}
+
+/// Returns the soname of a shared library by its path, e.g. `libz.so.1`
+/// of `usr/lib/libz.so.1.3.1`.
+pub fn soname(path: &str) -> Option<String> {
+	let name = path.rsplit('/').next()?;
+	let (stem, version) = name.split_once(".so.")?;
+	let major = version.split('.').next()?;
+	(stem.starts_with("lib") && !major.is_empty() && major.bytes().all(|b| b.is_ascii_digit()))
+		.then(|| format!("{stem}.so.{major}"))
+}
+
+/// A file added, removed or changed between two builds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FileDelta {
+	pub path: String,
+	/// Size in the build compared against, or [`None`] if added.
+	pub old_size: Option<u64>,
+	/// Size in the compared build, or [`None`] if removed.
+	pub new_size: Option<u64>,
+}
+
+/// Comparison of artifacts of two builds, stored in `artifact_diff.result`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ArtifactDiff {
+	/// Added, removed and changed files, ordered by path.
+	pub files: Vec<FileDelta>,
+	/// Difference of total sizes of files in bytes.
+	pub size_delta: i64,
+	/// Sonames of shared libraries only in the compared build.
+	pub sonames_added: Vec<String>,
+	/// Sonames of shared libraries only in the build compared against.
+	pub sonames_removed: Vec<String>,
+}
+
+/// Compares files of two builds.
+pub fn compare(
+	old: &BTreeMap<String, ArtifactFile>,
+	new: &BTreeMap<String, ArtifactFile>,
+) -> ArtifactDiff {
+	let paths = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+	let files = paths
+		.into_iter()
+		.filter(|path| old.get(*path) != new.get(*path))
+		.map(|path| FileDelta {
+			path: path.clone(),
+			old_size: old.get(path).map(|file| file.size),
+			new_size: new.get(path).map(|file| file.size),
+		})
+		.collect();
+	let total = |files: &BTreeMap<String, ArtifactFile>| -> i64 {
+		files.values().map(|file| file.size as i64).sum()
+	};
+	let sonames = |files: &BTreeMap<String, ArtifactFile>| -> BTreeSet<String> {
+		files
+			.keys()
+			.map(String::as_str)
+			.filter_map(soname)
+			.collect()
+	};
+	let (old_sonames, new_sonames) = (sonames(old), sonames(new));
+	ArtifactDiff {
+		files,
+		size_delta: total(new) - total(old),
+		sonames_added: new_sonames.difference(&old_sonames).cloned().collect(),
+		sonames_removed: old_sonames.difference(&new_sonames).cloned().collect(),
+	}
+}
+
+/// Service for comparisons of artifacts.
+#[derive(Debug)]
+pub struct ArtifactDiffService {
+	db: Arc<DatabaseService>,
+	job_queue: Arc<JobQueue>,
+	/// Artifacts cannot be compared if not set.
+	config: Option<ArtifactStoreConfig>,
+	http: reqwest::Client,
+}
+
+impl ArtifactDiffService {
+	pub fn new(
+		db: Arc<DatabaseService>,
+		job_queue: Arc<JobQueue>,
+		config: Option<&ArtifactStoreConfig>,
+	) -> Self {
+		Self {
+			db,
+			job_queue,
+			config: config.cloned(),
+			http: reqwest::Client::new(),
+		}
+	}
+
+	/// Returns whether the artifact store is configured.
+	pub fn is_enabled(&self) -> bool {
+		self.config.is_some()
+	}
+
+	/// Returns file names of artifacts of a build, or [`None`] if the build
+	/// is not cached.
+	pub async fn artifacts(&self, build: Uuid) -> Result<Option<Vec<String>>> {
+		let mut conn = self.db.get().await?;
+		let row: Option<BuildCacheRow> = conn
+			.load_one_select(cache_dsl::build_cache.filter(cache_dsl::build.eq(XUuidVal(build))))
+			.await
+			.optional()?;
+		Ok(row.map(|row| row.artifact_names()).transpose()?)
+	}
+
+	/// Returns the cached comparison of artifacts of `build` against `against`,
+	/// enqueuing a [`JobCommand::DiffArtifacts`] job if not compared yet.
+	pub async fn get_or_enqueue(&self, build: Uuid, against: Uuid) -> Result<ArtifactDiffRow> {
+		if !self.is_enabled() {
+			return Err(ArtifactDiffError::Disabled.into());
+		}
+		let mut conn = self.db.get().await?;
+		let query = || {
+			dsl::artifact_diff
+				.filter(dsl::build.eq(XUuidVal(build)))
+				.filter(dsl::against.eq(XUuidVal(against)))
+		};
+		let row: Option<ArtifactDiffRow> = conn.load_one_select(query()).await.optional()?;
+		if let Some(row) = row {
+			return Ok(row);
+		}
+
+		let time = OffsetDateTime::now_utc();
+		let row = ArtifactDiffRow {
+			build: XUuidVal(build),
+			against: XUuidVal(against),
+			result: None,
+			error: None,
+			created_at: PrimitiveDateTime::new(time.date(), time.time()),
+		};
+		let result = conn
+			.transaction::<_, crate::BackendError, _>(async |conn| {
+				conn.execute(insert_into(dsl::artifact_diff).values(&row))
+					.await?;
+				self.job_queue
+					.enqueue(conn, JobCommand::DiffArtifacts { build, against })
+					.await?;
+				Ok(())
+			})
+			.await;
+		match result {
+			Ok(()) => Ok(row),
+			// enqueued by another request at the same time
+			Err(crate::BackendError::DatabaseError(DatabaseError::QueryError(
+				DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _),
+			))) => Ok(conn.load_one_select(query()).await?),
+			Err(error) => Err(error),
+		}
+	}
+
+	/// Compares artifacts of two builds, and caches the result.
+	///
+	/// Builds without cached artifacts and invalid archives are recorded as
+	/// errors, while failures to download artifacts are returned to be retried.
+	pub async fn run(&self, build: Uuid, against: Uuid) -> Result<()> {
+		let config = self.config.as_ref().ok_or(ArtifactDiffError::Disabled)?;
+		let mut files = Vec::with_capacity(2);
+		for id in [against, build] {
+			let Some(names) = self.artifacts(id).await? else {
+				return self
+					.record(build, against, Err(format!("build {id} not found")))
+					.await;
+			};
+			let mut artifacts = Vec::with_capacity(names.len());
+			for name in names {
+				let data = self
+					.http
+					.get(format!("{}/{id}/{name}", config.url.trim_end_matches('/')))
+					.send()
+					.await
+					.and_then(|response| response.error_for_status())
+					.map_err(ArtifactDiffError::FetchError)?
+					.bytes()
+					.await
+					.map_err(ArtifactDiffError::FetchError)?;
+				artifacts.push((name, data));
+			}
+			let listed = spawn_blocking(move || {
+				let mut files = BTreeMap::new();
+				for (name, data) in artifacts {
+					files.extend(
+						list_files(&name, &data)
+							.map_err(|error| format!("failed to list {name}: {error}"))?,
+					);
+				}
+				Ok::<_, String>(files)
+			})
+			.await
+			.map_err(DatabaseError::from)?;
+			match listed {
+				Ok(listed) => files.push(listed),
+				Err(error) => return self.record(build, against, Err(error)).await,
+			}
+		}
+		let diff = compare(&files[0], &files[1]);
+		info!(%build, %against, files = diff.files.len(), "compared artifacts");
+		self.record(build, against, Ok(diff)).await
+	}
+
+	/// Records the result of a comparison.
+	pub async fn record(
+		&self,
+		build: Uuid,
+		against: Uuid,
+		result: std::result::Result<ArtifactDiff, String>,
+	) -> Result<()> {
+		let (result, error) = match result {
+			Ok(diff) => (Some(XJsonVal(serde_json::to_value(diff)?)), None),
+			Err(error) => (None, Some(error)),
+		};
+		let mut conn = self.db.get().await?;
+		conn.execute(
+			update(dsl::artifact_diff)
+				.filter(dsl::build.eq(XUuidVal(build)))
+				.filter(dsl::against.eq(XUuidVal(against)))
+				.set((dsl::result.eq(result), dsl::error.eq(error))),
+		)
+		.await?;
+		Ok(())
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum ArtifactDiffError {
+	#[error("artifact store is not configured")]
+	Disabled,
+	#[error("failed to download artifact: {0}")]
+	FetchError(reqwest::Error),
+}
+
+#[cfg(test)]
+mod test {
+	use diesel::insert_into;
+	use uuid::Uuid;
+
+	use crate::{
+		artifact_diff::{
+			ArtifactDiff, ArtifactStoreConfig, FileDelta, compare, list_files, soname,
+		},
+		db::{
+			schema::build_cache::dsl as cache_dsl,
+			utils::{XJsonVal, XUuidVal},
+		},
+		job_queue::JobCommand,
+		model::BuildCacheRow,
+		test::{test_config, test_env_with_config, test_time},
+	};
+
+	fn tarball(files: &[(&str, &[u8])]) -> Vec<u8> {
+		let mut builder = tar::Builder::new(Vec::new());
+		for (path, content) in files {
+			let mut header = tar::Header::new_gnu();
+			header.set_size(content.len() as u64);
+			header.set_mode(0o644);
+			header.set_cksum();
+			builder.append_data(&mut header, path, *content).unwrap();
+		}
+		builder.into_inner().unwrap()
+	}
+
+	fn deb(data: &[u8]) -> Vec<u8> {
+		let mut deb = b"!<arch>\n".to_vec();
+		for (name, content) in [("debian-binary", &b"2.0\n"[..]), ("data.tar", data)] {
+			deb.extend(format!("{:<16}{:<32}{:<10}`\n", name, "", content.len()).bytes());
+			deb.extend(content);
+			if content.len() % 2 == 1 {
+				deb.push(b'\n');
+			}
+		}
+		deb
+	}
+
+	#[test]
+	fn test_soname() {
+		assert_eq!(
+			soname("usr/lib/libz.so.1.3.1").as_deref(),
+			Some("libz.so.1")
+		);
+		assert_eq!(soname("usr/lib/libz.so"), None);
+		assert_eq!(soname("usr/bin/bash"), None);
+	}
+
+	#[test]
+	fn test_compare() {
+		let old = deb(&tarball(&[
+			("./usr/bin/xz", b"xz"),
+			("./usr/lib/liblzma.so.5.4.6", b"lzma"),
+		]));
+		let new = deb(&tarball(&[
+			("./usr/bin/xz", b"xz2"),
+			("./usr/lib/liblzma.so.6.0.0", b"lzma"),
+			("./usr/share/doc/xz", b""),
+		]));
+		let old = list_files("xz.deb", &old).unwrap();
+		let new = list_files("xz.deb", &new).unwrap();
+		assert_eq!(old.len(), 2);
+		assert_eq!(
+			compare(&old, &new),
+			ArtifactDiff {
+				files: vec![
+					FileDelta {
+						path: "usr/bin/xz".to_string(),
+						old_size: Some(2),
+						new_size: Some(3),
+					},
+					FileDelta {
+						path: "usr/lib/liblzma.so.5.4.6".to_string(),
+						old_size: Some(4),
+						new_size: None,
+					},
+					FileDelta {
+						path: "usr/lib/liblzma.so.6.0.0".to_string(),
+						old_size: None,
+						new_size: Some(4),
+					},
+					FileDelta {
+						path: "usr/share/doc/xz".to_string(),
+						old_size: None,
+						new_size: Some(0),
+					},
+				],
+				size_delta: 1,
+				sonames_added: vec!["liblzma.so.6".to_string()],
+				sonames_removed: vec!["liblzma.so.5".to_string()],
+			}
+		);
+		assert_eq!(compare(&old, &old), ArtifactDiff::default());
+
+		let files = list_files("xz.log", b"log").unwrap();
+		assert_eq!(files.keys().collect::<Vec<_>>(), ["xz.log"]);
+		assert!(list_files("xz.deb", b"log").is_err());
+	}
+
+	#[tokio::test]
+	async fn test_get_or_enqueue() {
+		let mut config = test_config();
+		config.artifact_store = Some(ArtifactStoreConfig {
+			url: "http://localhost".to_string(),
+		});
+		let env = test_env_with_config(config).await;
+		let (build, against) = (Uuid::now_v7(), Uuid::now_v7());
+		let mut db = env.database.get().await.unwrap();
+		db.execute(insert_into(cache_dsl::build_cache).values(BuildCacheRow {
+			key: "0".repeat(64),
+			target: 0,
+			build: XUuidVal(build),
+			artifacts: XJsonVal(serde_json::json!(["xz.deb"])),
+			created_at: test_time(),
+		}))
+		.await
+		.unwrap();
+		drop(db);
+		assert_eq!(
+			env.artifact_diff.artifacts(build).await.unwrap(),
+			Some(vec!["xz.deb".to_string()])
+		);
+		assert_eq!(env.artifact_diff.artifacts(against).await.unwrap(), None);
+
+		let row = env
+			.artifact_diff
+			.get_or_enqueue(build, against)
+			.await
+			.unwrap();
+		assert_eq!((row.result, row.error), (None, None));
+		let job = env.job_queue.fetch_and_start().await.unwrap().unwrap();
+		assert_eq!(job.command, JobCommand::DiffArtifacts { build, against });
+
+		// builds without cached artifacts are recorded as errors without requests
+		env.artifact_diff.run(build, against).await.unwrap();
+		let row = env
+			.artifact_diff
+			.get_or_enqueue(build, against)
+			.await
+			.unwrap();
+		assert_eq!(row.error, Some(format!("build {against} not found")));
+		assert!(env.job_queue.fetch_and_start().await.unwrap().is_none());
+	}
+}
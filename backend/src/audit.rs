@@ -0,0 +1,50 @@
+//! Audit log of manual interventions.
+//!
+//! Interventions bypassing the usual workflows, e.g. forcing states of packages,
+//! are recorded with their callers, in the same transactions as the changes.
+
+use diesel::{ExpressionMethods, QueryDsl, insert_into};
+use serde_json::Value;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use crate::{
+	Result,
+	db::{BoxedSqlConn, schema::audit_log::dsl, utils::XJsonVal},
+	model::{AuditLogRow, NewAuditLogRow},
+};
+
+/// Records an intervention.
+pub async fn record(
+	conn: &mut BoxedSqlConn,
+	actor: &str,
+	action: &str,
+	subject: &str,
+	detail: Value,
+) -> Result<()> {
+	let time = OffsetDateTime::now_utc();
+	conn.execute(insert_into(dsl::audit_log).values(NewAuditLogRow {
+		actor,
+		action,
+		subject,
+		detail: XJsonVal(detail),
+		created_at: PrimitiveDateTime::new(time.date(), time.time()),
+	}))
+	.await?;
+	Ok(())
+}
+
+/// Lists at most `limit` interventions on a subject, newest first.
+pub async fn list(
+	conn: &mut BoxedSqlConn,
+	subject: &str,
+	limit: usize,
+) -> Result<Vec<AuditLogRow>> {
+	Ok(conn
+		.load_select(
+			dsl::audit_log
+				.filter(dsl::subject.eq(subject))
+				.order(dsl::id.desc())
+				.limit(limit.try_into().unwrap_or(i64::MAX)),
+		)
+		.await?)
+}
@@ -0,0 +1,258 @@
+//! Backups of instances.
+//!
+//! A backup is a tar archive containing:
+//! - `backup.json`, the [`BackupManifest`]
+//! - `database.sqlite`, a consistent snapshot of the SQLite database
+//!
+//! Only SQLite deployments are supported.
+//! PostgreSQL databases should be backed up with `pg_dump` instead.
+
+use std::{
+	fs::{self, File},
+	io,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use diesel::{RunQueryDsl, sql_query, sql_types::Text};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::task::spawn_blocking;
+use tracing::info;
+
+use crate::{
+	Result,
+	db::{
+		BoxedSqlConn,
+		service::{DatabaseConfig, DatabaseError, DatabaseService},
+	},
+	job_queue::JobQueue,
+};
+
+const MANIFEST_ENTRY: &str = "backup.json";
+const DATABASE_ENTRY: &str = "database.sqlite";
+
+/// Version of the backup format.
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Manifest of a backup archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+	/// Version of the backup format.
+	pub version: u32,
+	/// Created time in seconds since UNIX epoch.
+	pub created_at: u64,
+	/// Whether the job queue was paused.
+	pub job_queue_paused: bool,
+}
+
+/// Service for exporting and restoring backups.
+#[derive(Debug)]
+pub struct BackupService {
+	config: DatabaseConfig,
+	db: Arc<DatabaseService>,
+	job_queue: Arc<JobQueue>,
+}
+
+impl BackupService {
+	pub fn new(config: DatabaseConfig, db: Arc<DatabaseService>, job_queue: Arc<JobQueue>) -> Self {
+		Self {
+			config,
+			db,
+			job_queue,
+		}
+	}
+
+	/// Exports a snapshot of this instance to a tar archive.
+	///
+	/// The database is copied with `VACUUM INTO`,
+	/// so that the snapshot is consistent without stopping the instance.
+	pub async fn export(&self, archive: &Path) -> Result<BackupManifest> {
+		if self.config.sqlite_path().is_none() {
+			return Err(BackupError::Unsupported.into());
+		}
+		let snapshot = archive.with_extension("sqlite.tmp");
+		let _ = fs::remove_file(&snapshot);
+		{
+			let mut conn = self.db.get().await?;
+			let BoxedSqlConn::Sqlite(conn) = &mut *conn else {
+				return Err(BackupError::Unsupported.into());
+			};
+			sql_query("VACUUM INTO ?")
+				.bind::<Text, _>(snapshot.to_string_lossy().into_owned())
+				.execute(conn)?;
+		}
+
+		let manifest = BackupManifest {
+			version: BACKUP_VERSION,
+			created_at: SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map(|time| time.as_secs())
+				.unwrap_or_default(),
+			job_queue_paused: self.job_queue.is_paused().await?,
+		};
+		let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+
+		let archive = archive.to_owned();
+		spawn_blocking(move || {
+			let result = write_archive(&archive, &manifest_json, &snapshot);
+			let _ = fs::remove_file(&snapshot);
+			result
+		})
+		.await
+		.map_err(DatabaseError::from)?
+		.map_err(BackupError::from)?;
+		info!(?manifest, "exported backup");
+		Ok(manifest)
+	}
+
+	/// Restores the database from a backup archive.
+	///
+	/// This must be called before services are initialized,
+	/// and the database file must not exist yet.
+	/// The returned manifest should be applied with [`BackupService::apply`]
+	/// after services are initialized.
+	pub async fn restore(config: &DatabaseConfig, archive: &Path) -> Result<BackupManifest> {
+		let Some(path) = config.sqlite_path() else {
+			return Err(BackupError::Unsupported.into());
+		};
+		if path.exists() {
+			return Err(BackupError::NotClean(path.to_owned()).into());
+		}
+		let path = path.to_owned();
+		let archive = archive.to_owned();
+		let manifest = spawn_blocking(move || read_archive(&archive, &path))
+			.await
+			.map_err(DatabaseError::from)??;
+		info!(?manifest, "restored database from backup");
+		Ok(manifest)
+	}
+
+	/// Applies states outside the database recorded in a backup.
+	pub async fn apply(&self, manifest: &BackupManifest) -> Result<()> {
+		if manifest.job_queue_paused {
+			self.job_queue.pause().await?;
+		}
+		Ok(())
+	}
+}
+
+fn write_archive(archive: &Path, manifest: &[u8], snapshot: &Path) -> io::Result<()> {
+	let mut builder = tar::Builder::new(File::create(archive)?);
+	let mut header = tar::Header::new_gnu();
+	header.set_size(manifest.len() as u64);
+	header.set_mode(0o644);
+	header.set_cksum();
+	builder.append_data(&mut header, MANIFEST_ENTRY, manifest)?;
+	builder.append_path_with_name(snapshot, DATABASE_ENTRY)?;
+	builder.into_inner()?.sync_all()
+}
+
+fn read_archive(archive: &Path, path: &Path) -> Result<BackupManifest> {
+	let mut manifest = None;
+	let mut restored = false;
+	let mut entries = tar::Archive::new(File::open(archive).map_err(BackupError::from)?);
+	for entry in entries.entries().map_err(BackupError::from)? {
+		let mut entry = entry.map_err(BackupError::from)?;
+		let name = entry.path().map_err(BackupError::from)?.into_owned();
+		if name == Path::new(MANIFEST_ENTRY) {
+			let value = serde_json::from_reader::<_, BackupManifest>(&mut entry)?;
+			if value.version != BACKUP_VERSION {
+				return Err(BackupError::UnknownVersion(value.version).into());
+			}
+			manifest = Some(value);
+		} else if name == Path::new(DATABASE_ENTRY) {
+			entry.unpack(path).map_err(BackupError::from)?;
+			restored = true;
+		}
+	}
+	match manifest {
+		Some(manifest) if restored => Ok(manifest),
+		_ => {
+			let _ = fs::remove_file(path);
+			Err(BackupError::InvalidArchive.into())
+		}
+	}
+}
+
+/// Errors of backups.
+#[derive(Debug, Error)]
+pub enum BackupError {
+	#[error("backups are only supported for SQLite database files, use pg_dump for PostgreSQL")]
+	Unsupported,
+	#[error("database already exists: {0:?}")]
+	NotClean(PathBuf),
+	#[error("unknown backup version: {0}")]
+	UnknownVersion(u32),
+	#[error("invalid backup archive")]
+	InvalidArchive,
+	#[error("I/O error: {0}")]
+	IoError(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+	use std::{env, fs};
+
+	use uuid::Uuid;
+
+	use crate::{
+		BackendError,
+		backup::{BackupError, BackupService},
+		branch::BranchConfigInfo,
+		namespace::DEFAULT_NAMESPACE_ID,
+		test::{test_config, test_env, test_env_with_config},
+	};
+
+	#[tokio::test]
+	async fn test_backup_restore() {
+		let dir = env::temp_dir().join(format!("fabricia-backup-{}", Uuid::now_v7()));
+		fs::create_dir_all(&dir).unwrap();
+		let mut config = test_config();
+		config.redis = None;
+		config.database.url = format!("sqlite://{}", dir.join("source.db").display());
+		let env = test_env_with_config(config.clone()).await;
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "main", BranchConfigInfo::default())
+			.await
+			.unwrap();
+		env.job_queue.pause().await.unwrap();
+
+		let archive = dir.join("backup.tar");
+		let manifest = env.backup.export(&archive).await.unwrap();
+		assert!(manifest.job_queue_paused);
+
+		assert!(matches!(
+			BackupService::restore(&config.database, &archive).await,
+			Err(BackendError::BackupError(BackupError::NotClean(_)))
+		));
+		config.database.url = format!("sqlite://{}", dir.join("restored.db").display());
+		let restored = BackupService::restore(&config.database, &archive)
+			.await
+			.unwrap();
+		assert_eq!(restored, manifest);
+
+		let env = test_env_with_config(config).await;
+		env.backup.apply(&restored).await.unwrap();
+		assert!(env.job_queue.is_paused().await.unwrap());
+		assert!(
+			env.branch
+				.find_id(DEFAULT_NAMESPACE_ID, "main")
+				.await
+				.unwrap()
+				.is_some()
+		);
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_backup_unsupported() {
+		let env = test_env().await;
+		let archive = env::temp_dir().join(format!("fabricia-backup-{}.tar", Uuid::now_v7()));
+		assert!(matches!(
+			env.backup.export(&archive).await,
+			Err(BackendError::BackupError(BackupError::Unsupported))
+		));
+	}
+}
@@ -0,0 +1,150 @@
+//! Seed data of new deployments.
+//!
+//! Bootstrapping creates the first admin token, the namespace of initial
+//! branches and the branches themselves. It can be run again safely, as
+//! existing tokens, namespaces and branches are kept.
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+	BackendServices, Result,
+	admin_token::AdminToken,
+	branch::{BranchImport, BranchManifest, BranchManifestEntry},
+	namespace::{DEFAULT_NAMESPACE, NamespaceConfigInfo, NamespaceRef, NamespaceToken},
+};
+
+/// Configuration of bootstrapping.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BootstrapConfig {
+	/// Name of the first admin token.
+	#[serde(default = "default_admin_token")]
+	pub admin_token: String,
+	/// Namespace of initial branches, created if not existing.
+	#[serde(default = "default_namespace")]
+	pub namespace: String,
+	/// Branches to be tracked initially.
+	#[serde(default)]
+	pub branch: Vec<BranchManifestEntry>,
+}
+
+impl Default for BootstrapConfig {
+	fn default() -> Self {
+		Self {
+			admin_token: default_admin_token(),
+			namespace: default_namespace(),
+			branch: Vec::new(),
+		}
+	}
+}
+
+fn default_admin_token() -> String {
+	"admin".to_string()
+}
+
+fn default_namespace() -> String {
+	DEFAULT_NAMESPACE.to_string()
+}
+
+/// Outcome of bootstrapping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootstrapReport {
+	/// The admin token, or [`None`] if admin tokens have existed.
+	pub admin_token: Option<AdminToken>,
+	pub namespace: NamespaceRef,
+	/// Token of the namespace, or [`None`] if the namespace has existed.
+	pub namespace_token: Option<NamespaceToken>,
+	/// Import of branches not existing yet, or [`None`] if there is none.
+	pub import: Option<BranchImport>,
+}
+
+/// Creates seed data of a new deployment.
+pub async fn bootstrap(
+	services: &BackendServices,
+	config: &BootstrapConfig,
+) -> Result<BootstrapReport> {
+	let admin_token = if services.admin_token.exists().await? {
+		info!("admin tokens have existed, skipping");
+		None
+	} else {
+		Some(services.admin_token.create(&config.admin_token).await?)
+	};
+
+	let (namespace, namespace_token) =
+		match services.namespace.get_by_name(&config.namespace).await? {
+			Some(namespace) => (namespace.id, None),
+			None => {
+				let token = services
+					.namespace
+					.create(&config.namespace, NamespaceConfigInfo::default())
+					.await?;
+				(token.id, Some(token))
+			}
+		};
+
+	let mut branches = Vec::with_capacity(config.branch.len());
+	for entry in &config.branch {
+		if services
+			.branch
+			.find_id(namespace, &entry.name)
+			.await?
+			.is_none()
+		{
+			branches.push(entry.clone());
+		}
+	}
+	let import = if branches.is_empty() {
+		None
+	} else {
+		Some(
+			services
+				.branch
+				.import(namespace, BranchManifest { branches })
+				.await?,
+		)
+	};
+
+	Ok(BootstrapReport {
+		admin_token,
+		namespace,
+		namespace_token,
+		import,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{
+		branch::{BranchConfigInfo, BranchManifestEntry},
+		namespace::DEFAULT_NAMESPACE_ID,
+		test::test_env,
+	};
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_bootstrap() {
+		let env = test_env().await;
+		let config = BootstrapConfig {
+			branch: vec![BranchManifestEntry {
+				name: "main".to_string(),
+				config: BranchConfigInfo::default(),
+			}],
+			..Default::default()
+		};
+
+		let report = bootstrap(&env, &config).await.unwrap();
+		let token = report.admin_token.unwrap();
+		assert!(env.admin_token.authorize(Some(&token.token)).await.unwrap());
+		assert_eq!(report.namespace, DEFAULT_NAMESPACE_ID);
+		assert!(report.namespace_token.is_none());
+		let import = report.import.unwrap();
+		assert_eq!(import.branches.len(), 1);
+		assert!(import.branches[0].result.is_ok());
+
+		let report = bootstrap(&env, &config).await.unwrap();
+		assert!(report.admin_token.is_none());
+		assert!(report.import.is_none());
+	}
+}
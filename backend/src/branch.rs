@@ -1,23 +1,43 @@
-use std::sync::Arc;
+use std::{collections::HashSet, fmt::Debug, sync::Arc};
 
+use async_trait::async_trait;
 use diesel::{
-	ExpressionMethods, OptionalExtension, QueryDsl, delete, insert_into,
+	BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, delete,
+	deserialize::FromSqlRow,
+	expression::AsExpression,
+	insert_into,
 	prelude::{AsChangeset, Identifiable},
+	result::DatabaseErrorKind,
+	sql_types::SmallInt,
 	update,
 };
-use fabricia_common_model::branch::{BranchStatus, TrackingMode};
+use fabricia_common_model::{
+	branch::{BranchStatus, RemovalPolicy, TrackingMode},
+	git::GitOid,
+};
 use kstring::KString;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::info;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::{info, warn};
 
 use crate::{
 	Result,
+	branch_acl::{BranchAclService, Principal},
+	bus::{BackendBusMessage, BoxedBusService},
 	db::{
-		schema::{self, branch::dsl},
+		BoxedSqlConn, DEFAULT_TRANSACTION_ATTEMPTS,
+		schema::{self, branch::dsl, branch_event::dsl as event_dsl},
 		service::DatabaseService,
+		utils::small_int_enum,
 	},
-	job_queue::{JobCommand, JobQueue},
+	job_queue::{JobCommand, JobQueue, JobRef},
+	model::{BranchEventRow, NewBranchEventRow, NewBranchRow},
+	namespace::{NamespaceRef, NamespaceService},
+	operation::{OperationRef, OperationService},
+	package::StatusActor,
+	repository::{RepositoryRef, RepositoryService},
+	target::TargetService,
 };
 
 pub type BranchRef = i64;
@@ -25,7 +45,8 @@ pub type BranchRef = i64;
 /// State of a branch.
 ///
 /// Stored as a tiny unsigned column. Unknown values are decoded as suspended.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = SmallInt)]
 #[repr(u8)]
 pub enum SqlBranchStatus {
 	/// State for branches needing refresh.
@@ -76,6 +97,8 @@ impl From<i16> for SqlBranchStatus {
 	}
 }
 
+small_int_enum!(SqlBranchStatus);
+
 impl SqlBranchStatus {
 	pub fn into_common(&self, message: Option<String>) -> BranchStatus {
 		match self {
@@ -92,7 +115,8 @@ impl SqlBranchStatus {
 }
 
 /// Database representation of [TrackingMode].
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = SmallInt)]
 #[repr(u8)]
 pub enum SqlTrackingMode {
 	/// [TrackingMode::Auto]
@@ -117,6 +141,8 @@ impl From<i16> for SqlTrackingMode {
 	}
 }
 
+small_int_enum!(SqlTrackingMode);
+
 impl From<TrackingMode> for SqlTrackingMode {
 	fn from(value: TrackingMode) -> Self {
 		match value {
@@ -135,61 +161,317 @@ impl From<SqlTrackingMode> for TrackingMode {
 	}
 }
 
+/// Database representation of [RemovalPolicy].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = SmallInt)]
+#[repr(u8)]
+pub enum SqlRemovalPolicy {
+	/// [RemovalPolicy::Drop]
+	Drop = 0,
+	/// [RemovalPolicy::Archive]
+	Archive = 1,
+	/// [RemovalPolicy::KeepWithWarning]
+	KeepWithWarning = 2,
+}
+
+impl From<u8> for SqlRemovalPolicy {
+	fn from(value: u8) -> Self {
+		Self::from(value as i16)
+	}
+}
+
+impl From<i16> for SqlRemovalPolicy {
+	fn from(value: i16) -> Self {
+		match value {
+			0 => Self::Drop,
+			1 => Self::Archive,
+			2 => Self::KeepWithWarning,
+			// keeping is the safest for policies of newer versions
+			_ => Self::KeepWithWarning,
+		}
+	}
+}
+
+small_int_enum!(SqlRemovalPolicy);
+
+impl From<RemovalPolicy> for SqlRemovalPolicy {
+	fn from(value: RemovalPolicy) -> Self {
+		match value {
+			RemovalPolicy::Drop => Self::Drop,
+			RemovalPolicy::Archive => Self::Archive,
+			RemovalPolicy::KeepWithWarning => Self::KeepWithWarning,
+		}
+	}
+}
+
+impl From<SqlRemovalPolicy> for RemovalPolicy {
+	fn from(value: SqlRemovalPolicy) -> Self {
+		match value {
+			SqlRemovalPolicy::Drop => Self::Drop,
+			SqlRemovalPolicy::Archive => Self::Archive,
+			SqlRemovalPolicy::KeepWithWarning => Self::KeepWithWarning,
+		}
+	}
+}
+
+/// Operations on branches used by API handlers.
+///
+/// Implemented by [`BranchService`], and mocked by `MockBranchApi`
+/// with the `mock` feature.
+#[cfg_attr(feature = "mock", mockall::automock)]
+#[async_trait]
+pub trait BranchApi: Send + Sync + Debug {
+	/// See [`BranchService::track_in`].
+	async fn track_in(
+		&self,
+		conn: &mut BoxedSqlConn,
+		namespace: NamespaceRef,
+		name: &str,
+		info: BranchConfigInfo,
+	) -> Result<OperationRef>;
+	/// See [`BranchService::find_id`].
+	async fn find_id(&self, namespace: NamespaceRef, name: &str) -> Result<Option<BranchRef>>;
+	/// See [`BranchService::find_id_or_err`].
+	async fn find_id_or_err(&self, namespace: NamespaceRef, name: &str) -> Result<BranchRef>;
+	/// See [`BranchService::untrack`].
+	async fn untrack(&self, id: BranchRef, principal: &Principal) -> Result<OperationRef>;
+	/// See [`BranchService::update_config`].
+	async fn update_config(
+		&self,
+		id: BranchRef,
+		info: &BranchConfigInfo,
+		version: Option<i64>,
+		principal: &Principal,
+	) -> Result<i64>;
+	/// See [`BranchService::unpin`].
+	async fn unpin(&self, id: BranchRef, principal: &Principal) -> Result<i64>;
+	/// See [`BranchService::import`].
+	async fn import(
+		&self,
+		namespace: NamespaceRef,
+		manifest: BranchManifest,
+	) -> Result<BranchImport>;
+	/// See [`BranchService::timeline`].
+	async fn timeline(&self, id: BranchRef) -> Result<Vec<BranchEventRow>>;
+}
+
 #[derive(Debug)]
 pub struct BranchService {
 	db: Arc<DatabaseService>,
+	target: Arc<TargetService>,
+	repository: Arc<RepositoryService>,
 	job_queue: Arc<JobQueue>,
+	operation: Arc<OperationService>,
+	bus: Arc<BoxedBusService>,
 }
 
 impl BranchService {
-	pub fn new(db: Arc<DatabaseService>, job_queue: Arc<JobQueue>) -> Self {
-		Self { db, job_queue }
+	pub fn new(
+		db: Arc<DatabaseService>,
+		target: Arc<TargetService>,
+		repository: Arc<RepositoryService>,
+		job_queue: Arc<JobQueue>,
+		operation: Arc<OperationService>,
+		bus: Arc<BoxedBusService>,
+	) -> Self {
+		Self {
+			db,
+			target,
+			repository,
+			job_queue,
+			operation,
+			bus,
+		}
 	}
 
-	/// Tracks a new branch.
-	pub async fn track(&self, name: &str, info: BranchConfigInfo) -> Result<()> {
+	/// Tracks a new branch in a namespace.
+	///
+	/// The base branch is resolved in the same namespace and transaction as the insertion.
+	/// Returns the operation linked to the initial synchronization job.
+	pub async fn track(
+		&self,
+		namespace: NamespaceRef,
+		name: &str,
+		info: BranchConfigInfo,
+	) -> Result<OperationRef> {
 		let mut conn = self.db.get().await?;
+		self.track_in(&mut conn, namespace, name, info).await
+	}
+
+	/// Tracks a new branch with the given connection.
+	///
+	/// If the connection is in a transaction, a savepoint is used.
+	pub async fn track_in(
+		&self,
+		conn: &mut BoxedSqlConn,
+		namespace: NamespaceRef,
+		name: &str,
+		info: BranchConfigInfo,
+	) -> Result<OperationRef> {
+		let (operation, _) = self.track_with_job(conn, namespace, name, info).await?;
+		Ok(operation)
+	}
+
+	/// Tracks a new branch, and returns the created operation and synchronization job.
+	async fn track_with_job(
+		&self,
+		conn: &mut BoxedSqlConn,
+		namespace: NamespaceRef,
+		name: &str,
+		info: BranchConfigInfo,
+	) -> Result<(OperationRef, JobRef)> {
 		let branch = name.to_owned();
+		let repository = match info.repository.as_deref() {
+			Some(repository) if !repository.is_empty() => {
+				Some(self.resolve_repository(repository)?)
+			}
+			_ => None,
+		};
+		let target_group = match info.target_group.as_deref() {
+			Some(group) if !group.is_empty() => Some(self.check_target_group(group)?),
+			_ => None,
+		};
 
-		conn.transaction::<(), crate::BackendError, _>(async |conn| {
-			let base = match info.base {
-				Some(base) => Some(self.find_id_or_err(base).await?),
-				None => None,
-			};
-			let priority = info.priority.unwrap_or(100) as u16;
-
-			let id = conn
-				.get_result::<_, i64>(
-					insert_into(dsl::branch)
-						.values((
-							dsl::name.eq(&branch),
-							dsl::status.eq(SqlBranchStatus::Dirty as i16),
-							dsl::base.eq(base),
-							dsl::priority.eq(priority as i16),
-							dsl::tracking.eq(SqlTrackingMode::from(
-								info.tracking_mode.unwrap_or(TrackingMode::Auto),
-							) as i16),
-						))
-						.returning(dsl::id),
+		let (operation, job) = conn
+			.transaction::<_, crate::BackendError, _>(async |conn| {
+				let base = match &info.base {
+					Some(base) if base.as_str() == branch => {
+						return Err(BranchError::SelfBase(base.clone()).into());
+					}
+					Some(base) => Some(Self::resolve_base(conn, namespace, base).await?),
+					None => None,
+				};
+				NamespaceService::check_branch_quota(conn, namespace).await?;
+				let priority = info.priority.unwrap_or(100) as u16;
+				let time = OffsetDateTime::now_utc();
+				let time = PrimitiveDateTime::new(time.date(), time.time());
+
+				let id = conn
+					.get_result::<_, i64>(
+						insert_into(dsl::branch)
+							.values(NewBranchRow {
+								namespace,
+								repository,
+								target_group,
+								name: &branch,
+								base,
+								status: SqlBranchStatus::Dirty,
+								priority: priority as i16,
+								pinned_commit: info.pinned_commit.as_ref().map(GitOid::as_bytes),
+								tracking: SqlTrackingMode::from(
+									info.tracking_mode.unwrap_or(TrackingMode::Auto),
+								),
+								removal_policy: SqlRemovalPolicy::from(
+									info.removal_policy.unwrap_or_default(),
+								),
+								max_running_jobs: info.max_running_jobs.and_then(quota_limit),
+								max_queued_jobs: info.max_queued_jobs.and_then(quota_limit),
+								created_at: time,
+								updated_at: time,
+							})
+							.returning(dsl::id),
+					)
+					.await
+					.map_err(|error| match error {
+						diesel::result::Error::DatabaseError(
+							DatabaseErrorKind::UniqueViolation,
+							_,
+						) => BranchError::AlreadyExists(KString::from_ref(&branch)).into(),
+						error => crate::BackendError::from(error),
+					})?;
+				Self::record_status(
+					conn,
+					id,
+					SqlBranchStatus::Dirty,
+					Some("tracked"),
+					&StatusActor::System,
 				)
 				.await?;
-			self.job_queue
-				.enqueue_with_priority(conn, JobCommand::SyncBranch(id), priority)
-				.await?;
+				let job = self
+					.job_queue
+					.enqueue_with_priority(conn, JobCommand::SyncBranch(id), priority)
+					.await?;
 
-			Ok(())
-		})
-		.await?;
-		info!(branch, "tracked branch");
+				let operation = self
+					.operation
+					.create(conn, "track", Some(&branch), &[job])
+					.await?;
+				Ok((operation, job))
+			})
+			.await?;
+		info!(namespace, branch, "tracked branch");
 
-		Ok(())
+		Ok((operation, job))
+	}
+
+	/// Tracks branches listed in a manifest, e.g. exported from another instance.
+	///
+	/// Branches are tracked one by one, after their bases in the same manifest.
+	/// Failing to track a branch does not affect others. An `import` operation
+	/// is created with synchronization jobs of all tracked branches.
+	pub async fn import(
+		&self,
+		namespace: NamespaceRef,
+		manifest: BranchManifest,
+	) -> Result<BranchImport> {
+		let mut conn = self.db.get().await?;
+		let mut branches = Vec::with_capacity(manifest.branches.len());
+		let mut jobs = Vec::with_capacity(manifest.branches.len());
+		for entry in manifest.into_ordered() {
+			let result = self
+				.track_with_job(&mut conn, namespace, &entry.name, entry.config)
+				.await;
+			let result = match result {
+				Ok((operation, job)) => {
+					jobs.push(job);
+					Ok(operation)
+				}
+				Err(error) => {
+					warn!(branch = %entry.name, %error, "failed to import branch");
+					Err(error.to_string())
+				}
+			};
+			branches.push(ImportedBranch {
+				name: entry.name,
+				result,
+			});
+		}
+
+		let operation = self
+			.operation
+			.create(&mut conn, "import", None, &jobs)
+			.await?;
+		info!(
+			%operation,
+			branches = branches.len(),
+			tracked = jobs.len(),
+			"imported branches"
+		);
+		Ok(BranchImport {
+			operation,
+			branches,
+		})
 	}
 
-	pub async fn find_id<S: AsRef<str>>(&self, name: S) -> Result<Option<BranchRef>> {
+	pub async fn find_id<S: AsRef<str>>(
+		&self,
+		namespace: NamespaceRef,
+		name: S,
+	) -> Result<Option<BranchRef>> {
 		let mut conn = self.db.get().await?;
+		Self::find_id_in(&mut conn, namespace, name).await
+	}
+
+	/// Finds the ID of a branch with the given connection.
+	pub async fn find_id_in<S: AsRef<str>>(
+		conn: &mut BoxedSqlConn,
+		namespace: NamespaceRef,
+		name: S,
+	) -> Result<Option<BranchRef>> {
 		Ok(conn
 			.get_result(
 				dsl::branch
+					.filter(dsl::namespace.eq(namespace))
 					.filter(dsl::name.eq(name.as_ref()))
 					.select(dsl::id),
 			)
@@ -197,57 +479,396 @@ impl BranchService {
 			.optional()?)
 	}
 
-	pub async fn find_id_or_err<S: AsRef<str>>(&self, name: S) -> Result<BranchRef> {
+	/// Finds the ID of a base branch in a transaction.
+	async fn resolve_base(
+		conn: &mut BoxedSqlConn,
+		namespace: NamespaceRef,
+		name: &KString,
+	) -> Result<BranchRef> {
+		Ok(conn
+			.get_result(
+				dsl::branch
+					.filter(dsl::namespace.eq(namespace))
+					.filter(dsl::name.eq(name.as_str()))
+					.select(dsl::id),
+			)
+			.await
+			.optional()?
+			.ok_or_else(|| BranchError::BaseNotFound(name.clone()))?)
+	}
+
+	/// Finds the ID of a configured repository.
+	fn resolve_repository(&self, name: &str) -> Result<RepositoryRef> {
+		Ok(self
+			.repository
+			.find(name)
+			.ok_or_else(|| BranchError::RepositoryNotFound(KString::from_ref(name)))?
+			.id)
+	}
+
+	/// Ensures that a target group is configured.
+	fn check_target_group<'a>(&self, group: &'a str) -> Result<&'a str> {
+		if self.target.has_group(group) {
+			Ok(group)
+		} else {
+			Err(BranchError::TargetGroupNotFound(KString::from_ref(group)).into())
+		}
+	}
+
+	pub async fn find_id_or_err<S: AsRef<str>>(
+		&self,
+		namespace: NamespaceRef,
+		name: S,
+	) -> Result<BranchRef> {
 		Ok(self
-			.find_id(&name)
+			.find_id(namespace, &name)
 			.await?
 			.ok_or_else(|| BranchError::BranchNameNotFound(KString::from_ref(name.as_ref())))?)
 	}
 
 	/// Untracks a new branch.
-	pub async fn untrack(&self, id: BranchRef) -> Result<()> {
+	///
+	/// The principal must be allowed by the ACL of the branch.
+	/// Jobs of the branch are cancelled, and runners of its running jobs are
+	/// notified over the bus to abort them.
+	/// Returns the operation, which is completed immediately.
+	pub async fn untrack(&self, id: BranchRef, principal: &Principal) -> Result<OperationRef> {
 		let mut conn = self.db.get().await?;
 
-		conn.transaction::<(), crate::BackendError, _>(async |conn| {
-			non_zero_or_not_found(
-				conn.execute(delete(dsl::branch).filter(dsl::id.eq(id)))
-					.await?,
-				id,
-			)?;
+		let (operation, cancelled) = conn
+			.transaction_with_retries::<_, crate::BackendError, _>(
+				DEFAULT_TRANSACTION_ATTEMPTS,
+				async |conn| {
+					BranchAclService::check(conn, id, principal).await?;
+					let name = conn
+						.get_result::<_, String>(
+							delete(dsl::branch)
+								.filter(dsl::id.eq(id))
+								.returning(dsl::name),
+						)
+						.await
+						.optional()?
+						.ok_or(BranchError::BranchNotFound(id))?;
 
-			Ok(())
-		})
-		.await?;
+					let time = OffsetDateTime::now_utc();
+					conn.execute(update(dsl::branch).filter(dsl::base.eq(id)).set((
+						dsl::base.eq(None::<BranchRef>),
+						dsl::updated_at.eq(PrimitiveDateTime::new(time.date(), time.time())),
+					)))
+					.await?;
+					conn.execute(delete(event_dsl::branch_event).filter(event_dsl::branch.eq(id)))
+						.await?;
+					BranchAclService::clear(conn, id).await?;
+					let cancelled = self.job_queue.cancel_branch(conn, id).await?;
+
+					let operation = self
+						.operation
+						.create(conn, "untrack", Some(&name), &[])
+						.await?;
+					Ok((operation, cancelled))
+				},
+			)
+			.await?;
 		info!(id, "untracked branch");
+		if !cancelled.is_empty() {
+			// the branch is gone even if runners are not notified
+			if let Err(error) = self
+				.bus
+				.broadcast(BackendBusMessage::JobsCancelled(cancelled))
+				.await
+			{
+				warn!(id, %error, "failed to notify runners of cancelled jobs");
+			}
+		}
 
-		Ok(())
+		Ok(operation)
 	}
 
-	pub async fn update_config(&self, id: BranchRef, info: &BranchConfigInfo) -> Result<()> {
-		let mut conn = self.db.get().await?;
-		let base = match &info.base {
-			Some(base) => {
-				if base.is_empty() {
-					Some(None)
-				} else {
-					Some(Some(self.find_id_or_err(base).await?))
-				}
-			}
+	/// Updates configuration of a branch, and returns the new version.
+	///
+	/// If `version` is given, the update is only applied when the configuration
+	/// has not been changed since that version.
+	/// The principal must be allowed by the ACL of the branch.
+	/// The base branch is resolved and checked against cycles in the same transaction
+	/// as the update.
+	pub async fn update_config(
+		&self,
+		id: BranchRef,
+		info: &BranchConfigInfo,
+		version: Option<i64>,
+		principal: &Principal,
+	) -> Result<i64> {
+		let repository = match info.repository.as_deref() {
+			Some("") => Some(None),
+			Some(repository) => Some(Some(self.resolve_repository(repository)?)),
 			None => None,
 		};
+		let target_group = match info.target_group.as_deref() {
+			Some("") => Some(None),
+			Some(group) => Some(Some(self.check_target_group(group)?)),
+			None => None,
+		};
+		let mut conn = self.db.get().await?;
+
+		let new_version = conn
+			.transaction_with_retries::<_, crate::BackendError, _>(
+				DEFAULT_TRANSACTION_ATTEMPTS,
+				async |conn| {
+					BranchAclService::check(conn, id, principal).await?;
+					let base = match &info.base {
+						Some(base) if base.is_empty() => Some(None),
+						Some(base) => {
+							let namespace = conn
+								.get_result::<_, NamespaceRef>(
+									dsl::branch.filter(dsl::id.eq(id)).select(dsl::namespace),
+								)
+								.await
+								.optional()?
+								.ok_or(BranchError::BranchNotFound(id))?;
+							let base = Self::resolve_base(conn, namespace, base).await?;
+							Self::check_base_cycle(conn, id, base).await?;
+							Some(Some(base))
+						}
+						None => None,
+					};
+
+					let time = OffsetDateTime::now_utc();
+					let changeset = (
+						SqlBranchConfig {
+							id,
+							base,
+							repository,
+							target_group,
+							priority: info.priority.map(|pri| pri as i16),
+							tracking: info.tracking_mode.map(SqlTrackingMode::from),
+							removal_policy: info.removal_policy.map(SqlRemovalPolicy::from),
+							max_running_jobs: info.max_running_jobs.map(quota_limit),
+							max_queued_jobs: info.max_queued_jobs.map(quota_limit),
+							pinned_commit: info.pinned_commit.as_ref().map(GitOid::as_bytes),
+						},
+						dsl::version.eq(dsl::version + 1),
+						dsl::updated_at.eq(PrimitiveDateTime::new(time.date(), time.time())),
+					);
+					let new_version = match version {
+						Some(version) => {
+							conn.get_result::<_, i64>(
+								update(dsl::branch)
+									.filter(dsl::id.eq(id).and(dsl::version.eq(version)))
+									.set(changeset)
+									.returning(dsl::version),
+							)
+							.await
+						}
+						None => {
+							conn.get_result::<_, i64>(
+								update(dsl::branch)
+									.filter(dsl::id.eq(id))
+									.set(changeset)
+									.returning(dsl::version),
+							)
+							.await
+						}
+					}
+					.optional()?;
+
+					if let Some(new_version) = new_version {
+						Ok(new_version)
+					} else if version.is_some() && Self::exists(conn, id).await? {
+						Err(BranchError::VersionMismatch(id).into())
+					} else {
+						Err(BranchError::BranchNotFound(id).into())
+					}
+				},
+			)
+			.await?;
+		info!(id, new_version, "updated branch configuration");
+
+		Ok(new_version)
+	}
+
+	/// Unpins a branch, so that syncs follow the head of the Git branch again,
+	/// and returns the new version of the configuration.
+	///
+	/// The principal must be allowed by the ACL of the branch.
+	pub async fn unpin(&self, id: BranchRef, principal: &Principal) -> Result<i64> {
+		let time = OffsetDateTime::now_utc();
+		let mut conn = self.db.get().await?;
+		let new_version = conn
+			.transaction::<_, crate::BackendError, _>(async |conn| {
+				BranchAclService::check(conn, id, principal).await?;
+				conn.get_result::<_, i64>(
+					update(dsl::branch)
+						.filter(dsl::id.eq(id))
+						.set((
+							dsl::pinned_commit.eq(None::<Vec<u8>>),
+							dsl::version.eq(dsl::version + 1),
+							dsl::updated_at.eq(PrimitiveDateTime::new(time.date(), time.time())),
+						))
+						.returning(dsl::version),
+				)
+				.await
+				.optional()?
+				.ok_or_else(|| BranchError::BranchNotFound(id).into())
+			})
+			.await?;
+		info!(id, new_version, "unpinned branch");
 
-		non_zero_or_not_found(
-			conn.execute(update(dsl::branch).set(SqlBranchConfig {
+		Ok(new_version)
+	}
+
+	/// Ensures that setting `base` as the base branch of `id` does not form a cycle.
+	async fn check_base_cycle(
+		conn: &mut BoxedSqlConn,
+		id: BranchRef,
+		base: BranchRef,
+	) -> Result<()> {
+		let mut ancestor = Some(base);
+		let mut visited = HashSet::new();
+		while let Some(current) = ancestor {
+			if current == id || !visited.insert(current) {
+				return Err(BranchError::BaseCycle(id).into());
+			}
+			ancestor = conn
+				.get_result::<_, Option<BranchRef>>(
+					dsl::branch.filter(dsl::id.eq(current)).select(dsl::base),
+				)
+				.await
+				.optional()?
+				.flatten();
+		}
+		Ok(())
+	}
+
+	async fn exists(conn: &mut BoxedSqlConn, id: BranchRef) -> Result<bool> {
+		Ok(conn
+			.get_result::<_, i64>(dsl::branch.filter(dsl::id.eq(id)).select(dsl::id))
+			.await
+			.optional()?
+			.is_some())
+	}
+
+	/// Marks a branch as failed with a branch-level error.
+	///
+	/// The reason is truncated to fit into the status message column.
+	pub async fn mark_error(&self, id: BranchRef, reason: &str, actor: &StatusActor) -> Result<()> {
+		let reason = reason.chars().take(256).collect::<String>();
+		self.set_status(id, SqlBranchStatus::Error, Some(&reason), actor)
+			.await?;
+		info!(id, reason, "marked branch as failed");
+		Ok(())
+	}
+
+	/// Sets the status of a branch, recording the transition in its timeline.
+	///
+	/// Suspensions should be given a message explaining why.
+	pub async fn set_status(
+		&self,
+		id: BranchRef,
+		status: SqlBranchStatus,
+		message: Option<&str>,
+		actor: &StatusActor,
+	) -> Result<()> {
+		let time = OffsetDateTime::now_utc();
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			non_zero_or_not_found(
+				conn.execute(update(dsl::branch).filter(dsl::id.eq(id)).set((
+					dsl::status.eq(status),
+					dsl::status_msg.eq(message),
+					dsl::updated_at.eq(PrimitiveDateTime::new(time.date(), time.time())),
+				)))
+				.await?,
 				id,
-				base,
-				priority: info.priority.map(|pri| pri as i16),
-				tracking: info.tracking_mode.map(|mode| mode as i16),
-			}))
-			.await?,
-			id,
-		)?;
+			)?;
+			Self::record_status(conn, id, status, message, actor).await
+		})
+		.await
+	}
+
+	/// Records a status transition of a branch.
+	async fn record_status(
+		conn: &mut BoxedSqlConn,
+		id: BranchRef,
+		status: SqlBranchStatus,
+		message: Option<&str>,
+		actor: &StatusActor,
+	) -> Result<()> {
+		let time = OffsetDateTime::now_utc();
+		conn.execute(
+			insert_into(event_dsl::branch_event).values(NewBranchEventRow {
+				branch: id,
+				status,
+				message,
+				actor: &actor.to_string(),
+				created_at: PrimitiveDateTime::new(time.date(), time.time()),
+			}),
+		)
+		.await?;
 		Ok(())
 	}
+
+	/// Lists status transitions of a branch, newest first.
+	pub async fn timeline(&self, id: BranchRef) -> Result<Vec<BranchEventRow>> {
+		let mut conn = self.db.get().await?;
+		Ok(conn
+			.load_select(
+				event_dsl::branch_event
+					.filter(event_dsl::branch.eq(id))
+					.order(event_dsl::id.desc()),
+			)
+			.await?)
+	}
+}
+
+#[async_trait]
+impl BranchApi for BranchService {
+	async fn track_in(
+		&self,
+		conn: &mut BoxedSqlConn,
+		namespace: NamespaceRef,
+		name: &str,
+		info: BranchConfigInfo,
+	) -> Result<OperationRef> {
+		BranchService::track_in(self, conn, namespace, name, info).await
+	}
+
+	async fn find_id(&self, namespace: NamespaceRef, name: &str) -> Result<Option<BranchRef>> {
+		BranchService::find_id(self, namespace, name).await
+	}
+
+	async fn find_id_or_err(&self, namespace: NamespaceRef, name: &str) -> Result<BranchRef> {
+		BranchService::find_id_or_err(self, namespace, name).await
+	}
+
+	async fn untrack(&self, id: BranchRef, principal: &Principal) -> Result<OperationRef> {
+		BranchService::untrack(self, id, principal).await
+	}
+
+	async fn update_config(
+		&self,
+		id: BranchRef,
+		info: &BranchConfigInfo,
+		version: Option<i64>,
+		principal: &Principal,
+	) -> Result<i64> {
+		BranchService::update_config(self, id, info, version, principal).await
+	}
+
+	async fn unpin(&self, id: BranchRef, principal: &Principal) -> Result<i64> {
+		BranchService::unpin(self, id, principal).await
+	}
+
+	async fn import(
+		&self,
+		namespace: NamespaceRef,
+		manifest: BranchManifest,
+	) -> Result<BranchImport> {
+		BranchService::import(self, namespace, manifest).await
+	}
+
+	async fn timeline(&self, id: BranchRef) -> Result<Vec<BranchEventRow>> {
+		BranchService::timeline(self, id).await
+	}
 }
 
 #[derive(Debug, Error)]
@@ -256,6 +877,22 @@ pub enum BranchError {
 	BranchNameNotFound(KString),
 	#[error("branch {0} not found")]
 	BranchNotFound(BranchRef),
+	#[error("branch {0} has been modified by others")]
+	VersionMismatch(BranchRef),
+	#[error("base of branch {0} would form a cycle")]
+	BaseCycle(BranchRef),
+	#[error("base branch {0} not found")]
+	BaseNotFound(KString),
+	#[error("branch {0} cannot be its own base")]
+	SelfBase(KString),
+	#[error("branch {0} has already been tracked")]
+	AlreadyExists(KString),
+	#[error("repository {0} not found")]
+	RepositoryNotFound(KString),
+	#[error("target group {0} not found")]
+	TargetGroupNotFound(KString),
+	#[error("access to branch {0} is denied by its ACL")]
+	AccessDenied(BranchRef),
 }
 
 fn non_zero_or_not_found(val: usize, id: BranchRef) -> Result<(), BranchError> {
@@ -266,43 +903,214 @@ fn non_zero_or_not_found(val: usize, id: BranchRef) -> Result<(), BranchError> {
 	}
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+/// Converts a quota in branch configuration into the column value.
+///
+/// Zero means no limit.
+fn quota_limit(limit: u32) -> Option<i32> {
+	if limit == 0 {
+		None
+	} else {
+		Some(limit.min(i32::MAX as u32) as i32)
+	}
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize, Default)]
 pub struct BranchConfigInfo {
 	/// Name of the base branch of this branch.
 	///
 	/// Set this to empty string to remove base branch.
 	pub base: Option<KString>,
+	/// Name of the repository of this branch.
+	///
+	/// Set this to empty string to use the default repository.
+	pub repository: Option<KString>,
+	/// Name of the target group to build for.
+	///
+	/// Set this to empty string to build for all targets.
+	pub target_group: Option<KString>,
 	pub priority: Option<u16>,
 	pub tracking_mode: Option<TrackingMode>,
+	/// Handling of packages removed upstream which have been built.
+	pub removal_policy: Option<RemovalPolicy>,
+	/// The maximum count of running jobs of this branch.
+	///
+	/// Set this to zero to remove the limit.
+	pub max_running_jobs: Option<u32>,
+	/// The maximum count of pending jobs of this branch.
+	///
+	/// Set this to zero to remove the limit.
+	pub max_queued_jobs: Option<u32>,
+	/// Commit to pin the branch to, e.g. for release freezes.
+	///
+	/// Syncs check out this commit regardless of the head of the Git branch,
+	/// until the branch is unpinned with [`BranchService::unpin`].
+	pub pinned_commit: Option<GitOid>,
+}
+
+/// A list of branches to be imported, see [`BranchService::import`].
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub struct BranchManifest {
+	pub branches: Vec<BranchManifestEntry>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct BranchManifestEntry {
+	pub name: String,
+	#[serde(flatten)]
+	pub config: BranchConfigInfo,
+}
+
+impl BranchManifest {
+	/// Orders branches so that bases in the manifest are tracked before derived branches.
+	///
+	/// Branches with cyclic bases are put at the end, and will fail to be tracked.
+	fn into_ordered(self) -> Vec<BranchManifestEntry> {
+		let names = self
+			.branches
+			.iter()
+			.map(|entry| entry.name.clone())
+			.collect::<HashSet<_>>();
+		let mut ordered = Vec::with_capacity(self.branches.len());
+		let mut tracked = HashSet::new();
+		let mut pending = self.branches;
+		while !pending.is_empty() {
+			let (ready, rest): (Vec<_>, Vec<_>) =
+				pending
+					.into_iter()
+					.partition(|entry| match entry.config.base.as_deref() {
+						Some(base) if !base.is_empty() && names.contains(base) => {
+							tracked.contains(base)
+						}
+						_ => true,
+					});
+			if ready.is_empty() {
+				ordered.extend(rest);
+				break;
+			}
+			tracked.extend(ready.iter().map(|entry| entry.name.clone()));
+			ordered.extend(ready);
+			pending = rest;
+		}
+		ordered
+	}
+}
+
+/// Result of [`BranchService::import`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchImport {
+	/// Operation with synchronization jobs of all tracked branches.
+	pub operation: OperationRef,
+	/// Results of branches, in the order they have been tracked.
+	pub branches: Vec<ImportedBranch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedBranch {
+	pub name: String,
+	/// The `track` operation of the branch, or the error message.
+	pub result: std::result::Result<OperationRef, String>,
 }
 
 #[derive(Debug, Identifiable, AsChangeset)]
 #[diesel(table_name = schema::branch)]
-pub struct SqlBranchConfig {
+pub struct SqlBranchConfig<'a> {
 	id: BranchRef,
 	base: Option<Option<BranchRef>>,
+	repository: Option<Option<RepositoryRef>>,
+	target_group: Option<Option<&'a str>>,
 	priority: Option<i16>,
-	tracking: Option<i16>,
+	tracking: Option<SqlTrackingMode>,
+	removal_policy: Option<SqlRemovalPolicy>,
+	max_running_jobs: Option<Option<i32>>,
+	max_queued_jobs: Option<Option<i32>>,
+	pinned_commit: Option<&'a [u8]>,
 }
 
 #[cfg(test)]
 mod test {
-	use diesel::QueryDsl;
+	use diesel::{ExpressionMethods, QueryDsl};
+	use fabricia_common_model::git::GitOid;
+	use kstring::KString;
+	use uuid::Uuid;
 
-	use crate::{db::schema::branch::dsl, job_queue::JobCommand, test::test_env};
+	use crate::{
+		BackendError,
+		branch::{
+			BranchConfigInfo, BranchError, BranchManifest, BranchManifestEntry, SqlBranchStatus,
+		},
+		branch_acl::Principal,
+		bus::{BackendBusMessage, LocalBusMessage},
+		db::schema::branch::dsl,
+		job_history::SqlJobOutcome,
+		job_queue::JobCommand,
+		model::BranchRow,
+		namespace::DEFAULT_NAMESPACE_ID,
+		package::StatusActor,
+		test::test_env,
+	};
+
+	#[tokio::test]
+	async fn test_import() {
+		let env = test_env().await;
+		let entry = |name: &str, base: Option<&str>| BranchManifestEntry {
+			name: name.to_string(),
+			config: BranchConfigInfo {
+				base: base.map(KString::from_ref),
+				..Default::default()
+			},
+		};
+		let manifest = BranchManifest {
+			branches: vec![
+				entry("derived", Some("stable")),
+				entry("stable", None),
+				entry("stable", None),
+			],
+		};
+
+		let import = env
+			.branch
+			.import(DEFAULT_NAMESPACE_ID, manifest)
+			.await
+			.unwrap();
+		let names = import
+			.branches
+			.iter()
+			.map(|branch| branch.name.as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(names, ["stable", "stable", "derived"]);
+		assert!(import.branches[0].result.is_ok());
+		assert!(import.branches[1].result.is_err());
+		assert!(import.branches[2].result.is_ok());
+		assert!(
+			env.branch
+				.find_id(DEFAULT_NAMESPACE_ID, "derived")
+				.await
+				.unwrap()
+				.is_some()
+		);
+
+		let operation = env.operation.get(import.operation).await.unwrap().unwrap();
+		assert_eq!(operation.kind, "import");
+		assert_eq!(operation.jobs.len(), 2);
+	}
 
 	#[tokio::test]
 	async fn test_track() {
 		let env = test_env().await;
-		env.branch.track("test", Default::default()).await.unwrap();
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", Default::default())
+			.await
+			.unwrap();
 
 		// assert object
 		let mut db = env.database.get().await.unwrap();
 		assert_eq!(
-			db.get_result::<_, (String, i16)>(dsl::branch.select((dsl::name, dsl::status)))
-				.await
-				.unwrap(),
-			("test".to_string(), 0)
+			db.get_result::<_, (String, SqlBranchStatus)>(
+				dsl::branch.select((dsl::name, dsl::status))
+			)
+			.await
+			.unwrap(),
+			("test".to_string(), SqlBranchStatus::Dirty)
 		);
 		drop(db);
 
@@ -310,4 +1118,307 @@ mod test {
 		let job = env.job_queue.fetch_and_start().await.unwrap().unwrap();
 		assert_eq!(job.command, JobCommand::SyncBranch(1));
 	}
+
+	#[tokio::test]
+	async fn test_timeline() {
+		let env = test_env().await;
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", Default::default())
+			.await
+			.unwrap();
+		let job = Uuid::now_v7();
+		env.branch
+			.mark_error(1, "sync failed", &StatusActor::Job(job))
+			.await
+			.unwrap();
+		env.branch
+			.set_status(
+				1,
+				SqlBranchStatus::Suspended,
+				Some("maintenance"),
+				&StatusActor::User("alice".to_string()),
+			)
+			.await
+			.unwrap();
+
+		let timeline = env.branch.timeline(1).await.unwrap();
+		let events = timeline
+			.iter()
+			.map(|event| (event.status, event.message.as_deref(), event.actor.as_str()))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			events,
+			[
+				(
+					SqlBranchStatus::Suspended,
+					Some("maintenance"),
+					"user:alice"
+				),
+				(
+					SqlBranchStatus::Error,
+					Some("sync failed"),
+					format!("job:{job}").as_str()
+				),
+				(SqlBranchStatus::Dirty, Some("tracked"), "system"),
+			]
+		);
+
+		env.branch.untrack(1, &Principal::system()).await.unwrap();
+		assert!(env.branch.timeline(1).await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_untrack_cancels_jobs() {
+		let env = test_env().await;
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", Default::default())
+			.await
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+		env.job_queue
+			.enqueue(
+				&mut db,
+				JobCommand::LintPackage {
+					branch: 1,
+					package: Uuid::now_v7(),
+				},
+			)
+			.await
+			.unwrap();
+		drop(db);
+		let running = env.job_queue.fetch_and_start().await.unwrap().unwrap();
+		let mut bus = env.bus.subscribe_local().unwrap();
+
+		env.branch.untrack(1, &Principal::system()).await.unwrap();
+		assert!(
+			env.job_queue
+				.list(DEFAULT_NAMESPACE_ID, 10)
+				.await
+				.unwrap()
+				.is_empty()
+		);
+		let finished = env
+			.job_queue
+			.get_finished(running.id)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(finished.outcome, SqlJobOutcome::Failed);
+		assert_eq!(
+			bus.try_recv().unwrap(),
+			LocalBusMessage::Backend(BackendBusMessage::JobsCancelled(vec![running.id]))
+		);
+
+		// the runner of the cancelled job cannot finish it
+		let mut db = env.database.get().await.unwrap();
+		assert!(env.job_queue.finish_job(&mut db, running.id).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_target_group() {
+		let env = test_env().await;
+		let info = |group: &str| BranchConfigInfo {
+			target_group: Some(KString::from_ref(group)),
+			..Default::default()
+		};
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", info("group1"))
+			.await
+			.unwrap();
+		assert!(matches!(
+			env.branch
+				.track(DEFAULT_NAMESPACE_ID, "retro", info("group2"))
+				.await,
+			Err(BackendError::BranchError(BranchError::TargetGroupNotFound(
+				_
+			)))
+		));
+
+		// empty string removes the group
+		env.branch
+			.update_config(1, &info(""), None, &Principal::system())
+			.await
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+		assert_eq!(
+			db.get_result::<_, Option<String>>(dsl::branch.select(dsl::target_group))
+				.await
+				.unwrap(),
+			None
+		);
+	}
+
+	#[tokio::test]
+	async fn test_update_config_version() {
+		let env = test_env().await;
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", Default::default())
+			.await
+			.unwrap();
+		let info = BranchConfigInfo {
+			priority: Some(120),
+			..Default::default()
+		};
+
+		assert_eq!(
+			env.branch
+				.update_config(1, &info, Some(0), &Principal::system())
+				.await
+				.unwrap(),
+			1
+		);
+		assert!(matches!(
+			env.branch
+				.update_config(1, &info, Some(0), &Principal::system())
+				.await,
+			Err(BackendError::BranchError(BranchError::VersionMismatch(1)))
+		));
+		assert_eq!(
+			env.branch
+				.update_config(1, &info, None, &Principal::system())
+				.await
+				.unwrap(),
+			2
+		);
+		assert!(matches!(
+			env.branch
+				.update_config(2, &info, Some(2), &Principal::system())
+				.await,
+			Err(BackendError::BranchError(BranchError::BranchNotFound(2)))
+		));
+	}
+
+	#[tokio::test]
+	async fn test_pin() {
+		let env = test_env().await;
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", Default::default())
+			.await
+			.unwrap();
+		let head = GitOid::Sha1([1; 20]);
+		let pinned = GitOid::Sha1([2; 20]);
+		let info = BranchConfigInfo {
+			pinned_commit: Some(pinned),
+			..Default::default()
+		};
+		env.branch
+			.update_config(1, &info, None, &Principal::system())
+			.await
+			.unwrap();
+
+		let mut db = env.database.get().await.unwrap();
+		let row: BranchRow = db
+			.load_one_select(dsl::branch.filter(dsl::id.eq(1)))
+			.await
+			.unwrap();
+		assert_eq!(row.sync_commit(head).unwrap(), pinned);
+
+		// other updates keep the pin
+		let info = BranchConfigInfo {
+			priority: Some(120),
+			..Default::default()
+		};
+		env.branch
+			.update_config(1, &info, None, &Principal::system())
+			.await
+			.unwrap();
+		assert_eq!(env.branch.unpin(1, &Principal::system()).await.unwrap(), 3);
+		let row: BranchRow = db
+			.load_one_select(dsl::branch.filter(dsl::id.eq(1)))
+			.await
+			.unwrap();
+		assert_eq!(row.sync_commit(head).unwrap(), head);
+		assert!(matches!(
+			env.branch.unpin(2, &Principal::system()).await,
+			Err(BackendError::BranchError(BranchError::BranchNotFound(2)))
+		));
+	}
+
+	#[tokio::test]
+	async fn test_base_cycle() {
+		let env = test_env().await;
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "stable", Default::default())
+			.await
+			.unwrap();
+		env.branch
+			.track(
+				DEFAULT_NAMESPACE_ID,
+				"testing",
+				BranchConfigInfo {
+					base: Some("stable".into()),
+					..Default::default()
+				},
+			)
+			.await
+			.unwrap();
+
+		for base in ["stable", "testing"] {
+			let info = BranchConfigInfo {
+				base: Some(base.into()),
+				..Default::default()
+			};
+			assert!(matches!(
+				env.branch
+					.update_config(1, &info, None, &Principal::system())
+					.await,
+				Err(BackendError::BranchError(BranchError::BaseCycle(1)))
+			));
+		}
+	}
+
+	#[tokio::test]
+	async fn test_track_invalid_base() {
+		let env = test_env().await;
+		for (name, base) in [("stable", "stable"), ("testing", "missing")] {
+			let result = env
+				.branch
+				.track(
+					DEFAULT_NAMESPACE_ID,
+					name,
+					BranchConfigInfo {
+						base: Some(base.into()),
+						..Default::default()
+					},
+				)
+				.await;
+			assert!(matches!(
+				result,
+				Err(BackendError::BranchError(
+					BranchError::SelfBase(_) | BranchError::BaseNotFound(_)
+				))
+			));
+		}
+		assert!(
+			env.branch
+				.find_id(DEFAULT_NAMESPACE_ID, "stable")
+				.await
+				.unwrap()
+				.is_none()
+		);
+		assert!(
+			env.branch
+				.find_id(DEFAULT_NAMESPACE_ID, "testing")
+				.await
+				.unwrap()
+				.is_none()
+		);
+	}
+
+	#[tokio::test]
+	async fn test_track_duplicate() {
+		let env = test_env().await;
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "stable", BranchConfigInfo::default())
+			.await
+			.unwrap();
+		let result = env
+			.branch
+			.track(DEFAULT_NAMESPACE_ID, "stable", BranchConfigInfo::default())
+			.await;
+		assert!(matches!(
+			result,
+			Err(BackendError::BranchError(BranchError::AlreadyExists(_)))
+		));
+	}
 }
@@ -0,0 +1,204 @@
+//! Access control lists of branches.
+//!
+//! A branch with ACL entries can only be mutated by principals having any
+//! identity in the entries, e.g. only release managers may touch `stable`.
+//! Branches without entries can be mutated by any principal.
+//!
+//! ACLs are checked by [`BranchService`](crate::branch::BranchService) in the
+//! same transactions as mutations, so that every caller respects them.
+
+use std::sync::Arc;
+
+use diesel::{ExpressionMethods, QueryDsl, delete, insert_into};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+	Result,
+	branch::{BranchError, BranchRef},
+	db::{BoxedSqlConn, schema::branch_acl::dsl, service::DatabaseService},
+};
+
+/// Identities of a caller mutating branches.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Principal {
+	/// Identities, e.g. `token:{name}`, `user:{sub}` and `role:{name}`.
+	pub identities: Vec<String>,
+}
+
+impl Principal {
+	const SYSTEM: &str = "system";
+
+	/// Fabricia itself, e.g. jobs synchronizing branches, which bypasses ACLs.
+	pub fn system() -> Self {
+		Self {
+			identities: vec![Self::SYSTEM.to_string()],
+		}
+	}
+
+	/// A caller without identities, which can only mutate unrestricted branches.
+	pub fn anonymous() -> Self {
+		Self::default()
+	}
+
+	pub fn with_identity(mut self, kind: &str, name: &str) -> Self {
+		self.identities.push(format!("{kind}:{name}"));
+		self
+	}
+
+	pub fn is_system(&self) -> bool {
+		self.identities.iter().any(|id| id == Self::SYSTEM)
+	}
+
+	/// Returns the first identity, which is the most specific one,
+	/// or `anonymous` without identities.
+	pub fn name(&self) -> &str {
+		self.identities
+			.first()
+			.map(String::as_str)
+			.unwrap_or("anonymous")
+	}
+}
+
+#[derive(Debug)]
+pub struct BranchAclService {
+	db: Arc<DatabaseService>,
+}
+
+impl BranchAclService {
+	pub fn new(db: Arc<DatabaseService>) -> Self {
+		Self { db }
+	}
+
+	/// Lists ACL entries of a branch.
+	pub async fn list(&self, branch: BranchRef) -> Result<Vec<String>> {
+		let mut conn = self.db.get().await?;
+		Self::list_in(&mut conn, branch).await
+	}
+
+	async fn list_in(conn: &mut BoxedSqlConn, branch: BranchRef) -> Result<Vec<String>> {
+		Ok(conn
+			.load(
+				dsl::branch_acl
+					.filter(dsl::branch.eq(branch))
+					.order_by(dsl::principal)
+					.select(dsl::principal),
+			)
+			.await?)
+	}
+
+	/// Checks whether a principal may mutate a branch.
+	pub async fn check(
+		conn: &mut BoxedSqlConn,
+		branch: BranchRef,
+		principal: &Principal,
+	) -> Result<()> {
+		if principal.is_system() {
+			return Ok(());
+		}
+		let entries = Self::list_in(conn, branch).await?;
+		if entries.is_empty()
+			|| entries
+				.iter()
+				.any(|entry| principal.identities.contains(entry))
+		{
+			Ok(())
+		} else {
+			Err(BranchError::AccessDenied(branch).into())
+		}
+	}
+
+	/// Replaces ACL entries of a branch.
+	///
+	/// The principal must be allowed by the current entries.
+	/// Setting no entries removes the restriction.
+	pub async fn set(
+		&self,
+		branch: BranchRef,
+		entries: &[String],
+		principal: &Principal,
+	) -> Result<()> {
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			Self::check(conn, branch, principal).await?;
+			conn.execute(delete(dsl::branch_acl).filter(dsl::branch.eq(branch)))
+				.await?;
+			let mut entries = entries.to_vec();
+			entries.sort();
+			entries.dedup();
+			let rows = entries
+				.iter()
+				.map(|entry| (dsl::branch.eq(branch), dsl::principal.eq(entry)))
+				.collect::<Vec<_>>();
+			if !rows.is_empty() {
+				conn.execute(insert_into(dsl::branch_acl).values(rows))
+					.await?;
+			}
+			Ok(())
+		})
+		.await?;
+		info!(branch, ?entries, "updated branch ACL");
+		Ok(())
+	}
+
+	/// Removes ACL entries of an untracked branch.
+	pub(crate) async fn clear(conn: &mut BoxedSqlConn, branch: BranchRef) -> Result<()> {
+		conn.execute(delete(dsl::branch_acl).filter(dsl::branch.eq(branch)))
+			.await?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{
+		BackendError,
+		branch::{BranchConfigInfo, BranchError},
+		namespace::DEFAULT_NAMESPACE_ID,
+		test::test_env,
+	};
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_branch_acl() {
+		let env = test_env().await;
+		let release = Principal::anonymous().with_identity("role", "release");
+		let ci = Principal::anonymous().with_identity("token", "ci");
+		let info = BranchConfigInfo {
+			priority: Some(10),
+			..Default::default()
+		};
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "stable", BranchConfigInfo::default())
+			.await
+			.unwrap();
+
+		env.branch.update_config(1, &info, None, &ci).await.unwrap();
+		env.branch_acl
+			.set(1, &["role:release".to_string()], &ci)
+			.await
+			.unwrap();
+		assert_eq!(env.branch_acl.list(1).await.unwrap(), vec!["role:release"]);
+
+		assert!(matches!(
+			env.branch.update_config(1, &info, None, &ci).await,
+			Err(BackendError::BranchError(BranchError::AccessDenied(1)))
+		));
+		assert!(matches!(
+			env.branch_acl.set(1, &[], &ci).await,
+			Err(BackendError::BranchError(BranchError::AccessDenied(1)))
+		));
+		env.branch
+			.update_config(1, &info, None, &release)
+			.await
+			.unwrap();
+		env.branch
+			.update_config(1, &info, None, &Principal::system())
+			.await
+			.unwrap();
+
+		env.branch.untrack(1, &release).await.unwrap();
+		assert!(env.branch_acl.list(1).await.unwrap().is_empty());
+	}
+}
@@ -0,0 +1,326 @@
+//! Comparison of packages of two branches.
+//!
+//! Packages of both branches are read with their states on targets in one query,
+//! page by page in the order of names, and compared by version and states.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use diesel::{ExpressionMethods, JoinOnDsl, QueryDsl};
+
+use crate::{
+	Result,
+	branch::BranchRef,
+	changelog::full_version,
+	db::{
+		BoxedSqlConn,
+		schema::{pkg, pkg_target},
+	},
+	model::{PkgRow, PkgTargetRow},
+	package::SqlPackageTargetState,
+	target::TargetId,
+};
+
+/// Change of a package from a branch to the other branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PackageChange {
+	/// The package is only in the other branch.
+	Added,
+	/// The package is only in the branch.
+	Removed,
+	/// The package differs in versions or states on targets.
+	Changed,
+}
+
+/// States of a package on a target which differ between two branches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetComparison {
+	pub target: TargetId,
+	/// State in the branch, [`None`] if never built for the target.
+	pub state: Option<SqlPackageTargetState>,
+	/// State in the other branch.
+	pub other_state: Option<SqlPackageTargetState>,
+}
+
+/// A package differing between two branches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageComparison {
+	pub name: String,
+	pub change: PackageChange,
+	/// Full version in the branch, [`None`] if added.
+	pub version: Option<String>,
+	/// Full version in the other branch, [`None`] if removed.
+	pub other_version: Option<String>,
+	/// Targets with differing states, ordered by ID.
+	pub targets: Vec<TargetComparison>,
+}
+
+/// A page of differing packages.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ComparisonPage {
+	/// Differing packages, ordered by name.
+	pub packages: Vec<PackageComparison>,
+	/// Name of the last compared package, which the next page starts after,
+	/// or [`None`] if all packages have been compared.
+	pub next: Option<String>,
+}
+
+/// A package of either branch, with its states on targets.
+#[derive(Debug, Default)]
+struct Side {
+	version: Option<String>,
+	states: BTreeMap<TargetId, SqlPackageTargetState>,
+}
+
+impl Side {
+	fn read(&mut self, pkg: &PkgRow, target: Option<PkgTargetRow>) -> Result<()> {
+		if self.version.is_none() {
+			self.version = Some(full_version(&pkg.pkg_data()?));
+		}
+		if let Some(target) = target {
+			self.states.insert(target.target as TargetId, target.status);
+		}
+		Ok(())
+	}
+}
+
+/// Compares packages with names after `after` of two branches, up to `limit` packages.
+///
+/// `max_targets` is the count of configured targets, which bounds rows of each package.
+pub async fn compare(
+	conn: &mut BoxedSqlConn,
+	branch: BranchRef,
+	other: BranchRef,
+	after: Option<String>,
+	limit: usize,
+	max_targets: usize,
+) -> Result<ComparisonPage> {
+	// each package has a row for each target in each branch, or one row without targets
+	let max_rows = limit.max(1) * 2 * max_targets.max(1);
+	let rows: Vec<(PkgRow, Option<PkgTargetRow>)> = conn
+		.load_select(
+			pkg::table
+				.left_join(pkg_target::table.on(pkg_target::package.eq(pkg::id)))
+				.filter(pkg::branch.eq_any([branch, other]))
+				.filter(pkg::name.gt(after.unwrap_or_default()))
+				.order((pkg::name.asc(), pkg::branch.asc()))
+				.limit(max_rows.try_into().unwrap_or(i64::MAX)),
+		)
+		.await?;
+	compare_rows(rows, branch, max_rows)
+}
+
+/// Compares packages of rows ordered by name, from a query limited to `max_rows`.
+///
+/// If the rows reach the limit, the last package may be cut, so it is left
+/// for the next page, unless it is the only package.
+fn compare_rows(
+	rows: Vec<(PkgRow, Option<PkgTargetRow>)>,
+	branch: BranchRef,
+	max_rows: usize,
+) -> Result<ComparisonPage> {
+	let truncated = rows.len() >= max_rows;
+	let mut packages: Vec<(String, Side, Side)> = Vec::new();
+	for (pkg, target) in rows {
+		if packages.last().is_none_or(|(name, ..)| *name != pkg.name) {
+			packages.push((pkg.name.clone(), Side::default(), Side::default()));
+		}
+		let (_, side, other_side) = packages.last_mut().expect("pushed above");
+		match pkg.branch == branch {
+			true => side.read(&pkg, target)?,
+			false => other_side.read(&pkg, target)?,
+		}
+	}
+	if truncated && packages.len() > 1 {
+		packages.pop();
+	}
+	let next = match truncated {
+		true => packages.last().map(|(name, ..)| name.clone()),
+		false => None,
+	};
+
+	let mut page = ComparisonPage {
+		packages: Vec::new(),
+		next,
+	};
+	for (name, side, other_side) in packages {
+		let change = match (&side.version, &other_side.version) {
+			(None, _) => PackageChange::Added,
+			(_, None) => PackageChange::Removed,
+			_ => PackageChange::Changed,
+		};
+		let mut targets = Vec::new();
+		let ids = side.states.keys().chain(other_side.states.keys());
+		for id in ids.copied().collect::<BTreeSet<_>>() {
+			let state = side.states.get(&id).copied();
+			let other_state = other_side.states.get(&id).copied();
+			if state != other_state {
+				targets.push(TargetComparison {
+					target: id,
+					state,
+					other_state,
+				});
+			}
+		}
+		if change == PackageChange::Changed
+			&& side.version == other_side.version
+			&& targets.is_empty()
+		{
+			continue;
+		}
+		page.packages.push(PackageComparison {
+			name,
+			change,
+			version: side.version,
+			other_version: other_side.version,
+			targets,
+		});
+	}
+	Ok(page)
+}
+
+#[cfg(test)]
+mod test {
+	use serde_json::json;
+
+	use crate::{
+		branch::BranchRef,
+		branch_compare::{PackageChange, TargetComparison, compare, compare_rows},
+		model::{PkgRow, PkgTargetRow},
+		namespace::DEFAULT_NAMESPACE_ID,
+		package::{PkgData, SqlPackageTargetState},
+		test::{insert_test_package, insert_test_target, test_env, test_package, test_target},
+	};
+
+	fn data(version: &str) -> serde_json::Value {
+		let data = PkgData {
+			version: version.to_string(),
+			release: 1,
+			..Default::default()
+		};
+		data.to_json().unwrap()
+	}
+
+	fn pkg(branch: BranchRef, name: &str, version: &str) -> PkgRow {
+		test_package(branch, name, data(version))
+	}
+
+	fn target(pkg: &PkgRow, target: i64, status: SqlPackageTargetState) -> Option<PkgTargetRow> {
+		Some(test_target(pkg, target, status, json!({})))
+	}
+
+	#[test]
+	fn test_compare_rows() {
+		let bash = [pkg(1, "bash", "5.2.37"), pkg(2, "bash", "5.3")];
+		let glibc = [pkg(1, "glibc", "2.41"), pkg(2, "glibc", "2.41")];
+		let xz = pkg(2, "xz", "5.8.1");
+		let zlib = pkg(1, "zlib", "1.3.1");
+		let rows = vec![
+			(bash[0].clone(), None),
+			(bash[1].clone(), None),
+			(
+				glibc[0].clone(),
+				target(&glibc[0], 10, SqlPackageTargetState::Ready),
+			),
+			(
+				glibc[0].clone(),
+				target(&glibc[0], 11, SqlPackageTargetState::Ready),
+			),
+			(
+				glibc[1].clone(),
+				target(&glibc[1], 10, SqlPackageTargetState::Ready),
+			),
+			(
+				glibc[1].clone(),
+				target(&glibc[1], 11, SqlPackageTargetState::BuildFailed),
+			),
+			(xz, None),
+			(zlib, None),
+		];
+
+		let page = compare_rows(rows.clone(), 1, 100).unwrap();
+		assert_eq!(page.next, None);
+		let changes = page
+			.packages
+			.iter()
+			.map(|pkg| (pkg.name.as_str(), pkg.change))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			changes,
+			[
+				("bash", PackageChange::Changed),
+				("glibc", PackageChange::Changed),
+				("xz", PackageChange::Added),
+				("zlib", PackageChange::Removed),
+			]
+		);
+		assert_eq!(page.packages[0].version.as_deref(), Some("5.2.37-1"));
+		assert_eq!(page.packages[0].other_version.as_deref(), Some("5.3-1"));
+		assert_eq!(
+			page.packages[1].targets,
+			[TargetComparison {
+				target: 11,
+				state: Some(SqlPackageTargetState::Ready),
+				other_state: Some(SqlPackageTargetState::BuildFailed),
+			}]
+		);
+
+		// glibc may be cut by the limit, and is left for the next page
+		let page = compare_rows(rows[..5].to_vec(), 1, 5).unwrap();
+		assert_eq!(page.next.as_deref(), Some("bash"));
+		assert_eq!(page.packages.len(), 1);
+
+		let unchanged = vec![(glibc[0].clone(), None), (glibc[1].clone(), None)];
+		assert!(compare_rows(unchanged, 1, 100).unwrap().packages.is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_compare() {
+		let env = test_env().await;
+		let mut ids = Vec::new();
+		for name in ["main", "topic/bash"] {
+			env.branch
+				.track(DEFAULT_NAMESPACE_ID, name, Default::default())
+				.await
+				.unwrap();
+			let id = env
+				.branch
+				.find_id(DEFAULT_NAMESPACE_ID, name)
+				.await
+				.unwrap();
+			ids.push(id.unwrap());
+		}
+		let mut db = env.database.get().await.unwrap();
+		let mut rows = Vec::new();
+		for (branch, name, version) in [
+			(ids[0], "bash", "5.2.37"),
+			(ids[1], "bash", "5.3"),
+			(ids[0], "zlib", "1.3.1"),
+			(ids[1], "zlib", "1.3.1"),
+		] {
+			rows.push(insert_test_package(&mut db, branch, name, data(version)).await);
+		}
+		let failed = SqlPackageTargetState::BuildFailed;
+		insert_test_target(&mut db, &rows[3], 10, failed, json!({})).await;
+
+		// one package with one target in each page
+		let page = compare(&mut db, ids[0], ids[1], None, 1, 1).await.unwrap();
+		assert_eq!(page.packages[0].name, "bash");
+		assert_eq!(page.next.as_deref(), Some("bash"));
+		let page = compare(&mut db, ids[0], ids[1], page.next, 1, 1)
+			.await
+			.unwrap();
+		assert_eq!(page.packages[0].name, "zlib");
+		assert_eq!(
+			page.packages[0].targets,
+			[TargetComparison {
+				target: 10,
+				state: None,
+				other_state: Some(SqlPackageTargetState::BuildFailed),
+			}]
+		);
+		let page = compare(&mut db, ids[0], ids[1], page.next, 1, 1)
+			.await
+			.unwrap();
+		assert!(page.packages.is_empty() && page.next.is_none());
+	}
+}
@@ -0,0 +1,356 @@
+//! Discovery of topic branches.
+//!
+//! Remote branches of a repository are listed by [`JobCommand::ScanBranches`] jobs,
+//! which are enqueued periodically by [`BranchScanService::run_scheduler`]. New
+//! remote branches matching the configured pattern are tracked in the default
+//! namespace, and automatically tracked branches deleted upstream are suspended.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use diesel::{ExpressionMethods, QueryDsl};
+use fabricia_common_model::branch::TrackingMode;
+use kstring::KString;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::{
+	Result,
+	branch::{BranchConfigInfo, BranchError, BranchService, SqlBranchStatus, SqlTrackingMode},
+	db::{schema::branch::dsl, service::DatabaseService},
+	job_queue::{JobCommand, JobQueue},
+	model::BranchRow,
+	namespace::DEFAULT_NAMESPACE_ID,
+	package::StatusActor,
+	repository::{RepositoryInfo, RepositoryService},
+};
+
+/// Job kind of [`JobCommand::ScanBranches`].
+const SCAN_JOB_KIND: &str = "ScanBranches";
+
+/// Status message of branches suspended after deleted upstream.
+pub const DELETED_UPSTREAM: &str = "deleted upstream";
+
+/// Configuration of topic branch discovery.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BranchScanConfig {
+	/// Name of the scanned repository, the default repository if not set.
+	#[serde(default)]
+	pub repository: Option<KString>,
+	/// Names of topic branches.
+	///
+	/// A trailing `*` matches all names with the prefix, e.g. `topic/*`.
+	pub pattern: String,
+	/// Name of the base branch of discovered branches.
+	#[serde(default)]
+	pub base: Option<KString>,
+	/// Interval in seconds between scans.
+	#[serde(default = "default_interval")]
+	pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+	5 * 60
+}
+
+impl BranchScanConfig {
+	/// Returns whether a branch name matches [`BranchScanConfig::pattern`].
+	pub fn is_topic(&self, name: &str) -> bool {
+		match self.pattern.strip_suffix('*') {
+			Some(prefix) => name.starts_with(prefix),
+			None => name == self.pattern,
+		}
+	}
+}
+
+/// Parses names of branches from the output of `git ls-remote --heads`.
+pub fn parse_heads(output: &str) -> Vec<String> {
+	output
+		.lines()
+		.filter_map(|line| line.split_once('\t'))
+		.filter_map(|(_, name)| name.strip_prefix("refs/heads/"))
+		.map(str::to_string)
+		.collect()
+}
+
+/// Changes applied by a scan.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ScanStats {
+	/// Names of newly tracked branches.
+	pub tracked: Vec<String>,
+	/// Names of branches suspended after deleted upstream.
+	pub archived: Vec<String>,
+}
+
+/// Service for topic branch discovery.
+#[derive(Debug)]
+pub struct BranchScanService {
+	db: Arc<DatabaseService>,
+	job_queue: Arc<JobQueue>,
+	branch: Arc<BranchService>,
+	repository: Arc<RepositoryService>,
+	/// Branches are never discovered if not set.
+	config: Option<BranchScanConfig>,
+}
+
+impl BranchScanService {
+	pub fn new(
+		db: Arc<DatabaseService>,
+		job_queue: Arc<JobQueue>,
+		branch: Arc<BranchService>,
+		repository: Arc<RepositoryService>,
+		config: Option<&BranchScanConfig>,
+	) -> Self {
+		Self {
+			db,
+			job_queue,
+			branch,
+			repository,
+			config: config.cloned(),
+		}
+	}
+
+	/// Returns whether topic branch discovery is enabled.
+	pub fn is_enabled(&self) -> bool {
+		self.config.is_some()
+	}
+
+	/// Returns the scanned repository.
+	fn scanned_repository(&self, config: &BranchScanConfig) -> Result<&Arc<RepositoryInfo>> {
+		let repository = match &config.repository {
+			Some(name) => self.repository.find(name),
+			None => self.repository.default_repository(),
+		};
+		Ok(repository.ok_or_else(|| {
+			BranchError::RepositoryNotFound(config.repository.clone().unwrap_or_default())
+		})?)
+	}
+
+	/// Lists remote branches of the scanned repository, and applies changes.
+	pub async fn scan(&self) -> Result<ScanStats> {
+		let Some(config) = &self.config else {
+			return Ok(ScanStats::default());
+		};
+		let repository = self.scanned_repository(config)?;
+		let output = Command::new("git")
+			.arg("ls-remote")
+			.arg("--heads")
+			.arg(&repository.url)
+			.output()
+			.await
+			.map_err(BranchScanError::IoError)?;
+		if !output.status.success() {
+			return Err(BranchScanError::ListError(
+				repository.url.clone(),
+				String::from_utf8_lossy(&output.stderr).trim().to_string(),
+			)
+			.into());
+		}
+		let heads = parse_heads(&String::from_utf8_lossy(&output.stdout));
+		self.apply(&heads).await
+	}
+
+	/// Tracks new topic branches, and suspends automatically tracked topic branches
+	/// missing from `heads`, the remote branches of the scanned repository.
+	///
+	/// Suspended branches are resumed only by maintainers, even if recreated upstream.
+	pub async fn apply(&self, heads: &[String]) -> Result<ScanStats> {
+		let Some(config) = &self.config else {
+			return Ok(ScanStats::default());
+		};
+		let repository = self.scanned_repository(config)?;
+		let is_default = self
+			.repository
+			.default_repository()
+			.is_some_and(|default| default.id == repository.id);
+		let rows: Vec<BranchRow> = {
+			let mut conn = self.db.get().await?;
+			conn.load_select(dsl::branch.filter(dsl::namespace.eq(DEFAULT_NAMESPACE_ID)))
+				.await?
+		};
+		let heads = heads
+			.iter()
+			.filter(|name| config.is_topic(name))
+			.map(String::as_str)
+			.collect::<HashSet<_>>();
+		let tracked = rows
+			.iter()
+			.map(|row| row.name.as_str())
+			.collect::<HashSet<_>>();
+
+		let mut stats = ScanStats::default();
+		for name in heads.iter().filter(|name| !tracked.contains(*name)) {
+			let info = BranchConfigInfo {
+				base: config.base.clone(),
+				repository: config.repository.clone(),
+				tracking_mode: Some(TrackingMode::Auto),
+				..Default::default()
+			};
+			match self.branch.track(DEFAULT_NAMESPACE_ID, name, info).await {
+				Ok(_) => stats.tracked.push(name.to_string()),
+				Err(error) => warn!(branch = %name, %error, "failed to track topic branch"),
+			}
+		}
+
+		for row in rows {
+			let in_repository = match row.repository {
+				Some(id) => id == repository.id,
+				None => is_default,
+			};
+			if !in_repository
+				|| row.tracking != SqlTrackingMode::Auto
+				|| row.status == SqlBranchStatus::Suspended
+				|| !config.is_topic(&row.name)
+				|| heads.contains(row.name.as_str())
+			{
+				continue;
+			}
+			self.branch
+				.set_status(
+					row.id,
+					SqlBranchStatus::Suspended,
+					Some(DELETED_UPSTREAM),
+					&StatusActor::System,
+				)
+				.await?;
+			stats.archived.push(row.name);
+		}
+		stats.tracked.sort();
+		info!(
+			tracked = stats.tracked.len(),
+			archived = stats.archived.len(),
+			"scanned topic branches"
+		);
+		Ok(stats)
+	}
+
+	/// Enqueues a scan unless one is pending or running.
+	pub async fn schedule(&self) -> Result<bool> {
+		let depth = self.job_queue.depth().await?;
+		let queued = depth
+			.iter()
+			.any(|depth| depth.kind == SCAN_JOB_KIND && depth.pending + depth.running > 0);
+		if queued {
+			return Ok(false);
+		}
+		let mut conn = self.db.get().await?;
+		self.job_queue
+			.enqueue(&mut conn, JobCommand::ScanBranches)
+			.await?;
+		Ok(true)
+	}
+
+	/// Enqueues scans periodically until the process exits.
+	///
+	/// Does nothing if topic branch discovery is disabled.
+	pub async fn run_scheduler(self: Arc<Self>) {
+		let Some(config) = &self.config else {
+			return;
+		};
+		let mut interval = tokio::time::interval(Duration::from_secs(config.interval.max(1)));
+		loop {
+			interval.tick().await;
+			if let Err(error) = self.schedule().await {
+				warn!(?error, "failed to schedule topic branch scan");
+			}
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum BranchScanError {
+	#[error("failed to run git: {0}")]
+	IoError(std::io::Error),
+	#[error("failed to list remote branches of {0}: {1}")]
+	ListError(String, String),
+}
+
+#[cfg(test)]
+mod test {
+	use diesel::{ExpressionMethods, QueryDsl};
+	use fabricia_common_model::branch::TrackingMode;
+
+	use crate::{
+		branch::{BranchConfigInfo, SqlBranchStatus},
+		branch_scan::{BranchScanConfig, DELETED_UPSTREAM, parse_heads},
+		db::schema::branch::dsl,
+		model::BranchRow,
+		namespace::DEFAULT_NAMESPACE_ID,
+		test::{test_config, test_env_with_config},
+	};
+
+	#[test]
+	fn test_parse_heads() {
+		let output = "\
+			0123456789abcdef0123456789abcdef01234567\trefs/heads/main\n\
+			0123456789abcdef0123456789abcdef01234567\trefs/heads/topic/xz\n\
+			0123456789abcdef0123456789abcdef01234567\trefs/tags/v1\n";
+		assert_eq!(parse_heads(output), ["main", "topic/xz"]);
+	}
+
+	#[tokio::test]
+	async fn test_apply() {
+		let mut config = test_config();
+		config.branch_scan = Some(BranchScanConfig {
+			repository: None,
+			pattern: "topic/*".to_string(),
+			base: None,
+			interval: 60,
+		});
+		let env = test_env_with_config(config).await;
+		let auto = BranchConfigInfo {
+			tracking_mode: Some(TrackingMode::Auto),
+			..Default::default()
+		};
+		for name in ["topic/bash", "topic/zlib"] {
+			env.branch
+				.track(DEFAULT_NAMESPACE_ID, name, auto.clone())
+				.await
+				.unwrap();
+		}
+		let unmanaged = BranchConfigInfo {
+			tracking_mode: Some(TrackingMode::Unmanaged),
+			..Default::default()
+		};
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "topic/glibc", unmanaged)
+			.await
+			.unwrap();
+
+		let heads = ["main", "topic/bash", "topic/xz"].map(str::to_string);
+		let stats = env.branch_scan.apply(&heads).await.unwrap();
+		assert_eq!(stats.tracked, ["topic/xz"]);
+		assert_eq!(stats.archived, ["topic/zlib"]);
+
+		let mut db = env.database.get().await.unwrap();
+		let rows: Vec<BranchRow> = db
+			.load_select(dsl::branch.order(dsl::name.asc()))
+			.await
+			.unwrap();
+		drop(db);
+		let states = rows
+			.iter()
+			.map(|row| (row.name.as_str(), row.status, row.status_msg.as_deref()))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			states,
+			[
+				("topic/bash", SqlBranchStatus::Dirty, None),
+				("topic/glibc", SqlBranchStatus::Dirty, None),
+				("topic/xz", SqlBranchStatus::Dirty, None),
+				(
+					"topic/zlib",
+					SqlBranchStatus::Suspended,
+					Some(DELETED_UPSTREAM)
+				),
+			]
+		);
+
+		let stats = env.branch_scan.apply(&heads).await.unwrap();
+		assert!(stats.tracked.is_empty() && stats.archived.is_empty());
+		assert!(env.branch_scan.schedule().await.unwrap());
+		assert!(!env.branch_scan.schedule().await.unwrap());
+	}
+}
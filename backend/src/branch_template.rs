@@ -0,0 +1,217 @@
+//! Named templates of branch configurations.
+//!
+//! Templates are defined by administrators, and referred when tracking branches,
+//! so that common setups like security updates do not need all fields each time.
+//! Fields given when tracking take precedence over the template.
+
+use std::sync::Arc;
+
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, delete, insert_into, update};
+use fabricia_common_model::branch::TrackingMode;
+use kstring::KString;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::info;
+
+use crate::{
+	Result,
+	branch::{BranchConfigInfo, BranchError},
+	db::{BoxedSqlConn, schema::branch_template::dsl, service::DatabaseService, utils::XJsonVal},
+	model::BranchTemplateRow,
+	target::TargetService,
+};
+
+/// A template of branch configurations, stored in `branch_template.config`.
+///
+/// Fields have the same meanings as in [`BranchConfigInfo`].
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct BranchTemplate {
+	pub priority: Option<u16>,
+	pub tracking_mode: Option<TrackingMode>,
+	pub target_group: Option<KString>,
+	pub max_running_jobs: Option<u32>,
+	pub max_queued_jobs: Option<u32>,
+}
+
+impl BranchTemplate {
+	/// Fills fields of a branch configuration which are not given from this template.
+	pub fn apply(&self, info: BranchConfigInfo) -> BranchConfigInfo {
+		BranchConfigInfo {
+			priority: info.priority.or(self.priority),
+			tracking_mode: info.tracking_mode.or(self.tracking_mode),
+			target_group: info.target_group.or_else(|| self.target_group.clone()),
+			max_running_jobs: info.max_running_jobs.or(self.max_running_jobs),
+			max_queued_jobs: info.max_queued_jobs.or(self.max_queued_jobs),
+			..info
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct BranchTemplateService {
+	db: Arc<DatabaseService>,
+	target: Arc<TargetService>,
+}
+
+impl BranchTemplateService {
+	pub fn new(db: Arc<DatabaseService>, target: Arc<TargetService>) -> Self {
+		Self { db, target }
+	}
+
+	/// Lists templates, ordered by name.
+	pub async fn list(&self) -> Result<Vec<BranchTemplateRow>> {
+		let mut conn = self.db.get().await?;
+		Ok(conn
+			.load_select(dsl::branch_template.order(dsl::name.asc()))
+			.await?)
+	}
+
+	pub async fn find(&self, name: &str) -> Result<Option<BranchTemplate>> {
+		let mut conn = self.db.get().await?;
+		Self::find_in(&mut conn, name).await
+	}
+
+	/// Finds a template with the given connection.
+	pub async fn find_in(conn: &mut BoxedSqlConn, name: &str) -> Result<Option<BranchTemplate>> {
+		let row: Option<BranchTemplateRow> = conn
+			.load_one_select(dsl::branch_template.filter(dsl::name.eq(name)))
+			.await
+			.optional()?;
+		Ok(row.map(|row| row.template()).transpose()?)
+	}
+
+	/// Creates or replaces a template.
+	///
+	/// Fails with [`BranchError::TargetGroupNotFound`] if the target group
+	/// is not configured.
+	pub async fn put(&self, name: &str, template: &BranchTemplate) -> Result<()> {
+		let unknown_group = template
+			.target_group
+			.as_deref()
+			.filter(|group| !group.is_empty() && !self.target.has_group(group));
+		if let Some(group) = unknown_group {
+			return Err(BranchError::TargetGroupNotFound(KString::from_ref(group)).into());
+		}
+		let config = XJsonVal(serde_json::to_value(template)?);
+		let time = OffsetDateTime::now_utc();
+		let time = PrimitiveDateTime::new(time.date(), time.time());
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			let updated = conn
+				.execute(
+					update(dsl::branch_template)
+						.filter(dsl::name.eq(name))
+						.set((dsl::config.eq(config.clone()), dsl::updated_at.eq(time))),
+				)
+				.await?;
+			if updated == 0 {
+				conn.execute(insert_into(dsl::branch_template).values(BranchTemplateRow {
+					name: name.to_string(),
+					config: config.clone(),
+					updated_at: time,
+				}))
+				.await?;
+			}
+			Ok(())
+		})
+		.await?;
+		info!(name, "saved branch template");
+		Ok(())
+	}
+
+	/// Deletes a template. Returns whether it has existed.
+	///
+	/// Branches tracked with the template are not affected.
+	pub async fn delete(&self, name: &str) -> Result<bool> {
+		let mut conn = self.db.get().await?;
+		let deleted = conn
+			.execute(delete(dsl::branch_template).filter(dsl::name.eq(name)))
+			.await?;
+		if deleted > 0 {
+			info!(name, "deleted branch template");
+		}
+		Ok(deleted > 0)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use fabricia_common_model::branch::TrackingMode;
+
+	use crate::{
+		BackendError,
+		branch::{BranchConfigInfo, BranchError},
+		branch_template::BranchTemplate,
+		test::test_env,
+	};
+
+	#[test]
+	fn test_apply() {
+		let template = BranchTemplate {
+			priority: Some(150),
+			tracking_mode: Some(TrackingMode::Unmanaged),
+			target_group: Some("group1".into()),
+			max_running_jobs: Some(4),
+			max_queued_jobs: None,
+		};
+		let info = BranchConfigInfo {
+			base: Some("main".into()),
+			priority: Some(120),
+			..Default::default()
+		};
+		assert_eq!(
+			template.apply(info),
+			BranchConfigInfo {
+				base: Some("main".into()),
+				repository: None,
+				target_group: Some("group1".into()),
+				priority: Some(120),
+				tracking_mode: Some(TrackingMode::Unmanaged),
+				removal_policy: None,
+				max_running_jobs: Some(4),
+				max_queued_jobs: None,
+				pinned_commit: None,
+			}
+		);
+	}
+
+	#[tokio::test]
+	async fn test_templates() {
+		let env = test_env().await;
+		let mut template = BranchTemplate {
+			priority: Some(150),
+			..Default::default()
+		};
+		env.branch_template
+			.put("security-update", &template)
+			.await
+			.unwrap();
+		template.max_queued_jobs = Some(10);
+		env.branch_template
+			.put("security-update", &template)
+			.await
+			.unwrap();
+		assert_eq!(
+			env.branch_template.find("security-update").await.unwrap(),
+			Some(template.clone())
+		);
+		assert_eq!(env.branch_template.list().await.unwrap().len(), 1);
+
+		template.target_group = Some("unknown".into());
+		let result = env.branch_template.put("invalid", &template).await;
+		assert!(matches!(
+			result,
+			Err(BackendError::BranchError(BranchError::TargetGroupNotFound(
+				_
+			)))
+		));
+
+		assert!(env.branch_template.delete("security-update").await.unwrap());
+		assert!(!env.branch_template.delete("security-update").await.unwrap());
+		assert_eq!(
+			env.branch_template.find("security-update").await.unwrap(),
+			None
+		);
+	}
+}
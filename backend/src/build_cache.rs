@@ -0,0 +1,493 @@
+//! Reuse of build results.
+//!
+//! A build of a package on a target is identified by its cache key, which is derived
+//! from metadata of the package, the target, and metadata of all packages in its
+//! dependency closure. Builds are skipped if their keys are unchanged since the last
+//! successful builds, and artifacts are reused from builds with the same key in any branch.
+
+use std::{
+	collections::{BTreeSet, HashMap, HashSet},
+	sync::Arc,
+};
+
+use diesel::{
+	ExpressionMethods, OptionalExtension, QueryDsl, insert_into,
+	result::{DatabaseErrorKind, Error as DieselError},
+	update,
+};
+use kstring::KString;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	branch::BranchRef,
+	db::{
+		BoxedSqlConn,
+		schema::{build_cache::dsl, pkg::dsl as pkg_dsl, pkg_target::dsl as target_dsl},
+		service::DatabaseService,
+		utils::{XJsonVal, XUuidVal},
+	},
+	job_queue::{JobCommand, JobQueue},
+	model::{BuildCacheRow, PkgRow, PkgTargetRow},
+	package::{PackageService, PkgData, PkgTargetData, SqlPackageStatus, SqlPackageTargetState},
+	target::{TargetId, TargetInfo, TargetService},
+};
+
+/// Hash of metadata of packages in a dependency closure.
+pub type ClosureHash = [u8; 32];
+
+/// Computes dependency closure hashes of packages in a branch, by package name.
+///
+/// The closure of a package contains its direct and indirect dependencies
+/// in the same branch. Dependencies outside of the branch are ignored.
+pub fn closure_hashes(packages: &HashMap<String, PkgData>) -> HashMap<String, ClosureHash> {
+	let mut hashes = HashMap::with_capacity(packages.len());
+	for name in packages.keys() {
+		let mut closure = BTreeSet::new();
+		let mut queue = vec![name.as_str()];
+		while let Some(current) = queue.pop() {
+			for dep in &packages[current].dependencies {
+				if packages.contains_key(dep) && closure.insert(dep.as_str()) {
+					queue.push(dep);
+				}
+			}
+		}
+
+		let mut hasher = Sha256::new();
+		for dep in closure {
+			let data = &packages[dep];
+			hasher.update(format!(
+				"{dep}\0{}:{}-{}\0",
+				data.epoch, data.version, data.release
+			));
+		}
+		hashes.insert(name.clone(), hasher.finalize().into());
+	}
+	hashes
+}
+
+/// Computes the cache key of a build of a package on a target.
+pub fn cache_key(name: &str, data: &PkgData, target: &TargetInfo, closure: &ClosureHash) -> String {
+	let mut hasher = Sha256::new();
+	hasher.update(format!(
+		"{name}\0{}:{}-{}\0",
+		data.epoch, data.version, data.release
+	));
+	for src in &data.srcs {
+		hasher.update(src);
+		hasher.update(b"\0");
+	}
+	hasher.update(format!("{}\0{}\0", target.name, target.arch));
+	hasher.update(closure);
+	hex::encode(hasher.finalize())
+}
+
+/// A build which cannot be skipped, returned by [`BuildCacheService::plan`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedBuild {
+	/// ID of the package target state.
+	pub id: Uuid,
+	pub package: Uuid,
+	pub target: TargetId,
+	/// Architecture of the target.
+	///
+	/// Only runners capable of the architecture may run the build.
+	pub arch: KString,
+	pub key: String,
+	/// Revision of the state when planned.
+	///
+	/// Results are not recorded if the state has been changed since planned.
+	pub revision: i64,
+}
+
+/// Service for cached build results.
+#[derive(Debug)]
+pub struct BuildCacheService {
+	db: Arc<DatabaseService>,
+	target: Arc<TargetService>,
+	job_queue: Arc<JobQueue>,
+}
+
+impl BuildCacheService {
+	pub fn new(
+		db: Arc<DatabaseService>,
+		target: Arc<TargetService>,
+		job_queue: Arc<JobQueue>,
+	) -> Self {
+		Self {
+			db,
+			target,
+			job_queue,
+		}
+	}
+
+	/// Plans builds of dirty package targets in a branch.
+	///
+	/// Package targets are marked as built without building if their cache keys
+	/// are unchanged since their last successful builds, or have been built
+	/// in any branch.
+	pub async fn plan(&self, branch: BranchRef) -> Result<Vec<PlannedBuild>> {
+		let mut conn = self.db.get().await?;
+		let packages: Vec<PkgRow> = conn
+			.load_select(pkg_dsl::pkg.filter(pkg_dsl::branch.eq(branch)))
+			.await?;
+		let rows: Vec<PkgTargetRow> = conn
+			.load_select(
+				target_dsl::pkg_target
+					.filter(target_dsl::branch.eq(branch))
+					.filter(target_dsl::status.eq(SqlPackageTargetState::Dirty)),
+			)
+			.await?;
+
+		let mut names = HashMap::with_capacity(packages.len());
+		let mut datas = HashMap::with_capacity(packages.len());
+		let mut ready = HashSet::new();
+		for pkg in packages {
+			if pkg.status == SqlPackageStatus::Ready && !pkg.is_held() {
+				ready.insert(pkg.id.0);
+			}
+			names.insert(pkg.id.0, pkg.name.clone());
+			datas.insert(pkg.name.clone(), pkg.pkg_data()?);
+		}
+		let closures = closure_hashes(&datas);
+
+		let mut planned = Vec::new();
+		for row in rows {
+			// packages with stale metadata, errors or holds are not built
+			if !ready.contains(&row.package.0) {
+				continue;
+			}
+			// rows of targets removed from the configuration or deprecated are skipped
+			let Some(target) = self
+				.target
+				.get(row.target as TargetId)
+				.filter(|target| !target.deprecated)
+			else {
+				continue;
+			};
+			let name = &names[&row.package.0];
+			let key = cache_key(name, &datas[name], target, &closures[name]);
+
+			let mut data = row.target_data()?;
+			if data.cache_key.as_deref() == Some(key.as_str()) {
+				// unchanged since the last successful build
+				Self::mark_built(&mut conn, row.id.0, row.revision, &data).await?;
+				continue;
+			}
+			let cached: Option<BuildCacheRow> = conn
+				.load_one_select(dsl::build_cache.filter(dsl::key.eq(&key)))
+				.await
+				.optional()?;
+			if let Some(cached) = cached {
+				data = PkgTargetData {
+					last_build: Some(cached.build.0),
+					artifacts: cached.artifact_names()?,
+					cache_key: Some(key),
+					..Default::default()
+				};
+				Self::mark_built(&mut conn, row.id.0, row.revision, &data).await?;
+				continue;
+			}
+			planned.push(PlannedBuild {
+				id: row.id.0,
+				package: row.package.0,
+				target: target.id,
+				arch: target.arch.clone(),
+				key,
+				revision: row.revision,
+			});
+		}
+		Ok(planned)
+	}
+
+	/// Plans builds of dirty package targets in a branch, and enqueues
+	/// a [`JobCommand::BuildPackage`] job for each of them.
+	///
+	/// Returns the count of enqueued jobs.
+	pub async fn schedule(&self, branch: BranchRef) -> Result<usize> {
+		let planned = self.plan(branch).await?;
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			for build in &planned {
+				self.job_queue
+					.enqueue(
+						conn,
+						JobCommand::BuildPackage {
+							branch,
+							build: build.clone(),
+						},
+					)
+					.await?;
+			}
+			Ok(())
+		})
+		.await?;
+		Ok(planned.len())
+	}
+
+	/// Records a successful build, and marks the package target as built.
+	///
+	/// Fails with [`PackageError::Conflict`](crate::package::PackageError::Conflict)
+	/// if the package target has been changed since planned. Artifacts are cached
+	/// for builds with the same key anyway.
+	pub async fn record(
+		&self,
+		build: &PlannedBuild,
+		job: Uuid,
+		artifacts: Vec<String>,
+	) -> Result<()> {
+		let time = OffsetDateTime::now_utc();
+		let row = BuildCacheRow {
+			key: build.key.clone(),
+			target: build.target as i64,
+			build: XUuidVal(job),
+			artifacts: XJsonVal(serde_json::to_value(&artifacts)?),
+			created_at: PrimitiveDateTime::new(time.date(), time.time()),
+		};
+		let data = PkgTargetData {
+			last_build: Some(job),
+			artifacts,
+			cache_key: Some(build.key.clone()),
+			..Default::default()
+		};
+
+		let mut conn = self.db.get().await?;
+		let updated = conn
+			.execute(update(dsl::build_cache).filter(dsl::key.eq(&row.key)).set((
+				dsl::build.eq(row.build),
+				dsl::artifacts.eq(&row.artifacts),
+				dsl::created_at.eq(row.created_at),
+			)))
+			.await?;
+		if updated == 0 {
+			let result = conn
+				.execute(insert_into(dsl::build_cache).values(&row))
+				.await;
+			match result {
+				// cached by another build with the same key at the same time
+				Ok(_) | Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {}
+				Err(error) => return Err(error.into()),
+			}
+		}
+		Self::mark_built(&mut conn, build.id, build.revision, &data).await
+	}
+
+	/// Records a failed build, and marks the package target as failed.
+	///
+	/// Artifacts of the last successful build are kept. Fails like
+	/// [`BuildCacheService::record`] if the package target has been changed since planned.
+	pub async fn record_failure(&self, build: &PlannedBuild, job: Uuid) -> Result<()> {
+		let mut conn = self.db.get().await?;
+		let row: PkgTargetRow = conn
+			.load_one_select(target_dsl::pkg_target.filter(target_dsl::id.eq(XUuidVal(build.id))))
+			.await?;
+		let data = PkgTargetData {
+			last_build: Some(job),
+			..row.target_data()?
+		};
+		PackageService::transition_target(
+			&mut conn,
+			build.id,
+			build.revision,
+			SqlPackageTargetState::Error,
+			&data,
+		)
+		.await?;
+		Ok(())
+	}
+
+	async fn mark_built(
+		conn: &mut BoxedSqlConn,
+		id: Uuid,
+		revision: i64,
+		data: &PkgTargetData,
+	) -> Result<()> {
+		PackageService::transition_target(conn, id, revision, SqlPackageTargetState::Ready, data)
+			.await?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::HashMap;
+
+	use diesel::{ExpressionMethods, update};
+	use uuid::Uuid;
+
+	use crate::{
+		BackendError,
+		build_cache::{cache_key, closure_hashes},
+		db::{
+			schema::{pkg::dsl as pkg_dsl, pkg_target::dsl as target_dsl},
+			utils::{XJsonVal, XUuidVal},
+		},
+		job_queue::JobCommand,
+		package::{PackageError, PkgData, SqlPackageTargetState},
+		target::TargetInfo,
+		test::{
+			insert_test_package, insert_test_target, test_config, test_env, test_env_with_config,
+		},
+	};
+
+	fn pkg_data(version: &str, dependencies: &[&str]) -> PkgData {
+		PkgData {
+			version: version.to_string(),
+			release: 1,
+			dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_cache_key() {
+		let target = TargetInfo {
+			id: TargetInfo::make_id("amd64"),
+			name: "amd64".into(),
+			arch: "amd64".into(),
+			deprecated: false,
+		};
+		let mut packages: HashMap<String, PkgData> = [
+			("bash", pkg_data("5.2.37", &["readline"])),
+			("readline", pkg_data("8.2", &["glibc"])),
+			("glibc", pkg_data("2.40", &[])),
+			("zsh", pkg_data("5.9", &[])),
+		]
+		.map(|(name, data)| (name.to_string(), data))
+		.into_iter()
+		.collect();
+		let key = |packages: &HashMap<String, PkgData>, name: &str| {
+			let closures = closure_hashes(packages);
+			cache_key(name, &packages[name], &target, &closures[name])
+		};
+		let bash = key(&packages, "bash");
+		let zsh = key(&packages, "zsh");
+		assert_eq!(bash.len(), 64);
+		assert_ne!(bash, zsh);
+
+		// indirect dependencies are in the closure
+		packages.get_mut("glibc").unwrap().version = "2.41".to_string();
+		assert_ne!(key(&packages, "bash"), bash);
+		assert_eq!(key(&packages, "zsh"), zsh);
+	}
+
+	#[tokio::test]
+	async fn test_plan() {
+		let env = test_env().await;
+		let target = TargetInfo::make_id("arch1");
+		let mut db = env.database.get().await.unwrap();
+		let mut ids = Vec::new();
+		for branch in [1, 2] {
+			for (name, data) in [
+				("bash", pkg_data("5.2.37", &["glibc"])),
+				("glibc", pkg_data("2.40", &[])),
+			] {
+				let data = data.to_json().unwrap();
+				let pkg = insert_test_package(&mut db, branch, name, data).await;
+				let dirty = SqlPackageTargetState::Dirty;
+				insert_test_target(&mut db, &pkg, target as i64, dirty, serde_json::json!({}))
+					.await;
+				ids.push(pkg.id.0);
+			}
+		}
+		drop(db);
+
+		let planned = env.build_cache.plan(1).await.unwrap();
+		assert_eq!(planned.len(), 2);
+		for build in &planned {
+			let artifacts = vec![format!("{}.deb", build.package)];
+			env.build_cache
+				.record(build, Uuid::now_v7(), artifacts)
+				.await
+				.unwrap();
+		}
+		assert!(env.build_cache.plan(1).await.unwrap().is_empty());
+
+		// unchanged since the last successful build
+		let mut db = env.database.get().await.unwrap();
+		db.execute(
+			update(target_dsl::pkg_target)
+				.filter(target_dsl::package.eq(XUuidVal(ids[0])))
+				.set(target_dsl::status.eq(SqlPackageTargetState::Dirty)),
+		)
+		.await
+		.unwrap();
+		drop(db);
+		assert!(env.build_cache.plan(1).await.unwrap().is_empty());
+
+		// built in another branch
+		assert!(env.build_cache.plan(2).await.unwrap().is_empty());
+		let rows = env.package.list_targets(vec![ids[2]]).await.unwrap();
+		assert_eq!(rows[0].status, SqlPackageTargetState::Ready);
+		assert_eq!(
+			rows[0].target_data().unwrap().artifacts,
+			vec![format!("{}.deb", ids[0])]
+		);
+
+		// dependencies are changed
+		let mut db = env.database.get().await.unwrap();
+		db.execute(
+			update(pkg_dsl::pkg)
+				.filter(pkg_dsl::id.eq(XUuidVal(ids[1])))
+				.set(pkg_dsl::data.eq(XJsonVal(pkg_data("2.41", &[]).to_json().unwrap()))),
+		)
+		.await
+		.unwrap();
+		db.execute(
+			update(target_dsl::pkg_target)
+				.filter(target_dsl::branch.eq(1))
+				.set(target_dsl::status.eq(SqlPackageTargetState::Dirty)),
+		)
+		.await
+		.unwrap();
+		drop(db);
+		// held packages are not built
+		let hold = Some("bisecting".to_string());
+		env.package.set_hold(1, "bash", hold).await.unwrap();
+		assert_eq!(env.build_cache.plan(1).await.unwrap().len(), 1);
+		env.package.set_hold(1, "bash", None).await.unwrap();
+		let planned = env.build_cache.plan(1).await.unwrap();
+		assert_eq!(planned.len(), 2);
+
+		// results of stale builds are rejected
+		let build = &planned[0];
+		env.build_cache
+			.record(build, Uuid::now_v7(), vec![])
+			.await
+			.unwrap();
+		let result = env.build_cache.record(build, Uuid::now_v7(), vec![]).await;
+		assert!(matches!(
+			result,
+			Err(BackendError::PackageError(PackageError::Conflict(id, _))) if id == build.id
+		));
+	}
+
+	#[tokio::test]
+	async fn test_schedule_arch() {
+		let mut config = test_config();
+		config.job_queue.arches = Some(vec!["arch1".to_string()]);
+		let env = test_env_with_config(config).await;
+		let mut db = env.database.get().await.unwrap();
+		let data = pkg_data("5.2.37", &[]).to_json().unwrap();
+		let pkg = insert_test_package(&mut db, 1, "bash", data).await;
+		for target in ["arch1", "arch2"] {
+			let target = TargetInfo::make_id(target) as i64;
+			let dirty = SqlPackageTargetState::Dirty;
+			insert_test_target(&mut db, &pkg, target, dirty, serde_json::json!({})).await;
+		}
+		drop(db);
+
+		assert_eq!(env.build_cache.schedule(1).await.unwrap(), 2);
+		let job = env.job_queue.fetch_and_start().await.unwrap().unwrap();
+		let JobCommand::BuildPackage { build, .. } = job.command else {
+			panic!("unexpected job: {:?}", job.command);
+		};
+		assert_eq!(build.target, TargetInfo::make_id("arch1"));
+		assert_eq!(build.arch.as_str(), "arch1");
+		// the build on arch2 is not claimed, as its architecture testarch2 is not capable
+		assert!(env.job_queue.fetch_and_start().await.unwrap().is_none());
+	}
+}
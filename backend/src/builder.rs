@@ -0,0 +1,215 @@
+//! Execution of builds.
+//!
+//! Packages are built by an external command configured on each Axis instance,
+//! which writes artifacts into an output directory. The command is responsible
+//! for publishing artifacts, e.g. to the [artifact store](crate::artifact_diff::ArtifactStoreConfig).
+//! SHA-256 digests of artifacts are returned for [`crate::repro`].
+
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+	process::Stdio,
+	sync::Arc,
+};
+
+use diesel::{ExpressionMethods, QueryDsl};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::{fs, process::Command};
+use tracing::info;
+
+use crate::{
+	Result,
+	build_cache::PlannedBuild,
+	db::{
+		schema::{branch::dsl as branch_dsl, pkg::dsl as pkg_dsl},
+		service::DatabaseService,
+		utils::XUuidVal,
+	},
+	job_queue::JobRef,
+	model::{BranchRow, PkgRow},
+	repository::RepositoryService,
+	target::{TargetId, TargetService},
+};
+
+/// Configuration of the build command.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuilderConfig {
+	/// Program and arguments building a package.
+	///
+	/// The command is run with these environment variables:
+	/// - `FABRICIA_JOB`: ID of the build job
+	/// - `FABRICIA_BRANCH`: name of the branch
+	/// - `FABRICIA_REPOSITORY`: URL of the Git repository of the branch
+	/// - `FABRICIA_COMMIT`: commit of the branch, if synchronized
+	/// - `FABRICIA_PACKAGE`: name of the package
+	/// - `FABRICIA_VERSION`: version of the package, as `{epoch}:{version}-{release}`
+	/// - `FABRICIA_TARGET`: name of the target
+	/// - `FABRICIA_ARCH`: architecture of the target
+	/// - `FABRICIA_OUTPUT`: directory to write artifacts into
+	pub command: Vec<String>,
+	/// Directory where output directories of builds are created.
+	///
+	/// Output directories are removed after builds finish.
+	pub work_dir: PathBuf,
+}
+
+/// Service running builds on this instance.
+#[derive(Debug)]
+pub struct BuilderService {
+	db: Arc<DatabaseService>,
+	target: Arc<TargetService>,
+	repository: Arc<RepositoryService>,
+	/// Builds fail with [`BuilderError::NotConfigured`] if not set.
+	config: Option<BuilderConfig>,
+}
+
+impl BuilderService {
+	pub fn new(
+		db: Arc<DatabaseService>,
+		target: Arc<TargetService>,
+		repository: Arc<RepositoryService>,
+		config: Option<&BuilderConfig>,
+	) -> Self {
+		Self {
+			db,
+			target,
+			repository,
+			config: config.cloned(),
+		}
+	}
+
+	/// Builds a package on a target with the build command.
+	///
+	/// Returns SHA-256 digests of artifacts in hex, by file name.
+	pub async fn run(&self, job: JobRef, build: &PlannedBuild) -> Result<BTreeMap<String, String>> {
+		let Some(config) = &self.config else {
+			return Err(BuilderError::NotConfigured.into());
+		};
+		let Some((program, args)) = config.command.split_first() else {
+			return Err(BuilderError::NotConfigured.into());
+		};
+		let target = self
+			.target
+			.get(build.target)
+			.ok_or(BuilderError::UnknownTarget(build.target))?;
+		let mut conn = self.db.get().await?;
+		let pkg: PkgRow = conn
+			.load_one_select(pkg_dsl::pkg.filter(pkg_dsl::id.eq(XUuidVal(build.package))))
+			.await?;
+		let branch: BranchRow = conn
+			.load_one_select(branch_dsl::branch.filter(branch_dsl::id.eq(pkg.branch)))
+			.await?;
+		drop(conn);
+		let data = pkg.pkg_data()?;
+		let repository = self
+			.repository
+			.resolve(branch.repository)
+			.map(|repository| repository.url.clone())
+			.unwrap_or_default();
+		let commit = branch
+			.commit
+			.as_deref()
+			.map(hex::encode)
+			.unwrap_or_default();
+
+		let output = config.work_dir.join(job.to_string());
+		let mut command = Command::new(program);
+		command
+			.args(args)
+			.env("FABRICIA_JOB", job.to_string())
+			.env("FABRICIA_BRANCH", &branch.name)
+			.env("FABRICIA_REPOSITORY", repository)
+			.env("FABRICIA_COMMIT", commit)
+			.env("FABRICIA_PACKAGE", &pkg.name)
+			.env(
+				"FABRICIA_VERSION",
+				format!("{}:{}-{}", data.epoch, data.version, data.release),
+			)
+			.env("FABRICIA_TARGET", target.name.as_str())
+			.env("FABRICIA_ARCH", target.arch.as_str())
+			.env("FABRICIA_OUTPUT", &output)
+			.stdin(Stdio::null())
+			.kill_on_drop(true);
+		info!(%job, package = %pkg.name, target = %target.name, "building package");
+		fs::create_dir_all(&output)
+			.await
+			.map_err(BuilderError::from)?;
+		let result = execute(&mut command, &output).await;
+		fs::remove_dir_all(&output)
+			.await
+			.map_err(BuilderError::from)?;
+		Ok(result?)
+	}
+}
+
+/// Runs the build command, and hashes artifacts in the output directory.
+async fn execute(
+	command: &mut Command,
+	output: &Path,
+) -> Result<BTreeMap<String, String>, BuilderError> {
+	let status = command.status().await?;
+	if !status.success() {
+		return Err(BuilderError::Failed(status.to_string()));
+	}
+	hash_artifacts(output).await
+}
+
+/// Computes SHA-256 digests of regular files in a directory, by file name.
+async fn hash_artifacts(dir: &Path) -> Result<BTreeMap<String, String>, BuilderError> {
+	let mut hashes = BTreeMap::new();
+	let mut entries = fs::read_dir(dir).await?;
+	while let Some(entry) = entries.next_entry().await? {
+		if !entry.file_type().await?.is_file() {
+			continue;
+		}
+		let Ok(name) = entry.file_name().into_string() else {
+			continue;
+		};
+		let data = fs::read(entry.path()).await?;
+		hashes.insert(name, hex::encode(Sha256::digest(&data)));
+	}
+	Ok(hashes)
+}
+
+#[derive(Debug, Error)]
+pub enum BuilderError {
+	#[error("build command is not configured on this instance")]
+	NotConfigured,
+	#[error("target {0} is not configured")]
+	UnknownTarget(TargetId),
+	#[error("build command has failed with {0}")]
+	Failed(String),
+	#[error(transparent)]
+	IoError(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::BTreeMap;
+
+	use uuid::Uuid;
+
+	use crate::builder::hash_artifacts;
+
+	#[tokio::test]
+	async fn test_hash_artifacts() {
+		let dir = std::env::temp_dir().join(format!("fabricia-test-{}", Uuid::now_v7()));
+		tokio::fs::create_dir_all(dir.join("logs")).await.unwrap();
+		tokio::fs::write(dir.join("bash.deb"), b"").await.unwrap();
+		tokio::fs::write(dir.join("logs/build.log"), b"log")
+			.await
+			.unwrap();
+		let hashes = hash_artifacts(&dir).await.unwrap();
+		tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+		// subdirectories are not artifacts
+		let expected = BTreeMap::from([(
+			"bash.deb".to_string(),
+			"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string(),
+		)]);
+		assert_eq!(hashes, expected);
+	}
+}
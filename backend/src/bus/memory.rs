@@ -0,0 +1,105 @@
+//! In-process [`BackendBusService`] implementation.
+
+use std::sync::Arc;
+
+use futures::{
+	FutureExt,
+	future::{BoxFuture, ready},
+};
+use tokio::sync::broadcast;
+use tracing::debug;
+
+use crate::{Result, redis::RedisService};
+
+use super::{
+	BackendBusFactory, BackendBusMessage, BackendBusService, BoxedBusService, C2ABusMessage,
+	LocalBusMessage,
+};
+
+/// Backend bus backed by a [`broadcast`] channel.
+///
+/// Messages are delivered to all subscribers in the same process,
+/// and both broadcast and C2A messages are always handled locally.
+#[derive(Debug)]
+pub struct MemoryBusService {
+	sender: broadcast::Sender<LocalBusMessage>,
+}
+
+impl MemoryBusService {
+	/// The maximum count of messages buffered for slow subscribers.
+	const CAPACITY: usize = 256;
+
+	pub fn new() -> Self {
+		let (sender, _) = broadcast::channel(Self::CAPACITY);
+		Self { sender }
+	}
+
+	fn send(&self, message: LocalBusMessage) {
+		// sending only fails when there are no subscribers
+		if self.sender.send(message).is_err() {
+			debug!("dropped bus message without subscribers");
+		}
+	}
+}
+
+impl Default for MemoryBusService {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl BackendBusService for MemoryBusService {
+	fn broadcast(&self, message: BackendBusMessage) -> BoxFuture<'_, Result<()>> {
+		self.send(LocalBusMessage::Backend(message));
+		ready(Ok(())).boxed()
+	}
+
+	fn send_c2a(&self, message: C2ABusMessage) -> BoxFuture<'_, Result<()>> {
+		self.send(LocalBusMessage::C2A(message));
+		ready(Ok(())).boxed()
+	}
+
+	fn subscribe_local(&self) -> Option<broadcast::Receiver<LocalBusMessage>> {
+		Some(self.sender.subscribe())
+	}
+}
+
+/// Factory of [`MemoryBusService`].
+pub struct MemoryBusFactory;
+
+impl BackendBusFactory for MemoryBusFactory {
+	fn construct(self, _: Arc<RedisService>) -> BoxFuture<'static, Result<BoxedBusService>> {
+		ready(Ok(
+			Box::new(MemoryBusService::new()) as Box<dyn BackendBusService>
+		))
+		.boxed()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_memory_bus() {
+		let bus = MemoryBusService::new();
+		// no subscribers
+		bus.broadcast(BackendBusMessage::JobQueuePaused(true))
+			.await
+			.unwrap();
+
+		let mut receiver = bus.subscribe_local().unwrap();
+		bus.broadcast(BackendBusMessage::JobQueuePaused(false))
+			.await
+			.unwrap();
+		bus.send_c2a(C2ABusMessage::ResumeJobRunner).await.unwrap();
+		assert_eq!(
+			receiver.recv().await.unwrap(),
+			LocalBusMessage::Backend(BackendBusMessage::JobQueuePaused(false))
+		);
+		assert_eq!(
+			receiver.recv().await.unwrap(),
+			LocalBusMessage::C2A(C2ABusMessage::ResumeJobRunner)
+		);
+	}
+}
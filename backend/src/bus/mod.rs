@@ -0,0 +1,94 @@
+//! Backend bus
+
+use std::{fmt::Debug, sync::Arc};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{Result, redis::RedisService};
+
+pub mod memory;
+
+/// Configuration of the backend bus.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BusConfig {
+	/// Implementation of the backend bus.
+	#[serde(default)]
+	pub kind: BusKind,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BusKind {
+	/// Redis pub/sub, delivering messages across instances.
+	#[default]
+	Redis,
+	/// In-process channels, see [`memory::MemoryBusService`].
+	///
+	/// Messages are only delivered within the same process,
+	/// which is suitable for single-node deployments and tests.
+	Memory,
+}
+
+/// A backend bus message that can be broadcasted across the backend bus.
+///
+/// Backend bus messages will be received by all Axis and Crayon
+/// instances listening on the bus.
+///
+/// This kind of message can be used to flush in memory caches across the backend.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub enum BackendBusMessage {
+	/// The job queue has been paused or resumed.
+	JobQueuePaused(bool),
+	/// Running jobs have been cancelled, and their runners should abort them.
+	JobsCancelled(Vec<Uuid>),
+}
+
+/// A backend bus message from Crayon to Axis.
+///
+/// Not all Axis instances will receive the posted C2A message.
+/// When attempting to post a C2A bus message from a Axis instance,
+/// the message will be immediately handled locally, and will not be
+/// published to other instances.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub enum C2ABusMessage {
+	ResumeJobRunner,
+	/// Resize the pool of default job runners to the given count.
+	ResizeJobRunners(usize),
+}
+
+/// A message delivered by an in-process bus.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LocalBusMessage {
+	Backend(BackendBusMessage),
+	C2A(C2ABusMessage),
+}
+
+pub trait BackendBusService
+where
+	Self: Send + Sync + Debug,
+{
+	fn broadcast(&self, message: BackendBusMessage) -> BoxFuture<'_, Result<()>>;
+	fn send_c2a(&self, message: C2ABusMessage) -> BoxFuture<'_, Result<()>>;
+
+	/// Subscribes to messages of an in-process bus.
+	///
+	/// Returns [`None`] if messages are delivered by an external broker,
+	/// and should be received from it instead.
+	fn subscribe_local(&self) -> Option<broadcast::Receiver<LocalBusMessage>> {
+		None
+	}
+}
+
+pub type BoxedBusService = Box<dyn BackendBusService + 'static>;
+
+pub trait BackendBusFactory {
+	fn construct(self, redis: Arc<RedisService>) -> BoxFuture<'static, Result<BoxedBusService>>;
+}
+
+/// Redis channels of the bus, prefixed by [`RedisService::key`].
+pub const BACKEND_BUS_CHANNEL: &str = "bus:backend";
+pub const BACKEND_BUS_C2A_CHANNEL: &str = "bus:c2a";
@@ -0,0 +1,161 @@
+//! Changelogs of branches.
+//!
+//! Changelog entries of packages are extracted into [`PkgData::changelog`] on
+//! synchronization of branches. The changelog of a branch aggregates entries of its
+//! packages which are not in its base branch, so that release notes of a branch do
+//! not need to be assembled by hand.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Result, model::PkgRow, package::PkgData};
+
+/// A changelog entry of a package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChangelogEntry {
+	/// Full version introducing the change, e.g. `1:5.2.37-1`.
+	pub version: String,
+	pub author: String,
+	pub message: String,
+}
+
+/// Changelog entries of a package since the base branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageChangelog {
+	pub name: String,
+	/// Full version of the package in the branch.
+	pub version: String,
+	/// Entries not in the base branch, newest first.
+	pub entries: Vec<ChangelogEntry>,
+}
+
+/// Formats the full version of a package, like `{epoch}:{version}-{release}`.
+///
+/// The epoch is omitted if zero.
+pub fn full_version(data: &PkgData) -> String {
+	match data.epoch {
+		0 => format!("{}-{}", data.version, data.release),
+		epoch => format!("{epoch}:{}-{}", data.version, data.release),
+	}
+}
+
+/// Returns entries of a changelog whose versions are not in the changelog of the
+/// same package in the base branch.
+pub fn entries_since(entries: &[ChangelogEntry], base: &[ChangelogEntry]) -> Vec<ChangelogEntry> {
+	let base = base
+		.iter()
+		.map(|entry| entry.version.as_str())
+		.collect::<HashSet<_>>();
+	entries
+		.iter()
+		.filter(|entry| !base.contains(entry.version.as_str()))
+		.cloned()
+		.collect()
+}
+
+/// Aggregates changelogs of packages of a branch since its base branch, by name.
+///
+/// Packages without new entries are omitted. Without a base branch, all entries
+/// are included.
+pub fn aggregate(packages: &[PkgRow], base: &[PkgRow]) -> Result<Vec<PackageChangelog>> {
+	let mut base_changelogs = HashMap::with_capacity(base.len());
+	for row in base {
+		base_changelogs.insert(row.name.as_str(), row.pkg_data()?.changelog);
+	}
+	let mut changelogs = Vec::new();
+	for row in packages {
+		let data = row.pkg_data()?;
+		let base = base_changelogs
+			.get(row.name.as_str())
+			.map(Vec::as_slice)
+			.unwrap_or_default();
+		let entries = entries_since(&data.changelog, base);
+		if entries.is_empty() {
+			continue;
+		}
+		changelogs.push(PackageChangelog {
+			name: row.name.clone(),
+			version: full_version(&data),
+			entries,
+		});
+	}
+	changelogs.sort_by(|a, b| a.name.cmp(&b.name));
+	Ok(changelogs)
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{
+		branch::BranchRef,
+		changelog::{ChangelogEntry, PackageChangelog, aggregate},
+		model::PkgRow,
+		package::PkgData,
+		test::test_package,
+	};
+
+	fn entry(version: &str, message: &str) -> ChangelogEntry {
+		ChangelogEntry {
+			version: version.to_string(),
+			author: "Alice <alice@example.com>".to_string(),
+			message: message.to_string(),
+		}
+	}
+
+	fn row(branch: BranchRef, name: &str, version: &str, changelog: Vec<ChangelogEntry>) -> PkgRow {
+		let data = PkgData {
+			version: version.to_string(),
+			release: 1,
+			changelog,
+			..Default::default()
+		};
+		test_package(branch, name, data.to_json().unwrap())
+	}
+
+	#[test]
+	fn test_aggregate() {
+		let base = [
+			row(1, "bash", "5.2.37", vec![entry("5.2.37-1", "update")]),
+			row(1, "zlib", "1.3.1", vec![entry("1.3.1-1", "update")]),
+		];
+		let packages = [
+			row(
+				2,
+				"zlib",
+				"1.3.1",
+				vec![entry("1.3.1-1", "update edited on the branch")],
+			),
+			row(
+				2,
+				"bash",
+				"5.3",
+				vec![
+					entry("5.3-1", "update to 5.3"),
+					entry("5.2.37-2", "fix CVE"),
+					entry("5.2.37-1", "update"),
+				],
+			),
+			row(2, "xz", "5.8.1", vec![entry("5.8.1-1", "new package")]),
+		];
+		assert_eq!(
+			aggregate(&packages, &base).unwrap(),
+			[
+				PackageChangelog {
+					name: "bash".to_string(),
+					version: "5.3-1".to_string(),
+					entries: vec![
+						entry("5.3-1", "update to 5.3"),
+						entry("5.2.37-2", "fix CVE")
+					],
+				},
+				PackageChangelog {
+					name: "xz".to_string(),
+					version: "5.8.1-1".to_string(),
+					entries: vec![entry("5.8.1-1", "new package")],
+				},
+			]
+		);
+		assert_eq!(aggregate(&packages, &[]).unwrap().len(), 3);
+	}
+}
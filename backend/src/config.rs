@@ -1,10 +1,68 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{db::service::DatabaseConfig, redis::RedisConfig, target::TargetConfig};
+use crate::{
+	artifact_diff::ArtifactStoreConfig,
+	branch_scan::BranchScanConfig,
+	builder::BuilderConfig,
+	bus::BusConfig,
+	db::service::DatabaseConfig,
+	evaluator::EvaluatorConfig,
+	job_queue::JobQueueConfig,
+	lint::LintConfig,
+	mirror::MirrorConfig,
+	redis::RedisConfig,
+	repository::RepositoryConfig,
+	repro::ReproConfig,
+	security::SecurityConfig,
+	target::{TargetConfig, TargetGroupConfig},
+	upstream::UpstreamConfig,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash, Deserialize, Serialize)]
 pub struct BackendConfig {
 	pub database: DatabaseConfig,
-	pub redis: RedisConfig,
+	/// Redis is optional for single-node deployments.
+	///
+	/// Without Redis, locks are in-process and the in-memory bus is used.
+	#[serde(default)]
+	pub redis: Option<RedisConfig>,
 	pub target: Vec<TargetConfig>,
+	/// Named groups of targets, which branches may select.
+	#[serde(default)]
+	pub target_group: Vec<TargetGroupConfig>,
+	/// Git repositories of branches.
+	///
+	/// The first repository is used by branches without repositories.
+	#[serde(default)]
+	pub repository: Vec<RepositoryConfig>,
+	pub job_queue: JobQueueConfig,
+	/// Static checks of packages.
+	#[serde(default)]
+	pub lint: LintConfig,
+	/// Feeds of security advisories.
+	#[serde(default)]
+	pub security: SecurityConfig,
+	/// Upstream version checks, disabled if not set.
+	#[serde(default)]
+	pub upstream: Option<UpstreamConfig>,
+	/// Mirror of package sources, disabled if not set.
+	#[serde(default)]
+	pub mirror: Option<MirrorConfig>,
+	/// Evaluation command, required to synchronize branches on this instance.
+	#[serde(default)]
+	pub evaluator: Option<EvaluatorConfig>,
+	/// Build command, required to run builds on this instance.
+	#[serde(default)]
+	pub builder: Option<BuilderConfig>,
+	/// Reproducible build verification, disabled if not set.
+	#[serde(default)]
+	pub repro: Option<ReproConfig>,
+	/// Store of build artifacts, required to compare artifacts of builds.
+	#[serde(default)]
+	pub artifact_store: Option<ArtifactStoreConfig>,
+	/// Discovery of topic branches, disabled if not set.
+	#[serde(default)]
+	pub branch_scan: Option<BranchScanConfig>,
+	#[serde(default)]
+	pub bus: BusConfig,
 }
@@ -0,0 +1,159 @@
+//! Guard against instances of incompatible versions sharing a database.
+//!
+//! Each build has an application schema version, which is recorded in the
+//! database with the minimum version of instances compatible with it, by the
+//! newest instance started. Older instances below the minimum refuse to start,
+//! as they may corrupt data of the newer schema.
+
+use diesel::{ExpressionMethods, OptionalExtension, insert_into, update};
+use tracing::{info, warn};
+
+use crate::{
+	Result,
+	db::{BoxedSqlConn, schema::app_schema::dsl, service::DatabaseError},
+	model::AppSchemaRow,
+};
+
+/// Application schema version of this build.
+///
+/// This should be increased when the meaning of existing data changes,
+/// which is not always accompanied by migrations.
+pub const SCHEMA_VERSION: i32 = 1;
+
+/// The minimum schema version of instances compatible with this build.
+///
+/// This should be raised to [`SCHEMA_VERSION`] when instances of older versions
+/// would corrupt data written by this build.
+pub const MIN_COMPATIBLE_VERSION: i32 = 1;
+
+/// ID of the only row of [`crate::db::schema::app_schema`].
+const APP_SCHEMA_ID: i32 = 1;
+
+/// Checks the recorded schema against this build, and returns the record to be
+/// stored if this build is newer.
+fn check(stored: Option<AppSchemaRow>) -> Result<Option<AppSchemaRow>, DatabaseError> {
+	let current = AppSchemaRow {
+		id: APP_SCHEMA_ID,
+		version: SCHEMA_VERSION,
+		min_compatible: MIN_COMPATIBLE_VERSION,
+	};
+	let Some(stored) = stored else {
+		return Ok(Some(current));
+	};
+	if SCHEMA_VERSION < stored.min_compatible {
+		return Err(DatabaseError::IncompatibleSchema {
+			min_compatible: stored.min_compatible,
+			supported: SCHEMA_VERSION,
+		});
+	}
+	match stored.version < SCHEMA_VERSION {
+		true => Ok(Some(current)),
+		false => Ok(None),
+	}
+}
+
+/// Records the schema version of this build, or fails if the database has been
+/// used by newer instances incompatible with this build.
+///
+/// This should be called after migrations, with the migration lock held.
+pub async fn check_schema(conn: &mut BoxedSqlConn) -> Result<()> {
+	conn.transaction::<_, crate::BackendError, _>(async |conn| {
+		let stored: Option<AppSchemaRow> =
+			conn.load_one_select(dsl::app_schema).await.optional()?;
+		match (check(stored)?, stored) {
+			(Some(current), None) => {
+				conn.execute(insert_into(dsl::app_schema).values(current))
+					.await?;
+			}
+			(Some(current), Some(stored)) => {
+				conn.execute(
+					update(dsl::app_schema)
+						.filter(dsl::id.eq(APP_SCHEMA_ID))
+						.set((
+							dsl::version.eq(current.version),
+							dsl::min_compatible.eq(current.min_compatible),
+						)),
+				)
+				.await?;
+				info!(
+					from = stored.version,
+					to = current.version,
+					"upgraded application schema version"
+				);
+			}
+			(None, Some(stored)) if stored.version > SCHEMA_VERSION => {
+				warn!(
+					version = SCHEMA_VERSION,
+					recorded = stored.version,
+					"database has been used by newer instances"
+				);
+			}
+			(None, _) => {}
+		}
+		Ok(())
+	})
+	.await
+}
+
+#[cfg(test)]
+mod test {
+	use diesel::{ExpressionMethods, update};
+
+	use crate::{
+		BackendError,
+		db::{
+			compat::{MIN_COMPATIBLE_VERSION, SCHEMA_VERSION, check, check_schema},
+			schema::app_schema::dsl,
+			service::DatabaseError,
+		},
+		model::AppSchemaRow,
+		test::test_env,
+	};
+
+	fn row(version: i32, min_compatible: i32) -> AppSchemaRow {
+		AppSchemaRow {
+			id: 1,
+			version,
+			min_compatible,
+		}
+	}
+
+	#[test]
+	fn test_check() {
+		let current = row(SCHEMA_VERSION, MIN_COMPATIBLE_VERSION);
+		assert_eq!(check(None).unwrap(), Some(current));
+		assert_eq!(check(Some(row(0, 0))).unwrap(), Some(current));
+		assert_eq!(check(Some(current)).unwrap(), None);
+		// newer, but compatible
+		assert_eq!(
+			check(Some(row(SCHEMA_VERSION + 1, SCHEMA_VERSION))).unwrap(),
+			None
+		);
+		assert!(matches!(
+			check(Some(row(SCHEMA_VERSION + 1, SCHEMA_VERSION + 1))),
+			Err(DatabaseError::IncompatibleSchema { .. })
+		));
+	}
+
+	#[tokio::test]
+	async fn test_check_schema() {
+		let env = test_env().await;
+		let mut db = env.database.get().await.unwrap();
+		// recorded on startup
+		let stored: AppSchemaRow = db.load_one_select(dsl::app_schema).await.unwrap();
+		assert_eq!(stored, row(SCHEMA_VERSION, MIN_COMPATIBLE_VERSION));
+
+		db.execute(update(dsl::app_schema).set((
+			dsl::version.eq(SCHEMA_VERSION + 1),
+			dsl::min_compatible.eq(SCHEMA_VERSION + 1),
+		)))
+		.await
+		.unwrap();
+		assert!(matches!(
+			check_schema(&mut db).await,
+			Err(BackendError::DatabaseError(
+				DatabaseError::IncompatibleSchema { .. }
+			))
+		));
+	}
+}
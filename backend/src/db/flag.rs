@@ -0,0 +1,60 @@
+//! Flags shared by instances through the database.
+//!
+//! Services keep their shared states in Redis if configured, and fall back to
+//! these flags otherwise, so that instances sharing only the database agree.
+
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, delete, insert_into};
+
+use crate::{
+	Result,
+	db::{BoxedSqlConn, schema::cluster_flag::dsl},
+};
+
+/// Returns the value of a flag, [`None`] if the flag is not set.
+pub async fn get(conn: &mut BoxedSqlConn, name: &str) -> Result<Option<String>> {
+	Ok(conn
+		.get_result::<_, String>(
+			dsl::cluster_flag
+				.filter(dsl::name.eq(name))
+				.select(dsl::value),
+		)
+		.await
+		.optional()?)
+}
+
+/// Sets the value of a flag, or clears the flag with [`None`].
+pub async fn set(conn: &mut BoxedSqlConn, name: &str, value: Option<&str>) -> Result<()> {
+	conn.transaction::<_, crate::BackendError, _>(async |conn| {
+		conn.execute(delete(dsl::cluster_flag.filter(dsl::name.eq(name))))
+			.await?;
+		if let Some(value) = value {
+			conn.execute(
+				insert_into(dsl::cluster_flag).values((dsl::name.eq(name), dsl::value.eq(value))),
+			)
+			.await?;
+		}
+		Ok(())
+	})
+	.await
+}
+
+#[cfg(test)]
+mod test {
+	use crate::test::test_env;
+
+	#[tokio::test]
+	async fn test_flag() {
+		let env = test_env().await;
+		let mut conn = env.database.get().await.unwrap();
+
+		assert_eq!(super::get(&mut conn, "test").await.unwrap(), None);
+		super::set(&mut conn, "test", Some("1")).await.unwrap();
+		super::set(&mut conn, "test", Some("2")).await.unwrap();
+		assert_eq!(
+			super::get(&mut conn, "test").await.unwrap().as_deref(),
+			Some("2")
+		);
+		super::set(&mut conn, "test", None).await.unwrap();
+		assert_eq!(super::get(&mut conn, "test").await.unwrap(), None);
+	}
+}
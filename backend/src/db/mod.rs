@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use diesel::{
 	QueryResult, Queryable, RunQueryDsl, Selectable, SelectableHelper, SqliteConnection,
 	connection::{AnsiTransactionManager, SimpleConnection, TransactionManager},
@@ -7,6 +9,7 @@ use diesel::{
 	pg::Pg,
 	query_builder::{AsQuery, QueryId},
 	query_dsl::methods::{ExecuteDsl, LimitDsl, LoadQuery, SelectDsl},
+	result::DatabaseErrorKind,
 	sql_types::{self, HasSqlType, SqlType},
 	sqlite::Sqlite,
 };
@@ -20,7 +23,12 @@ use diesel_async::{
 };
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 use futures::future::{BoxFuture, FutureExt, ready};
+use rand::Rng;
+use tracing::warn;
 
+pub mod compat;
+pub mod flag;
+pub mod phase;
 pub mod schema;
 pub mod service;
 pub mod utils;
@@ -52,6 +60,14 @@ pub enum BoxedSqlConn {
 }
 
 impl BoxedSqlConn {
+	/// Returns the name of the SQL backend, e.g. `postgresql`.
+	pub fn backend_name(&self) -> &'static str {
+		match self {
+			BoxedSqlConn::Pg(_) => "postgresql",
+			BoxedSqlConn::Sqlite(_) => "sqlite",
+		}
+	}
+
 	/// Executes `SELECT 1` to test if the connection is ready for use.
 	pub fn ping(&mut self) -> BoxFuture<Result<(), diesel::result::Error>> {
 		match self {
@@ -77,35 +93,14 @@ impl BoxedSqlConn {
 		E: From<diesel::result::Error> + Send,
 		R: Send,
 	{
-		match self {
-			BoxedSqlConn::Pg(conn) => {
-				AsyncAnsiTransactionManager::begin_transaction(conn).await?;
-			}
-			BoxedSqlConn::Sqlite(conn) => {
-				AnsiTransactionManager::begin_transaction(conn)?;
-			}
-		}
+		self.begin_transaction().await?;
 		match callback(self).await {
 			Ok(value) => {
-				match self {
-					BoxedSqlConn::Pg(conn) => {
-						AsyncAnsiTransactionManager::commit_transaction(conn).await?;
-					}
-					BoxedSqlConn::Sqlite(conn) => {
-						AnsiTransactionManager::commit_transaction(conn)?;
-					}
-				}
+				self.commit_transaction().await?;
 				Ok(value)
 			}
 			Err(user_error) => {
-				let result = match self {
-					BoxedSqlConn::Pg(conn) => {
-						AsyncAnsiTransactionManager::rollback_transaction(conn).await
-					}
-					BoxedSqlConn::Sqlite(conn) => {
-						AnsiTransactionManager::rollback_transaction(conn)
-					}
-				};
+				let result = self.rollback_transaction().await;
 				match result {
 					Ok(()) => Err(user_error),
 					Err(diesel::result::Error::BrokenTransactionManager) => {
@@ -118,6 +113,98 @@ impl BoxedSqlConn {
 			}
 		}
 	}
+
+	/// Runs a transaction, retrying it if it fails with a retryable error.
+	///
+	/// Retryable errors are serialization failures and deadlocks on PostgreSQL,
+	/// and busy databases on SQLite, see [`RetryableError`].
+	/// Attempts are separated with jittered exponential backoff, and the last error is returned after `max_attempts`.
+	///
+	/// Each attempt calls a clone of `callback`, so that its future owns the captures
+	/// instead of borrowing the callback, which would make the future not `Send`.
+	///
+	/// This must not be called in another transaction, as rolling back
+	/// a savepoint does not resolve conflicts of the outer transaction.
+	pub async fn transaction_with_retries<R, E, F>(
+		&mut self,
+		max_attempts: u32,
+		callback: F,
+	) -> Result<R, E>
+	where
+		F: AsyncFnOnce(&mut Self) -> Result<R, E> + Clone,
+		E: From<diesel::result::Error> + RetryableError + Send,
+		R: Send,
+	{
+		let mut attempt = 1;
+		loop {
+			match self.transaction(callback.clone()).await {
+				Err(error) if attempt < max_attempts && error.is_retryable() => {
+					let backoff = TRANSACTION_RETRY_BACKOFF_MS << (attempt - 1).min(6);
+					let backoff = rand::rng().random_range(backoff / 2..=backoff);
+					warn!(attempt, backoff, "transaction conflicted, retrying");
+					tokio::time::sleep(Duration::from_millis(backoff)).await;
+					attempt += 1;
+				}
+				result => return result,
+			}
+		}
+	}
+
+	/// Begins a transaction, or a savepoint if a transaction has been begun.
+	///
+	/// Prefer [`BoxedSqlConn::transaction`] unless the transaction outlives a scope.
+	pub async fn begin_transaction(&mut self) -> QueryResult<()> {
+		match self {
+			BoxedSqlConn::Pg(conn) => AsyncAnsiTransactionManager::begin_transaction(conn).await,
+			BoxedSqlConn::Sqlite(conn) => AnsiTransactionManager::begin_transaction(conn),
+		}
+	}
+
+	/// Commits the innermost transaction begun by [`BoxedSqlConn::begin_transaction`].
+	pub async fn commit_transaction(&mut self) -> QueryResult<()> {
+		match self {
+			BoxedSqlConn::Pg(conn) => AsyncAnsiTransactionManager::commit_transaction(conn).await,
+			BoxedSqlConn::Sqlite(conn) => AnsiTransactionManager::commit_transaction(conn),
+		}
+	}
+
+	/// Rolls back the innermost transaction begun by [`BoxedSqlConn::begin_transaction`].
+	pub async fn rollback_transaction(&mut self) -> QueryResult<()> {
+		match self {
+			BoxedSqlConn::Pg(conn) => AsyncAnsiTransactionManager::rollback_transaction(conn).await,
+			BoxedSqlConn::Sqlite(conn) => AnsiTransactionManager::rollback_transaction(conn),
+		}
+	}
+}
+
+/// Base delay of backoff between attempts of [`BoxedSqlConn::transaction_with_retries`].
+const TRANSACTION_RETRY_BACKOFF_MS: u64 = 20;
+
+/// Default count of attempts for [`BoxedSqlConn::transaction_with_retries`].
+pub const DEFAULT_TRANSACTION_ATTEMPTS: u32 = 5;
+
+/// Errors which may be resolved by retrying the whole transaction.
+pub trait RetryableError {
+	fn is_retryable(&self) -> bool;
+}
+
+impl RetryableError for diesel::result::Error {
+	fn is_retryable(&self) -> bool {
+		match self {
+			diesel::result::Error::DatabaseError(DatabaseErrorKind::SerializationFailure, _) => {
+				true
+			}
+			diesel::result::Error::DatabaseError(DatabaseErrorKind::Unknown, info) => {
+				// Deadlocks on PostgreSQL (SQLSTATE 40P01), SQLITE_BUSY and SQLITE_LOCKED
+				// are not classified by diesel
+				let message = info.message();
+				message.contains("deadlock detected")
+					|| message.contains("database is locked")
+					|| message.contains("database table is locked")
+			}
+			_ => false,
+		}
+	}
 }
 
 impl<'query> BoxedSqlConn {
@@ -307,31 +394,77 @@ impl<'query> BoxedSqlConn {
 	}
 }
 
+diesel::table! {
+	/// Table of applied migrations, maintained by [`diesel_migrations`].
+	__diesel_schema_migrations (version) {
+		version -> VarChar,
+		run_on -> Timestamp,
+	}
+}
+
 const POSTGRESQL_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/postgresql");
 const SQLITE_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/sqlite");
 
-/// Run all pending migrations.
+/// Key of the PostgreSQL advisory lock held while running migrations.
+///
+/// This excludes instances sharing only the database, which do not share
+/// locks of [`crate::lock::LockService`] without Redis.
+const MIGRATION_ADVISORY_LOCK: i64 = 0x4661_6272_6963_6961;
+
+/// Run pending migrations of a phase, or of all phases if `phase` is [`None`].
 ///
 /// This is not async, so a spawn-blocking wrapper is required.
 ///
-/// Dispatches [MigrationHarness::run_pending_migrations].
+/// See [`phase`] for phases of migrations.
 pub fn run_migrations(
-	mut conn: BoxedSqlConn,
+	conn: BoxedSqlConn,
+	phase: Option<phase::SqlMigrationPhase>,
+) -> diesel::migration::Result<Vec<phase::AppliedMigration>> {
+	match conn {
+		BoxedSqlConn::Pg(conn) => {
+			let mut async_wrapper: AsyncConnectionWrapper<AsyncPgConnection> =
+				AsyncConnectionWrapper::from(conn);
+			// released with the session when the connection is dropped
+			SimpleConnection::batch_execute(
+				&mut async_wrapper,
+				&format!("SELECT pg_advisory_lock({MIGRATION_ADVISORY_LOCK})"),
+			)?;
+			phase::run_phase(&mut async_wrapper, POSTGRESQL_MIGRATIONS, phase)
+		}
+		BoxedSqlConn::Sqlite(mut conn) => phase::run_phase(&mut conn, SQLITE_MIGRATIONS, phase),
+	}
+}
+
+/// Returns versions of migrations which are not applied yet.
+///
+/// This is not async, so a spawn-blocking wrapper is required.
+///
+/// Dispatches [MigrationHarness::pending_migrations].
+pub fn pending_migrations(
+	conn: BoxedSqlConn,
 ) -> diesel::migration::Result<Vec<MigrationVersion<'static>>> {
 	match conn {
 		BoxedSqlConn::Pg(conn) => {
 			let mut async_wrapper: AsyncConnectionWrapper<AsyncPgConnection> =
 				AsyncConnectionWrapper::from(conn);
 			async_wrapper
-				.run_pending_migrations(POSTGRESQL_MIGRATIONS)
-				.map(|versions| {
-					versions
-						.into_iter()
-						.map(|version| version.as_owned())
+				.pending_migrations(POSTGRESQL_MIGRATIONS)
+				.map(|migrations| {
+					migrations
+						.iter()
+						.map(|migration| migration.name().version().as_owned())
+						.collect()
+				})
+		}
+		BoxedSqlConn::Sqlite(mut conn) => {
+			conn.pending_migrations(SQLITE_MIGRATIONS)
+				.map(|migrations| {
+					migrations
+						.iter()
+						.map(|migration| migration.name().version().as_owned())
 						.collect()
 				})
 		}
-		BoxedSqlConn::Sqlite(_) => run_migrations_sqlite(&mut conn),
 	}
 }
 
@@ -341,24 +474,30 @@ pub fn run_migrations(
 /// to avoid taking over the connection ownership.
 pub fn run_migrations_sqlite(
 	conn: &mut BoxedSqlConn,
-) -> diesel::migration::Result<Vec<MigrationVersion<'static>>> {
+) -> diesel::migration::Result<Vec<phase::AppliedMigration>> {
 	match conn {
 		BoxedSqlConn::Pg(_) => unreachable!(),
-		BoxedSqlConn::Sqlite(conn) => {
-			conn.run_pending_migrations(SQLITE_MIGRATIONS)
-				.map(|versions| {
-					versions
-						.into_iter()
-						.map(|version| version.as_owned())
-						.collect()
-				})
-		}
+		BoxedSqlConn::Sqlite(conn) => phase::run_phase(conn, SQLITE_MIGRATIONS, None),
 	}
 }
 
+/// Returns the version of the latest migration applied to the database.
+///
+/// Instances of different versions may share a database, so that this may be
+/// newer than migrations embedded in this instance.
+pub async fn migration_level(conn: &mut BoxedSqlConn) -> QueryResult<Option<String>> {
+	conn.get_result(
+		__diesel_schema_migrations::table
+			.select(diesel::dsl::max(__diesel_schema_migrations::version)),
+	)
+	.await
+}
+
 #[cfg(test)]
 
 pub(crate) mod test {
+	use std::cell::Cell;
+
 	use diesel::Connection;
 
 	use super::*;
@@ -370,6 +509,68 @@ pub(crate) mod test {
 	#[test]
 	fn test_sqlite_migrations() {
 		let db = make_empty_test_db();
-		run_migrations(db).unwrap();
+		run_migrations(db, None).unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_migration_level() {
+		let mut db = make_empty_test_db();
+		let versions = run_migrations_sqlite(&mut db).unwrap();
+		assert_eq!(db.backend_name(), "sqlite");
+		assert_eq!(
+			migration_level(&mut db).await.unwrap(),
+			versions
+				.iter()
+				.map(|(version, _)| version)
+				.max()
+				.map(|version| version.to_string())
+		);
+	}
+
+	#[tokio::test]
+	async fn test_transaction_with_retries() {
+		let mut db = make_empty_test_db();
+		// callbacks are cloned for each attempt, so attempts are counted in a cell
+		let attempts = Cell::new(0);
+		let result = db
+			.transaction_with_retries::<_, diesel::result::Error, _>(3, async |_| {
+				attempts.set(attempts.get() + 1);
+				if attempts.get() < 3 {
+					Err(diesel::result::Error::DatabaseError(
+						DatabaseErrorKind::SerializationFailure,
+						Box::new("could not serialize access".to_string()),
+					))
+				} else {
+					Ok(attempts.get())
+				}
+			})
+			.await;
+		assert_eq!(result.unwrap(), 3);
+
+		attempts.set(0);
+		let result = db
+			.transaction_with_retries::<_, diesel::result::Error, _>(3, async |_| {
+				attempts.set(attempts.get() + 1);
+				if attempts.get() < 2 {
+					Err(diesel::result::Error::DatabaseError(
+						DatabaseErrorKind::Unknown,
+						Box::new("deadlock detected".to_string()),
+					))
+				} else {
+					Ok(attempts.get())
+				}
+			})
+			.await;
+		assert_eq!(result.unwrap(), 2);
+
+		attempts.set(0);
+		let result = db
+			.transaction_with_retries::<(), _, _>(3, async |_| {
+				attempts.set(attempts.get() + 1);
+				Err(diesel::result::Error::NotFound)
+			})
+			.await;
+		assert!(matches!(result, Err(diesel::result::Error::NotFound)));
+		assert_eq!(attempts.get(), 1);
 	}
 }
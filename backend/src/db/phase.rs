@@ -0,0 +1,185 @@
+//! Expand/contract phases of migrations, for upgrades without downtime.
+//!
+//! Expand migrations only add to the schema, so that instances of previous
+//! versions keep working with them, and are run at startup. Contract migrations
+//! remove or change what previous versions depend on. They are named with
+//! [`CONTRACT_SUFFIX`], and with [`DatabaseConfig::expand_only`] set, they are
+//! deferred until [`DatabaseService::contract`] is run by an admin after all
+//! instances have been upgraded.
+//!
+//! [`DatabaseConfig::expand_only`]: super::service::DatabaseConfig::expand_only
+//! [`DatabaseService::contract`]: super::service::DatabaseService::contract
+
+use diesel::{
+	AsExpression, FromSqlRow,
+	backend::Backend,
+	insert_into,
+	migration::{Migration, MigrationSource, MigrationVersion},
+	sql_types::SmallInt,
+};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use crate::{
+	db::{BoxedSqlConn, schema::migration_phase, utils::small_int_enum},
+	model::MigrationPhaseRow,
+};
+
+/// Suffix of names of contract migrations, e.g. `0042_drop_pkg_legacy_contract`.
+pub const CONTRACT_SUFFIX: &str = "_contract";
+
+/// Phase of a migration.
+///
+/// Stored as a tiny unsigned column. Unknown values are decoded as contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = SmallInt)]
+#[repr(u8)]
+pub enum SqlMigrationPhase {
+	/// The migration is compatible with instances of previous versions.
+	Expand = 0,
+	/// The migration breaks instances of previous versions.
+	Contract = 1,
+}
+
+impl SqlMigrationPhase {
+	/// Classifies a migration by its name.
+	pub fn of(name: &str) -> Self {
+		match name.ends_with(CONTRACT_SUFFIX) {
+			true => Self::Contract,
+			false => Self::Expand,
+		}
+	}
+}
+
+impl From<u8> for SqlMigrationPhase {
+	fn from(value: u8) -> Self {
+		Self::from(value as i16)
+	}
+}
+
+impl From<i16> for SqlMigrationPhase {
+	fn from(value: i16) -> Self {
+		match value {
+			0 => Self::Expand,
+			1 => Self::Contract,
+			_ => Self::Contract,
+		}
+	}
+}
+
+small_int_enum!(SqlMigrationPhase);
+
+/// A migration applied by [`run_phase`].
+pub type AppliedMigration = (MigrationVersion<'static>, SqlMigrationPhase);
+
+/// Embedded migrations of one phase, or of all phases if `phase` is [`None`].
+struct PhasedMigrations {
+	source: EmbeddedMigrations,
+	phase: Option<SqlMigrationPhase>,
+}
+
+impl<DB: Backend> MigrationSource<DB> for PhasedMigrations {
+	fn migrations(&self) -> diesel::migration::Result<Vec<Box<dyn Migration<DB>>>> {
+		let migrations = MigrationSource::<DB>::migrations(&self.source)?;
+		Ok(migrations
+			.into_iter()
+			.filter(|migration| {
+				self.phase.is_none_or(|phase| {
+					SqlMigrationPhase::of(&migration.name().to_string()) == phase
+				})
+			})
+			.collect())
+	}
+}
+
+/// Runs pending migrations of one phase, or of all phases if `phase` is [`None`].
+///
+/// Dispatches [MigrationHarness::run_migrations].
+pub(super) fn run_phase<DB: Backend>(
+	harness: &mut impl MigrationHarness<DB>,
+	source: EmbeddedMigrations,
+	phase: Option<SqlMigrationPhase>,
+) -> diesel::migration::Result<Vec<AppliedMigration>> {
+	let pending = harness.pending_migrations(PhasedMigrations { source, phase })?;
+	harness.run_migrations(&pending)?;
+	Ok(pending
+		.iter()
+		.map(|migration| {
+			let name = migration.name();
+			(
+				name.version().as_owned(),
+				SqlMigrationPhase::of(&name.to_string()),
+			)
+		})
+		.collect())
+}
+
+/// Records phases of applied migrations in [`migration_phase`].
+pub async fn record_migrations(
+	conn: &mut BoxedSqlConn,
+	applied: &[AppliedMigration],
+) -> diesel::QueryResult<()> {
+	if applied.is_empty() {
+		return Ok(());
+	}
+	let time = OffsetDateTime::now_utc();
+	let rows = applied
+		.iter()
+		.map(|(version, phase)| MigrationPhaseRow {
+			version: version.to_string(),
+			phase: *phase,
+			applied_at: PrimitiveDateTime::new(time.date(), time.time()),
+		})
+		.collect::<Vec<_>>();
+	conn.execute(insert_into(migration_phase::table).values(rows))
+		.await?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use diesel::QueryDsl;
+
+	use crate::db::{
+		BoxedSqlConn, SQLITE_MIGRATIONS,
+		phase::{SqlMigrationPhase, record_migrations, run_phase},
+		schema::migration_phase,
+		test::make_empty_test_db,
+	};
+
+	#[test]
+	fn test_classify() {
+		assert_eq!(
+			SqlMigrationPhase::of("0031_migration_phase"),
+			SqlMigrationPhase::Expand
+		);
+		assert_eq!(
+			SqlMigrationPhase::of("0042_drop_pkg_legacy_contract"),
+			SqlMigrationPhase::Contract
+		);
+	}
+
+	#[tokio::test]
+	async fn test_record_migrations() {
+		let mut db = make_empty_test_db();
+		let BoxedSqlConn::Sqlite(conn) = &mut db else {
+			unreachable!()
+		};
+		let applied = run_phase(conn, SQLITE_MIGRATIONS, Some(SqlMigrationPhase::Expand)).unwrap();
+		assert!(!applied.is_empty());
+		assert!(
+			applied
+				.iter()
+				.all(|(_, phase)| *phase == SqlMigrationPhase::Expand)
+		);
+		// nothing left of other phases
+		assert!(run_phase(conn, SQLITE_MIGRATIONS, None).unwrap().is_empty());
+
+		record_migrations(&mut db, &applied).await.unwrap();
+		let count = db
+			.get_result::<_, i64>(migration_phase::table.count())
+			.await
+			.unwrap();
+		assert_eq!(count, applied.len() as i64);
+	}
+}
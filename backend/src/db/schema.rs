@@ -15,10 +15,67 @@ diesel::table! {
 		priority -> Int2,
 		/// Commit OID of the current tracked metadata.
 		commit -> Nullable<Binary>,
+		/// Commit OID which this branch is pinned to.
+		///
+		/// Syncs check out this commit instead of the head of the Git branch
+		/// until the branch is unpinned.
+		pinned_commit -> Nullable<Binary>,
 		/// Tracking mode [crate::branch::SqlTrackingMode].
 		tracking -> SmallInt,
+		/// Handling of packages removed upstream [crate::branch::SqlRemovalPolicy].
+		removal_policy -> SmallInt,
 		/// Count of tracked packages in this branch.
 		total_srcpkgs -> Int4,
+		/// The maximum count of running jobs of this branch.
+		max_running_jobs -> Nullable<Int4>,
+		/// The maximum count of pending jobs of this branch.
+		max_queued_jobs -> Nullable<Int4>,
+		/// Version of the branch configuration.
+		///
+		/// This is increased on each configuration update.
+		version -> BigInt,
+		/// Namespace [namespace] of this branch.
+		///
+		/// Names of branches are unique in each namespace.
+		namespace -> BigInt,
+		/// Repository [repository] of this branch.
+		///
+		/// Branches without repositories are in the default repository.
+		repository -> Nullable<BigInt>,
+		/// Name of the target group selected by this branch.
+		///
+		/// Branches without target groups are built for all targets.
+		target_group -> Nullable<Varchar>,
+		/// Time of creation in UTC.
+		created_at -> Timestamp,
+		/// Time of the last modification in UTC.
+		updated_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	/// Table for Git repositories, synchronized from configuration.
+	repository (id) {
+		id -> BigInt,
+		name -> Varchar,
+		/// URL of the remote to mirror.
+		url -> Varchar,
+	}
+}
+
+diesel::table! {
+	/// Table for namespaces, which isolate sets of branches.
+	namespace (id) {
+		id -> BigInt,
+		name -> Varchar,
+		/// Hex-encoded SHA-256 of the bearer token of this namespace.
+		///
+		/// Namespaces without tokens are accessible without authentication.
+		token_hash -> Nullable<Varchar>,
+		/// The maximum count of branches in this namespace.
+		max_branches -> Nullable<Int4>,
+		/// The maximum count of pending jobs of all branches in this namespace.
+		max_queued_jobs -> Nullable<Int4>,
 	}
 }
 
@@ -39,6 +96,105 @@ diesel::table! {
 		///
 		/// This column is null when and only when the job is not started.
 		started_at -> Nullable<Timestamp>,
+		/// Count of failed attempts which have been retried.
+		attempts -> Int2,
+		/// The branch which this job works on.
+		subject_branch -> Nullable<BigInt>,
+		/// Estimated duration of this job in milliseconds.
+		///
+		/// This is zero if there is no history of the same job.
+		estimated_ms -> BigInt,
+		/// W3C `traceparent` of the request which has enqueued this job.
+		traceparent -> Nullable<VarChar>,
+		/// Target architecture which this job builds for.
+		///
+		/// Only runners capable of the architecture may claim this job.
+		target_arch -> Nullable<VarChar>,
+		/// The package which this job works on.
+		///
+		/// Pending jobs are removed with their packages.
+		subject_pkg -> Nullable<XUuid>,
+		/// Time before which this job is not claimed, e.g. when it is retried
+		/// with backoff after a transient failure.
+		///
+		/// Pending jobs are claimable at any time if this column is null.
+		scheduled_at -> Nullable<Timestamp>,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for finished jobs.
+	job_history (id) {
+		/// ID of the job in [job_queue].
+		id -> XUuid,
+		kind -> VarChar,
+		data -> XJson,
+		subject_branch -> Nullable<BigInt>,
+		/// Key of what this job works on.
+		///
+		/// See [crate::job_queue::JobCommand::subject].
+		subject -> VarChar,
+		/// Outcome [crate::job_history::SqlJobOutcome].
+		outcome -> Int2,
+		started_at -> Timestamp,
+		finished_at -> Timestamp,
+		duration_ms -> BigInt,
+		/// Error message of the failed job.
+		error -> Nullable<VarChar>,
+		/// Whether the failure of the job was considered transient.
+		error_retryable -> Nullable<Bool>,
+		/// ID with which the backtrace of the error has been logged.
+		error_backtrace -> Nullable<XUuid>,
+		/// Time from enqueueing to the start of the last attempt in milliseconds.
+		///
+		/// This is null for jobs recorded before it was tracked.
+		wait_ms -> Nullable<BigInt>,
+		/// Name of the instance which has run the job.
+		///
+		/// See [crate::job_queue::JobQueueConfig::runner].
+		runner -> Nullable<VarChar>,
+	}
+}
+
+diesel::table! {
+	/// Table for moving averages of job durations.
+	job_duration (kind, subject) {
+		kind -> VarChar,
+		subject -> VarChar,
+		/// Moving average of durations in milliseconds.
+		avg_ms -> BigInt,
+		/// Count of samples of the moving average.
+		samples -> Int4,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for long-running operations requested by users.
+	operation (id) {
+		id -> XUuid,
+		kind -> VarChar,
+		/// Name of the branch affected by this operation.
+		///
+		/// This is kept as a name so that it survives untracking.
+		branch -> Nullable<VarChar>,
+		created_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for jobs spawned by operations.
+	operation_job (operation, job) {
+		operation -> XUuid,
+		job -> XUuid,
 	}
 }
 
@@ -53,8 +209,15 @@ diesel::table! {
 		name -> VarChar,
 		section -> VarChar,
 		status -> Int2,
-		status_msg -> VarChar,
+		status_msg -> Nullable<VarChar>,
 		data -> XJson,
+		/// Time of creation in UTC.
+		created_at -> Timestamp,
+		/// Time of the last modification in UTC.
+		updated_at -> Timestamp,
+		/// Reason of holding the package from automatic syncs and builds,
+		/// or null if not held.
+		hold_reason -> Nullable<VarChar>,
 	}
 }
 
@@ -73,5 +236,259 @@ diesel::table! {
 		target -> BigInt,
 		status -> Int2,
 		data -> XJson,
+		/// Time of creation in UTC.
+		created_at -> Timestamp,
+		/// Time of the last modification in UTC.
+		updated_at -> Timestamp,
+		/// Revision of the state, increased on each transition.
+		///
+		/// Transitions are conditional on the revision, see
+		/// [crate::package::PackageService::transition_target].
+		revision -> BigInt,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for findings of the last static checks of packages.
+	pkg_finding (id) {
+		id -> BigInt,
+		branch -> BigInt,
+		package -> XUuid,
+		/// Check [crate::lint::LintCheck] which has found this.
+		kind -> VarChar,
+		/// Severity [crate::lint::SqlFindingSeverity].
+		severity -> Int2,
+		message -> VarChar,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for security advisories, ingested from feeds.
+	advisory (id) {
+		id -> BigInt,
+		/// Name of the feed which this advisory is ingested from.
+		source -> VarChar,
+		/// ID of the advisory in its feed, e.g. `CVE-2024-3094`.
+		name -> VarChar,
+		/// Name of the affected package.
+		package -> VarChar,
+		summary -> VarChar,
+		/// Affected versions [crate::security::AffectedVersions].
+		affected -> XJson,
 	}
 }
+
+diesel::table! {
+	/// Table for latest upstream versions of packages, by package name.
+	upstream_version (name) {
+		name -> VarChar,
+		/// Latest stable upstream version, or null if the project is unknown upstream.
+		version -> Nullable<VarChar>,
+		checked_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for artifacts of successful builds, keyed by build cache keys.
+	///
+	/// See [crate::build_cache].
+	build_cache (key) {
+		key -> VarChar,
+		target -> BigInt,
+		/// ID of the build job.
+		build -> XUuid,
+		/// File names of artifacts.
+		artifacts -> XJson,
+		created_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for cached comparisons of artifacts of two builds.
+	///
+	/// See [crate::artifact_diff].
+	artifact_diff (build, against) {
+		/// ID of the compared build job.
+		build -> XUuid,
+		/// ID of the build job compared against.
+		against -> XUuid,
+		/// Result [crate::artifact_diff::ArtifactDiff], or null if not compared yet.
+		result -> Nullable<XJson>,
+		/// Error message if the comparison has failed.
+		error -> Nullable<VarChar>,
+		created_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for status transitions of packages.
+	status_event (id) {
+		id -> BigInt,
+		package -> XUuid,
+		/// Status [crate::package::SqlPackageStatus] entered.
+		status -> Int2,
+		message -> Nullable<VarChar>,
+		/// Actor [crate::package::StatusActor] of the transition.
+		actor -> VarChar,
+		created_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	/// Table for status transitions of branches.
+	branch_event (id) {
+		id -> BigInt,
+		branch -> BigInt,
+		/// Status [crate::branch::SqlBranchStatus] entered.
+		status -> Int2,
+		message -> Nullable<VarChar>,
+		/// Actor [crate::package::StatusActor] of the transition.
+		actor -> VarChar,
+		created_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	/// Table for bearer tokens of administrators.
+	admin_token (id) {
+		id -> BigInt,
+		name -> Varchar,
+		/// Hex-encoded SHA-256 of the bearer token.
+		token_hash -> Varchar,
+		created_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	/// Table for principals allowed to mutate branches.
+	///
+	/// Branches without entries can be mutated by any principal.
+	branch_acl (branch, principal) {
+		branch -> BigInt,
+		/// Identity of the principal, e.g. `token:{name}`, `user:{sub}` or `role:{name}`.
+		principal -> Varchar,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for named templates of branch configurations.
+	///
+	/// See [crate::branch_template].
+	branch_template (name) {
+		name -> VarChar,
+		/// Configuration [crate::branch_template::BranchTemplate].
+		config -> XJson,
+		updated_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	/// Table of the application schema version, with a single row.
+	///
+	/// See [crate::db::compat].
+	app_schema (id) {
+		id -> Int4,
+		/// Schema version of the newest instance which has used the database.
+		version -> Int4,
+		/// The minimum schema version of instances compatible with the database.
+		min_compatible -> Int4,
+	}
+}
+
+diesel::table! {
+	/// Table of phases of applied migrations.
+	///
+	/// See [crate::db::phase].
+	migration_phase (version) {
+		version -> VarChar,
+		/// Phase [crate::db::phase::SqlMigrationPhase].
+		phase -> Int2,
+		applied_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table for packages removed upstream, archived with their build states.
+	///
+	/// See [crate::branch::SqlRemovalPolicy::Archive].
+	pkg_archive (id) {
+		/// ID of the package in [pkg].
+		id -> XUuid,
+		branch -> BigInt,
+		name -> VarChar,
+		/// Data [crate::package::PkgData] of the package.
+		data -> XJson,
+		/// States [crate::package::SqlPackageTargetState] by target IDs.
+		targets -> XJson,
+		archived_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table of manual interventions, e.g. overrides of package states.
+	///
+	/// See [crate::audit].
+	audit_log (id) {
+		id -> BigInt,
+		/// Identity of the caller.
+		actor -> VarChar,
+		/// Kind of the intervention, e.g. `package.override`.
+		action -> VarChar,
+		/// The intervened object, e.g. `pkg:{id}`.
+		subject -> VarChar,
+		detail -> XJson,
+		created_at -> Timestamp,
+	}
+}
+
+diesel::table! {
+	/// Table of flags shared by instances without Redis, e.g. the maintenance mode.
+	///
+	/// See [crate::db::flag].
+	cluster_flag (name) {
+		name -> VarChar,
+		value -> VarChar,
+	}
+}
+
+diesel::table! {
+	use crate::db::utils::*;
+	use diesel::sql_types::*;
+
+	/// Table of running instances without Redis.
+	///
+	/// See [crate::instance::InstanceRegistry].
+	instance (id) {
+		id -> XUuid,
+		/// Serialized [crate::instance::InstanceInfo].
+		info -> VarChar,
+		/// Time of the last heartbeat in seconds since UNIX epoch.
+		heartbeat_at -> BigInt,
+	}
+}
+
+diesel::allow_tables_to_appear_in_same_query!(pkg, pkg_target);
@@ -12,7 +12,7 @@ use diesel::{
 	pg::{Pg, PgValue},
 	query_builder::{QueryFragment, QueryId},
 	serialize::{self, IsNull, Output, ToSql},
-	sql_types::{Binary, Bool, Jsonb, SqlType, VarChar},
+	sql_types::{Binary, Bool, Jsonb, SmallInt, SqlType, VarChar},
 	sqlite::{Sqlite, SqliteValue},
 };
 use uuid::Uuid;
@@ -22,7 +22,7 @@ use uuid::Uuid;
 #[diesel(sqlite_type(name = "Binary"))]
 pub struct XUuid;
 
-#[derive(Debug, AsExpression, FromSqlRow, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, AsExpression, FromSqlRow, Clone, Copy, PartialEq, Eq, Hash)]
 #[diesel(sql_type = XUuid)]
 pub struct XUuidVal(pub Uuid);
 
@@ -168,3 +168,69 @@ where
 	Self: Expression<SqlType = Bool> + NonAggregate,
 {
 }
+
+/// Implements [FromSql] and [ToSql] of [SmallInt] for `#[repr(u8)]` state enums.
+///
+/// The enum must implement `From<i16>`, and derive [AsExpression] and [FromSqlRow]
+/// with `#[diesel(sql_type = SmallInt)]`.
+///
+/// The discriminants are a storage detail, so such enums do not implement
+/// serde traits. Rows are converted to the enums of `fabricia_common_model`
+/// before being serialized, e.g. with [`SqlBranchStatus::into_common`].
+///
+/// [`SqlBranchStatus::into_common`]: crate::branch::SqlBranchStatus::into_common
+macro_rules! small_int_enum {
+	($ty:ty) => {
+		impl diesel::deserialize::FromSql<diesel::sql_types::SmallInt, diesel::pg::Pg> for $ty {
+			fn from_sql(value: diesel::pg::PgValue<'_>) -> diesel::deserialize::Result<Self> {
+				Ok(Self::from($crate::db::utils::small_int_from_pg(value)?))
+			}
+		}
+
+		impl diesel::serialize::ToSql<diesel::sql_types::SmallInt, diesel::pg::Pg> for $ty {
+			fn to_sql<'b>(
+				&'b self,
+				out: &mut diesel::serialize::Output<'b, '_, diesel::pg::Pg>,
+			) -> diesel::serialize::Result {
+				$crate::db::utils::small_int_to_pg(*self as i16, out)
+			}
+		}
+
+		impl diesel::deserialize::FromSql<diesel::sql_types::SmallInt, diesel::sqlite::Sqlite>
+			for $ty
+		{
+			fn from_sql(
+				value: diesel::sqlite::SqliteValue<'_, '_, '_>,
+			) -> diesel::deserialize::Result<Self> {
+				Ok(Self::from($crate::db::utils::small_int_from_sqlite(value)?))
+			}
+		}
+
+		impl diesel::serialize::ToSql<diesel::sql_types::SmallInt, diesel::sqlite::Sqlite> for $ty {
+			fn to_sql<'b>(
+				&'b self,
+				out: &mut diesel::serialize::Output<'b, '_, diesel::sqlite::Sqlite>,
+			) -> diesel::serialize::Result {
+				out.set_value(*self as i16 as i32);
+				Ok(diesel::serialize::IsNull::No)
+			}
+		}
+	};
+}
+
+pub(crate) use small_int_enum;
+
+#[doc(hidden)]
+pub fn small_int_from_pg(value: PgValue<'_>) -> deserialize::Result<i16> {
+	<i16 as FromSql<SmallInt, Pg>>::from_sql(value)
+}
+
+#[doc(hidden)]
+pub fn small_int_to_pg(value: i16, out: &mut Output<'_, '_, Pg>) -> serialize::Result {
+	<i16 as ToSql<SmallInt, Pg>>::to_sql(&value, &mut out.reborrow())
+}
+
+#[doc(hidden)]
+pub fn small_int_from_sqlite(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<i16> {
+	<i16 as FromSql<SmallInt, Sqlite>>::from_sql(value)
+}
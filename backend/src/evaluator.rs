@@ -0,0 +1,205 @@
+//! Evaluation of branches.
+//!
+//! Packages of a branch are evaluated by an external command configured on each
+//! instance, which prints them as a JSON array of [`EvaluatedPackage`]. This is
+//! the evaluation phase of [`JobCommand::SyncBranch`](crate::job_queue::JobCommand::SyncBranch),
+//! also run synchronously to preview synchronizations.
+
+use std::{process::Stdio, sync::Arc, time::Duration};
+
+use fabricia_common_model::git::{GitOid, GitOidError};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::process::Command;
+use tracing::info;
+
+use crate::{Result, model::BranchRow, package::EvaluatedPackage, repository::RepositoryService};
+
+/// Configuration of the evaluation command.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EvaluatorConfig {
+	/// Program and arguments evaluating a branch.
+	///
+	/// The command is run with these environment variables:
+	/// - `FABRICIA_BRANCH`: name of the branch
+	/// - `FABRICIA_REPOSITORY`: URL of the Git repository of the branch
+	/// - `FABRICIA_COMMIT`: evaluated commit
+	pub command: Vec<String>,
+	/// Timeout in seconds of evaluations.
+	#[serde(default = "default_timeout")]
+	pub timeout: u64,
+}
+
+fn default_timeout() -> u64 {
+	5 * 60
+}
+
+/// Packages evaluated from a commit of a branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+	pub commit: GitOid,
+	pub packages: Vec<EvaluatedPackage>,
+}
+
+/// Service evaluating branches on this instance.
+#[derive(Debug)]
+pub struct EvaluatorService {
+	repository: Arc<RepositoryService>,
+	/// Evaluations fail with [`EvaluatorError::NotConfigured`] if not set.
+	config: Option<EvaluatorConfig>,
+}
+
+impl EvaluatorService {
+	pub fn new(repository: Arc<RepositoryService>, config: Option<&EvaluatorConfig>) -> Self {
+		Self {
+			repository,
+			config: config.cloned(),
+		}
+	}
+
+	/// Evaluates packages of the commit which a branch would be synchronized to,
+	/// see [`BranchRow::sync_commit`].
+	///
+	/// Nothing is written, the result is applied by [`PackageService::apply`](crate::package::PackageService::apply).
+	pub async fn evaluate(&self, branch: &BranchRow) -> Result<Evaluation> {
+		let Some(config) = &self.config else {
+			return Err(EvaluatorError::NotConfigured.into());
+		};
+		let url = self
+			.repository
+			.resolve(branch.repository)
+			.map(|repository| repository.url.clone())
+			.ok_or(EvaluatorError::NoRepository)?;
+		let head = resolve_head(&url, &branch.name).await?;
+		let commit = branch
+			.sync_commit(head)
+			.map_err(EvaluatorError::InvalidCommit)?;
+		info!(branch = %branch.name, %commit, "evaluating branch");
+		let packages = run(config, &url, &branch.name, commit).await?;
+		Ok(Evaluation { commit, packages })
+	}
+}
+
+/// Resolves the head of a remote Git branch.
+async fn resolve_head(url: &str, name: &str) -> Result<GitOid, EvaluatorError> {
+	let output = Command::new("git")
+		.arg("ls-remote")
+		.arg(url)
+		.arg(format!("refs/heads/{name}"))
+		.stdin(Stdio::null())
+		.output()
+		.await?;
+	if !output.status.success() {
+		return Err(EvaluatorError::ResolveError(
+			url.to_string(),
+			String::from_utf8_lossy(&output.stderr).trim().to_string(),
+		));
+	}
+	parse_head(&String::from_utf8_lossy(&output.stdout))
+		.ok_or_else(|| EvaluatorError::BranchNotFound(name.to_string()))?
+		.map_err(EvaluatorError::InvalidCommit)
+}
+
+/// Parses the commit of the first ref from the output of `git ls-remote`.
+pub fn parse_head(output: &str) -> Option<Result<GitOid, GitOidError>> {
+	let (commit, _) = output.lines().next()?.split_once('\t')?;
+	Some(commit.parse())
+}
+
+/// Runs the evaluation command, bounded by the configured timeout.
+async fn run(
+	config: &EvaluatorConfig,
+	url: &str,
+	name: &str,
+	commit: GitOid,
+) -> Result<Vec<EvaluatedPackage>, EvaluatorError> {
+	let Some((program, args)) = config.command.split_first() else {
+		return Err(EvaluatorError::NotConfigured);
+	};
+	let output = Command::new(program)
+		.args(args)
+		.env("FABRICIA_BRANCH", name)
+		.env("FABRICIA_REPOSITORY", url)
+		.env("FABRICIA_COMMIT", commit.to_string())
+		.stdin(Stdio::null())
+		.kill_on_drop(true)
+		.output();
+	let output = tokio::time::timeout(Duration::from_secs(config.timeout), output)
+		.await
+		.map_err(|_| EvaluatorError::Timeout(config.timeout))??;
+	if !output.status.success() {
+		return Err(EvaluatorError::Failed(
+			String::from_utf8_lossy(&output.stderr).trim().to_string(),
+		));
+	}
+	Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+#[derive(Debug, Error)]
+pub enum EvaluatorError {
+	#[error("evaluation command is not configured on this instance")]
+	NotConfigured,
+	#[error("repository of the branch is not configured")]
+	NoRepository,
+	#[error("failed to resolve the branch in {0}: {1}")]
+	ResolveError(String, String),
+	#[error("branch {0} does not exist in the repository")]
+	BranchNotFound(String),
+	#[error("invalid commit: {0}")]
+	InvalidCommit(GitOidError),
+	#[error("evaluation has timed out after {0} seconds")]
+	Timeout(u64),
+	#[error("evaluation command has failed: {0}")]
+	Failed(String),
+	#[error("invalid output of the evaluation command: {0}")]
+	InvalidOutput(#[from] serde_json::Error),
+	#[error(transparent)]
+	IoError(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod test {
+	use fabricia_common_model::git::GitOid;
+
+	use crate::evaluator::{EvaluatorConfig, EvaluatorError, parse_head, run};
+
+	const COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+	fn config(script: &str) -> EvaluatorConfig {
+		EvaluatorConfig {
+			command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+			timeout: 1,
+		}
+	}
+
+	#[test]
+	fn test_parse_head() {
+		let output = format!("{COMMIT}\trefs/heads/stable\n");
+		let commit: GitOid = COMMIT.parse().unwrap();
+		assert_eq!(parse_head(&output).unwrap().unwrap(), commit);
+		assert!(parse_head("").is_none());
+	}
+
+	#[tokio::test]
+	async fn test_run() {
+		let commit: GitOid = COMMIT.parse().unwrap();
+		let script = r#"echo "[{\"name\":\"$FABRICIA_BRANCH\",\"section\":\"base\",\"data\":{\"version\":\"$FABRICIA_COMMIT\"}}]""#;
+		let packages = run(
+			&config(script),
+			"https://example.com/main.git",
+			"bash",
+			commit,
+		)
+		.await
+		.unwrap();
+		assert_eq!(packages.len(), 1);
+		assert_eq!(packages[0].name, "bash");
+		assert_eq!(packages[0].data.version, COMMIT);
+
+		let result = run(&config("exit 1"), "", "bash", commit).await;
+		assert!(matches!(result, Err(EvaluatorError::Failed(_))));
+		let result = run(&config("sleep 5"), "", "bash", commit).await;
+		assert!(matches!(result, Err(EvaluatorError::Timeout(1))));
+	}
+}
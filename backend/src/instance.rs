@@ -0,0 +1,319 @@
+//! Registry of running Axis and Crayon instances.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+};
+
+use diesel::{ExpressionMethods, QueryDsl, delete, insert_into};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	db::{schema::instance::dsl, service::DatabaseService, utils::XUuidVal},
+	redis::{RedisError, RedisService},
+};
+
+/// Redis hash key of registered instances.
+const INSTANCES_KEY: &str = "instances";
+
+/// Interval between heartbeats of an instance.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::seconds(10);
+
+/// Instances without heartbeats for this duration are considered as dead.
+pub const INSTANCE_TIMEOUT: Duration = Duration::seconds(30);
+
+/// Role of an instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstanceRole {
+	Axis,
+	Crayon,
+}
+
+/// Mode of an instance being stopped, selected by operators before shutting it down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopMode {
+	/// New jobs are refused, while running jobs are finished.
+	///
+	/// Jobs fetched but not started yet are released to other instances.
+	Drain,
+	/// Running jobs are aborted and released to other instances immediately.
+	HardStop,
+}
+
+/// Information of a running instance.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct InstanceInfo {
+	pub id: Uuid,
+	pub role: InstanceRole,
+	pub version: String,
+	/// Started time in seconds since UNIX epoch.
+	pub started_at: i64,
+	/// Time of the last heartbeat in seconds since UNIX epoch.
+	pub heartbeat_at: i64,
+	/// Mode of stopping, if the instance is being stopped.
+	#[serde(default)]
+	pub stop_mode: Option<StopMode>,
+}
+
+impl InstanceInfo {
+	/// Creates information for the current process.
+	pub fn new(role: InstanceRole, version: &str) -> Self {
+		let now = OffsetDateTime::now_utc().unix_timestamp();
+		Self {
+			id: Uuid::now_v7(),
+			role,
+			version: version.to_string(),
+			started_at: now,
+			heartbeat_at: now,
+			stop_mode: None,
+		}
+	}
+
+	/// Returns whether the instance has sent heartbeats recently.
+	pub fn is_alive(&self, now: OffsetDateTime) -> bool {
+		self.heartbeat_at + INSTANCE_TIMEOUT.whole_seconds() >= now.unix_timestamp()
+	}
+}
+
+/// Service for self-registration of instances sharing one backend.
+///
+/// Without Redis, instances are registered in the database.
+#[derive(Debug)]
+pub struct InstanceRegistry {
+	redis: Option<Arc<RedisService>>,
+	db: Arc<DatabaseService>,
+	/// Stop mode of the instance of this process.
+	stop_mode: Mutex<Option<StopMode>>,
+	/// Notifier to send a heartbeat immediately.
+	changed: Notify,
+}
+
+impl InstanceRegistry {
+	pub fn new(redis: Option<Arc<RedisService>>, db: Arc<DatabaseService>) -> Self {
+		Self {
+			redis,
+			db,
+			stop_mode: Mutex::new(None),
+			changed: Notify::const_new(),
+		}
+	}
+
+	/// Sets the stop mode of the instance of this process,
+	/// which is published with the next heartbeat.
+	pub fn set_stop_mode(&self, mode: Option<StopMode>) {
+		*self.stop_mode.lock().unwrap() = mode;
+		self.changed.notify_waiters();
+	}
+
+	/// Returns the stop mode of the instance of this process.
+	pub fn stop_mode(&self) -> Option<StopMode> {
+		*self.stop_mode.lock().unwrap()
+	}
+
+	/// Registers an instance or refreshes its heartbeat.
+	pub async fn heartbeat(&self, info: &mut InstanceInfo) -> Result<()> {
+		info.heartbeat_at = OffsetDateTime::now_utc().unix_timestamp();
+		info.stop_mode = self.stop_mode();
+		let Some(redis) = &self.redis else {
+			let id = XUuidVal(info.id);
+			let value = serde_json::to_string(info)?;
+			let heartbeat_at = info.heartbeat_at;
+			let mut conn = self.db.get().await?;
+			conn.transaction::<_, crate::BackendError, _>(async |conn| {
+				conn.execute(delete(dsl::instance.filter(dsl::id.eq(id))))
+					.await?;
+				conn.execute(insert_into(dsl::instance).values((
+					dsl::id.eq(id),
+					dsl::info.eq(&value),
+					dsl::heartbeat_at.eq(heartbeat_at),
+				)))
+				.await?;
+				Ok(())
+			})
+			.await?;
+			debug!(id = %info.id, "sent instance heartbeat");
+			return Ok(());
+		};
+		let _: () = redis
+			.get()
+			.await?
+			.hset(
+				redis.key(INSTANCES_KEY),
+				info.id.to_string(),
+				serde_json::to_string(info)?,
+			)
+			.await
+			.map_err(RedisError::RedisError)?;
+		debug!(id = %info.id, "sent instance heartbeat");
+		Ok(())
+	}
+
+	/// Removes an instance from the registry.
+	pub async fn deregister(&self, id: Uuid) -> Result<()> {
+		let Some(redis) = &self.redis else {
+			let mut conn = self.db.get().await?;
+			conn.execute(delete(dsl::instance.filter(dsl::id.eq(XUuidVal(id)))))
+				.await?;
+			info!(%id, "deregistered instance");
+			return Ok(());
+		};
+		let _: () = redis
+			.get()
+			.await?
+			.hdel(redis.key(INSTANCES_KEY), id.to_string())
+			.await
+			.map_err(RedisError::RedisError)?;
+		info!(%id, "deregistered instance");
+		Ok(())
+	}
+
+	/// Sends heartbeats of an instance until the process exits.
+	///
+	/// Changes of the stop mode are sent immediately.
+	pub async fn run_heartbeat(self: Arc<Self>, mut info: InstanceInfo) {
+		info!(id = %info.id, role = ?info.role, "registering instance");
+		let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL.unsigned_abs());
+		loop {
+			tokio::select! {
+				_ = interval.tick() => {}
+				_ = self.changed.notified() => {}
+			}
+			if let Err(error) = self.heartbeat(&mut info).await {
+				warn!(?error, "failed to send instance heartbeat");
+			}
+		}
+	}
+
+	/// Lists all live instances.
+	///
+	/// Dead instances are removed from the registry.
+	pub async fn list(&self) -> Result<Vec<InstanceInfo>> {
+		let now = OffsetDateTime::now_utc();
+		let Some(redis) = &self.redis else {
+			let mut conn = self.db.get().await?;
+			let dead_before = (now - INSTANCE_TIMEOUT).unix_timestamp();
+			let removed = conn
+				.execute(delete(
+					dsl::instance.filter(dsl::heartbeat_at.lt(dead_before)),
+				))
+				.await?;
+			if removed > 0 {
+				debug!(removed, "removed dead instances");
+			}
+			let entries = conn
+				.load::<_, String>(dsl::instance.select(dsl::info))
+				.await?;
+			let mut instances = entries
+				.iter()
+				.filter_map(|info| serde_json::from_str::<InstanceInfo>(info).ok())
+				.filter(|info| info.is_alive(now))
+				.collect::<Vec<_>>();
+			instances.sort_by_key(|info| (info.role as u8, info.started_at));
+			return Ok(instances);
+		};
+
+		let mut conn = redis.get().await?;
+		let entries: HashMap<String, String> = conn
+			.hgetall(redis.key(INSTANCES_KEY))
+			.await
+			.map_err(RedisError::RedisError)?;
+
+		let mut instances = Vec::with_capacity(entries.len());
+		let mut dead = vec![];
+		for (id, info) in entries {
+			match serde_json::from_str::<InstanceInfo>(&info) {
+				Ok(info) if info.is_alive(now) => instances.push(info),
+				_ => dead.push(id),
+			}
+		}
+		if !dead.is_empty() {
+			let _: () = conn
+				.hdel(redis.key(INSTANCES_KEY), &dead)
+				.await
+				.map_err(RedisError::RedisError)?;
+			debug!(?dead, "removed dead instances");
+		}
+
+		instances.sort_by_key(|info| (info.role as u8, info.started_at));
+		Ok(instances)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::test::test_env;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_register() {
+		let env = test_env().await;
+		let registry = &env.instance;
+
+		let mut info = InstanceInfo::new(InstanceRole::Axis, "0.1.0");
+		registry.heartbeat(&mut info).await.unwrap();
+		assert!(registry.list().await.unwrap().contains(&info));
+
+		registry.deregister(info.id).await.unwrap();
+		assert!(!registry.list().await.unwrap().contains(&info));
+	}
+
+	#[tokio::test]
+	async fn test_register_database() {
+		let env = test_env().await;
+		let registry = InstanceRegistry::new(None, env.database.clone());
+
+		let mut info = InstanceInfo::new(InstanceRole::Crayon, "0.1.0");
+		registry.heartbeat(&mut info).await.unwrap();
+		registry.heartbeat(&mut info).await.unwrap();
+		assert_eq!(registry.list().await.unwrap(), [info.clone()]);
+
+		// dead instances are removed
+		let mut dead = InstanceInfo::new(InstanceRole::Axis, "0.1.0");
+		registry.heartbeat(&mut dead).await.unwrap();
+		let mut conn = env.database.get().await.unwrap();
+		conn.execute(
+			diesel::update(dsl::instance)
+				.filter(dsl::id.eq(XUuidVal(dead.id)))
+				.set(dsl::heartbeat_at.eq(0)),
+		)
+		.await
+		.unwrap();
+		drop(conn);
+		assert_eq!(registry.list().await.unwrap(), [info.clone()]);
+
+		registry.deregister(info.id).await.unwrap();
+		assert!(registry.list().await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_stop_mode() {
+		let env = test_env().await;
+		let registry = &env.instance;
+
+		let mut info = InstanceInfo::new(InstanceRole::Axis, "0.1.0");
+		registry.set_stop_mode(Some(StopMode::HardStop));
+		registry.heartbeat(&mut info).await.unwrap();
+		assert_eq!(info.stop_mode, Some(StopMode::HardStop));
+		assert!(registry.list().await.unwrap().contains(&info));
+
+		registry.set_stop_mode(None);
+		registry.heartbeat(&mut info).await.unwrap();
+		assert_eq!(registry.list().await.unwrap()[0].stop_mode, None);
+
+		// registered by instances of previous versions
+		let info = serde_json::from_str::<InstanceInfo>(
+			r#"{"id":"0192a2d4-3c5f-7000-8000-000000000000","role":"crayon","version":"0.1.0","started_at":0,"heartbeat_at":0}"#,
+		)
+		.unwrap();
+		assert_eq!(info.stop_mode, None);
+	}
+}
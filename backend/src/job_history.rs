@@ -0,0 +1,757 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use diesel::{
+	BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, deserialize::FromSqlRow,
+	expression::AsExpression, insert_into, pg::Pg, sql_types::SmallInt, sqlite::Sqlite, update,
+};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+use tracing::debug;
+
+use crate::{
+	Result,
+	branch::BranchRef,
+	db::{
+		BoxedSqlConn,
+		schema::{branch::dsl as branch_dsl, job_duration::dsl as duration_dsl, job_history::dsl},
+		service::DatabaseService,
+		utils::{XJsonVal, XUuidVal, small_int_enum},
+	},
+	job_queue::{JobError, JobRef},
+	namespace::{DEFAULT_NAMESPACE_ID, NamespaceRef},
+};
+
+/// Outcome of a finished job.
+///
+/// Stored as a tiny unsigned column. Unknown values are decoded as failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = SmallInt)]
+#[repr(u8)]
+pub enum SqlJobOutcome {
+	/// The job has finished successfully.
+	Succeeded = 0,
+	/// The job has failed and will not be retried.
+	Failed = 1,
+}
+
+impl From<u8> for SqlJobOutcome {
+	fn from(value: u8) -> Self {
+		Self::from(value as i16)
+	}
+}
+
+impl From<i16> for SqlJobOutcome {
+	fn from(value: i16) -> Self {
+		match value {
+			0 => Self::Succeeded,
+			1 => Self::Failed,
+			_ => Self::Failed,
+		}
+	}
+}
+
+small_int_enum!(SqlJobOutcome);
+
+/// Count of samples after which the moving average of durations
+/// stops giving more weight to older samples.
+const DURATION_WINDOW: i32 = 10;
+
+/// A finished job to be recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinishedJob {
+	pub id: JobRef,
+	pub kind: String,
+	pub data: serde_json::Value,
+	pub subject_branch: Option<BranchRef>,
+	pub subject: String,
+	pub outcome: SqlJobOutcome,
+	pub started_at: PrimitiveDateTime,
+	/// Error if the job has failed.
+	pub error: Option<JobError>,
+	/// Name of the instance which has run the job.
+	pub runner: Option<String>,
+}
+
+/// A job recorded in history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryJob {
+	pub id: JobRef,
+	pub kind: String,
+	pub data: serde_json::Value,
+	pub subject_branch: Option<BranchRef>,
+	pub outcome: SqlJobOutcome,
+	/// Started time of this job in UTC.
+	pub started_at: PrimitiveDateTime,
+	/// Finished time of this job in UTC.
+	pub finished_at: PrimitiveDateTime,
+	/// Error if the job has failed.
+	pub error: Option<JobError>,
+	/// Name of the instance which has run the job.
+	pub runner: Option<String>,
+}
+
+/// Filter of [`JobHistoryService::list`].
+///
+/// Jobs match all set fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryFilter {
+	pub kind: Option<String>,
+	/// Jobs of branches in the namespace.
+	///
+	/// Jobs without subject branches are in [`DEFAULT_NAMESPACE_ID`].
+	pub namespace: Option<NamespaceRef>,
+	pub branch: Option<BranchRef>,
+	pub outcome: Option<SqlJobOutcome>,
+	/// Jobs finished at or after the time in UTC.
+	pub finished_after: Option<PrimitiveDateTime>,
+	/// Jobs finished before the time in UTC.
+	pub finished_before: Option<PrimitiveDateTime>,
+	pub runner: Option<String>,
+}
+
+/// Percentiles of durations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentiles {
+	pub p50: Duration,
+	pub p95: Duration,
+	pub p99: Duration,
+}
+
+impl Percentiles {
+	/// Computes nearest-rank percentiles of durations in milliseconds,
+	/// or returns [`None`] if there are none.
+	fn new(mut samples: Vec<i64>) -> Option<Self> {
+		if samples.is_empty() {
+			return None;
+		}
+		samples.sort_unstable();
+		let percentile = |p: usize| {
+			let rank = (samples.len() * p).div_ceil(100).max(1);
+			Duration::milliseconds(samples[rank - 1])
+		};
+		Some(Self {
+			p50: percentile(50),
+			p95: percentile(95),
+			p99: percentile(99),
+		})
+	}
+}
+
+/// Latencies of jobs of a kind finished within a window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyStats {
+	pub kind: String,
+	/// Length of the window until now.
+	pub window: Duration,
+	/// Count of finished jobs.
+	pub jobs: usize,
+	/// Time from enqueueing to start, [`None`] if not recorded for any job.
+	pub wait: Option<Percentiles>,
+	/// Time from start to finish.
+	pub run: Percentiles,
+}
+
+/// Windows of [`JobHistoryService::latency_stats`].
+pub const LATENCY_WINDOWS: [Duration; 3] = [
+	Duration::minutes(5),
+	Duration::hours(1),
+	Duration::hours(24),
+];
+
+/// Returns the enqueueing time of a job from its UUIDv7.
+fn enqueued_at(id: JobRef) -> Option<OffsetDateTime> {
+	let (secs, nanos) = id.get_timestamp()?.to_unix();
+	let time = OffsetDateTime::from_unix_timestamp(secs.try_into().ok()?).ok()?;
+	Some(time + Duration::nanoseconds(nanos.into()))
+}
+
+type HistoryRow = (
+	XUuidVal,
+	String,
+	XJsonVal,
+	Option<BranchRef>,
+	SqlJobOutcome,
+	PrimitiveDateTime,
+	PrimitiveDateTime,
+	Option<String>,
+	Option<bool>,
+	Option<XUuidVal>,
+	Option<String>,
+);
+
+type HistoryColumns = (
+	dsl::id,
+	dsl::kind,
+	dsl::data,
+	dsl::subject_branch,
+	dsl::outcome,
+	dsl::started_at,
+	dsl::finished_at,
+	dsl::error,
+	dsl::error_retryable,
+	dsl::error_backtrace,
+	dsl::runner,
+);
+
+/// Columns of [`HistoryRow`].
+const HISTORY_COLUMNS: HistoryColumns = (
+	dsl::id,
+	dsl::kind,
+	dsl::data,
+	dsl::subject_branch,
+	dsl::outcome,
+	dsl::started_at,
+	dsl::finished_at,
+	dsl::error,
+	dsl::error_retryable,
+	dsl::error_backtrace,
+	dsl::runner,
+);
+
+fn history_job(row: HistoryRow) -> HistoryJob {
+	let (
+		id,
+		kind,
+		data,
+		subject_branch,
+		outcome,
+		started_at,
+		finished_at,
+		error,
+		retryable,
+		backtrace,
+		runner,
+	) = row;
+	HistoryJob {
+		id: id.0,
+		kind,
+		data: data.0,
+		subject_branch,
+		outcome,
+		started_at,
+		finished_at,
+		error: error.map(|message| JobError {
+			retryable: retryable.unwrap_or(false),
+			message,
+			backtrace: backtrace.map(|id| id.0),
+		}),
+		runner,
+	}
+}
+
+/// Service for histories of finished jobs.
+#[derive(Debug)]
+pub struct JobHistoryService {
+	db: Arc<DatabaseService>,
+}
+
+impl JobHistoryService {
+	pub fn new(db: Arc<DatabaseService>) -> Self {
+		Self { db }
+	}
+
+	/// Records a finished job.
+	///
+	/// Durations of succeeded jobs are taken into the moving average
+	/// of the same kind and subject.
+	pub async fn record(&self, conn: &mut BoxedSqlConn, job: FinishedJob) -> Result<()> {
+		let now = OffsetDateTime::now_utc();
+		let finished_at = PrimitiveDateTime::new(now.date(), now.time());
+		let duration = (finished_at - job.started_at).max(Duration::ZERO);
+		let duration_ms = duration.whole_milliseconds() as i64;
+		let wait_ms = enqueued_at(job.id).map(|enqueued_at| {
+			(job.started_at.assume_utc() - enqueued_at)
+				.max(Duration::ZERO)
+				.whole_milliseconds() as i64
+		});
+
+		conn.execute(
+			insert_into(dsl::job_history).values((
+				dsl::id.eq(XUuidVal(job.id)),
+				dsl::kind.eq(job.kind.as_str()),
+				dsl::data.eq(XJsonVal(job.data)),
+				dsl::subject_branch.eq(job.subject_branch),
+				dsl::subject.eq(job.subject.as_str()),
+				dsl::outcome.eq(job.outcome),
+				dsl::started_at.eq(job.started_at),
+				dsl::finished_at.eq(finished_at),
+				dsl::duration_ms.eq(duration_ms),
+				dsl::error.eq(job.error.as_ref().map(|error| error.message.as_str())),
+				dsl::error_retryable.eq(job.error.as_ref().map(|error| error.retryable)),
+				dsl::error_backtrace.eq(job
+					.error
+					.as_ref()
+					.and_then(|error| error.backtrace)
+					.map(XUuidVal)),
+				dsl::wait_ms.eq(wait_ms),
+				dsl::runner.eq(job.runner.as_deref()),
+			)),
+		)
+		.await?;
+
+		if job.outcome == SqlJobOutcome::Succeeded {
+			self.update_duration(conn, &job.kind, &job.subject, duration_ms)
+				.await?;
+		}
+		Ok(())
+	}
+
+	async fn update_duration(
+		&self,
+		conn: &mut BoxedSqlConn,
+		kind: &str,
+		subject: &str,
+		duration_ms: i64,
+	) -> Result<()> {
+		let current = conn
+			.get_result::<_, (i64, i32)>(
+				duration_dsl::job_duration
+					.filter(duration_dsl::kind.eq(kind))
+					.filter(duration_dsl::subject.eq(subject))
+					.select((duration_dsl::avg_ms, duration_dsl::samples)),
+			)
+			.await
+			.optional()?;
+		match current {
+			Some((avg_ms, samples)) => {
+				let samples = samples.saturating_add(1);
+				let avg_ms = avg_ms + (duration_ms - avg_ms) / samples.min(DURATION_WINDOW) as i64;
+				conn.execute(
+					update(duration_dsl::job_duration)
+						.filter(duration_dsl::kind.eq(kind))
+						.filter(duration_dsl::subject.eq(subject))
+						.set((
+							duration_dsl::avg_ms.eq(avg_ms),
+							duration_dsl::samples.eq(samples),
+						)),
+				)
+				.await?;
+				debug!(kind, subject, avg_ms, samples, "updated job duration");
+			}
+			None => {
+				conn.execute(insert_into(duration_dsl::job_duration).values((
+					duration_dsl::kind.eq(kind),
+					duration_dsl::subject.eq(subject),
+					duration_dsl::avg_ms.eq(duration_ms),
+					duration_dsl::samples.eq(1),
+				)))
+				.await?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Returns a finished job.
+	pub async fn get(&self, id: JobRef) -> Result<Option<HistoryJob>> {
+		let mut conn = self.db.get().await?;
+		let row = conn
+			.get_result::<_, HistoryRow>(
+				dsl::job_history
+					.filter(dsl::id.eq(XUuidVal(id)))
+					.select(HISTORY_COLUMNS),
+			)
+			.await
+			.optional()?;
+		Ok(row.map(history_job))
+	}
+
+	/// Lists up to `limit` finished jobs matching a filter, latest first.
+	pub async fn list(&self, filter: &HistoryFilter, limit: usize) -> Result<Vec<HistoryJob>> {
+		let limit = limit.try_into().unwrap_or(i64::MAX);
+		let mut conn = self.db.get().await?;
+		let namespace = match filter.namespace {
+			Some(namespace) => Some((
+				conn.load::<_, BranchRef>(
+					branch_dsl::branch
+						.filter(branch_dsl::namespace.eq(namespace))
+						.select(branch_dsl::id),
+				)
+				.await?,
+				namespace == DEFAULT_NAMESPACE_ID,
+			)),
+			None => None,
+		};
+		// queries with optional filters are boxed, which is specific to backends
+		macro_rules! query {
+			($backend:ty) => {{
+				let mut query = dsl::job_history
+					.select(HISTORY_COLUMNS)
+					.into_boxed::<$backend>();
+				if let Some(kind) = &filter.kind {
+					query = query.filter(dsl::kind.eq(kind));
+				}
+				if let Some((branches, default)) = namespace.clone() {
+					query = match default {
+						true => query.filter(
+							dsl::subject_branch
+								.eq_any(branches)
+								.or(dsl::subject_branch.is_null()),
+						),
+						false => query.filter(dsl::subject_branch.eq_any(branches)),
+					};
+				}
+				if let Some(branch) = filter.branch {
+					query = query.filter(dsl::subject_branch.eq(branch));
+				}
+				if let Some(outcome) = filter.outcome {
+					query = query.filter(dsl::outcome.eq(outcome));
+				}
+				if let Some(time) = filter.finished_after {
+					query = query.filter(dsl::finished_at.ge(time));
+				}
+				if let Some(time) = filter.finished_before {
+					query = query.filter(dsl::finished_at.lt(time));
+				}
+				if let Some(runner) = &filter.runner {
+					query = query.filter(dsl::runner.eq(runner));
+				}
+				query.order(dsl::finished_at.desc()).limit(limit)
+			}};
+		}
+
+		let rows: Vec<HistoryRow> = match &mut *conn {
+			BoxedSqlConn::Pg(conn) => diesel_async::RunQueryDsl::load(query!(Pg), conn).await?,
+			BoxedSqlConn::Sqlite(conn) => diesel::RunQueryDsl::load(query!(Sqlite), conn)?,
+		};
+		Ok(rows.into_iter().map(history_job).collect())
+	}
+
+	/// Returns the moving average of durations of jobs of the same kind and subject.
+	pub async fn estimate(
+		&self,
+		conn: &mut BoxedSqlConn,
+		kind: &str,
+		subject: &str,
+	) -> Result<Option<Duration>> {
+		let avg_ms = conn
+			.get_result::<_, i64>(
+				duration_dsl::job_duration
+					.filter(duration_dsl::kind.eq(kind))
+					.filter(duration_dsl::subject.eq(subject))
+					.select(duration_dsl::avg_ms),
+			)
+			.await
+			.optional()?;
+		Ok(avg_ms.map(Duration::milliseconds))
+	}
+
+	/// Returns the average duration of jobs of a kind.
+	pub async fn estimate_kind(&self, kind: &str) -> Result<Option<Duration>> {
+		let mut conn = self.db.get().await?;
+		let avg_ms = conn
+			.load::<_, i64>(
+				duration_dsl::job_duration
+					.filter(duration_dsl::kind.eq(kind))
+					.select(duration_dsl::avg_ms),
+			)
+			.await?;
+		if avg_ms.is_empty() {
+			return Ok(None);
+		}
+		let total = avg_ms.iter().sum::<i64>();
+		Ok(Some(Duration::milliseconds(total / avg_ms.len() as i64)))
+	}
+
+	/// Computes percentiles of latencies of jobs by kind, finished within each
+	/// of [`LATENCY_WINDOWS`].
+	///
+	/// Windows without finished jobs of a kind are omitted.
+	pub async fn latency_stats(&self) -> Result<Vec<LatencyStats>> {
+		let now = OffsetDateTime::now_utc();
+		let now = PrimitiveDateTime::new(now.date(), now.time());
+		let longest = LATENCY_WINDOWS.iter().max().copied().unwrap_or_default();
+		let mut conn = self.db.get().await?;
+		let rows = conn
+			.load::<_, (String, PrimitiveDateTime, Option<i64>, i64)>(
+				dsl::job_history
+					.filter(dsl::finished_at.ge(now - longest))
+					.select((dsl::kind, dsl::finished_at, dsl::wait_ms, dsl::duration_ms)),
+			)
+			.await?;
+		Ok(latency_stats(rows, now))
+	}
+}
+
+/// Groups rows of finished jobs by kinds and windows ending at `now`.
+fn latency_stats(
+	rows: Vec<(String, PrimitiveDateTime, Option<i64>, i64)>,
+	now: PrimitiveDateTime,
+) -> Vec<LatencyStats> {
+	let mut kinds = BTreeMap::<String, Vec<_>>::new();
+	for (kind, finished_at, wait_ms, duration_ms) in rows {
+		kinds
+			.entry(kind)
+			.or_default()
+			.push((finished_at, wait_ms, duration_ms));
+	}
+	let mut stats = Vec::new();
+	for (kind, jobs) in kinds {
+		for window in LATENCY_WINDOWS {
+			let jobs = jobs
+				.iter()
+				.filter(|(finished_at, ..)| *finished_at >= now - window)
+				.collect::<Vec<_>>();
+			let run = jobs
+				.iter()
+				.map(|(_, _, duration_ms)| *duration_ms)
+				.collect();
+			let Some(run) = Percentiles::new(run) else {
+				continue;
+			};
+			stats.push(LatencyStats {
+				kind: kind.clone(),
+				window,
+				jobs: jobs.len(),
+				wait: Percentiles::new(
+					jobs.iter().filter_map(|(_, wait_ms, _)| *wait_ms).collect(),
+				),
+				run,
+			});
+		}
+	}
+	stats
+}
+
+#[cfg(test)]
+mod test {
+	use diesel::QueryDsl;
+	use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+	use uuid::Uuid;
+
+	use crate::{
+		db::schema::job_history::dsl,
+		job_history::{HistoryFilter, LATENCY_WINDOWS, SqlJobOutcome, latency_stats},
+		job_queue::{FailureClass, JobCommand, JobError},
+		namespace::DEFAULT_NAMESPACE_ID,
+		test::{test_env, test_time},
+	};
+
+	#[tokio::test]
+	async fn test_record() {
+		let env = test_env().await;
+		let jq = &env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+		let id = jq.fetch_and_start().await.unwrap().unwrap().id;
+		let mut db = env.database.get().await.unwrap();
+		jq.finish_job(&mut db, id).await.unwrap();
+
+		assert_eq!(
+			db.get_result::<_, i64>(dsl::job_history.count())
+				.await
+				.unwrap(),
+			1
+		);
+		assert!(
+			env.job_history
+				.estimate(&mut db, "SyncBranch", "branch:1")
+				.await
+				.unwrap()
+				.is_some()
+		);
+		assert!(
+			env.job_history
+				.estimate(&mut db, "SyncBranch", "branch:2")
+				.await
+				.unwrap()
+				.is_none()
+		);
+		let wait_ms = db
+			.get_result::<_, Option<i64>>(dsl::job_history.select(dsl::wait_ms))
+			.await
+			.unwrap();
+		assert!(wait_ms.is_some_and(|wait_ms| wait_ms >= 0));
+		let stats = env.job_history.latency_stats().await.unwrap();
+		assert_eq!(stats.len(), LATENCY_WINDOWS.len());
+		assert!(stats.iter().all(|stats| stats.jobs == 1));
+	}
+
+	#[test]
+	fn test_latency_stats() {
+		let now = test_time();
+		let mut rows = (1..=100)
+			.map(|i| {
+				(
+					"SyncBranch".to_string(),
+					now - Duration::minutes(i),
+					Some(i * 10),
+					i * 1000,
+				)
+			})
+			.collect::<Vec<_>>();
+		rows.push(("IngestAdvisories".to_string(), now, None, 5));
+
+		let stats = latency_stats(rows, now);
+		let windows = stats
+			.iter()
+			.map(|stats| (stats.kind.as_str(), stats.window, stats.jobs))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			windows,
+			[
+				("IngestAdvisories", LATENCY_WINDOWS[0], 1),
+				("IngestAdvisories", LATENCY_WINDOWS[1], 1),
+				("IngestAdvisories", LATENCY_WINDOWS[2], 1),
+				("SyncBranch", LATENCY_WINDOWS[0], 5),
+				("SyncBranch", LATENCY_WINDOWS[1], 60),
+				("SyncBranch", LATENCY_WINDOWS[2], 100),
+			]
+		);
+		assert_eq!(stats[0].wait, None);
+		let day = &stats[5];
+		let wait = day.wait.unwrap();
+		assert_eq!(
+			[wait.p50, wait.p95, wait.p99],
+			[500, 950, 990].map(Duration::milliseconds)
+		);
+		assert_eq!(day.run.p99, Duration::seconds(99));
+	}
+
+	#[tokio::test]
+	async fn test_record_error() {
+		let env = test_env().await;
+		let jq = &env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+		let id = jq.fetch_and_start().await.unwrap().unwrap().id;
+		let error = JobError {
+			backtrace: Some(Uuid::now_v7()),
+			..JobError::new(FailureClass::Permanent, "bad metadata")
+		};
+		let mut db = env.database.get().await.unwrap();
+		jq.fail_job(&mut db, id, &error).await.unwrap();
+		drop(db);
+
+		let job = env.job_history.get(id).await.unwrap().unwrap();
+		assert_eq!(job.outcome, SqlJobOutcome::Failed);
+		assert_eq!(job.error, Some(error));
+		assert!(env.job_history.get(Uuid::now_v7()).await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_list() {
+		let env = test_env().await;
+		let jq = &env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+		let succeeded = jq.fetch_and_start().await.unwrap().unwrap().id;
+		let mut db = env.database.get().await.unwrap();
+		jq.finish_job(&mut db, succeeded).await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(2))
+			.await
+			.unwrap();
+		drop(db);
+		let failed = jq.fetch_and_start().await.unwrap().unwrap().id;
+		let mut db = env.database.get().await.unwrap();
+		let error = JobError::new(FailureClass::Permanent, "bad metadata");
+		jq.fail_job(&mut db, failed, &error).await.unwrap();
+		drop(db);
+
+		let history = &env.job_history;
+		let list = |filter: HistoryFilter| async move {
+			history
+				.list(&filter, 10)
+				.await
+				.unwrap()
+				.into_iter()
+				.map(|job| job.id)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(list(HistoryFilter::default()).await, [failed, succeeded]);
+		assert_eq!(
+			list(HistoryFilter {
+				outcome: Some(SqlJobOutcome::Failed),
+				..Default::default()
+			})
+			.await,
+			[failed]
+		);
+		assert_eq!(
+			list(HistoryFilter {
+				kind: Some("SyncBranch".to_string()),
+				branch: Some(1),
+				..Default::default()
+			})
+			.await,
+			[succeeded]
+		);
+		let now = OffsetDateTime::now_utc();
+		let tomorrow = PrimitiveDateTime::new(now.date(), now.time()) + Duration::days(1);
+		assert!(
+			list(HistoryFilter {
+				finished_after: Some(tomorrow),
+				..Default::default()
+			})
+			.await
+			.is_empty()
+		);
+		assert!(
+			list(HistoryFilter {
+				runner: Some("builder-1".to_string()),
+				..Default::default()
+			})
+			.await
+			.is_empty()
+		);
+	}
+
+	#[tokio::test]
+	async fn test_list_namespace() {
+		let env = test_env().await;
+		let jq = &env.job_queue;
+
+		let namespace = env
+			.namespace
+			.create("team", Default::default())
+			.await
+			.unwrap()
+			.id;
+		// enqueues the first sync job
+		env.branch
+			.track(namespace, "test", Default::default())
+			.await
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::IngestAdvisories)
+			.await
+			.unwrap();
+		drop(db);
+		let (mut synced, mut ingested) = (None, None);
+		while let Some(job) = jq.fetch_and_start().await.unwrap() {
+			let mut db = env.database.get().await.unwrap();
+			jq.finish_job(&mut db, job.id).await.unwrap();
+			match job.command {
+				JobCommand::SyncBranch(_) => synced = Some(job.id),
+				_ => ingested = Some(job.id),
+			}
+		}
+
+		let history = &env.job_history;
+		let list = |namespace| async move {
+			let filter = HistoryFilter {
+				namespace: Some(namespace),
+				..Default::default()
+			};
+			history
+				.list(&filter, 10)
+				.await
+				.unwrap()
+				.into_iter()
+				.map(|job| job.id)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(list(namespace).await, [synced.unwrap()]);
+		assert_eq!(list(DEFAULT_NAMESPACE_ID).await, [ingested.unwrap()]);
+	}
+}
@@ -0,0 +1,2670 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	fmt::Debug,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use diesel::{
+	BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, OptionalExtension,
+	QueryDsl, define_sql_function, delete,
+	dsl::not,
+	insert_into,
+	sql_types::{BigInt, Nullable},
+	update,
+};
+use diesel_async::AsyncPgConnection;
+use futures::{
+	FutureExt,
+	future::{BoxFuture, ready},
+};
+use kstring::KString;
+use rand::Rng;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	branch::BranchRef,
+	build_cache::PlannedBuild,
+	db::{
+		BoxedSqlConn, flag,
+		schema::{branch::dsl as branch_dsl, job_queue::dsl, namespace::dsl as ns_dsl},
+		service::DatabaseService,
+		utils::{XJsonVal, XUuidVal},
+	},
+	job_history::{FinishedJob, HistoryFilter, HistoryJob, JobHistoryService, SqlJobOutcome},
+	model::JobRow,
+	namespace::{DEFAULT_NAMESPACE_ID, NamespaceRef},
+	redis::{RedisError, RedisService},
+	trace::TraceContext,
+};
+
+pub mod stream;
+
+use stream::StreamDispatcher;
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "t", content = "c", rename = "kebab-case")]
+pub enum JobCommand {
+	/// Synchronize metadata of a branch.
+	SyncBranch(BranchRef),
+	/// Run static checks on a package, see [`crate::lint`].
+	LintPackage { branch: BranchRef, package: Uuid },
+	/// Ingest security advisory feeds, and flag affected packages of all branches.
+	IngestAdvisories,
+	/// Refresh upstream versions of packages, see [`crate::upstream`].
+	RefreshUpstream,
+	/// Download sources of a package to the mirror, see [`crate::mirror`].
+	PrefetchSources { branch: BranchRef, package: Uuid },
+	/// Compare artifacts of two builds, see [`crate::artifact_diff`].
+	DiffArtifacts { build: Uuid, against: Uuid },
+	/// Discover topic branches of a repository, see [`crate::branch_scan`].
+	ScanBranches,
+	/// Build a package on a target, see [`crate::build_cache`].
+	BuildPackage {
+		branch: BranchRef,
+		build: PlannedBuild,
+	},
+	/// Build a package again to verify that its last build is reproducible,
+	/// see [`crate::repro`].
+	VerifyBuild {
+		branch: BranchRef,
+		/// The verified build, with the revision of the state when scheduled.
+		build: PlannedBuild,
+		/// Runner which has run the verified build, which must not run this job.
+		builder: String,
+	},
+}
+
+impl JobCommand {
+	/// Returns the branch which this job works on.
+	///
+	/// Per-branch quotas are enforced on jobs with a subject branch.
+	pub fn subject_branch(&self) -> Option<BranchRef> {
+		match self {
+			JobCommand::SyncBranch(branch) => Some(*branch),
+			JobCommand::LintPackage { branch, .. } => Some(*branch),
+			JobCommand::PrefetchSources { branch, .. } => Some(*branch),
+			JobCommand::BuildPackage { branch, .. } => Some(*branch),
+			JobCommand::VerifyBuild { branch, .. } => Some(*branch),
+			JobCommand::IngestAdvisories
+			| JobCommand::RefreshUpstream
+			| JobCommand::DiffArtifacts { .. }
+			| JobCommand::ScanBranches => None,
+		}
+	}
+
+	/// Returns the package which this job works on.
+	pub fn subject_pkg(&self) -> Option<Uuid> {
+		match self {
+			JobCommand::LintPackage { package, .. } => Some(*package),
+			JobCommand::PrefetchSources { package, .. } => Some(*package),
+			JobCommand::BuildPackage { build, .. } => Some(build.package),
+			JobCommand::VerifyBuild { build, .. } => Some(build.package),
+			JobCommand::SyncBranch(_)
+			| JobCommand::IngestAdvisories
+			| JobCommand::RefreshUpstream
+			| JobCommand::DiffArtifacts { .. }
+			| JobCommand::ScanBranches => None,
+		}
+	}
+
+	/// Returns the key of what this job works on.
+	///
+	/// Durations are tracked for jobs of the same kind and subject.
+	pub fn subject(&self) -> String {
+		match self {
+			JobCommand::SyncBranch(branch) => format!("branch:{branch}"),
+			JobCommand::LintPackage { package, .. } => format!("pkg:{package}"),
+			JobCommand::PrefetchSources { package, .. } => format!("src:{package}"),
+			JobCommand::IngestAdvisories => "security".to_string(),
+			JobCommand::RefreshUpstream => "upstream".to_string(),
+			JobCommand::DiffArtifacts { build, against } => format!("diff:{build}:{against}"),
+			JobCommand::ScanBranches => "branch-scan".to_string(),
+			JobCommand::BuildPackage { build, .. } => format!("build:{}", build.id),
+			JobCommand::VerifyBuild { build, .. } => format!("verify:{}", build.id),
+		}
+	}
+
+	/// Returns the target architecture which this job builds for.
+	///
+	/// Jobs without target architectures may be run by any runner.
+	pub fn target_arch(&self) -> Option<KString> {
+		match self {
+			JobCommand::BuildPackage { build, .. } | JobCommand::VerifyBuild { build, .. } => {
+				Some(build.arch.clone())
+			}
+			JobCommand::SyncBranch(_)
+			| JobCommand::LintPackage { .. }
+			| JobCommand::IngestAdvisories
+			| JobCommand::RefreshUpstream
+			| JobCommand::PrefetchSources { .. }
+			| JobCommand::DiffArtifacts { .. }
+			| JobCommand::ScanBranches => None,
+		}
+	}
+
+	pub fn serialize(&self) -> serde_json::Result<(KString, serde_json::Value)> {
+		let mut value = serde_json::to_value(self)?;
+		Ok((
+			KString::from_ref(value["t"].as_str().unwrap()),
+			value.as_object_mut().unwrap().remove("c").unwrap(),
+		))
+	}
+
+	pub fn deserialize(kind: &str, value: serde_json::Value) -> serde_json::Result<Self> {
+		let value = serde_json::json!({ "t": kind, "c": value });
+		serde_json::from_value(value)
+	}
+}
+
+pub type JobRef = Uuid;
+
+/// Kinds of jobs which a runner may claim, by [`JobCommand::serialize`]d kinds.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum KindFilter {
+	/// Jobs of any kind.
+	#[default]
+	Any,
+	/// Only jobs of these kinds.
+	Only(Vec<String>),
+	/// Jobs of kinds other than these.
+	Except(Vec<String>),
+}
+
+/// Configuration for [`JobQueue`].
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct JobQueueConfig {
+	/// The maximum count of retries for jobs failed with [`FailureClass::Transient`].
+	#[serde(default = "default_retry_budget")]
+	pub retry_budget: u16,
+	/// Base delay in seconds before retrying jobs failed with [`FailureClass::Transient`].
+	///
+	/// The delay doubles with each attempt, and is jittered down to a half.
+	#[serde(default = "default_retry_backoff")]
+	pub retry_backoff: u64,
+	/// Scheduling policy of pending jobs.
+	#[serde(default)]
+	pub scheduling: SchedulingMode,
+	/// Assumed duration of a job in seconds, used to estimate the backlog duration.
+	#[serde(default = "default_job_duration")]
+	pub default_job_duration: u64,
+	/// Implementation dispatching pending jobs.
+	#[serde(default)]
+	pub backend: JobQueueBackend,
+	/// Timeouts of jobs in seconds, by job kind.
+	///
+	/// Jobs running longer are failed with [`FailureClass::Timeout`].
+	#[serde(default)]
+	pub timeouts: BTreeMap<String, u64>,
+	/// Timeout in seconds of jobs of kinds not in [`JobQueueConfig::timeouts`].
+	///
+	/// Jobs of such kinds never time out if not set.
+	#[serde(default)]
+	pub default_timeout: Option<u64>,
+	/// Target architectures which runners of this instance are capable of.
+	///
+	/// Jobs of all target architectures are claimed if not set.
+	#[serde(default)]
+	pub arches: Option<Vec<String>>,
+	/// The maximum count of running jobs of all instances, by target architecture.
+	#[serde(default)]
+	pub arch_max_running_jobs: BTreeMap<String, u32>,
+	/// Name of this instance recorded in history as the runner of finished jobs,
+	/// e.g. the host name.
+	#[serde(default)]
+	pub runner: Option<String>,
+}
+
+impl JobQueueConfig {
+	/// Returns the timeout of jobs of a kind.
+	pub fn timeout(&self, kind: &str) -> Option<Duration> {
+		self.timeouts
+			.get(kind)
+			.copied()
+			.or(self.default_timeout)
+			.map(Duration::from_secs)
+	}
+}
+
+fn default_retry_budget() -> u16 {
+	3
+}
+
+fn default_retry_backoff() -> u64 {
+	30
+}
+
+fn default_job_duration() -> u64 {
+	60
+}
+
+impl Default for JobQueueConfig {
+	fn default() -> Self {
+		Self {
+			retry_budget: default_retry_budget(),
+			retry_backoff: default_retry_backoff(),
+			scheduling: SchedulingMode::default(),
+			default_job_duration: default_job_duration(),
+			backend: JobQueueBackend::default(),
+			timeouts: BTreeMap::new(),
+			default_timeout: None,
+			arches: None,
+			arch_max_running_jobs: BTreeMap::new(),
+			runner: None,
+		}
+	}
+}
+
+/// Implementation dispatching pending jobs to runners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobQueueBackend {
+	/// Runners poll the queue table, see [`SqlDispatcher`].
+	#[default]
+	Sql,
+	/// Jobs are dispatched with Redis Streams, see [`StreamDispatcher`].
+	///
+	/// Falls back to [`JobQueueBackend::Sql`] if Redis is not configured.
+	RedisStreams,
+}
+
+/// Scheduling policy of pending jobs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SchedulingMode {
+	/// Jobs are ordered by priority, and then by enqueue time.
+	///
+	/// A branch with a high priority may occupy all runners.
+	#[default]
+	Priority,
+	/// Jobs are ordered by priority band, see [`PRIORITY_BAND_WIDTH`], and branches
+	/// with pending jobs in the same band are served in turn.
+	///
+	/// Turns are weighted by the highest priority of pending jobs of each branch
+	/// in the band, so that a branch with priority `band + 9` is served ten times
+	/// as often as one with priority `band`.
+	Fair,
+}
+
+/// Classification of a job failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+	/// Failures caused by the infrastructure rather than the job itself,
+	/// e.g. network errors or a busy worker running out of memory.
+	///
+	/// Jobs failed with this class are requeued until the retry budget is used up.
+	Transient,
+	/// Genuine failures of the job.
+	///
+	/// Jobs failed with this class are never retried.
+	Permanent,
+	/// Jobs running longer than their timeouts, see [`JobQueueConfig::timeouts`].
+	///
+	/// Jobs failed with this class are retried like [`FailureClass::Transient`].
+	Timeout,
+}
+
+impl FailureClass {
+	/// Returns whether jobs failed with this class are requeued.
+	pub fn is_retryable(&self) -> bool {
+		match self {
+			FailureClass::Transient | FailureClass::Timeout => true,
+			FailureClass::Permanent => false,
+		}
+	}
+}
+
+/// Error of a failed job.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct JobError {
+	/// Whether the job may be retried, see [`FailureClass::is_retryable`].
+	pub retryable: bool,
+	pub message: String,
+	/// ID with which the backtrace of the error has been logged by the runner.
+	pub backtrace: Option<Uuid>,
+}
+
+impl JobError {
+	pub fn new(class: FailureClass, message: impl Into<String>) -> Self {
+		Self {
+			retryable: class.is_retryable(),
+			message: message.into(),
+			backtrace: None,
+		}
+	}
+}
+
+impl std::fmt::Display for JobError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&self.message)
+	}
+}
+
+/// Count of jobs of a kind in the queue.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JobQueueDepth {
+	pub kind: KString,
+	pub pending: u64,
+	pub running: u64,
+}
+
+/// Width of priority bands in [`JobQueueStats`].
+pub const PRIORITY_BAND_WIDTH: u16 = 100;
+
+/// Count of jobs in the queue by kind, priority band and subject branch.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JobQueueStats {
+	pub kind: KString,
+	/// Priority of the jobs, rounded down to a multiple of [`PRIORITY_BAND_WIDTH`].
+	pub priority_band: u16,
+	/// Subject branch of the jobs, see [`JobCommand::subject_branch`].
+	pub branch: Option<BranchRef>,
+	pub pending: u64,
+	pub running: u64,
+}
+
+/// What happened to a failed job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailureOutcome {
+	/// The job has been put back into the queue.
+	Requeued,
+	/// The job has been removed from the queue.
+	Dropped,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Job {
+	pub id: JobRef,
+	pub command: JobCommand,
+	/// Trace context of the request which has enqueued this job.
+	pub trace: Option<TraceContext>,
+}
+
+impl Job {
+	fn from_pending((id, kind, data, traceparent): PendingJob) -> Result<Self> {
+		Ok(Self {
+			id: id.0,
+			command: JobCommand::deserialize(&kind, data.0)?,
+			trace: traceparent.as_deref().and_then(TraceContext::parse),
+		})
+	}
+}
+
+/// Information of a job in the queue.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct JobInfo {
+	pub id: JobRef,
+	pub command: JobCommand,
+	pub priority: u16,
+	pub attempts: u16,
+	/// Started time of this job in UTC.
+	pub started_at: Option<PrimitiveDateTime>,
+	/// Estimated duration from history of the same job.
+	pub estimated_duration: Option<time::Duration>,
+}
+
+/// A job delivered by a [`JobDispatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DispatchedJob {
+	pub id: JobRef,
+	/// ID of the delivery in the dispatcher.
+	pub entry: String,
+	/// Enqueued time in milliseconds since UNIX epoch.
+	pub enqueued_at: u64,
+}
+
+/// Dispatcher delivering pending jobs to runners.
+///
+/// The queue table is always the source of truth of jobs. Dispatchers only decide
+/// which job is claimed next, and [`JobQueue`] polls the table when a dispatcher
+/// has nothing to deliver.
+pub trait JobDispatcher
+where
+	Self: Send + Sync + Debug,
+{
+	/// Called after a job has been inserted or requeued.
+	///
+	/// The insertion may not have been committed yet.
+	fn enqueued(&self, id: JobRef) -> BoxFuture<'_, Result<()>>;
+	/// Returns the next job to be claimed, or [`None`] to poll the queue table.
+	fn next(&self) -> BoxFuture<'_, Result<Option<DispatchedJob>>>;
+	/// Acknowledges a delivered job after it has been claimed,
+	/// or found to be claimed or removed already.
+	fn ack<'a>(&'a self, job: &'a DispatchedJob) -> BoxFuture<'a, Result<()>>;
+	/// Returns whether a delivered job missing in the queue table should be dropped.
+	///
+	/// Jobs of uncommitted transactions are missing until the commit, and should
+	/// be delivered again later.
+	fn is_stale(&self, job: &DispatchedJob) -> bool;
+}
+
+/// Dispatcher polling the queue table only.
+#[derive(Debug)]
+pub struct SqlDispatcher;
+
+impl JobDispatcher for SqlDispatcher {
+	fn enqueued(&self, _: JobRef) -> BoxFuture<'_, Result<()>> {
+		ready(Ok(())).boxed()
+	}
+
+	fn next(&self) -> BoxFuture<'_, Result<Option<DispatchedJob>>> {
+		ready(Ok(None)).boxed()
+	}
+
+	fn ack<'a>(&'a self, _: &'a DispatchedJob) -> BoxFuture<'a, Result<()>> {
+		ready(Ok(())).boxed()
+	}
+
+	fn is_stale(&self, _: &DispatchedJob) -> bool {
+		true
+	}
+}
+
+/// Operations on the job queue used by API handlers.
+///
+/// Implemented by [`JobQueue`], and mocked by `MockJobQueueApi`
+/// with the `mock` feature.
+#[cfg_attr(feature = "mock", mockall::automock)]
+#[async_trait]
+pub trait JobQueueApi: Send + Sync + Debug {
+	/// See [`JobQueue::pause`].
+	async fn pause(&self) -> Result<()>;
+	/// See [`JobQueue::resume`].
+	async fn resume(&self) -> Result<()>;
+	/// See [`JobQueue::is_paused`].
+	async fn is_paused(&self) -> Result<bool>;
+	/// See [`JobQueue::depth`].
+	async fn depth(&self) -> Result<Vec<JobQueueDepth>>;
+	/// See [`JobQueue::estimate_backlog`].
+	async fn estimate_backlog(&self, depth: &[JobQueueDepth]) -> Result<time::Duration>;
+	/// See [`JobQueue::stats`].
+	async fn stats(&self) -> Result<Vec<JobQueueStats>>;
+	/// See [`JobQueue::list`].
+	async fn list(&self, namespace: NamespaceRef, limit: usize) -> Result<Vec<JobInfo>>;
+	/// See [`JobQueue::list_branch`].
+	async fn list_branch(&self, branch: BranchRef, limit: usize) -> Result<Vec<JobInfo>>;
+	/// See [`JobQueue::get`].
+	async fn get(&self, id: JobRef) -> Result<Option<JobInfo>>;
+	/// See [`JobQueue::get_finished`].
+	async fn get_finished(&self, id: JobRef) -> Result<Option<HistoryJob>>;
+	/// See [`JobQueue::list_finished`].
+	async fn list_finished(&self, filter: &HistoryFilter, limit: usize) -> Result<Vec<HistoryJob>>;
+}
+
+#[derive(Debug)]
+pub struct JobQueue {
+	db: Arc<DatabaseService>,
+	redis: Option<Arc<RedisService>>,
+	history: Arc<JobHistoryService>,
+	config: JobQueueConfig,
+	dispatcher: Box<dyn JobDispatcher>,
+	/// Current weights of branches in the smooth weighted round-robin
+	/// of [`SchedulingMode::Fair`].
+	fair_weights: Mutex<BTreeMap<BranchRef, i64>>,
+}
+
+type PendingJob = (XUuidVal, String, XJsonVal, Option<String>);
+
+/// Pending jobs which must not be claimed by this instance.
+#[derive(Debug, Default)]
+struct Exclusions {
+	/// Branches which have used up their running jobs quotas.
+	branches: Vec<BranchRef>,
+	/// Target architectures which runners of this instance are not capable of,
+	/// or which have used up their running jobs caps.
+	arches: Vec<String>,
+	/// Kinds of jobs which the fetching runner is not assigned to, see [`KindFilter`].
+	kinds: Vec<String>,
+}
+
+/// Redis key, or name of the database flag without Redis,
+/// which exists when and only when the job queue is paused.
+pub const JOB_QUEUE_PAUSED_KEY: &str = "job-queue:paused";
+
+/// Placeholder of [`JobCommand::subject_branch`] for jobs without a subject branch.
+const NO_SUBJECT_BRANCH: BranchRef = 0;
+
+define_sql_function! {
+	fn coalesce(x: Nullable<BigInt>, y: BigInt) -> BigInt;
+}
+
+// Claims select pending jobs in subqueries of the alias, as a subquery
+// of the updated table itself is not allowed by diesel.
+diesel::alias!(crate::db::schema::job_queue as pending_job: PendingJobAlias);
+
+impl JobQueue {
+	pub fn new(
+		db: Arc<DatabaseService>,
+		redis: Option<Arc<RedisService>>,
+		history: Arc<JobHistoryService>,
+		config: &JobQueueConfig,
+	) -> Self {
+		let dispatcher: Box<dyn JobDispatcher> = match (config.backend, &redis) {
+			(JobQueueBackend::RedisStreams, Some(redis)) => {
+				Box::new(StreamDispatcher::new(redis.clone()))
+			}
+			(JobQueueBackend::RedisStreams, None) => {
+				warn!("Redis is not configured, falling back to SQL job queue");
+				Box::new(SqlDispatcher)
+			}
+			(JobQueueBackend::Sql, _) => Box::new(SqlDispatcher),
+		};
+		Self {
+			db,
+			redis,
+			history,
+			config: config.to_owned(),
+			dispatcher,
+			fair_weights: Mutex::new(BTreeMap::new()),
+		}
+	}
+
+	pub async fn enqueue(&self, conn: &mut BoxedSqlConn, job: JobCommand) -> Result<JobRef> {
+		self.enqueue_with_priority(conn, job, 100).await
+	}
+
+	pub async fn enqueue_with_priority(
+		&self,
+		conn: &mut BoxedSqlConn,
+		job: JobCommand,
+		priority: u16,
+	) -> Result<JobRef> {
+		let id = Uuid::now_v7();
+		let (kind, job_data) = job.serialize()?;
+		let subject_branch = job.subject_branch();
+		let target_arch = job.target_arch();
+		if let Some(branch) = subject_branch {
+			self.check_queue_quota(conn, branch).await?;
+		}
+		let estimated = self
+			.history
+			.estimate(conn, &kind, &job.subject())
+			.await?
+			.unwrap_or_default();
+
+		let id = conn
+			.get_result::<_, XUuidVal>(
+				insert_into(dsl::job_queue)
+					.values((
+						dsl::id.eq(XUuidVal(id)),
+						dsl::kind.eq(kind.as_str()),
+						dsl::data.eq(XJsonVal(job_data)),
+						dsl::priority.eq(priority as i16),
+						dsl::subject_branch.eq(subject_branch),
+						dsl::estimated_ms.eq(estimated.whole_milliseconds() as i64),
+						dsl::traceparent.eq(TraceContext::current().map(|trace| trace.to_string())),
+						dsl::target_arch.eq(target_arch.as_deref()),
+						dsl::subject_pkg.eq(job.subject_pkg().map(XUuidVal)),
+					))
+					.returning(dsl::id),
+			)
+			.await?;
+		let id = id.0;
+		info!(%kind, %id, "enqueued job");
+		self.dispatcher.enqueued(id).await?;
+
+		// TODO: notify a job worker
+
+		Ok(id)
+	}
+
+	/// Ensures that the branch and its namespace have not used up their queued jobs quotas.
+	async fn check_queue_quota(&self, conn: &mut BoxedSqlConn, branch: BranchRef) -> Result<()> {
+		let Some((limit, namespace)) = conn
+			.get_result::<_, (Option<i32>, NamespaceRef)>(
+				branch_dsl::branch
+					.filter(branch_dsl::id.eq(branch))
+					.select((branch_dsl::max_queued_jobs, branch_dsl::namespace)),
+			)
+			.await
+			.optional()?
+		else {
+			return Ok(());
+		};
+		if let Some(limit) = limit {
+			let queued: i64 = conn
+				.get_result(
+					dsl::job_queue
+						.filter(
+							dsl::subject_branch
+								.eq(branch)
+								.and(dsl::started_at.is_null()),
+						)
+						.count(),
+				)
+				.await?;
+			if queued >= limit as i64 {
+				warn!(branch, limit, "branch queued jobs quota exceeded");
+				return Err(JobQueueError::QuotaExceeded(branch).into());
+			}
+		}
+
+		let limit = conn
+			.get_result::<_, Option<i32>>(
+				ns_dsl::namespace
+					.filter(ns_dsl::id.eq(namespace))
+					.select(ns_dsl::max_queued_jobs),
+			)
+			.await
+			.optional()?
+			.flatten();
+		if let Some(limit) = limit {
+			let branches = conn
+				.load::<_, BranchRef>(
+					branch_dsl::branch
+						.filter(branch_dsl::namespace.eq(namespace))
+						.select(branch_dsl::id),
+				)
+				.await?;
+			let queued: i64 = conn
+				.get_result(
+					dsl::job_queue
+						.filter(
+							dsl::subject_branch
+								.eq_any(branches)
+								.and(dsl::started_at.is_null()),
+						)
+						.count(),
+				)
+				.await?;
+			if queued >= limit as i64 {
+				warn!(namespace, limit, "namespace queued jobs quota exceeded");
+				return Err(JobQueueError::NamespaceQuotaExceeded(namespace).into());
+			}
+		}
+		Ok(())
+	}
+
+	/// Finds branches which have used up their running jobs quota.
+	async fn find_saturated_branches(&self, conn: &mut BoxedSqlConn) -> Result<Vec<BranchRef>> {
+		let limits = conn
+			.load::<_, (BranchRef, i32)>(
+				branch_dsl::branch
+					.filter(branch_dsl::max_running_jobs.is_not_null())
+					.select((
+						branch_dsl::id,
+						branch_dsl::max_running_jobs.assume_not_null(),
+					)),
+			)
+			.await?;
+		if limits.is_empty() {
+			return Ok(vec![]);
+		}
+
+		let running = conn
+			.load::<_, (Option<BranchRef>, i64)>(
+				dsl::job_queue
+					.filter(
+						dsl::started_at
+							.is_not_null()
+							.and(dsl::subject_branch.eq_any(limits.iter().map(|(id, _)| *id))),
+					)
+					.group_by(dsl::subject_branch)
+					.select((dsl::subject_branch, diesel::dsl::count_star())),
+			)
+			.await?
+			.into_iter()
+			.filter_map(|(branch, count)| branch.map(|branch| (branch, count)))
+			.collect::<HashMap<_, _>>();
+		// branches without running jobs have none of them counted
+		let saturated = limits
+			.into_iter()
+			.filter(|(id, limit)| running.get(id).copied().unwrap_or(0) >= *limit as i64)
+			.map(|(id, _)| id)
+			.collect();
+		Ok(saturated)
+	}
+
+	/// Finds target architectures of which pending jobs must not be claimed,
+	/// see [`JobQueueConfig::arches`] and [`JobQueueConfig::arch_max_running_jobs`].
+	async fn find_excluded_arches(&self, conn: &mut BoxedSqlConn) -> Result<Vec<String>> {
+		let mut excluded = match &self.config.arches {
+			Some(arches) => conn
+				.load::<_, String>(
+					dsl::job_queue
+						.filter(dsl::started_at.is_null())
+						.filter(dsl::target_arch.is_not_null())
+						.select(dsl::target_arch.assume_not_null())
+						.distinct(),
+				)
+				.await?
+				.into_iter()
+				.filter(|arch| !arches.contains(arch))
+				.collect(),
+			None => vec![],
+		};
+		let limits = &self.config.arch_max_running_jobs;
+		if limits.is_empty() {
+			return Ok(excluded);
+		}
+
+		let running = conn
+			.load::<_, (Option<String>, i64)>(
+				dsl::job_queue
+					.filter(
+						dsl::started_at
+							.is_not_null()
+							.and(dsl::target_arch.eq_any(limits.keys())),
+					)
+					.group_by(dsl::target_arch)
+					.select((dsl::target_arch, diesel::dsl::count_star())),
+			)
+			.await?;
+		for (arch, limit) in limits {
+			let saturated = running.iter().any(|(running_arch, count)| {
+				running_arch.as_ref() == Some(arch) && *count >= *limit as i64
+			});
+			if saturated && !excluded.contains(arch) {
+				excluded.push(arch.clone());
+			}
+		}
+		Ok(excluded)
+	}
+
+	/// Finds kinds of pending jobs which must not be claimed with a [`KindFilter`].
+	async fn find_excluded_kinds(
+		&self,
+		conn: &mut BoxedSqlConn,
+		kinds: &KindFilter,
+	) -> Result<Vec<String>> {
+		match kinds {
+			KindFilter::Any => Ok(vec![]),
+			KindFilter::Except(kinds) => Ok(kinds.clone()),
+			KindFilter::Only(kinds) => Ok(conn
+				.load::<_, String>(
+					dsl::job_queue
+						.filter(dsl::started_at.is_null())
+						.filter(not(dsl::kind.eq_any(kinds)))
+						.select(dsl::kind)
+						.distinct(),
+				)
+				.await?),
+		}
+	}
+
+	/// Pauses dispatching of all pending jobs.
+	///
+	/// Running jobs are not affected.
+	/// Without Redis, the paused state is shared through the database, see [`flag`].
+	pub async fn pause(&self) -> Result<()> {
+		match &self.redis {
+			Some(redis) => {
+				let _: () = redis
+					.get()
+					.await?
+					.set(redis.key(JOB_QUEUE_PAUSED_KEY), 1)
+					.await
+					.map_err(RedisError::RedisError)?;
+			}
+			None => flag::set(&mut *self.db.get().await?, JOB_QUEUE_PAUSED_KEY, Some("1")).await?,
+		}
+		warn!("paused job queue");
+		Ok(())
+	}
+
+	/// Resumes dispatching of pending jobs.
+	pub async fn resume(&self) -> Result<()> {
+		match &self.redis {
+			Some(redis) => {
+				let _: () = redis
+					.get()
+					.await?
+					.del(redis.key(JOB_QUEUE_PAUSED_KEY))
+					.await
+					.map_err(RedisError::RedisError)?;
+			}
+			None => flag::set(&mut *self.db.get().await?, JOB_QUEUE_PAUSED_KEY, None).await?,
+		}
+		info!("resumed job queue");
+		Ok(())
+	}
+
+	/// Returns whether the job queue has been paused.
+	pub async fn is_paused(&self) -> Result<bool> {
+		match &self.redis {
+			Some(redis) => Ok(redis
+				.get()
+				.await?
+				.exists(redis.key(JOB_QUEUE_PAUSED_KEY))
+				.await
+				.map_err(RedisError::RedisError)?),
+			None => Ok(flag::get(&mut *self.db.get().await?, JOB_QUEUE_PAUSED_KEY)
+				.await?
+				.is_some()),
+		}
+	}
+
+	/// Fetches a pending job and marks it as started.
+	///
+	/// Returns [`None`] if there are no pending jobs, or the queue has been paused.
+	pub async fn fetch_and_start(&self) -> Result<Option<Job>> {
+		self.fetch_and_start_with_kinds(&KindFilter::Any).await
+	}
+
+	/// Fetches a pending job of the allowed kinds and marks it as started.
+	pub async fn fetch_and_start_with_kinds(&self, kinds: &KindFilter) -> Result<Option<Job>> {
+		if self.is_paused().await? {
+			return Ok(None);
+		}
+		let mut conn = self.db.get().await?;
+		let excluded = Exclusions {
+			branches: self.find_saturated_branches(&mut conn).await?,
+			arches: self.find_excluded_arches(&mut conn).await?,
+			kinds: self.find_excluded_kinds(&mut conn, kinds).await?,
+		};
+		if let Some(job) = self.fetch_dispatched(&mut conn, &excluded).await? {
+			return Ok(Some(job));
+		}
+
+		if matches!(*conn, BoxedSqlConn::Pg(_)) {
+			let time = OffsetDateTime::now_utc();
+			let time = PrimitiveDateTime::new(time.date(), time.time());
+			// branches of which pending jobs are all being claimed by other workers
+			let mut skipped = vec![];
+			loop {
+				let fair = match self.config.scheduling {
+					SchedulingMode::Priority => None,
+					SchedulingMode::Fair => {
+						match self
+							.select_fair_branch(&mut conn, &excluded, &skipped, time)
+							.await?
+						{
+							Some(selected) => Some(selected),
+							None => return Ok(None),
+						}
+					}
+				};
+				let BoxedSqlConn::Pg(pg) = &mut *conn else {
+					unreachable!()
+				};
+				let result = match fair {
+					None => Self::claim_pending_pg(pg, &excluded, time).await?,
+					Some((band, branch)) => {
+						Self::claim_pending_fair_pg(pg, &excluded, band, branch, time).await?
+					}
+				};
+				match (result, fair) {
+					(Some(pending), _) => {
+						info!(id = %pending.0, "polled lightweight job");
+						return Job::from_pending(pending).map(Some);
+					}
+					(None, Some((_, branch))) => {
+						debug!(
+							branch,
+							"jobs of branch are being claimed, serving the next branch"
+						);
+						skipped.push(branch);
+					}
+					(None, None) => return Ok(None),
+				}
+			}
+		}
+
+		// SQLite serializes writers, so optimistic updates rarely contend
+		loop {
+			let time = OffsetDateTime::now_utc();
+			let time = PrimitiveDateTime::new(time.date(), time.time());
+
+			let result = match self.config.scheduling {
+				SchedulingMode::Priority => self.find_pending(&mut conn, &excluded, time).await?,
+				SchedulingMode::Fair => self.find_pending_fair(&mut conn, &excluded, time).await?,
+			};
+			if let Some(pending) = result {
+				let id = pending.0;
+				let cols = conn
+					.execute(
+						update(dsl::job_queue)
+							.filter(dsl::id.eq(id).and(dsl::started_at.is_null()))
+							.set(dsl::started_at.eq(time)),
+					)
+					.await?;
+				#[cfg(test)]
+				assert!(cols != 0);
+				if cols == 0 {
+					warn!(%id, "SQL lightweight job queue polling hit contented");
+					continue;
+				}
+				info!(%id, "polled lightweight job");
+				return Job::from_pending(pending).map(Some);
+			} else {
+				return Ok(None);
+			}
+		}
+	}
+
+	/// Fetches up to `n` pending jobs of the allowed kinds and marks them as started.
+	///
+	/// In [`SchedulingMode::Priority`], jobs of branches without running jobs quotas
+	/// are claimed in one query. The rest are claimed one by one with
+	/// [`JobQueue::fetch_and_start_with_kinds`].
+	pub async fn fetch_and_start_many(&self, n: usize, kinds: &KindFilter) -> Result<Vec<Job>> {
+		if n == 0 || self.is_paused().await? {
+			return Ok(vec![]);
+		}
+		let mut jobs = Vec::with_capacity(n);
+		if self.config.scheduling == SchedulingMode::Priority {
+			let mut conn = self.db.get().await?;
+			jobs = self.claim_batch(&mut conn, n, kinds).await?;
+		}
+		while jobs.len() < n {
+			match self.fetch_and_start_with_kinds(kinds).await? {
+				Some(job) => jobs.push(job),
+				None => break,
+			}
+		}
+		Ok(jobs)
+	}
+
+	/// Claims up to `n` pending jobs of branches without running jobs quotas,
+	/// with the highest priorities.
+	async fn claim_batch(
+		&self,
+		conn: &mut BoxedSqlConn,
+		n: usize,
+		kinds: &KindFilter,
+	) -> Result<Vec<Job>> {
+		let limited = conn
+			.load::<_, BranchRef>(
+				branch_dsl::branch
+					.filter(branch_dsl::max_running_jobs.is_not_null())
+					.select(branch_dsl::id),
+			)
+			.await?;
+		let excluded_arches = self.find_excluded_arches(conn).await?;
+		let excluded_kinds = self.find_excluded_kinds(conn, kinds).await?;
+		let time = OffsetDateTime::now_utc();
+		let time = PrimitiveDateTime::new(time.date(), time.time());
+
+		let pending = pending_job
+			.filter(pending_job.field(dsl::started_at).is_null())
+			.filter(
+				pending_job
+					.field(dsl::scheduled_at)
+					.is_null()
+					.or(pending_job.field(dsl::scheduled_at).le(time)),
+			)
+			.filter(
+				pending_job
+					.field(dsl::subject_branch)
+					.is_null()
+					.or(not(pending_job.field(dsl::subject_branch).eq_any(limited))),
+			)
+			.filter(pending_job.field(dsl::target_arch).is_null().or(not(
+				pending_job.field(dsl::target_arch).eq_any(excluded_arches),
+			)))
+			.filter(not(pending_job.field(dsl::kind).eq_any(excluded_kinds)))
+			.order((
+				pending_job.field(dsl::priority).desc(),
+				pending_job.field(dsl::estimated_ms).desc(),
+				pending_job.field(dsl::id).asc(),
+			))
+			.limit(n.try_into().unwrap_or(i64::MAX))
+			.select(pending_job.field(dsl::id));
+		let claim = update(dsl::job_queue)
+			.filter(dsl::started_at.is_null())
+			.set(dsl::started_at.eq(time));
+		let claimed = match conn {
+			// rows being claimed by other workers are skipped instead of contended
+			BoxedSqlConn::Pg(pg) => {
+				diesel_async::RunQueryDsl::load(
+					claim
+						.filter(dsl::id.eq_any(pending.for_update().skip_locked()))
+						.returning((dsl::id, dsl::kind, dsl::data, dsl::traceparent)),
+					pg,
+				)
+				.await?
+			}
+			BoxedSqlConn::Sqlite(_) => {
+				conn.load::<_, PendingJob>(claim.filter(dsl::id.eq_any(pending)).returning((
+					dsl::id,
+					dsl::kind,
+					dsl::data,
+					dsl::traceparent,
+				)))
+				.await?
+			}
+		};
+		let jobs = claimed
+			.into_iter()
+			.map(Job::from_pending)
+			.collect::<Result<Vec<_>>>()?;
+		if !jobs.is_empty() {
+			info!(count = jobs.len(), "polled lightweight jobs in batch");
+		}
+		Ok(jobs)
+	}
+
+	/// Claims jobs delivered by the dispatcher.
+	///
+	/// Returns [`None`] when the dispatcher has nothing to deliver.
+	/// Jobs of excluded architectures and kinds are left for polling of capable runners.
+	async fn fetch_dispatched(
+		&self,
+		conn: &mut BoxedSqlConn,
+		excluded: &Exclusions,
+	) -> Result<Option<Job>> {
+		while let Some(dispatched) = self.dispatcher.next().await? {
+			let time = OffsetDateTime::now_utc();
+			let time = PrimitiveDateTime::new(time.date(), time.time());
+			let claimed = conn
+				.get_result::<_, PendingJob>(
+					update(dsl::job_queue)
+						.filter(dsl::id.eq(XUuidVal(dispatched.id)))
+						.filter(dsl::started_at.is_null())
+						.filter(dsl::scheduled_at.is_null().or(dsl::scheduled_at.le(time)))
+						.filter(
+							dsl::subject_branch
+								.is_null()
+								.or(not(dsl::subject_branch.eq_any(&excluded.branches))),
+						)
+						.filter(
+							dsl::target_arch
+								.is_null()
+								.or(not(dsl::target_arch.eq_any(&excluded.arches))),
+						)
+						.filter(not(dsl::kind.eq_any(&excluded.kinds)))
+						.set(dsl::started_at.eq(time))
+						.returning((dsl::id, dsl::kind, dsl::data, dsl::traceparent)),
+				)
+				.await
+				.optional()?;
+			if let Some(pending) = claimed {
+				self.dispatcher.ack(&dispatched).await?;
+				info!(id = %dispatched.id, "claimed dispatched job");
+				return Job::from_pending(pending).map(Some);
+			}
+
+			let started_at = conn
+				.get_result::<_, Option<PrimitiveDateTime>>(
+					dsl::job_queue
+						.filter(dsl::id.eq(XUuidVal(dispatched.id)))
+						.select(dsl::started_at),
+				)
+				.await
+				.optional()?;
+			match started_at {
+				Some(None) => {
+					// excluded by quotas, left for capable runners, or not due yet, and
+					// dispatched again later; the rest of the stream is left for polling,
+					// which skips excluded jobs
+					self.dispatcher.ack(&dispatched).await?;
+					self.dispatcher.enqueued(dispatched.id).await?;
+					debug!(id = %dispatched.id, "requeued excluded dispatched job");
+					return Ok(None);
+				}
+				// claimed by polling
+				Some(Some(_)) => self.dispatcher.ack(&dispatched).await?,
+				// removed
+				None if self.dispatcher.is_stale(&dispatched) => {
+					self.dispatcher.ack(&dispatched).await?
+				}
+				None => {
+					// possibly not committed yet, and will be delivered again
+					warn!(id = %dispatched.id, "dispatched job is not visible yet");
+					return Ok(None);
+				}
+			}
+		}
+		Ok(None)
+	}
+
+	/// Finds a pending job with the highest priority.
+	async fn find_pending(
+		&self,
+		conn: &mut BoxedSqlConn,
+		excluded: &Exclusions,
+		time: PrimitiveDateTime,
+	) -> Result<Option<PendingJob>> {
+		// for jobs with the same priority, longer jobs are started first,
+		// and then we order them with ID.
+		// because ID are UUID v7, this is equivalent to ordering with
+		// insertion time
+		Ok(conn
+			.get_result(
+				dsl::job_queue
+					.limit(1)
+					.filter(dsl::started_at.is_null())
+					.filter(dsl::scheduled_at.is_null().or(dsl::scheduled_at.le(time)))
+					.filter(
+						dsl::subject_branch
+							.is_null()
+							.or(not(dsl::subject_branch.eq_any(&excluded.branches))),
+					)
+					.filter(
+						dsl::target_arch
+							.is_null()
+							.or(not(dsl::target_arch.eq_any(&excluded.arches))),
+					)
+					.filter(not(dsl::kind.eq_any(&excluded.kinds)))
+					.order((
+						dsl::priority.desc(),
+						dsl::estimated_ms.desc(),
+						dsl::id.asc(),
+					))
+					.select((dsl::id, dsl::kind, dsl::data, dsl::traceparent)),
+			)
+			.await
+			.optional()?)
+	}
+
+	/// Claims a pending job with the highest priority on PostgreSQL.
+	///
+	/// Rows being claimed by other workers are skipped instead of contended.
+	async fn claim_pending_pg(
+		conn: &mut AsyncPgConnection,
+		excluded: &Exclusions,
+		time: PrimitiveDateTime,
+	) -> Result<Option<PendingJob>> {
+		Ok(diesel_async::RunQueryDsl::get_result(
+			update(dsl::job_queue)
+				.filter(
+					dsl::id.eq_any(
+						pending_job
+							.limit(1)
+							.filter(pending_job.field(dsl::started_at).is_null())
+							.filter(
+								pending_job
+									.field(dsl::scheduled_at)
+									.is_null()
+									.or(pending_job.field(dsl::scheduled_at).le(time)),
+							)
+							.filter(
+								pending_job.field(dsl::subject_branch).is_null().or(not(
+									pending_job
+										.field(dsl::subject_branch)
+										.eq_any(&excluded.branches),
+								)),
+							)
+							.filter(pending_job.field(dsl::target_arch).is_null().or(not(
+								pending_job.field(dsl::target_arch).eq_any(&excluded.arches),
+							)))
+							.filter(not(pending_job.field(dsl::kind).eq_any(&excluded.kinds)))
+							.order((
+								pending_job.field(dsl::priority).desc(),
+								pending_job.field(dsl::estimated_ms).desc(),
+								pending_job.field(dsl::id).asc(),
+							))
+							.select(pending_job.field(dsl::id))
+							.for_update()
+							.skip_locked(),
+					),
+				)
+				.set(dsl::started_at.eq(time))
+				.returning((dsl::id, dsl::kind, dsl::data, dsl::traceparent)),
+			conn,
+		)
+		.await
+		.optional()?)
+	}
+
+	/// Claims a pending job of a branch selected by [`JobQueue::select_fair_branch`]
+	/// on PostgreSQL.
+	async fn claim_pending_fair_pg(
+		conn: &mut AsyncPgConnection,
+		excluded: &Exclusions,
+		band: i16,
+		branch: BranchRef,
+		time: PrimitiveDateTime,
+	) -> Result<Option<PendingJob>> {
+		Ok(diesel_async::RunQueryDsl::get_result(
+			update(dsl::job_queue)
+				.filter(
+					dsl::id.eq_any(
+						pending_job
+							.limit(1)
+							.filter(
+								pending_job
+									.field(dsl::started_at)
+									.is_null()
+									.and(pending_job.field(dsl::priority).ge(band)),
+							)
+							.filter(
+								pending_job
+									.field(dsl::scheduled_at)
+									.is_null()
+									.or(pending_job.field(dsl::scheduled_at).le(time)),
+							)
+							.filter(
+								coalesce(pending_job.field(dsl::subject_branch), NO_SUBJECT_BRANCH)
+									.eq(branch),
+							)
+							.filter(pending_job.field(dsl::target_arch).is_null().or(not(
+								pending_job.field(dsl::target_arch).eq_any(&excluded.arches),
+							)))
+							.filter(not(pending_job.field(dsl::kind).eq_any(&excluded.kinds)))
+							.order((
+								pending_job.field(dsl::priority).desc(),
+								pending_job.field(dsl::estimated_ms).desc(),
+								pending_job.field(dsl::id).asc(),
+							))
+							.select(pending_job.field(dsl::id))
+							.for_update()
+							.skip_locked(),
+					),
+				)
+				.set(dsl::started_at.eq(time))
+				.returning((dsl::id, dsl::kind, dsl::data, dsl::traceparent)),
+			conn,
+		)
+		.await
+		.optional()?)
+	}
+
+	/// Finds a pending job in the highest priority band, serving branches in turn.
+	async fn find_pending_fair(
+		&self,
+		conn: &mut BoxedSqlConn,
+		excluded: &Exclusions,
+		time: PrimitiveDateTime,
+	) -> Result<Option<PendingJob>> {
+		let Some((band, branch)) = self.select_fair_branch(conn, excluded, &[], time).await? else {
+			return Ok(None);
+		};
+
+		Ok(conn
+			.get_result(
+				dsl::job_queue
+					.limit(1)
+					.filter(dsl::started_at.is_null().and(dsl::priority.ge(band)))
+					.filter(dsl::scheduled_at.is_null().or(dsl::scheduled_at.le(time)))
+					.filter(coalesce(dsl::subject_branch, NO_SUBJECT_BRANCH).eq(branch))
+					.filter(
+						dsl::target_arch
+							.is_null()
+							.or(not(dsl::target_arch.eq_any(&excluded.arches))),
+					)
+					.filter(not(dsl::kind.eq_any(&excluded.kinds)))
+					.order((
+						dsl::priority.desc(),
+						dsl::estimated_ms.desc(),
+						dsl::id.asc(),
+					))
+					.select((dsl::id, dsl::kind, dsl::data, dsl::traceparent)),
+			)
+			.await
+			.optional()?)
+	}
+
+	/// Selects the highest pending priority band, and the next branch to serve in it.
+	///
+	/// Branches in `skipped` are not selected, e.g. when their jobs are being claimed.
+	///
+	/// Branches are selected by smooth weighted round-robin, i.e. the branch with
+	/// the highest current weight is selected, after current weights of all branches
+	/// are increased by their weights, and then its current weight is decreased
+	/// by the total weight.
+	async fn select_fair_branch(
+		&self,
+		conn: &mut BoxedSqlConn,
+		excluded: &Exclusions,
+		skipped: &[BranchRef],
+		time: PrimitiveDateTime,
+	) -> Result<Option<(i16, BranchRef)>> {
+		let priority = conn
+			.get_result::<_, i16>(
+				dsl::job_queue
+					.limit(1)
+					.filter(dsl::started_at.is_null())
+					.filter(dsl::scheduled_at.is_null().or(dsl::scheduled_at.le(time)))
+					.filter(
+						dsl::subject_branch
+							.is_null()
+							.or(not(dsl::subject_branch.eq_any(&excluded.branches))),
+					)
+					.filter(
+						dsl::target_arch
+							.is_null()
+							.or(not(dsl::target_arch.eq_any(&excluded.arches))),
+					)
+					.filter(not(dsl::kind.eq_any(&excluded.kinds)))
+					.filter(not(
+						coalesce(dsl::subject_branch, NO_SUBJECT_BRANCH).eq_any(skipped)
+					))
+					.order(dsl::priority.desc())
+					.select(dsl::priority),
+			)
+			.await
+			.optional()?;
+		let Some(priority) = priority else {
+			return Ok(None);
+		};
+		let band = priority - priority.rem_euclid(PRIORITY_BAND_WIDTH as i16);
+
+		let branches = conn
+			.load::<_, (Option<BranchRef>, Option<i16>)>(
+				dsl::job_queue
+					.filter(dsl::started_at.is_null().and(dsl::priority.ge(band)))
+					.filter(dsl::scheduled_at.is_null().or(dsl::scheduled_at.le(time)))
+					.filter(
+						dsl::subject_branch
+							.is_null()
+							.or(not(dsl::subject_branch.eq_any(&excluded.branches))),
+					)
+					.filter(
+						dsl::target_arch
+							.is_null()
+							.or(not(dsl::target_arch.eq_any(&excluded.arches))),
+					)
+					.filter(not(dsl::kind.eq_any(&excluded.kinds)))
+					.filter(not(
+						coalesce(dsl::subject_branch, NO_SUBJECT_BRANCH).eq_any(skipped)
+					))
+					.group_by(dsl::subject_branch)
+					.select((dsl::subject_branch, diesel::dsl::max(dsl::priority))),
+			)
+			.await?;
+		let weights = branches
+			.into_iter()
+			.map(|(branch, priority)| {
+				let weight = priority.unwrap_or(band) - band + 1;
+				(branch.unwrap_or(NO_SUBJECT_BRANCH), weight as i64)
+			})
+			.collect::<BTreeMap<_, _>>();
+		let total = weights.values().sum::<i64>();
+
+		let mut current = self.fair_weights.lock().unwrap();
+		current.retain(|branch, _| weights.contains_key(branch));
+		for (branch, weight) in &weights {
+			*current.entry(*branch).or_default() += weight;
+		}
+		// ties are broken by the lowest branch ID
+		let branch = current
+			.iter()
+			.max_by_key(|(branch, current)| (**current, std::cmp::Reverse(**branch)))
+			.map(|(branch, _)| *branch);
+		if let Some(branch) = branch {
+			*current.get_mut(&branch).unwrap() -= total;
+		}
+		Ok(branch.map(|branch| (band, branch)))
+	}
+
+	pub async fn finish_job(&self, conn: &mut BoxedSqlConn, id: JobRef) -> Result<()> {
+		self.remove_started(conn, id, SqlJobOutcome::Succeeded, None)
+			.await
+	}
+
+	/// Removes a started job from the queue, and records it into history.
+	async fn remove_started(
+		&self,
+		conn: &mut BoxedSqlConn,
+		id: JobRef,
+		outcome: SqlJobOutcome,
+		error: Option<&JobError>,
+	) -> Result<()> {
+		let removed = conn
+			.get_result::<_, (
+				String,
+				XJsonVal,
+				Option<BranchRef>,
+				Option<PrimitiveDateTime>,
+			)>(
+				delete(dsl::job_queue)
+					.filter(dsl::id.eq(XUuidVal(id)).and(dsl::started_at.is_not_null()))
+					.returning((dsl::kind, dsl::data, dsl::subject_branch, dsl::started_at)),
+			)
+			.await
+			.optional()?;
+		let Some((kind, data, subject_branch, started_at)) = removed else {
+			warn!(%id, "job has been aborted or finished by another worker");
+			return Err(JobQueueError::JobAborted(id).into());
+		};
+
+		let subject = JobCommand::deserialize(&kind, data.0.clone())
+			.map(|command| command.subject())
+			.unwrap_or_default();
+		let started_at = started_at.unwrap_or_else(|| {
+			let time = OffsetDateTime::now_utc();
+			PrimitiveDateTime::new(time.date(), time.time())
+		});
+		self.history
+			.record(
+				conn,
+				FinishedJob {
+					id,
+					kind,
+					data: data.0,
+					subject_branch,
+					subject,
+					outcome,
+					started_at,
+					error: error.cloned(),
+					runner: self.config.runner.clone(),
+				},
+			)
+			.await
+	}
+
+	/// Marks a started job as failed.
+	///
+	/// Transient failures are requeued while the retry budget is not used up,
+	/// other failed jobs are removed from the queue.
+	pub async fn fail_job(
+		&self,
+		conn: &mut BoxedSqlConn,
+		id: JobRef,
+		error: &JobError,
+	) -> Result<FailureOutcome> {
+		if error.retryable {
+			let attempts = conn
+				.get_result::<_, i16>(
+					dsl::job_queue
+						.filter(dsl::id.eq(XUuidVal(id)).and(dsl::started_at.is_not_null()))
+						.select(dsl::attempts),
+				)
+				.await
+				.optional()?
+				.filter(|attempts| *attempts < self.config.retry_budget as i16);
+			if let Some(attempts) = attempts {
+				let backoff = self.retry_backoff(attempts as u32);
+				let time = OffsetDateTime::now_utc() + backoff;
+				let time = PrimitiveDateTime::new(time.date(), time.time());
+				let cols = conn
+					.execute(
+						update(dsl::job_queue)
+							.filter(
+								dsl::id
+									.eq(XUuidVal(id))
+									.and(dsl::started_at.is_not_null())
+									.and(dsl::attempts.eq(attempts)),
+							)
+							.set((
+								dsl::started_at.eq(None::<PrimitiveDateTime>),
+								dsl::attempts.eq(attempts + 1),
+								dsl::scheduled_at.eq(time),
+							)),
+					)
+					.await?;
+				if cols != 0 {
+					// not delivered by the dispatcher, as it is not due yet;
+					// polling claims it after the backoff
+					info!(%id, ?backoff, "requeued job after transient failure");
+					return Ok(FailureOutcome::Requeued);
+				}
+			}
+		}
+
+		self.remove_started(conn, id, SqlJobOutcome::Failed, Some(error))
+			.await?;
+		warn!(%id, retryable = error.retryable, %error, "dropped failed job");
+		Ok(FailureOutcome::Dropped)
+	}
+
+	/// Returns the delay before retrying a job failed after `attempts` retries.
+	///
+	/// The delay is exponential in `attempts`, with jitter so that jobs failed
+	/// together are not retried together.
+	fn retry_backoff(&self, attempts: u32) -> Duration {
+		let backoff = self
+			.config
+			.retry_backoff
+			.saturating_mul(1 << attempts.min(10));
+		Duration::from_secs(rand::rng().random_range(backoff / 2..=backoff))
+	}
+
+	/// Releases a started job back to pending, e.g. when its runner is stopped.
+	///
+	/// Attempts are not counted, as the job has not failed. Returns whether the
+	/// job has been released, i.e. it has not been finished in the meantime.
+	pub async fn release_job(&self, conn: &mut BoxedSqlConn, id: JobRef) -> Result<bool> {
+		let cols = conn
+			.execute(
+				update(dsl::job_queue)
+					.filter(dsl::id.eq(XUuidVal(id)).and(dsl::started_at.is_not_null()))
+					.set(dsl::started_at.eq(None::<PrimitiveDateTime>)),
+			)
+			.await?;
+		if cols == 0 {
+			return Ok(false);
+		}
+		info!(%id, "released started job");
+		self.dispatcher.enqueued(id).await?;
+		Ok(true)
+	}
+
+	/// Cancels jobs working on a branch, e.g. when the branch is untracked.
+	///
+	/// Pending jobs are removed from the queue. Running jobs are removed and recorded
+	/// as failed, so that their runners cannot finish them, and are returned
+	/// for the runners to be notified to abort them.
+	pub async fn cancel_branch(
+		&self,
+		conn: &mut BoxedSqlConn,
+		branch: BranchRef,
+	) -> Result<Vec<JobRef>> {
+		let pending = conn
+			.execute(
+				delete(dsl::job_queue).filter(
+					dsl::subject_branch
+						.eq(branch)
+						.and(dsl::started_at.is_null()),
+				),
+			)
+			.await?;
+		let running = conn
+			.load::<_, XUuidVal>(
+				dsl::job_queue
+					.filter(dsl::subject_branch.eq(branch))
+					.select(dsl::id),
+			)
+			.await?
+			.into_iter()
+			.map(|id| id.0)
+			.collect::<Vec<_>>();
+		let error = JobError::new(
+			FailureClass::Permanent,
+			"cancelled as the branch is untracked",
+		);
+		for id in &running {
+			self.remove_started(conn, *id, SqlJobOutcome::Failed, Some(&error))
+				.await?;
+		}
+		if pending != 0 || !running.is_empty() {
+			info!(
+				branch,
+				pending,
+				running = running.len(),
+				"cancelled jobs of branch"
+			);
+		}
+		Ok(running)
+	}
+
+	/// Returns count of pending and running jobs per job kind.
+	pub async fn depth(&self) -> Result<Vec<JobQueueDepth>> {
+		let mut conn = self.db.get().await?;
+
+		let pending = conn
+			.load::<_, (String, i64)>(
+				dsl::job_queue
+					.filter(dsl::started_at.is_null())
+					.group_by(dsl::kind)
+					.select((dsl::kind, diesel::dsl::count_star())),
+			)
+			.await?;
+		let running = conn
+			.load::<_, (String, i64)>(
+				dsl::job_queue
+					.filter(dsl::started_at.is_not_null())
+					.group_by(dsl::kind)
+					.select((dsl::kind, diesel::dsl::count_star())),
+			)
+			.await?;
+
+		let mut depth = pending
+			.into_iter()
+			.map(|(kind, count)| JobQueueDepth {
+				kind: KString::from(kind),
+				pending: count as u64,
+				running: 0,
+			})
+			.collect::<Vec<_>>();
+		for (kind, count) in running {
+			match depth.iter_mut().find(|depth| depth.kind == kind) {
+				Some(depth) => depth.running = count as u64,
+				None => depth.push(JobQueueDepth {
+					kind: KString::from(kind),
+					pending: 0,
+					running: count as u64,
+				}),
+			}
+		}
+		Ok(depth)
+	}
+
+	/// Returns count of pending and running jobs per job kind, priority band
+	/// and subject branch, ordered by these keys.
+	pub async fn stats(&self) -> Result<Vec<JobQueueStats>> {
+		let mut conn = self.db.get().await?;
+		let mut stats = BTreeMap::new();
+		for running in [false, true] {
+			let query = dsl::job_queue
+				.group_by((dsl::kind, dsl::priority, dsl::subject_branch))
+				.select((
+					dsl::kind,
+					dsl::priority,
+					dsl::subject_branch,
+					diesel::dsl::count_star(),
+				));
+			let rows = match running {
+				false => {
+					conn.load::<_, (String, i16, Option<BranchRef>, i64)>(
+						query.filter(dsl::started_at.is_null()),
+					)
+					.await?
+				}
+				true => {
+					conn.load::<_, (String, i16, Option<BranchRef>, i64)>(
+						query.filter(dsl::started_at.is_not_null()),
+					)
+					.await?
+				}
+			};
+			for (kind, priority, branch, count) in rows {
+				let band = priority.max(0) as u16 / PRIORITY_BAND_WIDTH * PRIORITY_BAND_WIDTH;
+				let counts: &mut (u64, u64) = stats.entry((kind, band, branch)).or_default();
+				match running {
+					false => counts.0 += count as u64,
+					true => counts.1 += count as u64,
+				}
+			}
+		}
+		Ok(stats
+			.into_iter()
+			.map(
+				|((kind, priority_band, branch), (pending, running))| JobQueueStats {
+					kind: KString::from(kind),
+					priority_band,
+					branch,
+					pending,
+					running,
+				},
+			)
+			.collect())
+	}
+
+	/// Estimates the time needed by one runner to finish all pending jobs.
+	///
+	/// Jobs of kinds without any history are assumed to take
+	/// [`JobQueueConfig::default_job_duration`].
+	pub async fn estimate_backlog(&self, depth: &[JobQueueDepth]) -> Result<time::Duration> {
+		let default_duration = time::Duration::seconds(self.config.default_job_duration as i64);
+		let mut backlog = time::Duration::ZERO;
+		for depth in depth {
+			let duration = self
+				.history
+				.estimate_kind(&depth.kind)
+				.await?
+				.unwrap_or(default_duration);
+			backlog += duration * depth.pending as u32;
+		}
+		Ok(backlog)
+	}
+
+	/// Lists jobs of branches in a namespace in the queue, ordered by priority.
+	///
+	/// Jobs without subject branches are listed in [`DEFAULT_NAMESPACE_ID`].
+	pub async fn list(&self, namespace: NamespaceRef, limit: usize) -> Result<Vec<JobInfo>> {
+		let mut conn = self.db.get().await?;
+
+		let branches = conn
+			.load::<_, BranchRef>(
+				branch_dsl::branch
+					.filter(branch_dsl::namespace.eq(namespace))
+					.select(branch_dsl::id),
+			)
+			.await?;
+		let query = dsl::job_queue
+			.order((
+				dsl::priority.desc(),
+				dsl::estimated_ms.desc(),
+				dsl::id.asc(),
+			))
+			.limit(limit.try_into().unwrap_or(i64::MAX));
+		let jobs: Vec<JobRow> = match namespace == DEFAULT_NAMESPACE_ID {
+			true => {
+				conn.load_select(
+					query.filter(
+						dsl::subject_branch
+							.eq_any(branches)
+							.or(dsl::subject_branch.is_null()),
+					),
+				)
+				.await?
+			}
+			false => {
+				conn.load_select(query.filter(dsl::subject_branch.eq_any(branches)))
+					.await?
+			}
+		};
+		jobs.into_iter().map(Self::job_info).collect()
+	}
+
+	/// Lists jobs of a branch in the queue, ordered by priority.
+	pub async fn list_branch(&self, branch: BranchRef, limit: usize) -> Result<Vec<JobInfo>> {
+		let mut conn = self.db.get().await?;
+
+		let jobs: Vec<JobRow> = conn
+			.load_select(
+				dsl::job_queue
+					.filter(dsl::subject_branch.eq(branch))
+					.order((
+						dsl::priority.desc(),
+						dsl::estimated_ms.desc(),
+						dsl::id.asc(),
+					))
+					.limit(limit.try_into().unwrap_or(i64::MAX)),
+			)
+			.await?;
+		jobs.into_iter().map(Self::job_info).collect()
+	}
+
+	/// Returns a job in the queue.
+	pub async fn get(&self, id: JobRef) -> Result<Option<JobInfo>> {
+		let mut conn = self.db.get().await?;
+
+		let job: Option<JobRow> = conn
+			.load_one_select(dsl::job_queue.filter(dsl::id.eq(XUuidVal(id))))
+			.await
+			.optional()?;
+		job.map(Self::job_info).transpose()
+	}
+
+	/// Returns a finished job from history.
+	pub async fn get_finished(&self, id: JobRef) -> Result<Option<HistoryJob>> {
+		self.history.get(id).await
+	}
+
+	/// Lists finished jobs from history, latest first.
+	pub async fn list_finished(
+		&self,
+		filter: &HistoryFilter,
+		limit: usize,
+	) -> Result<Vec<HistoryJob>> {
+		self.history.list(filter, limit).await
+	}
+
+	fn job_info(job: JobRow) -> Result<JobInfo> {
+		Ok(JobInfo {
+			id: job.id.0,
+			command: JobCommand::deserialize(&job.kind, job.data.0)?,
+			priority: job.priority as u16,
+			attempts: job.attempts as u16,
+			started_at: job.started_at,
+			estimated_duration: (job.estimated_ms != 0)
+				.then_some(time::Duration::milliseconds(job.estimated_ms)),
+		})
+	}
+
+	/// Returns the count of pending jobs, saturated at `max`.
+	///
+	/// At most `max` rows are scanned, through the `job_queue_pending` index,
+	/// so that watchers can poll the queue cheaply however long it is.
+	pub async fn count_pending(&self, max: usize) -> Result<usize> {
+		if max == 0 {
+			return Ok(0);
+		}
+		let mut conn = self.db.get().await?;
+
+		let pending = conn
+			.load::<_, i16>(
+				dsl::job_queue
+					.filter(dsl::started_at.is_null())
+					.select(dsl::priority)
+					.limit(max.try_into().unwrap_or(i64::MAX)),
+			)
+			.await?;
+		Ok(pending.len())
+	}
+}
+
+#[async_trait]
+impl JobQueueApi for JobQueue {
+	async fn pause(&self) -> Result<()> {
+		JobQueue::pause(self).await
+	}
+
+	async fn resume(&self) -> Result<()> {
+		JobQueue::resume(self).await
+	}
+
+	async fn is_paused(&self) -> Result<bool> {
+		JobQueue::is_paused(self).await
+	}
+
+	async fn depth(&self) -> Result<Vec<JobQueueDepth>> {
+		JobQueue::depth(self).await
+	}
+
+	async fn estimate_backlog(&self, depth: &[JobQueueDepth]) -> Result<time::Duration> {
+		JobQueue::estimate_backlog(self, depth).await
+	}
+
+	async fn stats(&self) -> Result<Vec<JobQueueStats>> {
+		JobQueue::stats(self).await
+	}
+
+	async fn list(&self, namespace: NamespaceRef, limit: usize) -> Result<Vec<JobInfo>> {
+		JobQueue::list(self, namespace, limit).await
+	}
+
+	async fn list_branch(&self, branch: BranchRef, limit: usize) -> Result<Vec<JobInfo>> {
+		JobQueue::list_branch(self, branch, limit).await
+	}
+
+	async fn get(&self, id: JobRef) -> Result<Option<JobInfo>> {
+		JobQueue::get(self, id).await
+	}
+
+	async fn get_finished(&self, id: JobRef) -> Result<Option<HistoryJob>> {
+		JobQueue::get_finished(self, id).await
+	}
+
+	async fn list_finished(&self, filter: &HistoryFilter, limit: usize) -> Result<Vec<HistoryJob>> {
+		JobQueue::list_finished(self, filter, limit).await
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum JobQueueError {
+	#[error("job {0} has been aborted")]
+	JobAborted(JobRef),
+	#[error("branch {0} has too many queued jobs")]
+	QuotaExceeded(BranchRef),
+	#[error("namespace {0} has too many queued jobs")]
+	NamespaceQuotaExceeded(NamespaceRef),
+}
+
+#[cfg(test)]
+mod test {
+	use diesel::{ExpressionMethods, QueryDsl, update};
+	use time::{OffsetDateTime, PrimitiveDateTime};
+	use uuid::Uuid;
+
+	use crate::{
+		BackendError,
+		branch::BranchConfigInfo,
+		db::{
+			schema::{branch::dsl as branch_dsl, job_queue::dsl},
+			service::DatabaseService,
+			utils::XUuidVal,
+		},
+		job_queue::{
+			FailureClass, FailureOutcome, JobCommand, JobError, JobInfo, JobQueueBackend,
+			JobQueueDepth, JobQueueError, JobQueueStats, KindFilter, SchedulingMode,
+		},
+		namespace::{DEFAULT_NAMESPACE_ID, NamespaceConfigInfo},
+		test::{test_config, test_env, test_env_pg, test_env_with_config},
+		trace::TraceContext,
+	};
+
+	/// Makes jobs requeued with backoff claimable immediately.
+	async fn skip_backoff(database: &DatabaseService) {
+		database
+			.get()
+			.await
+			.unwrap()
+			.execute(update(dsl::job_queue).set(dsl::scheduled_at.eq(None::<PrimitiveDateTime>)))
+			.await
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_enqueue() {
+		let env = test_env().await;
+		let mut db = env.database.get().await.unwrap();
+		env.job_queue
+			.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_list_branch() {
+		let env = test_env().await;
+		let mut db = env.database.get().await.unwrap();
+		let package = Uuid::now_v7();
+		env.job_queue
+			.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		let id = env
+			.job_queue
+			.enqueue(&mut db, JobCommand::LintPackage { branch: 2, package })
+			.await
+			.unwrap();
+
+		let jobs = env.job_queue.list_branch(2, 10).await.unwrap();
+		assert_eq!(jobs.len(), 1);
+		assert_eq!(jobs[0].id, id);
+		let subject = db
+			.get_result::<_, Option<XUuidVal>>(
+				dsl::job_queue
+					.filter(dsl::id.eq(XUuidVal(id)))
+					.select(dsl::subject_pkg),
+			)
+			.await
+			.unwrap();
+		assert_eq!(subject, Some(XUuidVal(package)));
+	}
+
+	#[tokio::test]
+	async fn test_enqueue_fetch() {
+		let env = test_env().await;
+		let mut db = env.database.get().await.unwrap();
+		let jq = env.job_queue;
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		jq.enqueue_with_priority(&mut db, JobCommand::SyncBranch(2), 120)
+			.await
+			.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(3))
+			.await
+			.unwrap();
+		drop(db);
+		assert_eq!(
+			jq.fetch_and_start().await.unwrap().unwrap().command,
+			JobCommand::SyncBranch(2)
+		);
+		assert_eq!(
+			jq.fetch_and_start().await.unwrap().unwrap().command,
+			JobCommand::SyncBranch(1)
+		);
+		assert_eq!(
+			jq.fetch_and_start().await.unwrap().unwrap().command,
+			JobCommand::SyncBranch(3)
+		);
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_enqueue_fetch_fair() {
+		let mut config = test_config();
+		config.job_queue.scheduling = SchedulingMode::Fair;
+		let env = test_env_with_config(config).await;
+		let mut db = env.database.get().await.unwrap();
+		let jq = env.job_queue;
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(2))
+			.await
+			.unwrap();
+		drop(db);
+		assert_eq!(
+			jq.fetch_and_start().await.unwrap().unwrap().command,
+			JobCommand::SyncBranch(1)
+		);
+		assert_eq!(
+			jq.fetch_and_start().await.unwrap().unwrap().command,
+			JobCommand::SyncBranch(2)
+		);
+		assert_eq!(
+			jq.fetch_and_start().await.unwrap().unwrap().command,
+			JobCommand::SyncBranch(1)
+		);
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_enqueue_fetch_fair_weighted() {
+		let mut config = test_config();
+		config.job_queue.scheduling = SchedulingMode::Fair;
+		let env = test_env_with_config(config).await;
+		let mut db = env.database.get().await.unwrap();
+		let jq = env.job_queue;
+		for (branch, priority) in [(1, 100), (2, 101), (3, 250)] {
+			let count = if branch == 3 { 1 } else { 3 };
+			for _ in 0..count {
+				jq.enqueue_with_priority(&mut db, JobCommand::SyncBranch(branch), priority)
+					.await
+					.unwrap();
+			}
+		}
+		drop(db);
+
+		let mut branches = vec![];
+		while let Some(job) = jq.fetch_and_start().await.unwrap() {
+			let JobCommand::SyncBranch(branch) = job.command else {
+				panic!("unexpected job {:?}", job.command);
+			};
+			branches.push(branch);
+		}
+		// the highest band first, and then branch 2 is served twice as often
+		assert_eq!(branches, [3, 2, 1, 2, 2, 1, 1]);
+	}
+
+	#[tokio::test]
+	async fn test_enqueue_fetch_stream() {
+		let mut config = test_config();
+		config.job_queue.backend = JobQueueBackend::RedisStreams;
+		let env = test_env_with_config(config).await;
+		let mut db = env.database.get().await.unwrap();
+		let jq = env.job_queue;
+		let id = jq
+			.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+		assert_eq!(jq.fetch_and_start().await.unwrap().unwrap().id, id);
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_fetch_many() {
+		let env = test_env().await;
+		let mut db = env.database.get().await.unwrap();
+		let jq = env.job_queue;
+		for branch in 1..=3 {
+			jq.enqueue(&mut db, JobCommand::SyncBranch(branch))
+				.await
+				.unwrap();
+		}
+		drop(db);
+
+		let jobs = jq.fetch_and_start_many(2, &KindFilter::Any).await.unwrap();
+		assert_eq!(jobs.len(), 2);
+		let jobs = jq.fetch_and_start_many(2, &KindFilter::Any).await.unwrap();
+		assert_eq!(jobs.len(), 1);
+		assert!(
+			jq.fetch_and_start_many(2, &KindFilter::Any)
+				.await
+				.unwrap()
+				.is_empty()
+		);
+	}
+
+	#[tokio::test]
+	async fn test_fetch_kinds() {
+		let env = test_env().await;
+		let mut db = env.database.get().await.unwrap();
+		let jq = env.job_queue;
+		let sync = jq
+			.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		let upstream = jq
+			.enqueue(&mut db, JobCommand::RefreshUpstream)
+			.await
+			.unwrap();
+		drop(db);
+
+		let only_upstream = KindFilter::Only(vec!["RefreshUpstream".to_string()]);
+		let jobs = jq.fetch_and_start_many(2, &only_upstream).await.unwrap();
+		assert_eq!(jobs.len(), 1);
+		assert_eq!(jobs[0].id, upstream);
+		let except_sync = KindFilter::Except(vec!["SyncBranch".to_string()]);
+		assert!(
+			jq.fetch_and_start_with_kinds(&except_sync)
+				.await
+				.unwrap()
+				.is_none()
+		);
+		assert_eq!(jq.fetch_and_start().await.unwrap().unwrap().id, sync);
+	}
+
+	#[tokio::test]
+	async fn test_fetch_concurrent_pg() {
+		let env = test_env_pg().await;
+		let mut db = env.database.get().await.unwrap();
+		let jq = env.job_queue;
+		for branch in 1..=2 {
+			jq.enqueue(&mut db, JobCommand::SyncBranch(branch))
+				.await
+				.unwrap();
+		}
+		drop(db);
+
+		let (a, b) = tokio::join!(jq.fetch_and_start(), jq.fetch_and_start());
+		let (a, b) = (a.unwrap().unwrap(), b.unwrap().unwrap());
+		assert_ne!(a.id, b.id);
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_timeout() {
+		let mut config = test_config();
+		config
+			.job_queue
+			.timeouts
+			.insert("SyncBranch".to_string(), 10);
+		assert_eq!(
+			config.job_queue.timeout("SyncBranch"),
+			Some(std::time::Duration::from_secs(10))
+		);
+		assert_eq!(config.job_queue.timeout("Other"), None);
+		let env = test_env_with_config(config).await;
+		let jq = env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+		let id = jq.fetch_and_start().await.unwrap().unwrap().id;
+		let mut db = env.database.get().await.unwrap();
+		assert_eq!(
+			jq.fail_job(
+				&mut db,
+				id,
+				&JobError::new(FailureClass::Timeout, "job timed out after 10s")
+			)
+			.await
+			.unwrap(),
+			FailureOutcome::Requeued
+		);
+		drop(db);
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+		skip_backoff(&env.database).await;
+		assert_eq!(jq.fetch_and_start().await.unwrap().unwrap().id, id);
+	}
+
+	#[tokio::test]
+	async fn test_trace() {
+		let env = test_env().await;
+		let jq = env.job_queue;
+		let trace = TraceContext::new_root();
+
+		let mut db = env.database.get().await.unwrap();
+		trace
+			.scope(jq.enqueue(&mut db, JobCommand::SyncBranch(1)))
+			.await
+			.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(2))
+			.await
+			.unwrap();
+		drop(db);
+
+		let job = jq.fetch_and_start().await.unwrap().unwrap();
+		assert_eq!(job.trace, Some(trace));
+		let job = jq.fetch_and_start().await.unwrap().unwrap();
+		assert_eq!(job.trace, None);
+	}
+
+	#[tokio::test]
+	async fn test_finish() {
+		let env = test_env().await;
+		let jq = env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+
+		let id = jq.fetch_and_start().await.unwrap().unwrap().id;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.finish_job(&mut db, id).await.unwrap();
+		assert_eq!(
+			db.get_result::<_, i64>(dsl::job_queue.count())
+				.await
+				.unwrap(),
+			0
+		);
+		drop(db);
+
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_release() {
+		let env = test_env().await;
+		let jq = env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+
+		let id = jq.fetch_and_start().await.unwrap().unwrap().id;
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+
+		let mut db = env.database.get().await.unwrap();
+		assert!(jq.release_job(&mut db, id).await.unwrap());
+		// already pending
+		assert!(!jq.release_job(&mut db, id).await.unwrap());
+		drop(db);
+
+		assert_eq!(jq.fetch_and_start().await.unwrap().unwrap().id, id);
+		let mut db = env.database.get().await.unwrap();
+		let attempts = db
+			.get_result::<_, i16>(dsl::job_queue.select(dsl::attempts))
+			.await
+			.unwrap();
+		assert_eq!(attempts, 0);
+	}
+
+	#[tokio::test]
+	async fn test_fail_transient() {
+		let env = test_env().await;
+		let jq = env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+
+		for attempt in 0..3 {
+			let id = jq.fetch_and_start().await.unwrap().unwrap().id;
+			let mut db = env.database.get().await.unwrap();
+			assert_eq!(
+				jq.fail_job(
+					&mut db,
+					id,
+					&JobError::new(FailureClass::Transient, "timed out")
+				)
+				.await
+				.unwrap(),
+				FailureOutcome::Requeued
+			);
+
+			// backed off exponentially, jittered down to a half
+			let scheduled_at = db
+				.get_result::<_, Option<PrimitiveDateTime>>(
+					dsl::job_queue.select(dsl::scheduled_at),
+				)
+				.await
+				.unwrap()
+				.unwrap();
+			let now = OffsetDateTime::now_utc();
+			let backoff = scheduled_at - PrimitiveDateTime::new(now.date(), now.time());
+			let max = 30 << attempt;
+			assert!(backoff <= time::Duration::seconds(max));
+			assert!(backoff >= time::Duration::seconds(max / 2 - 1));
+			drop(db);
+			assert!(jq.fetch_and_start().await.unwrap().is_none());
+			skip_backoff(&env.database).await;
+		}
+
+		// retry budget has been used up
+		let id = jq.fetch_and_start().await.unwrap().unwrap().id;
+		let mut db = env.database.get().await.unwrap();
+		assert_eq!(
+			jq.fail_job(
+				&mut db,
+				id,
+				&JobError::new(FailureClass::Transient, "timed out")
+			)
+			.await
+			.unwrap(),
+			FailureOutcome::Dropped
+		);
+		drop(db);
+
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_fail_permanent() {
+		let env = test_env().await;
+		let jq = env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+
+		let id = jq.fetch_and_start().await.unwrap().unwrap().id;
+		let mut db = env.database.get().await.unwrap();
+		assert_eq!(
+			jq.fail_job(
+				&mut db,
+				id,
+				&JobError::new(FailureClass::Permanent, "build failed")
+			)
+			.await
+			.unwrap(),
+			FailureOutcome::Dropped
+		);
+		drop(db);
+
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_queued_quota() {
+		let env = test_env().await;
+		let info = BranchConfigInfo {
+			max_queued_jobs: Some(1),
+			..Default::default()
+		};
+		// enqueues the first sync job
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", info)
+			.await
+			.unwrap();
+
+		let mut db = env.database.get().await.unwrap();
+		assert!(matches!(
+			env.job_queue
+				.enqueue(&mut db, JobCommand::SyncBranch(1))
+				.await,
+			Err(BackendError::JobQueueError(JobQueueError::QuotaExceeded(1)))
+		));
+	}
+
+	#[tokio::test]
+	async fn test_namespace_queued_quota() {
+		let env = test_env().await;
+		let info = NamespaceConfigInfo {
+			max_queued_jobs: Some(1),
+			..Default::default()
+		};
+		let namespace = env.namespace.create("team", info).await.unwrap().id;
+		// enqueues the first sync job
+		env.branch
+			.track(namespace, "test", BranchConfigInfo::default())
+			.await
+			.unwrap();
+		let id = env
+			.branch
+			.find_id(namespace, "test")
+			.await
+			.unwrap()
+			.unwrap();
+
+		let mut db = env.database.get().await.unwrap();
+		assert!(matches!(
+			env.job_queue
+				.enqueue(&mut db, JobCommand::SyncBranch(id))
+				.await,
+			Err(BackendError::JobQueueError(
+				JobQueueError::NamespaceQuotaExceeded(_)
+			))
+		));
+		// other namespaces are not affected
+		drop(db);
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", BranchConfigInfo::default())
+			.await
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_list_namespace() {
+		let env = test_env().await;
+		let namespace = env
+			.namespace
+			.create("team", NamespaceConfigInfo::default())
+			.await
+			.unwrap()
+			.id;
+		// enqueues the first sync job
+		env.branch
+			.track(namespace, "test", BranchConfigInfo::default())
+			.await
+			.unwrap();
+		let branch = env
+			.branch
+			.find_id(namespace, "test")
+			.await
+			.unwrap()
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+		env.job_queue
+			.enqueue(&mut db, JobCommand::IngestAdvisories)
+			.await
+			.unwrap();
+		drop(db);
+
+		let branches = |jobs: Vec<JobInfo>| {
+			jobs.into_iter()
+				.map(|job| job.command.subject_branch())
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			branches(env.job_queue.list(namespace, 10).await.unwrap()),
+			[Some(branch)]
+		);
+		assert_eq!(
+			branches(env.job_queue.list(DEFAULT_NAMESPACE_ID, 10).await.unwrap()),
+			[None]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_running_quota() {
+		let env = test_env().await;
+		let jq = &env.job_queue;
+		let info = BranchConfigInfo {
+			max_running_jobs: Some(1),
+			..Default::default()
+		};
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", info)
+			.await
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+
+		let id = jq.fetch_and_start().await.unwrap().unwrap().id;
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+
+		let mut db = env.database.get().await.unwrap();
+		jq.finish_job(&mut db, id).await.unwrap();
+		drop(db);
+		assert!(jq.fetch_and_start().await.unwrap().is_some());
+	}
+
+	#[tokio::test]
+	async fn test_running_quota_zero() {
+		let env = test_env().await;
+		let jq = &env.job_queue;
+		// enqueues the first sync job
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", Default::default())
+			.await
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+		db.execute(update(branch_dsl::branch).set(branch_dsl::max_running_jobs.eq(0)))
+			.await
+			.unwrap();
+		drop(db);
+
+		// saturated without any running jobs
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_running_quota_stream() {
+		let mut config = test_config();
+		config.job_queue.backend = JobQueueBackend::RedisStreams;
+		let env = test_env_with_config(config).await;
+		let jq = &env.job_queue;
+		let info = BranchConfigInfo {
+			max_running_jobs: Some(1),
+			..Default::default()
+		};
+		// enqueues the first sync job
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", info)
+			.await
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+		let second = jq
+			.enqueue(
+				&mut db,
+				JobCommand::LintPackage {
+					branch: 1,
+					package: Uuid::now_v7(),
+				},
+			)
+			.await
+			.unwrap();
+		drop(db);
+
+		let first = jq.fetch_and_start().await.unwrap().unwrap().id;
+		assert_ne!(first, second);
+		// the dispatched job is requeued until the quota is released
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+
+		let mut db = env.database.get().await.unwrap();
+		jq.finish_job(&mut db, first).await.unwrap();
+		drop(db);
+		assert_eq!(jq.fetch_and_start().await.unwrap().unwrap().id, second);
+	}
+
+	#[tokio::test]
+	async fn test_target_arch() {
+		let mut config = test_config();
+		config.job_queue.arches = Some(vec!["x86_64".to_string()]);
+		config
+			.job_queue
+			.arch_max_running_jobs
+			.insert("x86_64".to_string(), 1);
+		let env = test_env_with_config(config).await;
+		let jq = &env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		let mut ids = vec![];
+		for (branch, arch) in [(1, "riscv64"), (2, "x86_64"), (3, "x86_64")] {
+			let id = jq
+				.enqueue(&mut db, JobCommand::SyncBranch(branch))
+				.await
+				.unwrap();
+			db.execute(
+				update(dsl::job_queue)
+					.filter(dsl::id.eq(XUuidVal(id)))
+					.set(dsl::target_arch.eq(arch)),
+			)
+			.await
+			.unwrap();
+			ids.push(id);
+		}
+		drop(db);
+
+		// riscv64 is not capable, and then x86_64 is capped
+		let job = jq.fetch_and_start().await.unwrap().unwrap();
+		assert_eq!(job.id, ids[1]);
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+
+		let mut db = env.database.get().await.unwrap();
+		jq.finish_job(&mut db, job.id).await.unwrap();
+		drop(db);
+		assert_eq!(jq.fetch_and_start().await.unwrap().unwrap().id, ids[2]);
+		assert!(
+			jq.fetch_and_start_many(2, &KindFilter::Any)
+				.await
+				.unwrap()
+				.is_empty()
+		);
+	}
+
+	#[tokio::test]
+	async fn test_pause() {
+		let env = test_env().await;
+		let jq = env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		drop(db);
+
+		jq.pause().await.unwrap();
+		assert!(jq.is_paused().await.unwrap());
+		assert!(jq.fetch_and_start().await.unwrap().is_none());
+		jq.resume().await.unwrap();
+		assert!(!jq.is_paused().await.unwrap());
+		assert!(jq.fetch_and_start().await.unwrap().is_some());
+	}
+
+	#[tokio::test]
+	async fn test_depth() {
+		let env = test_env().await;
+		let jq = env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(1))
+			.await
+			.unwrap();
+		jq.enqueue(&mut db, JobCommand::SyncBranch(2))
+			.await
+			.unwrap();
+		drop(db);
+		jq.fetch_and_start().await.unwrap().unwrap();
+
+		let depth = jq.depth().await.unwrap();
+		assert_eq!(
+			depth,
+			vec![JobQueueDepth {
+				kind: "SyncBranch".into(),
+				pending: 1,
+				running: 1,
+			}]
+		);
+		assert_eq!(
+			jq.estimate_backlog(&depth).await.unwrap(),
+			time::Duration::minutes(1)
+		);
+	}
+
+	#[tokio::test]
+	async fn test_count_pending() {
+		let env = test_env().await;
+		let jq = env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		for branch in 1..=3 {
+			jq.enqueue(&mut db, JobCommand::SyncBranch(branch))
+				.await
+				.unwrap();
+		}
+		drop(db);
+		jq.fetch_and_start().await.unwrap().unwrap();
+
+		assert_eq!(jq.count_pending(0).await.unwrap(), 0);
+		assert_eq!(jq.count_pending(1).await.unwrap(), 1);
+		assert_eq!(jq.count_pending(10).await.unwrap(), 2);
+	}
+
+	#[tokio::test]
+	async fn test_stats() {
+		let env = test_env().await;
+		let jq = env.job_queue;
+
+		let mut db = env.database.get().await.unwrap();
+		jq.enqueue_with_priority(&mut db, JobCommand::SyncBranch(1), 250)
+			.await
+			.unwrap();
+		jq.enqueue_with_priority(&mut db, JobCommand::SyncBranch(1), 120)
+			.await
+			.unwrap();
+		jq.enqueue_with_priority(&mut db, JobCommand::SyncBranch(1), 110)
+			.await
+			.unwrap();
+		jq.enqueue(&mut db, JobCommand::IngestAdvisories)
+			.await
+			.unwrap();
+		drop(db);
+		jq.fetch_and_start().await.unwrap().unwrap();
+
+		let stats = jq.stats().await.unwrap();
+		assert_eq!(
+			stats,
+			vec![
+				JobQueueStats {
+					kind: "IngestAdvisories".into(),
+					priority_band: 100,
+					branch: None,
+					pending: 1,
+					running: 0,
+				},
+				JobQueueStats {
+					kind: "SyncBranch".into(),
+					priority_band: 100,
+					branch: Some(1),
+					pending: 2,
+					running: 0,
+				},
+				JobQueueStats {
+					kind: "SyncBranch".into(),
+					priority_band: 200,
+					branch: Some(1),
+					pending: 0,
+					running: 1,
+				},
+			]
+		);
+	}
+}
@@ -0,0 +1,189 @@
+//! Redis Streams dispatcher of the job queue.
+
+use std::sync::Arc;
+
+use futures::{FutureExt, future::BoxFuture};
+use redis::{
+	AsyncCommands,
+	streams::{
+		StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamReadOptions, StreamReadReply,
+	},
+};
+use tokio::sync::OnceCell;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	redis::{RedisError, RedisService},
+};
+
+use super::{DispatchedJob, JobDispatcher, JobRef};
+
+/// Redis stream key of enqueued jobs.
+pub const JOB_STREAM_KEY: &str = "job-queue:stream";
+
+/// Consumer group of job runners.
+const JOB_STREAM_GROUP: &str = "job-runners";
+
+/// Entries delivered but not acknowledged for this duration are claimed
+/// by other consumers, as their consumers may be dead.
+const CLAIM_IDLE_MS: usize = 5 * 60 * 1000;
+
+/// Pending entries of jobs missing in the queue table after this duration
+/// are dropped, as their enqueuing transactions may have been rolled back.
+const STALE_ENTRY_MS: u64 = 30 * 60 * 1000;
+
+/// Dispatcher with Redis Streams.
+///
+/// Jobs are added to a stream on enqueuing, and delivered to runners through
+/// a consumer group. Entries are acknowledged after the job is claimed in the queue table,
+/// so jobs delivered to dead runners are claimed by others after [`CLAIM_IDLE_MS`].
+///
+/// Jobs are dispatched in the order of enqueuing, ignoring priorities and
+/// scheduling modes. Jobs excluded by running jobs quotas, or by capabilities
+/// of runners, are added to the stream again.
+#[derive(Debug)]
+pub struct StreamDispatcher {
+	redis: Arc<RedisService>,
+	consumer: String,
+	group: OnceCell<()>,
+}
+
+impl StreamDispatcher {
+	pub fn new(redis: Arc<RedisService>) -> Self {
+		Self {
+			redis,
+			consumer: Uuid::now_v7().to_string(),
+			group: OnceCell::new(),
+		}
+	}
+
+	async fn ensure_group(&self) -> Result<()> {
+		self.group
+			.get_or_try_init(async || {
+				let result: redis::RedisResult<()> = self
+					.redis
+					.get()
+					.await?
+					.xgroup_create_mkstream(self.redis.key(JOB_STREAM_KEY), JOB_STREAM_GROUP, "0")
+					.await;
+				match result {
+					Ok(()) => info!("created job stream consumer group"),
+					Err(error) if error.code() == Some("BUSYGROUP") => {}
+					Err(error) => return Err(RedisError::RedisError(error).into()),
+				}
+				Ok::<_, crate::BackendError>(())
+			})
+			.await?;
+		Ok(())
+	}
+
+	async fn ack_entry(&self, entry: &str) -> Result<()> {
+		let _: usize = self
+			.redis
+			.get()
+			.await?
+			.xack(self.redis.key(JOB_STREAM_KEY), JOB_STREAM_GROUP, &[entry])
+			.await
+			.map_err(RedisError::RedisError)?;
+		Ok(())
+	}
+
+	async fn next_entry(&self) -> Result<Option<StreamId>> {
+		self.ensure_group().await?;
+		let mut conn = self.redis.get().await?;
+
+		let claimed: StreamAutoClaimReply = conn
+			.xautoclaim_options(
+				self.redis.key(JOB_STREAM_KEY),
+				JOB_STREAM_GROUP,
+				&self.consumer,
+				CLAIM_IDLE_MS,
+				"0-0",
+				StreamAutoClaimOptions::default().count(1),
+			)
+			.await
+			.map_err(RedisError::RedisError)?;
+		if let Some(entry) = claimed.claimed.into_iter().next() {
+			debug!(entry = entry.id, "claimed idle job stream entry");
+			return Ok(Some(entry));
+		}
+
+		let reply: Option<StreamReadReply> = conn
+			.xread_options(
+				&[self.redis.key(JOB_STREAM_KEY)],
+				&[">"],
+				&StreamReadOptions::default()
+					.group(JOB_STREAM_GROUP, &self.consumer)
+					.count(1),
+			)
+			.await
+			.map_err(RedisError::RedisError)?;
+		Ok(reply.and_then(|reply| reply.keys.into_iter().flat_map(|key| key.ids).next()))
+	}
+}
+
+impl JobDispatcher for StreamDispatcher {
+	fn enqueued(&self, id: JobRef) -> BoxFuture<'_, Result<()>> {
+		async move {
+			let _: String = self
+				.redis
+				.get()
+				.await?
+				.xadd(
+					self.redis.key(JOB_STREAM_KEY),
+					"*",
+					&[("job", id.to_string())],
+				)
+				.await
+				.map_err(RedisError::RedisError)?;
+			Ok(())
+		}
+		.boxed()
+	}
+
+	fn next(&self) -> BoxFuture<'_, Result<Option<DispatchedJob>>> {
+		async move {
+			loop {
+				let Some(entry) = self.next_entry().await? else {
+					return Ok(None);
+				};
+				match entry
+					.get::<String>("job")
+					.and_then(|id| Uuid::parse_str(&id).ok())
+				{
+					Some(id) => {
+						let enqueued_at = entry
+							.id
+							.split_once('-')
+							.and_then(|(ms, _)| ms.parse().ok())
+							.unwrap_or_default();
+						return Ok(Some(DispatchedJob {
+							id,
+							entry: entry.id,
+							enqueued_at,
+						}));
+					}
+					None => {
+						warn!(entry = entry.id, "dropped malformed job stream entry");
+						self.ack_entry(&entry.id).await?;
+					}
+				}
+			}
+		}
+		.boxed()
+	}
+
+	fn ack<'a>(&'a self, job: &'a DispatchedJob) -> BoxFuture<'a, Result<()>> {
+		self.ack_entry(&job.entry).boxed()
+	}
+
+	fn is_stale(&self, job: &DispatchedJob) -> bool {
+		let now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_millis() as u64;
+		now.saturating_sub(job.enqueued_at) > STALE_ENTRY_MS
+	}
+}
@@ -2,23 +2,83 @@
 
 use std::sync::Arc;
 
+use admin_token::{AdminTokenError, AdminTokenService};
+use artifact_diff::{ArtifactDiffError, ArtifactDiffService};
+use backup::{BackupError, BackupService};
 use branch::{BranchError, BranchService};
-use bus::{BackendBusFactory, BoxedBusService};
+use branch_acl::BranchAclService;
+use branch_scan::{BranchScanError, BranchScanService};
+use branch_template::BranchTemplateService;
+use build_cache::BuildCacheService;
+use builder::{BuilderError, BuilderService};
+use bus::{BackendBusFactory, BoxedBusService, BusKind, memory::MemoryBusService};
 use config::BackendConfig;
-use db::service::{DatabaseError, DatabaseService};
-use job_queue::{JobQueue, JobQueueError};
+use db::{
+	RetryableError,
+	service::{DatabaseError, DatabaseService},
+};
+use deadpool::managed::PoolError;
+use evaluator::{EvaluatorError, EvaluatorService};
+use instance::InstanceRegistry;
+use job_history::JobHistoryService;
+use job_queue::{FailureClass, JobQueue, JobQueueError};
+use lint::LintService;
+use lock::LockService;
+use maintenance::MaintenanceService;
+use mirror::{MirrorError, MirrorService};
+use namespace::{NamespaceError, NamespaceService};
+use operation::OperationService;
+use package::{PackageError, PackageService};
 use redis::{RedisError, RedisService};
-use target::TargetService;
+use repository::RepositoryService;
+use repro::{ReproError, ReproService};
+use security::{SecurityError, SecurityService};
+use target::{TargetError, TargetService};
 use thiserror::Error;
+use tracing::warn;
+use upstream::UpstreamService;
+use webhook::WebhookVerifier;
 
+pub mod admin_token;
+pub mod artifact_diff;
+pub mod audit;
+pub mod backup;
+pub mod bootstrap;
 pub mod branch;
+pub mod branch_acl;
+pub mod branch_compare;
+pub mod branch_scan;
+pub mod branch_template;
+pub mod build_cache;
+pub mod builder;
 pub mod bus;
+pub mod changelog;
 pub mod config;
 pub mod db;
+pub mod evaluator;
+pub mod instance;
+pub mod job_history;
 pub mod job_queue;
+pub mod lint;
+pub mod lock;
+pub mod maintenance;
+pub mod mirror;
+pub mod model;
+pub mod namespace;
+pub mod operation;
 pub mod package;
+pub mod preflight;
+pub mod problem;
 pub mod redis;
+pub mod repository;
+pub mod repro;
+pub mod secrets;
+pub mod security;
 pub mod target;
+pub mod target_status;
+pub mod trace;
+pub mod upstream;
+pub mod webhook;
 
 /// Service container for Fabricia backends.
 ///
@@ -27,11 +87,35 @@ pub mod target;
 pub struct BackendServices {
 	pub config: Arc<BackendConfig>,
 	pub target: Arc<TargetService>,
-	pub redis: Arc<RedisService>,
+	/// Redis service, or [`None`] in single-node mode without Redis.
+	pub redis: Option<Arc<RedisService>>,
+	pub lock: Arc<LockService>,
 	pub database: Arc<DatabaseService>,
+	pub repository: Arc<RepositoryService>,
 	pub bus: Arc<BoxedBusService>,
+	pub instance: Arc<InstanceRegistry>,
+	pub job_history: Arc<JobHistoryService>,
 	pub job_queue: Arc<JobQueue>,
+	pub operation: Arc<OperationService>,
+	pub namespace: Arc<NamespaceService>,
 	pub branch: Arc<BranchService>,
+	pub package: Arc<PackageService>,
+	pub evaluator: Arc<EvaluatorService>,
+	pub build_cache: Arc<BuildCacheService>,
+	pub builder: Arc<BuilderService>,
+	pub lint: Arc<LintService>,
+	pub security: Arc<SecurityService>,
+	pub upstream: Arc<UpstreamService>,
+	pub mirror: Arc<MirrorService>,
+	pub repro: Arc<ReproService>,
+	pub artifact_diff: Arc<ArtifactDiffService>,
+	pub backup: Arc<BackupService>,
+	pub admin_token: Arc<AdminTokenService>,
+	pub branch_acl: Arc<BranchAclService>,
+	pub branch_scan: Arc<BranchScanService>,
+	pub branch_template: Arc<BranchTemplateService>,
+	pub webhook: Arc<WebhookVerifier>,
+	pub maintenance: Arc<MaintenanceService>,
 }
 
 impl BackendServices {
@@ -41,20 +125,131 @@ impl BackendServices {
 		Bus: BackendBusFactory,
 	{
 		let config = Arc::new(config);
-		let target = Arc::new(TargetService::new(&config.target)?);
-		let redis = Arc::new(RedisService::new(&config.redis).await?);
-		let database = Arc::new(DatabaseService::new(&config.database, &redis).await?);
-		let bus = Arc::new(bus.construct(redis.clone()).await?);
-		let job_queue = Arc::new(JobQueue::new(database.clone()));
-		let branch = Arc::new(BranchService::new(database.clone(), job_queue.clone()));
+		let target = Arc::new(TargetService::new(&config.target, &config.target_group)?);
+		let redis = match &config.redis {
+			Some(redis) => Some(Arc::new(RedisService::new(redis).await?)),
+			None => {
+				warn!("Redis is not configured, running in single-node mode");
+				None
+			}
+		};
+		let lock = Arc::new(LockService::new(redis.clone()));
+		let database = Arc::new(DatabaseService::new(&config.database, &lock).await?);
+		let repository = Arc::new(RepositoryService::new(&database, &config.repository).await?);
+		let bus = Arc::new(match (config.bus.kind, &redis) {
+			(BusKind::Redis, Some(redis)) => bus.construct(redis.clone()).await?,
+			(BusKind::Redis, None) | (BusKind::Memory, _) => Box::new(MemoryBusService::new()),
+		});
+		let instance = Arc::new(InstanceRegistry::new(redis.clone(), database.clone()));
+		let job_history = Arc::new(JobHistoryService::new(database.clone()));
+		let job_queue = Arc::new(JobQueue::new(
+			database.clone(),
+			redis.clone(),
+			job_history.clone(),
+			&config.job_queue,
+		));
+		let operation = Arc::new(OperationService::new(database.clone()));
+		let namespace = Arc::new(NamespaceService::new(database.clone()));
+		let branch = Arc::new(BranchService::new(
+			database.clone(),
+			target.clone(),
+			repository.clone(),
+			job_queue.clone(),
+			operation.clone(),
+			bus.clone(),
+		));
+		let package = Arc::new(PackageService::new(database.clone(), target.clone()));
+		let evaluator = Arc::new(EvaluatorService::new(
+			repository.clone(),
+			config.evaluator.as_ref(),
+		));
+		let build_cache = Arc::new(BuildCacheService::new(
+			database.clone(),
+			target.clone(),
+			job_queue.clone(),
+		));
+		let builder = Arc::new(BuilderService::new(
+			database.clone(),
+			target.clone(),
+			repository.clone(),
+			config.builder.as_ref(),
+		));
+		let lint = Arc::new(LintService::new(
+			database.clone(),
+			job_queue.clone(),
+			&config.lint,
+		));
+		let security = Arc::new(SecurityService::new(database.clone(), &config.security));
+		let upstream = Arc::new(UpstreamService::new(
+			database.clone(),
+			job_queue.clone(),
+			config.upstream.as_ref(),
+		));
+		let mirror = Arc::new(MirrorService::new(
+			database.clone(),
+			job_queue.clone(),
+			config.mirror.as_ref(),
+		));
+		let repro = Arc::new(ReproService::new(
+			database.clone(),
+			job_queue.clone(),
+			target.clone(),
+			config.repro.as_ref(),
+		));
+		let artifact_diff = Arc::new(ArtifactDiffService::new(
+			database.clone(),
+			job_queue.clone(),
+			config.artifact_store.as_ref(),
+		));
+		let backup = Arc::new(BackupService::new(
+			config.database.clone(),
+			database.clone(),
+			job_queue.clone(),
+		));
+		let admin_token = Arc::new(AdminTokenService::new(database.clone()));
+		let branch_acl = Arc::new(BranchAclService::new(database.clone()));
+		let branch_scan = Arc::new(BranchScanService::new(
+			database.clone(),
+			job_queue.clone(),
+			branch.clone(),
+			repository.clone(),
+			config.branch_scan.as_ref(),
+		));
+		let branch_template =
+			Arc::new(BranchTemplateService::new(database.clone(), target.clone()));
+		let webhook = Arc::new(WebhookVerifier::new(redis.clone()));
+		let maintenance = Arc::new(MaintenanceService::new(redis.clone(), database.clone()));
 		let services = Self {
 			config,
 			target,
 			redis,
+			lock,
 			database,
+			repository,
 			bus,
+			instance,
+			job_history,
 			job_queue,
+			operation,
+			namespace,
 			branch,
+			package,
+			evaluator,
+			build_cache,
+			builder,
+			lint,
+			security,
+			upstream,
+			mirror,
+			repro,
+			artifact_diff,
+			backup,
+			admin_token,
+			branch_acl,
+			branch_scan,
+			branch_template,
+			webhook,
+			maintenance,
 		};
 
 		Ok(services)
@@ -74,82 +269,312 @@ pub enum BackendError {
 	JobQueueError(#[from] JobQueueError),
 	#[error(transparent)]
 	BranchError(#[from] BranchError),
+	#[error(transparent)]
+	PackageError(#[from] PackageError),
+	#[error(transparent)]
+	NamespaceError(#[from] NamespaceError),
+	#[error(transparent)]
+	TargetError(#[from] TargetError),
+	#[error(transparent)]
+	BackupError(#[from] BackupError),
+	#[error(transparent)]
+	SecurityError(#[from] SecurityError),
+	#[error(transparent)]
+	AdminTokenError(#[from] AdminTokenError),
+	#[error(transparent)]
+	MirrorError(#[from] MirrorError),
+	#[error(transparent)]
+	EvaluatorError(#[from] EvaluatorError),
+	#[error(transparent)]
+	BuilderError(#[from] BuilderError),
+	#[error(transparent)]
+	ReproError(#[from] ReproError),
+	#[error(transparent)]
+	ArtifactDiffError(#[from] ArtifactDiffError),
+	#[error(transparent)]
+	BranchScanError(#[from] BranchScanError),
 }
 
 /// A specialized [`Result`] for backend errors.
 pub type Result<T, E = BackendError> = std::result::Result<T, E>;
 
+impl BackendError {
+	/// Classifies this error when it fails a job.
+	///
+	/// Errors raised by the infrastructure (connections, pools and locks)
+	/// are considered as transient.
+	pub fn failure_class(&self) -> FailureClass {
+		match self {
+			BackendError::DatabaseError(
+				DatabaseError::ConnectionError(_)
+				| DatabaseError::PoolError(_)
+				| DatabaseError::JoinError(_),
+			) => FailureClass::Transient,
+			BackendError::DatabaseError(DatabaseError::QueryError(
+				diesel::result::Error::DatabaseError(
+					diesel::result::DatabaseErrorKind::SerializationFailure
+					| diesel::result::DatabaseErrorKind::ClosedConnection,
+					_,
+				),
+			)) => FailureClass::Transient,
+			BackendError::RedisError(_) => FailureClass::Transient,
+			BackendError::SecurityError(
+				SecurityError::FetchError(_) | SecurityError::IoError(_),
+			) => FailureClass::Transient,
+			BackendError::MirrorError(MirrorError::FetchError(_) | MirrorError::StoreError(_)) => {
+				FailureClass::Transient
+			}
+			BackendError::EvaluatorError(
+				EvaluatorError::ResolveError(..)
+				| EvaluatorError::Timeout(_)
+				| EvaluatorError::IoError(_),
+			) => FailureClass::Transient,
+			BackendError::BuilderError(BuilderError::IoError(_)) => FailureClass::Transient,
+			// retried, hopefully on another runner
+			BackendError::ReproError(ReproError::SameBuilder(_)) => FailureClass::Transient,
+			BackendError::ArtifactDiffError(ArtifactDiffError::FetchError(_)) => {
+				FailureClass::Transient
+			}
+			BackendError::BranchScanError(_) => FailureClass::Transient,
+			_ => FailureClass::Permanent,
+		}
+	}
+
+	/// Returns if this error is caused by timing out waiting for a pooled connection.
+	pub fn is_pool_timeout(&self) -> bool {
+		matches!(
+			self,
+			BackendError::DatabaseError(DatabaseError::PoolError(PoolError::Timeout(_)))
+				| BackendError::RedisError(RedisError::PoolError(PoolError::Timeout(_)))
+		)
+	}
+}
+
+impl RetryableError for BackendError {
+	fn is_retryable(&self) -> bool {
+		match self {
+			BackendError::DatabaseError(DatabaseError::QueryError(error)) => error.is_retryable(),
+			_ => false,
+		}
+	}
+}
+
 impl From<diesel::result::Error> for BackendError {
 	fn from(value: diesel::result::Error) -> Self {
 		Self::DatabaseError(DatabaseError::QueryError(value))
 	}
 }
 
-#[cfg(test)]
-pub(crate) mod test {
+/// Helpers of tests, available to other crates with the `test-util` feature.
+#[cfg(any(test, feature = "test-util"))]
+pub mod test {
 	use crate::redis::RedisConfig;
-	use bus::{BackendBusMessage, BackendBusService, C2ABusMessage};
-	use db::service::DatabaseConfig;
-	use futures::{
-		FutureExt,
-		future::{BoxFuture, ready},
+	use branch::BranchRef;
+	use bus::{BusConfig, memory::MemoryBusFactory};
+	use db::{
+		BoxedSqlConn, schema,
+		service::DatabaseConfig,
+		utils::{XJsonVal, XUuidVal},
 	};
+	use diesel::insert_into;
+	use fabricia_testkit::TestDatabase;
+	use job_queue::JobQueueConfig;
+	use lint::LintConfig;
+	use model::{PkgRow, PkgTargetRow};
+	use package::{SqlPackageStatus, SqlPackageTargetState};
+	use repository::RepositoryConfig;
+	use security::SecurityConfig;
 	use target::*;
+	use uuid::Uuid;
 
 	use crate::*;
 
+	/// Creates services for tests.
+	///
+	/// The SQL backend is selected with `FABRICIA_TEST_DATABASE`,
+	/// see [`TestDatabase::from_env`].
 	pub async fn test_env() -> BackendServices {
-		let config = BackendConfig {
-			database: DatabaseConfig {
+		test_env_with_config(test_config()).await
+	}
+
+	/// Creates services for tests with an empty PostgreSQL database.
+	pub async fn test_env_pg() -> BackendServices {
+		let mut config = test_config();
+		config.database = test_database_config(TestDatabase::Postgres);
+		test_env_with_config(config).await
+	}
+
+	/// Returns a fixed time for rows inserted by tests.
+	pub fn test_time() -> time::PrimitiveDateTime {
+		time::PrimitiveDateTime::new(
+			time::Date::from_calendar_date(2025, time::Month::January, 1).unwrap(),
+			time::Time::from_hms(12, 0, 0).unwrap(),
+		)
+	}
+
+	/// Returns a ready package in section `base` for tests.
+	pub fn test_package(branch: BranchRef, name: &str, data: serde_json::Value) -> PkgRow {
+		PkgRow {
+			id: XUuidVal(Uuid::now_v7()),
+			branch,
+			name: name.to_string(),
+			section: "base".to_string(),
+			status: SqlPackageStatus::Ready,
+			status_msg: None,
+			data: XJsonVal(data),
+			created_at: test_time(),
+			updated_at: test_time(),
+			hold_reason: None,
+		}
+	}
+
+	/// Inserts a package returned by [`test_package`].
+	pub async fn insert_test_package(
+		conn: &mut BoxedSqlConn,
+		branch: BranchRef,
+		name: &str,
+		data: serde_json::Value,
+	) -> PkgRow {
+		let pkg = test_package(branch, name, data);
+		conn.execute(insert_into(schema::pkg::table).values(pkg.clone()))
+			.await
+			.unwrap();
+		pkg
+	}
+
+	/// Returns a state of a package on a target for tests.
+	pub fn test_target(
+		pkg: &PkgRow,
+		target: i64,
+		status: SqlPackageTargetState,
+		data: serde_json::Value,
+	) -> PkgTargetRow {
+		PkgTargetRow {
+			id: XUuidVal(Uuid::now_v7()),
+			branch: pkg.branch,
+			package: pkg.id,
+			target,
+			status,
+			data: XJsonVal(data),
+			created_at: test_time(),
+			updated_at: test_time(),
+			revision: 0,
+		}
+	}
+
+	/// Inserts a state returned by [`test_target`].
+	pub async fn insert_test_target(
+		conn: &mut BoxedSqlConn,
+		pkg: &PkgRow,
+		target: i64,
+		status: SqlPackageTargetState,
+		data: serde_json::Value,
+	) -> PkgTargetRow {
+		let row = test_target(pkg, target, status, data);
+		conn.execute(insert_into(schema::pkg_target::table).values(row.clone()))
+			.await
+			.unwrap();
+		row
+	}
+
+	fn test_database_config(database: TestDatabase) -> DatabaseConfig {
+		match database {
+			TestDatabase::Sqlite => DatabaseConfig {
 				url: "sqlite://:memory:".to_string(),
+				url_file: None,
 				max_connections: 1,
+				expand_only: false,
 			},
-			redis: RedisConfig {
-				url: "redis://127.0.0.1".to_string(),
-				max_connections: 1,
+			TestDatabase::Postgres => DatabaseConfig {
+				url: fabricia_testkit::postgres_url(),
+				url_file: None,
+				max_connections: 3,
+				expand_only: false,
 			},
+		}
+	}
+
+	pub fn test_config() -> BackendConfig {
+		BackendConfig {
+			database: test_database_config(TestDatabase::from_env()),
+			redis: Some(RedisConfig {
+				url: fabricia_testkit::redis_url(),
+				url_file: None,
+				max_connections: 1,
+				key_prefix: String::new(),
+				lock_max_wait: None,
+			}),
 			target: vec![
 				TargetConfig {
 					name: "arch1".into(),
 					arch: None,
+					deprecated: false,
 				},
 				TargetConfig {
 					name: "arch2".into(),
 					arch: Some("testarch2".into()),
+					deprecated: false,
 				},
 			],
-		};
-		BackendServices::new(config, TestingBusFactory)
+			target_group: vec![TargetGroupConfig {
+				name: "group1".into(),
+				targets: vec!["arch1".into()],
+			}],
+			repository: vec![
+				RepositoryConfig {
+					name: "main".into(),
+					url: "https://example.com/main.git".into(),
+				},
+				RepositoryConfig {
+					name: "extra".into(),
+					url: "https://example.com/extra.git".into(),
+				},
+			],
+			job_queue: JobQueueConfig::default(),
+			lint: LintConfig::default(),
+			security: SecurityConfig::default(),
+			upstream: None,
+			mirror: None,
+			evaluator: None,
+			builder: None,
+			repro: None,
+			artifact_store: None,
+			branch_scan: None,
+			bus: BusConfig {
+				kind: BusKind::Memory,
+			},
+		}
+	}
+
+	pub async fn test_env_with_config(config: BackendConfig) -> BackendServices {
+		BackendServices::new(config, MemoryBusFactory)
 			.await
 			.unwrap()
 	}
 
-	#[derive(Debug)]
-	struct TestingBusService;
-
-	impl BackendBusService for TestingBusService {
-		fn broadcast(&self, message: BackendBusMessage) -> BoxFuture<'_, Result<()>> {
-			dbg!(message);
-			ready(Ok(())).boxed()
-		}
-
-		fn send_c2a(&self, message: C2ABusMessage) -> BoxFuture<'_, Result<()>> {
-			dbg!(message);
-			ready(Ok(())).boxed()
-		}
+	#[tokio::test]
+	async fn test_init_services() {
+		let env = test_env().await;
+		assert!(env.job_queue.fetch_and_start().await.unwrap().is_none());
 	}
 
-	struct TestingBusFactory;
+	#[tokio::test]
+	async fn test_init_services_without_redis() {
+		let mut config = test_config();
+		config.redis = None;
+		let env = test_env_with_config(config).await;
+		assert!(env.redis.is_none());
 
-	impl BackendBusFactory for TestingBusFactory {
-		fn construct(self, _: Arc<RedisService>) -> BoxFuture<'static, Result<BoxedBusService>> {
-			ready(Ok(Box::new(TestingBusService) as Box<dyn BackendBusService>)).boxed()
-		}
+		env.job_queue.pause().await.unwrap();
+		assert!(env.job_queue.is_paused().await.unwrap());
+		assert!(env.job_queue.fetch_and_start().await.unwrap().is_none());
+		env.job_queue.resume().await.unwrap();
+		assert!(!env.job_queue.is_paused().await.unwrap());
 	}
 
 	#[tokio::test]
-	async fn test_init_services() {
-		let env = test_env().await;
+	async fn test_init_services_pg() {
+		let env = test_env_pg().await;
 		assert!(env.job_queue.fetch_and_start().await.unwrap().is_none());
 	}
 }
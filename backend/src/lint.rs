@@ -0,0 +1,619 @@
+//! Static checks of packages.
+//!
+//! Checks are run by [`JobCommand::LintPackage`] jobs. Findings of the last run
+//! of each package are stored in `pkg_finding`, replacing findings of earlier runs.
+
+use std::{sync::Arc, time::Duration};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use diesel::{
+	ExpressionMethods, OptionalExtension, QueryDsl, delete, deserialize::FromSqlRow,
+	expression::AsExpression, insert_into, sql_types::SmallInt,
+};
+use fabricia_common_model::package::FindingSeverity;
+use reqwest::{StatusCode, header::HeaderMap};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	branch::BranchRef,
+	db::{
+		schema::{pkg::dsl as pkg_dsl, pkg_finding::dsl},
+		service::DatabaseService,
+		utils::{XUuidVal, small_int_enum},
+	},
+	job_queue::{JobCommand, JobQueue},
+	mirror::{http_sources, object_url},
+	model::{NewPkgFindingRow, PkgFindingRow, PkgRow},
+	package::PkgData,
+};
+
+/// Kind of a static check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintCheck {
+	/// Syntax of package metadata, e.g. source specifications.
+	Spec,
+	/// Fields which all packages must have.
+	MissingFields,
+	/// Versions affected by known vulnerabilities, see [`LintConfig::advisories`].
+	Advisory,
+	/// Versions affected by ingested security advisories.
+	///
+	/// This is not run by [`JobCommand::LintPackage`] jobs, but by
+	/// [`crate::security::SecurityService::flag_branch`].
+	Security,
+	/// Declared checksums of sources mismatching upstream or the mirror,
+	/// see [`LintConfig::source_mirror`].
+	SourceChecksum,
+}
+
+impl LintCheck {
+	/// Returns the name of this check, stored in `pkg_finding.kind`.
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			LintCheck::Spec => "spec",
+			LintCheck::MissingFields => "missing-fields",
+			LintCheck::Advisory => "advisory",
+			LintCheck::Security => "security",
+			LintCheck::SourceChecksum => "src-checksum",
+		}
+	}
+}
+
+/// Configuration of static checks.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LintConfig {
+	/// Checks to run on packages.
+	///
+	/// All checks are run if not set.
+	#[serde(default)]
+	pub checks: Option<Vec<LintCheck>>,
+	/// Known vulnerabilities, checked by [`LintCheck::Advisory`].
+	#[serde(default)]
+	pub advisories: Vec<AdvisoryConfig>,
+	/// Content-addressed mirror of sources, serving `{mirror}/sha256/{hex}`.
+	///
+	/// [`LintCheck::SourceChecksum`] verifies mirrored sources by their content.
+	/// Sources missing on the mirror are verified by digest headers of
+	/// HEAD responses of upstream, if provided.
+	#[serde(default)]
+	pub source_mirror: Option<String>,
+}
+
+impl LintConfig {
+	/// Returns whether a check is enabled.
+	pub fn is_enabled(&self, check: LintCheck) -> bool {
+		self.checks
+			.as_ref()
+			.is_none_or(|checks| checks.contains(&check))
+	}
+}
+
+/// A known vulnerability of a package.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct AdvisoryConfig {
+	/// ID of the advisory, e.g. `CVE-2024-3094`.
+	pub id: String,
+	/// Name of the affected package.
+	pub package: String,
+	/// Affected upstream versions.
+	///
+	/// A trailing `*` matches all versions with the prefix, e.g. `5.6.*`.
+	pub versions: Vec<String>,
+}
+
+impl AdvisoryConfig {
+	/// Returns whether a version of a package is affected.
+	pub fn affects(&self, package: &str, version: &str) -> bool {
+		self.package == package
+			&& self
+				.versions
+				.iter()
+				.any(|pattern| match pattern.strip_suffix('*') {
+					Some(prefix) => version.starts_with(prefix),
+					None => version == pattern,
+				})
+	}
+}
+
+/// Severity of a finding.
+///
+/// Stored as a tiny unsigned column. Unknown values are decoded as error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = SmallInt)]
+#[repr(u8)]
+pub enum SqlFindingSeverity {
+	Info = 0,
+	Warning = 1,
+	Error = 2,
+}
+
+impl From<i16> for SqlFindingSeverity {
+	fn from(value: i16) -> Self {
+		match value {
+			0 => Self::Info,
+			1 => Self::Warning,
+			_ => Self::Error,
+		}
+	}
+}
+
+small_int_enum!(SqlFindingSeverity);
+
+impl From<SqlFindingSeverity> for FindingSeverity {
+	fn from(value: SqlFindingSeverity) -> Self {
+		match value {
+			SqlFindingSeverity::Info => Self::Info,
+			SqlFindingSeverity::Warning => Self::Warning,
+			SqlFindingSeverity::Error => Self::Error,
+		}
+	}
+}
+
+/// A problem found by a check.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Finding {
+	pub check: LintCheck,
+	pub severity: SqlFindingSeverity,
+	pub message: String,
+}
+
+impl Finding {
+	fn new(check: LintCheck, severity: SqlFindingSeverity, message: impl Into<String>) -> Self {
+		Self {
+			check,
+			severity,
+			message: message.into(),
+		}
+	}
+}
+
+/// Runs enabled checks on a package.
+pub fn lint(config: &LintConfig, pkg: &PkgRow) -> Vec<Finding> {
+	let data = match pkg.pkg_data() {
+		Ok(data) => data,
+		Err(error) => {
+			return vec![Finding::new(
+				LintCheck::Spec,
+				SqlFindingSeverity::Error,
+				format!("invalid package data: {error}"),
+			)];
+		}
+	};
+	let mut findings = vec![];
+	if config.is_enabled(LintCheck::MissingFields) {
+		check_missing_fields(pkg, &data, &mut findings);
+	}
+	if config.is_enabled(LintCheck::Spec) {
+		check_spec(pkg, &data, &mut findings);
+	}
+	if config.is_enabled(LintCheck::Advisory) {
+		for advisory in &config.advisories {
+			if advisory.affects(&pkg.name, &data.version) {
+				findings.push(Finding::new(
+					LintCheck::Advisory,
+					SqlFindingSeverity::Error,
+					format!("version {} is affected by {}", data.version, advisory.id),
+				));
+			}
+		}
+	}
+	findings
+}
+
+fn check_missing_fields(pkg: &PkgRow, data: &PkgData, findings: &mut Vec<Finding>) {
+	let mut missing = |severity, field: &str| {
+		findings.push(Finding::new(
+			LintCheck::MissingFields,
+			severity,
+			format!("missing {field}"),
+		));
+	};
+	if data.version.is_empty() {
+		missing(SqlFindingSeverity::Error, "version");
+	}
+	if data.srcs.is_empty() {
+		missing(SqlFindingSeverity::Error, "sources");
+	}
+	if pkg.section.is_empty() {
+		missing(SqlFindingSeverity::Warning, "section");
+	}
+}
+
+fn check_spec(pkg: &PkgRow, data: &PkgData, findings: &mut Vec<Finding>) {
+	if data.version.contains(char::is_whitespace) {
+		findings.push(Finding::new(
+			LintCheck::Spec,
+			SqlFindingSeverity::Error,
+			format!("version contains whitespaces: {:?}", data.version),
+		));
+	}
+	if !data.checksums.is_empty() && data.checksums.len() != data.srcs.len() {
+		findings.push(Finding::new(
+			LintCheck::Spec,
+			SqlFindingSeverity::Error,
+			format!(
+				"{} checksums are declared for {} sources",
+				data.checksums.len(),
+				data.srcs.len()
+			),
+		));
+	}
+	for checksum in &data.checksums {
+		if checksum != "SKIP" && declared_sha256(checksum).is_none() {
+			findings.push(Finding::new(
+				LintCheck::Spec,
+				SqlFindingSeverity::Error,
+				format!("malformed checksum: {checksum}"),
+			));
+		}
+	}
+	// sources are specified as `type::url`
+	for src in &data.srcs {
+		if !src
+			.split_once("::")
+			.is_some_and(|(kind, url)| !kind.is_empty() && !url.is_empty())
+		{
+			findings.push(Finding::new(
+				LintCheck::Spec,
+				SqlFindingSeverity::Error,
+				format!("malformed source: {src}"),
+			));
+		}
+	}
+	for (index, dependency) in data.dependencies.iter().enumerate() {
+		if *dependency == pkg.name {
+			findings.push(Finding::new(
+				LintCheck::Spec,
+				SqlFindingSeverity::Warning,
+				"package depends on itself",
+			));
+		} else if data.dependencies[..index].contains(dependency) {
+			findings.push(Finding::new(
+				LintCheck::Spec,
+				SqlFindingSeverity::Info,
+				format!("duplicated dependency: {dependency}"),
+			));
+		}
+	}
+}
+
+/// Decodes a declared checksum of `sha256::<hex>`.
+pub(crate) fn declared_sha256(checksum: &str) -> Option<Vec<u8>> {
+	let digest = hex::decode(checksum.strip_prefix("sha256::")?).ok()?;
+	(digest.len() == 32).then_some(digest)
+}
+
+/// Decodes the SHA-256 digest of a response from its headers, if provided.
+///
+/// `Repr-Digest` (RFC 9530), `Digest` (RFC 3230) and `X-Checksum-Sha256`
+/// of artifact repositories are recognized.
+fn header_sha256(headers: &HeaderMap) -> Option<Vec<u8>> {
+	let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+	// both list digests as `algorithm=value`, `Repr-Digest` wraps values in colons
+	let sha256 = |value: &str| {
+		value.split(',').find_map(|digest| {
+			let (algorithm, value) = digest.trim().split_once('=')?;
+			if !algorithm.eq_ignore_ascii_case("sha-256") {
+				return None;
+			}
+			STANDARD.decode(value.trim_matches(':')).ok()
+		})
+	};
+	header("repr-digest")
+		.and_then(sha256)
+		.or_else(|| header("digest").and_then(sha256))
+		.or_else(|| header("x-checksum-sha256").and_then(|value| hex::decode(value).ok()))
+}
+
+/// Timeout of requests verifying sources.
+const SOURCE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Service for static checks of packages.
+#[derive(Debug)]
+pub struct LintService {
+	db: Arc<DatabaseService>,
+	job_queue: Arc<JobQueue>,
+	config: LintConfig,
+	http: reqwest::Client,
+}
+
+impl LintService {
+	pub fn new(db: Arc<DatabaseService>, job_queue: Arc<JobQueue>, config: &LintConfig) -> Self {
+		Self {
+			db,
+			job_queue,
+			config: config.to_owned(),
+			http: reqwest::Client::new(),
+		}
+	}
+
+	/// Verifies declared checksums of sources, see [`LintCheck::SourceChecksum`].
+	///
+	/// Only HTTP sources are verified. Unreachable sources are reported as warnings,
+	/// since they may be transient.
+	async fn check_source_checksums(&self, data: &PkgData) -> Vec<Finding> {
+		let mut findings = vec![];
+		for (url, expected) in http_sources(data) {
+			if let Some(finding) = self.verify_source(url, &expected).await {
+				findings.push(finding);
+			}
+		}
+		findings
+	}
+
+	async fn verify_source(&self, url: &str, expected: &[u8]) -> Option<Finding> {
+		let mismatch = |actual: &[u8], origin: &str| {
+			Finding::new(
+				LintCheck::SourceChecksum,
+				SqlFindingSeverity::Error,
+				format!(
+					"source {url} has sha256 {} on {origin}, but {} is declared",
+					hex::encode(actual),
+					hex::encode(expected)
+				),
+			)
+		};
+
+		if let Some(mirror) = &self.config.source_mirror {
+			let mirrored = object_url(mirror, expected);
+			let response = self
+				.http
+				.get(&mirrored)
+				.timeout(SOURCE_TIMEOUT)
+				.send()
+				.await
+				.and_then(|response| response.error_for_status());
+			match response {
+				Ok(response) => match response.bytes().await {
+					Ok(body) => {
+						let actual = Sha256::digest(&body);
+						return (actual.as_slice() != expected)
+							.then(|| mismatch(actual.as_slice(), "the mirror"));
+					}
+					Err(error) => debug!(%mirrored, ?error, "failed to read mirrored source"),
+				},
+				Err(error) if error.status() == Some(StatusCode::NOT_FOUND) => {}
+				Err(error) => debug!(%mirrored, ?error, "failed to fetch mirrored source"),
+			}
+		}
+
+		let response = self
+			.http
+			.head(url)
+			.timeout(SOURCE_TIMEOUT)
+			.send()
+			.await
+			.and_then(|response| response.error_for_status());
+		match response {
+			Ok(response) => {
+				let actual = header_sha256(response.headers())?;
+				(actual != expected).then(|| mismatch(&actual, "upstream"))
+			}
+			Err(error) => Some(Finding::new(
+				LintCheck::SourceChecksum,
+				SqlFindingSeverity::Warning,
+				format!("source {url} is unreachable: {error}"),
+			)),
+		}
+	}
+
+	/// Runs checks on a package, and replaces its findings.
+	///
+	/// Findings of removed packages are cleared.
+	/// [`LintCheck::Security`] findings are kept, as they are flagged separately.
+	pub async fn run(&self, package: Uuid) -> Result<Vec<Finding>> {
+		let mut conn = self.db.get().await?;
+		let pkg: Option<PkgRow> = conn
+			.load_one_select(pkg_dsl::pkg.filter(pkg_dsl::id.eq(XUuidVal(package))))
+			.await
+			.optional()?;
+		let mut findings = match &pkg {
+			Some(pkg) => lint(&self.config, pkg),
+			None => vec![],
+		};
+		let checked = pkg
+			.as_ref()
+			.filter(|_| self.config.is_enabled(LintCheck::SourceChecksum))
+			.and_then(|pkg| pkg.pkg_data().ok());
+		if let Some(data) = checked {
+			findings.extend(self.check_source_checksums(&data).await);
+		}
+
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			conn.execute(
+				delete(dsl::pkg_finding)
+					.filter(dsl::package.eq(XUuidVal(package)))
+					.filter(dsl::kind.ne(LintCheck::Security.as_str())),
+			)
+			.await?;
+			let Some(pkg) = &pkg else {
+				return Ok(());
+			};
+			let rows = findings
+				.iter()
+				.map(|finding| NewPkgFindingRow {
+					branch: pkg.branch,
+					package: pkg.id,
+					kind: finding.check.as_str(),
+					severity: finding.severity,
+					message: &finding.message,
+				})
+				.collect::<Vec<_>>();
+			if !rows.is_empty() {
+				conn.execute(insert_into(dsl::pkg_finding).values(rows))
+					.await?;
+			}
+			Ok(())
+		})
+		.await?;
+		info!(%package, findings = findings.len(), "linted package");
+		Ok(findings)
+	}
+
+	/// Enqueues checks of all packages of a branch.
+	///
+	/// Returns the count of enqueued jobs.
+	pub async fn enqueue_branch(&self, branch: BranchRef) -> Result<usize> {
+		let mut conn = self.db.get().await?;
+		let packages = conn
+			.load::<_, XUuidVal>(
+				pkg_dsl::pkg
+					.filter(pkg_dsl::branch.eq(branch))
+					.select(pkg_dsl::id),
+			)
+			.await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			for package in &packages {
+				self.job_queue
+					.enqueue(
+						conn,
+						JobCommand::LintPackage {
+							branch,
+							package: package.0,
+						},
+					)
+					.await?;
+			}
+			Ok(())
+		})
+		.await?;
+		Ok(packages.len())
+	}
+
+	/// Lists findings of packages.
+	pub async fn list(&self, packages: Vec<Uuid>) -> Result<Vec<PkgFindingRow>> {
+		let mut conn = self.db.get().await?;
+		let packages = packages.into_iter().map(XUuidVal).collect::<Vec<_>>();
+		Ok(conn
+			.load_select(
+				dsl::pkg_finding
+					.filter(dsl::package.eq_any(packages))
+					.order(dsl::id.asc()),
+			)
+			.await?)
+	}
+
+	/// Lists findings of all packages in a branch.
+	pub async fn list_branch(&self, branch: BranchRef) -> Result<Vec<PkgFindingRow>> {
+		let mut conn = self.db.get().await?;
+		Ok(conn
+			.load_select(
+				dsl::pkg_finding
+					.filter(dsl::branch.eq(branch))
+					.order(dsl::id.asc()),
+			)
+			.await?)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use reqwest::header::HeaderMap;
+	use serde_json::json;
+
+	use crate::{
+		job_queue::JobCommand,
+		lint::{AdvisoryConfig, LintCheck, SqlFindingSeverity, declared_sha256, header_sha256},
+		test::{insert_test_package, test_config, test_env_with_config},
+	};
+
+	#[test]
+	fn test_advisory() {
+		let advisory = AdvisoryConfig {
+			id: "CVE-2024-3094".to_string(),
+			package: "xz".to_string(),
+			versions: vec!["5.6.*".to_string(), "5.4.9".to_string()],
+		};
+		assert!(advisory.affects("xz", "5.6.1"));
+		assert!(advisory.affects("xz", "5.4.9"));
+		assert!(!advisory.affects("xz", "5.4.10"));
+		assert!(!advisory.affects("zstd", "5.6.1"));
+	}
+
+	#[test]
+	fn test_source_checksums() {
+		let hex = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+		let digest = hex::decode(hex).unwrap();
+		assert_eq!(
+			declared_sha256(&format!("sha256::{hex}")),
+			Some(digest.clone())
+		);
+		assert_eq!(declared_sha256(hex), None);
+		assert_eq!(declared_sha256("sha256::2c26"), None);
+
+		let mut headers = HeaderMap::new();
+		assert_eq!(header_sha256(&headers), None);
+		headers.insert("x-checksum-sha256", hex.parse().unwrap());
+		assert_eq!(header_sha256(&headers), Some(digest.clone()));
+		headers.insert(
+			"repr-digest",
+			"sha-512=:AAAA:, sha-256=:LCa0a2j/xo/5m0U8HTBBNBNCLXBkg7+g+YpeiGJm564=:"
+				.parse()
+				.unwrap(),
+		);
+		assert_eq!(header_sha256(&headers), Some(digest));
+	}
+
+	#[tokio::test]
+	async fn test_lint() {
+		let mut config = test_config();
+		config.lint.advisories.push(AdvisoryConfig {
+			id: "CVE-2024-3094".to_string(),
+			package: "xz".to_string(),
+			versions: vec!["5.6.*".to_string()],
+		});
+		let env = test_env_with_config(config).await;
+
+		let mut db = env.database.get().await.unwrap();
+		let clean = insert_test_package(
+			&mut db,
+			1,
+			"bash",
+			json!({ "version": "5.2.37", "srcs": ["tbl::https://ftp.gnu.org/bash.tar.gz"] }),
+		)
+		.await;
+		let broken = insert_test_package(
+			&mut db,
+			1,
+			"xz",
+			json!({ "version": "5.6.1", "srcs": ["bad"], "dependencies": ["xz"] }),
+		)
+		.await;
+		drop(db);
+
+		assert!(env.lint.run(clean.id.0).await.unwrap().is_empty());
+		let findings = env.lint.run(broken.id.0).await.unwrap();
+		let checks = findings
+			.iter()
+			.map(|finding| (finding.check, finding.severity))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			checks,
+			[
+				(LintCheck::Spec, SqlFindingSeverity::Error),
+				(LintCheck::Spec, SqlFindingSeverity::Warning),
+				(LintCheck::Advisory, SqlFindingSeverity::Error),
+			]
+		);
+
+		// findings are replaced on each run
+		env.lint.run(broken.id.0).await.unwrap();
+		let rows = env.lint.list_branch(1).await.unwrap();
+		assert_eq!(rows.len(), 3);
+		assert!(rows.iter().all(|row| row.package == broken.id));
+		assert_eq!(rows[2].kind, "advisory");
+
+		assert_eq!(env.lint.enqueue_branch(1).await.unwrap(), 2);
+		let job = env.job_queue.fetch_and_start().await.unwrap().unwrap();
+		assert!(matches!(
+			job.command,
+			JobCommand::LintPackage { branch: 1, .. }
+		));
+	}
+}
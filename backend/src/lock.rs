@@ -0,0 +1,115 @@
+//! Locks across backend instances.
+
+use std::{collections::HashMap, sync::Arc};
+
+use time::Duration;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tracing::debug;
+
+use crate::redis::{self, LockKey, RedisResult, RedisService};
+
+/// Lock service, using Redis when available.
+///
+/// Without Redis, locks fall back to in-process mutexes,
+/// which only exclude holders in the same process.
+/// Holders which must exclude other instances sharing only the database
+/// take database locks in addition, e.g. migrations on PostgreSQL.
+#[derive(Debug)]
+pub struct LockService {
+	redis: Option<Arc<RedisService>>,
+	local: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl LockService {
+	pub fn new(redis: Option<Arc<RedisService>>) -> Self {
+		Self {
+			redis,
+			local: Default::default(),
+		}
+	}
+
+	/// Acquires a lock, waiting until it is released by other holders.
+	///
+	/// `ttl` is ignored by in-process locks, as holders cannot die without the process.
+	pub async fn lock<K: Into<LockKey>>(&self, key: K, ttl: Duration) -> RedisResult<LockGuard> {
+		match &self.redis {
+			Some(redis) => Ok(LockGuard::Redis(redis.lock(key, ttl).await?)),
+			None => {
+				// in-process locks are not shared with other deployments
+				let key = key.into().to_key("");
+				let mutex = self
+					.local
+					.lock()
+					.unwrap()
+					.entry(key.clone())
+					.or_default()
+					.clone();
+				let guard = mutex.lock_owned().await;
+				debug!(key, "acquired in-process lock");
+				Ok(LockGuard::Local(guard))
+			}
+		}
+	}
+}
+
+/// Guard of a lock from [`LockService::lock`], releasing the lock when dropped.
+#[derive(Debug)]
+pub enum LockGuard {
+	Redis(redis::LockGuard),
+	Local(OwnedMutexGuard<()>),
+}
+
+impl LockGuard {
+	/// Extends the TTL of the lock.
+	pub async fn extend(&mut self, ttl: Duration) -> RedisResult<()> {
+		match self {
+			LockGuard::Redis(guard) => guard.extend(ttl).await,
+			LockGuard::Local(_) => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_local_lock() {
+		let locks = LockService::new(None);
+		let guard = locks.lock("test", Duration::seconds(1)).await.unwrap();
+		assert!(matches!(guard, LockGuard::Local(_)));
+		let other = locks.lock("other", Duration::seconds(1)).await.unwrap();
+		drop(other);
+
+		let waiting = tokio::time::timeout(
+			std::time::Duration::from_millis(50),
+			locks.lock("test", Duration::seconds(1)),
+		)
+		.await;
+		assert!(waiting.is_err());
+		drop(guard);
+		locks.lock("test", Duration::seconds(1)).await.unwrap();
+	}
+
+	#[test]
+	fn test_key_prefix() {
+		assert_eq!(LockKey::Branch(1).to_key(""), "lock:branch:1");
+		let package = uuid::Uuid::nil();
+		assert_eq!(
+			LockKey::Package(package).to_key(""),
+			format!("lock:pkg:{package}")
+		);
+		assert_eq!(
+			LockKey::PkgTarget(package, 7).to_key(""),
+			format!("lock:pkg-target:{package}:7")
+		);
+		assert_eq!(
+			LockKey::Job(package).to_key(""),
+			format!("lock:job:{package}")
+		);
+		assert_eq!(
+			LockKey::from("gc").to_key("staging:"),
+			"staging:lock:misc:gc"
+		);
+	}
+}
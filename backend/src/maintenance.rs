@@ -0,0 +1,120 @@
+//! Deployment-wide maintenance mode.
+//!
+//! During maintenance, APIs reject mutations with the message of the mode,
+//! while reads keep working.
+
+use std::sync::Arc;
+
+use redis::AsyncCommands;
+use tracing::{info, warn};
+
+use crate::{
+	Result,
+	db::{flag, service::DatabaseService},
+	redis::{RedisError, RedisService},
+};
+
+/// Redis key, or name of the database flag without Redis, holding the message
+/// of the maintenance mode, which exists when and only when the mode is enabled.
+pub const MAINTENANCE_KEY: &str = "maintenance";
+
+/// Switch of the maintenance mode.
+///
+/// Without Redis, the mode is shared through the database, see [`flag`].
+#[derive(Debug)]
+pub struct MaintenanceService {
+	redis: Option<Arc<RedisService>>,
+	db: Arc<DatabaseService>,
+}
+
+impl MaintenanceService {
+	pub fn new(redis: Option<Arc<RedisService>>, db: Arc<DatabaseService>) -> Self {
+		Self { redis, db }
+	}
+
+	/// Enables maintenance mode with a message shown to clients,
+	/// or replaces the message if already enabled.
+	pub async fn enable(&self, message: &str) -> Result<()> {
+		match &self.redis {
+			Some(redis) => {
+				let _: () = redis
+					.get()
+					.await?
+					.set(redis.key(MAINTENANCE_KEY), message)
+					.await
+					.map_err(RedisError::RedisError)?;
+			}
+			None => flag::set(&mut *self.db.get().await?, MAINTENANCE_KEY, Some(message)).await?,
+		}
+		warn!(message, "enabled maintenance mode");
+		Ok(())
+	}
+
+	/// Disables maintenance mode.
+	pub async fn disable(&self) -> Result<()> {
+		match &self.redis {
+			Some(redis) => {
+				let _: () = redis
+					.get()
+					.await?
+					.del(redis.key(MAINTENANCE_KEY))
+					.await
+					.map_err(RedisError::RedisError)?;
+			}
+			None => flag::set(&mut *self.db.get().await?, MAINTENANCE_KEY, None).await?,
+		}
+		info!("disabled maintenance mode");
+		Ok(())
+	}
+
+	/// Returns the message if maintenance mode is enabled.
+	pub async fn message(&self) -> Result<Option<String>> {
+		match &self.redis {
+			Some(redis) => Ok(redis
+				.get()
+				.await?
+				.get(redis.key(MAINTENANCE_KEY))
+				.await
+				.map_err(RedisError::RedisError)?),
+			None => flag::get(&mut *self.db.get().await?, MAINTENANCE_KEY).await,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::sync::Arc;
+
+	use crate::{
+		maintenance::MaintenanceService,
+		redis::RedisService,
+		test::{test_config, test_env},
+	};
+
+	async fn test_switch(service: MaintenanceService) {
+		assert_eq!(service.message().await.unwrap(), None);
+		service.enable("upgrading database").await.unwrap();
+		assert_eq!(
+			service.message().await.unwrap().as_deref(),
+			Some("upgrading database")
+		);
+		service.disable().await.unwrap();
+		assert_eq!(service.message().await.unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn test_database() {
+		let env = test_env().await;
+		test_switch(MaintenanceService::new(None, env.database.clone())).await;
+	}
+
+	#[tokio::test]
+	async fn test_redis() {
+		let mut redis = test_config().redis.unwrap();
+		// isolated from other tests sharing the server
+		redis.key_prefix = format!("{}{}:", redis.key_prefix, uuid::Uuid::now_v7());
+		let redis = Arc::new(RedisService::new(&redis).await.unwrap());
+		let env = test_env().await;
+		test_switch(MaintenanceService::new(Some(redis), env.database.clone())).await;
+	}
+}
@@ -0,0 +1,407 @@
+//! Mirror of package sources.
+//!
+//! Sources of changed packages are downloaded by [`JobCommand::PrefetchSources`] jobs
+//! before builds start, and stored in an object store by their declared SHA-256
+//! digests at `{url}/sha256/{hex}`. Builds fetch sources from the mirror instead of
+//! upstream, and stay reproducible after upstream sources disappear.
+
+use std::{sync::Arc, time::Duration};
+
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	branch::BranchRef,
+	db::{schema::pkg::dsl as pkg_dsl, service::DatabaseService, utils::XUuidVal},
+	job_queue::{JobCommand, JobQueue},
+	lint::declared_sha256,
+	model::PkgRow,
+	package::{PackageDiff, PkgData},
+	secrets::{self, SecretError},
+};
+
+/// Priority of [`JobCommand::PrefetchSources`] jobs, above the default priority,
+/// so that sources are mirrored before builds of the same packages.
+const PREFETCH_PRIORITY: u16 = 110;
+
+/// Timeout of requests downloading or uploading a source.
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Configuration of the source mirror.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MirrorConfig {
+	/// Base URL of the object store, e.g. a bucket of an S3-compatible service.
+	///
+	/// Objects are looked up with HEAD requests, and stored with PUT requests.
+	pub url: String,
+	/// Bearer token of requests storing objects.
+	///
+	/// The token may reference a secret, see [`crate::secrets`].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub token: Option<String>,
+	/// The maximum size in bytes of a mirrored source.
+	#[serde(default = "default_max_size")]
+	pub max_size: u64,
+}
+
+fn default_max_size() -> u64 {
+	1 << 30
+}
+
+impl MirrorConfig {
+	/// Resolves the token from secret references.
+	pub async fn resolve_secrets(&mut self) -> Result<(), SecretError> {
+		if let Some(token) = &mut self.token {
+			secrets::resolve_in_place(token).await?;
+		}
+		Ok(())
+	}
+
+	/// Returns the URL of a mirrored source by its SHA-256 digest.
+	pub fn object_url(&self, digest: &[u8]) -> String {
+		object_url(&self.url, digest)
+	}
+}
+
+/// Returns the URL of a source by its SHA-256 digest on a mirror.
+pub fn object_url(mirror: &str, digest: &[u8]) -> String {
+	format!(
+		"{}/sha256/{}",
+		mirror.trim_end_matches('/'),
+		hex::encode(digest)
+	)
+}
+
+/// Returns URLs and declared SHA-256 digests of HTTP sources of a package.
+///
+/// Sources without checksums, e.g. VCS checkouts, are skipped.
+pub fn http_sources(data: &PkgData) -> Vec<(&str, Vec<u8>)> {
+	data.srcs
+		.iter()
+		.zip(&data.checksums)
+		.filter_map(|(src, checksum)| {
+			let digest = declared_sha256(checksum)?;
+			let (_, url) = src.split_once("::")?;
+			(url.starts_with("https://") || url.starts_with("http://")).then_some((url, digest))
+		})
+		.collect()
+}
+
+/// Results of prefetching sources of a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PrefetchStats {
+	/// Count of sources downloaded and stored on the mirror.
+	pub mirrored: usize,
+	/// Count of sources already on the mirror.
+	pub cached: usize,
+}
+
+/// Service for the source mirror.
+#[derive(Debug)]
+pub struct MirrorService {
+	db: Arc<DatabaseService>,
+	job_queue: Arc<JobQueue>,
+	/// The source mirror is disabled if not set.
+	config: Option<MirrorConfig>,
+	http: reqwest::Client,
+}
+
+impl MirrorService {
+	pub fn new(
+		db: Arc<DatabaseService>,
+		job_queue: Arc<JobQueue>,
+		config: Option<&MirrorConfig>,
+	) -> Self {
+		Self {
+			db,
+			job_queue,
+			config: config.cloned(),
+			http: reqwest::Client::new(),
+		}
+	}
+
+	/// Returns whether the source mirror is enabled.
+	pub fn is_enabled(&self) -> bool {
+		self.config.is_some()
+	}
+
+	/// Downloads sources of a package missing on the mirror, and stores them.
+	///
+	/// Fails with [`MirrorError::ChecksumMismatch`] if a downloaded source
+	/// does not match its declared checksum, which is never stored.
+	pub async fn prefetch(&self, package: Uuid) -> Result<PrefetchStats> {
+		let Some(config) = &self.config else {
+			return Ok(PrefetchStats::default());
+		};
+		let pkg: Option<PkgRow> = {
+			let mut conn = self.db.get().await?;
+			conn.load_one_select(pkg_dsl::pkg.filter(pkg_dsl::id.eq(XUuidVal(package))))
+				.await
+				.optional()?
+		};
+		let Some(pkg) = pkg else {
+			return Ok(PrefetchStats::default());
+		};
+		let data = pkg.pkg_data()?;
+
+		let mut stats = PrefetchStats::default();
+		for (url, digest) in http_sources(&data) {
+			let object = config.object_url(&digest);
+			if self.is_mirrored(&object).await? {
+				stats.cached += 1;
+				continue;
+			}
+			let body = self.download(config, url, &digest).await?;
+			let mut request = self.http.put(&object).timeout(TRANSFER_TIMEOUT).body(body);
+			if let Some(token) = &config.token {
+				request = request.bearer_auth(token);
+			}
+			request
+				.send()
+				.await
+				.and_then(|response| response.error_for_status())
+				.map_err(MirrorError::StoreError)?;
+			debug!(url, %object, "mirrored source");
+			stats.mirrored += 1;
+		}
+		info!(
+			%package,
+			mirrored = stats.mirrored,
+			cached = stats.cached,
+			"prefetched sources"
+		);
+		Ok(stats)
+	}
+
+	/// Returns whether an object exists on the mirror.
+	async fn is_mirrored(&self, object: &str) -> Result<bool, MirrorError> {
+		let response = self
+			.http
+			.head(object)
+			.send()
+			.await
+			.map_err(MirrorError::StoreError)?;
+		match response.status() {
+			StatusCode::NOT_FOUND => Ok(false),
+			_ => response
+				.error_for_status()
+				.map(|_| true)
+				.map_err(MirrorError::StoreError),
+		}
+	}
+
+	/// Downloads a source from upstream, verifying its size and checksum.
+	async fn download(
+		&self,
+		config: &MirrorConfig,
+		url: &str,
+		expected: &[u8],
+	) -> Result<Vec<u8>, MirrorError> {
+		let mut response = self
+			.http
+			.get(url)
+			.timeout(TRANSFER_TIMEOUT)
+			.send()
+			.await
+			.and_then(|response| response.error_for_status())?;
+		if response
+			.content_length()
+			.is_some_and(|length| length > config.max_size)
+		{
+			return Err(MirrorError::TooLarge(url.to_string()));
+		}
+		let mut body = Vec::new();
+		let mut hasher = Sha256::new();
+		while let Some(chunk) = response.chunk().await? {
+			if (body.len() + chunk.len()) as u64 > config.max_size {
+				return Err(MirrorError::TooLarge(url.to_string()));
+			}
+			hasher.update(&chunk);
+			body.extend_from_slice(&chunk);
+		}
+		let actual = hasher.finalize();
+		if actual.as_slice() != expected {
+			return Err(MirrorError::ChecksumMismatch {
+				url: url.to_string(),
+				expected: hex::encode(expected),
+				actual: hex::encode(actual),
+			});
+		}
+		Ok(body)
+	}
+
+	/// Enqueues prefetches of packages.
+	async fn enqueue(&self, branch: BranchRef, packages: &[XUuidVal]) -> Result<()> {
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			for package in packages {
+				self.job_queue
+					.enqueue_with_priority(
+						conn,
+						JobCommand::PrefetchSources {
+							branch,
+							package: package.0,
+						},
+						PREFETCH_PRIORITY,
+					)
+					.await?;
+			}
+			Ok(())
+		})
+		.await
+	}
+
+	/// Enqueues prefetches of added and dirty packages of a branch,
+	/// after [`PackageService::apply`](crate::package::PackageService::apply).
+	///
+	/// Returns the count of enqueued jobs. Does nothing if the mirror is disabled.
+	pub async fn enqueue_changed(&self, branch: BranchRef, diff: &PackageDiff) -> Result<usize> {
+		if !self.is_enabled() {
+			return Ok(0);
+		}
+		let names = diff
+			.added
+			.iter()
+			.chain(&diff.dirty)
+			.map(String::as_str)
+			.collect::<Vec<_>>();
+		let packages = {
+			let mut conn = self.db.get().await?;
+			conn.load::<_, XUuidVal>(
+				pkg_dsl::pkg
+					.filter(pkg_dsl::branch.eq(branch))
+					.filter(pkg_dsl::name.eq_any(names))
+					.select(pkg_dsl::id),
+			)
+			.await?
+		};
+		self.enqueue(branch, &packages).await?;
+		Ok(packages.len())
+	}
+
+	/// Enqueues prefetches of all packages of a branch.
+	///
+	/// Returns the count of enqueued jobs. Does nothing if the mirror is disabled.
+	pub async fn enqueue_branch(&self, branch: BranchRef) -> Result<usize> {
+		if !self.is_enabled() {
+			return Ok(0);
+		}
+		let packages = {
+			let mut conn = self.db.get().await?;
+			conn.load::<_, XUuidVal>(
+				pkg_dsl::pkg
+					.filter(pkg_dsl::branch.eq(branch))
+					.select(pkg_dsl::id),
+			)
+			.await?
+		};
+		self.enqueue(branch, &packages).await?;
+		Ok(packages.len())
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum MirrorError {
+	#[error("failed to download source: {0}")]
+	FetchError(#[from] reqwest::Error),
+	#[error("failed to access the mirror: {0}")]
+	StoreError(reqwest::Error),
+	#[error("source {0} exceeds the maximum size")]
+	TooLarge(String),
+	#[error("source {url} has sha256 {actual}, but {expected} is declared")]
+	ChecksumMismatch {
+		url: String,
+		expected: String,
+		actual: String,
+	},
+}
+
+#[cfg(test)]
+mod test {
+	use serde_json::json;
+
+	use crate::{
+		job_queue::JobCommand,
+		mirror::{MirrorConfig, http_sources, object_url},
+		package::{PackageDiff, PkgData},
+		test::{insert_test_package, test_config, test_env, test_env_with_config},
+	};
+
+	#[test]
+	fn test_http_sources() {
+		let hex = "0".repeat(64);
+		let data = PkgData {
+			srcs: vec![
+				"tbl::https://ftp.gnu.org/gnu/bash/bash-5.2.37.tar.gz".to_string(),
+				"git::https://git.savannah.gnu.org/git/bash.git".to_string(),
+				"file::bash.patch".to_string(),
+			],
+			checksums: vec![
+				format!("sha256::{hex}"),
+				"SKIP".to_string(),
+				format!("sha256::{hex}"),
+			],
+			..Default::default()
+		};
+		let sources = http_sources(&data);
+		assert_eq!(
+			sources,
+			[(
+				"https://ftp.gnu.org/gnu/bash/bash-5.2.37.tar.gz",
+				vec![0; 32]
+			)]
+		);
+		assert_eq!(
+			object_url("https://mirror.example.com/", &sources[0].1),
+			format!("https://mirror.example.com/sha256/{hex}")
+		);
+	}
+
+	#[tokio::test]
+	async fn test_enqueue() {
+		let env = test_env().await;
+		assert!(!env.mirror.is_enabled());
+		assert_eq!(env.mirror.enqueue_branch(1).await.unwrap(), 0);
+
+		let mut config = test_config();
+		config.mirror = Some(MirrorConfig {
+			url: "http://localhost".to_string(),
+			token: None,
+			max_size: 1024,
+		});
+		let env = test_env_with_config(config).await;
+		let mut db = env.database.get().await.unwrap();
+		let pkg = insert_test_package(&mut db, 1, "bash", json!({ "version": "5.2.37" })).await;
+		drop(db);
+
+		let diff = PackageDiff {
+			removed: vec!["bash".to_string()],
+			..Default::default()
+		};
+		assert_eq!(env.mirror.enqueue_changed(1, &diff).await.unwrap(), 0);
+		let diff = PackageDiff {
+			dirty: vec!["bash".to_string()],
+			..Default::default()
+		};
+		assert_eq!(env.mirror.enqueue_changed(1, &diff).await.unwrap(), 1);
+		let job = env.job_queue.fetch_and_start().await.unwrap().unwrap();
+		assert_eq!(
+			job.command,
+			JobCommand::PrefetchSources {
+				branch: 1,
+				package: pkg.id.0
+			}
+		);
+
+		// packages without sources are prefetched without requests
+		let stats = env.mirror.prefetch(pkg.id.0).await.unwrap();
+		assert_eq!((stats.mirrored, stats.cached), (0, 0));
+	}
+}
@@ -0,0 +1,700 @@
+//! Canonical row structs of database tables.
+//!
+//! Services and routes should use these structs instead of ad hoc column tuples.
+
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
+use fabricia_common_model::git::{GitOid, GitOidError};
+use time::PrimitiveDateTime;
+
+use crate::{
+	artifact_diff::ArtifactDiff,
+	branch::{BranchRef, SqlBranchStatus, SqlRemovalPolicy, SqlTrackingMode},
+	branch_template::BranchTemplate,
+	db::{
+		phase::SqlMigrationPhase,
+		schema,
+		utils::{XJsonVal, XUuidVal},
+	},
+	lint::SqlFindingSeverity,
+	namespace::NamespaceRef,
+	package::{ArchivedTarget, PkgData, PkgTargetData, SqlPackageStatus, SqlPackageTargetState},
+	repository::RepositoryRef,
+	security::AffectedVersions,
+};
+
+/// A row of [`schema::branch`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = schema::branch)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct BranchRow {
+	pub id: BranchRef,
+	pub name: String,
+	pub base: Option<BranchRef>,
+	pub status: SqlBranchStatus,
+	pub status_msg: Option<String>,
+	pub priority: i16,
+	pub commit: Option<Vec<u8>>,
+	pub pinned_commit: Option<Vec<u8>>,
+	pub tracking: SqlTrackingMode,
+	pub removal_policy: SqlRemovalPolicy,
+	pub total_srcpkgs: i32,
+	pub max_running_jobs: Option<i32>,
+	pub max_queued_jobs: Option<i32>,
+	pub version: i64,
+	pub namespace: NamespaceRef,
+	pub repository: Option<RepositoryRef>,
+	pub target_group: Option<String>,
+	pub created_at: PrimitiveDateTime,
+	pub updated_at: PrimitiveDateTime,
+}
+
+/// A new row of [`schema::branch`].
+///
+/// The ID is generated by the database.
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = schema::branch)]
+pub struct NewBranchRow<'a> {
+	pub namespace: NamespaceRef,
+	pub repository: Option<RepositoryRef>,
+	pub target_group: Option<&'a str>,
+	pub name: &'a str,
+	pub base: Option<BranchRef>,
+	pub status: SqlBranchStatus,
+	pub priority: i16,
+	pub pinned_commit: Option<&'a [u8]>,
+	pub tracking: SqlTrackingMode,
+	pub removal_policy: SqlRemovalPolicy,
+	pub max_running_jobs: Option<i32>,
+	pub max_queued_jobs: Option<i32>,
+	pub created_at: PrimitiveDateTime,
+	pub updated_at: PrimitiveDateTime,
+}
+
+impl BranchRow {
+	/// Returns the commit which syncs should check out, which is the pinned commit
+	/// if any, or `head` of the Git branch.
+	pub fn sync_commit(&self, head: GitOid) -> Result<GitOid, GitOidError> {
+		match &self.pinned_commit {
+			Some(commit) => GitOid::from_bytes(commit),
+			None => Ok(head),
+		}
+	}
+}
+
+/// A row of [`schema::app_schema`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Queryable, Selectable, Insertable)]
+#[diesel(table_name = schema::app_schema)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct AppSchemaRow {
+	pub id: i32,
+	pub version: i32,
+	pub min_compatible: i32,
+}
+
+/// A row of [`schema::migration_phase`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Insertable)]
+#[diesel(table_name = schema::migration_phase)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct MigrationPhaseRow {
+	pub version: String,
+	pub phase: SqlMigrationPhase,
+	pub applied_at: PrimitiveDateTime,
+}
+
+/// A row of [`schema::job_queue`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = schema::job_queue)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct JobRow {
+	pub id: XUuidVal,
+	pub kind: String,
+	pub data: XJsonVal,
+	pub priority: i16,
+	pub started_at: Option<PrimitiveDateTime>,
+	pub attempts: i16,
+	pub subject_branch: Option<BranchRef>,
+	pub estimated_ms: i64,
+}
+
+/// A row of [`schema::pkg`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = schema::pkg)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PkgRow {
+	pub id: XUuidVal,
+	pub branch: BranchRef,
+	pub name: String,
+	pub section: String,
+	pub status: SqlPackageStatus,
+	pub status_msg: Option<String>,
+	pub data: XJsonVal,
+	pub created_at: PrimitiveDateTime,
+	pub updated_at: PrimitiveDateTime,
+	pub hold_reason: Option<String>,
+}
+
+impl PkgRow {
+	/// Decodes [`PkgRow::data`].
+	pub fn pkg_data(&self) -> serde_json::Result<PkgData> {
+		PkgData::from_json(self.data.0.clone())
+	}
+
+	/// Whether the package is held from automatic syncs and builds.
+	pub fn is_held(&self) -> bool {
+		self.hold_reason.is_some()
+	}
+}
+
+/// A row of [`schema::pkg_target`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = schema::pkg_target)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PkgTargetRow {
+	pub id: XUuidVal,
+	pub branch: BranchRef,
+	pub package: XUuidVal,
+	pub target: i64,
+	pub status: SqlPackageTargetState,
+	pub data: XJsonVal,
+	pub created_at: PrimitiveDateTime,
+	pub updated_at: PrimitiveDateTime,
+	pub revision: i64,
+}
+
+impl PkgTargetRow {
+	/// Decodes [`PkgTargetRow::data`].
+	pub fn target_data(&self) -> serde_json::Result<PkgTargetData> {
+		PkgTargetData::from_json(self.data.0.clone())
+	}
+}
+
+/// A row of [`schema::pkg_finding`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = schema::pkg_finding)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PkgFindingRow {
+	pub id: i64,
+	pub branch: BranchRef,
+	pub package: XUuidVal,
+	pub kind: String,
+	pub severity: SqlFindingSeverity,
+	pub message: String,
+}
+
+/// A new row of [`schema::pkg_finding`].
+///
+/// The ID is generated by the database.
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = schema::pkg_finding)]
+pub struct NewPkgFindingRow<'a> {
+	pub branch: BranchRef,
+	pub package: XUuidVal,
+	pub kind: &'a str,
+	pub severity: SqlFindingSeverity,
+	pub message: &'a str,
+}
+
+/// A row of [`schema::advisory`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = schema::advisory)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct AdvisoryRow {
+	pub id: i64,
+	pub source: String,
+	pub name: String,
+	pub package: String,
+	pub summary: String,
+	pub affected: XJsonVal,
+}
+
+impl AdvisoryRow {
+	/// Decodes [`AdvisoryRow::affected`].
+	pub fn affected_versions(&self) -> serde_json::Result<AffectedVersions> {
+		serde_json::from_value(self.affected.0.clone())
+	}
+}
+
+/// A new row of [`schema::advisory`].
+///
+/// The ID is generated by the database.
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = schema::advisory)]
+pub struct NewAdvisoryRow<'a> {
+	pub source: &'a str,
+	pub name: &'a str,
+	pub package: &'a str,
+	pub summary: &'a str,
+	pub affected: XJsonVal,
+}
+
+/// A row of [`schema::upstream_version`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = schema::upstream_version)]
+#[diesel(primary_key(name))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct UpstreamVersionRow {
+	pub name: String,
+	/// Latest upstream version, or [`None`] if the package is unknown upstream.
+	pub version: Option<String>,
+	pub checked_at: PrimitiveDateTime,
+}
+
+/// A row of [`schema::status_event`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = schema::status_event)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct StatusEventRow {
+	pub id: i64,
+	pub package: XUuidVal,
+	pub status: SqlPackageStatus,
+	pub message: Option<String>,
+	pub actor: String,
+	pub created_at: PrimitiveDateTime,
+}
+
+/// A new row of [`schema::status_event`].
+///
+/// The ID is generated by the database.
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = schema::status_event)]
+pub struct NewStatusEventRow<'a> {
+	pub package: XUuidVal,
+	pub status: SqlPackageStatus,
+	pub message: Option<&'a str>,
+	pub actor: &'a str,
+	pub created_at: PrimitiveDateTime,
+}
+
+/// A row of [`schema::branch_event`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = schema::branch_event)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct BranchEventRow {
+	pub id: i64,
+	pub branch: BranchRef,
+	pub status: SqlBranchStatus,
+	pub message: Option<String>,
+	pub actor: String,
+	pub created_at: PrimitiveDateTime,
+}
+
+/// A new row of [`schema::branch_event`].
+///
+/// The ID is generated by the database.
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = schema::branch_event)]
+pub struct NewBranchEventRow<'a> {
+	pub branch: BranchRef,
+	pub status: SqlBranchStatus,
+	pub message: Option<&'a str>,
+	pub actor: &'a str,
+	pub created_at: PrimitiveDateTime,
+}
+
+/// A row of [`schema::build_cache`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = schema::build_cache)]
+#[diesel(primary_key(key))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct BuildCacheRow {
+	pub key: String,
+	pub target: i64,
+	pub build: XUuidVal,
+	pub artifacts: XJsonVal,
+	pub created_at: PrimitiveDateTime,
+}
+
+impl BuildCacheRow {
+	/// Decodes [`BuildCacheRow::artifacts`].
+	pub fn artifact_names(&self) -> serde_json::Result<Vec<String>> {
+		serde_json::from_value(self.artifacts.0.clone())
+	}
+}
+
+/// A row of [`schema::artifact_diff`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = schema::artifact_diff)]
+#[diesel(primary_key(build, against))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct ArtifactDiffRow {
+	pub build: XUuidVal,
+	pub against: XUuidVal,
+	pub result: Option<XJsonVal>,
+	pub error: Option<String>,
+	pub created_at: PrimitiveDateTime,
+}
+
+impl ArtifactDiffRow {
+	/// Decodes [`ArtifactDiffRow::result`].
+	pub fn diff(&self) -> serde_json::Result<Option<ArtifactDiff>> {
+		self.result
+			.as_ref()
+			.map(|result| serde_json::from_value(result.0.clone()))
+			.transpose()
+	}
+}
+
+/// A row of [`schema::branch_template`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = schema::branch_template)]
+#[diesel(primary_key(name))]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct BranchTemplateRow {
+	pub name: String,
+	pub config: XJsonVal,
+	pub updated_at: PrimitiveDateTime,
+}
+
+impl BranchTemplateRow {
+	/// Decodes [`BranchTemplateRow::config`].
+	pub fn template(&self) -> serde_json::Result<BranchTemplate> {
+		serde_json::from_value(self.config.0.clone())
+	}
+}
+
+/// A row of [`schema::pkg_archive`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = schema::pkg_archive)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PkgArchiveRow {
+	pub id: XUuidVal,
+	pub branch: BranchRef,
+	pub name: String,
+	pub data: XJsonVal,
+	pub targets: XJsonVal,
+	pub archived_at: PrimitiveDateTime,
+}
+
+impl PkgArchiveRow {
+	/// Decodes [`PkgArchiveRow::targets`].
+	pub fn targets(&self) -> serde_json::Result<Vec<ArchivedTarget>> {
+		serde_json::from_value(self.targets.0.clone())
+	}
+}
+
+/// A row of [`schema::audit_log`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = schema::audit_log)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct AuditLogRow {
+	pub id: i64,
+	pub actor: String,
+	pub action: String,
+	pub subject: String,
+	pub detail: XJsonVal,
+	pub created_at: PrimitiveDateTime,
+}
+
+/// A new row of [`schema::audit_log`].
+///
+/// The ID is generated by the database.
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = schema::audit_log)]
+pub struct NewAuditLogRow<'a> {
+	pub actor: &'a str,
+	pub action: &'a str,
+	pub subject: &'a str,
+	pub detail: XJsonVal,
+	pub created_at: PrimitiveDateTime,
+}
+
+#[cfg(test)]
+mod test {
+	use diesel::insert_into;
+	use serde_json::json;
+	use time::{Date, Month, Time};
+	use uuid::Uuid;
+
+	use crate::{
+		namespace::DEFAULT_NAMESPACE_ID,
+		test::{test_env, test_package, test_target},
+	};
+
+	use super::*;
+
+	/// Inserts and reads every column of all tables.
+	#[tokio::test]
+	async fn test_round_trip() {
+		let env = test_env().await;
+		let mut db = env.database.get().await.unwrap();
+		let time = PrimitiveDateTime::new(
+			Date::from_calendar_date(2025, Month::January, 1).unwrap(),
+			Time::from_hms(12, 0, 0).unwrap(),
+		);
+
+		db.execute(insert_into(schema::branch::table).values(NewBranchRow {
+			namespace: DEFAULT_NAMESPACE_ID,
+			repository: None,
+			target_group: Some("group1"),
+			name: "test",
+			base: Some(1),
+			status: SqlBranchStatus::Ready,
+			priority: 120,
+			pinned_commit: Some(&[0xab; 20]),
+			tracking: SqlTrackingMode::Unmanaged,
+			removal_policy: SqlRemovalPolicy::Archive,
+			max_running_jobs: Some(2),
+			max_queued_jobs: None,
+			created_at: time,
+			updated_at: time,
+		}))
+		.await
+		.unwrap();
+		let branch: BranchRow = db.load_one_select(schema::branch::table).await.unwrap();
+		assert_eq!(
+			branch,
+			BranchRow {
+				id: 1,
+				name: "test".to_string(),
+				base: Some(1),
+				status: SqlBranchStatus::Ready,
+				status_msg: None,
+				priority: 120,
+				commit: None,
+				pinned_commit: Some(vec![0xab; 20]),
+				tracking: SqlTrackingMode::Unmanaged,
+				removal_policy: SqlRemovalPolicy::Archive,
+				total_srcpkgs: 0,
+				max_running_jobs: Some(2),
+				max_queued_jobs: None,
+				version: 0,
+				namespace: DEFAULT_NAMESPACE_ID,
+				repository: None,
+				target_group: Some("group1".to_string()),
+				created_at: time,
+				updated_at: time,
+			}
+		);
+
+		let job = JobRow {
+			id: XUuidVal(Uuid::now_v7()),
+			kind: "SyncBranch".to_string(),
+			data: XJsonVal(json!(1)),
+			priority: 100,
+			started_at: Some(PrimitiveDateTime::new(
+				Date::from_calendar_date(2025, Month::January, 1).unwrap(),
+				Time::from_hms(12, 0, 0).unwrap(),
+			)),
+			attempts: 1,
+			subject_branch: Some(1),
+			estimated_ms: 1000,
+		};
+		db.execute(insert_into(schema::job_queue::table).values(job.clone()))
+			.await
+			.unwrap();
+		let result: JobRow = db.load_one_select(schema::job_queue::table).await.unwrap();
+		assert_eq!(result, job);
+
+		let pkg = PkgRow {
+			section: "app-shells".to_string(),
+			status: SqlPackageStatus::Error,
+			status_msg: Some("bad metadata".to_string()),
+			hold_reason: Some("bisecting".to_string()),
+			..test_package(1, "bash", json!({}))
+		};
+		db.execute(insert_into(schema::pkg::table).values(pkg.clone()))
+			.await
+			.unwrap();
+		let result: PkgRow = db.load_one_select(schema::pkg::table).await.unwrap();
+		assert_eq!(result, pkg);
+
+		let pkg_target = PkgTargetRow {
+			revision: 3,
+			..test_target(&pkg, 1, SqlPackageTargetState::BuildFailed, json!({}))
+		};
+		db.execute(insert_into(schema::pkg_target::table).values(pkg_target.clone()))
+			.await
+			.unwrap();
+		let result: PkgTargetRow = db.load_one_select(schema::pkg_target::table).await.unwrap();
+		assert_eq!(result, pkg_target);
+
+		db.execute(
+			insert_into(schema::pkg_finding::table).values(NewPkgFindingRow {
+				branch: 1,
+				package: pkg.id,
+				kind: "missing-fields",
+				severity: SqlFindingSeverity::Warning,
+				message: "missing version",
+			}),
+		)
+		.await
+		.unwrap();
+		let result: PkgFindingRow = db
+			.load_one_select(schema::pkg_finding::table)
+			.await
+			.unwrap();
+		assert_eq!(
+			result,
+			PkgFindingRow {
+				id: 1,
+				branch: 1,
+				package: pkg.id,
+				kind: "missing-fields".to_string(),
+				severity: SqlFindingSeverity::Warning,
+				message: "missing version".to_string(),
+			}
+		);
+
+		let affected = XJsonVal(json!({ "versions": ["5.6.0"] }));
+		db.execute(insert_into(schema::advisory::table).values(NewAdvisoryRow {
+			source: "osv",
+			name: "CVE-2024-3094",
+			package: "xz",
+			summary: "backdoor in liblzma",
+			affected: affected.clone(),
+		}))
+		.await
+		.unwrap();
+		let result: AdvisoryRow = db.load_one_select(schema::advisory::table).await.unwrap();
+		assert_eq!(
+			result,
+			AdvisoryRow {
+				id: 1,
+				source: "osv".to_string(),
+				name: "CVE-2024-3094".to_string(),
+				package: "xz".to_string(),
+				summary: "backdoor in liblzma".to_string(),
+				affected,
+			}
+		);
+
+		let upstream = UpstreamVersionRow {
+			name: "bash".to_string(),
+			version: Some("5.2.37".to_string()),
+			checked_at: PrimitiveDateTime::new(
+				Date::from_calendar_date(2025, Month::January, 1).unwrap(),
+				Time::from_hms(12, 0, 0).unwrap(),
+			),
+		};
+		db.execute(insert_into(schema::upstream_version::table).values(upstream.clone()))
+			.await
+			.unwrap();
+		let result: UpstreamVersionRow = db
+			.load_one_select(schema::upstream_version::table)
+			.await
+			.unwrap();
+		assert_eq!(result, upstream);
+
+		db.execute(
+			insert_into(schema::status_event::table).values(NewStatusEventRow {
+				package: pkg.id,
+				status: SqlPackageStatus::Error,
+				message: Some("bad metadata"),
+				actor: "system",
+				created_at: upstream.checked_at,
+			}),
+		)
+		.await
+		.unwrap();
+		let result: StatusEventRow = db
+			.load_one_select(schema::status_event::table)
+			.await
+			.unwrap();
+		assert_eq!(
+			result,
+			StatusEventRow {
+				id: 1,
+				package: pkg.id,
+				status: SqlPackageStatus::Error,
+				message: Some("bad metadata".to_string()),
+				actor: "system".to_string(),
+				created_at: upstream.checked_at,
+			}
+		);
+
+		db.execute(
+			insert_into(schema::branch_event::table).values(NewBranchEventRow {
+				branch: 1,
+				status: SqlBranchStatus::Suspended,
+				message: Some("maintenance"),
+				actor: "user:alice",
+				created_at: upstream.checked_at,
+			}),
+		)
+		.await
+		.unwrap();
+		let result: BranchEventRow = db
+			.load_one_select(schema::branch_event::table)
+			.await
+			.unwrap();
+		assert_eq!(
+			result,
+			BranchEventRow {
+				id: 1,
+				branch: 1,
+				status: SqlBranchStatus::Suspended,
+				message: Some("maintenance".to_string()),
+				actor: "user:alice".to_string(),
+				created_at: upstream.checked_at,
+			}
+		);
+
+		let cache = BuildCacheRow {
+			key: "0".repeat(64),
+			target: 1,
+			build: XUuidVal(Uuid::now_v7()),
+			artifacts: XJsonVal(json!(["bash_5.2.37-1_amd64.deb"])),
+			created_at: upstream.checked_at,
+		};
+		db.execute(insert_into(schema::build_cache::table).values(cache.clone()))
+			.await
+			.unwrap();
+		let result: BuildCacheRow = db
+			.load_one_select(schema::build_cache::table)
+			.await
+			.unwrap();
+		assert_eq!(result, cache);
+		assert_eq!(
+			result.artifact_names().unwrap(),
+			vec!["bash_5.2.37-1_amd64.deb".to_string()]
+		);
+
+		let diff = ArtifactDiffRow {
+			build: cache.build,
+			against: XUuidVal(Uuid::now_v7()),
+			result: Some(XJsonVal(json!({ "size-delta": 42 }))),
+			error: None,
+			created_at: upstream.checked_at,
+		};
+		db.execute(insert_into(schema::artifact_diff::table).values(diff.clone()))
+			.await
+			.unwrap();
+		let result: ArtifactDiffRow = db
+			.load_one_select(schema::artifact_diff::table)
+			.await
+			.unwrap();
+		assert_eq!(result, diff);
+		assert_eq!(result.diff().unwrap().unwrap().size_delta, 42);
+
+		let template = BranchTemplateRow {
+			name: "security-update".to_string(),
+			config: XJsonVal(json!({ "priority": 150 })),
+			updated_at: upstream.checked_at,
+		};
+		db.execute(insert_into(schema::branch_template::table).values(template.clone()))
+			.await
+			.unwrap();
+		let result: BranchTemplateRow = db
+			.load_one_select(schema::branch_template::table)
+			.await
+			.unwrap();
+		assert_eq!(result, template);
+		assert_eq!(result.template().unwrap().priority, Some(150));
+	}
+}
@@ -0,0 +1,396 @@
+//! Namespaces of branches.
+//!
+//! Teams or repositories sharing one deployment are isolated by namespaces.
+//! Names of branches are unique in each namespace, and each namespace may
+//! have its own bearer token and quotas.
+
+use std::{fmt::Debug, sync::Arc};
+
+use async_trait::async_trait;
+use diesel::{
+	ExpressionMethods, OptionalExtension, QueryDsl, delete, insert_into, result::DatabaseErrorKind,
+	update,
+};
+use kstring::KString;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+	Result,
+	db::{
+		BoxedSqlConn,
+		schema::{branch::dsl as branch_dsl, namespace::dsl},
+		service::DatabaseService,
+	},
+};
+
+pub type NamespaceRef = i64;
+
+/// Name of the namespace of branches created without namespaces.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// ID of [`DEFAULT_NAMESPACE`], which is created by migrations.
+pub const DEFAULT_NAMESPACE_ID: NamespaceRef = 1;
+
+/// Operations on namespaces used by API handlers.
+///
+/// Implemented by [`NamespaceService`], and mocked by `MockNamespaceApi`
+/// with the `mock` feature.
+#[cfg_attr(feature = "mock", mockall::automock)]
+#[async_trait]
+pub trait NamespaceApi: Send + Sync + Debug {
+	/// See [`NamespaceService::get_by_name`].
+	async fn get_by_name(&self, name: &str) -> Result<Option<NamespaceInfo>>;
+	/// See [`NamespaceService::list`].
+	async fn list(&self) -> Result<Vec<NamespaceInfo>>;
+	/// See [`NamespaceService::create`].
+	async fn create(&self, name: &str, info: NamespaceConfigInfo) -> Result<NamespaceToken>;
+	/// See [`NamespaceService::rotate_token`].
+	async fn rotate_token(&self, id: NamespaceRef) -> Result<NamespaceToken>;
+	/// See [`NamespaceService::delete`].
+	async fn delete(&self, id: NamespaceRef) -> Result<()>;
+}
+
+/// Configuration of a namespace.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub struct NamespaceConfigInfo {
+	/// The maximum count of branches in this namespace.
+	///
+	/// Zero means no limit.
+	pub max_branches: Option<u32>,
+	/// The maximum count of pending jobs of all branches in this namespace.
+	///
+	/// Zero means no limit.
+	pub max_queued_jobs: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceInfo {
+	pub id: NamespaceRef,
+	pub name: String,
+	/// Hex-encoded SHA-256 of the bearer token, see [`hash_token`].
+	pub token_hash: Option<String>,
+	pub max_branches: Option<u32>,
+	pub max_queued_jobs: Option<u32>,
+}
+
+impl NamespaceInfo {
+	/// Returns whether the given bearer token grants access to this namespace.
+	///
+	/// Namespaces without tokens are accessible with any or no token.
+	pub fn authorize(&self, token: Option<&str>) -> bool {
+		match (&self.token_hash, token) {
+			(None, _) => true,
+			(Some(hash), Some(token)) => *hash == hash_token(token),
+			(Some(_), None) => false,
+		}
+	}
+}
+
+/// A newly generated bearer token.
+///
+/// Only the hash is stored, so the token cannot be read again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceToken {
+	pub id: NamespaceRef,
+	pub token: String,
+}
+
+/// Hashes a bearer token for storage.
+pub fn hash_token(token: &str) -> String {
+	hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+type NamespaceTuple = (
+	NamespaceRef,
+	String,
+	Option<String>,
+	Option<i32>,
+	Option<i32>,
+);
+
+fn namespace_from_tuple(
+	(id, name, token_hash, max_branches, max_queued_jobs): NamespaceTuple,
+) -> NamespaceInfo {
+	NamespaceInfo {
+		id,
+		name,
+		token_hash,
+		max_branches: max_branches.map(|limit| limit as u32),
+		max_queued_jobs: max_queued_jobs.map(|limit| limit as u32),
+	}
+}
+
+#[derive(Debug)]
+pub struct NamespaceService {
+	db: Arc<DatabaseService>,
+}
+
+impl NamespaceService {
+	pub fn new(db: Arc<DatabaseService>) -> Self {
+		Self { db }
+	}
+
+	pub async fn get_by_name(&self, name: &str) -> Result<Option<NamespaceInfo>> {
+		let mut conn = self.db.get().await?;
+		let row = conn
+			.get_result::<_, NamespaceTuple>(dsl::namespace.filter(dsl::name.eq(name)).select((
+				dsl::id,
+				dsl::name,
+				dsl::token_hash,
+				dsl::max_branches,
+				dsl::max_queued_jobs,
+			)))
+			.await
+			.optional()?;
+		Ok(row.map(namespace_from_tuple))
+	}
+
+	pub async fn list(&self) -> Result<Vec<NamespaceInfo>> {
+		let mut conn = self.db.get().await?;
+		let rows = conn
+			.load::<_, NamespaceTuple>(dsl::namespace.order_by(dsl::id).select((
+				dsl::id,
+				dsl::name,
+				dsl::token_hash,
+				dsl::max_branches,
+				dsl::max_queued_jobs,
+			)))
+			.await?;
+		Ok(rows.into_iter().map(namespace_from_tuple).collect())
+	}
+
+	/// Creates a namespace with a new bearer token.
+	pub async fn create(&self, name: &str, info: NamespaceConfigInfo) -> Result<NamespaceToken> {
+		let mut conn = self.db.get().await?;
+		let token = generate_token();
+		let id = conn
+			.get_result::<_, NamespaceRef>(
+				insert_into(dsl::namespace)
+					.values((
+						dsl::name.eq(name),
+						dsl::token_hash.eq(hash_token(&token)),
+						dsl::max_branches.eq(info.max_branches.and_then(quota_limit)),
+						dsl::max_queued_jobs.eq(info.max_queued_jobs.and_then(quota_limit)),
+					))
+					.returning(dsl::id),
+			)
+			.await
+			.map_err(|error| match error {
+				diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+					NamespaceError::AlreadyExists(KString::from_ref(name)).into()
+				}
+				error => crate::BackendError::from(error),
+			})?;
+		info!(id, name, "created namespace");
+		Ok(NamespaceToken { id, token })
+	}
+
+	/// Replaces the bearer token of a namespace.
+	///
+	/// This also enables authentication of namespaces without tokens,
+	/// like the default namespace.
+	pub async fn rotate_token(&self, id: NamespaceRef) -> Result<NamespaceToken> {
+		let mut conn = self.db.get().await?;
+		let token = generate_token();
+		let updated = conn
+			.execute(
+				update(dsl::namespace)
+					.filter(dsl::id.eq(id))
+					.set(dsl::token_hash.eq(hash_token(&token))),
+			)
+			.await?;
+		if updated == 0 {
+			return Err(NamespaceError::NotFound(id).into());
+		}
+		info!(id, "rotated namespace token");
+		Ok(NamespaceToken { id, token })
+	}
+
+	/// Deletes an empty namespace.
+	pub async fn delete(&self, id: NamespaceRef) -> Result<()> {
+		if id == DEFAULT_NAMESPACE_ID {
+			return Err(NamespaceError::DeleteDefault.into());
+		}
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			let branches = conn
+				.get_result::<_, i64>(
+					branch_dsl::branch
+						.filter(branch_dsl::namespace.eq(id))
+						.count(),
+				)
+				.await?;
+			if branches > 0 {
+				return Err(NamespaceError::NotEmpty(id).into());
+			}
+			let deleted = conn
+				.execute(delete(dsl::namespace).filter(dsl::id.eq(id)))
+				.await?;
+			if deleted == 0 {
+				return Err(NamespaceError::NotFound(id).into());
+			}
+			Ok(())
+		})
+		.await?;
+		info!(id, "deleted namespace");
+		Ok(())
+	}
+
+	/// Ensures that a branch can be added into the namespace.
+	pub async fn check_branch_quota(conn: &mut BoxedSqlConn, id: NamespaceRef) -> Result<()> {
+		let limit = conn
+			.get_result::<_, Option<i32>>(
+				dsl::namespace
+					.filter(dsl::id.eq(id))
+					.select(dsl::max_branches),
+			)
+			.await
+			.optional()?
+			.ok_or(NamespaceError::NotFound(id))?;
+		if let Some(limit) = limit {
+			let branches = conn
+				.get_result::<_, i64>(
+					branch_dsl::branch
+						.filter(branch_dsl::namespace.eq(id))
+						.count(),
+				)
+				.await?;
+			if branches >= limit as i64 {
+				warn!(namespace = id, limit, "namespace branches quota exceeded");
+				return Err(NamespaceError::BranchQuotaExceeded(id).into());
+			}
+		}
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl NamespaceApi for NamespaceService {
+	async fn get_by_name(&self, name: &str) -> Result<Option<NamespaceInfo>> {
+		NamespaceService::get_by_name(self, name).await
+	}
+
+	async fn list(&self) -> Result<Vec<NamespaceInfo>> {
+		NamespaceService::list(self).await
+	}
+
+	async fn create(&self, name: &str, info: NamespaceConfigInfo) -> Result<NamespaceToken> {
+		NamespaceService::create(self, name, info).await
+	}
+
+	async fn rotate_token(&self, id: NamespaceRef) -> Result<NamespaceToken> {
+		NamespaceService::rotate_token(self, id).await
+	}
+
+	async fn delete(&self, id: NamespaceRef) -> Result<()> {
+		NamespaceService::delete(self, id).await
+	}
+}
+
+pub(crate) fn generate_token() -> String {
+	hex::encode(rand::rng().random::<[u8; 32]>())
+}
+
+/// Converts a quota in namespace configuration into the column value.
+///
+/// Zero means no limit.
+fn quota_limit(limit: u32) -> Option<i32> {
+	if limit == 0 {
+		None
+	} else {
+		Some(limit.min(i32::MAX as u32) as i32)
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum NamespaceError {
+	#[error("namespace {0} not found")]
+	NotFound(NamespaceRef),
+	#[error("namespace {0} has already existed")]
+	AlreadyExists(KString),
+	#[error("namespace {0} still has branches")]
+	NotEmpty(NamespaceRef),
+	#[error("the default namespace cannot be deleted")]
+	DeleteDefault,
+	#[error("namespace {0} has used up its branches quota")]
+	BranchQuotaExceeded(NamespaceRef),
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{
+		BackendError,
+		branch::BranchConfigInfo,
+		namespace::{DEFAULT_NAMESPACE, DEFAULT_NAMESPACE_ID, NamespaceConfigInfo, NamespaceError},
+		test::test_env,
+	};
+
+	#[tokio::test]
+	async fn test_default_namespace() {
+		let env = test_env().await;
+		let default = env
+			.namespace
+			.get_by_name(DEFAULT_NAMESPACE)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(default.id, DEFAULT_NAMESPACE_ID);
+		assert!(default.authorize(None));
+		assert!(matches!(
+			env.namespace.delete(DEFAULT_NAMESPACE_ID).await,
+			Err(BackendError::NamespaceError(NamespaceError::DeleteDefault))
+		));
+	}
+
+	#[tokio::test]
+	async fn test_namespace() {
+		let env = test_env().await;
+		let info = NamespaceConfigInfo {
+			max_branches: Some(1),
+			..Default::default()
+		};
+		let token = env.namespace.create("team", info).await.unwrap();
+		let team = env.namespace.get_by_name("team").await.unwrap().unwrap();
+		assert!(team.authorize(Some(&token.token)));
+		assert!(!team.authorize(Some("wrong")));
+		assert!(!team.authorize(None));
+
+		// branch names are unique per namespace
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "main", BranchConfigInfo::default())
+			.await
+			.unwrap();
+		env.branch
+			.track(token.id, "main", BranchConfigInfo::default())
+			.await
+			.unwrap();
+		assert_ne!(
+			env.branch
+				.find_id(DEFAULT_NAMESPACE_ID, "main")
+				.await
+				.unwrap(),
+			env.branch.find_id(token.id, "main").await.unwrap()
+		);
+		assert!(matches!(
+			env.branch
+				.track(token.id, "stable", BranchConfigInfo::default())
+				.await,
+			Err(BackendError::NamespaceError(
+				NamespaceError::BranchQuotaExceeded(_)
+			))
+		));
+		assert!(matches!(
+			env.namespace.delete(token.id).await,
+			Err(BackendError::NamespaceError(NamespaceError::NotEmpty(_)))
+		));
+
+		let rotated = env.namespace.rotate_token(token.id).await.unwrap();
+		let team = env.namespace.get_by_name("team").await.unwrap().unwrap();
+		assert!(team.authorize(Some(&rotated.token)));
+		assert!(!team.authorize(Some(&token.token)));
+	}
+}
@@ -0,0 +1,281 @@
+//! Long-running operations requested by users.
+//!
+//! Mutations which spawn jobs return an operation, so that users may
+//! find out when the spawned jobs are completed, and whether they have failed.
+
+use std::sync::Arc;
+
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, insert_into};
+use fabricia_common_model::job::JobStatus;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	db::{
+		BoxedSqlConn,
+		schema::{job_history, job_queue, operation::dsl, operation_job},
+		service::DatabaseService,
+		utils::XUuidVal,
+	},
+	job_history::SqlJobOutcome,
+	job_queue::JobRef,
+};
+
+pub type OperationRef = Uuid;
+
+/// Overall status of an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperationStatus {
+	/// Some jobs of the operation are pending or running.
+	Pending,
+	/// All jobs of the operation have succeeded.
+	Succeeded,
+	/// Some jobs of the operation have failed or been aborted.
+	Failed,
+}
+
+/// State of a job spawned by an operation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OperationJobState {
+	Pending,
+	Running,
+	Succeeded,
+	Failed {
+		error: Option<String>,
+	},
+	/// The job has been removed from the queue without being recorded.
+	Aborted,
+}
+
+impl OperationJobState {
+	pub fn status(&self) -> JobStatus {
+		match self {
+			OperationJobState::Pending => JobStatus::Pending,
+			OperationJobState::Running => JobStatus::Running,
+			OperationJobState::Succeeded => JobStatus::Succeeded,
+			OperationJobState::Failed { .. } => JobStatus::Failed,
+			OperationJobState::Aborted => JobStatus::Aborted,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OperationJob {
+	pub id: JobRef,
+	/// Kind of the job, or `None` if the job has been aborted.
+	pub kind: Option<String>,
+	pub state: OperationJobState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OperationInfo {
+	pub id: OperationRef,
+	pub kind: String,
+	/// Name of the affected branch.
+	pub branch: Option<String>,
+	/// Created time of this operation in UTC.
+	pub created_at: PrimitiveDateTime,
+	pub jobs: Vec<OperationJob>,
+}
+
+impl OperationInfo {
+	pub fn status(&self) -> OperationStatus {
+		let mut status = OperationStatus::Succeeded;
+		for job in &self.jobs {
+			match job.state {
+				OperationJobState::Pending | OperationJobState::Running => {
+					status = OperationStatus::Pending;
+				}
+				OperationJobState::Failed { .. } | OperationJobState::Aborted => {
+					return OperationStatus::Failed;
+				}
+				OperationJobState::Succeeded => {}
+			}
+		}
+		status
+	}
+}
+
+#[derive(Debug)]
+pub struct OperationService {
+	db: Arc<DatabaseService>,
+}
+
+impl OperationService {
+	pub fn new(db: Arc<DatabaseService>) -> Self {
+		Self { db }
+	}
+
+	/// Creates an operation linked to spawned jobs.
+	///
+	/// This should be called in the same transaction as the mutation.
+	pub async fn create(
+		&self,
+		conn: &mut BoxedSqlConn,
+		kind: &str,
+		branch: Option<&str>,
+		jobs: &[JobRef],
+	) -> Result<OperationRef> {
+		let id = Uuid::now_v7();
+		let now = OffsetDateTime::now_utc();
+		conn.execute(insert_into(dsl::operation).values((
+			dsl::id.eq(XUuidVal(id)),
+			dsl::kind.eq(kind),
+			dsl::branch.eq(branch),
+			dsl::created_at.eq(PrimitiveDateTime::new(now.date(), now.time())),
+		)))
+		.await?;
+		for job in jobs {
+			conn.execute(insert_into(operation_job::table).values((
+				operation_job::operation.eq(XUuidVal(id)),
+				operation_job::job.eq(XUuidVal(*job)),
+			)))
+			.await?;
+		}
+		info!(%id, kind, branch, jobs = jobs.len(), "created operation");
+		Ok(id)
+	}
+
+	/// Returns an operation with states of its jobs.
+	pub async fn get(&self, id: OperationRef) -> Result<Option<OperationInfo>> {
+		let mut conn = self.db.get().await?;
+		Self::get_in(&mut conn, id).await
+	}
+
+	/// Returns an operation with states of its jobs, using the given connection.
+	pub async fn get_in(
+		conn: &mut BoxedSqlConn,
+		id: OperationRef,
+	) -> Result<Option<OperationInfo>> {
+		let Some((kind, branch, created_at)) = conn
+			.get_result::<_, (String, Option<String>, PrimitiveDateTime)>(
+				dsl::operation.filter(dsl::id.eq(XUuidVal(id))).select((
+					dsl::kind,
+					dsl::branch,
+					dsl::created_at,
+				)),
+			)
+			.await
+			.optional()?
+		else {
+			return Ok(None);
+		};
+
+		let job_ids = conn
+			.load::<_, XUuidVal>(
+				operation_job::table
+					.filter(operation_job::operation.eq(XUuidVal(id)))
+					.select(operation_job::job),
+			)
+			.await?;
+		let mut jobs = Vec::with_capacity(job_ids.len());
+		for job in job_ids {
+			jobs.push(Self::get_job(conn, job.0).await?);
+		}
+
+		Ok(Some(OperationInfo {
+			id,
+			kind,
+			branch,
+			created_at,
+			jobs,
+		}))
+	}
+
+	async fn get_job(conn: &mut BoxedSqlConn, id: JobRef) -> Result<OperationJob> {
+		let queued = conn
+			.get_result::<_, (String, Option<PrimitiveDateTime>)>(
+				job_queue::table
+					.filter(job_queue::id.eq(XUuidVal(id)))
+					.select((job_queue::kind, job_queue::started_at)),
+			)
+			.await
+			.optional()?;
+		if let Some((kind, started_at)) = queued {
+			return Ok(OperationJob {
+				id,
+				kind: Some(kind),
+				state: match started_at {
+					None => OperationJobState::Pending,
+					Some(_) => OperationJobState::Running,
+				},
+			});
+		}
+
+		let finished = conn
+			.get_result::<_, (String, SqlJobOutcome, Option<String>)>(
+				job_history::table
+					.filter(job_history::id.eq(XUuidVal(id)))
+					.select((job_history::kind, job_history::outcome, job_history::error)),
+			)
+			.await
+			.optional()?;
+		Ok(match finished {
+			Some((kind, outcome, error)) => OperationJob {
+				id,
+				kind: Some(kind),
+				state: match outcome {
+					SqlJobOutcome::Succeeded => OperationJobState::Succeeded,
+					SqlJobOutcome::Failed => OperationJobState::Failed { error },
+				},
+			},
+			None => OperationJob {
+				id,
+				kind: None,
+				state: OperationJobState::Aborted,
+			},
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{
+		job_queue::{FailureClass, JobError},
+		namespace::DEFAULT_NAMESPACE_ID,
+		test::test_env,
+	};
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_operation() {
+		let env = test_env().await;
+		let operation = env
+			.branch
+			.track(DEFAULT_NAMESPACE_ID, "test", Default::default())
+			.await
+			.unwrap();
+
+		let info = env.operation.get(operation).await.unwrap().unwrap();
+		assert_eq!(info.kind, "track");
+		assert_eq!(info.branch.as_deref(), Some("test"));
+		assert_eq!(info.jobs.len(), 1);
+		assert_eq!(info.status(), OperationStatus::Pending);
+
+		let job = env.job_queue.fetch_and_start().await.unwrap().unwrap();
+		let mut db = env.database.get().await.unwrap();
+		env.job_queue
+			.fail_job(
+				&mut db,
+				job.id,
+				&JobError::new(FailureClass::Permanent, "bad metadata"),
+			)
+			.await
+			.unwrap();
+		drop(db);
+
+		let info = env.operation.get(operation).await.unwrap().unwrap();
+		assert_eq!(info.status(), OperationStatus::Failed);
+		assert_eq!(
+			info.jobs[0].state,
+			OperationJobState::Failed {
+				error: Some("bad metadata".to_string())
+			}
+		);
+
+		assert!(env.operation.get(Uuid::now_v7()).await.unwrap().is_none());
+	}
+}
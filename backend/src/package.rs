@@ -1,7 +1,55 @@
+use std::{
+	cmp::Ordering,
+	collections::{BTreeMap, HashMap, HashSet},
+	fmt::{Debug, Display},
+	sync::Arc,
+};
+
+use async_trait::async_trait;
+use diesel::{
+	BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, delete,
+	deserialize::FromSqlRow, expression::AsExpression, insert_into, sql_types::SmallInt, update,
+};
+use fabricia_common_model::{
+	git::GitOid,
+	package::{PackageStatus, PackageTargetStatus, ReproStatus},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+	Result, audit,
+	branch::{BranchRef, SqlRemovalPolicy},
+	branch_acl::{BranchAclService, Principal},
+	branch_compare::{self, ComparisonPage},
+	changelog::ChangelogEntry,
+	db::{
+		BoxedSqlConn,
+		schema::{
+			branch::dsl as branch_dsl, job_queue::dsl as job_dsl, pkg::dsl,
+			pkg_archive::dsl as archive_dsl, pkg_finding::dsl as finding_dsl,
+			pkg_target::dsl as target_dsl, status_event::dsl as event_dsl,
+		},
+		service::DatabaseService,
+		utils::{XJsonVal, XUuidVal, small_int_enum},
+	},
+	job_queue::JobRef,
+	model::{NewStatusEventRow, PkgArchiveRow, PkgRow, PkgTargetRow, StatusEventRow},
+	problem::{self, PackageProblem},
+	target::{TargetId, TargetService},
+	target_status::{self, TargetStatus},
+};
+
 /// State of a package.
 ///
 /// Stored as a tiny unsigned column. Unknown values are decoded as error.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = SmallInt)]
 #[repr(u8)]
 pub enum SqlPackageStatus {
 	/// State for packages needing a metadata refresh.
@@ -43,10 +91,34 @@ impl From<i16> for SqlPackageStatus {
 	}
 }
 
+small_int_enum!(SqlPackageStatus);
+
+impl SqlPackageStatus {
+	pub fn into_common(&self, message: Option<String>) -> PackageStatus {
+		match self {
+			SqlPackageStatus::Dirty => PackageStatus::Dirty,
+			SqlPackageStatus::Ready => PackageStatus::Ready,
+			SqlPackageStatus::Error => PackageStatus::Error {
+				reason: message.unwrap_or_default(),
+			},
+		}
+	}
+
+	/// Converts a status into the stored status and message.
+	pub fn from_common(status: PackageStatus) -> (Self, Option<String>) {
+		match status {
+			PackageStatus::Dirty => (SqlPackageStatus::Dirty, None),
+			PackageStatus::Ready => (SqlPackageStatus::Ready, None),
+			PackageStatus::Error { reason } => (SqlPackageStatus::Error, Some(reason)),
+		}
+	}
+}
+
 /// State of a (package, target).
 ///
 /// Stored as a tiny unsigned column. Unknown values are decoded as error.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, AsExpression, FromSqlRow)]
+#[diesel(sql_type = SmallInt)]
 #[repr(u8)]
 pub enum SqlPackageTargetState {
 	#[default]
@@ -73,3 +145,1480 @@ impl From<i16> for SqlPackageTargetState {
 		}
 	}
 }
+
+small_int_enum!(SqlPackageTargetState);
+
+impl From<SqlPackageTargetState> for PackageTargetStatus {
+	fn from(value: SqlPackageTargetState) -> Self {
+		match value {
+			SqlPackageTargetState::Dirty => Self::Dirty,
+			SqlPackageTargetState::Ready => Self::Ready,
+			SqlPackageTargetState::BuildFailed => Self::BuildFailed,
+			SqlPackageTargetState::Error => Self::Error,
+		}
+	}
+}
+
+impl From<PackageTargetStatus> for SqlPackageTargetState {
+	fn from(value: PackageTargetStatus) -> Self {
+		match value {
+			PackageTargetStatus::Dirty => Self::Dirty,
+			PackageTargetStatus::Ready => Self::Ready,
+			PackageTargetStatus::BuildFailed => Self::BuildFailed,
+			PackageTargetStatus::Error => Self::Error,
+		}
+	}
+}
+
+/// Key of the schema version in package data JSON.
+const DATA_SCHEMA_KEY: &str = "schema";
+
+/// Takes the schema version out of a data JSON.
+///
+/// Data written before versioning have no schema version, and are treated as version 0.
+fn take_data_schema(value: &mut Value) -> u64 {
+	value
+		.as_object_mut()
+		.and_then(|object| object.remove(DATA_SCHEMA_KEY))
+		.and_then(|schema| schema.as_u64())
+		.unwrap_or(0)
+}
+
+fn put_data_schema(mut value: Value, schema: u64) -> Value {
+	if let Some(object) = value.as_object_mut() {
+		object.insert(DATA_SCHEMA_KEY.to_string(), schema.into());
+	}
+	value
+}
+
+/// Compares versions segment by segment.
+///
+/// Versions are split into runs of digits and runs of letters, and other characters
+/// are separators. Numeric segments are compared as numbers, and are newer than
+/// alphabetic segments, like `rpmvercmp`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+	let a = version_segments(a);
+	let b = version_segments(b);
+	for (a, b) in a.iter().zip(&b) {
+		let a_numeric = a.starts_with(|c: char| c.is_ascii_digit());
+		let b_numeric = b.starts_with(|c: char| c.is_ascii_digit());
+		let ordering = match (a_numeric, b_numeric) {
+			(true, true) => {
+				let a = a.trim_start_matches('0');
+				let b = b.trim_start_matches('0');
+				a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+			}
+			(true, false) => Ordering::Greater,
+			(false, true) => Ordering::Less,
+			(false, false) => a.cmp(b),
+		};
+		if ordering.is_ne() {
+			return ordering;
+		}
+	}
+	a.len().cmp(&b.len())
+}
+
+fn version_segments(version: &str) -> Vec<&str> {
+	let mut segments = vec![];
+	// start and whether the current segment is numeric
+	let mut current: Option<(usize, bool)> = None;
+	for (index, c) in version.char_indices() {
+		if let Some((start, numeric)) = current {
+			if c.is_ascii_alphanumeric() && c.is_ascii_digit() == numeric {
+				continue;
+			}
+			segments.push(&version[start..index]);
+			current = None;
+		}
+		if c.is_ascii_alphanumeric() {
+			current = Some((index, c.is_ascii_digit()));
+		}
+	}
+	if let Some((start, _)) = current {
+		segments.push(&version[start..]);
+	}
+	segments
+}
+
+/// Data of a package, stored in `pkg.data`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct PkgData {
+	/// Upstream version.
+	pub version: String,
+	/// Packaging release.
+	pub release: u32,
+	pub epoch: u32,
+	/// Names of runtime dependencies.
+	pub dependencies: Vec<String>,
+	/// Source specifications.
+	pub srcs: Vec<String>,
+	/// Checksums of sources in the order of [`PkgData::srcs`], as `sha256::<hex>`,
+	/// or `SKIP` for sources without stable content, e.g. VCS checkouts.
+	pub checksums: Vec<String>,
+	/// Git tree of the package directory.
+	pub tree: Option<GitOid>,
+	/// Changelog entries of the package, newest first, see [`crate::changelog`].
+	pub changelog: Vec<ChangelogEntry>,
+}
+
+impl PkgData {
+	/// Current schema version.
+	pub const SCHEMA: u64 = 1;
+
+	/// Decodes package data, migrating data of older schemas.
+	pub fn from_json(mut value: Value) -> serde_json::Result<Self> {
+		match take_data_schema(&mut value) {
+			// version 0 has the same fields, but some of them may be absent
+			0 | 1 => serde_json::from_value(value),
+			schema => Err(serde::de::Error::custom(format!(
+				"unsupported package data schema {}",
+				schema
+			))),
+		}
+	}
+
+	/// Encodes package data with the current schema version.
+	pub fn to_json(&self) -> serde_json::Result<Value> {
+		Ok(put_data_schema(serde_json::to_value(self)?, Self::SCHEMA))
+	}
+}
+
+/// Data of a (package, target), stored in `pkg_target.data`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct PkgTargetData {
+	/// ID of the last build job.
+	pub last_build: Option<Uuid>,
+	/// File names of artifacts of the last successful build.
+	pub artifacts: Vec<String>,
+	/// Cache key of the last successful build, see [`crate::build_cache`].
+	pub cache_key: Option<String>,
+	/// SHA-256 digests of artifacts of the last successful build, by file name.
+	pub artifact_hashes: BTreeMap<String, String>,
+	/// Worker which has run the last successful build.
+	pub builder: Option<String>,
+	/// Reproducibility of the last successful build, see [`crate::repro`].
+	pub repro: Option<ReproState>,
+}
+
+/// Reproducibility of a build, stored in [`PkgTargetData::repro`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReproState {
+	pub status: ReproStatus,
+	/// ID of the job verifying the build.
+	pub verify_build: Option<Uuid>,
+	/// File names of artifacts which differ between the builds.
+	#[serde(default)]
+	pub differing: Vec<String>,
+}
+
+impl PkgTargetData {
+	/// Current schema version.
+	pub const SCHEMA: u64 = 1;
+
+	/// Decodes target data, migrating data of older schemas.
+	pub fn from_json(mut value: Value) -> serde_json::Result<Self> {
+		match take_data_schema(&mut value) {
+			0 | 1 => serde_json::from_value(value),
+			schema => Err(serde::de::Error::custom(format!(
+				"unsupported package target data schema {}",
+				schema
+			))),
+		}
+	}
+
+	/// Encodes target data with the current schema version.
+	pub fn to_json(&self) -> serde_json::Result<Value> {
+		Ok(put_data_schema(serde_json::to_value(self)?, Self::SCHEMA))
+	}
+}
+
+/// Computes input hashes of packages in a branch, by package name.
+///
+/// The input hash of a package covers its source tree, its metadata, and versions of
+/// its direct dependencies in the same branch. Packages need to be rebuilt only if
+/// their input hashes are changed.
+pub fn input_hashes(packages: &HashMap<String, PkgData>) -> HashMap<String, String> {
+	packages
+		.iter()
+		.map(|(name, data)| {
+			let mut hasher = Sha256::new();
+			let tree = data.tree.map(|tree| tree.to_string()).unwrap_or_default();
+			hasher.update(format!(
+				"{name}\0{tree}\0{}:{}-{}\0",
+				data.epoch, data.version, data.release
+			));
+			for src in &data.srcs {
+				hasher.update(src);
+				hasher.update(b"\0");
+			}
+			let deps = data
+				.dependencies
+				.iter()
+				.filter_map(|dep| Some((dep, packages.get(dep)?)))
+				.collect::<BTreeMap<_, _>>();
+			for (dep, data) in deps {
+				hasher.update(format!(
+					"{dep}\0{}:{}-{}\0",
+					data.epoch, data.version, data.release
+				));
+			}
+			(name.clone(), hex::encode(hasher.finalize()))
+		})
+		.collect()
+}
+
+/// Actor of a status transition of a package.
+///
+/// Stored as `job:{id}`, `user:{name}`, the identity of a caller or `system`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatusActor {
+	Job(JobRef),
+	/// A user, by name.
+	User(String),
+	/// A caller of the API, by its identity, see [`Principal::name`].
+	Caller(String),
+	/// Fabricia itself, e.g. on synchronization of branches.
+	System,
+}
+
+impl Display for StatusActor {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			StatusActor::Job(id) => write!(f, "job:{id}"),
+			StatusActor::User(name) => write!(f, "user:{name}"),
+			StatusActor::Caller(identity) => f.write_str(identity),
+			StatusActor::System => f.write_str("system"),
+		}
+	}
+}
+
+/// State forced onto a package, see [`PackageService::override_state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateOverride {
+	/// Status of the package, with the message of errors.
+	Package(SqlPackageStatus, Option<String>),
+	/// State of the package on a target.
+	Target(TargetId, SqlPackageTargetState),
+}
+
+/// A package evaluated from a branch.
+///
+/// Deserialized from the output of the [evaluation command](crate::evaluator::EvaluatorConfig).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct EvaluatedPackage {
+	pub name: String,
+	pub section: String,
+	pub data: PkgData,
+}
+
+/// Changes to packages of a branch, see [`PackageService::diff`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PackageDiff {
+	/// Names of added packages.
+	pub added: Vec<String>,
+	/// Names of removed packages.
+	pub removed: Vec<String>,
+	/// Names of packages whose input hashes are changed, which need to be rebuilt.
+	pub dirty: Vec<String>,
+	/// Names of packages removed upstream but kept in the branch,
+	/// see [`SqlRemovalPolicy::KeepWithWarning`].
+	pub kept: Vec<String>,
+	/// Names of held packages which are changed or removed upstream,
+	/// whose changes are not applied, see [`PkgRow::hold_reason`].
+	pub held: Vec<String>,
+}
+
+/// State of an archived package on a target, see [`SqlRemovalPolicy::Archive`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedTarget {
+	pub target: TargetId,
+	/// Discriminant of [`SqlPackageTargetState`].
+	pub status: u8,
+	/// Data [`PkgTargetData`] of the package on the target.
+	pub data: Value,
+}
+
+impl PackageDiff {
+	/// Compares packages of a branch with newly evaluated packages.
+	fn compare(rows: &[PkgRow], packages: &[EvaluatedPackage]) -> Result<Self> {
+		let mut old = HashMap::with_capacity(rows.len());
+		for row in rows {
+			old.insert(row.name.clone(), row.pkg_data()?);
+		}
+		let new = packages
+			.iter()
+			.map(|pkg| (pkg.name.clone(), pkg.data.clone()))
+			.collect::<HashMap<_, _>>();
+		let old_hashes = input_hashes(&old);
+		let new_hashes = input_hashes(&new);
+
+		let mut diff = Self::default();
+		for (name, hash) in &new_hashes {
+			match old_hashes.get(name) {
+				None => diff.added.push(name.clone()),
+				Some(old_hash) if old_hash != hash => diff.dirty.push(name.clone()),
+				Some(_) => {}
+			}
+		}
+		diff.removed = old_hashes
+			.into_keys()
+			.filter(|name| !new_hashes.contains_key(name))
+			.collect();
+
+		// held packages are left as they are
+		let held = rows
+			.iter()
+			.filter(|row| row.is_held())
+			.map(|row| row.name.as_str())
+			.collect::<HashSet<_>>();
+		diff.held = diff
+			.dirty
+			.iter()
+			.chain(&diff.removed)
+			.filter(|name| held.contains(name.as_str()))
+			.cloned()
+			.collect();
+		diff.dirty.retain(|name| !held.contains(name.as_str()));
+		diff.removed.retain(|name| !held.contains(name.as_str()));
+
+		diff.added.sort();
+		diff.removed.sort();
+		diff.dirty.sort();
+		diff.held.sort();
+		Ok(diff)
+	}
+}
+
+/// Operations on packages used by API handlers.
+///
+/// Implemented by [`PackageService`], and mocked by `MockPackageApi`
+/// with the `mock` feature.
+#[cfg_attr(feature = "mock", mockall::automock)]
+#[async_trait]
+pub trait PackageApi: Send + Sync + Debug {
+	/// See [`PackageService::list`].
+	async fn list(&self, branch: BranchRef) -> Result<Vec<PkgRow>>;
+	/// See [`PackageService::find`].
+	async fn find(&self, branch: BranchRef, name: &str) -> Result<Option<PkgRow>>;
+	/// See [`PackageService::list_page`].
+	async fn list_page(
+		&self,
+		branch: BranchRef,
+		after: Option<String>,
+		limit: usize,
+	) -> Result<Vec<PkgRow>>;
+	/// See [`PackageService::list_targets`].
+	async fn list_targets(&self, packages: Vec<Uuid>) -> Result<Vec<PkgTargetRow>>;
+	/// See [`PackageService::compare`].
+	async fn compare(
+		&self,
+		branch: BranchRef,
+		other: BranchRef,
+		after: Option<String>,
+		limit: usize,
+		max_targets: usize,
+	) -> Result<ComparisonPage>;
+	/// See [`PackageService::problems`].
+	async fn problems(&self, branch: BranchRef) -> Result<Vec<PackageProblem>>;
+	/// See [`PackageService::target_status`].
+	async fn target_status(&self, target: TargetId, failures: usize) -> Result<TargetStatus>;
+	/// See [`PackageService::events`].
+	async fn events(&self, package: Uuid) -> Result<Vec<StatusEventRow>>;
+	/// See [`PackageService::override_state`].
+	async fn override_state(
+		&self,
+		branch: BranchRef,
+		name: &str,
+		state: &StateOverride,
+		reason: &str,
+		principal: &Principal,
+	) -> Result<Option<PkgRow>>;
+	/// See [`PackageService::set_hold`].
+	async fn set_hold(
+		&self,
+		branch: BranchRef,
+		name: &str,
+		reason: Option<String>,
+	) -> Result<Option<PkgRow>>;
+	/// See [`PackageService::diff`].
+	async fn diff(&self, branch: BranchRef, packages: &[EvaluatedPackage]) -> Result<PackageDiff>;
+}
+
+/// Service for packages of branches.
+#[derive(Debug)]
+pub struct PackageService {
+	db: Arc<DatabaseService>,
+	target: Arc<TargetService>,
+}
+
+impl PackageService {
+	pub fn new(db: Arc<DatabaseService>, target: Arc<TargetService>) -> Self {
+		Self { db, target }
+	}
+
+	/// Lists packages of a branch, ordered by name.
+	pub async fn list(&self, branch: BranchRef) -> Result<Vec<PkgRow>> {
+		let mut conn = self.db.get().await?;
+		Ok(conn
+			.load_select(
+				dsl::pkg
+					.filter(dsl::branch.eq(branch))
+					.order(dsl::name.asc()),
+			)
+			.await?)
+	}
+
+	/// Lists at most `limit` packages of a branch with names after `after`,
+	/// ordered by name.
+	///
+	/// Large branches are read page by page with this, without loading
+	/// all packages at once.
+	pub async fn list_page(
+		&self,
+		branch: BranchRef,
+		after: Option<String>,
+		limit: usize,
+	) -> Result<Vec<PkgRow>> {
+		let mut conn = self.db.get().await?;
+		// names are never empty, so the first page starts after an empty name
+		let after = after.unwrap_or_default();
+		Ok(conn
+			.load_select(
+				dsl::pkg
+					.filter(dsl::branch.eq(branch))
+					.filter(dsl::name.gt(after))
+					.order(dsl::name.asc())
+					.limit(limit.try_into().unwrap_or(i64::MAX)),
+			)
+			.await?)
+	}
+
+	/// Compares packages of two branches, see [`crate::branch_compare::compare`].
+	pub async fn compare(
+		&self,
+		branch: BranchRef,
+		other: BranchRef,
+		after: Option<String>,
+		limit: usize,
+		max_targets: usize,
+	) -> Result<ComparisonPage> {
+		let mut conn = self.db.get().await?;
+		branch_compare::compare(&mut conn, branch, other, after, limit, max_targets).await
+	}
+
+	/// Lists packages of a branch failing on any target,
+	/// see [`crate::problem::problems`].
+	pub async fn problems(&self, branch: BranchRef) -> Result<Vec<PackageProblem>> {
+		let mut conn = self.db.get().await?;
+		problem::problems(&mut conn, branch).await
+	}
+
+	/// Returns the health of a target in all branches,
+	/// see [`crate::target_status::target_status`].
+	pub async fn target_status(&self, target: TargetId, failures: usize) -> Result<TargetStatus> {
+		let mut conn = self.db.get().await?;
+		target_status::target_status(&mut conn, target, failures).await
+	}
+
+	/// Lists states on build targets of packages.
+	pub async fn list_targets(&self, packages: Vec<Uuid>) -> Result<Vec<PkgTargetRow>> {
+		let mut conn = self.db.get().await?;
+		let packages = packages.into_iter().map(XUuidVal).collect::<Vec<_>>();
+		Ok(conn
+			.load_select(target_dsl::pkg_target.filter(target_dsl::package.eq_any(packages)))
+			.await?)
+	}
+
+	/// Compares packages of a branch with newly evaluated packages,
+	/// e.g. after its base branch has advanced.
+	pub async fn diff(
+		&self,
+		branch: BranchRef,
+		packages: &[EvaluatedPackage],
+	) -> Result<PackageDiff> {
+		let rows = self.list(branch).await?;
+		PackageDiff::compare(&rows, packages)
+	}
+
+	/// Replaces packages of a branch with newly evaluated packages.
+	///
+	/// Only packages whose input hashes are changed are marked as dirty on all
+	/// targets, while states of unchanged packages are kept. Added packages are
+	/// dirty on `targets`, except deprecated ones. Removed packages which have
+	/// been built are handled by the [`SqlRemovalPolicy`] of the branch.
+	/// Held packages are not changed.
+	pub async fn apply(
+		&self,
+		branch: BranchRef,
+		packages: Vec<EvaluatedPackage>,
+		targets: &[TargetId],
+		actor: &StatusActor,
+	) -> Result<PackageDiff> {
+		let targets = targets
+			.iter()
+			.copied()
+			.filter(|target| !self.target.is_deprecated(*target))
+			.collect::<Vec<_>>();
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			let rows: Vec<PkgRow> = conn
+				.load_select(dsl::pkg.filter(dsl::branch.eq(branch)))
+				.await?;
+			let mut diff = PackageDiff::compare(&rows, &packages)?;
+			let ids = rows
+				.iter()
+				.map(|row| (row.name.as_str(), row.id))
+				.collect::<HashMap<_, _>>();
+			let held = rows
+				.iter()
+				.filter(|row| row.is_held())
+				.map(|row| row.name.as_str())
+				.collect::<HashSet<_>>();
+
+			let removed = Self::handle_removed(conn, branch, &rows, &mut diff).await?;
+			conn.execute(
+				delete(finding_dsl::pkg_finding).filter(finding_dsl::package.eq_any(&removed)),
+			)
+			.await?;
+			conn.execute(
+				delete(target_dsl::pkg_target).filter(target_dsl::package.eq_any(&removed)),
+			)
+			.await?;
+			conn.execute(
+				delete(event_dsl::status_event).filter(event_dsl::package.eq_any(&removed)),
+			)
+			.await?;
+			conn.execute(delete(dsl::pkg).filter(dsl::id.eq_any(&removed)))
+				.await?;
+			// running jobs fail by themselves as their packages are gone
+			conn.execute(
+				delete(job_dsl::job_queue).filter(
+					job_dsl::subject_pkg
+						.eq_any(&removed)
+						.and(job_dsl::started_at.is_null()),
+				),
+			)
+			.await?;
+
+			let time = OffsetDateTime::now_utc();
+			let time = PrimitiveDateTime::new(time.date(), time.time());
+			for pkg in &packages {
+				if held.contains(pkg.name.as_str()) {
+					continue;
+				}
+				let data = XJsonVal(pkg.data.to_json()?);
+				if let Some(id) = ids.get(pkg.name.as_str()) {
+					conn.execute(update(dsl::pkg).filter(dsl::id.eq(*id)).set((
+						dsl::section.eq(&pkg.section),
+						dsl::data.eq(data),
+						dsl::updated_at.eq(time),
+					)))
+					.await?;
+					continue;
+				}
+				let id = XUuidVal(Uuid::now_v7());
+				conn.execute(insert_into(dsl::pkg).values(PkgRow {
+					id,
+					branch,
+					name: pkg.name.clone(),
+					section: pkg.section.clone(),
+					status: SqlPackageStatus::Ready,
+					status_msg: None,
+					data,
+					created_at: time,
+					updated_at: time,
+					hold_reason: None,
+				}))
+				.await?;
+				Self::record_status(conn, id.0, SqlPackageStatus::Ready, None, actor).await?;
+				let rows = targets
+					.iter()
+					.map(|target| PkgTargetRow {
+						id: XUuidVal(Uuid::now_v7()),
+						branch,
+						package: id,
+						target: *target as i64,
+						status: SqlPackageTargetState::Dirty,
+						data: XJsonVal(serde_json::json!({})),
+						created_at: time,
+						updated_at: time,
+						revision: 0,
+					})
+					.collect::<Vec<_>>();
+				conn.execute(insert_into(target_dsl::pkg_target).values(rows))
+					.await?;
+			}
+
+			let dirty = diff
+				.dirty
+				.iter()
+				.map(|name| ids[name.as_str()])
+				.collect::<Vec<_>>();
+			conn.execute(
+				update(target_dsl::pkg_target)
+					.filter(target_dsl::package.eq_any(dirty))
+					.set((
+						target_dsl::status.eq(SqlPackageTargetState::Dirty),
+						target_dsl::revision.eq(target_dsl::revision + 1),
+						target_dsl::updated_at.eq(time),
+					)),
+			)
+			.await?;
+			Ok(diff)
+		})
+		.await
+	}
+
+	/// Applies the removal policy of a branch to removed packages which have been
+	/// built, and returns IDs of packages to be dropped.
+	///
+	/// Kept packages are moved from [`PackageDiff::removed`] to [`PackageDiff::kept`].
+	async fn handle_removed(
+		conn: &mut BoxedSqlConn,
+		branch: BranchRef,
+		rows: &[PkgRow],
+		diff: &mut PackageDiff,
+	) -> Result<Vec<XUuidVal>> {
+		let removed = rows
+			.iter()
+			.filter(|row| diff.removed.contains(&row.name))
+			.collect::<Vec<_>>();
+		let targets: Vec<PkgTargetRow> = conn
+			.load_select(
+				target_dsl::pkg_target
+					.filter(target_dsl::package.eq_any(removed.iter().map(|row| row.id))),
+			)
+			.await?;
+		let built = |row: &PkgRow| {
+			targets.iter().any(|target| {
+				target.package == row.id && target.status != SqlPackageTargetState::Dirty
+			})
+		};
+		let (built, unbuilt): (Vec<&PkgRow>, Vec<&PkgRow>) =
+			removed.into_iter().partition(|row| built(row));
+		let mut dropped = unbuilt.iter().map(|row| row.id).collect::<Vec<_>>();
+		if built.is_empty() {
+			return Ok(dropped);
+		}
+
+		let policy = conn
+			.get_result::<_, SqlRemovalPolicy>(
+				branch_dsl::branch
+					.filter(branch_dsl::id.eq(branch))
+					.select(branch_dsl::removal_policy),
+			)
+			.await
+			.optional()?
+			.unwrap_or(SqlRemovalPolicy::Drop);
+		match policy {
+			SqlRemovalPolicy::Drop => dropped.extend(built.iter().map(|row| row.id)),
+			SqlRemovalPolicy::Archive => {
+				let time = OffsetDateTime::now_utc();
+				let time = PrimitiveDateTime::new(time.date(), time.time());
+				let mut archived = Vec::with_capacity(built.len());
+				for row in &built {
+					let states = targets
+						.iter()
+						.filter(|target| target.package == row.id)
+						.map(|target| ArchivedTarget {
+							target: target.target as TargetId,
+							status: target.status as u8,
+							data: target.data.0.clone(),
+						})
+						.collect::<Vec<_>>();
+					archived.push(PkgArchiveRow {
+						id: row.id,
+						branch,
+						name: row.name.clone(),
+						data: row.data.clone(),
+						targets: XJsonVal(serde_json::to_value(states)?),
+						archived_at: time,
+					});
+				}
+				conn.execute(insert_into(archive_dsl::pkg_archive).values(archived))
+					.await?;
+				info!(
+					branch,
+					count = built.len(),
+					"archived packages removed upstream"
+				);
+				dropped.extend(built.iter().map(|row| row.id));
+			}
+			SqlRemovalPolicy::KeepWithWarning => {
+				diff.kept = built.iter().map(|row| row.name.clone()).collect();
+				diff.removed.retain(|name| !diff.kept.contains(name));
+				warn!(branch, packages = ?diff.kept, "kept packages removed upstream");
+			}
+		}
+		Ok(dropped)
+	}
+
+	/// Sets the status of a package, recording the transition.
+	pub async fn set_status(
+		&self,
+		package: Uuid,
+		status: SqlPackageStatus,
+		message: Option<&str>,
+		actor: &StatusActor,
+	) -> Result<()> {
+		let time = OffsetDateTime::now_utc();
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			conn.execute(update(dsl::pkg).filter(dsl::id.eq(XUuidVal(package))).set((
+				dsl::status.eq(status),
+				dsl::status_msg.eq(message),
+				dsl::updated_at.eq(PrimitiveDateTime::new(time.date(), time.time())),
+			)))
+			.await?;
+			Self::record_status(conn, package, status, message, actor).await
+		})
+		.await
+	}
+
+	/// Records a status transition of a package.
+	async fn record_status(
+		conn: &mut BoxedSqlConn,
+		package: Uuid,
+		status: SqlPackageStatus,
+		message: Option<&str>,
+		actor: &StatusActor,
+	) -> Result<()> {
+		let time = OffsetDateTime::now_utc();
+		conn.execute(
+			insert_into(event_dsl::status_event).values(NewStatusEventRow {
+				package: XUuidVal(package),
+				status,
+				message,
+				actor: &actor.to_string(),
+				created_at: PrimitiveDateTime::new(time.date(), time.time()),
+			}),
+		)
+		.await?;
+		Ok(())
+	}
+
+	/// Lists status transitions of a package, oldest first.
+	pub async fn events(&self, package: Uuid) -> Result<Vec<StatusEventRow>> {
+		let mut conn = self.db.get().await?;
+		Ok(conn
+			.load_select(
+				event_dsl::status_event
+					.filter(event_dsl::package.eq(XUuidVal(package)))
+					.order(event_dsl::id.asc()),
+			)
+			.await?)
+	}
+
+	/// Transitions the state of a package on a target.
+	///
+	/// The transition is only applied if the state is still at `revision`, so that
+	/// results of stale builds never overwrite states written later, e.g. by retries.
+	/// Returns the new revision.
+	pub async fn transition_target(
+		conn: &mut BoxedSqlConn,
+		id: Uuid,
+		revision: i64,
+		status: SqlPackageTargetState,
+		data: &PkgTargetData,
+	) -> Result<i64> {
+		let time = OffsetDateTime::now_utc();
+		let updated = conn
+			.execute(
+				update(target_dsl::pkg_target)
+					.filter(target_dsl::id.eq(XUuidVal(id)))
+					.filter(target_dsl::revision.eq(revision))
+					.set((
+						target_dsl::status.eq(status),
+						target_dsl::data.eq(XJsonVal(data.to_json()?)),
+						target_dsl::revision.eq(revision + 1),
+						target_dsl::updated_at.eq(PrimitiveDateTime::new(time.date(), time.time())),
+					)),
+			)
+			.await?;
+		if updated == 0 {
+			return Err(PackageError::Conflict(id, revision).into());
+		}
+		Ok(revision + 1)
+	}
+
+	/// Finds a package of a branch by name.
+	pub async fn find(&self, branch: BranchRef, name: &str) -> Result<Option<PkgRow>> {
+		let mut conn = self.db.get().await?;
+		Ok(conn
+			.load_one_select(
+				dsl::pkg
+					.filter(dsl::branch.eq(branch))
+					.filter(dsl::name.eq(name)),
+			)
+			.await
+			.optional()?)
+	}
+
+	/// Forces a package or its state on a target into `state`, e.g. marks a
+	/// flaky build as ready after manual verification.
+	///
+	/// The principal must be allowed by the ACL of the branch. The override is
+	/// recorded in the audit log and status events of the package. States on
+	/// targets are bumped to a new revision, so that results of running builds
+	/// are rejected.
+	///
+	/// Returns the package, or [`None`] if it or its state on the target is not found.
+	pub async fn override_state(
+		&self,
+		branch: BranchRef,
+		name: &str,
+		state: &StateOverride,
+		reason: &str,
+		principal: &Principal,
+	) -> Result<Option<PkgRow>> {
+		let mut conn = self.db.get().await?;
+		let time = OffsetDateTime::now_utc();
+		let time = PrimitiveDateTime::new(time.date(), time.time());
+		let actor = StatusActor::Caller(principal.name().to_string());
+		let found = conn
+			.transaction::<_, crate::BackendError, _>(async |conn| {
+				BranchAclService::check(conn, branch, principal).await?;
+				let Some(pkg): Option<PkgRow> = conn
+					.load_one_select(
+						dsl::pkg
+							.filter(dsl::branch.eq(branch))
+							.filter(dsl::name.eq(name)),
+					)
+					.await
+					.optional()?
+				else {
+					return Ok(false);
+				};
+				let (status, message, detail) = match state {
+					StateOverride::Package(status, message) => {
+						conn.execute(update(dsl::pkg).filter(dsl::id.eq(pkg.id)).set((
+							dsl::status.eq(status),
+							dsl::status_msg.eq(message),
+							dsl::updated_at.eq(time),
+						)))
+						.await?;
+						let detail = serde_json::json!({
+							"status": *status as u8,
+							"message": message,
+							"reason": reason,
+						});
+						(*status, format!("overridden: {reason}"), detail)
+					}
+					StateOverride::Target(target, target_state) => {
+						let updated = conn
+							.execute(
+								update(target_dsl::pkg_target)
+									.filter(target_dsl::package.eq(pkg.id))
+									.filter(target_dsl::target.eq(*target as i64))
+									.set((
+										target_dsl::status.eq(target_state),
+										target_dsl::revision.eq(target_dsl::revision + 1),
+										target_dsl::updated_at.eq(time),
+									)),
+							)
+							.await?;
+						if updated == 0 {
+							return Ok(false);
+						}
+						let detail = serde_json::json!({
+							"target": target,
+							"state": *target_state as u8,
+							"reason": reason,
+						});
+						let message = format!(
+							"state on target {target} overridden to {target_state:?}: {reason}"
+						);
+						(pkg.status, message, detail)
+					}
+				};
+				Self::record_status(conn, pkg.id.0, status, Some(&message), &actor).await?;
+				let subject = format!("pkg:{}", pkg.id.0);
+				audit::record(conn, principal.name(), "package.override", &subject, detail).await?;
+				Ok(true)
+			})
+			.await?;
+		if !found {
+			return Ok(None);
+		}
+		warn!(
+			branch,
+			package = name,
+			?state,
+			reason,
+			"overrode package state"
+		);
+		drop(conn);
+		self.find(branch, name).await
+	}
+
+	/// Holds a package from automatic syncs and builds, or releases it if
+	/// `reason` is [`None`].
+	///
+	/// Returns the updated package, or [`None`] if it is not found.
+	pub async fn set_hold(
+		&self,
+		branch: BranchRef,
+		name: &str,
+		reason: Option<String>,
+	) -> Result<Option<PkgRow>> {
+		let mut conn = self.db.get().await?;
+		let updated = conn
+			.execute(
+				update(dsl::pkg)
+					.filter(dsl::branch.eq(branch))
+					.filter(dsl::name.eq(name))
+					.set(dsl::hold_reason.eq(&reason)),
+			)
+			.await?;
+		if updated == 0 {
+			return Ok(None);
+		}
+		match &reason {
+			Some(reason) => info!(branch, package = name, %reason, "held package"),
+			None => info!(branch, package = name, "released package"),
+		}
+		drop(conn);
+		self.find(branch, name).await
+	}
+}
+
+#[async_trait]
+impl PackageApi for PackageService {
+	async fn list(&self, branch: BranchRef) -> Result<Vec<PkgRow>> {
+		PackageService::list(self, branch).await
+	}
+
+	async fn find(&self, branch: BranchRef, name: &str) -> Result<Option<PkgRow>> {
+		PackageService::find(self, branch, name).await
+	}
+
+	async fn list_page(
+		&self,
+		branch: BranchRef,
+		after: Option<String>,
+		limit: usize,
+	) -> Result<Vec<PkgRow>> {
+		PackageService::list_page(self, branch, after, limit).await
+	}
+
+	async fn list_targets(&self, packages: Vec<Uuid>) -> Result<Vec<PkgTargetRow>> {
+		PackageService::list_targets(self, packages).await
+	}
+
+	async fn compare(
+		&self,
+		branch: BranchRef,
+		other: BranchRef,
+		after: Option<String>,
+		limit: usize,
+		max_targets: usize,
+	) -> Result<ComparisonPage> {
+		PackageService::compare(self, branch, other, after, limit, max_targets).await
+	}
+
+	async fn problems(&self, branch: BranchRef) -> Result<Vec<PackageProblem>> {
+		PackageService::problems(self, branch).await
+	}
+
+	async fn target_status(&self, target: TargetId, failures: usize) -> Result<TargetStatus> {
+		PackageService::target_status(self, target, failures).await
+	}
+
+	async fn events(&self, package: Uuid) -> Result<Vec<StatusEventRow>> {
+		PackageService::events(self, package).await
+	}
+
+	async fn override_state(
+		&self,
+		branch: BranchRef,
+		name: &str,
+		state: &StateOverride,
+		reason: &str,
+		principal: &Principal,
+	) -> Result<Option<PkgRow>> {
+		PackageService::override_state(self, branch, name, state, reason, principal).await
+	}
+
+	async fn set_hold(
+		&self,
+		branch: BranchRef,
+		name: &str,
+		reason: Option<String>,
+	) -> Result<Option<PkgRow>> {
+		PackageService::set_hold(self, branch, name, reason).await
+	}
+
+	async fn diff(&self, branch: BranchRef, packages: &[EvaluatedPackage]) -> Result<PackageDiff> {
+		PackageService::diff(self, branch, packages).await
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum PackageError {
+	#[error("state {0} of package target has been changed since revision {1}")]
+	Conflict(Uuid, i64),
+}
+
+#[cfg(test)]
+mod test {
+	use fabricia_common_model::branch::RemovalPolicy;
+	use serde_json::json;
+
+	use crate::{
+		branch::BranchConfigInfo,
+		target::TargetInfo,
+		test::{insert_test_package, test_config, test_env, test_env_with_config},
+	};
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_list_empty() {
+		let env = test_env().await;
+		assert!(env.package.list(1).await.unwrap().is_empty());
+		assert!(env.package.find(1, "bash").await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_list_page() {
+		let env = test_env().await;
+		let mut db = env.database.get().await.unwrap();
+		for name in ["bash", "curl", "zsh"] {
+			insert_test_package(&mut db, 1, name, json!({})).await;
+		}
+		drop(db);
+
+		let page = env.package.list_page(1, None, 2).await.unwrap();
+		let names = page.iter().map(|pkg| pkg.name.as_str()).collect::<Vec<_>>();
+		assert_eq!(names, ["bash", "curl"]);
+		let page = env
+			.package
+			.list_page(1, Some("curl".to_string()), 2)
+			.await
+			.unwrap();
+		assert_eq!(page.len(), 1);
+		assert_eq!(page[0].name, "zsh");
+		let targets = env.package.list_targets(vec![page[0].id.0]).await.unwrap();
+		assert!(targets.is_empty());
+	}
+
+	fn evaluated(name: &str, version: &str, dependencies: &[&str]) -> EvaluatedPackage {
+		EvaluatedPackage {
+			name: name.to_string(),
+			section: "base".to_string(),
+			data: PkgData {
+				version: version.to_string(),
+				dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+				..Default::default()
+			},
+		}
+	}
+
+	#[tokio::test]
+	async fn test_apply() {
+		let env = test_env().await;
+		let targets = [TargetInfo::make_id("arch1")];
+		let packages = vec![
+			evaluated("bash", "5.2.37", &["glibc"]),
+			evaluated("glibc", "2.40", &[]),
+			evaluated("zsh", "5.9", &[]),
+		];
+		let diff = env
+			.package
+			.apply(1, packages, &targets, &StatusActor::System)
+			.await
+			.unwrap();
+		assert_eq!(diff.added, ["bash", "glibc", "zsh"]);
+
+		// mark all targets as built
+		let mut db = env.database.get().await.unwrap();
+		db.execute(
+			update(target_dsl::pkg_target).set(target_dsl::status.eq(SqlPackageTargetState::Ready)),
+		)
+		.await
+		.unwrap();
+		drop(db);
+
+		// the base branch advanced, bumping glibc and removing zsh
+		let packages = vec![
+			evaluated("bash", "5.2.37", &["glibc"]),
+			evaluated("glibc", "2.41", &[]),
+			evaluated("curl", "8.11.1", &[]),
+		];
+		let diff = env.package.diff(1, &packages).await.unwrap();
+		assert_eq!(
+			diff,
+			PackageDiff {
+				added: vec!["curl".to_string()],
+				removed: vec!["zsh".to_string()],
+				dirty: vec!["bash".to_string(), "glibc".to_string()],
+				kept: vec![],
+				held: vec![],
+			}
+		);
+		assert_eq!(
+			env.package
+				.apply(1, packages, &targets, &StatusActor::System)
+				.await
+				.unwrap(),
+			diff
+		);
+		assert!(env.package.find(1, "zsh").await.unwrap().is_none());
+
+		// unchanged packages are not dirty
+		let packages = vec![
+			evaluated("bash", "5.2.37", &["glibc"]),
+			evaluated("glibc", "2.41", &[]),
+			evaluated("curl", "8.11.1", &[]),
+		];
+		assert_eq!(
+			env.package.diff(1, &packages).await.unwrap(),
+			PackageDiff::default()
+		);
+		let rows = env.package.list(1).await.unwrap();
+		let states = env
+			.package
+			.list_targets(rows.iter().map(|pkg| pkg.id.0).collect())
+			.await
+			.unwrap();
+		let dirty = states
+			.iter()
+			.filter(|state| state.status == SqlPackageTargetState::Dirty)
+			.count();
+		assert_eq!(states.len(), 3);
+		assert_eq!(dirty, 3);
+	}
+
+	#[tokio::test]
+	async fn test_deprecated_target() {
+		let mut config = test_config();
+		config.target[1].deprecated = true;
+		let env = test_env_with_config(config).await;
+		let targets = [TargetInfo::make_id("arch1"), TargetInfo::make_id("arch2")];
+		let packages = vec![evaluated("bash", "5.2.37", &[])];
+		env.package
+			.apply(1, packages, &targets, &StatusActor::System)
+			.await
+			.unwrap();
+		let pkg = env.package.find(1, "bash").await.unwrap().unwrap();
+		let rows = env.package.list_targets(vec![pkg.id.0]).await.unwrap();
+		assert_eq!(rows.len(), 1);
+		assert_eq!(rows[0].target as TargetId, targets[0]);
+	}
+
+	#[tokio::test]
+	async fn test_hold() {
+		let env = test_env().await;
+		let targets = [TargetInfo::make_id("arch1")];
+		let packages = vec![
+			evaluated("bash", "5.2.37", &[]),
+			evaluated("glibc", "2.40", &[]),
+		];
+		env.package
+			.apply(1, packages, &targets, &StatusActor::System)
+			.await
+			.unwrap();
+		let reason = Some("bisecting".to_string());
+		let glibc = env
+			.package
+			.set_hold(1, "glibc", reason.clone())
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(glibc.hold_reason, reason);
+		assert!(
+			env.package
+				.set_hold(1, "zsh", reason)
+				.await
+				.unwrap()
+				.is_none()
+		);
+
+		// held packages are neither removed nor re-evaluated
+		let diff = env
+			.package
+			.apply(1, vec![], &targets, &StatusActor::System)
+			.await
+			.unwrap();
+		assert_eq!(diff.removed, ["bash"]);
+		assert_eq!(diff.held, ["glibc"]);
+		let packages = vec![evaluated("glibc", "2.41", &[])];
+		let diff = env
+			.package
+			.apply(1, packages.clone(), &targets, &StatusActor::System)
+			.await
+			.unwrap();
+		assert!(diff.dirty.is_empty());
+		assert_eq!(diff.held, ["glibc"]);
+		let row = env.package.find(1, "glibc").await.unwrap().unwrap();
+		assert_eq!(row.pkg_data().unwrap().version, "2.40");
+
+		// released packages are synchronized again
+		env.package.set_hold(1, "glibc", None).await.unwrap();
+		let diff = env
+			.package
+			.apply(1, packages, &targets, &StatusActor::System)
+			.await
+			.unwrap();
+		assert_eq!(diff.dirty, ["glibc"]);
+		assert!(diff.held.is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_removal_policy() {
+		let env = test_env().await;
+		let targets = [TargetInfo::make_id("arch1")];
+		let packages = vec![
+			evaluated("bash", "5.2.37", &[]),
+			evaluated("zsh", "5.9", &[]),
+		];
+		env.package
+			.apply(1, packages, &targets, &StatusActor::System)
+			.await
+			.unwrap();
+		let zsh = env.package.find(1, "zsh").await.unwrap().unwrap();
+		let mut db = env.database.get().await.unwrap();
+		db.execute(
+			update(target_dsl::pkg_target)
+				.filter(target_dsl::package.eq(zsh.id))
+				.set(target_dsl::status.eq(SqlPackageTargetState::Ready)),
+		)
+		.await
+		.unwrap();
+		drop(db);
+
+		// built packages are kept, while unbuilt ones are dropped
+		let config = |policy| BranchConfigInfo {
+			removal_policy: Some(policy),
+			..Default::default()
+		};
+		env.branch
+			.update_config(
+				1,
+				&config(RemovalPolicy::KeepWithWarning),
+				None,
+				&Principal::system(),
+			)
+			.await
+			.unwrap();
+		let diff = env
+			.package
+			.apply(1, vec![], &targets, &StatusActor::System)
+			.await
+			.unwrap();
+		assert_eq!(diff.removed, ["bash"]);
+		assert_eq!(diff.kept, ["zsh"]);
+		assert!(env.package.find(1, "bash").await.unwrap().is_none());
+		assert!(env.package.find(1, "zsh").await.unwrap().is_some());
+
+		// built packages are archived before dropped
+		env.branch
+			.update_config(
+				1,
+				&config(RemovalPolicy::Archive),
+				None,
+				&Principal::system(),
+			)
+			.await
+			.unwrap();
+		let diff = env
+			.package
+			.apply(1, vec![], &targets, &StatusActor::System)
+			.await
+			.unwrap();
+		assert_eq!(diff.removed, ["zsh"]);
+		assert!(diff.kept.is_empty());
+		assert!(env.package.find(1, "zsh").await.unwrap().is_none());
+		let mut db = env.database.get().await.unwrap();
+		let archived: PkgArchiveRow = db.load_one_select(archive_dsl::pkg_archive).await.unwrap();
+		assert_eq!(archived.id, zsh.id);
+		assert_eq!(archived.name, "zsh");
+		assert_eq!(
+			archived.targets().unwrap(),
+			[ArchivedTarget {
+				target: targets[0],
+				status: SqlPackageTargetState::Ready as u8,
+				data: json!({}),
+			}]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_status_events() {
+		let env = test_env().await;
+		let packages = vec![evaluated("bash", "5.2.37", &[])];
+		env.package
+			.apply(1, packages, &[], &StatusActor::System)
+			.await
+			.unwrap();
+		let pkg = env.package.find(1, "bash").await.unwrap().unwrap();
+		let job = Uuid::now_v7();
+		env.package
+			.set_status(
+				pkg.id.0,
+				SqlPackageStatus::Error,
+				Some("bad metadata"),
+				&StatusActor::Job(job),
+			)
+			.await
+			.unwrap();
+		env.package
+			.set_status(
+				pkg.id.0,
+				SqlPackageStatus::Dirty,
+				None,
+				&StatusActor::User("alice".to_string()),
+			)
+			.await
+			.unwrap();
+
+		let pkg = env.package.find(1, "bash").await.unwrap().unwrap();
+		assert_eq!(pkg.status, SqlPackageStatus::Dirty);
+		assert_eq!(pkg.status_msg, None);
+		let events = env.package.events(pkg.id.0).await.unwrap();
+		let events = events
+			.iter()
+			.map(|event| (event.status, event.message.as_deref(), event.actor.as_str()))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			events,
+			[
+				(SqlPackageStatus::Ready, None, "system"),
+				(
+					SqlPackageStatus::Error,
+					Some("bad metadata"),
+					format!("job:{job}").as_str()
+				),
+				(SqlPackageStatus::Dirty, None, "user:alice"),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_override_state() {
+		let env = test_env().await;
+		let target = TargetInfo::make_id("arch1");
+		let packages = vec![evaluated("bash", "5.2.37", &[])];
+		env.package
+			.apply(1, packages, &[target], &StatusActor::System)
+			.await
+			.unwrap();
+		let caller = Principal::anonymous().with_identity("token", "ci");
+
+		let state = StateOverride::Target(target, SqlPackageTargetState::Ready);
+		let pkg = env
+			.package
+			.override_state(1, "bash", &state, "verified manually", &caller)
+			.await
+			.unwrap()
+			.unwrap();
+		let rows = env.package.list_targets(vec![pkg.id.0]).await.unwrap();
+		assert_eq!(rows[0].status, SqlPackageTargetState::Ready);
+		assert_eq!(rows[0].revision, 1);
+
+		let state = StateOverride::Package(SqlPackageStatus::Error, Some("broken".to_string()));
+		let pkg = env
+			.package
+			.override_state(1, "bash", &state, "upstream is broken", &caller)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(pkg.status, SqlPackageStatus::Error);
+		assert_eq!(pkg.status_msg.as_deref(), Some("broken"));
+
+		// unknown packages and targets
+		assert!(
+			env.package
+				.override_state(1, "zsh", &state, "", &caller)
+				.await
+				.unwrap()
+				.is_none()
+		);
+		let state =
+			StateOverride::Target(TargetInfo::make_id("riscv64"), SqlPackageTargetState::Ready);
+		assert!(
+			env.package
+				.override_state(1, "bash", &state, "", &caller)
+				.await
+				.unwrap()
+				.is_none()
+		);
+
+		let events = env.package.events(pkg.id.0).await.unwrap();
+		assert_eq!(events.len(), 3);
+		assert!(events[1..].iter().all(|event| event.actor == "token:ci"));
+		let mut db = env.database.get().await.unwrap();
+		let logs = audit::list(&mut db, &format!("pkg:{}", pkg.id.0), 10)
+			.await
+			.unwrap();
+		assert_eq!(logs.len(), 2);
+		assert_eq!(logs[0].actor, "token:ci");
+		assert_eq!(logs[0].detail.0["reason"], "upstream is broken");
+	}
+
+	#[test]
+	fn test_compare_versions() {
+		assert_eq!(compare_versions("1.2.10", "1.2.9"), Ordering::Greater);
+		assert_eq!(compare_versions("1.02", "1.2"), Ordering::Equal);
+		assert_eq!(compare_versions("5.6", "5.6.0"), Ordering::Less);
+		assert_eq!(compare_versions("2.0", "2.a"), Ordering::Greater);
+		assert_eq!(compare_versions("1.0-rc1", "1.0-rc2"), Ordering::Less);
+	}
+
+	#[test]
+	fn test_pkg_data() {
+		let data = PkgData {
+			version: "5.2.37".to_string(),
+			release: 1,
+			epoch: 0,
+			dependencies: vec!["glibc".to_string()],
+			srcs: vec!["tbl::https://ftp.gnu.org/gnu/bash/bash-5.2.37.tar.gz".to_string()],
+			checksums: vec![format!("sha256::{}", "0".repeat(64))],
+			tree: Some(GitOid::Sha1([1; 20])),
+			changelog: vec![ChangelogEntry {
+				version: "5.2.37-1".to_string(),
+				author: "Alice <alice@example.com>".to_string(),
+				message: "update to 5.2.37".to_string(),
+			}],
+		};
+		let value = data.to_json().unwrap();
+		assert_eq!(value["schema"], json!(1));
+		assert_eq!(PkgData::from_json(value).unwrap(), data);
+
+		// unversioned data
+		assert_eq!(
+			PkgData::from_json(json!({ "version": "1.0" })).unwrap(),
+			PkgData {
+				version: "1.0".to_string(),
+				..Default::default()
+			}
+		);
+		assert!(PkgData::from_json(json!({ "schema": 2 })).is_err());
+	}
+
+	#[test]
+	fn test_pkg_target_data() {
+		let data = PkgTargetData {
+			last_build: Some(Uuid::now_v7()),
+			artifacts: vec!["bash_5.2.37-1_amd64.deb".to_string()],
+			cache_key: Some("0".repeat(64)),
+			artifact_hashes: [("bash_5.2.37-1_amd64.deb".to_string(), "0".repeat(64))].into(),
+			builder: Some("runner-1".to_string()),
+			repro: Some(ReproState {
+				status: ReproStatus::Pending,
+				verify_build: None,
+				differing: vec![],
+			}),
+		};
+		let value = data.to_json().unwrap();
+		assert_eq!(PkgTargetData::from_json(value).unwrap(), data);
+		assert_eq!(
+			PkgTargetData::from_json(json!({})).unwrap(),
+			PkgTargetData::default()
+		);
+	}
+}
@@ -0,0 +1,173 @@
+//! Startup self-checks of deployments.
+//!
+//! Unlike [`BackendServices::new`](crate::BackendServices::new), checks do not
+//! apply migrations or change any state, so they can be run before upgrades.
+
+use std::fmt::{self, Display};
+
+use tokio::task::spawn_blocking;
+
+use crate::{
+	config::BackendConfig,
+	db::{
+		self,
+		service::{DatabaseError, DatabaseService},
+	},
+	redis::RedisService,
+	target::TargetService,
+};
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightCheck {
+	pub name: &'static str,
+	pub passed: bool,
+	/// Details of the outcome, or the error if failed.
+	pub message: String,
+}
+
+/// Outcomes of all checks, in the order they are run.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PreflightReport {
+	pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+	/// Returns whether all checks passed.
+	pub fn passed(&self) -> bool {
+		self.checks.iter().all(|check| check.passed)
+	}
+
+	fn push<E: Display>(&mut self, name: &'static str, result: Result<String, E>) {
+		let (passed, message) = match result {
+			Ok(message) => (true, message),
+			Err(error) => (false, error.to_string()),
+		};
+		self.checks.push(PreflightCheck {
+			name,
+			passed,
+			message,
+		});
+	}
+
+	fn pass(&mut self, name: &'static str, message: &str) {
+		self.push::<String>(name, Ok(message.to_string()));
+	}
+}
+
+impl Display for PreflightReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for check in &self.checks {
+			let status = if check.passed { "ok" } else { "FAILED" };
+			writeln!(f, "{:<12} {:<6} {}", check.name, status, check.message)?;
+		}
+		Ok(())
+	}
+}
+
+/// Checks the configuration, connectivity and migrations.
+///
+/// Failed checks are recorded in the report instead of aborting, so that
+/// all problems of a deployment are reported at once.
+pub async fn check(config: &BackendConfig) -> PreflightReport {
+	let mut report = PreflightReport::default();
+
+	report.push(
+		"targets",
+		TargetService::new(&config.target, &config.target_group).map(|_| {
+			format!(
+				"{} targets, {} target groups",
+				config.target.len(),
+				config.target_group.len()
+			)
+		}),
+	);
+
+	match &config.redis {
+		Some(redis) => {
+			let result = async {
+				let redis = RedisService::new(redis).await?;
+				let mut conn = redis.get().await?;
+				::redis::cmd("PING")
+					.query_async::<String>(&mut *conn)
+					.await?;
+				Ok::<_, crate::redis::RedisError>("connected".to_string())
+			}
+			.await;
+			report.push("redis", result);
+		}
+		None => report.pass("redis", "not configured"),
+	}
+
+	let conn = DatabaseService::connect(&config.database).await;
+	let conn = match conn {
+		Ok(conn) => {
+			report.pass("database", "connected");
+			conn
+		}
+		Err(error) => {
+			report.push("database", Err(error));
+			return report;
+		}
+	};
+	let pending = spawn_blocking(move || db::pending_migrations(conn))
+		.await
+		.map_err(DatabaseError::from)
+		.and_then(|result| result.map_err(DatabaseError::MigrationError));
+	let result = pending
+		.map_err(|error| error.to_string())
+		.and_then(|pending| {
+			if pending.is_empty() {
+				Ok("up to date".to_string())
+			} else {
+				let versions = pending.iter().map(|version| version.to_string());
+				Err(format!(
+					"{} pending: {}",
+					pending.len(),
+					versions.collect::<Vec<_>>().join(", ")
+				))
+			}
+		});
+	report.push("migrations", result);
+
+	report
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{db::service::DatabaseConfig, target::TargetGroupConfig, test::test_config};
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_check() {
+		let mut config = test_config();
+		config.database = DatabaseConfig {
+			url: "sqlite://:memory:".to_string(),
+			url_file: None,
+			max_connections: 1,
+			expand_only: false,
+		};
+		config.target_group.push(TargetGroupConfig {
+			name: "broken".into(),
+			targets: vec!["unknown".into()],
+		});
+
+		let report = check(&config).await;
+		assert!(!report.passed());
+		let passed = report
+			.checks
+			.iter()
+			.map(|check| (check.name, check.passed))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			passed,
+			vec![
+				("targets", false),
+				("redis", true),
+				("database", true),
+				("migrations", false),
+			]
+		);
+	}
+}
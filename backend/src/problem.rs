@@ -0,0 +1,194 @@
+//! Packages failing to build in a branch.
+//!
+//! Failures on all targets of a package are usually caused by the package
+//! itself or its dependencies, while failures on some targets are porting issues,
+//! so that they are triaged separately.
+
+use std::collections::BTreeMap;
+
+use diesel::{ExpressionMethods, JoinOnDsl, QueryDsl};
+
+use crate::{
+	Result,
+	branch::BranchRef,
+	db::{
+		BoxedSqlConn,
+		schema::{pkg, pkg_target},
+		utils::XUuidVal,
+	},
+	model::{PkgRow, PkgTargetRow},
+	package::SqlPackageTargetState,
+	target::TargetId,
+};
+
+/// States of package targets which are failures.
+const FAILED_STATES: [SqlPackageTargetState; 2] = [
+	SqlPackageTargetState::BuildFailed,
+	SqlPackageTargetState::Error,
+];
+
+/// How widely a package fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProblemScope {
+	/// The package fails on all of its targets.
+	Everywhere,
+	/// The package fails on some targets, and is ready or pending on others.
+	ArchSpecific,
+}
+
+/// A package failing on at least one target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageProblem {
+	pub name: String,
+	/// States of the package on all of its targets.
+	pub targets: BTreeMap<TargetId, SqlPackageTargetState>,
+}
+
+impl PackageProblem {
+	/// Classifies the failure, or returns [`None`] if no target has failed.
+	pub fn scope(&self) -> Option<ProblemScope> {
+		let failed = self
+			.targets
+			.values()
+			.filter(|state| FAILED_STATES.contains(state))
+			.count();
+		match failed {
+			0 => None,
+			n if n == self.targets.len() => Some(ProblemScope::Everywhere),
+			_ => Some(ProblemScope::ArchSpecific),
+		}
+	}
+
+	/// Returns targets which the package fails on.
+	pub fn failed(&self) -> impl Iterator<Item = TargetId> + '_ {
+		self.targets
+			.iter()
+			.filter(|(_, state)| FAILED_STATES.contains(state))
+			.map(|(id, _)| *id)
+	}
+}
+
+/// Lists packages of a branch failing on at least one target, ordered by name.
+pub async fn problems(conn: &mut BoxedSqlConn, branch: BranchRef) -> Result<Vec<PackageProblem>> {
+	let failing = conn
+		.load::<_, XUuidVal>(
+			pkg_target::table
+				.filter(pkg_target::branch.eq(branch))
+				.filter(pkg_target::status.eq_any(FAILED_STATES))
+				.select(pkg_target::package)
+				.distinct(),
+		)
+		.await?;
+	if failing.is_empty() {
+		return Ok(vec![]);
+	}
+
+	let rows: Vec<(PkgRow, PkgTargetRow)> = conn
+		.load_select(
+			pkg::table
+				.inner_join(pkg_target::table.on(pkg_target::package.eq(pkg::id)))
+				.filter(pkg::id.eq_any(failing))
+				.order((pkg::name.asc(), pkg_target::target.asc())),
+		)
+		.await?;
+	let mut problems: Vec<PackageProblem> = Vec::new();
+	for (pkg, target) in rows {
+		if problems
+			.last()
+			.is_none_or(|problem| problem.name != pkg.name)
+		{
+			problems.push(PackageProblem {
+				name: pkg.name,
+				targets: BTreeMap::new(),
+			});
+		}
+		let problem = problems.last_mut().expect("pushed above");
+		problem
+			.targets
+			.insert(target.target as TargetId, target.status);
+	}
+	Ok(problems)
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::BTreeMap;
+
+	use crate::{
+		namespace::DEFAULT_NAMESPACE_ID,
+		package::{PkgData, SqlPackageTargetState},
+		problem::{PackageProblem, ProblemScope, problems},
+		test::{insert_test_package, insert_test_target, test_env},
+	};
+
+	use SqlPackageTargetState::*;
+
+	#[test]
+	fn test_scope() {
+		let problem = |states: &[SqlPackageTargetState]| PackageProblem {
+			name: "bash".to_string(),
+			targets: states
+				.iter()
+				.enumerate()
+				.map(|(id, state)| (id as u64, *state))
+				.collect(),
+		};
+		assert_eq!(problem(&[Ready, Dirty]).scope(), None);
+		assert_eq!(problem(&[]).scope(), None);
+		assert_eq!(
+			problem(&[BuildFailed, Error]).scope(),
+			Some(ProblemScope::Everywhere)
+		);
+		let arch = problem(&[Ready, BuildFailed, Dirty]);
+		assert_eq!(arch.scope(), Some(ProblemScope::ArchSpecific));
+		assert_eq!(arch.failed().collect::<Vec<_>>(), [1]);
+	}
+
+	#[tokio::test]
+	async fn test_problems() {
+		let env = test_env().await;
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "main", Default::default())
+			.await
+			.unwrap();
+		let branch = env
+			.branch
+			.find_id(DEFAULT_NAMESPACE_ID, "main")
+			.await
+			.unwrap()
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+		assert!(problems(&mut db, branch).await.unwrap().is_empty());
+
+		let packages = [
+			("bash", [Ready, Ready]),
+			("glibc", [BuildFailed, Error]),
+			("zlib", [Ready, BuildFailed]),
+		];
+		for (name, states) in packages {
+			let data = PkgData::default().to_json().unwrap();
+			let pkg = insert_test_package(&mut db, branch, name, data).await;
+			for (target, status) in states.into_iter().enumerate() {
+				let target = 10 + target as i64;
+				insert_test_target(&mut db, &pkg, target, status, serde_json::json!({})).await;
+			}
+		}
+
+		let problems = problems(&mut db, branch).await.unwrap();
+		assert_eq!(
+			problems,
+			[
+				PackageProblem {
+					name: "glibc".to_string(),
+					targets: BTreeMap::from([(10, BuildFailed), (11, Error)]),
+				},
+				PackageProblem {
+					name: "zlib".to_string(),
+					targets: BTreeMap::from([(10, Ready), (11, BuildFailed)]),
+				},
+			]
+		);
+		assert_eq!(problems[0].scope(), Some(ProblemScope::Everywhere));
+		assert_eq!(problems[1].scope(), Some(ProblemScope::ArchSpecific));
+	}
+}
@@ -1,6 +1,8 @@
 // Redis connection manager.
 
-use std::{fmt::Debug, ops::Deref};
+use std::{
+	collections::BTreeMap, fmt::Debug, ops::Deref, path::PathBuf, sync::Mutex, time::Instant,
+};
 
 use deadpool::managed::{Manager, Object, Pool, PoolError, RecycleError, RecycleResult};
 use rand::Rng;
@@ -9,8 +11,14 @@ use rslock::{Lock, LockManager};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use time::Duration;
+use tracing::{Span, debug, field::Empty, info_span, warn};
+use uuid::Uuid;
 
-use crate::branch::BranchRef;
+use crate::{
+	branch::BranchRef,
+	secrets::{self, SecretError},
+	target::TargetId,
+};
 
 /// Configuration for [`RedisService`].
 #[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
@@ -19,10 +27,27 @@ pub struct RedisConfig {
 	/// URL to the Redis server.
 	///
 	/// For example: `redis://127.0.0.1/`.
+	///
+	/// The URL may reference a secret, see [`crate::secrets`].
+	#[serde(default)]
 	pub url: String,
+	/// File containing the URL, used instead of [`RedisConfig::url`] if set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub url_file: Option<PathBuf>,
 	/// The maximum number of connections managed by the pool.
 	#[serde(default = "default_max_conns")]
 	pub max_connections: usize,
+	/// Prefix of all keys and channels, e.g. `fabricia-staging:`.
+	///
+	/// Deployments sharing a Redis server must have distinct prefixes.
+	#[serde(default)]
+	pub key_prefix: String,
+	/// The maximum duration in seconds to wait for a lock.
+	///
+	/// Waiting for locks fails with [`RedisError::LockTimeout`] after the duration,
+	/// and never times out if not set.
+	#[serde(default)]
+	pub lock_max_wait: Option<u64>,
 }
 
 fn default_max_conns() -> usize {
@@ -30,6 +55,15 @@ fn default_max_conns() -> usize {
 }
 
 impl RedisConfig {
+	/// Resolves the URL from [`RedisConfig::url_file`] or secret references.
+	pub async fn resolve_secrets(&mut self) -> Result<(), SecretError> {
+		match &self.url_file {
+			Some(path) => self.url = secrets::read_file(path).await?,
+			None => secrets::resolve_in_place(&mut self.url).await?,
+		}
+		Ok(())
+	}
+
 	pub async fn make_client(&self) -> Result<Client, redis::RedisError> {
 		Ok(Client::open(self.url.as_str())?)
 	}
@@ -38,6 +72,23 @@ impl RedisConfig {
 pub struct RedisService {
 	pool: Pool<RedisManager>,
 	locker: LockManager,
+	prefix: String,
+	lock_max_wait: Option<std::time::Duration>,
+	/// Statistics of locks, by [`LockKey::kind`].
+	lock_stats: Mutex<BTreeMap<&'static str, LockStats>>,
+}
+
+/// Statistics of acquiring locks of a kind.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LockStats {
+	/// Count of acquired locks.
+	pub acquired: u64,
+	/// Count of locks which have been held by others on the first attempt.
+	pub contended: u64,
+	/// Count of attempts timed out, see [`RedisConfig::lock_max_wait`].
+	pub timeouts: u64,
+	/// Total duration of waiting for locks, in seconds.
+	pub wait_seconds: f64,
 }
 
 impl RedisService {
@@ -49,7 +100,18 @@ impl RedisService {
 
 		let locker = LockManager::new(vec![config.url.clone()]);
 
-		Ok(Self { pool, locker })
+		Ok(Self {
+			pool,
+			locker,
+			prefix: config.key_prefix.clone(),
+			lock_max_wait: config.lock_max_wait.map(std::time::Duration::from_secs),
+			lock_stats: Default::default(),
+		})
+	}
+
+	/// Returns a key or channel name with the configured prefix.
+	pub fn key(&self, key: &str) -> String {
+		format!("{}{key}", self.prefix)
 	}
 
 	pub async fn get(&self) -> RedisResult<RedisConnRef> {
@@ -60,16 +122,47 @@ impl RedisService {
 		Ok(self.pool.manager().0.make_client().await?)
 	}
 
+	/// Acquires a lock, retrying with backoff while it is held by others.
+	///
+	/// Fails with [`RedisError::LockTimeout`] after [`RedisConfig::lock_max_wait`].
 	pub async fn lock<K: Into<LockKey>>(&self, key: K, ttl: Duration) -> RedisResult<LockGuard> {
-		let key = key.into().to_key();
+		let key = key.into();
+		let kind = key.kind();
+		let key = key.to_key(&self.prefix);
+		let started = Instant::now();
 		let mut delay = Duration::milliseconds(50);
+		let mut attempts = 0u32;
 		loop {
+			attempts += 1;
 			match self.locker.lock(key.as_bytes(), ttl.try_into()?).await {
-				Ok(lock) => return Ok(lock.into()),
+				Ok(lock) => {
+					let waited = started.elapsed();
+					self.record_lock(kind, |stats| {
+						stats.acquired += 1;
+						if attempts > 1 {
+							stats.contended += 1;
+						}
+						stats.wait_seconds += waited.as_secs_f64();
+					});
+					if attempts > 1 {
+						debug!(key, attempts, ?waited, "acquired contended lock");
+					}
+					return Ok(LockGuard::new(lock, &key));
+				}
 				Err(rslock::LockError::TtlTooLarge) => {
 					return Err(rslock::LockError::TtlTooLarge.into());
 				}
 				Err(_) => {
+					if let Some(max_wait) = self.lock_max_wait {
+						if started.elapsed() >= max_wait {
+							self.record_lock(kind, |stats| {
+								stats.timeouts += 1;
+								stats.wait_seconds += started.elapsed().as_secs_f64();
+							});
+							warn!(key, attempts, "timed out waiting for lock");
+							return Err(RedisError::LockTimeout(key));
+						}
+					}
 					tokio::time::sleep(delay.try_into()?).await;
 					if delay <= Duration::seconds(3) {
 						delay *= 2;
@@ -79,11 +172,22 @@ impl RedisService {
 			}
 		}
 	}
+
+	fn record_lock(&self, kind: &'static str, update: impl FnOnce(&mut LockStats)) {
+		update(self.lock_stats.lock().unwrap().entry(kind).or_default());
+	}
+
+	/// Returns statistics of locks acquired by this instance, by [`LockKey::kind`].
+	pub fn lock_stats(&self) -> BTreeMap<&'static str, LockStats> {
+		self.lock_stats.lock().unwrap().clone()
+	}
 }
 
 impl Debug for RedisService {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		f.debug_struct("RedisService").finish()
+		f.debug_struct("RedisService")
+			.field("prefix", &self.prefix)
+			.finish()
 	}
 }
 
@@ -97,6 +201,8 @@ pub enum RedisError {
 	PoolBuildError(#[from] deadpool::managed::BuildError),
 	#[error("distributed lock error: {0}")]
 	LockError(#[from] rslock::LockError),
+	#[error("timed out waiting for lock {0}")]
+	LockTimeout(String),
 	#[error("time conversion error: {0}")]
 	TimeConversionError(#[from] time::error::ConversionRange),
 }
@@ -142,17 +248,44 @@ impl Manager for RedisManager {
 }
 
 /// Key for distributed locking
+///
+/// Prefer the finest key covering the mutated state, so that work on
+/// different packages of a branch is not serialized.
+/// Formats of keys are stable, as instances of different versions may share locks.
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum LockKey {
 	Branch(BranchRef),
+	/// A package, e.g. evaluating its metadata.
+	Package(Uuid),
+	/// A package on a target, e.g. building it.
+	PkgTarget(Uuid, TargetId),
+	/// A job, e.g. running it exclusively across instances.
+	Job(Uuid),
 	Misc(&'static str),
 }
 
 impl LockKey {
-	pub fn to_key(&self) -> String {
+	/// Returns the kind of the key, labelling [`LockStats`].
+	pub fn kind(&self) -> &'static str {
+		match self {
+			LockKey::Branch(_) => "branch",
+			LockKey::Package(_) => "pkg",
+			LockKey::PkgTarget(_, _) => "pkg-target",
+			LockKey::Job(_) => "job",
+			LockKey::Misc(_) => "misc",
+		}
+	}
+
+	/// Returns the key of the lock, prefixed by [`RedisConfig::key_prefix`].
+	pub fn to_key(&self, prefix: &str) -> String {
 		match self {
-			LockKey::Branch(branch) => format!("lock:branch:{}", branch),
-			LockKey::Misc(key) => format!("lock:misc:{}", key),
+			LockKey::Branch(branch) => format!("{prefix}lock:branch:{}", branch),
+			LockKey::Package(package) => format!("{prefix}lock:pkg:{package}"),
+			LockKey::PkgTarget(package, target) => {
+				format!("{prefix}lock:pkg-target:{package}:{target}")
+			}
+			LockKey::Job(job) => format!("{prefix}lock:job:{job}"),
+			LockKey::Misc(key) => format!("{prefix}lock:misc:{}", key),
 		}
 	}
 }
@@ -163,16 +296,30 @@ impl From<&'static str> for LockKey {
 	}
 }
 
+/// Guard of a distributed lock, releasing the lock when dropped.
+///
+/// The guard holds a `lock` span, which records how long the lock was held.
 #[derive(Debug)]
-pub struct LockGuard(rslock::Lock);
+pub struct LockGuard(rslock::Lock, LockHeld);
 
-impl From<Lock> for LockGuard {
-	fn from(lock: Lock) -> Self {
-		Self(lock)
-	}
+#[derive(Debug)]
+struct LockHeld {
+	span: Span,
+	since: Instant,
 }
 
 impl LockGuard {
+	fn new(lock: Lock, key: &str) -> Self {
+		let span = info_span!("lock", key, held_ms = Empty);
+		Self(
+			lock,
+			LockHeld {
+				span,
+				since: Instant::now(),
+			},
+		)
+	}
+
 	pub async fn extend(&mut self, ttl: Duration) -> RedisResult<()> {
 		self.0 = self.0.lock_manager.extend(&self.0, ttl.try_into()?).await?;
 		Ok(())
@@ -181,6 +328,9 @@ impl LockGuard {
 
 impl Drop for LockGuard {
 	fn drop(&mut self) {
+		let held = self.1.since.elapsed();
+		self.1.span.record("held_ms", held.as_millis() as u64);
+		self.1.span.in_scope(|| debug!(?held, "released lock"));
 		// force clone the lock
 		let lock = Lock {
 			resource: self.0.resource.to_owned(),
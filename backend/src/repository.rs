@@ -0,0 +1,188 @@
+//! Git repositories of branches.
+//!
+//! Repositories are configured by name, and recorded in the database
+//! so that branches can refer to them by ID.
+//! Branches without repositories belong to the first configured repository.
+
+use std::{collections::HashMap, sync::Arc};
+
+use diesel::{ExpressionMethods, QueryDsl, insert_into, result::DatabaseErrorKind, update};
+use kstring::KString;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+	Result,
+	db::{schema::repository::dsl, service::DatabaseService},
+};
+
+pub type RepositoryRef = i64;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct RepositoryConfig {
+	/// Name of the repository, referred by branch configurations.
+	pub name: KString,
+	/// URL of the Git remote.
+	pub url: String,
+}
+
+/// A Git repository.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RepositoryInfo {
+	pub id: RepositoryRef,
+	pub name: KString,
+	pub url: String,
+}
+
+#[derive(Debug)]
+pub struct RepositoryService {
+	by_id: HashMap<RepositoryRef, Arc<RepositoryInfo>>,
+	by_name: HashMap<KString, Arc<RepositoryInfo>>,
+	default: Option<Arc<RepositoryInfo>>,
+}
+
+impl RepositoryService {
+	/// Records configured repositories into the database.
+	///
+	/// Repositories removed from the configuration are kept,
+	/// as branches may still refer to them.
+	pub async fn new(db: &DatabaseService, config: &[RepositoryConfig]) -> Result<Self> {
+		let mut conn = db.get().await?;
+		for repository in config {
+			let updated = conn
+				.execute(
+					update(dsl::repository)
+						.filter(dsl::name.eq(repository.name.as_str()))
+						.set(dsl::url.eq(&repository.url)),
+				)
+				.await?;
+			if updated > 0 {
+				continue;
+			}
+			let result = conn
+				.execute(insert_into(dsl::repository).values((
+					dsl::name.eq(repository.name.as_str()),
+					dsl::url.eq(&repository.url),
+				)))
+				.await;
+			match result {
+				Ok(_) => info!(name = %repository.name, "added repository"),
+				// inserted by another instance at the same time
+				Err(diesel::result::Error::DatabaseError(
+					DatabaseErrorKind::UniqueViolation,
+					_,
+				)) => {}
+				Err(error) => return Err(error.into()),
+			}
+		}
+
+		let rows = conn
+			.load::<_, (RepositoryRef, String, String)>(dsl::repository.select((
+				dsl::id,
+				dsl::name,
+				dsl::url,
+			)))
+			.await?;
+		let mut service = Self {
+			by_id: HashMap::with_capacity(rows.len()),
+			by_name: HashMap::with_capacity(rows.len()),
+			default: None,
+		};
+		for (id, name, url) in rows {
+			let repository = Arc::new(RepositoryInfo {
+				id,
+				name: name.into(),
+				url,
+			});
+			service.by_id.insert(id, repository.clone());
+			service.by_name.insert(repository.name.clone(), repository);
+		}
+		service.default = config
+			.first()
+			.and_then(|repository| service.by_name.get(&repository.name).cloned());
+		Ok(service)
+	}
+
+	/// Finds a repository by its ID.
+	pub fn get(&self, id: RepositoryRef) -> Option<&Arc<RepositoryInfo>> {
+		self.by_id.get(&id)
+	}
+
+	/// Finds a repository by its name.
+	pub fn find<S: AsRef<str>>(&self, name: S) -> Option<&Arc<RepositoryInfo>> {
+		self.by_name.get(name.as_ref())
+	}
+
+	/// Returns the repository of branches without repositories.
+	pub fn default_repository(&self) -> Option<&Arc<RepositoryInfo>> {
+		self.default.as_ref()
+	}
+
+	/// Returns the repository of a branch.
+	pub fn resolve(&self, id: Option<RepositoryRef>) -> Option<&Arc<RepositoryInfo>> {
+		match id {
+			Some(id) => self.get(id),
+			None => self.default_repository(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use diesel::{ExpressionMethods, QueryDsl};
+
+	use crate::{
+		BackendError,
+		branch::{BranchConfigInfo, BranchError},
+		db::schema::branch::dsl,
+		namespace::DEFAULT_NAMESPACE_ID,
+		test::test_env,
+	};
+
+	#[tokio::test]
+	async fn test_repository() {
+		let env = test_env().await;
+		let main = env.repository.find("main").unwrap().clone();
+		let extra = env.repository.find("extra").unwrap().clone();
+		assert_eq!(env.repository.resolve(None), Some(&main));
+
+		env.branch
+			.track(
+				DEFAULT_NAMESPACE_ID,
+				"stable",
+				BranchConfigInfo {
+					repository: Some("extra".into()),
+					..Default::default()
+				},
+			)
+			.await
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+		let repository = db
+			.get_result::<_, Option<i64>>(
+				dsl::branch
+					.filter(dsl::name.eq("stable"))
+					.select(dsl::repository),
+			)
+			.await
+			.unwrap();
+		assert_eq!(repository, Some(extra.id));
+		drop(db);
+
+		assert!(matches!(
+			env.branch
+				.track(
+					DEFAULT_NAMESPACE_ID,
+					"testing",
+					BranchConfigInfo {
+						repository: Some("unknown".into()),
+						..Default::default()
+					},
+				)
+				.await,
+			Err(BackendError::BranchError(BranchError::RepositoryNotFound(
+				_
+			)))
+		));
+	}
+}
@@ -0,0 +1,417 @@
+//! Verification of reproducible builds.
+//!
+//! After a successful build of a selected package, [`ReproService::record_artifacts`]
+//! schedules a [`JobCommand::VerifyBuild`] job, which builds the package again
+//! with [`crate::builder`] on a different runner. Digests of artifacts of both
+//! builds are compared by [`ReproService::verify`], and the result is stored in
+//! [`PkgTargetData::repro`].
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use diesel::{ExpressionMethods, QueryDsl};
+use fabricia_common_model::package::ReproStatus;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	build_cache::PlannedBuild,
+	db::{
+		schema::{pkg::dsl as pkg_dsl, pkg_target::dsl as target_dsl},
+		service::DatabaseService,
+		utils::XUuidVal,
+	},
+	job_queue::{JobCommand, JobQueue},
+	model::PkgTargetRow,
+	package::{PackageError, PackageService, PkgTargetData, ReproState},
+	target::TargetService,
+};
+
+/// Configuration of reproducible build verification.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReproConfig {
+	/// Names of packages whose builds are verified.
+	///
+	/// A trailing `*` matches all names with the prefix, e.g. `python-*`.
+	pub packages: Vec<String>,
+}
+
+impl ReproConfig {
+	/// Returns whether builds of a package are verified.
+	pub fn is_selected(&self, name: &str) -> bool {
+		self.packages
+			.iter()
+			.any(|pattern| match pattern.strip_suffix('*') {
+				Some(prefix) => name.starts_with(prefix),
+				None => name == pattern,
+			})
+	}
+}
+
+/// Compares digests of artifacts of two builds.
+///
+/// Returns file names of artifacts which differ or are only produced by one build.
+pub fn differing_artifacts(
+	a: &BTreeMap<String, String>,
+	b: &BTreeMap<String, String>,
+) -> Vec<String> {
+	let mut differing = a
+		.iter()
+		.filter(|(name, hash)| b.get(*name) != Some(hash))
+		.map(|(name, _)| name.clone())
+		.collect::<Vec<_>>();
+	differing.extend(b.keys().filter(|name| !a.contains_key(*name)).cloned());
+	differing.sort();
+	differing
+}
+
+/// Service for reproducible build verification.
+#[derive(Debug)]
+pub struct ReproService {
+	db: Arc<DatabaseService>,
+	job_queue: Arc<JobQueue>,
+	target: Arc<TargetService>,
+	/// Builds are never verified if not set.
+	config: Option<ReproConfig>,
+}
+
+impl ReproService {
+	pub fn new(
+		db: Arc<DatabaseService>,
+		job_queue: Arc<JobQueue>,
+		target: Arc<TargetService>,
+		config: Option<&ReproConfig>,
+	) -> Self {
+		Self {
+			db,
+			job_queue,
+			target,
+			config: config.cloned(),
+		}
+	}
+
+	/// Records digests of artifacts of a successful build and the runner which
+	/// has run it, after [`BuildCacheService::record`](crate::build_cache::BuildCacheService::record).
+	///
+	/// Schedules a verification if the package is selected. Builds run by
+	/// unnamed runners are never verified, as the verifying build could not be
+	/// told apart. Returns whether a verification is scheduled.
+	pub async fn record_artifacts(
+		&self,
+		build: &PlannedBuild,
+		builder: Option<&str>,
+		hashes: BTreeMap<String, String>,
+	) -> Result<bool> {
+		let mut conn = self.db.get().await?;
+		let row: PkgTargetRow = conn
+			.load_one_select(target_dsl::pkg_target.filter(target_dsl::id.eq(XUuidVal(build.id))))
+			.await?;
+		let name: String = conn
+			.get_result(
+				pkg_dsl::pkg
+					.filter(pkg_dsl::id.eq(row.package))
+					.select(pkg_dsl::name),
+			)
+			.await?;
+		let selected = self
+			.config
+			.as_ref()
+			.is_some_and(|config| config.is_selected(&name));
+		// rows of targets removed from the configuration or deprecated are never verified
+		let target = self
+			.target
+			.get(build.target)
+			.filter(|target| selected && !target.deprecated);
+
+		let mut data = row.target_data()?;
+		data.artifact_hashes = hashes;
+		data.builder = builder.map(str::to_string);
+		data.repro = None;
+		let (Some(_), Some(builder)) = (target, builder) else {
+			PackageService::transition_target(&mut conn, build.id, row.revision, row.status, &data)
+				.await?;
+			return Ok(false);
+		};
+
+		data.repro = Some(ReproState {
+			status: ReproStatus::Pending,
+			verify_build: None,
+			differing: vec![],
+		});
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			let revision =
+				PackageService::transition_target(conn, build.id, row.revision, row.status, &data)
+					.await?;
+			self.job_queue
+				.enqueue(
+					conn,
+					JobCommand::VerifyBuild {
+						branch: row.branch,
+						build: PlannedBuild {
+							revision,
+							..build.clone()
+						},
+						builder: builder.to_string(),
+					},
+				)
+				.await?;
+			Ok(())
+		})
+		.await?;
+		info!(id = %build.id, package = %name, "scheduled build verification");
+		Ok(true)
+	}
+
+	/// Checks that a runner may run a [`JobCommand::VerifyBuild`] job, before
+	/// building the package again.
+	///
+	/// Fails with [`ReproError::SameBuilder`] on the runner of the verified build,
+	/// and with [`ReproError::UnnamedRunner`] on unnamed runners. Returns the
+	/// name of the runner.
+	pub fn check_runner<'a>(&self, builder: &str, runner: Option<&'a str>) -> Result<&'a str> {
+		match runner {
+			None => Err(ReproError::UnnamedRunner.into()),
+			Some(runner) if runner == builder => {
+				Err(ReproError::SameBuilder(runner.to_string()).into())
+			}
+			Some(runner) => Ok(runner),
+		}
+	}
+
+	/// Records the result of a [`JobCommand::VerifyBuild`] job.
+	///
+	/// Fails with [`PackageError::Conflict`] if the package target has been
+	/// changed since scheduled, and with [`ReproError::SameBuilder`] if the
+	/// verifying build has been run by the runner of the verified build.
+	pub async fn verify(
+		&self,
+		build: &PlannedBuild,
+		job: Uuid,
+		builder: &str,
+		hashes: &BTreeMap<String, String>,
+	) -> Result<ReproStatus> {
+		let mut conn = self.db.get().await?;
+		let row: PkgTargetRow = conn
+			.load_one_select(target_dsl::pkg_target.filter(target_dsl::id.eq(XUuidVal(build.id))))
+			.await?;
+		if row.revision != build.revision {
+			return Err(PackageError::Conflict(build.id, build.revision).into());
+		}
+		let mut data = row.target_data()?;
+		if data.builder.as_deref() == Some(builder) {
+			return Err(ReproError::SameBuilder(builder.to_string()).into());
+		}
+
+		let differing = differing_artifacts(&data.artifact_hashes, hashes);
+		let status = match differing.is_empty() {
+			true => ReproStatus::Reproducible,
+			false => ReproStatus::Unreproducible,
+		};
+		data.repro = Some(ReproState {
+			status,
+			verify_build: Some(job),
+			differing,
+		});
+		PackageService::transition_target(&mut conn, build.id, build.revision, row.status, &data)
+			.await?;
+		info!(id = %build.id, ?status, "verified build");
+		Ok(status)
+	}
+
+	/// Clears the pending verification of a build, after its
+	/// [`JobCommand::VerifyBuild`] job has failed permanently.
+	///
+	/// Does nothing if the package target has been changed since scheduled.
+	pub async fn cancel(&self, build: &PlannedBuild) -> Result<()> {
+		let mut conn = self.db.get().await?;
+		let row: PkgTargetRow = conn
+			.load_one_select(target_dsl::pkg_target.filter(target_dsl::id.eq(XUuidVal(build.id))))
+			.await?;
+		if row.revision != build.revision {
+			return Ok(());
+		}
+		let data = PkgTargetData {
+			repro: None,
+			..row.target_data()?
+		};
+		let result = PackageService::transition_target(
+			&mut conn,
+			build.id,
+			build.revision,
+			row.status,
+			&data,
+		)
+		.await;
+		match result {
+			Ok(_) | Err(crate::BackendError::PackageError(PackageError::Conflict(..))) => Ok(()),
+			Err(error) => Err(error),
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum ReproError {
+	#[error("build is verified on the same runner {0}")]
+	SameBuilder(String),
+	#[error("builds cannot be verified on unnamed runners")]
+	UnnamedRunner,
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::BTreeMap;
+
+	use diesel::{ExpressionMethods, QueryDsl};
+	use fabricia_common_model::package::ReproStatus;
+	use serde_json::json;
+	use uuid::Uuid;
+
+	use crate::{
+		BackendError,
+		build_cache::PlannedBuild,
+		db::{
+			schema::{pkg::dsl as pkg_dsl, pkg_target::dsl as target_dsl},
+			utils::{XJsonVal, XUuidVal},
+		},
+		job_queue::JobCommand,
+		model::{PkgRow, PkgTargetRow},
+		package::{PackageError, SqlPackageStatus, SqlPackageTargetState},
+		repro::{ReproConfig, ReproError, differing_artifacts},
+		target::TargetInfo,
+		test::{test_config, test_env_with_config, test_time},
+	};
+
+	fn hashes(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+		entries
+			.iter()
+			.map(|(name, hash)| (name.to_string(), hash.to_string()))
+			.collect()
+	}
+
+	#[test]
+	fn test_selection() {
+		let config = ReproConfig {
+			packages: vec!["bash".to_string(), "python-*".to_string()],
+		};
+		assert!(config.is_selected("bash"));
+		assert!(config.is_selected("python-requests"));
+		assert!(!config.is_selected("bash-completion"));
+	}
+
+	#[test]
+	fn test_differing_artifacts() {
+		let a = hashes(&[("a.deb", "1"), ("b.deb", "2"), ("c.deb", "3")]);
+		let b = hashes(&[("a.deb", "1"), ("b.deb", "0"), ("d.deb", "4")]);
+		assert_eq!(differing_artifacts(&a, &b), ["b.deb", "c.deb", "d.deb"]);
+		assert!(differing_artifacts(&a, &a).is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_verify() {
+		let mut config = test_config();
+		config.repro = Some(ReproConfig {
+			packages: vec!["bash".to_string()],
+		});
+		let env = test_env_with_config(config).await;
+		let pkg = PkgRow {
+			id: XUuidVal(Uuid::now_v7()),
+			branch: 1,
+			name: "bash".to_string(),
+			section: "base".to_string(),
+			status: SqlPackageStatus::Ready,
+			status_msg: None,
+			data: XJsonVal(json!({})),
+			created_at: test_time(),
+			updated_at: test_time(),
+			hold_reason: None,
+		};
+		let pkg_id = pkg.id;
+		let target = PkgTargetRow {
+			id: XUuidVal(Uuid::now_v7()),
+			branch: 1,
+			package: pkg.id,
+			target: TargetInfo::make_id("arch1") as i64,
+			status: SqlPackageTargetState::Ready,
+			data: XJsonVal(json!({})),
+			created_at: test_time(),
+			updated_at: test_time(),
+			revision: 0,
+		};
+		let mut db = env.database.get().await.unwrap();
+		db.execute(diesel::insert_into(pkg_dsl::pkg).values(pkg))
+			.await
+			.unwrap();
+		db.execute(diesel::insert_into(target_dsl::pkg_target).values(target.clone()))
+			.await
+			.unwrap();
+		drop(db);
+
+		let built = hashes(&[("bash.deb", "1"), ("bash-doc.deb", "2")]);
+		let planned = PlannedBuild {
+			id: target.id.0,
+			package: pkg_id.0,
+			target: TargetInfo::make_id("arch1"),
+			arch: "arch1".into(),
+			key: "key".to_string(),
+			revision: 0,
+		};
+		assert!(
+			env.repro
+				.record_artifacts(&planned, Some("runner-1"), built.clone())
+				.await
+				.unwrap()
+		);
+		let job = env.job_queue.fetch_and_start().await.unwrap().unwrap();
+		let JobCommand::VerifyBuild { build, builder, .. } = job.command else {
+			panic!("unexpected job {:?}", job.command);
+		};
+		assert_eq!(
+			(build.id, build.revision, builder.as_str()),
+			(target.id.0, 1, "runner-1")
+		);
+		let mut db = env.database.get().await.unwrap();
+		env.job_queue.finish_job(&mut db, job.id).await.unwrap();
+		drop(db);
+
+		assert!(matches!(
+			env.repro.check_runner(&builder, Some("runner-1")),
+			Err(BackendError::ReproError(ReproError::SameBuilder(_)))
+		));
+		assert!(matches!(
+			env.repro.check_runner(&builder, None),
+			Err(BackendError::ReproError(ReproError::UnnamedRunner))
+		));
+		env.repro.check_runner(&builder, Some("runner-2")).unwrap();
+
+		let result = env.repro.verify(&build, job.id, "runner-1", &built).await;
+		assert!(matches!(
+			result,
+			Err(BackendError::ReproError(ReproError::SameBuilder(_)))
+		));
+		let rebuilt = hashes(&[("bash.deb", "1"), ("bash-doc.deb", "3")]);
+		let status = env
+			.repro
+			.verify(&build, job.id, "runner-2", &rebuilt)
+			.await
+			.unwrap();
+		assert_eq!(status, ReproStatus::Unreproducible);
+		let result = env.repro.verify(&build, job.id, "runner-2", &rebuilt).await;
+		assert!(matches!(
+			result,
+			Err(BackendError::PackageError(PackageError::Conflict(..)))
+		));
+
+		let mut db = env.database.get().await.unwrap();
+		let row: PkgTargetRow = db
+			.load_one_select(target_dsl::pkg_target.filter(target_dsl::id.eq(target.id)))
+			.await
+			.unwrap();
+		let repro = row.target_data().unwrap().repro.unwrap();
+		assert_eq!(repro.status, ReproStatus::Unreproducible);
+		assert_eq!(repro.verify_build, Some(job.id));
+		assert_eq!(repro.differing, ["bash-doc.deb"]);
+	}
+}
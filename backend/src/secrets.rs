@@ -0,0 +1,164 @@
+//! Secrets referenced by configuration.
+//!
+//! Instead of plaintext, secret options may reference where the secret is kept:
+//!
+//! - `env:NAME`, an environment variable
+//! - `file:PATH`, a file, e.g. mounted by container orchestrators
+//! - `sops:PATH#KEY`, a key of a file encrypted with SOPS, decrypted by the `sops` command
+//! - `vault:PATH#FIELD`, a field of a HashiCorp Vault KV secret, read from `VAULT_ADDR`
+//!   with `VAULT_TOKEN`
+//!
+//! Other values are used as is. References are resolved once on loading configuration.
+
+use std::{io, path::Path, time::Duration};
+
+use serde_json::Value;
+use thiserror::Error;
+use tokio::process::Command;
+
+/// Resolves a secret option in place.
+pub async fn resolve_in_place(value: &mut String) -> Result<(), SecretError> {
+	*value = resolve(value).await?;
+	Ok(())
+}
+
+/// Resolves a secret option, which is either a reference or a plaintext secret.
+pub async fn resolve(value: &str) -> Result<String, SecretError> {
+	if let Some(name) = value.strip_prefix("env:") {
+		std::env::var(name).map_err(|_| SecretError::EnvNotFound(name.to_string()))
+	} else if let Some(path) = value.strip_prefix("file:") {
+		read_file(Path::new(path)).await
+	} else if let Some(reference) = value.strip_prefix("sops:") {
+		let (path, key) = split_reference(reference)?;
+		read_sops(path, key).await
+	} else if let Some(reference) = value.strip_prefix("vault:") {
+		let (path, field) = split_reference(reference)?;
+		read_vault(path, field).await
+	} else {
+		Ok(value.to_string())
+	}
+}
+
+/// Reads a secret from a file, without the trailing newline.
+pub async fn read_file(path: &Path) -> Result<String, SecretError> {
+	let content = tokio::fs::read_to_string(path)
+		.await
+		.map_err(|error| SecretError::IoError(path.display().to_string(), error))?;
+	Ok(content.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn split_reference(reference: &str) -> Result<(&str, &str), SecretError> {
+	reference
+		.rsplit_once('#')
+		.filter(|(path, key)| !path.is_empty() && !key.is_empty())
+		.ok_or_else(|| SecretError::InvalidReference(reference.to_string()))
+}
+
+async fn read_sops(path: &str, key: &str) -> Result<String, SecretError> {
+	let output = Command::new("sops")
+		.arg("--decrypt")
+		.arg("--extract")
+		.arg(format!("[\"{key}\"]"))
+		.arg(path)
+		.output()
+		.await
+		.map_err(|error| SecretError::IoError("sops".to_string(), error))?;
+	if !output.status.success() {
+		return Err(SecretError::ProviderError(
+			format!("sops:{path}#{key}"),
+			String::from_utf8_lossy(&output.stderr).trim().to_string(),
+		));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout)
+		.trim_end_matches(['\r', '\n'])
+		.to_string())
+}
+
+/// Timeout of requests reading a secret from Vault.
+const VAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout of connecting to Vault.
+const VAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+async fn read_vault(path: &str, field: &str) -> Result<String, SecretError> {
+	let reference = format!("vault:{path}#{field}");
+	let addr = std::env::var("VAULT_ADDR")
+		.map_err(|_| SecretError::EnvNotFound("VAULT_ADDR".to_string()))?;
+	let token = std::env::var("VAULT_TOKEN")
+		.map_err(|_| SecretError::EnvNotFound("VAULT_TOKEN".to_string()))?;
+	let provider_error =
+		|error: reqwest::Error| SecretError::ProviderError(reference.clone(), error.to_string());
+	let body = reqwest::Client::builder()
+		.timeout(VAULT_TIMEOUT)
+		.connect_timeout(VAULT_CONNECT_TIMEOUT)
+		.build()
+		.map_err(provider_error)?
+		.get(format!(
+			"{}/v1/{}",
+			addr.trim_end_matches('/'),
+			path.trim_start_matches('/')
+		))
+		.header("X-Vault-Token", token)
+		.send()
+		.await
+		.and_then(|response| response.error_for_status())
+		.map_err(provider_error)?
+		.bytes()
+		.await
+		.map_err(provider_error)?;
+	let secret: Value = serde_json::from_slice(&body)
+		.map_err(|error| SecretError::ProviderError(reference.clone(), error.to_string()))?;
+	// KV version 2 nests fields in `data.data`, and version 1 in `data`
+	let data = &secret["data"];
+	let data = if data["data"].is_object() {
+		&data["data"]
+	} else {
+		data
+	};
+	data[field]
+		.as_str()
+		.map(str::to_string)
+		.ok_or_else(|| SecretError::ProviderError(reference, format!("field {field} not found")))
+}
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+	#[error("environment variable {0} is not set")]
+	EnvNotFound(String),
+	#[error("failed to read secret from {0}: {1}")]
+	IoError(String, io::Error),
+	#[error("invalid secret reference {0}, expecting PATH#KEY")]
+	InvalidReference(String),
+	#[error("failed to resolve secret {0}: {1}")]
+	ProviderError(String, String),
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_resolve() {
+		assert_eq!(
+			resolve("postgres://db/fabricia").await.unwrap(),
+			"postgres://db/fabricia"
+		);
+
+		let path = std::env::temp_dir().join(format!("fabricia-secret-{}", uuid::Uuid::now_v7()));
+		tokio::fs::write(&path, "sqlite://data.db\n").await.unwrap();
+		assert_eq!(
+			resolve(&format!("file:{}", path.display())).await.unwrap(),
+			"sqlite://data.db"
+		);
+		tokio::fs::remove_file(&path).await.unwrap();
+
+		assert!(matches!(
+			resolve("env:FABRICIA_TEST_SECRET_NOT_SET").await,
+			Err(SecretError::EnvNotFound(_))
+		));
+		assert!(matches!(
+			resolve("sops:secrets.yaml").await,
+			Err(SecretError::InvalidReference(_))
+		));
+	}
+}
@@ -0,0 +1,720 @@
+//! Cross-reference of security advisories.
+//!
+//! Advisories are ingested from OSV and NVD feeds configured in [`SecurityConfig`],
+//! and matched against names and versions of tracked packages.
+//! Affected packages are flagged with [`LintCheck::Security`] findings.
+
+use std::{collections::HashMap, io, sync::Arc, time::Duration};
+
+use diesel::{ExpressionMethods, QueryDsl, delete, insert_into};
+use kstring::KString;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	branch::BranchRef,
+	db::{
+		BoxedSqlConn,
+		schema::{
+			advisory::dsl, branch::dsl as branch_dsl, pkg::dsl as pkg_dsl,
+			pkg_finding::dsl as finding_dsl,
+		},
+		service::DatabaseService,
+		utils::{XJsonVal, XUuidVal},
+	},
+	lint::{LintCheck, SqlFindingSeverity},
+	model::{AdvisoryRow, NewAdvisoryRow, NewPkgFindingRow, PkgRow},
+	package::compare_versions,
+};
+
+/// Count of rows inserted at once, below bind parameter limits of SQLite.
+const INSERT_CHUNK_SIZE: usize = 500;
+
+/// Configuration of security advisories.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SecurityConfig {
+	/// Advisory feeds to ingest.
+	#[serde(default)]
+	pub feeds: Vec<FeedConfig>,
+}
+
+/// An advisory feed.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FeedConfig {
+	/// Name of the feed, recorded as the source of its advisories.
+	pub name: KString,
+	/// URL of the feed.
+	///
+	/// `file://` URLs are read from the local file system.
+	pub url: String,
+	pub format: FeedFormat,
+	/// OSV ecosystem of tracked packages, e.g. `Debian`.
+	///
+	/// Packages of other ecosystems are skipped. All packages are ingested if not set.
+	#[serde(default)]
+	pub ecosystem: Option<String>,
+}
+
+/// Format of an advisory feed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeedFormat {
+	/// A JSON array of OSV entries, or a single entry.
+	Osv,
+	/// A response of the NVD CVE API 2.0.
+	///
+	/// Products of CPE names are matched as package names.
+	Nvd,
+}
+
+/// Versions of a package affected by an advisory, stored in `advisory.affected`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AffectedVersions {
+	/// Exact affected versions.
+	pub versions: Vec<String>,
+	pub ranges: Vec<VersionRange>,
+}
+
+impl AffectedVersions {
+	/// Returns whether a version is affected.
+	pub fn contains(&self, version: &str) -> bool {
+		self.versions.iter().any(|affected| affected == version)
+			|| self.ranges.iter().any(|range| range.contains(version))
+	}
+}
+
+/// A range of affected versions, compared with [`compare_versions`].
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct VersionRange {
+	/// The first affected version, or unbounded if not set.
+	pub introduced: Option<String>,
+	/// The first fixed version, excluded from this range.
+	pub fixed: Option<String>,
+	/// The last affected version, included in this range.
+	pub last_affected: Option<String>,
+}
+
+impl VersionRange {
+	/// Returns whether a version is in this range.
+	pub fn contains(&self, version: &str) -> bool {
+		self.introduced
+			.as_deref()
+			.is_none_or(|introduced| compare_versions(version, introduced).is_ge())
+			&& self
+				.fixed
+				.as_deref()
+				.is_none_or(|fixed| compare_versions(version, fixed).is_lt())
+			&& self
+				.last_affected
+				.as_deref()
+				.is_none_or(|last| compare_versions(version, last).is_le())
+	}
+}
+
+/// An advisory of a package, parsed from a feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advisory {
+	pub name: String,
+	pub package: String,
+	pub summary: String,
+	pub affected: AffectedVersions,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OsvFeed {
+	Entries(Vec<OsvEntry>),
+	Entry(OsvEntry),
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvEntry {
+	id: String,
+	#[serde(default)]
+	summary: String,
+	#[serde(default)]
+	affected: Vec<OsvAffected>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvAffected {
+	package: OsvPackage,
+	#[serde(default)]
+	ranges: Vec<OsvRange>,
+	#[serde(default)]
+	versions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvPackage {
+	name: String,
+	#[serde(default)]
+	ecosystem: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvRange {
+	#[serde(rename = "type", default)]
+	kind: String,
+	#[serde(default)]
+	events: Vec<OsvEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvEvent {
+	introduced: Option<String>,
+	fixed: Option<String>,
+	last_affected: Option<String>,
+}
+
+/// Parses an OSV feed.
+///
+/// Ranges of Git commits are skipped, as tracked packages have no commits.
+pub fn parse_osv(data: &[u8], ecosystem: Option<&str>) -> serde_json::Result<Vec<Advisory>> {
+	let entries = match serde_json::from_slice(data)? {
+		OsvFeed::Entries(entries) => entries,
+		OsvFeed::Entry(entry) => vec![entry],
+	};
+	let mut advisories = vec![];
+	for entry in entries {
+		for affected in entry.affected {
+			// ecosystems may have suffixes of releases, e.g. `Debian:12`
+			let matched = ecosystem.is_none_or(|ecosystem| {
+				affected
+					.package
+					.ecosystem
+					.split(':')
+					.next()
+					.is_some_and(|name| name == ecosystem)
+			});
+			if !matched {
+				continue;
+			}
+			let ranges = affected
+				.ranges
+				.into_iter()
+				.filter(|range| range.kind != "GIT")
+				.flat_map(|range| osv_ranges(range.events))
+				.collect();
+			advisories.push(Advisory {
+				name: entry.id.clone(),
+				package: affected.package.name,
+				summary: entry.summary.clone(),
+				affected: AffectedVersions {
+					versions: affected.versions,
+					ranges,
+				},
+			});
+		}
+	}
+	Ok(advisories)
+}
+
+fn osv_ranges(events: Vec<OsvEvent>) -> Vec<VersionRange> {
+	let mut ranges = vec![];
+	let mut current: Option<VersionRange> = None;
+	for event in events {
+		if let Some(introduced) = event.introduced {
+			ranges.extend(current.take());
+			current = Some(VersionRange {
+				// `0` is the start of all versions
+				introduced: (introduced != "0").then_some(introduced),
+				..Default::default()
+			});
+		}
+		if event.fixed.is_some() || event.last_affected.is_some() {
+			let mut range = current.take().unwrap_or_default();
+			range.fixed = event.fixed;
+			range.last_affected = event.last_affected;
+			ranges.push(range);
+		}
+	}
+	ranges.extend(current);
+	ranges
+}
+
+#[derive(Debug, Deserialize)]
+struct NvdFeed {
+	#[serde(default)]
+	vulnerabilities: Vec<NvdItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NvdItem {
+	cve: NvdCve,
+}
+
+#[derive(Debug, Deserialize)]
+struct NvdCve {
+	id: String,
+	#[serde(default)]
+	descriptions: Vec<NvdDescription>,
+	#[serde(default)]
+	configurations: Vec<NvdConfiguration>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NvdDescription {
+	lang: String,
+	value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NvdConfiguration {
+	#[serde(default)]
+	nodes: Vec<NvdNode>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NvdNode {
+	#[serde(default)]
+	cpe_match: Vec<NvdCpeMatch>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NvdCpeMatch {
+	vulnerable: bool,
+	criteria: String,
+	version_start_including: Option<String>,
+	version_start_excluding: Option<String>,
+	version_end_including: Option<String>,
+	version_end_excluding: Option<String>,
+}
+
+/// Parses a response of the NVD CVE API 2.0.
+///
+/// Exclusive starts of ranges are treated as inclusive,
+/// which may flag the boundary version additionally.
+pub fn parse_nvd(data: &[u8]) -> serde_json::Result<Vec<Advisory>> {
+	let feed: NvdFeed = serde_json::from_slice(data)?;
+	let mut advisories = vec![];
+	for item in feed.vulnerabilities {
+		let cve = item.cve;
+		let summary = cve
+			.descriptions
+			.into_iter()
+			.find(|description| description.lang == "en")
+			.map(|description| description.value)
+			.unwrap_or_default();
+		let mut products: Vec<(String, AffectedVersions)> = vec![];
+		let matches = cve
+			.configurations
+			.into_iter()
+			.flat_map(|configuration| configuration.nodes)
+			.flat_map(|node| node.cpe_match)
+			.filter(|cpe| cpe.vulnerable);
+		for cpe in matches {
+			// cpe:2.3:part:vendor:product:version:...
+			let mut fields = cpe.criteria.split(':').skip(4);
+			let (Some(product), Some(version)) = (fields.next(), fields.next()) else {
+				continue;
+			};
+			let index = match products.iter().position(|(name, _)| name == product) {
+				Some(index) => index,
+				None => {
+					products.push((product.to_string(), AffectedVersions::default()));
+					products.len() - 1
+				}
+			};
+			let affected = &mut products[index].1;
+			let bounded = cpe.version_start_including.is_some()
+				|| cpe.version_start_excluding.is_some()
+				|| cpe.version_end_including.is_some()
+				|| cpe.version_end_excluding.is_some();
+			if !bounded && version != "*" && version != "-" {
+				affected.versions.push(version.to_string());
+			} else {
+				affected.ranges.push(VersionRange {
+					introduced: cpe.version_start_including.or(cpe.version_start_excluding),
+					fixed: cpe.version_end_excluding,
+					last_affected: cpe.version_end_including,
+				});
+			}
+		}
+		for (package, affected) in products {
+			advisories.push(Advisory {
+				name: cve.id.clone(),
+				package,
+				summary: summary.clone(),
+				affected,
+			});
+		}
+	}
+	Ok(advisories)
+}
+
+/// A tracked package affected by an advisory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AffectedPackage {
+	pub branch: BranchRef,
+	pub package: Uuid,
+	pub name: String,
+	pub version: String,
+	pub advisory: Arc<AdvisoryRow>,
+}
+
+/// Timeout of requests fetching a feed.
+const FEED_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Timeout of connecting to a feed.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Service for security advisories.
+#[derive(Debug)]
+pub struct SecurityService {
+	db: Arc<DatabaseService>,
+	config: SecurityConfig,
+	http: reqwest::Client,
+}
+
+impl SecurityService {
+	pub fn new(db: Arc<DatabaseService>, config: &SecurityConfig) -> Self {
+		Self {
+			db,
+			config: config.to_owned(),
+			http: reqwest::Client::builder()
+				.timeout(FEED_TIMEOUT)
+				.connect_timeout(CONNECT_TIMEOUT)
+				.build()
+				.expect("the HTTP client has no invalid options"),
+		}
+	}
+
+	/// Ingests all configured feeds.
+	///
+	/// Advisories of each feed replace the ones ingested earlier from the same feed.
+	/// Returns the count of ingested advisories.
+	pub async fn ingest(&self) -> Result<usize> {
+		let mut total = 0;
+		for feed in &self.config.feeds {
+			let data = self.fetch(&feed.url).await?;
+			let advisories = match feed.format {
+				FeedFormat::Osv => parse_osv(&data, feed.ecosystem.as_deref()),
+				FeedFormat::Nvd => parse_nvd(&data),
+			}
+			.map_err(|error| SecurityError::InvalidFeed(feed.name.clone(), error))?;
+			self.replace(&feed.name, &advisories).await?;
+			info!(feed = %feed.name, count = advisories.len(), "ingested advisories");
+			total += advisories.len();
+		}
+		Ok(total)
+	}
+
+	async fn fetch(&self, url: &str) -> Result<Vec<u8>, SecurityError> {
+		match url.strip_prefix("file://") {
+			Some(path) => Ok(tokio::fs::read(path).await?),
+			None => Ok(self
+				.http
+				.get(url)
+				.send()
+				.await?
+				.error_for_status()?
+				.bytes()
+				.await?
+				.to_vec()),
+		}
+	}
+
+	async fn replace(&self, source: &str, advisories: &[Advisory]) -> Result<()> {
+		let mut rows = Vec::with_capacity(advisories.len());
+		for advisory in advisories {
+			rows.push(NewAdvisoryRow {
+				source,
+				name: &advisory.name,
+				package: &advisory.package,
+				summary: &advisory.summary,
+				affected: XJsonVal(serde_json::to_value(&advisory.affected)?),
+			});
+		}
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			conn.execute(delete(dsl::advisory).filter(dsl::source.eq(source)))
+				.await?;
+			for chunk in rows.chunks(INSERT_CHUNK_SIZE) {
+				conn.execute(insert_into(dsl::advisory).values(chunk))
+					.await?;
+			}
+			Ok(())
+		})
+		.await
+	}
+
+	/// Finds packages of branches affected by ingested advisories.
+	pub async fn affected(&self, branches: &[BranchRef]) -> Result<Vec<AffectedPackage>> {
+		let mut conn = self.db.get().await?;
+		let packages: Vec<PkgRow> = conn
+			.load_select(pkg_dsl::pkg.filter(pkg_dsl::branch.eq_any(branches)))
+			.await?;
+		Self::find_affected(&mut conn, &packages).await
+	}
+
+	async fn find_affected(
+		conn: &mut BoxedSqlConn,
+		packages: &[PkgRow],
+	) -> Result<Vec<AffectedPackage>> {
+		let mut names = packages
+			.iter()
+			.map(|pkg| pkg.name.as_str())
+			.collect::<Vec<_>>();
+		names.sort_unstable();
+		names.dedup();
+		let mut advisories: HashMap<String, Vec<Arc<AdvisoryRow>>> = HashMap::new();
+		for chunk in names.chunks(INSERT_CHUNK_SIZE) {
+			let rows: Vec<AdvisoryRow> = conn
+				.load_select(dsl::advisory.filter(dsl::package.eq_any(chunk)))
+				.await?;
+			for row in rows {
+				advisories
+					.entry(row.package.clone())
+					.or_default()
+					.push(Arc::new(row));
+			}
+		}
+
+		let mut affected = vec![];
+		for pkg in packages {
+			let Some(advisories) = advisories.get(&pkg.name) else {
+				continue;
+			};
+			// packages without evaluated versions are checked after evaluation
+			let Ok(data) = pkg.pkg_data() else {
+				continue;
+			};
+			if data.version.is_empty() {
+				continue;
+			}
+			for advisory in advisories {
+				if advisory.affected_versions()?.contains(&data.version) {
+					affected.push(AffectedPackage {
+						branch: pkg.branch,
+						package: pkg.id.0,
+						name: pkg.name.clone(),
+						version: data.version.clone(),
+						advisory: advisory.clone(),
+					});
+				}
+			}
+		}
+		Ok(affected)
+	}
+
+	/// Flags packages of a branch affected by ingested advisories,
+	/// replacing earlier [`LintCheck::Security`] findings of the branch.
+	///
+	/// Returns the count of findings.
+	pub async fn flag_branch(&self, branch: BranchRef) -> Result<usize> {
+		let affected = self.affected(&[branch]).await?;
+		let kind = LintCheck::Security.as_str();
+		let messages = affected
+			.iter()
+			.map(|affected| {
+				format!(
+					"version {} is affected by {}",
+					affected.version, affected.advisory.name
+				)
+			})
+			.collect::<Vec<_>>();
+		let rows = affected
+			.iter()
+			.zip(&messages)
+			.map(|(affected, message)| NewPkgFindingRow {
+				branch,
+				package: XUuidVal(affected.package),
+				kind,
+				severity: SqlFindingSeverity::Error,
+				message,
+			})
+			.collect::<Vec<_>>();
+
+		let mut conn = self.db.get().await?;
+		conn.transaction::<_, crate::BackendError, _>(async |conn| {
+			conn.execute(
+				delete(finding_dsl::pkg_finding)
+					.filter(finding_dsl::branch.eq(branch))
+					.filter(finding_dsl::kind.eq(kind)),
+			)
+			.await?;
+			for chunk in rows.chunks(INSERT_CHUNK_SIZE) {
+				conn.execute(insert_into(finding_dsl::pkg_finding).values(chunk))
+					.await?;
+			}
+			Ok(())
+		})
+		.await?;
+		Ok(rows.len())
+	}
+
+	/// Flags packages of all branches, see [`SecurityService::flag_branch`].
+	pub async fn flag_all(&self) -> Result<usize> {
+		let branches = {
+			let mut conn = self.db.get().await?;
+			conn.load::<_, BranchRef>(branch_dsl::branch.select(branch_dsl::id))
+				.await?
+		};
+		let mut total = 0;
+		for branch in branches {
+			total += self.flag_branch(branch).await?;
+		}
+		info!(findings = total, "flagged packages affected by advisories");
+		Ok(total)
+	}
+}
+
+/// Errors of security advisories.
+#[derive(Debug, Error)]
+pub enum SecurityError {
+	#[error("failed to fetch advisory feed: {0}")]
+	FetchError(#[from] reqwest::Error),
+	#[error("failed to read advisory feed: {0}")]
+	IoError(#[from] io::Error),
+	#[error("invalid advisory feed {0}: {1}")]
+	InvalidFeed(KString, serde_json::Error),
+}
+
+#[cfg(test)]
+mod test {
+	use std::{env, fs};
+
+	use serde_json::json;
+	use uuid::Uuid;
+
+	use crate::{
+		branch::BranchConfigInfo,
+		lint::LintCheck,
+		namespace::DEFAULT_NAMESPACE_ID,
+		security::{AffectedVersions, FeedConfig, FeedFormat, VersionRange, parse_nvd, parse_osv},
+		test::{insert_test_package, test_config, test_env_with_config},
+	};
+
+	#[test]
+	fn test_parse_osv() {
+		let feed = json!([{
+			"id": "OSV-1",
+			"summary": "bad",
+			"affected": [
+				{
+					"package": { "name": "xz", "ecosystem": "Debian:12" },
+					"ranges": [
+						{
+							"type": "ECOSYSTEM",
+							"events": [{ "introduced": "0" }, { "fixed": "5.4.1" }],
+						},
+						{ "type": "GIT", "events": [{ "introduced": "abcdef" }] },
+					],
+					"versions": ["5.6.0"],
+				},
+				{ "package": { "name": "xz", "ecosystem": "Alpine" }, "versions": ["5.6.1"] },
+			],
+		}]);
+		let advisories = parse_osv(feed.to_string().as_bytes(), Some("Debian")).unwrap();
+		assert_eq!(advisories.len(), 1);
+		assert_eq!(
+			advisories[0].affected,
+			AffectedVersions {
+				versions: vec!["5.6.0".to_string()],
+				ranges: vec![VersionRange {
+					fixed: Some("5.4.1".to_string()),
+					..Default::default()
+				}],
+			}
+		);
+		assert!(advisories[0].affected.contains("5.2"));
+		assert!(advisories[0].affected.contains("5.6.0"));
+		assert!(!advisories[0].affected.contains("5.4.1"));
+	}
+
+	#[test]
+	fn test_parse_nvd() {
+		let feed = json!({
+			"vulnerabilities": [{
+				"cve": {
+					"id": "CVE-2024-3094",
+					"descriptions": [{ "lang": "en", "value": "backdoor" }],
+					"configurations": [{ "nodes": [{ "cpeMatch": [
+						{
+							"vulnerable": true,
+							"criteria": "cpe:2.3:a:tukaani:xz:5.6.0:*:*:*:*:*:*:*",
+						},
+						{
+							"vulnerable": true,
+							"criteria": "cpe:2.3:a:tukaani:xz:*:*:*:*:*:*:*:*",
+							"versionStartIncluding": "5.6.1",
+							"versionEndIncluding": "5.6.2",
+						},
+						{
+							"vulnerable": false,
+							"criteria": "cpe:2.3:o:linux:linux_kernel:-:*:*:*:*:*:*:*",
+						},
+					] }] }],
+				},
+			}],
+		});
+		let advisories = parse_nvd(feed.to_string().as_bytes()).unwrap();
+		assert_eq!(advisories.len(), 1);
+		let advisory = &advisories[0];
+		assert_eq!(advisory.package, "xz");
+		assert_eq!(advisory.summary, "backdoor");
+		assert!(advisory.affected.contains("5.6.0"));
+		assert!(advisory.affected.contains("5.6.2"));
+		assert!(!advisory.affected.contains("5.6.3"));
+	}
+
+	#[tokio::test]
+	async fn test_ingest_flag() {
+		let path = env::temp_dir().join(format!("fabricia-osv-{}.json", Uuid::now_v7()));
+		let feed = json!([{
+			"id": "CVE-2024-3094",
+			"affected": [{ "package": { "name": "xz" }, "versions": ["5.6.0"] }],
+		}]);
+		fs::write(&path, feed.to_string()).unwrap();
+		let mut config = test_config();
+		config.security.feeds.push(FeedConfig {
+			name: "osv".into(),
+			url: format!("file://{}", path.display()),
+			format: FeedFormat::Osv,
+			ecosystem: None,
+		});
+		let env = test_env_with_config(config).await;
+		for name in ["main", "stable"] {
+			env.branch
+				.track(DEFAULT_NAMESPACE_ID, name, BranchConfigInfo::default())
+				.await
+				.unwrap();
+		}
+
+		let mut db = env.database.get().await.unwrap();
+		let mut ids = vec![];
+		for version in ["5.6.0", "5.4.6"] {
+			let branch = ids.len() as i64 + 1;
+			let data = json!({ "version": version, "srcs": ["tbl::https://tukaani.org"] });
+			let row = insert_test_package(&mut db, branch, "xz", data).await;
+			ids.push(row.id);
+		}
+		drop(db);
+
+		assert_eq!(env.security.ingest().await.unwrap(), 1);
+		// ingesting again replaces advisories of the feed
+		assert_eq!(env.security.ingest().await.unwrap(), 1);
+		let affected = env.security.affected(&[1, 2]).await.unwrap();
+		assert_eq!(affected.len(), 1);
+		assert_eq!(affected[0].package, ids[0].0);
+
+		assert_eq!(env.security.flag_branch(1).await.unwrap(), 1);
+		// findings of other checks do not replace security findings
+		env.lint.run(ids[0].0).await.unwrap();
+		let findings = env.lint.list_branch(1).await.unwrap();
+		assert_eq!(findings.len(), 1);
+		assert_eq!(findings[0].kind, LintCheck::Security.as_str());
+		assert_eq!(env.security.flag_all().await.unwrap(), 1);
+		fs::remove_file(&path).unwrap();
+	}
+}
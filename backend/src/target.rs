@@ -6,6 +6,7 @@ use std::{
 
 use kstring::KString;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::Result;
 
@@ -22,6 +23,8 @@ pub struct TargetInfo {
 	pub name: KString,
 	/// AOSC OS architecture name
 	pub arch: KString,
+	/// Whether the target is being retired, see [`TargetConfig::deprecated`].
+	pub deprecated: bool,
 }
 
 impl TargetInfo {
@@ -32,6 +35,16 @@ impl TargetInfo {
 	}
 }
 
+impl From<&TargetInfo> for fabricia_common_model::target::TargetInfo {
+	fn from(value: &TargetInfo) -> Self {
+		Self {
+			name: value.name.to_string(),
+			arch: value.arch.to_string(),
+			deprecated: value.deprecated,
+		}
+	}
+}
+
 impl PartialOrd for TargetInfo {
 	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
 		self.id.partial_cmp(&other.id)
@@ -48,19 +61,37 @@ impl Ord for TargetInfo {
 pub struct TargetService {
 	by_id: HashMap<TargetId, Arc<TargetInfo>>,
 	by_name: HashMap<KString, Arc<TargetInfo>>,
+	/// Targets of each group, sorted by IDs.
+	groups: HashMap<KString, Vec<Arc<TargetInfo>>>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
 pub struct TargetConfig {
 	pub name: KString,
 	pub arch: Option<KString>,
+	/// Retires the target while keeping its history.
+	///
+	/// No package states or builds are created for deprecated targets,
+	/// while existing states are still readable.
+	#[serde(default)]
+	pub deprecated: bool,
+}
+
+/// A named set of targets, which branches may select to build for.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct TargetGroupConfig {
+	/// Name of the group, e.g. `mainline` or `retro`.
+	pub name: KString,
+	/// Names of targets in this group.
+	pub targets: Vec<KString>,
 }
 
 impl TargetService {
-	pub fn new(config: &Vec<TargetConfig>) -> Result<Self> {
+	pub fn new(config: &Vec<TargetConfig>, groups: &[TargetGroupConfig]) -> Result<Self> {
 		let mut service = Self {
 			by_id: HashMap::new(),
 			by_name: HashMap::new(),
+			groups: HashMap::with_capacity(groups.len()),
 		};
 
 		for target in config {
@@ -71,11 +102,133 @@ impl TargetService {
 				id,
 				name: target.name.clone(),
 				arch,
+				deprecated: target.deprecated,
 			});
 			service.by_id.insert(id, target.clone());
 			service.by_name.insert(target.name.clone(), target);
 		}
 
+		for group in groups {
+			let mut targets =
+				group
+					.targets
+					.iter()
+					.map(|name| {
+						service.by_name.get(name).cloned().ok_or_else(|| {
+							TargetError::UnknownTarget(group.name.clone(), name.clone())
+						})
+					})
+					.collect::<std::result::Result<Vec<_>, _>>()?;
+			targets.sort();
+			targets.dedup();
+			service.groups.insert(group.name.clone(), targets);
+		}
+
 		Ok(service)
 	}
+
+	/// Finds a target by its ID.
+	pub fn get(&self, id: TargetId) -> Option<&Arc<TargetInfo>> {
+		self.by_id.get(&id)
+	}
+
+	/// Finds a target by its name.
+	pub fn find<S: AsRef<str>>(&self, name: S) -> Option<&Arc<TargetInfo>> {
+		self.by_name.get(name.as_ref())
+	}
+
+	/// Returns whether a target group is configured.
+	pub fn has_group<S: AsRef<str>>(&self, group: S) -> bool {
+		self.groups.contains_key(group.as_ref())
+	}
+
+	/// Returns the count of configured targets.
+	pub fn count(&self) -> usize {
+		self.by_id.len()
+	}
+
+	/// Returns whether a target is configured and deprecated.
+	///
+	/// Targets removed from the configuration are not deprecated, as they are
+	/// skipped anyway.
+	pub fn is_deprecated(&self, id: TargetId) -> bool {
+		self.get(id).is_some_and(|target| target.deprecated)
+	}
+
+	/// Returns targets selected by a branch.
+	///
+	/// Branches without target groups are built for all targets.
+	/// Deprecated targets are never selected.
+	/// Returns [`None`] if the group is not configured.
+	pub fn select(&self, group: Option<&str>) -> Option<Vec<Arc<TargetInfo>>> {
+		let mut targets = match group {
+			Some(group) => self.groups.get(group).cloned()?,
+			None => {
+				let mut targets = self.by_id.values().cloned().collect::<Vec<_>>();
+				targets.sort();
+				targets
+			}
+		};
+		targets.retain(|target| !target.deprecated);
+		Some(targets)
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum TargetError {
+	#[error("target group {0} refers to unknown target {1}")]
+	UnknownTarget(KString, KString),
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{
+		BackendError,
+		target::{TargetConfig, TargetError, TargetGroupConfig, TargetService},
+	};
+
+	#[test]
+	fn test_select() {
+		let mut targets = ["amd64", "arm64", "loongson3"]
+			.map(|name| TargetConfig {
+				name: name.into(),
+				arch: None,
+				deprecated: false,
+			})
+			.to_vec();
+		let groups = [TargetGroupConfig {
+			name: "mainline".into(),
+			targets: vec!["arm64".into(), "amd64".into()],
+		}];
+		let service = TargetService::new(&targets, &groups).unwrap();
+
+		let names = |group| {
+			let mut names = service
+				.select(group)
+				.unwrap()
+				.iter()
+				.map(|target| target.name.to_string())
+				.collect::<Vec<_>>();
+			names.sort();
+			names
+		};
+		assert_eq!(names(None), ["amd64", "arm64", "loongson3"]);
+		assert_eq!(names(Some("mainline")), ["amd64", "arm64"]);
+		assert!(service.select(Some("retro")).is_none());
+
+		// deprecated targets are kept, but never selected
+		targets[2].deprecated = true;
+		let service = TargetService::new(&targets, &groups).unwrap();
+		assert_eq!(service.select(None).unwrap().len(), 2);
+		assert!(service.find("loongson3").unwrap().deprecated);
+
+		let groups = [TargetGroupConfig {
+			name: "retro".into(),
+			targets: vec!["i486".into()],
+		}];
+		assert!(matches!(
+			TargetService::new(&targets, &groups),
+			Err(BackendError::TargetError(TargetError::UnknownTarget(..)))
+		));
+	}
 }
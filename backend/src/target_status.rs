@@ -0,0 +1,244 @@
+//! Health of a build target across all branches.
+//!
+//! Builds are not timed by themselves, so durations are taken from the history
+//! of the jobs recorded as the last builds of package targets.
+
+use std::collections::HashSet;
+
+use diesel::{ExpressionMethods, JoinOnDsl, QueryDsl};
+use time::{Duration, PrimitiveDateTime};
+use uuid::Uuid;
+
+use crate::{
+	Result,
+	branch::BranchRef,
+	db::{
+		BoxedSqlConn,
+		schema::{job_history, pkg, pkg_target},
+		utils::{XJsonVal, XUuidVal},
+	},
+	job_history::SqlJobOutcome,
+	model::{PkgRow, PkgTargetRow},
+	package::{PkgTargetData, SqlPackageTargetState},
+	target::TargetId,
+};
+
+/// Count of the latest changed package targets sampled for build durations.
+const DURATION_SAMPLES: i64 = 500;
+
+/// Health of a build target.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TargetStatus {
+	/// Count of packages not built since the last change.
+	pub dirty: u64,
+	pub ready: u64,
+	pub build_failed: u64,
+	pub error: u64,
+	/// Packages failed on the target, latest first.
+	pub failures: Vec<TargetFailure>,
+	/// Average duration of builds on the target, [`None`] if none is sampled.
+	pub avg_build_duration: Option<Duration>,
+}
+
+/// A package whose last build on the target has failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetFailure {
+	pub branch: BranchRef,
+	pub package: String,
+	pub status: SqlPackageTargetState,
+	/// ID of the last build job.
+	pub last_build: Option<Uuid>,
+	/// Time of the failure in UTC.
+	pub updated_at: PrimitiveDateTime,
+}
+
+/// Returns the health of a target, with up to `failures` recent failures.
+pub async fn target_status(
+	conn: &mut BoxedSqlConn,
+	target: TargetId,
+	failures: usize,
+) -> Result<TargetStatus> {
+	let target = target as i64;
+	let mut status = TargetStatus::default();
+
+	let counts = conn
+		.load::<_, (SqlPackageTargetState, i64)>(
+			pkg_target::table
+				.filter(pkg_target::target.eq(target))
+				.group_by(pkg_target::status)
+				.select((pkg_target::status, diesel::dsl::count_star())),
+		)
+		.await?;
+	for (state, count) in counts {
+		let count = count as u64;
+		match state {
+			SqlPackageTargetState::Dirty => status.dirty += count,
+			SqlPackageTargetState::Ready => status.ready += count,
+			SqlPackageTargetState::BuildFailed => status.build_failed += count,
+			SqlPackageTargetState::Error => status.error += count,
+		}
+	}
+
+	let rows: Vec<(PkgRow, PkgTargetRow)> = conn
+		.load_select(
+			pkg::table
+				.inner_join(pkg_target::table.on(pkg_target::package.eq(pkg::id)))
+				.filter(pkg_target::target.eq(target))
+				.filter(pkg_target::status.eq_any([
+					SqlPackageTargetState::BuildFailed,
+					SqlPackageTargetState::Error,
+				]))
+				.order(pkg_target::updated_at.desc())
+				.limit(failures.try_into().unwrap_or(i64::MAX)),
+		)
+		.await?;
+	for (pkg, state) in rows {
+		status.failures.push(TargetFailure {
+			branch: pkg.branch,
+			package: pkg.name,
+			status: state.status,
+			last_build: state.target_data()?.last_build,
+			updated_at: state.updated_at,
+		});
+	}
+
+	status.avg_build_duration = avg_build_duration(conn, target).await?;
+	Ok(status)
+}
+
+/// Averages durations of the last succeeded builds of recently changed packages on a target.
+async fn avg_build_duration(conn: &mut BoxedSqlConn, target: i64) -> Result<Option<Duration>> {
+	let builds = conn
+		.load::<_, XJsonVal>(
+			pkg_target::table
+				.filter(pkg_target::target.eq(target))
+				.order(pkg_target::updated_at.desc())
+				.limit(DURATION_SAMPLES)
+				.select(pkg_target::data),
+		)
+		.await?
+		.into_iter()
+		.filter_map(|data| PkgTargetData::from_json(data.0).ok()?.last_build)
+		.map(XUuidVal)
+		.collect::<HashSet<_>>();
+	if builds.is_empty() {
+		return Ok(None);
+	}
+
+	// builds cached from other packages are counted once
+	let durations = conn
+		.load::<_, i64>(
+			job_history::table
+				.filter(job_history::id.eq_any(builds))
+				.filter(job_history::outcome.eq(SqlJobOutcome::Succeeded))
+				.select(job_history::duration_ms),
+		)
+		.await?;
+	if durations.is_empty() {
+		return Ok(None);
+	}
+	let avg_ms = durations.iter().sum::<i64>() / durations.len() as i64;
+	Ok(Some(Duration::milliseconds(avg_ms)))
+}
+
+#[cfg(test)]
+mod test {
+	use time::Duration;
+	use uuid::Uuid;
+
+	use crate::{
+		job_history::{FinishedJob, SqlJobOutcome},
+		model::PkgRow,
+		namespace::DEFAULT_NAMESPACE_ID,
+		package::{PkgData, PkgTargetData, SqlPackageTargetState},
+		target_status::target_status,
+		test::{insert_test_package, insert_test_target, test_env, test_time},
+	};
+
+	#[tokio::test]
+	async fn test_target_status() {
+		let env = test_env().await;
+		env.branch
+			.track(DEFAULT_NAMESPACE_ID, "main", Default::default())
+			.await
+			.unwrap();
+		let branch = env
+			.branch
+			.find_id(DEFAULT_NAMESPACE_ID, "main")
+			.await
+			.unwrap()
+			.unwrap();
+		let mut db = env.database.get().await.unwrap();
+
+		let build = Uuid::now_v7();
+		let states = [
+			("bash", 10, SqlPackageTargetState::Ready),
+			("glibc", 10, SqlPackageTargetState::BuildFailed),
+			("zlib", 10, SqlPackageTargetState::Dirty),
+			("zlib", 11, SqlPackageTargetState::BuildFailed),
+		];
+		let mut ids = Vec::new();
+		let mut packages: Vec<PkgRow> = Vec::new();
+		for (name, target, status) in states {
+			let pkg = match packages.iter().find(|pkg| pkg.name == name) {
+				Some(pkg) => pkg.clone(),
+				None => {
+					let data = PkgData::default().to_json().unwrap();
+					let pkg = insert_test_package(&mut db, branch, name, data).await;
+					packages.push(pkg.clone());
+					pkg
+				}
+			};
+			let data = PkgTargetData {
+				last_build: Some(build),
+				..Default::default()
+			};
+			let data = data.to_json().unwrap();
+			let row = insert_test_target(&mut db, &pkg, target, status, data).await;
+			ids.push(row.id.0);
+		}
+
+		let status = target_status(&mut db, 10, 20).await.unwrap();
+		assert_eq!(
+			(
+				status.dirty,
+				status.ready,
+				status.build_failed,
+				status.error
+			),
+			(1, 1, 1, 0)
+		);
+		assert_eq!(status.failures.len(), 1);
+		assert_eq!(status.failures[0].package, "glibc");
+		assert_eq!(status.failures[0].last_build, Some(build));
+		assert_eq!(status.avg_build_duration, None);
+
+		// the build job has finished
+		env.job_history
+			.record(
+				&mut db,
+				FinishedJob {
+					id: build,
+					kind: "Build".to_string(),
+					data: serde_json::json!({}),
+					subject_branch: Some(branch),
+					subject: format!("pkg-target:{}", ids[0]),
+					outcome: SqlJobOutcome::Succeeded,
+					started_at: test_time(),
+					error: None,
+					runner: None,
+				},
+			)
+			.await
+			.unwrap();
+		let status = target_status(&mut db, 10, 20).await.unwrap();
+		assert!(status.avg_build_duration.unwrap() > Duration::ZERO);
+		assert!(
+			target_status(&mut db, 12, 20)
+				.await
+				.unwrap()
+				.failures
+				.is_empty()
+		);
+	}
+}
@@ -0,0 +1,139 @@
+//! Propagation of trace contexts in the W3C Trace Context format.
+//!
+//! The trace context of a request is kept in a task-local while handling it,
+//! and recorded into jobs enqueued by the request, so that logs of the request
+//! and the spawned jobs can be correlated by the trace ID.
+
+use std::{fmt, future::Future};
+
+use rand::Rng;
+
+tokio::task_local! {
+	static CURRENT: TraceContext;
+}
+
+/// A trace context, formatted as a `traceparent` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceContext {
+	pub trace_id: u128,
+	/// ID of the span which this context is propagated from.
+	pub parent_id: u64,
+	pub sampled: bool,
+}
+
+impl TraceContext {
+	/// Creates a context of a new trace.
+	pub fn new_root() -> Self {
+		let mut rng = rand::rng();
+		Self {
+			trace_id: rng.random_range(1..=u128::MAX),
+			parent_id: rng.random_range(1..=u64::MAX),
+			sampled: true,
+		}
+	}
+
+	/// Creates a context of a new span in the same trace.
+	pub fn child(&self) -> Self {
+		Self {
+			parent_id: rand::rng().random_range(1..=u64::MAX),
+			..*self
+		}
+	}
+
+	/// Parses a `traceparent` header.
+	///
+	/// Returns [`None`] for malformed headers and all-zero IDs.
+	pub fn parse(traceparent: &str) -> Option<Self> {
+		let mut parts = traceparent.trim().split('-');
+		let version = parts.next()?;
+		let trace_id = parts.next()?;
+		let parent_id = parts.next()?;
+		let flags = parts.next()?;
+		if version.len() != 2 || version == "ff" || trace_id.len() != 32 {
+			return None;
+		}
+		// future versions may append fields
+		if version == "00" && parts.next().is_some() {
+			return None;
+		}
+		if parent_id.len() != 16 || flags.len() != 2 {
+			return None;
+		}
+		u8::from_str_radix(version, 16).ok()?;
+		let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+		let parent_id = u64::from_str_radix(parent_id, 16).ok()?;
+		let flags = u8::from_str_radix(flags, 16).ok()?;
+		if trace_id == 0 || parent_id == 0 {
+			return None;
+		}
+		Some(Self {
+			trace_id,
+			parent_id,
+			sampled: flags & 1 != 0,
+		})
+	}
+
+	/// Returns the trace context of the current task.
+	pub fn current() -> Option<Self> {
+		CURRENT.try_with(|context| *context).ok()
+	}
+
+	/// Runs a future with this context as the current one.
+	pub async fn scope<F: Future>(self, future: F) -> F::Output {
+		CURRENT.scope(self, future).await
+	}
+
+	/// Returns the trace ID in hex, to be recorded in spans.
+	pub fn trace_id_hex(&self) -> String {
+		format!("{:032x}", self.trace_id)
+	}
+}
+
+impl fmt::Display for TraceContext {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"00-{:032x}-{:016x}-{:02x}",
+			self.trace_id, self.parent_id, self.sampled as u8
+		)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_parse() {
+		let context =
+			TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+		assert_eq!(context.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+		assert_eq!(context.parent_id, 0x00f067aa0ba902b7);
+		assert!(context.sampled);
+		assert_eq!(
+			context.to_string(),
+			"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+		);
+
+		assert!(
+			TraceContext::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01")
+				.is_none()
+		);
+		assert!(
+			TraceContext::parse("ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+				.is_none()
+		);
+		assert!(TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-01").is_none());
+
+		let root = TraceContext::new_root();
+		assert_eq!(TraceContext::parse(&root.to_string()), Some(root));
+	}
+
+	#[tokio::test]
+	async fn test_scope() {
+		assert!(TraceContext::current().is_none());
+		let context = TraceContext::new_root();
+		let current = context.scope(async { TraceContext::current() }).await;
+		assert_eq!(current, Some(context));
+	}
+}
@@ -0,0 +1,289 @@
+//! Latest upstream versions of packages.
+//!
+//! Versions are looked up from an [Anitya](https://release-monitoring.org) instance
+//! by [`JobCommand::RefreshUpstream`] jobs, which are enqueued periodically by
+//! [`UpstreamService::run_scheduler`].
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use diesel::{
+	ExpressionMethods, QueryDsl, insert_into,
+	result::{DatabaseErrorKind, Error as DieselError},
+	update,
+};
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::{info, warn};
+
+use crate::{
+	Result,
+	db::{
+		schema::{pkg::dsl as pkg_dsl, upstream_version::dsl},
+		service::DatabaseService,
+	},
+	job_queue::{JobCommand, JobQueue},
+	model::UpstreamVersionRow,
+	package::compare_versions,
+	security::SecurityError,
+};
+
+/// Job kind of [`JobCommand::RefreshUpstream`].
+const REFRESH_JOB_KIND: &str = "RefreshUpstream";
+
+/// Configuration of upstream version checks.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UpstreamConfig {
+	/// Base URL of the Anitya instance.
+	#[serde(default = "default_url")]
+	pub url: String,
+	/// Distribution in Anitya, mapping package names to upstream projects.
+	///
+	/// Package names are looked up as project names if not set.
+	#[serde(default)]
+	pub distribution: Option<String>,
+	/// Interval in seconds between refreshes.
+	#[serde(default = "default_interval")]
+	pub interval: u64,
+}
+
+fn default_url() -> String {
+	"https://release-monitoring.org".to_string()
+}
+
+fn default_interval() -> u64 {
+	24 * 60 * 60
+}
+
+#[derive(Debug, Deserialize)]
+struct AnityaItems {
+	#[serde(default)]
+	items: Vec<AnityaItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnityaItem {
+	version: Option<String>,
+	stable_version: Option<String>,
+}
+
+/// Returns whether a packaged version is older than the upstream version.
+pub fn is_outdated(version: &str, upstream: &str) -> bool {
+	!version.is_empty() && compare_versions(version, upstream).is_lt()
+}
+
+/// Service for upstream versions of packages.
+#[derive(Debug)]
+pub struct UpstreamService {
+	db: Arc<DatabaseService>,
+	job_queue: Arc<JobQueue>,
+	/// Upstream version checks are disabled if not set.
+	config: Option<UpstreamConfig>,
+	http: reqwest::Client,
+}
+
+impl UpstreamService {
+	pub fn new(
+		db: Arc<DatabaseService>,
+		job_queue: Arc<JobQueue>,
+		config: Option<&UpstreamConfig>,
+	) -> Self {
+		Self {
+			db,
+			job_queue,
+			config: config.cloned(),
+			http: reqwest::Client::new(),
+		}
+	}
+
+	/// Returns whether upstream version checks are enabled.
+	pub fn is_enabled(&self) -> bool {
+		self.config.is_some()
+	}
+
+	/// Looks up the latest upstream version of a package.
+	async fn lookup(&self, config: &UpstreamConfig, name: &str) -> Result<Option<String>> {
+		let base = config.url.trim_end_matches('/');
+		let request = match &config.distribution {
+			Some(distribution) => self
+				.http
+				.get(format!("{base}/api/v2/packages/"))
+				.query(&[("name", name), ("distribution", distribution)]),
+			None => self
+				.http
+				.get(format!("{base}/api/v2/projects/"))
+				.query(&[("name", name)]),
+		};
+		let body = request
+			.send()
+			.await
+			.and_then(|response| response.error_for_status())
+			.map_err(SecurityError::from)?
+			.bytes()
+			.await
+			.map_err(SecurityError::from)?;
+		let items: AnityaItems = serde_json::from_slice(&body)?;
+		Ok(items
+			.items
+			.into_iter()
+			.next()
+			.and_then(|item| item.stable_version.or(item.version)))
+	}
+
+	/// Refreshes upstream versions of all tracked package names.
+	///
+	/// Returns the count of packages known upstream.
+	pub async fn refresh(&self) -> Result<usize> {
+		let Some(config) = &self.config else {
+			return Ok(0);
+		};
+		let names = {
+			let mut conn = self.db.get().await?;
+			conn.load::<_, String>(pkg_dsl::pkg.select(pkg_dsl::name).distinct())
+				.await?
+		};
+		let mut known = 0;
+		for name in names {
+			let version = self.lookup(config, &name).await?;
+			known += version.is_some() as usize;
+			self.record(&name, version.as_deref()).await?;
+		}
+		info!(known, "refreshed upstream versions");
+		Ok(known)
+	}
+
+	/// Records the upstream version of a package.
+	pub async fn record(&self, name: &str, version: Option<&str>) -> Result<()> {
+		let time = OffsetDateTime::now_utc();
+		let row = UpstreamVersionRow {
+			name: name.to_string(),
+			version: version.map(str::to_string),
+			checked_at: PrimitiveDateTime::new(time.date(), time.time()),
+		};
+		let mut conn = self.db.get().await?;
+		let updated = conn
+			.execute(
+				update(dsl::upstream_version)
+					.filter(dsl::name.eq(&row.name))
+					.set((
+						dsl::version.eq(&row.version),
+						dsl::checked_at.eq(row.checked_at),
+					)),
+			)
+			.await?;
+		if updated > 0 {
+			return Ok(());
+		}
+		let result = conn
+			.execute(insert_into(dsl::upstream_version).values(row))
+			.await;
+		match result {
+			// recorded by another runner at the same time
+			Ok(_) | Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+				Ok(())
+			}
+			Err(error) => Err(error.into()),
+		}
+	}
+
+	/// Returns recorded upstream versions of packages, by package name.
+	///
+	/// Packages unknown upstream are omitted.
+	pub async fn versions(&self, names: Vec<String>) -> Result<HashMap<String, String>> {
+		let mut conn = self.db.get().await?;
+		let rows: Vec<UpstreamVersionRow> = conn
+			.load_select(dsl::upstream_version.filter(dsl::name.eq_any(names)))
+			.await?;
+		Ok(rows
+			.into_iter()
+			.filter_map(|row| Some((row.name, row.version?)))
+			.collect())
+	}
+
+	/// Enqueues a refresh unless one is pending or running.
+	pub async fn schedule(&self) -> Result<bool> {
+		let depth = self.job_queue.depth().await?;
+		let queued = depth
+			.iter()
+			.any(|depth| depth.kind == REFRESH_JOB_KIND && depth.pending + depth.running > 0);
+		if queued {
+			return Ok(false);
+		}
+		let mut conn = self.db.get().await?;
+		self.job_queue
+			.enqueue(&mut conn, JobCommand::RefreshUpstream)
+			.await?;
+		Ok(true)
+	}
+
+	/// Enqueues refreshes periodically until the process exits.
+	///
+	/// Does nothing if upstream version checks are disabled.
+	pub async fn run_scheduler(self: Arc<Self>) {
+		let Some(config) = &self.config else {
+			return;
+		};
+		let mut interval = tokio::time::interval(Duration::from_secs(config.interval.max(1)));
+		loop {
+			interval.tick().await;
+			if let Err(error) = self.schedule().await {
+				warn!(?error, "failed to schedule upstream version refresh");
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{
+		job_queue::JobCommand,
+		test::{test_config, test_env, test_env_with_config},
+		upstream::{UpstreamConfig, is_outdated},
+	};
+
+	#[test]
+	fn test_is_outdated() {
+		assert!(is_outdated("5.2.15", "5.2.37"));
+		assert!(!is_outdated("5.2.37", "5.2.37"));
+		assert!(!is_outdated("", "5.2.37"));
+	}
+
+	#[tokio::test]
+	async fn test_versions() {
+		let env = test_env().await;
+		assert!(!env.upstream.is_enabled());
+		assert_eq!(env.upstream.refresh().await.unwrap(), 0);
+
+		env.upstream.record("bash", Some("5.2.36")).await.unwrap();
+		env.upstream.record("bash", Some("5.2.37")).await.unwrap();
+		env.upstream.record("unknown", None).await.unwrap();
+		let versions = env
+			.upstream
+			.versions(vec!["bash".to_string(), "unknown".to_string()])
+			.await
+			.unwrap();
+		assert_eq!(versions.len(), 1);
+		assert_eq!(versions["bash"], "5.2.37");
+	}
+
+	#[tokio::test]
+	async fn test_schedule() {
+		let mut config = test_config();
+		config.upstream = Some(UpstreamConfig {
+			url: "http://localhost".to_string(),
+			distribution: None,
+			interval: 60,
+		});
+		let env = test_env_with_config(config).await;
+		assert!(env.upstream.schedule().await.unwrap());
+		assert!(!env.upstream.schedule().await.unwrap());
+
+		let job = env.job_queue.fetch_and_start().await.unwrap().unwrap();
+		assert_eq!(job.command, JobCommand::RefreshUpstream);
+		assert!(!env.upstream.schedule().await.unwrap());
+		let mut db = env.database.get().await.unwrap();
+		env.job_queue.finish_job(&mut db, job.id).await.unwrap();
+		drop(db);
+		assert!(env.upstream.schedule().await.unwrap());
+	}
+}
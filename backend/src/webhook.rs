@@ -0,0 +1,303 @@
+//! Verification of inbound webhook deliveries.
+//!
+//! Deliveries are authenticated by signatures or tokens of their providers,
+//! and each delivery is accepted only once, to protect against replays.
+
+use std::{collections::HashMap, sync::Arc};
+
+use hmac::{Hmac, Mac};
+use redis::AsyncCommands;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+
+use crate::redis::{RedisError, RedisService};
+
+/// Providers of webhooks.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookProvider {
+	/// Signed with HMAC-SHA256 in `X-Hub-Signature-256`.
+	GitHub,
+	/// Authenticated with the secret token in `X-Gitlab-Token`.
+	GitLab,
+	/// Signed with HMAC-SHA256 in `X-Gitea-Signature`.
+	Gitea,
+}
+
+impl WebhookProvider {
+	fn name(self) -> &'static str {
+		match self {
+			WebhookProvider::GitHub => "github",
+			WebhookProvider::GitLab => "gitlab",
+			WebhookProvider::Gitea => "gitea",
+		}
+	}
+
+	fn delivery_header(self) -> &'static str {
+		match self {
+			WebhookProvider::GitHub => "x-github-delivery",
+			WebhookProvider::GitLab => "x-gitlab-event-uuid",
+			WebhookProvider::Gitea => "x-gitea-delivery",
+		}
+	}
+
+	fn event_header(self) -> &'static str {
+		match self {
+			WebhookProvider::GitHub => "x-github-event",
+			WebhookProvider::GitLab => "x-gitlab-event",
+			WebhookProvider::Gitea => "x-gitea-event",
+		}
+	}
+}
+
+/// An authenticated delivery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+	pub provider: WebhookProvider,
+	/// Unique ID of the delivery, assigned by the provider.
+	pub id: String,
+	/// Kind of the event, e.g. `push`.
+	pub event: Option<String>,
+}
+
+/// Verifier of webhook deliveries, using Redis when available.
+///
+/// Without Redis, seen deliveries are kept in process,
+/// which only rejects replays to the same instance.
+#[derive(Debug)]
+pub struct WebhookVerifier {
+	redis: Option<Arc<RedisService>>,
+	local: std::sync::Mutex<HashMap<String, OffsetDateTime>>,
+	/// Duration to remember seen deliveries for.
+	window: Duration,
+}
+
+impl WebhookVerifier {
+	pub fn new(redis: Option<Arc<RedisService>>) -> Self {
+		Self {
+			redis,
+			local: Default::default(),
+			window: Duration::days(1),
+		}
+	}
+
+	/// Authenticates a delivery and records it as seen.
+	///
+	/// Fails if the signature or token mismatches the secret, or if the
+	/// delivery has been seen within the replay window.
+	pub async fn verify(
+		&self,
+		provider: WebhookProvider,
+		secret: &str,
+		headers: &HeaderMap,
+		body: &[u8],
+	) -> Result<WebhookDelivery, WebhookError> {
+		verify_signature(provider, secret, headers, body)?;
+		let id = header(headers, provider.delivery_header())
+			.ok_or(WebhookError::MissingHeader(provider.delivery_header()))?
+			.to_string();
+		let key = format!("webhook:nonce:{}:{id}", provider.name());
+		if !self.record(&key).await? {
+			return Err(WebhookError::Replayed(id));
+		}
+		Ok(WebhookDelivery {
+			provider,
+			id,
+			event: header(headers, provider.event_header()).map(str::to_string),
+		})
+	}
+
+	/// Records a nonce, and returns whether it has not been seen.
+	async fn record(&self, key: &str) -> Result<bool, WebhookError> {
+		match &self.redis {
+			Some(redis) => {
+				let mut conn = redis.get().await?;
+				let key = redis.key(key);
+				let options = redis::SetOptions::default()
+					.conditional_set(redis::ExistenceCheck::NX)
+					.with_expiration(redis::SetExpiry::EX(self.window.whole_seconds() as u64));
+				let set: Option<String> = conn
+					.set_options(&key, 1, options)
+					.await
+					.map_err(RedisError::RedisError)?;
+				Ok(set.is_some())
+			}
+			None => {
+				let now = OffsetDateTime::now_utc();
+				let mut local = self.local.lock().unwrap();
+				local.retain(|_, expiry| *expiry > now);
+				if local.contains_key(key) {
+					Ok(false)
+				} else {
+					local.insert(key.to_string(), now + self.window);
+					Ok(true)
+				}
+			}
+		}
+	}
+}
+
+/// Checks the signature or token of a delivery, without replay protection.
+pub fn verify_signature(
+	provider: WebhookProvider,
+	secret: &str,
+	headers: &HeaderMap,
+	body: &[u8],
+) -> Result<(), WebhookError> {
+	match provider {
+		WebhookProvider::GitHub => {
+			let signature = header(headers, "x-hub-signature-256")
+				.ok_or(WebhookError::MissingHeader("x-hub-signature-256"))?;
+			let signature = signature
+				.strip_prefix("sha256=")
+				.ok_or(WebhookError::InvalidSignature)?;
+			verify_hmac(secret, signature, body)
+		}
+		WebhookProvider::Gitea => {
+			let signature = header(headers, "x-gitea-signature")
+				.ok_or(WebhookError::MissingHeader("x-gitea-signature"))?;
+			verify_hmac(secret, signature, body)
+		}
+		WebhookProvider::GitLab => {
+			let token = header(headers, "x-gitlab-token")
+				.ok_or(WebhookError::MissingHeader("x-gitlab-token"))?;
+			// compared by MACs, so that the time does not leak the secret
+			let mut mac = Hmac::<Sha256>::new_from_slice(token.as_bytes())
+				.map_err(|_| WebhookError::InvalidSignature)?;
+			mac.update(b"gitlab");
+			let expected = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+				.map_err(|_| WebhookError::InvalidSignature)?
+				.chain_update(b"gitlab")
+				.finalize()
+				.into_bytes();
+			mac.verify_slice(&expected)
+				.map_err(|_| WebhookError::InvalidSignature)
+		}
+	}
+}
+
+fn verify_hmac(secret: &str, signature: &str, body: &[u8]) -> Result<(), WebhookError> {
+	let signature = hex::decode(signature).map_err(|_| WebhookError::InvalidSignature)?;
+	let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+		.map_err(|_| WebhookError::InvalidSignature)?;
+	mac.update(body);
+	mac.verify_slice(&signature)
+		.map_err(|_| WebhookError::InvalidSignature)
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+	headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+#[derive(Debug, Error)]
+pub enum WebhookError {
+	#[error("header {0} is missing")]
+	MissingHeader(&'static str),
+	#[error("signature or token mismatched")]
+	InvalidSignature,
+	#[error("delivery {0} has been received")]
+	Replayed(String),
+	#[error(transparent)]
+	RedisError(#[from] RedisError),
+}
+
+#[cfg(test)]
+mod test {
+	use reqwest::header::HeaderValue;
+
+	use super::*;
+
+	// fixture from the documentation of GitHub on validating webhook deliveries
+	const SECRET: &str = "It's a Secret to Everybody";
+	const BODY: &[u8] = b"Hello, World!";
+	const SIGNATURE: &str = "757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+	fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		for (name, value) in pairs {
+			headers.insert(*name, HeaderValue::from_str(value).unwrap());
+		}
+		headers
+	}
+
+	#[test]
+	fn test_github_signature() {
+		let signed = headers(&[("x-hub-signature-256", &format!("sha256={SIGNATURE}"))]);
+		verify_signature(WebhookProvider::GitHub, SECRET, &signed, BODY).unwrap();
+		assert!(matches!(
+			verify_signature(WebhookProvider::GitHub, "other", &signed, BODY),
+			Err(WebhookError::InvalidSignature)
+		));
+		assert!(matches!(
+			verify_signature(WebhookProvider::GitHub, SECRET, &signed, b"Hello, World?"),
+			Err(WebhookError::InvalidSignature)
+		));
+		assert!(matches!(
+			verify_signature(WebhookProvider::GitHub, SECRET, &HeaderMap::new(), BODY),
+			Err(WebhookError::MissingHeader(_))
+		));
+	}
+
+	#[test]
+	fn test_gitea_signature() {
+		let signed = headers(&[("x-gitea-signature", SIGNATURE)]);
+		verify_signature(WebhookProvider::Gitea, SECRET, &signed, BODY).unwrap();
+		assert!(matches!(
+			verify_signature(WebhookProvider::Gitea, "other", &signed, BODY),
+			Err(WebhookError::InvalidSignature)
+		));
+	}
+
+	#[test]
+	fn test_gitlab_token() {
+		let signed = headers(&[("x-gitlab-token", SECRET)]);
+		verify_signature(WebhookProvider::GitLab, SECRET, &signed, BODY).unwrap();
+		assert!(matches!(
+			verify_signature(WebhookProvider::GitLab, "other", &signed, BODY),
+			Err(WebhookError::InvalidSignature)
+		));
+	}
+
+	#[tokio::test]
+	async fn test_replay() {
+		let verifier = WebhookVerifier::new(None);
+		let delivery = headers(&[
+			("x-hub-signature-256", &format!("sha256={SIGNATURE}")),
+			("x-github-delivery", "72d3162e-cc78-11e3-81ab-4c9367dc0958"),
+			("x-github-event", "push"),
+		]);
+		let verified = verifier
+			.verify(WebhookProvider::GitHub, SECRET, &delivery, BODY)
+			.await
+			.unwrap();
+		assert_eq!(verified.event.as_deref(), Some("push"));
+		assert!(matches!(
+			verifier
+				.verify(WebhookProvider::GitHub, SECRET, &delivery, BODY)
+				.await,
+			Err(WebhookError::Replayed(_))
+		));
+	}
+
+	#[tokio::test]
+	async fn test_replay_redis() {
+		let redis = crate::test::test_config().redis.unwrap();
+		let verifier =
+			WebhookVerifier::new(Some(Arc::new(RedisService::new(&redis).await.unwrap())));
+		let id = uuid::Uuid::now_v7().to_string();
+		let delivery = headers(&[("x-gitlab-token", SECRET), ("x-gitlab-event-uuid", &id)]);
+		verifier
+			.verify(WebhookProvider::GitLab, SECRET, &delivery, BODY)
+			.await
+			.unwrap();
+		assert!(matches!(
+			verifier
+				.verify(WebhookProvider::GitLab, SECRET, &delivery, BODY)
+				.await,
+			Err(WebhookError::Replayed(_))
+		));
+	}
+}
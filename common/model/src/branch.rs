@@ -34,3 +34,18 @@ pub enum TrackingMode {
 	/// Do not track any packages.
 	Unmanaged,
 }
+
+/// Handling of packages removed upstream which have been built in a branch.
+///
+/// Packages which have never been built are always dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovalPolicy {
+	/// Drop the packages with their build states.
+	#[default]
+	Drop,
+	/// Drop the packages, moving their build states to the archive.
+	Archive,
+	/// Keep the packages and their build states, warning on each sync.
+	KeepWithWarning,
+}
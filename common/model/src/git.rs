@@ -0,0 +1,70 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use thiserror::Error;
+
+/// Git object ID.
+///
+/// Serialized as a hexadecimal string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GitOid {
+	Sha1([u8; 20]),
+	Sha256([u8; 32]),
+}
+
+#[derive(Debug, Error)]
+pub enum GitOidError {
+	#[error("invalid length of git object ID: {0}")]
+	InvalidLength(usize),
+	#[error("invalid hex of git object ID: {0}")]
+	InvalidHex(#[from] hex::FromHexError),
+}
+
+impl GitOid {
+	/// Creates an object ID from raw bytes.
+	///
+	/// The hash algorithm is decided by the length.
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, GitOidError> {
+		if let Ok(bytes) = bytes.try_into() {
+			Ok(Self::Sha1(bytes))
+		} else if let Ok(bytes) = bytes.try_into() {
+			Ok(Self::Sha256(bytes))
+		} else {
+			Err(GitOidError::InvalidLength(bytes.len()))
+		}
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		match self {
+			GitOid::Sha1(bytes) => bytes,
+			GitOid::Sha256(bytes) => bytes,
+		}
+	}
+}
+
+impl Display for GitOid {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&hex::encode(self.as_bytes()))
+	}
+}
+
+impl FromStr for GitOid {
+	type Err = GitOidError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::from_bytes(&hex::decode(s)?)
+	}
+}
+
+impl Serialize for GitOid {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl<'de> Deserialize<'de> for GitOid {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let s = String::deserialize(deserializer)?;
+		s.parse().map_err(de::Error::custom)
+	}
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// State of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+	/// The job is waiting in the queue.
+	Pending,
+	/// The job has been started by a runner.
+	Running,
+	/// The job has finished successfully.
+	Succeeded,
+	/// The job has failed and will not be retried.
+	Failed,
+	/// The job has been removed from the queue without finishing.
+	Aborted,
+}
@@ -1,2 +1,6 @@
 /// Common models for Fabricia.
 pub mod branch;
+pub mod git;
+pub mod job;
+pub mod package;
+pub mod target;
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// State of a package.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PackageStatus {
+	/// State for packages needing a metadata refresh.
+	///
+	/// In this state, all pending build jobs will be paused and wait for
+	/// the metadata to be ready.
+	Dirty,
+	/// State for packages ready to start packaging.
+	///
+	/// Only in this state, pending build jobs may be dispatched.
+	Ready,
+	/// State for packages with source-package-level errors.
+	///
+	/// No pending build jobs can be dispatched in this state.
+	Error { reason: String },
+}
+
+/// State of a package on a build target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageTargetStatus {
+	/// The package needs to be built for this target.
+	Dirty,
+	/// The package has been built for this target.
+	Ready,
+	/// The last build for this target has failed.
+	BuildFailed,
+	/// The package cannot be built for this target.
+	Error,
+}
+
+/// Reproducibility of a build of a package on a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReproStatus {
+	/// A second build is scheduled to verify the build.
+	Pending,
+	/// The second build has produced identical artifacts.
+	Reproducible,
+	/// The second build has produced different artifacts.
+	Unreproducible,
+}
+
+/// Severity of a finding of package checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingSeverity {
+	Info,
+	Warning,
+	/// Problems which must be fixed before the package is released.
+	Error,
+}
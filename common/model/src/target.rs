@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// Information of a build target.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TargetInfo {
+	/// Name of the target.
+	pub name: String,
+	/// AOSC OS architecture name.
+	pub arch: String,
+	/// Whether the target is being retired.
+	///
+	/// No new builds are done for deprecated targets, while their existing
+	/// states are kept.
+	#[serde(default)]
+	pub deprecated: bool,
+}
@@ -0,0 +1,3 @@
+//! Common HTTP server utilities for Fabricia.
+
+pub mod listen;
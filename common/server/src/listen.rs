@@ -0,0 +1,142 @@
+//! Listening sockets.
+
+use std::{
+	fs,
+	io::{self, ErrorKind},
+	os::{
+		fd::{FromRawFd, IntoRawFd, RawFd},
+		unix::fs::{FileTypeExt, PermissionsExt},
+	},
+	path::Path,
+};
+
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, UnixListener};
+use tracing::info;
+
+/// Options for listening on Unix domain sockets.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Deserialize, Serialize)]
+pub struct UnixSocketConfig {
+	/// Permission bits of the socket file, e.g. `0o660`.
+	#[serde(default)]
+	pub socket_mode: Option<u32>,
+	/// Numeric user ID of the owner of the socket file.
+	#[serde(default)]
+	pub socket_owner: Option<u32>,
+	/// Numeric group ID of the owner of the socket file.
+	#[serde(default)]
+	pub socket_group: Option<u32>,
+}
+
+/// A bound listener.
+#[derive(Debug)]
+pub enum Listener {
+	Tcp(TcpListener),
+	Unix(UnixListener),
+}
+
+/// The first file descriptor passed by systemd socket activation.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Binds a listener on an address.
+///
+/// Supported addresses:
+/// - `unix://crayon.socket`
+/// - `tcp://127.0.0.1:8000`
+/// - `systemd://`, for the first socket passed by systemd socket activation
+pub fn bind(addr: &str, config: &UnixSocketConfig) -> io::Result<Listener> {
+	if let Some(path) = addr.strip_prefix("unix://") {
+		bind_unix(Path::new(path), config)
+	} else if let Some(addr) = addr.strip_prefix("tcp://") {
+		let listener = std::net::TcpListener::bind(addr)?;
+		listener.set_nonblocking(true)?;
+		let listener = TcpListener::from_std(listener)?;
+		info!("listening on TCP {}", listener.local_addr()?);
+		Ok(Listener::Tcp(listener))
+	} else if addr == "systemd://" {
+		from_systemd()
+	} else {
+		Err(io::Error::new(
+			ErrorKind::InvalidInput,
+			format!("unsupported listen address: {addr}"),
+		))
+	}
+}
+
+fn bind_unix(path: &Path, config: &UnixSocketConfig) -> io::Result<Listener> {
+	// only remove stale sockets, never regular files
+	match fs::symlink_metadata(path) {
+		Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+		Ok(_) => {
+			return Err(io::Error::new(
+				ErrorKind::AlreadyExists,
+				format!("{path:?} exists and is not a socket"),
+			));
+		}
+		Err(error) if error.kind() == ErrorKind::NotFound => {}
+		Err(error) => return Err(error),
+	}
+	if let Some(parent) = path
+		.parent()
+		.filter(|parent| !parent.as_os_str().is_empty())
+	{
+		fs::create_dir_all(parent)?;
+	}
+
+	let listener = UnixListener::bind(path)?;
+	if let Some(mode) = config.socket_mode {
+		fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+	}
+	if config.socket_owner.is_some() || config.socket_group.is_some() {
+		std::os::unix::fs::chown(path, config.socket_owner, config.socket_group)?;
+	}
+	info!("listening on UDS: {:?}", path);
+	Ok(Listener::Unix(listener))
+}
+
+fn from_systemd() -> io::Result<Listener> {
+	let pid = std::env::var("LISTEN_PID")
+		.ok()
+		.and_then(|pid| pid.parse::<u32>().ok());
+	let fds = std::env::var("LISTEN_FDS")
+		.ok()
+		.and_then(|fds| fds.parse::<u32>().ok())
+		.unwrap_or(0);
+	if pid != Some(std::process::id()) || fds == 0 {
+		return Err(io::Error::new(
+			ErrorKind::NotFound,
+			"no socket has been passed by systemd",
+		));
+	}
+
+	// SAFETY: systemd passes the listening sockets starting from SD_LISTEN_FDS_START,
+	// and the file descriptor is owned by nothing else in this process.
+	let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(SD_LISTEN_FDS_START) };
+	if listener.local_addr().is_ok() {
+		listener.set_nonblocking(true)?;
+		let listener = UnixListener::from_std(listener)?;
+		info!("listening on UDS passed by systemd");
+		return Ok(Listener::Unix(listener));
+	}
+
+	// not a Unix domain socket, take it back as a TCP socket
+	let fd = listener.into_raw_fd();
+	// SAFETY: the file descriptor has just been released above.
+	let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+	listener.set_nonblocking(true)?;
+	let listener = TcpListener::from_std(listener)?;
+	info!(
+		"listening on TCP {} passed by systemd",
+		listener.local_addr()?
+	);
+	Ok(Listener::Tcp(listener))
+}
+
+/// Serves a router on a listener.
+pub async fn serve(listener: Listener, router: Router) -> io::Result<()> {
+	match listener {
+		Listener::Tcp(listener) => axum::serve(listener, router).await,
+		Listener::Unix(listener) => axum::serve(listener, router).await,
+	}
+}
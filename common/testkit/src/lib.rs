@@ -0,0 +1,129 @@
+//! Test harness for Fabricia services.
+//!
+//! PostgreSQL and Redis servers are started with testcontainers on first use,
+//! and shared by all tests in the process.
+//! Set `FABRICIA_TEST_POSTGRES_URL` or `FABRICIA_TEST_REDIS_URL` to use existing servers
+//! instead, for example the ones started by `just dev-pgsql` and `just dev-valkey`.
+
+use std::{
+	sync::{OnceLock, mpsc},
+	thread,
+};
+
+use diesel::{Connection, PgConnection, connection::SimpleConnection};
+use testcontainers_modules::{
+	postgres::Postgres,
+	redis::{REDIS_PORT, Redis},
+	testcontainers::runners::AsyncRunner,
+};
+use uuid::Uuid;
+
+/// Environment variable selecting the SQL backend of service tests.
+///
+/// Either `sqlite` (default) or `postgres`.
+pub const TEST_DATABASE_ENV: &str = "FABRICIA_TEST_DATABASE";
+
+/// SQL backends to run service tests against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestDatabase {
+	Sqlite,
+	Postgres,
+}
+
+impl TestDatabase {
+	/// Returns the SQL backend selected by [`TEST_DATABASE_ENV`].
+	pub fn from_env() -> Self {
+		match std::env::var(TEST_DATABASE_ENV).as_deref() {
+			Ok("postgres" | "postgresql" | "pg") => Self::Postgres,
+			Ok("sqlite") | Err(_) => Self::Sqlite,
+			Ok(other) => panic!("unknown {TEST_DATABASE_ENV}: {other}"),
+		}
+	}
+}
+
+struct TestServers {
+	/// URL to the PostgreSQL server, without the database name.
+	postgres: String,
+	redis: String,
+}
+
+static SERVERS: OnceLock<TestServers> = OnceLock::new();
+
+fn servers() -> &'static TestServers {
+	SERVERS.get_or_init(|| {
+		let postgres = std::env::var("FABRICIA_TEST_POSTGRES_URL").ok();
+		let redis = std::env::var("FABRICIA_TEST_REDIS_URL").ok();
+		if let (Some(postgres), Some(redis)) = (&postgres, &redis) {
+			return TestServers {
+				postgres: postgres.trim_end_matches('/').to_string(),
+				redis: redis.to_string(),
+			};
+		}
+
+		// Containers are owned by a dedicated runtime which lives until the process exits,
+		// as each test has its own runtime.
+		let (tx, rx) = mpsc::channel();
+		thread::Builder::new()
+			.name("testkit-containers".to_string())
+			.spawn(move || {
+				let runtime = tokio::runtime::Builder::new_current_thread()
+					.enable_all()
+					.build()
+					.expect("failed to build runtime for test containers");
+				runtime.block_on(async move {
+					let mut containers = Vec::new();
+					let postgres = match postgres {
+						Some(url) => url,
+						None => {
+							let container = Postgres::default()
+								.start()
+								.await
+								.expect("failed to start PostgreSQL container");
+							let host = container.get_host().await.unwrap();
+							let port = container.get_host_port_ipv4(5432).await.unwrap();
+							containers.push(Box::new(container) as Box<dyn Send>);
+							format!("postgres://postgres:postgres@{host}:{port}")
+						}
+					};
+					let redis = match redis {
+						Some(url) => url,
+						None => {
+							let container = Redis::default()
+								.start()
+								.await
+								.expect("failed to start Redis container");
+							let host = container.get_host().await.unwrap();
+							let port = container.get_host_port_ipv4(REDIS_PORT).await.unwrap();
+							containers.push(Box::new(container) as Box<dyn Send>);
+							format!("redis://{host}:{port}")
+						}
+					};
+					tx.send(TestServers {
+						postgres: postgres.trim_end_matches('/').to_string(),
+						redis,
+					})
+					.ok();
+					std::future::pending::<()>().await;
+					drop(containers);
+				});
+			})
+			.expect("failed to spawn thread for test containers");
+		rx.recv().expect("failed to start test containers")
+	})
+}
+
+/// Returns the URL to the shared Redis server.
+pub fn redis_url() -> String {
+	servers().redis.clone()
+}
+
+/// Creates an empty PostgreSQL database and returns the URL to it.
+pub fn postgres_url() -> String {
+	let server = &servers().postgres;
+	let name = format!("fabricia_test_{}", Uuid::now_v7().simple());
+	let mut conn = PgConnection::establish(&format!("{server}/postgres"))
+		.expect("failed to connect to PostgreSQL server");
+	conn.batch_execute(&format!("CREATE DATABASE \"{name}\""))
+		.expect("failed to create test database");
+	format!("{server}/{name}")
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use fabricia_common_model::branch::TrackingMode;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::operation::ApiOperation;
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiJobQueueState {
+	pub paused: bool,
+}
+
+/// State of the maintenance mode.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiMaintenanceState {
+	pub enabled: bool,
+	/// Message shown to clients, set when enabled.
+	pub message: Option<String>,
+}
+
+/// Hints for external autoscalers.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiScaleHint {
+	/// Whether the job queue has been paused.
+	pub paused: bool,
+	/// Total count of pending jobs.
+	pub pending: u64,
+	/// Total count of running jobs.
+	pub running: u64,
+	/// Queue depth per job kind.
+	pub kinds: HashMap<String, ApiQueueDepth>,
+	/// Estimated time in seconds needed by one runner to finish all pending jobs.
+	pub estimated_backlog_secs: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiQueueDepth {
+	pub pending: u64,
+	pub running: u64,
+}
+
+/// A live Axis or Crayon instance.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiInstanceInfo {
+	pub id: Uuid,
+	/// Either `axis` or `crayon`.
+	pub role: String,
+	pub version: String,
+	#[serde(with = "time::serde::rfc3339")]
+	pub started_at: OffsetDateTime,
+	#[serde(with = "time::serde::rfc3339")]
+	pub heartbeat_at: OffsetDateTime,
+	/// Either `drain` or `hard-stop`, if the instance is being stopped.
+	#[serde(default)]
+	pub stop_mode: Option<String>,
+}
+
+/// Result of importing branches from a manifest.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchImport {
+	/// Operation with synchronization jobs of all tracked branches.
+	pub operation: ApiOperation,
+	/// Results of branches, in the order they have been tracked.
+	pub branches: Vec<ApiImportedBranch>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiImportedBranch {
+	pub name: String,
+	/// The `track` operation of the branch, if tracked.
+	pub operation: Option<Uuid>,
+	/// Error message if the branch has failed to be tracked.
+	pub error: Option<String>,
+}
+
+/// A namespace of branches.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiNamespaceInfo {
+	pub name: String,
+	/// Whether a bearer token is required to access this namespace.
+	pub protected: bool,
+	/// The maximum count of branches in this namespace.
+	pub max_branches: Option<u32>,
+	/// The maximum count of pending jobs of all branches in this namespace.
+	pub max_queued_jobs: Option<u32>,
+}
+
+/// A newly generated bearer token of a namespace.
+///
+/// The token is only returned once.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiNamespaceToken {
+	pub name: String,
+	pub token: String,
+}
+
+/// A named template of branch configurations.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchTemplate {
+	pub name: String,
+	pub priority: Option<u16>,
+	pub tracking_mode: Option<TrackingMode>,
+	pub target_group: Option<String>,
+	pub max_running_jobs: Option<u32>,
+	pub max_queued_jobs: Option<u32>,
+	#[serde(with = "time::serde::rfc3339")]
+	pub updated_at: OffsetDateTime,
+}
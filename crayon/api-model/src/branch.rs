@@ -1,13 +1,273 @@
-use fabricia_common_model::branch::{BranchStatus, TrackingMode};
+use std::collections::{BTreeMap, HashMap};
+
+use fabricia_common_model::{
+	branch::{BranchStatus, RemovalPolicy, TrackingMode},
+	package::{FindingSeverity, PackageTargetStatus},
+};
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{GitOid, package::ApiPackageFinding};
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct ApiBranchInfo {
 	pub name: String,
 	pub base: Option<String>,
+	/// Name of the repository of this branch.
+	///
+	/// This is `None` if no repositories are configured.
+	#[serde(default)]
+	pub repository: Option<String>,
+	/// Name of the target group to build for, or all targets if `None`.
+	#[serde(default)]
+	pub target_group: Option<String>,
 	pub status: BranchStatus,
 	pub priority: u16,
 	pub tracking_mode: TrackingMode,
-	pub commit: Option<String>,
+	/// Handling of packages removed upstream which have been built.
+	#[serde(default)]
+	pub removal_policy: RemovalPolicy,
+	pub commit: Option<GitOid>,
+	/// Commit which syncs are pinned to, regardless of the head of the Git branch.
+	#[serde(default)]
+	pub pinned_commit: Option<GitOid>,
 	pub packages: u32,
+	pub max_running_jobs: Option<u32>,
+	pub max_queued_jobs: Option<u32>,
+	#[serde(with = "time::serde::rfc3339")]
+	pub created_at: OffsetDateTime,
+	/// Time of the last change of the configuration, status or metadata.
+	#[serde(with = "time::serde::rfc3339")]
+	pub updated_at: OffsetDateTime,
+}
+
+/// Configuration of a branch, given when tracking or updating it.
+///
+/// Fields not given are unchanged on updates, and take defaults when tracking.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct ApiBranchConfig {
+	/// Name of the base branch, or empty to remove the base branch.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub base: Option<String>,
+	/// Name of the repository, or empty to use the default repository.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub repository: Option<String>,
+	/// Name of the target group, or empty to build for all targets.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub target_group: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub priority: Option<u16>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tracking_mode: Option<TrackingMode>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub removal_policy: Option<RemovalPolicy>,
+	/// The maximum count of running jobs, or zero to remove the limit.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_running_jobs: Option<u32>,
+	/// The maximum count of pending jobs, or zero to remove the limit.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_queued_jobs: Option<u32>,
+	/// Commit to pin the branch to, until it is unpinned.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub pinned_commit: Option<GitOid>,
+}
+
+/// Graph of base relationships between branches.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchGraph {
+	/// All branches, keyed by branch names.
+	pub nodes: HashMap<String, ApiBranchGraphNode>,
+	/// Edges from base branches to derived branches.
+	pub edges: Vec<ApiBranchGraphEdge>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchGraphNode {
+	pub base: Option<String>,
+	pub status: BranchStatus,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchGraphEdge {
+	pub base: String,
+	pub branch: String,
+}
+
+/// Changes which would be made by synchronizing a branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchSyncPreview {
+	/// Commit which the branch would be synchronized to.
+	pub commit: GitOid,
+	/// Names of packages which would be added.
+	pub added: Vec<String>,
+	/// Names of packages which would be removed.
+	pub removed: Vec<String>,
+	/// Names of packages which would be marked as dirty.
+	pub dirty: Vec<String>,
+}
+
+/// Findings of static checks of packages in a branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchReport {
+	pub branch: String,
+	/// Count of packages in the branch.
+	pub packages: u32,
+	/// Count of findings by severity.
+	pub severities: BTreeMap<FindingSeverity, u32>,
+	/// Findings keyed by package names.
+	///
+	/// Packages without findings are omitted.
+	pub findings: BTreeMap<String, Vec<ApiPackageFinding>>,
+	/// Reasons of held packages keyed by package names.
+	#[serde(default)]
+	pub held: BTreeMap<String, String>,
+}
+
+/// A status transition of a branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchEvent {
+	/// Status entered, with the message.
+	pub status: BranchStatus,
+	/// Actor of the transition, e.g. `job:{id}`, `user:{name}` or `system`.
+	pub actor: String,
+	#[serde(with = "time::serde::rfc3339")]
+	pub created_at: OffsetDateTime,
+}
+
+/// Counts of packages in a branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchStats {
+	pub branch: String,
+	pub packages: u32,
+	pub ready: u32,
+	pub dirty: u32,
+	pub error: u32,
+	/// Count of packages older than their upstream versions.
+	pub outdated: u32,
+}
+
+/// Static checks enqueued for a branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchLint {
+	/// Count of enqueued jobs, one for each package.
+	pub jobs: u32,
+}
+
+/// Prefetches of sources enqueued for a branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchPrefetch {
+	/// Count of enqueued jobs, one for each package.
+	pub jobs: u32,
+}
+
+/// Packages differing between two branches.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchComparison {
+	pub branch: String,
+	/// Name of the branch compared with.
+	pub other: String,
+	/// Differing packages, ordered by name.
+	pub packages: Vec<ApiPackageComparison>,
+	/// Name of the last compared package, given as `after` to read the next page,
+	/// or [`None`] if all packages have been compared.
+	pub next: Option<String>,
+}
+
+/// A package differing between two branches.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageComparison {
+	pub name: String,
+	pub change: ApiPackageChange,
+	/// Full version in the branch, [`None`] if added.
+	pub version: Option<String>,
+	/// Full version in the other branch, [`None`] if removed.
+	pub other_version: Option<String>,
+	/// Targets with differing states, ordered by name.
+	pub targets: Vec<ApiTargetComparison>,
+}
+
+/// Change of a package from the branch to the other branch.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiPackageChange {
+	Added,
+	Removed,
+	Changed,
+}
+
+/// States of a package on a target which differ between two branches.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiTargetComparison {
+	pub target: String,
+	/// State in the branch, [`None`] if never built for the target.
+	pub status: Option<PackageTargetStatus>,
+	/// State in the other branch.
+	pub other_status: Option<PackageTargetStatus>,
+}
+
+/// Packages of a branch failing to build, classified by how widely they fail.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchProblems {
+	pub branch: String,
+	/// Packages failing on all of their targets, ordered by name.
+	pub everywhere: Vec<ApiPackageProblem>,
+	/// Packages failing on some targets only, ordered by name.
+	pub arch_specific: Vec<ApiPackageProblem>,
+	/// Count of packages in [`Self::arch_specific`] failing on each target.
+	pub arch_specific_by_target: BTreeMap<String, u32>,
+}
+
+/// A package failing on at least one target.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageProblem {
+	pub name: String,
+	/// Names of targets which the package fails on.
+	pub failed: Vec<String>,
+	/// States of the package on all of its targets, ordered by names.
+	pub targets: Vec<ApiPackageTargetState>,
+}
+
+/// State of a package on a target.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageTargetState {
+	pub target: String,
+	pub status: PackageTargetStatus,
+}
+
+/// Changelog of a branch since its base branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchChangelog {
+	pub branch: String,
+	/// Name of the base branch, or [`None`] if all entries are included.
+	pub base: Option<String>,
+	/// Packages with entries not in the base branch, ordered by name.
+	pub packages: Vec<ApiPackageChangelog>,
+}
+
+/// Changelog entries of a package not in the base branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageChangelog {
+	pub name: String,
+	/// Full version of the package, like `{epoch}:{version}-{release}`.
+	pub version: String,
+	/// Entries, newest first.
+	pub entries: Vec<ApiChangelogEntry>,
+}
+
+/// A changelog entry of a package.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiChangelogEntry {
+	/// Full version introducing the change.
+	pub version: String,
+	pub author: String,
+	pub message: String,
+}
+
+/// Principals allowed to mutate a branch.
+///
+/// Any principal may mutate branches without entries.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiBranchAcl {
+	/// Identities, e.g. `token:{name}`, `user:{sub}` or `role:{name}`.
+	pub principals: Vec<String>,
 }
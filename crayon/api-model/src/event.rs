@@ -0,0 +1,37 @@
+use fabricia_common_model::{branch::BranchStatus, job::JobStatus};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::operation::ApiOperationStatus;
+
+/// An event pushed to API clients.
+///
+/// New kinds of events may be added without a major version bump,
+/// clients should ignore events of unknown types.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ApiEvent {
+	/// Status of a branch has been changed.
+	BranchStatusChanged {
+		branch: String,
+		status: BranchStatus,
+	},
+	/// A job has been started by a runner.
+	JobStarted { job: Uuid, kind: String },
+	/// A job has been removed from the queue.
+	JobFinished {
+		job: Uuid,
+		kind: String,
+		status: JobStatus,
+		/// Error message of the failed job.
+		error: Option<String>,
+	},
+	/// All jobs of an operation have been completed.
+	OperationFinished {
+		operation: Uuid,
+		status: ApiOperationStatus,
+	},
+	/// The job queue has been paused or resumed.
+	JobQueuePaused { paused: bool },
+}
@@ -0,0 +1,101 @@
+use fabricia_common_model::job::JobStatus;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiJobInfo {
+	pub id: Uuid,
+	pub kind: String,
+	/// Name of the branch which this job works on.
+	pub branch: Option<String>,
+	/// Pending or running for jobs in the queue,
+	/// succeeded or failed for finished jobs.
+	pub status: JobStatus,
+	pub priority: u16,
+	/// Count of failed attempts which have been retried.
+	pub attempts: u16,
+	#[serde(with = "time::serde::rfc3339::option")]
+	pub started_at: Option<OffsetDateTime>,
+	/// Estimated duration in seconds from history of the same job.
+	pub estimated_duration_secs: Option<u64>,
+	/// Estimated time of completion of a running job.
+	#[serde(with = "time::serde::rfc3339::option")]
+	pub eta: Option<OffsetDateTime>,
+	#[serde(default, with = "time::serde::rfc3339::option")]
+	pub finished_at: Option<OffsetDateTime>,
+	/// Error of the failed job.
+	#[serde(default)]
+	pub error: Option<ApiJobError>,
+	/// Name of the instance which has run the finished job.
+	#[serde(default)]
+	pub runner: Option<String>,
+}
+
+/// Filter of finished jobs in history.
+///
+/// Jobs match all set fields.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct ApiHistoryFilter {
+	pub kind: Option<String>,
+	/// Namespace of jobs and [`ApiHistoryFilter::branch`],
+	/// overriding the namespace of the route if set.
+	pub namespace: Option<String>,
+	/// Name of the branch which jobs work on.
+	pub branch: Option<String>,
+	/// Succeeded or failed.
+	pub outcome: Option<JobStatus>,
+	/// Jobs finished at or after the time.
+	#[serde(default, with = "time::serde::rfc3339::option")]
+	pub since: Option<OffsetDateTime>,
+	/// Jobs finished before the time.
+	#[serde(default, with = "time::serde::rfc3339::option")]
+	pub until: Option<OffsetDateTime>,
+	/// Name of the instance which has run jobs.
+	pub runner: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiJobError {
+	/// Whether the failure was considered transient.
+	pub retryable: bool,
+	pub message: String,
+	/// ID with which the backtrace of the error has been logged by the runner.
+	pub backtrace: Option<Uuid>,
+}
+
+/// Count of jobs in the queue by kind, priority band and branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiJobQueueStats {
+	pub kind: String,
+	/// Priority of the jobs, rounded down to a multiple of 100.
+	pub priority_band: u16,
+	/// Namespace of the branch.
+	pub namespace: Option<String>,
+	/// Name of the branch which the jobs work on.
+	pub branch: Option<String>,
+	pub pending: u64,
+	pub running: u64,
+}
+
+/// Latencies of jobs of a kind finished within a window until now.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiJobLatencyStats {
+	pub kind: String,
+	/// Length of the window in seconds.
+	pub window_secs: u64,
+	/// Count of jobs finished within the window.
+	pub jobs: u64,
+	/// Time from enqueueing to start, if recorded for any job.
+	pub wait: Option<ApiPercentiles>,
+	/// Time from start to finish.
+	pub run: ApiPercentiles,
+}
+
+/// Percentiles of durations in milliseconds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct ApiPercentiles {
+	pub p50_ms: u64,
+	pub p95_ms: u64,
+	pub p99_ms: u64,
+}
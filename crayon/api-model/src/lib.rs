@@ -1,6 +1,23 @@
+//! API models of Crayon.
+//!
+//! # Stability
+//!
+//! This crate is consumed by external tools. Within the same minor version:
+//!
+//! - Fields of structs are only added, never removed or renamed.
+//!   Clients should ignore unknown fields.
+//! - Variants of enums marked `#[non_exhaustive]` may be added.
+//!   Clients should tolerate unknown variants.
+//! - Serialized names and formats of existing fields never change.
+
+pub mod admin;
 pub mod branch;
+pub mod event;
+pub mod job;
+pub mod operation;
+pub mod package;
+pub mod security;
+pub mod status;
+pub mod target;
 
-/// Git object ID.
-///
-/// Currently only SHA-1 is supported.
-pub type GitOid = [u8; 20];
+pub use fabricia_common_model::git::GitOid;
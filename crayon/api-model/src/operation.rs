@@ -0,0 +1,36 @@
+use fabricia_common_model::job::JobStatus;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// A long-running operation started by a mutation.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiOperation {
+	pub id: Uuid,
+	pub kind: String,
+	pub status: ApiOperationStatus,
+	/// Name of the affected branch.
+	pub branch: Option<String>,
+	#[serde(with = "time::serde::rfc3339")]
+	pub created_at: OffsetDateTime,
+	/// Jobs spawned by this operation.
+	pub jobs: Vec<ApiOperationJob>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiOperationStatus {
+	Pending,
+	Succeeded,
+	Failed,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiOperationJob {
+	pub id: Uuid,
+	/// Kind of the job, or `None` if the job has been aborted.
+	pub kind: Option<String>,
+	pub state: JobStatus,
+	/// Error message of the failed job.
+	pub error: Option<String>,
+}
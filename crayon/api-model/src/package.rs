@@ -0,0 +1,151 @@
+use fabricia_common_model::{
+	package::{FindingSeverity, PackageStatus, PackageTargetStatus, ReproStatus},
+	target::TargetInfo,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// A tracked package in a branch.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageInfo {
+	pub name: String,
+	/// Name of the branch.
+	pub branch: String,
+	pub section: String,
+	pub status: PackageStatus,
+	/// Upstream version, if the metadata has been evaluated.
+	pub version: Option<String>,
+	pub release: u32,
+	pub epoch: u32,
+	pub dependencies: Vec<String>,
+	/// States of this package on each build target.
+	pub targets: Vec<ApiPackageTargetInfo>,
+	/// Findings of the last static checks of this package.
+	#[serde(default)]
+	pub findings: Vec<ApiPackageFinding>,
+	/// Latest version released upstream, if known.
+	#[serde(default)]
+	pub upstream_version: Option<String>,
+	/// Whether the packaged version is older than [`Self::upstream_version`].
+	#[serde(default)]
+	pub outdated: bool,
+	/// Reason of holding this package from automatic syncs and builds,
+	/// or `None` if not held.
+	#[serde(default)]
+	pub hold_reason: Option<String>,
+	#[serde(with = "time::serde::rfc3339")]
+	pub created_at: OffsetDateTime,
+	/// Time of the last change of the status or metadata.
+	#[serde(with = "time::serde::rfc3339")]
+	pub updated_at: OffsetDateTime,
+}
+
+/// Request to hold a package from automatic syncs and builds,
+/// e.g. while bisecting an issue manually.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageHold {
+	pub reason: String,
+}
+
+/// Request to force a package into a state, e.g. to mark a flaky build as
+/// ready after manual verification.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageOverride {
+	pub state: ApiStateOverride,
+	/// Reason of the override, recorded in the audit log.
+	pub reason: String,
+}
+
+/// State forced onto a package.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ApiStateOverride {
+	/// Status of the package.
+	Package { status: PackageStatus },
+	/// State of the package on a target, by the name of the target.
+	Target {
+		target: String,
+		status: PackageTargetStatus,
+	},
+}
+
+/// State of a package on a build target.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageTargetInfo {
+	pub target: TargetInfo,
+	pub status: PackageTargetStatus,
+	/// ID of the last build job.
+	pub last_build: Option<Uuid>,
+	/// File names of artifacts of the last successful build.
+	pub artifacts: Vec<String>,
+	/// Reproducibility of the last successful build, if verified.
+	#[serde(default)]
+	pub repro: Option<ApiPackageRepro>,
+	#[serde(with = "time::serde::rfc3339")]
+	pub created_at: OffsetDateTime,
+	/// Time of the last change of the build state.
+	#[serde(with = "time::serde::rfc3339")]
+	pub updated_at: OffsetDateTime,
+}
+
+/// Reproducibility of a build of a package on a target.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageRepro {
+	pub status: ReproStatus,
+	/// ID of the job verifying the build.
+	pub verify_build: Option<Uuid>,
+	/// File names of artifacts which differ between the builds.
+	pub differing: Vec<String>,
+}
+
+/// Comparison of artifacts of a build of a package on a target against another build.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiArtifactDiff {
+	/// ID of the compared build job.
+	pub build: Uuid,
+	/// ID of the build job compared against.
+	pub against: Uuid,
+	/// Whether the comparison is still running, with other fields empty.
+	pub pending: bool,
+	/// Why the builds cannot be compared, if failed.
+	pub error: Option<String>,
+	/// Added, removed and changed files, ordered by path.
+	pub files: Vec<ApiArtifactFileDelta>,
+	/// Difference of total sizes of files in bytes.
+	pub size_delta: i64,
+	/// Sonames of shared libraries only in the compared build.
+	pub sonames_added: Vec<String>,
+	/// Sonames of shared libraries only in the build compared against.
+	pub sonames_removed: Vec<String>,
+}
+
+/// A file added, removed or changed between two builds.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiArtifactFileDelta {
+	pub path: String,
+	/// Size in the build compared against, or [`None`] if added.
+	pub old_size: Option<u64>,
+	/// Size in the compared build, or [`None`] if removed.
+	pub new_size: Option<u64>,
+}
+
+/// A finding of static checks of a package.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageFinding {
+	/// Name of the check, e.g. `missing-fields`.
+	pub check: String,
+	pub severity: FindingSeverity,
+	pub message: String,
+}
+
+/// A status transition of a package.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiPackageStatusEvent {
+	/// Status entered, with the message.
+	pub status: PackageStatus,
+	/// Actor of the transition, e.g. `job:{id}`, `user:{name}` or `system`.
+	pub actor: String,
+	#[serde(with = "time::serde::rfc3339")]
+	pub created_at: OffsetDateTime,
+}
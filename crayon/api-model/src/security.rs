@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+/// A tracked package affected by security advisories.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiAffectedPackage {
+	/// Name of the branch.
+	pub branch: String,
+	pub package: String,
+	/// Affected version of the package.
+	pub version: String,
+	pub advisories: Vec<ApiAdvisory>,
+}
+
+/// A security advisory.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiAdvisory {
+	/// ID of the advisory, e.g. `CVE-2024-3094`.
+	pub name: String,
+	/// Name of the feed which this advisory is ingested from.
+	pub source: String,
+	pub summary: String,
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Status of the service, served without authentication.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiStatus {
+	/// Version of Crayon.
+	pub version: String,
+	/// Whether the deployment is in maintenance mode, in which mutations are
+	/// rejected with 503 while reads keep working.
+	pub maintenance: bool,
+	/// Message of the maintenance mode, set when in maintenance mode.
+	pub maintenance_message: Option<String>,
+}
+
+/// Version and build of an instance, for debugging mixed-version clusters.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiVersion {
+	pub version: String,
+	/// Git commit which the instance is built from.
+	pub commit: Option<String>,
+	#[serde(with = "time::serde::rfc3339::option")]
+	pub build_date: Option<OffsetDateTime>,
+	/// Enabled optional features, e.g. `graphql`.
+	pub features: Vec<String>,
+	/// SQL backend of the database, `postgresql` or `sqlite`.
+	pub sql_backend: String,
+	/// Version of the latest migration applied to the database.
+	pub migration_level: Option<String>,
+}
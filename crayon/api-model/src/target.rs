@@ -0,0 +1,37 @@
+use fabricia_common_model::{package::PackageTargetStatus, target::TargetInfo};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Health of a build target across all branches.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiTargetStatus {
+	pub target: TargetInfo,
+	/// Count of packages not built since their last changes.
+	pub dirty: u64,
+	/// Count of packages built successfully.
+	pub ready: u64,
+	/// Count of packages whose last builds have failed.
+	pub build_failed: u64,
+	/// Count of packages which cannot be built due to errors.
+	pub error: u64,
+	/// Packages failed on this target, latest first.
+	pub recent_failures: Vec<ApiTargetFailure>,
+	/// Average duration of recent builds on this target, if any is recorded.
+	pub avg_build_duration_secs: Option<u64>,
+}
+
+/// A package whose last build on a target has failed.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiTargetFailure {
+	/// Name of the namespace of the branch.
+	pub namespace: String,
+	/// Name of the branch.
+	pub branch: String,
+	pub package: String,
+	pub status: PackageTargetStatus,
+	/// ID of the last build job.
+	pub last_build: Option<Uuid>,
+	#[serde(with = "time::serde::rfc3339")]
+	pub updated_at: OffsetDateTime,
+}
@@ -0,0 +1,29 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+	#[error("invalid URL: {0}")]
+	InvalidUrl(String),
+	#[error("HTTP error: {0}")]
+	Http(#[from] reqwest::Error),
+	/// The API responded with an error status.
+	#[error("API error {status}: {message}")]
+	Api { status: StatusCode, message: String },
+	#[error("invalid response: {0}")]
+	Json(#[from] serde_json::Error),
+}
+
+impl ClientError {
+	/// Returns the status of API errors.
+	pub fn status(&self) -> Option<StatusCode> {
+		match self {
+			ClientError::Api { status, .. } => Some(*status),
+			_ => None,
+		}
+	}
+
+	pub fn is_not_found(&self) -> bool {
+		self.status() == Some(StatusCode::NOT_FOUND)
+	}
+}
@@ -0,0 +1,516 @@
+//! Typed client of the Crayon REST API.
+//!
+//! Requests are sent with [`Client`], and retried after transient failures
+//! according to its [`RetryPolicy`]. Paginated lists are also exposed as streams
+//! reading all pages, e.g. [`Client::packages`].
+//!
+//! Models are re-exported from `fabricia-crayon-api-model`, see its stability
+//! guarantees.
+
+use std::collections::BTreeMap;
+
+use fabricia_crayon_api_model::{
+	branch::{ApiBranchChangelog, ApiBranchConfig, ApiBranchInfo, ApiBranchSyncPreview},
+	job::{ApiHistoryFilter, ApiJobInfo},
+	operation::ApiOperation,
+	package::{ApiPackageHold, ApiPackageInfo, ApiPackageOverride},
+	status::{ApiStatus, ApiVersion},
+};
+use futures::Stream;
+use reqwest::{Method, Url};
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::debug;
+use uuid::Uuid;
+
+pub use fabricia_crayon_api_model as model;
+
+mod error;
+mod pagination;
+mod retry;
+
+pub use error::ClientError;
+pub use pagination::paginate;
+pub use retry::RetryPolicy;
+
+pub type Result<T, E = ClientError> = std::result::Result<T, E>;
+
+/// Count of packages requested in each page by [`Client::packages`].
+const PACKAGE_PAGE_SIZE: usize = 500;
+
+/// Builder of [`Client`].
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+	url: String,
+	token: Option<String>,
+	namespace: Option<String>,
+	retry: RetryPolicy,
+	http: Option<reqwest::Client>,
+}
+
+impl ClientBuilder {
+	/// Sets the bearer token sent with requests.
+	pub fn token(mut self, token: impl Into<String>) -> Self {
+		self.token = Some(token.into());
+		self
+	}
+
+	/// Sets the namespace of branches, the default namespace if not set.
+	pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+		self.namespace = Some(namespace.into());
+		self
+	}
+
+	pub fn retry(mut self, retry: RetryPolicy) -> Self {
+		self.retry = retry;
+		self
+	}
+
+	/// Sets the HTTP client, e.g. to configure timeouts or proxies.
+	pub fn http_client(mut self, http: reqwest::Client) -> Self {
+		self.http = Some(http);
+		self
+	}
+
+	pub fn build(self) -> Result<Client> {
+		let mut base =
+			Url::parse(&self.url).map_err(|error| ClientError::InvalidUrl(error.to_string()))?;
+		base.path_segments_mut()
+			.map_err(|_| ClientError::InvalidUrl(self.url.clone()))?
+			.pop_if_empty()
+			.extend(["api", "v0"]);
+		Ok(Client {
+			http: self.http.unwrap_or_default(),
+			base,
+			token: self.token,
+			namespace: self.namespace,
+			retry: self.retry,
+		})
+	}
+}
+
+/// Client of the Crayon REST API.
+///
+/// Cloning a client is cheap, and clones share connections.
+#[derive(Debug, Clone)]
+pub struct Client {
+	http: reqwest::Client,
+	/// URL of the API, e.g. `https://crayon.example.com/api/v0`.
+	base: Url,
+	token: Option<String>,
+	namespace: Option<String>,
+	retry: RetryPolicy,
+}
+
+impl Client {
+	/// Creates a client for the Crayon instance at the given URL,
+	/// e.g. `https://crayon.example.com`.
+	pub fn new(url: &str) -> Result<Self> {
+		Self::builder(url).build()
+	}
+
+	pub fn builder(url: &str) -> ClientBuilder {
+		ClientBuilder {
+			url: url.to_string(),
+			token: None,
+			namespace: None,
+			retry: RetryPolicy::default(),
+			http: None,
+		}
+	}
+
+	/// Returns the URL of a path of the API.
+	///
+	/// Segments are percent-encoded, so names with slashes like `topic/xz`
+	/// stay in one segment.
+	fn url(&self, segments: &[&str]) -> Url {
+		let mut url = self.base.clone();
+		url.path_segments_mut()
+			.expect("base URL has been checked")
+			.extend(segments);
+		url
+	}
+
+	/// Returns the URL of a path under the namespace of this client.
+	fn ns_url(&self, segments: &[&str]) -> Url {
+		let mut url = self.base.clone();
+		{
+			let mut path = url.path_segments_mut().expect("base URL has been checked");
+			if let Some(namespace) = &self.namespace {
+				path.extend(["ns", namespace.as_str()]);
+			}
+			path.extend(segments);
+		}
+		url
+	}
+
+	/// Sends a request, retrying after transient failures, and parses the response.
+	async fn send<T: DeserializeOwned>(
+		&self,
+		method: Method,
+		url: Url,
+		query: &[(&str, String)],
+		body: Option<serde_json::Value>,
+	) -> Result<T> {
+		let mut attempts = 1;
+		let response = loop {
+			let mut request = self.http.request(method.clone(), url.clone()).query(query);
+			if let Some(token) = &self.token {
+				request = request.bearer_auth(token);
+			}
+			if let Some(body) = &body {
+				request = request
+					.header(reqwest::header::CONTENT_TYPE, "application/json")
+					.body(body.to_string());
+			}
+			let result = request.send().await;
+			if attempts >= self.retry.max_attempts || !retry::is_retryable(&method, &result) {
+				break result?;
+			}
+			let delay = result
+				.as_ref()
+				.ok()
+				.and_then(|response| self.retry.retry_after(response.headers()))
+				.unwrap_or_else(|| self.retry.backoff(attempts));
+			debug!(%method, %url, attempts, ?delay, "retrying request");
+			tokio::time::sleep(delay).await;
+			attempts += 1;
+		};
+
+		let status = response.status();
+		if !status.is_success() {
+			let message = response.text().await.unwrap_or_default();
+			return Err(ClientError::Api { status, message });
+		}
+		Ok(serde_json::from_slice(&response.bytes().await?)?)
+	}
+
+	async fn get<T: DeserializeOwned>(&self, url: Url, query: &[(&str, String)]) -> Result<T> {
+		self.send(Method::GET, url, query, None).await
+	}
+
+	async fn send_json<T: DeserializeOwned>(
+		&self,
+		method: Method,
+		url: Url,
+		query: &[(&str, String)],
+		body: &impl Serialize,
+	) -> Result<T> {
+		let body = serde_json::to_value(body)?;
+		self.send(method, url, query, Some(body)).await
+	}
+
+	/// Lists branches of the namespace, by name.
+	pub async fn list_branches(&self) -> Result<BTreeMap<String, ApiBranchInfo>> {
+		self.get(self.ns_url(&["branch"]), &[]).await
+	}
+
+	pub async fn get_branch(&self, name: &str) -> Result<ApiBranchInfo> {
+		self.get(self.ns_url(&["branch", name]), &[]).await
+	}
+
+	/// Starts tracking a branch.
+	///
+	/// Fields not given in `config` are filled from the template, if given.
+	pub async fn track_branch(
+		&self,
+		name: &str,
+		config: &ApiBranchConfig,
+		template: Option<&str>,
+	) -> Result<ApiOperation> {
+		let query = template
+			.map(|template| ("template", template.to_string()))
+			.into_iter()
+			.collect::<Vec<_>>();
+		self.send_json(Method::PUT, self.ns_url(&["branch", name]), &query, config)
+			.await
+	}
+
+	/// Updates fields of the configuration of a branch which are given.
+	pub async fn update_branch(
+		&self,
+		name: &str,
+		config: &ApiBranchConfig,
+	) -> Result<ApiBranchInfo> {
+		self.send_json(Method::PATCH, self.ns_url(&["branch", name]), &[], config)
+			.await
+	}
+
+	/// Unpins a branch, so that syncs follow the head of the Git branch again.
+	pub async fn unpin_branch(&self, name: &str) -> Result<ApiBranchInfo> {
+		self.send(
+			Method::DELETE,
+			self.ns_url(&["branch", name, "pin"]),
+			&[],
+			None,
+		)
+		.await
+	}
+
+	/// Previews changes to packages which synchronizing a branch would make.
+	pub async fn preview_branch_sync(&self, name: &str) -> Result<ApiBranchSyncPreview> {
+		self.send(
+			Method::POST,
+			self.ns_url(&["branch", name, "sync"]),
+			&[("dry_run", "true".to_string())],
+			None,
+		)
+		.await
+	}
+
+	/// Stops tracking a branch.
+	pub async fn untrack_branch(&self, name: &str) -> Result<ApiOperation> {
+		self.send(Method::DELETE, self.ns_url(&["branch", name]), &[], None)
+			.await
+	}
+
+	/// Returns changelog entries of a branch not in its base branch.
+	pub async fn branch_changelog(&self, name: &str) -> Result<ApiBranchChangelog> {
+		self.get(self.ns_url(&["branch", name, "changelog"]), &[])
+			.await
+	}
+
+	pub async fn get_package(&self, branch: &str, name: &str) -> Result<ApiPackageInfo> {
+		self.get(self.ns_url(&["branch", branch, "pkg", name]), &[])
+			.await
+	}
+
+	/// Holds a package from automatic syncs and builds.
+	pub async fn hold_package(
+		&self,
+		branch: &str,
+		name: &str,
+		reason: &str,
+	) -> Result<ApiPackageInfo> {
+		let hold = ApiPackageHold {
+			reason: reason.to_string(),
+		};
+		self.send_json(
+			Method::PUT,
+			self.ns_url(&["branch", branch, "pkg", name, "hold"]),
+			&[],
+			&hold,
+		)
+		.await
+	}
+
+	/// Forces a package or its state on a target into a state.
+	pub async fn override_package(
+		&self,
+		branch: &str,
+		name: &str,
+		request: &ApiPackageOverride,
+	) -> Result<ApiPackageInfo> {
+		self.send_json(
+			Method::POST,
+			self.ns_url(&["branch", branch, "pkg", name, "override"]),
+			&[],
+			request,
+		)
+		.await
+	}
+
+	/// Releases a held package.
+	pub async fn release_package(&self, branch: &str, name: &str) -> Result<ApiPackageInfo> {
+		self.send(
+			Method::DELETE,
+			self.ns_url(&["branch", branch, "pkg", name, "hold"]),
+			&[],
+			None,
+		)
+		.await
+	}
+
+	/// Lists a page of packages of a branch, ordered by name.
+	///
+	/// `after` is the name of the last package of the previous page.
+	/// See [`Client::packages`] for reading all pages.
+	pub async fn list_packages(
+		&self,
+		branch: &str,
+		after: Option<&str>,
+		limit: usize,
+	) -> Result<Vec<ApiPackageInfo>> {
+		let mut query = vec![("limit", limit.to_string())];
+		if let Some(after) = after {
+			query.push(("after", after.to_string()));
+		}
+		self.get(self.ns_url(&["branch", branch, "pkg"]), &query)
+			.await
+	}
+
+	/// Reads all packages of a branch, ordered by name.
+	pub fn packages<'a>(
+		&'a self,
+		branch: &'a str,
+	) -> impl Stream<Item = Result<ApiPackageInfo>> + 'a {
+		paginate(
+			move |after| async move {
+				self.list_packages(branch, after.as_deref(), PACKAGE_PAGE_SIZE)
+					.await
+			},
+			|package: &ApiPackageInfo| package.name.clone(),
+		)
+	}
+
+	/// Lists jobs of the namespace of this client in the queue.
+	pub async fn list_jobs(&self, limit: usize) -> Result<Vec<ApiJobInfo>> {
+		self.get(self.ns_url(&["job"]), &[("limit", limit.to_string())])
+			.await
+	}
+
+	/// Lists finished jobs matching a filter, latest first.
+	///
+	/// Jobs are listed in the namespace of this client if the filter
+	/// has no namespace.
+	pub async fn list_history(
+		&self,
+		filter: &ApiHistoryFilter,
+		limit: usize,
+	) -> Result<Vec<ApiJobInfo>> {
+		let fields = match serde_json::to_value(filter)? {
+			serde_json::Value::Object(fields) => fields,
+			_ => unreachable!("filters are serialized as objects"),
+		};
+		let mut query = vec![("limit", limit.to_string())];
+		for (key, value) in &fields {
+			match value {
+				serde_json::Value::Null => {}
+				serde_json::Value::String(value) => query.push((key.as_str(), value.clone())),
+				value => query.push((key.as_str(), value.to_string())),
+			}
+		}
+		self.get(self.ns_url(&["history"]), &query).await
+	}
+
+	/// Returns a job of the namespace of this client in the queue, or a finished job.
+	pub async fn get_job(&self, id: Uuid) -> Result<ApiJobInfo> {
+		self.get(self.ns_url(&["job", &id.to_string()]), &[]).await
+	}
+
+	pub async fn get_operation(&self, id: Uuid) -> Result<ApiOperation> {
+		self.get(self.url(&["operation", &id.to_string()]), &[])
+			.await
+	}
+
+	/// Returns the status of the service, e.g. whether it is in maintenance.
+	pub async fn status(&self) -> Result<ApiStatus> {
+		self.get(self.url(&["status"]), &[]).await
+	}
+
+	/// Returns the version and build of the server.
+	pub async fn version(&self) -> Result<ApiVersion> {
+		self.get(self.url(&["version"]), &[]).await
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		sync::{
+			Arc,
+			atomic::{AtomicU32, Ordering},
+		},
+		time::Duration,
+	};
+
+	use axum::{
+		Json, Router,
+		extract::{Path, State},
+		http::{HeaderMap, HeaderValue, StatusCode, header::RETRY_AFTER},
+		response::{IntoResponse, Response},
+		routing::get,
+	};
+	use fabricia_crayon_api_model::operation::ApiOperationStatus;
+	use serde_json::json;
+	use uuid::Uuid;
+
+	use crate::{Client, RetryPolicy};
+
+	fn operation(id: Uuid, branch: &str) -> serde_json::Value {
+		json!({
+			"id": id,
+			"kind": "untrack",
+			"status": "pending",
+			"branch": branch,
+			"created_at": "2025-01-01T00:00:00Z",
+			"jobs": [],
+		})
+	}
+
+	async fn get_operation(
+		State(requests): State<Arc<AtomicU32>>,
+		Path(id): Path<Uuid>,
+	) -> Response {
+		if requests.fetch_add(1, Ordering::SeqCst) < 2 {
+			let mut headers = HeaderMap::new();
+			headers.insert(RETRY_AFTER, HeaderValue::from_static("0"));
+			return (StatusCode::SERVICE_UNAVAILABLE, headers).into_response();
+		}
+		Json(operation(id, "main")).into_response()
+	}
+
+	async fn get_branch() -> (StatusCode, &'static str) {
+		(StatusCode::NOT_FOUND, "branch not found")
+	}
+
+	async fn delete_branch(Path(branch): Path<String>) -> Json<serde_json::Value> {
+		Json(operation(Uuid::nil(), &branch))
+	}
+
+	/// Serves a test API, whose operations are unavailable for the first two requests.
+	async fn serve() -> (String, Arc<AtomicU32>) {
+		let requests = Arc::new(AtomicU32::new(0));
+		let router = Router::new()
+			.route("/api/v0/operation/{id}", get(get_operation))
+			.route(
+				"/api/v0/ns/security/branch/{branch}",
+				get(get_branch).delete(delete_branch),
+			)
+			.with_state(requests.clone());
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}/", listener.local_addr().unwrap());
+		tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+		(url, requests)
+	}
+
+	#[tokio::test]
+	async fn test_retry() {
+		let (url, requests) = serve().await;
+		let id = Uuid::now_v7();
+		let client = Client::builder(&url)
+			.retry(RetryPolicy {
+				max_attempts: 3,
+				initial_backoff: Duration::from_secs(10),
+				max_backoff: Duration::from_secs(10),
+			})
+			.build()
+			.unwrap();
+		let operation = client.get_operation(id).await.unwrap();
+		assert_eq!(operation.id, id);
+		assert_eq!(operation.status, ApiOperationStatus::Pending);
+		assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+		requests.store(0, Ordering::SeqCst);
+		let client = Client::builder(&url)
+			.retry(RetryPolicy::none())
+			.build()
+			.unwrap();
+		let error = client.get_operation(id).await.unwrap_err();
+		assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+		assert_eq!(requests.load(Ordering::SeqCst), 1);
+	}
+
+	#[tokio::test]
+	async fn test_namespace() {
+		let (url, _) = serve().await;
+		let client = Client::builder(&url)
+			.namespace("security")
+			.token("secret")
+			.build()
+			.unwrap();
+		let operation = client.untrack_branch("topic/xz").await.unwrap();
+		assert_eq!(operation.branch.as_deref(), Some("topic/xz"));
+		let error = client.get_branch("topic/xz").await.unwrap_err();
+		assert!(error.is_not_found());
+	}
+}
@@ -0,0 +1,60 @@
+use futures::{Stream, TryStreamExt, stream};
+
+use crate::{ClientError, Result};
+
+/// Reads a list page by page, with the key of the last item as the cursor
+/// of the next page.
+///
+/// `fetch` is called with `None` for the first page. Reading stops at the first
+/// empty page or error.
+pub fn paginate<T, F, Fut, K>(fetch: F, key: K) -> impl Stream<Item = Result<T>>
+where
+	F: FnMut(Option<String>) -> Fut,
+	Fut: Future<Output = Result<Vec<T>>>,
+	K: Fn(&T) -> String,
+{
+	stream::try_unfold(
+		(Some(None), fetch, key),
+		|(cursor, mut fetch, key)| async move {
+			let Some(after) = cursor else {
+				return Ok::<_, ClientError>(None);
+			};
+			let page = fetch(after).await?;
+			let next = page.last().map(|item| Some(key(item)));
+			let items = stream::iter(page.into_iter().map(Ok));
+			Ok(Some((items, (next, fetch, key))))
+		},
+	)
+	.try_flatten()
+}
+
+#[cfg(test)]
+mod test {
+	use std::{cell::RefCell, future::ready};
+
+	use futures::TryStreamExt;
+
+	use crate::paginate;
+
+	#[tokio::test]
+	async fn test_paginate() {
+		let names = ["bash", "glibc", "xz", "zlib"];
+		let cursors = RefCell::new(Vec::new());
+		let fetch = |after: Option<String>| {
+			cursors.borrow_mut().push(after.clone());
+			let page = names
+				.iter()
+				.filter(|name| after.as_deref().is_none_or(|after| **name > after))
+				.take(3)
+				.map(|name| name.to_string())
+				.collect::<Vec<_>>();
+			ready(Ok(page))
+		};
+		let items: Vec<String> = paginate(fetch, String::clone).try_collect().await.unwrap();
+		assert_eq!(items, names);
+		assert_eq!(
+			*cursors.borrow(),
+			[None, Some("xz".to_string()), Some("zlib".to_string())]
+		);
+	}
+}
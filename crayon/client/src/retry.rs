@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode, header::HeaderMap};
+
+/// Policy of retrying failed requests, with exponential backoff.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RetryPolicy {
+	/// The maximum count of attempts of each request, including the first one.
+	pub max_attempts: u32,
+	/// Delay before the first retry, doubled on each further retry.
+	pub initial_backoff: Duration,
+	/// Upper bound of delays, also applied to `Retry-After` of responses.
+	pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: 4,
+			initial_backoff: Duration::from_millis(500),
+			max_backoff: Duration::from_secs(30),
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// A policy sending each request only once.
+	pub fn none() -> Self {
+		Self {
+			max_attempts: 1,
+			..Default::default()
+		}
+	}
+
+	/// Returns the delay before retrying after the given count of failed attempts.
+	pub fn backoff(&self, attempts: u32) -> Duration {
+		let exponent = attempts.saturating_sub(1).min(31);
+		self.initial_backoff
+			.saturating_mul(1 << exponent)
+			.min(self.max_backoff)
+	}
+
+	/// Returns the delay requested by `Retry-After` in seconds, bounded by
+	/// [`RetryPolicy::max_backoff`].
+	pub(crate) fn retry_after(&self, headers: &HeaderMap) -> Option<Duration> {
+		let seconds = headers
+			.get(reqwest::header::RETRY_AFTER)?
+			.to_str()
+			.ok()?
+			.trim()
+			.parse()
+			.ok()?;
+		Some(Duration::from_secs(seconds).min(self.max_backoff))
+	}
+}
+
+/// Returns whether a request is retried after the given result.
+///
+/// Requests which may have been processed, e.g. on gateway timeouts, are retried
+/// only if idempotent. Rate limits and unavailability are reported by Crayon
+/// before processing, and connection failures never reach it, so those are
+/// retried for all requests.
+pub(crate) fn is_retryable(method: &Method, result: &reqwest::Result<reqwest::Response>) -> bool {
+	let idempotent = matches!(
+		*method,
+		Method::GET | Method::HEAD | Method::PUT | Method::DELETE
+	);
+	match result {
+		Ok(response) => match response.status() {
+			StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => true,
+			StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT => idempotent,
+			_ => false,
+		},
+		Err(error) => error.is_connect() || (idempotent && error.is_timeout()),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+	use crate::RetryPolicy;
+
+	#[test]
+	fn test_backoff() {
+		let policy = RetryPolicy {
+			max_attempts: 10,
+			initial_backoff: Duration::from_millis(100),
+			max_backoff: Duration::from_secs(1),
+		};
+		let delays = (1..=6).map(|n| policy.backoff(n)).collect::<Vec<_>>();
+		assert_eq!(
+			delays,
+			[100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+		);
+		assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+
+		let mut headers = HeaderMap::new();
+		assert_eq!(policy.retry_after(&headers), None);
+		headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+		assert_eq!(policy.retry_after(&headers), Some(Duration::from_secs(1)));
+		headers.insert(
+			RETRY_AFTER,
+			HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+		);
+		assert_eq!(policy.retry_after(&headers), None);
+	}
+}
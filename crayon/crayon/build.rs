@@ -0,0 +1,31 @@
+//! Embeds the Git commit and the build date, see `src/build_info.rs`.
+
+use std::{
+	env,
+	process::Command,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+	let commit = Command::new("git")
+		.args(["rev-parse", "HEAD"])
+		.output()
+		.ok()
+		.filter(|output| output.status.success())
+		.and_then(|output| String::from_utf8(output.stdout).ok());
+	if let Some(commit) = commit {
+		println!("cargo:rustc-env=FABRICIA_GIT_COMMIT={}", commit.trim());
+	}
+
+	// reproducible builds fix the date by `SOURCE_DATE_EPOCH`
+	let timestamp = match env::var("SOURCE_DATE_EPOCH") {
+		Ok(epoch) => epoch,
+		Err(_) => SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|time| time.as_secs().to_string())
+			.unwrap_or_default(),
+	};
+	println!("cargo:rustc-env=FABRICIA_BUILD_TIMESTAMP={timestamp}");
+	println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+	println!("cargo:rerun-if-changed=../../.git/HEAD");
+}
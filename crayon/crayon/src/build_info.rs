@@ -0,0 +1,28 @@
+//! Information about this build of Crayon.
+
+use time::OffsetDateTime;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Git commit which Crayon is built from, if built in a Git repository.
+pub const COMMIT: Option<&str> = option_env!("FABRICIA_GIT_COMMIT");
+
+/// Returns the time when Crayon is built.
+pub fn build_date() -> Option<OffsetDateTime> {
+	let timestamp = option_env!("FABRICIA_BUILD_TIMESTAMP")?.parse().ok()?;
+	OffsetDateTime::from_unix_timestamp(timestamp).ok()
+}
+
+/// Returns names of enabled optional features.
+pub fn features() -> Vec<&'static str> {
+	let features = [
+		("graphql", cfg!(feature = "graphql")),
+		("grpc", cfg!(feature = "grpc")),
+		("oidc", cfg!(feature = "oidc")),
+	];
+	features
+		.into_iter()
+		.filter(|(_, enabled)| *enabled)
+		.map(|(name, _)| name)
+		.collect()
+}
@@ -6,7 +6,7 @@ use fabricia_backend::{
 	Result,
 	bus::{
 		BACKEND_BUS_C2A_CHANNEL, BACKEND_BUS_CHANNEL, BackendBusFactory, BackendBusMessage,
-		BackendBusService, BoxedBusService, C2ABusMessage,
+		BackendBusService, BoxedBusService, C2ABusMessage, LocalBusMessage,
 	},
 	redis::{RedisError, RedisService},
 };
@@ -15,7 +15,8 @@ use futures::{
 	future::{BoxFuture, ready},
 };
 use redis::AsyncCommands;
-use tracing::{debug, error, info};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error, info, warn};
 
 use crate::CrayonServices;
 
@@ -32,7 +33,7 @@ impl BackendBusService for CrayonBusService {
 				.redis
 				.get()
 				.await?
-				.publish(BACKEND_BUS_CHANNEL, message.as_str())
+				.publish(self.redis.key(BACKEND_BUS_CHANNEL), message.as_str())
 				.await
 				.map_err(RedisError::RedisError)?;
 			Ok(())
@@ -47,7 +48,7 @@ impl BackendBusService for CrayonBusService {
 				.redis
 				.get()
 				.await?
-				.publish(BACKEND_BUS_C2A_CHANNEL, message.as_str())
+				.publish(self.redis.key(BACKEND_BUS_C2A_CHANNEL), message.as_str())
 				.await
 				.map_err(RedisError::RedisError)?;
 			Ok(())
@@ -68,9 +69,35 @@ impl BackendBusFactory for CrayonBusFactory {
 }
 
 pub async fn handle_bus_message(services: CrayonServices) {
-	let client = services.backend.redis.make_client().await.unwrap();
+	let Ok(backend) = services.backend() else {
+		return;
+	};
+	if let Some(mut receiver) = backend.bus.subscribe_local() {
+		info!("subscribed to in-process backend bus");
+		loop {
+			match receiver.recv().await {
+				Ok(LocalBusMessage::Backend(message)) => {
+					process_backend_bus_message(message, &services).await;
+				}
+				// C2A messages are only for Axis
+				Ok(LocalBusMessage::C2A(_)) => {}
+				Err(RecvError::Lagged(count)) => {
+					warn!(count, "skipped lagged in-process bus messages");
+				}
+				Err(RecvError::Closed) => break,
+			}
+		}
+		return;
+	}
+
+	let Some(redis) = &backend.redis else {
+		error!("neither in-process bus nor Redis is available");
+		return;
+	};
+	let client = redis.make_client().await.unwrap();
 	let mut pubsub = client.get_async_pubsub().await.unwrap();
-	pubsub.subscribe(BACKEND_BUS_CHANNEL).await.unwrap();
+	let backend_channel = redis.key(BACKEND_BUS_CHANNEL);
+	pubsub.subscribe(&backend_channel).await.unwrap();
 	info!("subscribed to backend bus channel");
 	while let Some(msg) = pubsub.on_message().next().await {
 		let channel = msg.get_channel_name();
@@ -83,7 +110,7 @@ pub async fn handle_bus_message(services: CrayonServices) {
 			}
 		};
 		match channel {
-			BACKEND_BUS_CHANNEL => {
+			channel if channel == backend_channel => {
 				let result = handle_backend_bus_message(payload, &services).await;
 				if let Err(error) = result {
 					error!(channel, %error, "failed to handle backend bus message");
@@ -98,9 +125,13 @@ pub async fn handle_bus_message(services: CrayonServices) {
 
 async fn handle_backend_bus_message(
 	message: String,
-	_services: &CrayonServices,
+	services: &CrayonServices,
 ) -> anyhow::Result<()> {
 	let message = serde_json::from_str::<BackendBusMessage>(&message)?;
-	debug!(?message, "received backend bus message");
+	process_backend_bus_message(message, services).await;
 	Ok(())
 }
+
+async fn process_backend_bus_message(message: BackendBusMessage, _services: &CrayonServices) {
+	debug!(?message, "received backend bus message");
+}
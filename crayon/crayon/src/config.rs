@@ -1,14 +1,88 @@
+use std::path::PathBuf;
+
 use fabricia_backend::{
-	config::BackendConfig, db::service::DatabaseConfig, redis::RedisConfig, target::TargetConfig,
+	artifact_diff::ArtifactStoreConfig,
+	bootstrap::BootstrapConfig,
+	branch_scan::BranchScanConfig,
+	bus::BusConfig,
+	config::BackendConfig,
+	db::service::DatabaseConfig,
+	evaluator::EvaluatorConfig,
+	job_queue::JobQueueConfig,
+	lint::LintConfig,
+	mirror::MirrorConfig,
+	redis::RedisConfig,
+	repository::RepositoryConfig,
+	repro::ReproConfig,
+	secrets::{self, SecretError},
+	security::SecurityConfig,
+	target::{TargetConfig, TargetGroupConfig},
+	upstream::UpstreamConfig,
 };
+use fabricia_common_server::listen::UnixSocketConfig;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash, Deserialize, Serialize)]
 pub struct CrayonConfig {
 	pub web: WebConfig,
+	/// The gRPC API is not served if not set.
+	#[serde(default)]
+	pub grpc: Option<GrpcConfig>,
+	/// OIDC login of the web UI, disabled if not set.
+	#[serde(default)]
+	pub oidc: Option<OidcConfig>,
 	pub database: DatabaseConfig,
-	pub redis: RedisConfig,
+	#[serde(default)]
+	pub redis: Option<RedisConfig>,
 	pub target: Vec<TargetConfig>,
+	#[serde(default)]
+	pub target_group: Vec<TargetGroupConfig>,
+	#[serde(default)]
+	pub repository: Vec<RepositoryConfig>,
+	#[serde(default)]
+	pub job_queue: JobQueueConfig,
+	#[serde(default)]
+	pub lint: LintConfig,
+	#[serde(default)]
+	pub security: SecurityConfig,
+	#[serde(default)]
+	pub upstream: Option<UpstreamConfig>,
+	#[serde(default)]
+	pub mirror: Option<MirrorConfig>,
+	/// Evaluation command, required to preview synchronizations of branches.
+	#[serde(default)]
+	pub evaluator: Option<EvaluatorConfig>,
+	#[serde(default)]
+	pub repro: Option<ReproConfig>,
+	#[serde(default)]
+	pub artifact_store: Option<ArtifactStoreConfig>,
+	#[serde(default)]
+	pub branch_scan: Option<BranchScanConfig>,
+	#[serde(default)]
+	pub bus: BusConfig,
+	/// Seed data created by `crayon --bootstrap`.
+	#[serde(default)]
+	pub bootstrap: BootstrapConfig,
+}
+
+impl CrayonConfig {
+	/// Resolves secrets referenced by options, see [`fabricia_backend::secrets`].
+	pub async fn resolve_secrets(&mut self) -> Result<(), SecretError> {
+		self.database.resolve_secrets().await?;
+		if let Some(redis) = &mut self.redis {
+			redis.resolve_secrets().await?;
+		}
+		if let Some(mirror) = &mut self.mirror {
+			mirror.resolve_secrets().await?;
+		}
+		if let Some(oidc) = &mut self.oidc {
+			if let Some(secret) = &mut oidc.client_secret {
+				secrets::resolve_in_place(secret).await?;
+			}
+			secrets::resolve_in_place(&mut oidc.session_secret).await?;
+		}
+		Ok(())
+	}
 }
 
 impl TryFrom<CrayonConfig> for BackendConfig {
@@ -19,6 +93,20 @@ impl TryFrom<CrayonConfig> for BackendConfig {
 			database: config.database,
 			redis: config.redis,
 			target: config.target,
+			target_group: config.target_group,
+			repository: config.repository,
+			job_queue: config.job_queue,
+			lint: config.lint,
+			security: config.security,
+			upstream: config.upstream,
+			mirror: config.mirror,
+			evaluator: config.evaluator,
+			// builds are run by Axis
+			builder: None,
+			repro: config.repro,
+			artifact_store: config.artifact_store,
+			branch_scan: config.branch_scan,
+			bus: config.bus,
 		})
 	}
 }
@@ -30,5 +118,128 @@ pub struct WebConfig {
 	/// Examples:
 	/// - `unix://crayon.socket`
 	/// - `tcp://127.0.0.1:8000`
+	/// - `systemd://`, for sockets passed by systemd socket activation
 	pub listen: String,
+	/// Options for listening on Unix domain sockets.
+	#[serde(flatten)]
+	pub socket: UnixSocketConfig,
+	/// Directory of static files of the web UI.
+	///
+	/// When set, files in this directory are served at the root path,
+	/// and unknown paths are served with `index.html`.
+	#[serde(default)]
+	pub static_dir: Option<PathBuf>,
+	/// `max-age` in seconds of `Cache-Control` for static files other than `index.html`.
+	#[serde(default = "default_static_max_age")]
+	pub static_max_age: u64,
+	/// Timeout in seconds of handling requests, or zero to disable the timeout.
+	///
+	/// Requests not handled in time are responded with 408. Response bodies
+	/// streamed after the response starts are not limited.
+	#[serde(default = "default_request_timeout")]
+	pub request_timeout: u64,
+	/// The maximum size in bytes of request bodies, responded with 413 if exceeded.
+	#[serde(default = "default_max_body_size")]
+	pub max_body_size: usize,
+	/// The maximum size in bytes of bodies of uploads, like manifests of
+	/// `/api/v0/admin/import`, used instead of [`WebConfig::max_body_size`].
+	#[serde(default = "default_max_upload_size")]
+	pub max_upload_size: usize,
+	/// Encodings to compress responses with, chosen by `Accept-Encoding` of requests.
+	///
+	/// Responses are never compressed if empty.
+	#[serde(default = "default_compression")]
+	pub compression: Vec<Compression>,
+	/// Message of 503 responses to mutations in maintenance mode,
+	/// used when the mode is enabled without a message.
+	#[serde(default = "default_maintenance_message")]
+	pub maintenance_message: String,
+}
+
+/// Encoding of compressed responses.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+	Gzip,
+	Br,
+}
+
+fn default_static_max_age() -> u64 {
+	3600
+}
+
+fn default_request_timeout() -> u64 {
+	60
+}
+
+fn default_max_body_size() -> usize {
+	1 << 20
+}
+
+fn default_max_upload_size() -> usize {
+	64 << 20
+}
+
+fn default_compression() -> Vec<Compression> {
+	vec![Compression::Gzip, Compression::Br]
+}
+
+fn default_maintenance_message() -> String {
+	"service is under maintenance".to_string()
+}
+
+/// Configuration of the gRPC API, served with the `grpc` feature.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Deserialize, Serialize)]
+pub struct GrpcConfig {
+	/// Address for the gRPC server to listen on, in the format of [`WebConfig::listen`].
+	pub listen: String,
+	/// Options for listening on Unix domain sockets.
+	#[serde(flatten)]
+	pub socket: UnixSocketConfig,
+}
+
+/// Configuration of OIDC login, served with the `oidc` feature.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Deserialize, Serialize)]
+pub struct OidcConfig {
+	/// URL of the issuer, where `.well-known/openid-configuration` is served.
+	pub issuer_url: String,
+	pub client_id: String,
+	/// Secret of confidential clients, not set for public clients.
+	///
+	/// The secret may be a reference, see [`fabricia_backend::secrets`].
+	#[serde(default)]
+	pub client_secret: Option<String>,
+	/// URL of `/auth/callback` of this instance, as registered in the provider.
+	pub redirect_url: String,
+	#[serde(default = "default_oidc_scopes")]
+	pub scopes: Vec<String>,
+	/// Hex-encoded key to sign session cookies, of at least 32 bytes.
+	///
+	/// The key may be a reference, see [`fabricia_backend::secrets`].
+	pub session_secret: String,
+	/// Lifetime of sessions in seconds.
+	#[serde(default = "default_session_ttl")]
+	pub session_ttl: u64,
+	/// Rules mapping claims of users to roles.
+	#[serde(default)]
+	pub role: Vec<OidcRoleRule>,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+	vec!["openid".to_string(), "profile".to_string()]
+}
+
+fn default_session_ttl() -> u64 {
+	3600
+}
+
+/// Grants a role to users with a claim of a value.
+///
+/// If the claim is an array, e.g. `groups`, any element may match the value.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Deserialize, Serialize)]
+pub struct OidcRoleRule {
+	/// Role to grant, e.g. `admin`.
+	pub role: String,
+	pub claim: String,
+	pub value: String,
 }
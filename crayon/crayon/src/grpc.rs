@@ -0,0 +1,466 @@
+//! gRPC API for machine clients, enabled by the `grpc` feature.
+//!
+//! Services are defined in `fabricia-crayon-proto` and served on the listener
+//! in [`GrpcConfig`](crate::config::GrpcConfig). Messages are converted from
+//! the models of the REST API, so that both APIs agree.
+
+use axum::{Router, http::StatusCode};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use fabricia_backend::{
+	branch::BranchRef,
+	db::schema::branch::dsl,
+	model::BranchRow,
+	namespace::{DEFAULT_NAMESPACE, NamespaceRef},
+};
+use fabricia_common_model::{
+	branch::{BranchStatus, TrackingMode},
+	job::JobStatus,
+	package::{PackageStatus, PackageTargetStatus},
+};
+use fabricia_crayon_api_model::{
+	branch::ApiBranchInfo,
+	job::ApiJobInfo,
+	package::{ApiPackageInfo, ApiPackageTargetInfo},
+};
+use fabricia_crayon_proto::{
+	self as proto,
+	branch_service_server::{BranchService, BranchServiceServer},
+	job_service_server::{JobService, JobServiceServer},
+	package_service_server::{PackageService, PackageServiceServer},
+};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tonic::{Code, Request, Response, Status, service::Routes};
+use uuid::Uuid;
+
+use crate::{
+	CrayonServices,
+	routes::{
+		api::{
+			auth::AuthRequired,
+			branch::branch_into_api,
+			error::{ApiError, ApiResult, OptionExt, backend_error_status},
+			export::packages_into_api,
+			job::{branch_names, finished_into_api, in_namespace, queued_into_api},
+		},
+		maintenance::maintenance_message,
+	},
+};
+
+/// Count of packages in a page if not requested.
+const DEFAULT_PAGE_SIZE: usize = 100;
+/// Maximum count of packages in a page.
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Makes the router of all gRPC services.
+pub fn make_router(services: CrayonServices) -> Router {
+	let api = GrpcApi(services);
+	Routes::new(BranchServiceServer::new(api.clone()))
+		.add_service(PackageServiceServer::new(api.clone()))
+		.add_service(JobServiceServer::new(api))
+		.into_axum_router()
+}
+
+impl From<ApiError> for Status {
+	fn from(error: ApiError) -> Self {
+		let status = match &error {
+			ApiError::BackendError(error) => backend_error_status(error),
+			ApiError::CustomRef(status, _) | ApiError::CustomString(status, _) => *status,
+			ApiError::AuthRequired => StatusCode::UNAUTHORIZED,
+		};
+		let code = match status {
+			StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+			StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+			StatusCode::FORBIDDEN => Code::PermissionDenied,
+			StatusCode::NOT_FOUND => Code::NotFound,
+			StatusCode::CONFLICT => Code::AlreadyExists,
+			StatusCode::PRECONDITION_FAILED | StatusCode::PRECONDITION_REQUIRED => {
+				Code::FailedPrecondition
+			}
+			StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+			StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+			StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+			_ => Code::Internal,
+		};
+		let message = match error {
+			ApiError::BackendError(error) => error.to_string(),
+			ApiError::CustomRef(_, message) => message.to_string(),
+			ApiError::CustomString(_, message) => message,
+			ApiError::AuthRequired => "authentication is required".to_string(),
+		};
+		Status::new(code, message)
+	}
+}
+
+#[derive(Debug, Clone)]
+struct GrpcApi(CrayonServices);
+
+impl GrpcApi {
+	/// Resolves a namespace by name, or the default namespace if empty.
+	///
+	/// If the namespace has a token, the request must carry it as the bearer token.
+	async fn namespace<T>(&self, request: &Request<T>, name: &str) -> ApiResult<NamespaceRef> {
+		let name = match name {
+			"" => DEFAULT_NAMESPACE,
+			name => name,
+		};
+		let namespace = self
+			.0
+			.namespace
+			.get_by_name(name)
+			.await?
+			.or_api_error(StatusCode::NOT_FOUND, "namespace not found")?;
+		let token = request
+			.metadata()
+			.get("authorization")
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| value.strip_prefix("Bearer "));
+		if namespace.authorize(token) {
+			Ok(namespace.id)
+		} else {
+			Err(ApiError::AuthRequired)
+		}
+	}
+
+	/// Checks a mutating request like REST routes with [`AuthRequired`],
+	/// and rejects it in maintenance mode.
+	async fn mutation<T>(&self, request: &Request<T>) -> ApiResult<()> {
+		AuthRequired::authorize(&self.0, &request.metadata().clone().into_headers()).await?;
+		match maintenance_message(&self.0).await {
+			Some(message) => Err(ApiError::CustomString(
+				StatusCode::SERVICE_UNAVAILABLE,
+				message,
+			)),
+			None => Ok(()),
+		}
+	}
+
+	async fn find_branch(&self, namespace: NamespaceRef, name: &str) -> ApiResult<BranchRef> {
+		self.0
+			.branch
+			.find_id(namespace, name)
+			.await?
+			.or_api_error(StatusCode::NOT_FOUND, "branch not found")
+	}
+}
+
+#[tonic::async_trait]
+impl BranchService for GrpcApi {
+	async fn list_branches(
+		&self,
+		request: Request<proto::ListBranchesRequest>,
+	) -> Result<Response<proto::ListBranchesResponse>, Status> {
+		let namespace = self
+			.namespace(&request, &request.get_ref().namespace)
+			.await?;
+		let mut db = self
+			.0
+			.backend()?
+			.database
+			.get()
+			.await
+			.map_err(ApiError::from)?;
+		let rows: Vec<BranchRow> = db
+			.load_select(
+				dsl::branch
+					.filter(dsl::namespace.eq(namespace))
+					.order(dsl::name.asc()),
+			)
+			.await
+			.map_err(ApiError::from)?;
+		let mut branches = Vec::with_capacity(rows.len());
+		for row in rows {
+			branches.push(branch_into_proto(
+				branch_into_api(&self.0, row, &mut db).await?,
+			));
+		}
+		Ok(Response::new(proto::ListBranchesResponse { branches }))
+	}
+
+	async fn get_branch(
+		&self,
+		request: Request<proto::GetBranchRequest>,
+	) -> Result<Response<proto::Branch>, Status> {
+		let namespace = self
+			.namespace(&request, &request.get_ref().namespace)
+			.await?;
+		let mut db = self
+			.0
+			.backend()?
+			.database
+			.get()
+			.await
+			.map_err(ApiError::from)?;
+		let row: BranchRow = db
+			.load_one_select(
+				dsl::branch
+					.filter(dsl::namespace.eq(namespace))
+					.filter(dsl::name.eq(&request.get_ref().name)),
+			)
+			.await
+			.optional()
+			.map_err(ApiError::from)?
+			.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+		let branch = branch_into_api(&self.0, row, &mut db).await?;
+		Ok(Response::new(branch_into_proto(branch)))
+	}
+
+	async fn lint_branch(
+		&self,
+		request: Request<proto::LintBranchRequest>,
+	) -> Result<Response<proto::LintBranchResponse>, Status> {
+		self.mutation(&request).await?;
+		let namespace = self
+			.namespace(&request, &request.get_ref().namespace)
+			.await?;
+		let branch = self.find_branch(namespace, &request.get_ref().name).await?;
+		let jobs = self
+			.0
+			.backend()?
+			.lint
+			.enqueue_branch(branch)
+			.await
+			.map_err(ApiError::from)?;
+		Ok(Response::new(proto::LintBranchResponse {
+			jobs: jobs as u32,
+		}))
+	}
+}
+
+#[tonic::async_trait]
+impl PackageService for GrpcApi {
+	async fn list_packages(
+		&self,
+		request: Request<proto::ListPackagesRequest>,
+	) -> Result<Response<proto::ListPackagesResponse>, Status> {
+		let namespace = self
+			.namespace(&request, &request.get_ref().namespace)
+			.await?;
+		let request = request.into_inner();
+		let branch = self.find_branch(namespace, &request.branch).await?;
+		let size = match request.limit {
+			0 => DEFAULT_PAGE_SIZE,
+			limit => (limit as usize).min(MAX_PAGE_SIZE),
+		};
+		let mut rows = self
+			.0
+			.package
+			.list_page(branch, request.after, size + 1)
+			.await
+			.map_err(ApiError::from)?;
+		let has_next = rows.len() > size;
+		rows.truncate(size);
+
+		let packages = packages_into_api(&self.0, rows, &request.branch).await?;
+		let next_after = match has_next {
+			true => packages.last().map(|package| package.name.clone()),
+			false => None,
+		};
+		Ok(Response::new(proto::ListPackagesResponse {
+			packages: packages.into_iter().map(package_into_proto).collect(),
+			next_after,
+		}))
+	}
+}
+
+#[tonic::async_trait]
+impl JobService for GrpcApi {
+	async fn list_jobs(
+		&self,
+		request: Request<proto::ListJobsRequest>,
+	) -> Result<Response<proto::ListJobsResponse>, Status> {
+		let limit = match request.get_ref().limit {
+			0 => 100,
+			limit => (limit as usize).min(1000),
+		};
+		let namespace = self
+			.namespace(&request, &request.get_ref().namespace)
+			.await?;
+		let jobs = self
+			.0
+			.job_queue
+			.list(namespace, limit)
+			.await
+			.map_err(ApiError::from)?;
+		let branches = branch_names(&self.0, namespace).await?;
+		Ok(Response::new(proto::ListJobsResponse {
+			jobs: jobs
+				.into_iter()
+				.map(|job| job_into_proto(queued_into_api(job, &branches)))
+				.collect(),
+		}))
+	}
+
+	async fn get_job(
+		&self,
+		request: Request<proto::GetJobRequest>,
+	) -> Result<Response<proto::Job>, Status> {
+		let id = Uuid::parse_str(&request.get_ref().id)
+			.map_err(|_| Status::invalid_argument("invalid job ID"))?;
+		let namespace = self
+			.namespace(&request, &request.get_ref().namespace)
+			.await?;
+		let branches = branch_names(&self.0, namespace).await?;
+		let job = match self.0.job_queue.get(id).await.map_err(ApiError::from)? {
+			Some(job) => Some((
+				job.command.subject_branch(),
+				queued_into_api(job, &branches),
+			)),
+			None => self
+				.0
+				.job_queue
+				.get_finished(id)
+				.await
+				.map_err(ApiError::from)?
+				.map(|job| (job.subject_branch, finished_into_api(job, &branches))),
+		};
+		let (_, job) = job
+			.filter(|(branch, _)| in_namespace(namespace, *branch, &branches))
+			.or_api_error(StatusCode::NOT_FOUND, "job not found")?;
+		Ok(Response::new(job_into_proto(job)))
+	}
+}
+
+fn branch_status_into_proto(status: BranchStatus) -> proto::Status {
+	let (kind, reason) = match status {
+		BranchStatus::Dirty => (proto::StatusKind::Dirty, None),
+		BranchStatus::Ready => (proto::StatusKind::Ready, None),
+		BranchStatus::Error { reason } => (proto::StatusKind::Error, Some(reason)),
+		BranchStatus::Suspended { reason } => (proto::StatusKind::Suspended, Some(reason)),
+	};
+	proto::Status {
+		kind: kind.into(),
+		reason,
+	}
+}
+
+fn package_status_into_proto(status: PackageStatus) -> proto::Status {
+	let (kind, reason) = match status {
+		PackageStatus::Dirty => (proto::StatusKind::Dirty, None),
+		PackageStatus::Ready => (proto::StatusKind::Ready, None),
+		PackageStatus::Error { reason } => (proto::StatusKind::Error, Some(reason)),
+	};
+	proto::Status {
+		kind: kind.into(),
+		reason,
+	}
+}
+
+fn branch_into_proto(branch: ApiBranchInfo) -> proto::Branch {
+	proto::Branch {
+		name: branch.name,
+		base: branch.base,
+		repository: branch.repository,
+		target_group: branch.target_group,
+		status: Some(branch_status_into_proto(branch.status)),
+		priority: branch.priority as u32,
+		tracking_mode: match branch.tracking_mode {
+			TrackingMode::Auto => proto::TrackingMode::Auto,
+			TrackingMode::Unmanaged => proto::TrackingMode::Unmanaged,
+		}
+		.into(),
+		commit: branch.commit.map(|commit| commit.to_string()),
+		packages: branch.packages,
+		created_at: time_into_proto(branch.created_at),
+		updated_at: time_into_proto(branch.updated_at),
+	}
+}
+
+fn package_target_into_proto(target: ApiPackageTargetInfo) -> proto::PackageTarget {
+	proto::PackageTarget {
+		target: target.target.name,
+		arch: target.target.arch,
+		status: match target.status {
+			PackageTargetStatus::Dirty => proto::TargetStatus::Dirty,
+			PackageTargetStatus::Ready => proto::TargetStatus::Ready,
+			PackageTargetStatus::BuildFailed => proto::TargetStatus::BuildFailed,
+			PackageTargetStatus::Error => proto::TargetStatus::Error,
+		}
+		.into(),
+		last_build: target.last_build.map(|id| id.to_string()),
+		artifacts: target.artifacts,
+		updated_at: time_into_proto(target.updated_at),
+	}
+}
+
+fn package_into_proto(package: ApiPackageInfo) -> proto::Package {
+	proto::Package {
+		name: package.name,
+		section: package.section,
+		status: Some(package_status_into_proto(package.status)),
+		version: package.version,
+		release: package.release,
+		epoch: package.epoch,
+		dependencies: package.dependencies,
+		targets: package
+			.targets
+			.into_iter()
+			.map(package_target_into_proto)
+			.collect(),
+		upstream_version: package.upstream_version,
+		outdated: package.outdated,
+		created_at: time_into_proto(package.created_at),
+		updated_at: time_into_proto(package.updated_at),
+	}
+}
+
+fn time_into_proto(time: OffsetDateTime) -> String {
+	time.format(&Rfc3339).unwrap_or_default()
+}
+
+fn job_into_proto(job: ApiJobInfo) -> proto::Job {
+	proto::Job {
+		id: job.id.to_string(),
+		kind: job.kind,
+		branch: job.branch,
+		status: match job.status {
+			JobStatus::Pending => proto::JobStatus::Pending,
+			JobStatus::Running => proto::JobStatus::Running,
+			JobStatus::Succeeded => proto::JobStatus::Succeeded,
+			JobStatus::Failed => proto::JobStatus::Failed,
+			JobStatus::Aborted => proto::JobStatus::Aborted,
+		}
+		.into(),
+		priority: job.priority as u32,
+		attempts: job.attempts as u32,
+		started_at: job.started_at.map(time_into_proto),
+		finished_at: job.finished_at.map(time_into_proto),
+		error: job.error.map(|error| proto::JobError {
+			retryable: error.retryable,
+			message: error.message,
+		}),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use fabricia_crayon_proto::{self as proto, branch_service_server::BranchService};
+	use tonic::{Code, Request};
+
+	use crate::test::MockApis;
+
+	use super::GrpcApi;
+
+	#[tokio::test]
+	async fn test_lint_branch_auth() {
+		let mut apis = MockApis::default().with_admin_token();
+		apis.namespace.expect_get_by_name().never();
+		let api = GrpcApi(apis.into_services());
+
+		let request = proto::LintBranchRequest {
+			namespace: String::new(),
+			name: "main".to_string(),
+		};
+		let status = api
+			.lint_branch(Request::new(request.clone()))
+			.await
+			.unwrap_err();
+		assert_eq!(status.code(), Code::Unauthenticated);
+
+		let mut request = Request::new(request);
+		request
+			.metadata_mut()
+			.insert("authorization", "Bearer other".parse().unwrap());
+		let status = api.lint_branch(request).await.unwrap_err();
+		assert_eq!(status.code(), Code::Unauthenticated);
+	}
+}
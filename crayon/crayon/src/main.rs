@@ -1,21 +1,56 @@
 use std::{fs, path::PathBuf, sync::Arc};
 
-use anyhow::{Result, bail};
+use anyhow::Result;
+use axum::http::StatusCode;
 use bus::CrayonBusFactory;
 use clap::Parser;
 use config::CrayonConfig;
-use fabricia_backend::BackendServices;
-use tokio::net::{TcpListener, UnixListener};
+use fabricia_backend::{
+	BackendServices,
+	admin_token::AdminTokenApi,
+	backup::BackupService,
+	bootstrap,
+	branch::BranchApi,
+	db,
+	instance::{InstanceInfo, InstanceRole},
+	job_queue::JobQueueApi,
+	namespace::NamespaceApi,
+	package::PackageApi,
+	preflight,
+};
+use fabricia_common_server::listen;
+use routes::api::error::{ApiResult, OptionExt};
+use time::OffsetDateTime;
 use tracing::info;
 
+mod build_info;
 mod bus;
 mod config;
+#[cfg(feature = "grpc")]
+mod grpc;
 mod routes;
 
 #[derive(clap::Parser)]
 struct Args {
 	#[arg(short, long, default_value = "crayon.toml")]
 	config: PathBuf,
+	/// Checks the configuration, connectivity and migrations, and exits.
+	///
+	/// Exits with a non-zero status if any check fails.
+	#[arg(long)]
+	check: bool,
+	/// Restores a backup archive into a clean instance and exits.
+	#[arg(long)]
+	restore: Option<PathBuf>,
+	/// Creates the first admin token, the namespace and branches in the
+	/// `bootstrap` section of the configuration, and exits.
+	#[arg(long)]
+	bootstrap: bool,
+	/// Runs contract migrations deferred by `database.expand-only`, and exits.
+	///
+	/// Fails if instances of other versions are running.
+	#[arg(long)]
+	contract: bool,
 }
 
 #[tokio::main]
@@ -27,45 +62,272 @@ async fn main() -> Result<()> {
 			.with_max_level(tracing::Level::INFO)
 			.finish(),
 	)?;
+	info!(
+		version = build_info::VERSION,
+		commit = build_info::COMMIT.unwrap_or("unknown"),
+		build_date = ?build_info::build_date(),
+		features = ?build_info::features(),
+		"starting Fabricia Crayon"
+	);
 
 	let config_path = &args.config;
-	let config = toml::from_str::<CrayonConfig>(&fs::read_to_string(config_path)?)?;
+	let mut config = toml::from_str::<CrayonConfig>(&fs::read_to_string(config_path)?)?;
+	config.resolve_secrets().await?;
 	info!("loaded configuration from file: {:?}", config_path);
 
+	if args.check {
+		let report = preflight::check(&config.clone().try_into()?).await;
+		print!("{report}");
+		if !report.passed() {
+			std::process::exit(1);
+		}
+		return Ok(());
+	}
+
+	let restored = match &args.restore {
+		Some(archive) => Some(BackupService::restore(&config.database, archive).await?),
+		None => None,
+	};
+
 	info!("initializing backend services ...");
 	let backend_services =
 		BackendServices::new(config.clone().try_into()?, CrayonBusFactory).await?;
 	info!("initialized backend services");
-	let services = CrayonServices {
-		config,
-		backend: Arc::new(backend_services),
-	};
+	{
+		let mut conn = backend_services.database.get().await?;
+		info!(
+			sql_backend = conn.backend_name(),
+			migration_level = ?db::migration_level(&mut conn).await?,
+			"connected to database"
+		);
+	}
+	if let Some(manifest) = restored {
+		backend_services.backup.apply(&manifest).await?;
+		info!("restored backup from archive: {:?}", args.restore);
+		return Ok(());
+	}
+	if args.contract {
+		let now = OffsetDateTime::now_utc();
+		let outdated = backend_services
+			.instance
+			.list()
+			.await?
+			.into_iter()
+			.filter(|instance| instance.is_alive(now) && instance.version != build_info::VERSION)
+			.collect::<Vec<_>>();
+		if !outdated.is_empty() {
+			for instance in &outdated {
+				println!(
+					"instance {} ({:?}) is of version {}",
+					instance.id, instance.role, instance.version
+				);
+			}
+			anyhow::bail!("{} instances of other versions are running", outdated.len());
+		}
+		let versions = backend_services
+			.database
+			.contract(&backend_services.lock)
+			.await?;
+		for version in &versions {
+			println!("applied contract migration {version}");
+		}
+		println!("{} contract migrations applied", versions.len());
+		return Ok(());
+	}
+	if args.bootstrap {
+		let report = bootstrap::bootstrap(&backend_services, &config.bootstrap).await?;
+		if let Some(token) = report.admin_token {
+			println!(
+				"admin token {}: {}",
+				config.bootstrap.admin_token, token.token
+			);
+		}
+		if let Some(token) = report.namespace_token {
+			println!(
+				"namespace token {}: {}",
+				config.bootstrap.namespace, token.token
+			);
+		}
+		for branch in report.import.iter().flat_map(|import| &import.branches) {
+			match &branch.result {
+				Ok(operation) => {
+					println!("tracked branch {} in operation {operation}", branch.name)
+				}
+				Err(error) => println!("failed to track branch {}: {error}", branch.name),
+			}
+		}
+		return Ok(());
+	}
+	let backend_services = Arc::new(backend_services);
+	let services = CrayonServices::new(config, backend_services.clone());
 
 	tokio::spawn(bus::handle_bus_message(services.clone()));
+	let instance = InstanceInfo::new(InstanceRole::Crayon, build_info::VERSION);
+	tokio::spawn(backend_services.instance.clone().run_heartbeat(instance));
 
-	let listen_addr = services.config.web.listen.clone();
-	let router = routes::make_router(services)?;
-	if let Some(path) = listen_addr.strip_prefix("unix://") {
-		let path = PathBuf::from(path);
-		_ = fs::remove_file(&path);
-		fs::create_dir_all(path.parent().unwrap())?;
-
-		let listener = UnixListener::bind(&path)?;
-		info!("listening on UDS: {:?}", path);
-		axum::serve(listener, router).await?;
-	} else if let Some(addr) = listen_addr.strip_prefix("tcp://") {
-		let listener = TcpListener::bind(addr).await?;
-		info!("listening on TCP {}", listener.local_addr()?);
-		axum::serve(listener, router).await.unwrap();
-	} else {
-		bail!("unsupported web.listen schema")
+	if let Some(grpc) = &services.config.grpc {
+		#[cfg(feature = "grpc")]
+		{
+			let listener = listen::bind(&grpc.listen, &grpc.socket)?;
+			let router = grpc::make_router(services.clone());
+			tokio::spawn(async move {
+				if let Err(error) = listen::serve(listener, router).await {
+					tracing::error!(?error, "gRPC server failed");
+				}
+			});
+		}
+		#[cfg(not(feature = "grpc"))]
+		tracing::warn!(
+			"gRPC API is configured on {}, but Crayon is built without the `grpc` feature",
+			grpc.listen
+		);
 	}
 
+	let listener = listen::bind(&services.config.web.listen, &services.config.web.socket)?;
+	let router = routes::make_router(services)?;
+	listen::serve(listener, router).await?;
+
 	Ok(())
 }
 
+/// Services used by Crayon routes.
+///
+/// Routes should prefer the service traits over [`CrayonServices::backend`],
+/// so that handlers can be tested with mocks.
 #[derive(Debug, Clone)]
 pub struct CrayonServices {
 	pub config: CrayonConfig,
-	pub backend: Arc<BackendServices>,
+	/// Backend services not covered by the service traits,
+	/// [`None`] if created by [`CrayonServices::with_apis`].
+	backend: Option<Arc<BackendServices>>,
+	pub admin_token: Arc<dyn AdminTokenApi>,
+	pub branch: Arc<dyn BranchApi>,
+	pub job_queue: Arc<dyn JobQueueApi>,
+	pub namespace: Arc<dyn NamespaceApi>,
+	pub package: Arc<dyn PackageApi>,
+}
+
+impl CrayonServices {
+	pub fn new(config: CrayonConfig, backend: Arc<BackendServices>) -> Self {
+		Self {
+			backend: Some(backend.clone()),
+			..Self::with_apis(
+				config,
+				backend.admin_token.clone(),
+				backend.branch.clone(),
+				backend.job_queue.clone(),
+				backend.namespace.clone(),
+				backend.package.clone(),
+			)
+		}
+	}
+
+	/// Creates services from the service traits alone, e.g. with mocks in tests.
+	///
+	/// Routes using [`CrayonServices::backend`] respond with 503.
+	pub fn with_apis(
+		config: CrayonConfig,
+		admin_token: Arc<dyn AdminTokenApi>,
+		branch: Arc<dyn BranchApi>,
+		job_queue: Arc<dyn JobQueueApi>,
+		namespace: Arc<dyn NamespaceApi>,
+		package: Arc<dyn PackageApi>,
+	) -> Self {
+		Self {
+			config,
+			backend: None,
+			admin_token,
+			branch,
+			job_queue,
+			namespace,
+			package,
+		}
+	}
+
+	/// Returns the backend services, for routes not covered by the service traits.
+	pub fn backend(&self) -> ApiResult<&Arc<BackendServices>> {
+		self.backend.as_ref().or_api_error(
+			StatusCode::SERVICE_UNAVAILABLE,
+			"backend services are unavailable",
+		)
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+	use std::sync::Arc;
+
+	use axum::{
+		body::{Body, to_bytes},
+		http::{Request, Response},
+	};
+	use fabricia_backend::{
+		admin_token::MockAdminTokenApi, branch::MockBranchApi, job_queue::MockJobQueueApi,
+		namespace::MockNamespaceApi, package::MockPackageApi,
+	};
+	use serde::de::DeserializeOwned;
+	use tower::ServiceExt;
+
+	use crate::{CrayonServices, config::CrayonConfig, routes};
+
+	/// Configuration of services in tests, whose database is never connected to.
+	const TEST_CONFIG: &str = r#"
+		target = []
+
+		[web]
+		listen = "tcp://127.0.0.1:0"
+
+		[database]
+		url = "sqlite://:memory:"
+	"#;
+
+	/// Admin token accepted by [`MockApis::with_admin_token`].
+	pub const ADMIN_TOKEN: &str = "admin-token";
+
+	/// Mocks of the service traits of [`CrayonServices`].
+	#[derive(Default)]
+	pub struct MockApis {
+		pub admin_token: MockAdminTokenApi,
+		pub branch: MockBranchApi,
+		pub job_queue: MockJobQueueApi,
+		pub namespace: MockNamespaceApi,
+		pub package: MockPackageApi,
+	}
+
+	impl MockApis {
+		/// Accepts only [`ADMIN_TOKEN`] as the admin token.
+		pub fn with_admin_token(mut self) -> Self {
+			self.admin_token
+				.expect_authorize()
+				.returning(|token| Ok(token == Some(ADMIN_TOKEN)));
+			self
+		}
+
+		pub fn into_services(self) -> CrayonServices {
+			let config: CrayonConfig = toml::from_str(TEST_CONFIG).unwrap();
+			CrayonServices::with_apis(
+				config,
+				Arc::new(self.admin_token),
+				Arc::new(self.branch),
+				Arc::new(self.job_queue),
+				Arc::new(self.namespace),
+				Arc::new(self.package),
+			)
+		}
+	}
+
+	/// Sends a request to the router of Crayon.
+	pub async fn send(services: CrayonServices, request: Request<Body>) -> Response<Body> {
+		routes::make_router(services)
+			.unwrap()
+			.oneshot(request)
+			.await
+			.unwrap()
+	}
+
+	/// Reads a JSON response body.
+	pub async fn json<T: DeserializeOwned>(response: Response<Body>) -> T {
+		let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+		serde_json::from_slice(&body).unwrap()
+	}
 }
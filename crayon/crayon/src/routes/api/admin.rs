@@ -0,0 +1,465 @@
+use std::{collections::HashMap, env};
+
+use axum::{
+	Json,
+	body::{Body, Bytes},
+	extract::{Path, Query, State},
+	http::{StatusCode, header},
+	response::IntoResponse,
+};
+use fabricia_backend::{
+	backup::BackupError,
+	branch::BranchManifest,
+	branch_template::BranchTemplate,
+	bus::BackendBusMessage,
+	instance::{InstanceRole, StopMode},
+	namespace::{DEFAULT_NAMESPACE, NamespaceConfigInfo, NamespaceInfo},
+};
+use fabricia_crayon_api_model::admin::*;
+use futures::{Stream, stream};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tokio::{fs, io::AsyncReadExt};
+use uuid::Uuid;
+
+use crate::CrayonServices;
+
+use super::{
+	auth::AuthRequired,
+	error::{ApiResult, OptionExt},
+	operation::get_api_operation,
+};
+
+pub async fn get_queue_state(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<Json<ApiJobQueueState>> {
+	let paused = services.job_queue.is_paused().await?;
+	Ok(Json(ApiJobQueueState { paused }))
+}
+
+pub async fn pause_queue(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<(StatusCode, &'static str)> {
+	services.job_queue.pause().await?;
+	services
+		.backend()?
+		.bus
+		.broadcast(BackendBusMessage::JobQueuePaused(true))
+		.await?;
+	Ok((StatusCode::ACCEPTED, "job queue paused"))
+}
+
+pub async fn resume_queue(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<(StatusCode, &'static str)> {
+	services.job_queue.resume().await?;
+	services
+		.backend()?
+		.bus
+		.broadcast(BackendBusMessage::JobQueuePaused(false))
+		.await?;
+	Ok((StatusCode::ACCEPTED, "job queue resumed"))
+}
+
+pub async fn get_maintenance_state(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<Json<ApiMaintenanceState>> {
+	let message = services.backend()?.maintenance.message().await?;
+	Ok(Json(ApiMaintenanceState {
+		enabled: message.is_some(),
+		message,
+	}))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceQuery {
+	/// Message shown to clients, or the configured message if not given.
+	message: Option<String>,
+}
+
+/// Enables maintenance mode on all instances, rejecting mutations with 503.
+pub async fn enable_maintenance(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Query(query): Query<MaintenanceQuery>,
+) -> ApiResult<(StatusCode, &'static str)> {
+	let message = query
+		.message
+		.unwrap_or_else(|| services.config.web.maintenance_message.clone());
+	services.backend()?.maintenance.enable(&message).await?;
+	Ok((StatusCode::ACCEPTED, "maintenance mode enabled"))
+}
+
+pub async fn disable_maintenance(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<(StatusCode, &'static str)> {
+	services.backend()?.maintenance.disable().await?;
+	Ok((StatusCode::ACCEPTED, "maintenance mode disabled"))
+}
+
+pub async fn get_scale_hint(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<Json<ApiScaleHint>> {
+	let job_queue = &services.job_queue;
+	let paused = job_queue.is_paused().await?;
+	let depth = job_queue.depth().await?;
+	let estimated_backlog = job_queue.estimate_backlog(&depth).await?;
+
+	let mut kinds = HashMap::with_capacity(depth.len());
+	for depth in depth {
+		kinds.insert(
+			depth.kind.to_string(),
+			ApiQueueDepth {
+				pending: depth.pending,
+				running: depth.running,
+			},
+		);
+	}
+	Ok(Json(ApiScaleHint {
+		paused,
+		pending: kinds.values().map(|depth| depth.pending).sum(),
+		running: kinds.values().map(|depth| depth.running).sum(),
+		kinds,
+		estimated_backlog_secs: estimated_backlog.whole_seconds().max(0) as u64,
+	}))
+}
+
+pub async fn list_instances(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<Json<Vec<ApiInstanceInfo>>> {
+	let instances = services.backend()?.instance.list().await?;
+	let instances = instances
+		.into_iter()
+		.map(|info| ApiInstanceInfo {
+			id: info.id,
+			role: match info.role {
+				InstanceRole::Axis => "axis",
+				InstanceRole::Crayon => "crayon",
+			}
+			.to_string(),
+			version: info.version,
+			started_at: OffsetDateTime::from_unix_timestamp(info.started_at)
+				.unwrap_or(OffsetDateTime::UNIX_EPOCH),
+			heartbeat_at: OffsetDateTime::from_unix_timestamp(info.heartbeat_at)
+				.unwrap_or(OffsetDateTime::UNIX_EPOCH),
+			stop_mode: info.stop_mode.map(|mode| {
+				match mode {
+					StopMode::Drain => "drain",
+					StopMode::HardStop => "hard-stop",
+				}
+				.to_string()
+			}),
+		})
+		.collect();
+	Ok(Json(instances))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+	/// Namespace to import branches into, or the default namespace.
+	namespace: Option<String>,
+}
+
+/// Tracks branches listed in a manifest, e.g. when migrating from another instance.
+pub async fn import_branches(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Query(query): Query<ImportQuery>,
+	Json(manifest): Json<BranchManifest>,
+) -> ApiResult<(StatusCode, Json<ApiBranchImport>)> {
+	let namespace = get_namespace(
+		&services,
+		query.namespace.as_deref().unwrap_or(DEFAULT_NAMESPACE),
+	)
+	.await?;
+	let import = services.branch.import(namespace.id, manifest).await?;
+	let branches = import
+		.branches
+		.into_iter()
+		.map(|branch| {
+			let (operation, error) = match branch.result {
+				Ok(operation) => (Some(operation), None),
+				Err(error) => (None, Some(error)),
+			};
+			ApiImportedBranch {
+				name: branch.name,
+				operation,
+				error,
+			}
+		})
+		.collect();
+	Ok((
+		StatusCode::ACCEPTED,
+		Json(ApiBranchImport {
+			operation: get_api_operation(&services, import.operation).await?,
+			branches,
+		}),
+	))
+}
+
+/// Exports a backup archive of this instance.
+///
+/// The archive can be restored into a clean instance with `crayon --restore`.
+pub async fn export_backup(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<impl IntoResponse> {
+	let path = env::temp_dir().join(format!("fabricia-backup-{}.tar", Uuid::now_v7()));
+	let manifest = services.backend()?.backup.export(&path).await?;
+	let archive = fs::File::open(&path).await.map_err(BackupError::from);
+	// the archive is streamed from the opened file after removed
+	let _ = fs::remove_file(&path).await;
+	let disposition = format!(
+		"attachment; filename=\"fabricia-backup-{}.tar\"",
+		manifest.created_at
+	);
+	Ok((
+		[
+			(header::CONTENT_TYPE, "application/x-tar".to_string()),
+			(header::CONTENT_DISPOSITION, disposition),
+		],
+		Body::from_stream(file_stream(archive?)),
+	))
+}
+
+/// Size of chunks of streamed files.
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads a file chunk by chunk, so that large files are not loaded into memory.
+fn file_stream(file: fs::File) -> impl Stream<Item = std::io::Result<Bytes>> + Send + 'static {
+	stream::try_unfold(file, |mut file| async move {
+		let mut chunk = vec![0; FILE_CHUNK_SIZE];
+		let len = file.read(&mut chunk).await?;
+		if len == 0 {
+			return Ok(None);
+		}
+		chunk.truncate(len);
+		Ok(Some((Bytes::from(chunk), file)))
+	})
+}
+
+async fn get_namespace(services: &CrayonServices, name: &str) -> ApiResult<NamespaceInfo> {
+	services
+		.namespace
+		.get_by_name(name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "namespace not found")
+}
+
+fn namespace_into_api(namespace: NamespaceInfo) -> ApiNamespaceInfo {
+	ApiNamespaceInfo {
+		name: namespace.name,
+		protected: namespace.token_hash.is_some(),
+		max_branches: namespace.max_branches,
+		max_queued_jobs: namespace.max_queued_jobs,
+	}
+}
+
+pub async fn list_namespaces(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<Json<Vec<ApiNamespaceInfo>>> {
+	let namespaces = services.namespace.list().await?;
+	Ok(Json(
+		namespaces.into_iter().map(namespace_into_api).collect(),
+	))
+}
+
+/// Creates a namespace, and returns its bearer token.
+pub async fn new_namespace(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Path(name): Path<String>,
+	Json(info): Json<NamespaceConfigInfo>,
+) -> ApiResult<(StatusCode, Json<ApiNamespaceToken>)> {
+	let token = services.namespace.create(&name, info).await?;
+	Ok((
+		StatusCode::CREATED,
+		Json(ApiNamespaceToken {
+			name,
+			token: token.token,
+		}),
+	))
+}
+
+/// Replaces the bearer token of a namespace.
+pub async fn rotate_namespace_token(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Path(name): Path<String>,
+) -> ApiResult<Json<ApiNamespaceToken>> {
+	let namespace = get_namespace(&services, &name).await?;
+	let token = services.namespace.rotate_token(namespace.id).await?;
+	Ok(Json(ApiNamespaceToken {
+		name,
+		token: token.token,
+	}))
+}
+
+/// Deletes a namespace without branches.
+pub async fn delete_namespace(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Path(name): Path<String>,
+) -> ApiResult<(StatusCode, &'static str)> {
+	let namespace = get_namespace(&services, &name).await?;
+	services.namespace.delete(namespace.id).await?;
+	Ok((StatusCode::OK, "namespace deleted"))
+}
+
+/// Lists templates of branch configurations.
+pub async fn list_branch_templates(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<Json<Vec<ApiBranchTemplate>>> {
+	let mut templates = vec![];
+	for row in services.backend()?.branch_template.list().await? {
+		let template = row.template()?;
+		templates.push(ApiBranchTemplate {
+			name: row.name,
+			priority: template.priority,
+			tracking_mode: template.tracking_mode,
+			target_group: template.target_group.map(|group| group.to_string()),
+			max_running_jobs: template.max_running_jobs,
+			max_queued_jobs: template.max_queued_jobs,
+			updated_at: row.updated_at.assume_utc(),
+		});
+	}
+	Ok(Json(templates))
+}
+
+/// Creates or replaces a template of branch configurations.
+pub async fn put_branch_template(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Path(name): Path<String>,
+	Json(template): Json<BranchTemplate>,
+) -> ApiResult<(StatusCode, &'static str)> {
+	services
+		.backend()?
+		.branch_template
+		.put(&name, &template)
+		.await?;
+	Ok((StatusCode::OK, "template saved"))
+}
+
+/// Deletes a template of branch configurations.
+///
+/// Branches tracked with the template are not affected.
+pub async fn delete_branch_template(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Path(name): Path<String>,
+) -> ApiResult<(StatusCode, &'static str)> {
+	services
+		.backend()?
+		.branch_template
+		.delete(&name)
+		.await?
+		.then_some(())
+		.or_api_error(StatusCode::NOT_FOUND, "template not found")?;
+	Ok((StatusCode::OK, "template deleted"))
+}
+
+#[cfg(test)]
+mod test {
+	use axum::{
+		body::Body,
+		http::{Method, Request, StatusCode, header},
+	};
+	use fabricia_backend::namespace::{NamespaceInfo, NamespaceToken};
+	use fabricia_crayon_api_model::admin::{ApiNamespaceInfo, ApiNamespaceToken};
+
+	use crate::test::{ADMIN_TOKEN, MockApis, json, send};
+
+	fn new_namespace_request(token: Option<&str>) -> Request<Body> {
+		let mut request = Request::builder()
+			.method(Method::PUT)
+			.uri("/api/v0/admin/namespace/dev")
+			.header(header::CONTENT_TYPE, "application/json");
+		if let Some(token) = token {
+			request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+		}
+		request.body(Body::from("{}")).unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_new_namespace() {
+		let mut apis = MockApis::default().with_admin_token();
+		apis.namespace
+			.expect_create()
+			.withf(|name, _| name == "dev")
+			.times(1)
+			.returning(|_, _| {
+				Ok(NamespaceToken {
+					id: 2,
+					token: "dev-token".to_string(),
+				})
+			});
+		let services = apis.into_services();
+
+		let response = send(services.clone(), new_namespace_request(None)).await;
+		assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+		let response = send(services.clone(), new_namespace_request(Some("dev"))).await;
+		assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+		let response = send(services, new_namespace_request(Some(ADMIN_TOKEN))).await;
+		assert_eq!(response.status(), StatusCode::CREATED);
+		assert_eq!(
+			json::<ApiNamespaceToken>(response).await,
+			ApiNamespaceToken {
+				name: "dev".to_string(),
+				token: "dev-token".to_string(),
+			}
+		);
+	}
+
+	#[tokio::test]
+	async fn test_list_namespaces() {
+		let mut apis = MockApis::default().with_admin_token();
+		apis.namespace.expect_list().returning(|| {
+			Ok(vec![NamespaceInfo {
+				id: 2,
+				name: "dev".to_string(),
+				token_hash: Some("0".repeat(64)),
+				max_branches: Some(10),
+				max_queued_jobs: None,
+			}])
+		});
+		let request = Request::builder()
+			.uri("/api/v0/admin/namespace")
+			.header(header::AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+			.body(Body::empty())
+			.unwrap();
+		let response = send(apis.into_services(), request).await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			json::<Vec<ApiNamespaceInfo>>(response).await,
+			[ApiNamespaceInfo {
+				name: "dev".to_string(),
+				protected: true,
+				max_branches: Some(10),
+				max_queued_jobs: None,
+			}]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_backend_unavailable() {
+		let apis = MockApis::default().with_admin_token();
+		let request = Request::builder()
+			.uri("/api/v0/admin/maintenance")
+			.header(header::AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+			.body(Body::empty())
+			.unwrap();
+		let response = send(apis.into_services(), request).await;
+		assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+	}
+}
@@ -1,21 +1,88 @@
-use axum::{extract::FromRequestParts, http::request::Parts};
+use axum::{
+	extract::FromRequestParts,
+	http::{HeaderMap, header, request::Parts},
+};
+use fabricia_backend::branch_acl::Principal;
 
-use super::error::ApiError;
+use crate::CrayonServices;
 
+use super::error::{ApiError, ApiResult};
+
+/// Requires the request to carry an admin token as the bearer token,
+/// or a session with the admin role with the `oidc` feature.
+///
+/// Admin routes are closed until the first admin token is created,
+/// see `crayon --bootstrap`.
 pub struct AuthRequired;
 
-impl<S> FromRequestParts<S> for AuthRequired
-where
-	S: Send + Sync,
-{
+impl FromRequestParts<CrayonServices> for AuthRequired {
 	type Rejection = ApiError;
 
-	async fn from_request_parts(_parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-		// TODO: implement authorization
-		if true {
+	async fn from_request_parts(
+		parts: &mut Parts,
+		services: &CrayonServices,
+	) -> Result<Self, Self::Rejection> {
+		Self::authorize(services, &parts.headers).await
+	}
+}
+
+impl AuthRequired {
+	/// Checks the headers of a request, also used by the gRPC API.
+	pub async fn authorize(services: &CrayonServices, headers: &HeaderMap) -> ApiResult<Self> {
+		#[cfg(feature = "oidc")]
+		if crate::routes::oidc::Session::from_headers(services, headers)
+			.is_some_and(|session| session.has_role(crate::routes::oidc::ADMIN_ROLE))
+		{
+			return Ok(Self);
+		}
+
+		if services
+			.admin_token
+			.authorize(bearer_token(headers))
+			.await?
+		{
 			Ok(Self)
 		} else {
 			Err(ApiError::AuthRequired)
 		}
 	}
 }
+
+/// Identities of the caller, checked against ACLs of branches.
+///
+/// Identities are `token:{name}` of admin tokens, and with the `oidc` feature,
+/// `user:{sub}` and `role:{name}` of the session.
+/// Requests without credentials are anonymous.
+pub struct Caller(pub Principal);
+
+impl FromRequestParts<CrayonServices> for Caller {
+	type Rejection = ApiError;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		services: &CrayonServices,
+	) -> Result<Self, Self::Rejection> {
+		let mut principal = Principal::anonymous();
+		if let Some(token) = bearer_token(&parts.headers) {
+			if let Some(name) = services.admin_token.find_name(token).await? {
+				principal = principal.with_identity("token", &name);
+			}
+		}
+		#[cfg(feature = "oidc")]
+		if let Some(session) = crate::routes::oidc::Session::from_headers(services, &parts.headers)
+		{
+			principal = principal.with_identity("user", &session.sub);
+			for role in &session.roles {
+				principal = principal.with_identity("role", role);
+			}
+		}
+		Ok(Self(principal))
+	}
+}
+
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+	headers
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+}
@@ -1,158 +1,438 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use axum::{
 	Json,
-	extract::{Path, State},
-	http::StatusCode,
+	extract::{Path, Query, State},
+	http::{HeaderMap, StatusCode, header},
+	response::Response,
 };
-use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, Queryable, Selectable};
+use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
 use fabricia_backend::{
-	branch::{BranchConfigInfo, SqlBranchStatus, SqlTrackingMode},
-	db::{
-		schema::{self, branch::dsl},
-		service::SqlConnRef,
-		utils::WherePredicate,
-	},
+	branch::{BranchConfigInfo, SqlBranchStatus},
+	branch_template::BranchTemplateService,
+	db::{schema::branch::dsl, service::SqlConnRef, utils::WherePredicate},
+	model::BranchRow,
 };
-use fabricia_common_model::branch::TrackingMode;
-use fabricia_crayon_api_model::branch::*;
-use serde::{Deserialize, Serialize};
+use fabricia_common_model::{
+	branch::{RemovalPolicy, TrackingMode},
+	git::GitOid,
+};
+use fabricia_crayon_api_model::{branch::*, operation::ApiOperation};
+use serde::Deserialize;
 
 use crate::CrayonServices;
 
 use super::{
-	auth::AuthRequired,
+	auth::{AuthRequired, Caller},
+	conditional::{conditional_json, json_tag, tagged_json},
 	error::{ApiError, ApiResult, OptionExt},
+	namespace::Namespace,
+	operation::{get_api_operation, get_api_operation_in},
+	tx::Tx,
 };
 
+/// Path parameters of branch routes.
+///
+/// Other parameters, like the namespace, are ignored.
+#[derive(Debug, Deserialize)]
+pub struct BranchPath {
+	pub branch: String,
+}
+
 pub async fn list_branches(
 	State(services): State<CrayonServices>,
-) -> ApiResult<Json<HashMap<String, ApiBranchInfo>>> {
-	let mut db = services.backend.database.get().await?;
-	let result: Vec<SqlApiBranchInfo> = db.load_select(dsl::branch).await?;
-	let mut output = HashMap::with_capacity(result.len());
+	Namespace(namespace): Namespace,
+	headers: HeaderMap,
+) -> ApiResult<Response> {
+	let mut db = services.backend()?.database.get().await?;
+	let result: Vec<BranchRow> = db
+		.load_select(dsl::branch.filter(dsl::namespace.eq(namespace)))
+		.await?;
+	// ordered by name, so that the entity tag is stable
+	let mut output = BTreeMap::new();
 	for info in result {
-		output.insert(info.name.clone(), info.into_api(&mut db).await?);
+		output.insert(
+			info.name.clone(),
+			branch_into_api(&services, info, &mut db).await?,
+		);
 	}
 
-	Ok(Json(output))
-}
-
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Queryable, Selectable)]
-#[diesel(table_name = schema::branch)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
-pub struct SqlApiBranchInfo {
-	name: String,
-	base: Option<i64>,
-	status: i16,
-	status_msg: Option<String>,
-	priority: i16,
-	tracking: i16,
-	commit: Option<Vec<u8>>,
-	total_srcpkgs: i32,
-}
-
-impl SqlApiBranchInfo {
-	async fn into_api(self, db: &mut SqlConnRef) -> ApiResult<ApiBranchInfo> {
-		let base = match self.base {
-			None => None,
-			Some(base) => db
-				.get_result(
-					dsl::branch
-						.select(dsl::name)
-						.filter(dsl::id.eq(base))
-						.limit(1),
-				)
-				.await
-				.optional()?,
-		};
-		let status = SqlBranchStatus::from(self.status).into_common(self.status_msg);
-		let tracking_mode = TrackingMode::from(SqlTrackingMode::from(self.tracking));
-		let commit = self.commit.map(hex::encode);
-		Ok(ApiBranchInfo {
-			name: self.name.clone(),
-			base,
-			status,
-			priority: self.priority as u16,
-			tracking_mode,
-			commit,
-			packages: self.total_srcpkgs as u32,
-		})
-	}
+	tagged_json(&headers, &output)
+}
+
+pub(crate) async fn branch_into_api(
+	services: &CrayonServices,
+	branch: BranchRow,
+	db: &mut SqlConnRef,
+) -> ApiResult<ApiBranchInfo> {
+	let base = match branch.base {
+		None => None,
+		Some(base) => db
+			.get_result(
+				dsl::branch
+					.select(dsl::name)
+					.filter(dsl::id.eq(base))
+					.limit(1),
+			)
+			.await
+			.optional()?,
+	};
+	let status = branch.status.into_common(branch.status_msg);
+	let tracking_mode = TrackingMode::from(branch.tracking);
+	let removal_policy = RemovalPolicy::from(branch.removal_policy);
+	let commit = branch
+		.commit
+		.map(|commit| GitOid::from_bytes(&commit))
+		.transpose()
+		.map_err(|error| {
+			ApiError::CustomString(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+		})?;
+	let pinned_commit = branch
+		.pinned_commit
+		.map(|commit| GitOid::from_bytes(&commit))
+		.transpose()
+		.map_err(|error| {
+			ApiError::CustomString(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+		})?;
+	let repository = services
+		.backend()?
+		.repository
+		.resolve(branch.repository)
+		.map(|repository| repository.name.to_string());
+	Ok(ApiBranchInfo {
+		name: branch.name,
+		base,
+		repository,
+		target_group: branch.target_group,
+		status,
+		priority: branch.priority as u16,
+		tracking_mode,
+		removal_policy,
+		commit,
+		pinned_commit,
+		packages: branch.total_srcpkgs as u32,
+		max_running_jobs: branch.max_running_jobs.map(|limit| limit as u32),
+		max_queued_jobs: branch.max_queued_jobs.map(|limit| limit as u32),
+		created_at: branch.created_at.assume_utc(),
+		updated_at: branch.updated_at.assume_utc(),
+	})
 }
 
 pub async fn get_branch(
 	State(services): State<CrayonServices>,
-	Path(name): Path<String>,
-) -> ApiResult<Json<ApiBranchInfo>> {
-	let mut db = services.backend.database.get().await?;
-	get_branch_info(&mut db, dsl::name.eq(name)).await
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	headers: HeaderMap,
+) -> ApiResult<Response> {
+	let mut db = services.backend()?.database.get().await?;
+	let (etag, info) = get_branch_info(
+		&services,
+		&mut db,
+		dsl::namespace.eq(namespace).and(dsl::name.eq(name)),
+	)
+	.await?;
+	conditional_json(&headers, &etag, &info)
 }
 
+/// Returns information of a branch, with its entity tag.
+///
+/// The entity tag is `"{version}-{hash}"`, where `version` is the configuration
+/// version for `If-Match`, and `hash` changes with any field of the branch.
 async fn get_branch_info<F: WherePredicate<dsl::branch>>(
+	services: &CrayonServices,
 	db: &mut SqlConnRef,
 	filter: F,
-) -> ApiResult<Json<ApiBranchInfo>> {
-	let result: SqlApiBranchInfo = db
+) -> ApiResult<(String, ApiBranchInfo)> {
+	let result: BranchRow = db
 		.load_one_select(dsl::branch.limit(1).filter(filter))
 		.await?;
-	Ok(Json(result.into_api(db).await?))
+	let version = result.version;
+	let info = branch_into_api(services, result, db).await?;
+	let etag = format!("\"{version}-{}\"", json_tag(&info)?);
+	Ok((etag, info))
+}
+
+/// Parses the configuration version in `If-Match`.
+///
+/// Only the version prefix of entity tags returned by [`get_branch_info`] is compared,
+/// and tags of bare versions are accepted as well.
+///
+/// Returns `None` for `*`, which matches any version.
+fn parse_if_match(headers: &HeaderMap) -> ApiResult<Option<i64>> {
+	let value = headers
+		.get(header::IF_MATCH)
+		.or_api_error(StatusCode::PRECONDITION_REQUIRED, "If-Match is required")?
+		.to_str()
+		.ok()
+		.map(str::trim);
+	match value {
+		Some("*") => Ok(None),
+		Some(value) => value
+			.strip_prefix("W/")
+			.unwrap_or(value)
+			.trim_matches('"')
+			.split('-')
+			.next()
+			.unwrap_or_default()
+			.parse()
+			.map(Some)
+			.map_err(|_| ApiError::CustomRef(StatusCode::BAD_REQUEST, "invalid If-Match")),
+		None => Err(ApiError::CustomRef(
+			StatusCode::BAD_REQUEST,
+			"invalid If-Match",
+		)),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewBranchQuery {
+	/// Name of the template filling fields not given.
+	pub template: Option<String>,
 }
 
 pub async fn new_branch(
 	AuthRequired: AuthRequired,
 	State(services): State<CrayonServices>,
-	Path(name): Path<String>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	Query(NewBranchQuery { template }): Query<NewBranchQuery>,
+	mut tx: Tx,
 	Json(info): Json<BranchConfigInfo>,
-) -> ApiResult<(StatusCode, Json<ApiBranchInfo>)> {
-	let branch = &services.backend.branch;
-	if branch.find_id(&name).await?.is_some() {
-		return Err(ApiError::CustomRef(
-			StatusCode::NOT_ACCEPTABLE,
-			"branch has already been tracked",
-		));
-	}
-
-	branch.track(&name, info).await?;
-
-	let mut db = services.backend.database.get().await?;
+) -> ApiResult<(StatusCode, Json<ApiOperation>)> {
+	let info = match template {
+		Some(template) => BranchTemplateService::find_in(&mut tx, &template)
+			.await?
+			.or_api_error(StatusCode::UNPROCESSABLE_ENTITY, "template not found")?
+			.apply(info),
+		None => info,
+	};
+	let branch = &services.branch;
+	let operation = branch.track_in(&mut tx, namespace, &name, info).await?;
 	Ok((
-		StatusCode::CREATED,
-		get_branch_info(&mut db, dsl::name.eq(name)).await?,
+		StatusCode::ACCEPTED,
+		Json(get_api_operation_in(&mut tx, operation).await?),
 	))
 }
 
 pub async fn update_branch_config(
 	AuthRequired: AuthRequired,
+	Caller(principal): Caller,
 	State(services): State<CrayonServices>,
-	Path(name): Path<String>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	headers: HeaderMap,
 	Json(info): Json<BranchConfigInfo>,
-) -> ApiResult<(StatusCode, Json<ApiBranchInfo>)> {
-	let branch = &services.backend.branch;
+) -> ApiResult<(StatusCode, HeaderMap, Json<ApiBranchInfo>)> {
+	let version = parse_if_match(&headers)?;
+	let branch = &services.branch;
 	let id = branch
-		.find_id(&name)
+		.find_id(namespace, &name)
 		.await?
 		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
-	branch.update_config(id, &info).await?;
+	branch.update_config(id, &info, version, &principal).await?;
+
+	let mut db = services.backend()?.database.get().await?;
+	let (etag, info) = get_branch_info(&services, &mut db, dsl::id.eq(id)).await?;
+	let mut headers = HeaderMap::new();
+	headers.insert(header::ETAG, etag.parse().unwrap());
+	Ok((StatusCode::ACCEPTED, headers, Json(info)))
+}
 
-	let mut db = services.backend.database.get().await?;
+/// Unpins a branch, so that syncs follow the head of the Git branch again.
+pub async fn unpin_branch(
+	AuthRequired: AuthRequired,
+	Caller(principal): Caller,
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+) -> ApiResult<(HeaderMap, Json<ApiBranchInfo>)> {
+	let branch = &services.branch;
+	let id = branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	branch.unpin(id, &principal).await?;
+
+	let mut db = services.backend()?.database.get().await?;
+	let (etag, info) = get_branch_info(&services, &mut db, dsl::id.eq(id)).await?;
+	let mut headers = HeaderMap::new();
+	headers.insert(header::ETAG, etag.parse().unwrap());
+	Ok((headers, Json(info)))
+}
+
+pub async fn delete_branch(
+	AuthRequired: AuthRequired,
+	Caller(principal): Caller,
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+) -> ApiResult<(StatusCode, Json<ApiOperation>)> {
+	let branch = &services.branch;
+	let id = branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let operation = branch.untrack(id, &principal).await?;
 	Ok((
 		StatusCode::ACCEPTED,
-		get_branch_info(&mut db, dsl::name.eq(name)).await?,
+		Json(get_api_operation(&services, operation).await?),
 	))
 }
 
-pub async fn delete_branch(
+/// Lists principals allowed to mutate a branch.
+pub async fn get_branch_acl(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+) -> ApiResult<Json<ApiBranchAcl>> {
+	let id = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	Ok(Json(ApiBranchAcl {
+		principals: services.backend()?.branch_acl.list(id).await?,
+	}))
+}
+
+/// Replaces principals allowed to mutate a branch.
+///
+/// The caller must be allowed by the current ACL.
+pub async fn update_branch_acl(
 	AuthRequired: AuthRequired,
+	Caller(principal): Caller,
 	State(services): State<CrayonServices>,
-	Path(name): Path<String>,
-) -> ApiResult<(StatusCode, &'static str)> {
-	let branch = &services.backend.branch;
-	let id = branch
-		.find_id(name)
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	Json(acl): Json<ApiBranchAcl>,
+) -> ApiResult<Json<ApiBranchAcl>> {
+	let id = services
+		.branch
+		.find_id(namespace, &name)
 		.await?
 		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
-	branch.untrack(id).await?;
-	Ok((StatusCode::ACCEPTED, "branch deleted"))
+	let acl_service = &services.backend()?.branch_acl;
+	acl_service.set(id, &acl.principals, &principal).await?;
+	Ok(Json(ApiBranchAcl {
+		principals: acl_service.list(id).await?,
+	}))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncBranchQuery {
+	#[serde(default)]
+	dry_run: bool,
+}
+
+/// Previews synchronization of a branch.
+///
+/// The branch is evaluated synchronously, bounded by the timeout of the evaluation
+/// command, and compared with its packages without writing anything. Only dry
+/// runs are accepted, as branches are synchronized by jobs enqueued on tracking.
+pub async fn sync_branch(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	Query(query): Query<SyncBranchQuery>,
+) -> ApiResult<Json<ApiBranchSyncPreview>> {
+	if !query.dry_run {
+		return Err(ApiError::CustomRef(
+			StatusCode::BAD_REQUEST,
+			"only dry runs are supported",
+		));
+	}
+	let backend = services.backend()?;
+	let mut db = backend.database.get().await?;
+	let branch: BranchRow = db
+		.load_one_select(
+			dsl::branch
+				.filter(dsl::namespace.eq(namespace))
+				.filter(dsl::name.eq(&name)),
+		)
+		.await
+		.optional()?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	drop(db);
+	let evaluation = backend.evaluator.evaluate(&branch).await?;
+	let diff = services
+		.package
+		.diff(branch.id, &evaluation.packages)
+		.await?;
+	Ok(Json(ApiBranchSyncPreview {
+		commit: evaluation.commit,
+		added: diff.added,
+		removed: diff.removed,
+		dirty: diff.dirty,
+	}))
+}
+
+/// Lists status transitions of a branch, newest first.
+pub async fn get_branch_timeline(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	headers: HeaderMap,
+) -> ApiResult<Response> {
+	let branch = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let events = services
+		.branch
+		.timeline(branch)
+		.await?
+		.into_iter()
+		.map(|event| ApiBranchEvent {
+			status: event.status.into_common(event.message),
+			actor: event.actor,
+			created_at: event.created_at.assume_utc(),
+		})
+		.collect::<Vec<_>>();
+	tagged_json(&headers, &events)
+}
+
+pub async fn get_branch_graph(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+) -> ApiResult<Json<ApiBranchGraph>> {
+	let mut db = services.backend()?.database.get().await?;
+	let branches = db
+		.load::<_, (i64, String, Option<i64>, SqlBranchStatus, Option<String>)>(
+			dsl::branch.filter(dsl::namespace.eq(namespace)).select((
+				dsl::id,
+				dsl::name,
+				dsl::base,
+				dsl::status,
+				dsl::status_msg,
+			)),
+		)
+		.await?;
+	let names: HashMap<i64, String> = branches
+		.iter()
+		.map(|(id, name, ..)| (*id, name.clone()))
+		.collect();
+
+	let mut graph = ApiBranchGraph {
+		nodes: HashMap::with_capacity(branches.len()),
+		edges: Vec::new(),
+	};
+	for (_, name, base, status, status_msg) in branches {
+		let base = base.and_then(|base| names.get(&base).cloned());
+		if let Some(base) = &base {
+			graph.edges.push(ApiBranchGraphEdge {
+				base: base.clone(),
+				branch: name.clone(),
+			});
+		}
+		graph.nodes.insert(
+			name,
+			ApiBranchGraphNode {
+				base,
+				status: status.into_common(status_msg),
+			},
+		);
+	}
+	Ok(Json(graph))
 }
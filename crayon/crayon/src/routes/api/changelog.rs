@@ -0,0 +1,78 @@
+//! Changelogs of branches.
+
+use axum::{
+	extract::{Path, State},
+	http::{HeaderMap, StatusCode},
+	response::Response,
+};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use fabricia_backend::{changelog, db::schema::branch::dsl, model::BranchRow};
+use fabricia_crayon_api_model::branch::{
+	ApiBranchChangelog, ApiChangelogEntry, ApiPackageChangelog,
+};
+
+use crate::CrayonServices;
+
+use super::{
+	branch::BranchPath,
+	conditional::tagged_json,
+	error::{ApiResult, OptionExt},
+	namespace::Namespace,
+};
+
+/// Aggregates changelog entries of packages in a branch since its base branch.
+pub async fn get_branch_changelog(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	headers: HeaderMap,
+) -> ApiResult<Response> {
+	let mut db = services.backend()?.database.get().await?;
+	let branch: BranchRow = db
+		.load_one_select(
+			dsl::branch
+				.filter(dsl::namespace.eq(namespace))
+				.filter(dsl::name.eq(&name)),
+		)
+		.await
+		.optional()?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let base: Option<String> = match branch.base {
+		Some(base) => Some(
+			db.get_result(dsl::branch.filter(dsl::id.eq(base)).select(dsl::name))
+				.await?,
+		),
+		None => None,
+	};
+	drop(db);
+
+	let packages = services.package.list(branch.id).await?;
+	let base_packages = match branch.base {
+		Some(base) => services.package.list(base).await?,
+		None => vec![],
+	};
+	let packages = changelog::aggregate(&packages, &base_packages)?
+		.into_iter()
+		.map(|pkg| ApiPackageChangelog {
+			name: pkg.name,
+			version: pkg.version,
+			entries: pkg
+				.entries
+				.into_iter()
+				.map(|entry| ApiChangelogEntry {
+					version: entry.version,
+					author: entry.author,
+					message: entry.message,
+				})
+				.collect(),
+		})
+		.collect();
+	tagged_json(
+		&headers,
+		&ApiBranchChangelog {
+			branch: name,
+			base,
+			packages,
+		},
+	)
+}
@@ -0,0 +1,107 @@
+//! Comparison of branches.
+
+use axum::{
+	Json,
+	extract::{Path, Query, State},
+	http::StatusCode,
+};
+use fabricia_backend::branch_compare::PackageChange;
+use fabricia_crayon_api_model::branch::{
+	ApiBranchComparison, ApiPackageChange, ApiPackageComparison, ApiTargetComparison,
+};
+use serde::Deserialize;
+
+use crate::CrayonServices;
+
+use super::{
+	error::{ApiResult, OptionExt},
+	namespace::Namespace,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ComparePath {
+	pub branch: String,
+	pub other: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompareQuery {
+	/// Name of the last compared package of the previous page.
+	after: Option<String>,
+	limit: Option<usize>,
+}
+
+/// Lists packages differing between two branches of the same namespace.
+///
+/// Packages are compared page by page, with `next` of the response as `after`
+/// of the next page. Pages may have less packages than `limit`, as packages
+/// without differences are omitted.
+pub async fn compare_branches(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(ComparePath { branch, other }): Path<ComparePath>,
+	Query(query): Query<CompareQuery>,
+) -> ApiResult<Json<ApiBranchComparison>> {
+	let branch_id = services
+		.branch
+		.find_id(namespace, &branch)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let other_id = services
+		.branch
+		.find_id(namespace, &other)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let targets = &services.backend()?.target;
+	let page = services
+		.package
+		.compare(
+			branch_id,
+			other_id,
+			query.after,
+			query.limit.unwrap_or(100).min(1000),
+			targets.count(),
+		)
+		.await?;
+
+	let packages = page
+		.packages
+		.into_iter()
+		.filter_map(|pkg| {
+			let mut target_infos = pkg
+				.targets
+				.into_iter()
+				// targets removed from the configuration are skipped
+				.filter_map(|diff| {
+					Some(ApiTargetComparison {
+						target: targets.get(diff.target)?.name.to_string(),
+						status: diff.state.map(Into::into),
+						other_status: diff.other_state.map(Into::into),
+					})
+				})
+				.collect::<Vec<_>>();
+			target_infos.sort_by(|a, b| a.target.cmp(&b.target));
+			// differing only on removed targets
+			if pkg.version == pkg.other_version && target_infos.is_empty() {
+				return None;
+			}
+			Some(ApiPackageComparison {
+				name: pkg.name,
+				change: match pkg.change {
+					PackageChange::Added => ApiPackageChange::Added,
+					PackageChange::Removed => ApiPackageChange::Removed,
+					PackageChange::Changed => ApiPackageChange::Changed,
+				},
+				version: pkg.version,
+				other_version: pkg.other_version,
+				targets: target_infos,
+			})
+		})
+		.collect();
+	Ok(Json(ApiBranchComparison {
+		branch,
+		other,
+		packages,
+		next: page.next,
+	}))
+}
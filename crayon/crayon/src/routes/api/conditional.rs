@@ -0,0 +1,69 @@
+//! Conditional GET of read routes.
+//!
+//! Entity tags are hashed from response bodies, so that clients polling a route
+//! receive `304 Not Modified` until the response changes.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use axum::{
+	body::Body,
+	http::{HeaderMap, StatusCode, header},
+	response::{IntoResponse, Response},
+};
+use fabricia_backend::BackendError;
+use serde::Serialize;
+
+use super::error::ApiResult;
+
+/// Hashes the JSON representation of a value, for entity tags.
+pub fn json_tag<T: Serialize>(value: &T) -> ApiResult<String> {
+	let body = serde_json::to_vec(value).map_err(BackendError::from)?;
+	let mut hasher = DefaultHasher::new();
+	body.hash(&mut hasher);
+	Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Returns whether an entity tag matches `If-None-Match`.
+///
+/// Tags are compared weakly, as required for `If-None-Match`.
+fn is_not_modified(headers: &HeaderMap, etag: &str) -> bool {
+	let etag = etag.strip_prefix("W/").unwrap_or(etag);
+	headers
+		.get_all(header::IF_NONE_MATCH)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(','))
+		.map(str::trim)
+		.any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Makes a JSON response with an entity tag.
+///
+/// Responds with `304 Not Modified` if the tag matches `If-None-Match`.
+/// Clients are asked to revalidate cached responses on every use.
+pub fn conditional_json<T: Serialize>(
+	headers: &HeaderMap,
+	etag: &str,
+	value: &T,
+) -> ApiResult<Response> {
+	let builder = Response::builder()
+		.header(header::ETAG, etag)
+		.header(header::CACHE_CONTROL, "no-cache");
+	let response = if is_not_modified(headers, etag) {
+		builder.status(StatusCode::NOT_MODIFIED).body(Body::empty())
+	} else {
+		let body = serde_json::to_vec(value).map_err(BackendError::from)?;
+		builder
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(Body::from(body))
+	};
+	Ok(response.unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response()))
+}
+
+/// Makes a JSON response with a weak entity tag hashed from the value.
+///
+/// See [`conditional_json`].
+pub fn tagged_json<T: Serialize>(headers: &HeaderMap, value: &T) -> ApiResult<Response> {
+	let etag = format!("W/\"{}\"", json_tag(value)?);
+	conditional_json(headers, &etag, value)
+}
@@ -2,7 +2,11 @@ use axum::{
 	http::StatusCode,
 	response::{AppendHeaders, IntoResponse, Response},
 };
-use fabricia_backend::BackendError;
+use fabricia_backend::{
+	BackendError, artifact_diff::ArtifactDiffError, backup::BackupError, branch::BranchError,
+	evaluator::EvaluatorError, job_queue::JobQueueError, namespace::NamespaceError,
+	package::PackageError,
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -32,12 +36,74 @@ impl IntoResponse for ApiError {
 				"authentication is required",
 			)
 				.into_response()
+		} else if let ApiError::BackendError(error) = self {
+			let status = backend_error_status(&error);
+			if status == StatusCode::SERVICE_UNAVAILABLE {
+				(
+					status,
+					AppendHeaders([("Retry-After", RETRY_AFTER_SECS)]),
+					error.to_string(),
+				)
+					.into_response()
+			} else {
+				(status, error.to_string()).into_response()
+			}
 		} else {
 			(StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
 		}
 	}
 }
 
+/// Seconds for clients to wait before retrying on temporary unavailability.
+const RETRY_AFTER_SECS: &str = "5";
+
+/// Maps a backend error to the HTTP status code.
+///
+/// Errors not listed here are internal server errors.
+pub(crate) fn backend_error_status(error: &BackendError) -> StatusCode {
+	match error {
+		BackendError::BranchError(error) => match error {
+			BranchError::BranchNameNotFound(_) | BranchError::BranchNotFound(_) => {
+				StatusCode::NOT_FOUND
+			}
+			BranchError::AlreadyExists(_) => StatusCode::CONFLICT,
+			BranchError::VersionMismatch(_) => StatusCode::PRECONDITION_FAILED,
+			BranchError::AccessDenied(_) => StatusCode::FORBIDDEN,
+			BranchError::BaseCycle(_)
+			| BranchError::BaseNotFound(_)
+			| BranchError::SelfBase(_)
+			| BranchError::RepositoryNotFound(_)
+			| BranchError::TargetGroupNotFound(_) => StatusCode::UNPROCESSABLE_ENTITY,
+		},
+		BackendError::JobQueueError(error) => match error {
+			JobQueueError::JobAborted(_) => StatusCode::CONFLICT,
+			JobQueueError::QuotaExceeded(_) | JobQueueError::NamespaceQuotaExceeded(_) => {
+				StatusCode::TOO_MANY_REQUESTS
+			}
+		},
+		BackendError::NamespaceError(error) => match error {
+			NamespaceError::NotFound(_) => StatusCode::NOT_FOUND,
+			NamespaceError::AlreadyExists(_) | NamespaceError::NotEmpty(_) => StatusCode::CONFLICT,
+			NamespaceError::DeleteDefault => StatusCode::FORBIDDEN,
+			NamespaceError::BranchQuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+		},
+		BackendError::PackageError(PackageError::Conflict(..)) => StatusCode::CONFLICT,
+		BackendError::BackupError(BackupError::Unsupported) => StatusCode::NOT_IMPLEMENTED,
+		BackendError::ArtifactDiffError(ArtifactDiffError::Disabled) => StatusCode::NOT_IMPLEMENTED,
+		BackendError::EvaluatorError(error) => match error {
+			EvaluatorError::NotConfigured => StatusCode::NOT_IMPLEMENTED,
+			EvaluatorError::NoRepository | EvaluatorError::BranchNotFound(_) => {
+				StatusCode::UNPROCESSABLE_ENTITY
+			}
+			EvaluatorError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+			EvaluatorError::ResolveError(..) | EvaluatorError::Failed(_) => StatusCode::BAD_GATEWAY,
+			_ => StatusCode::INTERNAL_SERVER_ERROR,
+		},
+		error if error.is_pool_timeout() => StatusCode::SERVICE_UNAVAILABLE,
+		_ => StatusCode::INTERNAL_SERVER_ERROR,
+	}
+}
+
 impl<T: Into<BackendError>> From<T> for ApiError {
 	fn from(value: T) -> Self {
 		Self::BackendError(value.into())
@@ -0,0 +1,302 @@
+//! Export of package states of branches.
+
+use std::{borrow::Cow, collections::HashMap, fmt::Write};
+
+use axum::{
+	body::{Body, Bytes},
+	extract::{Path, Query, State},
+	http::{StatusCode, header},
+	response::{IntoResponse, Response},
+};
+use fabricia_backend::{
+	branch::BranchRef,
+	model::{PkgFindingRow, PkgRow, PkgTargetRow},
+	target::TargetService,
+	upstream::is_outdated,
+};
+use fabricia_common_model::package::{PackageStatus, PackageTargetStatus};
+use fabricia_crayon_api_model::package::{ApiPackageInfo, ApiPackageRepro, ApiPackageTargetInfo};
+use futures::{
+	Stream, StreamExt,
+	future::ready,
+	stream::{self, TryStreamExt},
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::CrayonServices;
+
+use super::{
+	branch::BranchPath,
+	error::{ApiError, ApiResult, OptionExt},
+	lint::finding_into_api,
+	namespace::Namespace,
+};
+
+/// Count of packages read from the database at once.
+const EXPORT_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+	/// A JSON array of [`ApiPackageInfo`].
+	#[default]
+	Json,
+	/// One row per (package, target).
+	Csv,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+	#[serde(default)]
+	format: ExportFormat,
+}
+
+/// Exports packages of a branch with their states on all targets.
+///
+/// The response is streamed while packages are read page by page.
+pub async fn export_branch(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	Query(query): Query<ExportQuery>,
+) -> ApiResult<Response> {
+	let branch = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let disposition = |ext: &str| format!("attachment; filename=\"{name}.{ext}\"");
+
+	let pages = package_pages(services.clone(), branch, name.clone());
+	let (content_type, disposition, body) = match query.format {
+		ExportFormat::Json => (
+			"application/json",
+			disposition("json"),
+			Body::from_stream(json_stream(pages)),
+		),
+		ExportFormat::Csv => (
+			"text/csv; charset=utf-8",
+			disposition("csv"),
+			Body::from_stream(csv_stream(pages)),
+		),
+	};
+	Ok((
+		[
+			(header::CONTENT_TYPE, content_type.to_string()),
+			(header::CONTENT_DISPOSITION, disposition),
+		],
+		body,
+	)
+		.into_response())
+}
+
+/// Reads packages of a branch page by page.
+fn package_pages(
+	services: CrayonServices,
+	branch: BranchRef,
+	name: String,
+) -> impl Stream<Item = ApiResult<Vec<ApiPackageInfo>>> + Send + 'static {
+	// `None` when all pages have been read
+	stream::try_unfold(Some(None::<String>), move |after| {
+		let services = services.clone();
+		let name = name.clone();
+		async move {
+			let Some(after) = after else {
+				return Ok(None);
+			};
+			let packages = services
+				.package
+				.list_page(branch, after, EXPORT_PAGE_SIZE)
+				.await?;
+			if packages.is_empty() {
+				return Ok(None);
+			}
+			let next = match packages.len() == EXPORT_PAGE_SIZE {
+				true => packages.last().map(|pkg| Some(pkg.name.clone())),
+				false => None,
+			};
+			let infos = packages_into_api(&services, packages, &name).await?;
+			Ok(Some((infos, next)))
+		}
+	})
+}
+
+/// Converts packages of a branch, with their target states, findings and upstream versions.
+pub(crate) async fn packages_into_api(
+	services: &CrayonServices,
+	packages: Vec<PkgRow>,
+	branch: &str,
+) -> ApiResult<Vec<ApiPackageInfo>> {
+	let backend = services.backend()?;
+	let ids = packages.iter().map(|pkg| pkg.id.0).collect::<Vec<_>>();
+	let mut targets: HashMap<Uuid, Vec<PkgTargetRow>> = HashMap::new();
+	for row in services.package.list_targets(ids.clone()).await? {
+		targets.entry(row.package.0).or_default().push(row);
+	}
+	let mut findings: HashMap<Uuid, Vec<PkgFindingRow>> = HashMap::new();
+	for row in backend.lint.list(ids).await? {
+		findings.entry(row.package.0).or_default().push(row);
+	}
+	let names = packages.iter().map(|pkg| pkg.name.clone()).collect();
+	let upstream = backend.upstream.versions(names).await?;
+	packages
+		.into_iter()
+		.map(|pkg| {
+			let targets = targets.remove(&pkg.id.0).unwrap_or_default();
+			let findings = findings.remove(&pkg.id.0).unwrap_or_default();
+			let upstream = upstream.get(&pkg.name).cloned();
+			Ok(package_into_api(
+				pkg,
+				branch,
+				targets,
+				findings,
+				upstream,
+				&backend.target,
+			)?)
+		})
+		.collect()
+}
+
+fn package_into_api(
+	pkg: PkgRow,
+	branch: &str,
+	targets: Vec<PkgTargetRow>,
+	findings: Vec<PkgFindingRow>,
+	upstream_version: Option<String>,
+	target_service: &TargetService,
+) -> fabricia_backend::Result<ApiPackageInfo> {
+	let data = pkg.pkg_data()?;
+	let mut target_infos = Vec::with_capacity(targets.len());
+	for row in targets {
+		// rows of targets removed from the configuration are skipped
+		let Some(target) = target_service.get(row.target as u64) else {
+			continue;
+		};
+		let target_data = row.target_data()?;
+		target_infos.push(ApiPackageTargetInfo {
+			target: target.as_ref().into(),
+			status: row.status.into(),
+			last_build: target_data.last_build,
+			artifacts: target_data.artifacts,
+			repro: target_data.repro.map(|repro| ApiPackageRepro {
+				status: repro.status,
+				verify_build: repro.verify_build,
+				differing: repro.differing,
+			}),
+			created_at: row.created_at.assume_utc(),
+			updated_at: row.updated_at.assume_utc(),
+		});
+	}
+	target_infos.sort_by(|a, b| a.target.name.cmp(&b.target.name));
+	let outdated = upstream_version
+		.as_deref()
+		.is_some_and(|upstream| is_outdated(&data.version, upstream));
+	Ok(ApiPackageInfo {
+		name: pkg.name,
+		branch: branch.to_string(),
+		section: pkg.section,
+		status: pkg.status.into_common(pkg.status_msg),
+		version: (!data.version.is_empty()).then_some(data.version),
+		release: data.release,
+		epoch: data.epoch,
+		dependencies: data.dependencies,
+		targets: target_infos,
+		findings: findings.into_iter().map(finding_into_api).collect(),
+		upstream_version,
+		outdated,
+		hold_reason: pkg.hold_reason,
+		created_at: pkg.created_at.assume_utc(),
+		updated_at: pkg.updated_at.assume_utc(),
+	})
+}
+
+fn json_stream(
+	pages: impl Stream<Item = ApiResult<Vec<ApiPackageInfo>>> + Send + 'static,
+) -> impl Stream<Item = ApiResult<Bytes>> + Send + 'static {
+	let mut first = true;
+	let items = pages.and_then(move |infos| {
+		let mut output = Vec::new();
+		for info in infos {
+			if !first {
+				output.push(b',');
+			}
+			first = false;
+			if let Err(error) = serde_json::to_writer(&mut output, &info) {
+				return ready(Err(ApiError::from(error)));
+			}
+		}
+		ready(Ok(Bytes::from(output)))
+	});
+	stream::once(ready(Ok(Bytes::from_static(b"["))))
+		.chain(items)
+		.chain(stream::once(ready(Ok(Bytes::from_static(b"]")))))
+}
+
+const CSV_HEADER: &str = "package,section,status,status_reason,version,release,epoch,\
+	target,arch,target_status,last_build\n";
+
+fn csv_stream(
+	pages: impl Stream<Item = ApiResult<Vec<ApiPackageInfo>>> + Send + 'static,
+) -> impl Stream<Item = ApiResult<Bytes>> + Send + 'static {
+	let rows = pages.map_ok(|infos| {
+		let mut output = String::new();
+		for info in infos {
+			write_csv_rows(&mut output, &info);
+		}
+		Bytes::from(output)
+	});
+	stream::once(ready(Ok(Bytes::from_static(CSV_HEADER.as_bytes())))).chain(rows)
+}
+
+/// Writes rows of a package, one for each target.
+///
+/// Packages without any target states are written as a row with empty target columns.
+fn write_csv_rows(output: &mut String, info: &ApiPackageInfo) {
+	let (status, reason) = match &info.status {
+		PackageStatus::Dirty => ("dirty", ""),
+		PackageStatus::Ready => ("ready", ""),
+		PackageStatus::Error { reason } => ("error", reason.as_str()),
+	};
+	let package = format!(
+		"{},{},{},{},{},{},{}",
+		csv_field(&info.name),
+		csv_field(&info.section),
+		status,
+		csv_field(reason),
+		csv_field(info.version.as_deref().unwrap_or_default()),
+		info.release,
+		info.epoch,
+	);
+	if info.targets.is_empty() {
+		let _ = writeln!(output, "{package},,,,");
+		return;
+	}
+	for target in &info.targets {
+		let status = match target.status {
+			PackageTargetStatus::Dirty => "dirty",
+			PackageTargetStatus::Ready => "ready",
+			PackageTargetStatus::BuildFailed => "build_failed",
+			PackageTargetStatus::Error => "error",
+		};
+		let _ = writeln!(
+			output,
+			"{package},{},{},{status},{}",
+			csv_field(&target.target.name),
+			csv_field(&target.target.arch),
+			target
+				.last_build
+				.map(|id| id.to_string())
+				.unwrap_or_default(),
+		);
+	}
+}
+
+/// Quotes a CSV field if needed.
+fn csv_field(value: &str) -> Cow<'_, str> {
+	if value.contains([',', '"', '\n', '\r']) {
+		Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+	} else {
+		Cow::Borrowed(value)
+	}
+}
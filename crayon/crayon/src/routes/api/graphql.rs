@@ -0,0 +1,584 @@
+//! GraphQL facade of the API, enabled by the `graphql` feature.
+//!
+//! The object graph of namespaces, branches, packages, targets and jobs is resolved
+//! with the same services and conversions as the REST routes, so clients may fetch
+//! nested objects in one round trip.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use async_graphql::{
+	Context, EmptyMutation, EmptySubscription, Error, Guard, Object, Result, Schema, SimpleObject,
+	connection::{Connection, Edge},
+};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::{
+	extract::State,
+	http::{HeaderMap, header},
+};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use fabricia_backend::{
+	branch::BranchRef,
+	db::schema::branch::dsl,
+	job_queue::JobRef,
+	model::BranchRow,
+	namespace::{DEFAULT_NAMESPACE, NamespaceRef},
+};
+use fabricia_common_model::{branch::BranchStatus, package::PackageStatus};
+use fabricia_crayon_api_model::{
+	branch::ApiBranchInfo,
+	job::ApiJobInfo,
+	package::{ApiPackageFinding, ApiPackageInfo, ApiPackageTargetInfo},
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::CrayonServices;
+
+use super::{
+	auth::AuthRequired,
+	branch::branch_into_api,
+	error::ApiError,
+	export::packages_into_api,
+	job::{branch_names, finished_into_api, in_namespace, queued_into_api},
+};
+
+/// Count of objects in a page if not requested.
+const DEFAULT_PAGE_SIZE: usize = 50;
+/// Maximum count of objects in a page.
+const MAX_PAGE_SIZE: usize = 500;
+
+pub type ApiSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+static SCHEMA: LazyLock<ApiSchema> = LazyLock::new(|| {
+	Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+		.limit_depth(8)
+		.finish()
+});
+
+/// Credentials of a GraphQL request.
+struct Authorization {
+	/// Bearer token, checked against tokens of namespaces.
+	token: Option<String>,
+	/// Whether [`AuthRequired`] is satisfied.
+	authenticated: bool,
+}
+
+/// Requires [`AuthRequired`] to resolve a field.
+struct AuthGuard;
+
+impl Guard for AuthGuard {
+	async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+		match ctx.data::<Authorization>()?.authenticated {
+			true => Ok(()),
+			false => Err(ApiError::AuthRequired.into()),
+		}
+	}
+}
+
+/// Executes a GraphQL request.
+pub async fn graphql_handler(
+	State(services): State<CrayonServices>,
+	auth: std::result::Result<AuthRequired, ApiError>,
+	headers: HeaderMap,
+	request: GraphQLRequest,
+) -> GraphQLResponse {
+	let token = headers
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+		.map(str::to_string);
+	let request = request.into_inner().data(services).data(Authorization {
+		token,
+		authenticated: auth.is_ok(),
+	});
+	SCHEMA.execute(request).await.into()
+}
+
+fn page_size(first: Option<i32>) -> usize {
+	first.map_or(DEFAULT_PAGE_SIZE, |first| {
+		first.clamp(0, MAX_PAGE_SIZE as i32) as usize
+	})
+}
+
+/// Returns the name of a unit variant, as serialized in the REST API.
+fn variant_name<T: Serialize>(value: &T) -> String {
+	match serde_json::to_value(value) {
+		Ok(serde_json::Value::String(name)) => name,
+		_ => String::new(),
+	}
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+	/// Finds a namespace by name, which must be authorized with its token.
+	async fn namespace(
+		&self,
+		ctx: &Context<'_>,
+		#[graphql(default_with = "DEFAULT_NAMESPACE.to_string()")] name: String,
+	) -> Result<NamespaceObject> {
+		NamespaceObject::authorize(ctx, name).await
+	}
+
+	/// Finds a job of the default namespace in the queue, or a finished job.
+	async fn job(&self, ctx: &Context<'_>, id: JobRef) -> Result<Option<JobObject>> {
+		let namespace = NamespaceObject::authorize(ctx, DEFAULT_NAMESPACE.to_string()).await?;
+		namespace.find_job(ctx, id).await
+	}
+
+	/// Lists jobs of the default namespace in the queue.
+	async fn jobs(&self, ctx: &Context<'_>, limit: Option<i32>) -> Result<Vec<JobObject>> {
+		let namespace = NamespaceObject::authorize(ctx, DEFAULT_NAMESPACE.to_string()).await?;
+		namespace.list_jobs(ctx, limit).await
+	}
+}
+
+pub struct NamespaceObject {
+	id: NamespaceRef,
+	name: String,
+}
+
+impl NamespaceObject {
+	/// Finds a namespace by name, which must be authorized with its token.
+	async fn authorize(ctx: &Context<'_>, name: String) -> Result<Self> {
+		let services = ctx.data::<CrayonServices>()?;
+		let auth = ctx.data::<Authorization>()?;
+		let namespace = services
+			.namespace
+			.get_by_name(&name)
+			.await?
+			.ok_or_else(|| Error::new("namespace not found"))?;
+		if !namespace.authorize(auth.token.as_deref()) {
+			return Err(ApiError::AuthRequired.into());
+		}
+		Ok(Self {
+			id: namespace.id,
+			name,
+		})
+	}
+
+	async fn find_job(&self, ctx: &Context<'_>, id: JobRef) -> Result<Option<JobObject>> {
+		let services = ctx.data::<CrayonServices>()?;
+		let branches = branch_names(services, self.id).await?;
+		let job = match services.job_queue.get(id).await? {
+			Some(job) => Some((
+				job.command.subject_branch(),
+				queued_into_api(job, &branches),
+			)),
+			None => services
+				.job_queue
+				.get_finished(id)
+				.await?
+				.map(|job| (job.subject_branch, finished_into_api(job, &branches))),
+		};
+		Ok(job
+			.filter(|(branch, _)| in_namespace(self.id, *branch, &branches))
+			.map(|(_, job)| JobObject(job)))
+	}
+
+	async fn list_jobs(&self, ctx: &Context<'_>, limit: Option<i32>) -> Result<Vec<JobObject>> {
+		let services = ctx.data::<CrayonServices>()?;
+		let limit = limit.map_or(100, |limit| limit.clamp(0, 1000) as usize);
+		let branches = branch_names(services, self.id).await?;
+		Ok(services
+			.job_queue
+			.list(self.id, limit)
+			.await?
+			.into_iter()
+			.map(|job| JobObject(queued_into_api(job, &branches)))
+			.collect())
+	}
+}
+
+#[Object(name = "Namespace")]
+impl NamespaceObject {
+	async fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// Finds a job of this namespace in the queue, or a finished job.
+	///
+	/// Jobs without subject branches are in the default namespace.
+	async fn job(&self, ctx: &Context<'_>, id: JobRef) -> Result<Option<JobObject>> {
+		self.find_job(ctx, id).await
+	}
+
+	/// Lists jobs of this namespace in the queue.
+	async fn jobs(&self, ctx: &Context<'_>, limit: Option<i32>) -> Result<Vec<JobObject>> {
+		self.list_jobs(ctx, limit).await
+	}
+
+	async fn branch(&self, ctx: &Context<'_>, name: String) -> Result<Option<BranchObject>> {
+		let services = ctx.data::<CrayonServices>()?;
+		let mut db = services.backend()?.database.get().await?;
+		let row: Option<BranchRow> = db
+			.load_one_select(
+				dsl::branch
+					.filter(dsl::namespace.eq(self.id))
+					.filter(dsl::name.eq(name)),
+			)
+			.await
+			.optional()?;
+		let Some(row) = row else {
+			return Ok(None);
+		};
+		let id = row.id;
+		let info = branch_into_api(services, row, &mut db).await?;
+		Ok(Some(BranchObject { id, info }))
+	}
+
+	/// Lists branches ordered by name, paginated by names.
+	async fn branches(
+		&self,
+		ctx: &Context<'_>,
+		after: Option<String>,
+		first: Option<i32>,
+	) -> Result<Connection<String, BranchObject>> {
+		let services = ctx.data::<CrayonServices>()?;
+		let size = page_size(first);
+		let mut db = services.backend()?.database.get().await?;
+		let mut rows: Vec<BranchRow> = db
+			.load_select(
+				dsl::branch
+					.filter(dsl::namespace.eq(self.id))
+					.filter(dsl::name.gt(after.clone().unwrap_or_default()))
+					.order(dsl::name.asc())
+					.limit(size as i64 + 1),
+			)
+			.await?;
+		let has_next = rows.len() > size;
+		rows.truncate(size);
+
+		let mut connection = Connection::new(after.is_some(), has_next);
+		for row in rows {
+			let id = row.id;
+			let info = branch_into_api(services, row, &mut db).await?;
+			connection
+				.edges
+				.push(Edge::new(info.name.clone(), BranchObject { id, info }));
+		}
+		Ok(connection)
+	}
+}
+
+/// A status with the reason of errors and suspensions.
+#[derive(SimpleObject)]
+#[graphql(name = "Status")]
+pub struct StatusObject {
+	/// Name of the status, e.g. `ready`.
+	kind: String,
+	reason: Option<String>,
+}
+
+impl From<&BranchStatus> for StatusObject {
+	fn from(status: &BranchStatus) -> Self {
+		let (kind, reason) = match status {
+			BranchStatus::Dirty => ("dirty", None),
+			BranchStatus::Ready => ("ready", None),
+			BranchStatus::Error { reason } => ("error", Some(reason)),
+			BranchStatus::Suspended { reason } => ("suspended", Some(reason)),
+		};
+		Self {
+			kind: kind.to_string(),
+			reason: reason.cloned(),
+		}
+	}
+}
+
+impl From<&PackageStatus> for StatusObject {
+	fn from(status: &PackageStatus) -> Self {
+		let (kind, reason) = match status {
+			PackageStatus::Dirty => ("dirty", None),
+			PackageStatus::Ready => ("ready", None),
+			PackageStatus::Error { reason } => ("error", Some(reason)),
+		};
+		Self {
+			kind: kind.to_string(),
+			reason: reason.cloned(),
+		}
+	}
+}
+
+pub struct BranchObject {
+	id: BranchRef,
+	info: ApiBranchInfo,
+}
+
+#[Object(name = "Branch")]
+impl BranchObject {
+	async fn name(&self) -> &str {
+		&self.info.name
+	}
+
+	async fn base(&self) -> Option<&str> {
+		self.info.base.as_deref()
+	}
+
+	async fn repository(&self) -> Option<&str> {
+		self.info.repository.as_deref()
+	}
+
+	async fn target_group(&self) -> Option<&str> {
+		self.info.target_group.as_deref()
+	}
+
+	async fn status(&self) -> StatusObject {
+		StatusObject::from(&self.info.status)
+	}
+
+	async fn priority(&self) -> u16 {
+		self.info.priority
+	}
+
+	async fn tracking_mode(&self) -> String {
+		variant_name(&self.info.tracking_mode)
+	}
+
+	/// Handling of packages removed upstream which have been built.
+	async fn removal_policy(&self) -> String {
+		variant_name(&self.info.removal_policy)
+	}
+
+	/// Commit which the branch has been synchronized to.
+	async fn commit(&self) -> Option<String> {
+		self.info.commit.map(|commit| commit.to_string())
+	}
+
+	/// Commit which the branch is pinned to.
+	async fn pinned_commit(&self) -> Option<String> {
+		self.info.pinned_commit.map(|commit| commit.to_string())
+	}
+
+	/// Count of packages in the branch.
+	async fn package_count(&self) -> u32 {
+		self.info.packages
+	}
+
+	async fn created_at(&self) -> OffsetDateTime {
+		self.info.created_at
+	}
+
+	async fn updated_at(&self) -> OffsetDateTime {
+		self.info.updated_at
+	}
+
+	/// Lists packages ordered by name, paginated by names.
+	async fn packages(
+		&self,
+		ctx: &Context<'_>,
+		after: Option<String>,
+		first: Option<i32>,
+	) -> Result<Connection<String, PackageObject>> {
+		let services = ctx.data::<CrayonServices>()?;
+		let size = page_size(first);
+		let mut rows = services
+			.package
+			.list_page(self.id, after.clone(), size + 1)
+			.await?;
+		let has_next = rows.len() > size;
+		rows.truncate(size);
+
+		let mut connection = Connection::new(after.is_some(), has_next);
+		for info in packages_into_api(services, rows, &self.info.name).await? {
+			connection
+				.edges
+				.push(Edge::new(info.name.clone(), PackageObject(info)));
+		}
+		Ok(connection)
+	}
+
+	/// Lists jobs in the queue working on the branch.
+	async fn jobs(&self, ctx: &Context<'_>) -> Result<Vec<JobObject>> {
+		let services = ctx.data::<CrayonServices>()?;
+		let branches = HashMap::from([(self.id, self.info.name.clone())]);
+		Ok(services
+			.job_queue
+			.list(1000)
+			.await?
+			.into_iter()
+			.filter(|job| job.command.subject_branch() == Some(self.id))
+			.map(|job| JobObject(queued_into_api(job, &branches)))
+			.collect())
+	}
+}
+
+pub struct PackageObject(ApiPackageInfo);
+
+#[Object(name = "Package")]
+impl PackageObject {
+	async fn name(&self) -> &str {
+		&self.0.name
+	}
+
+	async fn section(&self) -> &str {
+		&self.0.section
+	}
+
+	async fn status(&self) -> StatusObject {
+		StatusObject::from(&self.0.status)
+	}
+
+	async fn version(&self) -> Option<&str> {
+		self.0.version.as_deref()
+	}
+
+	async fn release(&self) -> u32 {
+		self.0.release
+	}
+
+	async fn epoch(&self) -> u32 {
+		self.0.epoch
+	}
+
+	async fn dependencies(&self) -> &[String] {
+		&self.0.dependencies
+	}
+
+	async fn upstream_version(&self) -> Option<&str> {
+		self.0.upstream_version.as_deref()
+	}
+
+	async fn outdated(&self) -> bool {
+		self.0.outdated
+	}
+
+	/// Reason of holding the package from automatic syncs and builds.
+	async fn hold_reason(&self) -> Option<&str> {
+		self.0.hold_reason.as_deref()
+	}
+
+	async fn created_at(&self) -> OffsetDateTime {
+		self.0.created_at
+	}
+
+	async fn updated_at(&self) -> OffsetDateTime {
+		self.0.updated_at
+	}
+
+	/// States of the package on each build target.
+	async fn targets(&self) -> Vec<PackageTargetObject<'_>> {
+		self.0.targets.iter().map(PackageTargetObject).collect()
+	}
+
+	/// Findings of the last static checks.
+	async fn findings(&self) -> Vec<FindingObject<'_>> {
+		self.0.findings.iter().map(FindingObject).collect()
+	}
+}
+
+pub struct PackageTargetObject<'a>(&'a ApiPackageTargetInfo);
+
+#[Object(name = "PackageTarget")]
+impl PackageTargetObject<'_> {
+	async fn target(&self) -> &str {
+		&self.0.target.name
+	}
+
+	async fn arch(&self) -> &str {
+		&self.0.target.arch
+	}
+
+	/// Whether the target is being retired.
+	async fn deprecated(&self) -> bool {
+		self.0.target.deprecated
+	}
+
+	async fn status(&self) -> String {
+		variant_name(&self.0.status)
+	}
+
+	/// ID of the last build job.
+	async fn last_build(&self) -> Option<Uuid> {
+		self.0.last_build
+	}
+
+	/// File names of artifacts of the last successful build.
+	async fn artifacts(&self) -> &[String] {
+		&self.0.artifacts
+	}
+
+	/// Reproducibility of the last successful build, if verified.
+	async fn repro_status(&self) -> Option<String> {
+		self.0
+			.repro
+			.as_ref()
+			.map(|repro| variant_name(&repro.status))
+	}
+
+	/// File names of artifacts which differ from the verifying build.
+	async fn repro_differing(&self) -> &[String] {
+		self.0
+			.repro
+			.as_ref()
+			.map_or(&[], |repro| repro.differing.as_slice())
+	}
+
+	async fn updated_at(&self) -> OffsetDateTime {
+		self.0.updated_at
+	}
+}
+
+pub struct FindingObject<'a>(&'a ApiPackageFinding);
+
+#[Object(name = "Finding")]
+impl FindingObject<'_> {
+	async fn check(&self) -> &str {
+		&self.0.check
+	}
+
+	async fn severity(&self) -> String {
+		variant_name(&self.0.severity)
+	}
+
+	async fn message(&self) -> &str {
+		&self.0.message
+	}
+}
+
+pub struct JobObject(ApiJobInfo);
+
+#[Object(name = "Job")]
+impl JobObject {
+	async fn id(&self) -> Uuid {
+		self.0.id
+	}
+
+	async fn kind(&self) -> &str {
+		&self.0.kind
+	}
+
+	/// Name of the branch which the job works on.
+	async fn branch(&self) -> Option<&str> {
+		self.0.branch.as_deref()
+	}
+
+	async fn status(&self) -> String {
+		variant_name(&self.0.status)
+	}
+
+	async fn priority(&self) -> u16 {
+		self.0.priority
+	}
+
+	async fn attempts(&self) -> u16 {
+		self.0.attempts
+	}
+
+	async fn started_at(&self) -> Option<OffsetDateTime> {
+		self.0.started_at
+	}
+
+	async fn finished_at(&self) -> Option<OffsetDateTime> {
+		self.0.finished_at
+	}
+
+	/// Error message of a failed job.
+	///
+	/// Messages may contain details of the deployment, so authentication is required.
+	#[graphql(guard = "AuthGuard")]
+	async fn error(&self) -> Option<&str> {
+		self.0.error.as_ref().map(|error| error.message.as_str())
+	}
+}
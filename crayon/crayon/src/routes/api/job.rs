@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+
+use axum::{
+	Json,
+	extract::{Path, Query, RawPathParams, State, rejection::RawPathParamsRejection},
+	http::{HeaderMap, StatusCode},
+};
+use diesel::{ExpressionMethods, QueryDsl};
+use fabricia_backend::{
+	db::schema::branch::dsl as branch_dsl,
+	job_history::{HistoryFilter, HistoryJob, Percentiles, SqlJobOutcome},
+	job_queue::{JobInfo, JobRef},
+	namespace::{DEFAULT_NAMESPACE_ID, NamespaceRef},
+};
+use fabricia_common_model::job::JobStatus;
+use fabricia_crayon_api_model::job::*;
+use serde::Deserialize;
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+use crate::CrayonServices;
+
+use super::{
+	branch::BranchPath,
+	error::{ApiError, ApiResult, OptionExt},
+	namespace::{self, Namespace},
+};
+
+/// Path parameters of job routes.
+///
+/// Other parameters, like the namespace, are ignored.
+#[derive(Debug, Deserialize)]
+pub struct JobPath {
+	id: JobRef,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListJobsQuery {
+	limit: Option<usize>,
+}
+
+/// Lists jobs of the namespace in the queue.
+///
+/// Jobs without subject branches are listed in the default namespace.
+pub async fn list_jobs(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Query(query): Query<ListJobsQuery>,
+) -> ApiResult<Json<Vec<ApiJobInfo>>> {
+	let jobs = services
+		.job_queue
+		.list(namespace, query.limit.unwrap_or(100).min(1000))
+		.await?;
+
+	let branches = branch_names(&services, namespace).await?;
+	let jobs = jobs
+		.into_iter()
+		.map(|job| queued_into_api(job, &branches))
+		.collect();
+	Ok(Json(jobs))
+}
+
+/// Lists jobs of a branch in the queue.
+pub async fn list_branch_jobs(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	Query(query): Query<ListJobsQuery>,
+) -> ApiResult<Json<Vec<ApiJobInfo>>> {
+	let branch = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let jobs = services
+		.job_queue
+		.list_branch(branch, query.limit.unwrap_or(100).min(1000))
+		.await?;
+
+	let branches = branch_names(&services, namespace).await?;
+	let jobs = jobs
+		.into_iter()
+		.map(|job| queued_into_api(job, &branches))
+		.collect();
+	Ok(Json(jobs))
+}
+
+/// Returns a job of the namespace in the queue, or a finished job with its error.
+pub async fn get_job(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(JobPath { id }): Path<JobPath>,
+) -> ApiResult<Json<ApiJobInfo>> {
+	let branches = branch_names(&services, namespace).await?;
+	let job = match services.job_queue.get(id).await? {
+		Some(job) => Some((
+			job.command.subject_branch(),
+			queued_into_api(job, &branches),
+		)),
+		None => services
+			.job_queue
+			.get_finished(id)
+			.await?
+			.map(|job| (job.subject_branch, finished_into_api(job, &branches))),
+	};
+	let job = job
+		.filter(|(branch, _)| in_namespace(namespace, *branch, &branches))
+		.or_api_error(StatusCode::NOT_FOUND, "job not found")?;
+	Ok(Json(job.1))
+}
+
+/// Lists finished jobs of the namespace matching the filter, latest first.
+///
+/// The namespace in the filter overrides the namespace of the route,
+/// and its token is required if it has one.
+pub async fn list_history(
+	State(services): State<CrayonServices>,
+	params: Result<RawPathParams, RawPathParamsRejection>,
+	headers: HeaderMap,
+	Query(filter): Query<ApiHistoryFilter>,
+	Query(query): Query<ListJobsQuery>,
+) -> ApiResult<Json<Vec<ApiJobInfo>>> {
+	let ApiHistoryFilter {
+		kind,
+		namespace: name,
+		branch,
+		outcome,
+		since,
+		until,
+		runner,
+	} = filter;
+	let name = name
+		.as_deref()
+		.unwrap_or_else(|| namespace::path_name(params.as_ref().ok()));
+	let namespace = namespace::authorize(&services, name, &headers).await?;
+	let branch = match branch {
+		Some(name) => {
+			let branch = services
+				.branch
+				.find_id(namespace, &name)
+				.await?
+				.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+			Some(branch)
+		}
+		None => None,
+	};
+	let outcome = match outcome {
+		None => None,
+		Some(JobStatus::Succeeded) => Some(SqlJobOutcome::Succeeded),
+		Some(JobStatus::Failed) => Some(SqlJobOutcome::Failed),
+		Some(_) => {
+			return Err(ApiError::CustomRef(
+				StatusCode::BAD_REQUEST,
+				"outcome must be succeeded or failed",
+			));
+		}
+	};
+	let utc = |time: OffsetDateTime| {
+		let time = time.to_offset(UtcOffset::UTC);
+		PrimitiveDateTime::new(time.date(), time.time())
+	};
+	let filter = HistoryFilter {
+		kind,
+		namespace: Some(namespace),
+		branch,
+		outcome,
+		finished_after: since.map(utc),
+		finished_before: until.map(utc),
+		runner,
+	};
+	let jobs = services
+		.job_queue
+		.list_finished(&filter, query.limit.unwrap_or(100).min(1000))
+		.await?;
+
+	let branches = branch_names(&services, namespace).await?;
+	let jobs = jobs
+		.into_iter()
+		.map(|job| finished_into_api(job, &branches))
+		.collect();
+	Ok(Json(jobs))
+}
+
+/// Returns count of jobs of the namespace in the queue by kind, priority band and branch.
+pub async fn get_job_stats(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+) -> ApiResult<Json<Vec<ApiJobQueueStats>>> {
+	Ok(Json(job_stats(&services, Some(namespace)).await?))
+}
+
+/// Returns percentiles of latencies of recently finished jobs by kind.
+pub async fn get_job_latency_stats(
+	State(services): State<CrayonServices>,
+) -> ApiResult<Json<Vec<ApiJobLatencyStats>>> {
+	Ok(Json(job_latency_stats(&services).await?))
+}
+
+pub(crate) async fn job_latency_stats(
+	services: &CrayonServices,
+) -> ApiResult<Vec<ApiJobLatencyStats>> {
+	let stats = services.backend()?.job_history.latency_stats().await?;
+	Ok(stats
+		.into_iter()
+		.map(|stats| ApiJobLatencyStats {
+			kind: stats.kind,
+			window_secs: stats.window.whole_seconds() as u64,
+			jobs: stats.jobs as u64,
+			wait: stats.wait.map(percentiles_into_api),
+			run: percentiles_into_api(stats.run),
+		})
+		.collect())
+}
+
+fn percentiles_into_api(percentiles: Percentiles) -> ApiPercentiles {
+	let ms = |duration: time::Duration| duration.whole_milliseconds().max(0) as u64;
+	ApiPercentiles {
+		p50_ms: ms(percentiles.p50),
+		p95_ms: ms(percentiles.p95),
+		p99_ms: ms(percentiles.p99),
+	}
+}
+
+/// Returns count of jobs in the queue, of all namespaces if `namespace` is [`None`].
+pub(crate) async fn job_stats(
+	services: &CrayonServices,
+	namespace: Option<NamespaceRef>,
+) -> ApiResult<Vec<ApiJobQueueStats>> {
+	let stats = services.job_queue.stats().await?;
+	let namespaces = services
+		.namespace
+		.list()
+		.await?
+		.into_iter()
+		.map(|namespace| (namespace.id, namespace.name))
+		.collect::<HashMap<_, _>>();
+	let mut db = services.backend()?.database.get().await?;
+	let branches = db
+		.load::<_, (i64, String, i64)>(branch_dsl::branch.select((
+			branch_dsl::id,
+			branch_dsl::name,
+			branch_dsl::namespace,
+		)))
+		.await?
+		.into_iter()
+		.map(|(id, name, namespace)| (id, (name, namespace)))
+		.collect::<HashMap<_, _>>();
+
+	Ok(stats
+		.into_iter()
+		.filter(|stats| match namespace {
+			None => true,
+			Some(namespace) => match stats.branch {
+				None => namespace == DEFAULT_NAMESPACE_ID,
+				Some(branch) => branches
+					.get(&branch)
+					.is_some_and(|(_, branch_namespace)| *branch_namespace == namespace),
+			},
+		})
+		.map(|stats| {
+			let branch = stats.branch.and_then(|branch| branches.get(&branch));
+			ApiJobQueueStats {
+				kind: stats.kind.to_string(),
+				priority_band: stats.priority_band,
+				namespace: branch.and_then(|(_, namespace)| namespaces.get(namespace).cloned()),
+				branch: branch.map(|(name, _)| name.clone()),
+				pending: stats.pending,
+				running: stats.running,
+			}
+		})
+		.collect())
+}
+
+/// Returns names of branches in a namespace by their IDs.
+pub(crate) async fn branch_names(
+	services: &CrayonServices,
+	namespace: NamespaceRef,
+) -> ApiResult<HashMap<i64, String>> {
+	let mut db = services.backend()?.database.get().await?;
+	Ok(db
+		.load::<_, (i64, String)>(
+			branch_dsl::branch
+				.filter(branch_dsl::namespace.eq(namespace))
+				.select((branch_dsl::id, branch_dsl::name)),
+		)
+		.await?
+		.into_iter()
+		.collect())
+}
+
+/// Returns whether a job of the subject branch is in a namespace,
+/// given [`branch_names`] of the namespace.
+///
+/// Jobs without subject branches are in the default namespace.
+pub(crate) fn in_namespace(
+	namespace: NamespaceRef,
+	branch: Option<i64>,
+	branches: &HashMap<i64, String>,
+) -> bool {
+	match branch {
+		None => namespace == DEFAULT_NAMESPACE_ID,
+		Some(branch) => branches.contains_key(&branch),
+	}
+}
+
+pub(crate) fn queued_into_api(job: JobInfo, branches: &HashMap<i64, String>) -> ApiJobInfo {
+	let started_at = job.started_at.map(|time| time.assume_utc());
+	ApiJobInfo {
+		id: job.id,
+		kind: job
+			.command
+			.serialize()
+			.map(|(kind, _)| kind.to_string())
+			.unwrap_or_default(),
+		branch: job
+			.command
+			.subject_branch()
+			.and_then(|branch| branches.get(&branch).cloned()),
+		status: match job.started_at {
+			None => JobStatus::Pending,
+			Some(_) => JobStatus::Running,
+		},
+		priority: job.priority,
+		attempts: job.attempts,
+		started_at,
+		estimated_duration_secs: job
+			.estimated_duration
+			.map(|duration| duration.whole_seconds().max(0) as u64),
+		eta: started_at
+			.zip(job.estimated_duration)
+			.map(|(time, duration)| time + duration),
+		finished_at: None,
+		error: None,
+		runner: None,
+	}
+}
+
+pub(crate) fn finished_into_api(job: HistoryJob, branches: &HashMap<i64, String>) -> ApiJobInfo {
+	ApiJobInfo {
+		id: job.id,
+		kind: job.kind,
+		branch: job
+			.subject_branch
+			.and_then(|branch| branches.get(&branch).cloned()),
+		status: match job.outcome {
+			SqlJobOutcome::Succeeded => JobStatus::Succeeded,
+			SqlJobOutcome::Failed => JobStatus::Failed,
+		},
+		priority: 0,
+		attempts: 0,
+		started_at: Some(job.started_at.assume_utc()),
+		estimated_duration_secs: None,
+		eta: None,
+		finished_at: Some(job.finished_at.assume_utc()),
+		error: job.error.map(|error| ApiJobError {
+			retryable: error.retryable,
+			message: error.message,
+			backtrace: error.backtrace,
+		}),
+		runner: job.runner,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use axum::{
+		body::Body,
+		http::{Request, StatusCode, header},
+	};
+	use fabricia_backend::namespace::{NamespaceInfo, hash_token};
+	use uuid::Uuid;
+
+	use crate::test::{MockApis, send};
+
+	fn request(uri: &str, token: Option<&str>) -> Request<Body> {
+		let mut request = Request::builder().uri(uri);
+		if let Some(token) = token {
+			request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+		}
+		request.body(Body::empty()).unwrap()
+	}
+
+	/// Mocks namespace `dev` with the token `dev-token`.
+	fn protected_namespace() -> MockApis {
+		let mut apis = MockApis::default();
+		apis.namespace
+			.expect_get_by_name()
+			.withf(|name| name == "dev")
+			.returning(|name| {
+				Ok(Some(NamespaceInfo {
+					id: 2,
+					name: name.to_string(),
+					token_hash: Some(hash_token("dev-token")),
+					max_branches: None,
+					max_queued_jobs: None,
+				}))
+			});
+		apis
+	}
+
+	#[tokio::test]
+	async fn test_history_protected_namespace() {
+		let mut apis = protected_namespace();
+		apis.branch
+			.expect_find_id()
+			.withf(|namespace, name| *namespace == 2 && name == "main")
+			.times(1)
+			.returning(|_, _| Ok(None));
+		let services = apis.into_services();
+
+		let uri = "/api/v0/history?namespace=dev&branch=main";
+		let response = send(services.clone(), request(uri, None)).await;
+		assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+		let response = send(services.clone(), request(uri, Some("other"))).await;
+		assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+		let response = send(services, request(uri, Some("dev-token"))).await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn test_jobs_protected_namespace() {
+		let mut apis = protected_namespace();
+		apis.job_queue.expect_list().never();
+		apis.job_queue.expect_get().never();
+		apis.job_queue.expect_stats().never();
+		apis.job_queue.expect_list_finished().never();
+		let services = apis.into_services();
+
+		let job = format!("/api/v0/ns/dev/job/{}", Uuid::now_v7());
+		for uri in [
+			"/api/v0/ns/dev/job",
+			"/api/v0/ns/dev/job/stats",
+			&job,
+			"/api/v0/ns/dev/history",
+		] {
+			let response = send(services.clone(), request(uri, None)).await;
+			assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{uri}");
+		}
+	}
+}
@@ -0,0 +1,95 @@
+//! Static checks of packages in branches.
+
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+	Json,
+	extract::{Path, State},
+	http::StatusCode,
+};
+use fabricia_backend::model::PkgFindingRow;
+use fabricia_crayon_api_model::{
+	branch::{ApiBranchLint, ApiBranchReport},
+	package::ApiPackageFinding,
+};
+
+use crate::CrayonServices;
+
+use super::{
+	auth::AuthRequired,
+	branch::BranchPath,
+	error::{ApiResult, OptionExt},
+	namespace::Namespace,
+};
+
+pub(super) fn finding_into_api(row: PkgFindingRow) -> ApiPackageFinding {
+	ApiPackageFinding {
+		check: row.kind,
+		severity: row.severity.into(),
+		message: row.message,
+	}
+}
+
+/// Enqueues static checks of all packages in a branch.
+pub async fn lint_branch(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+) -> ApiResult<(StatusCode, Json<ApiBranchLint>)> {
+	let branch = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let jobs = services.backend()?.lint.enqueue_branch(branch).await?;
+	Ok((
+		StatusCode::ACCEPTED,
+		Json(ApiBranchLint { jobs: jobs as u32 }),
+	))
+}
+
+/// Returns findings of the last static checks of packages in a branch,
+/// with held packages.
+pub async fn get_branch_report(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+) -> ApiResult<Json<ApiBranchReport>> {
+	let branch = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let packages = services.package.list(branch).await?;
+	let names = packages
+		.iter()
+		.map(|pkg| (pkg.id, pkg.name.as_str()))
+		.collect::<HashMap<_, _>>();
+	let held = packages
+		.iter()
+		.filter_map(|pkg| Some((pkg.name.clone(), pkg.hold_reason.clone()?)))
+		.collect();
+
+	let mut severities = BTreeMap::new();
+	let mut findings: BTreeMap<String, Vec<ApiPackageFinding>> = BTreeMap::new();
+	for row in services.backend()?.lint.list_branch(branch).await? {
+		// findings of removed packages are skipped
+		let Some(package) = names.get(&row.package) else {
+			continue;
+		};
+		let finding = finding_into_api(row);
+		*severities.entry(finding.severity).or_default() += 1;
+		findings
+			.entry(package.to_string())
+			.or_default()
+			.push(finding);
+	}
+	Ok(Json(ApiBranchReport {
+		branch: name,
+		packages: packages.len() as u32,
+		severities,
+		findings,
+		held,
+	}))
+}
@@ -0,0 +1,38 @@
+//! Prefetches of package sources to the mirror.
+
+use axum::{
+	Json,
+	extract::{Path, State},
+	http::StatusCode,
+};
+use fabricia_crayon_api_model::branch::ApiBranchPrefetch;
+
+use crate::CrayonServices;
+
+use super::{
+	auth::AuthRequired,
+	branch::BranchPath,
+	error::{ApiResult, OptionExt},
+	namespace::Namespace,
+};
+
+/// Enqueues prefetches of sources of all packages in a branch.
+///
+/// Nothing is enqueued if the source mirror is disabled.
+pub async fn prefetch_branch(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+) -> ApiResult<(StatusCode, Json<ApiBranchPrefetch>)> {
+	let branch = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let jobs = services.backend()?.mirror.enqueue_branch(branch).await?;
+	Ok((
+		StatusCode::ACCEPTED,
+		Json(ApiBranchPrefetch { jobs: jobs as u32 }),
+	))
+}
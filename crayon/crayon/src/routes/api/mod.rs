@@ -1,14 +1,104 @@
-use axum::{Router, routing::get};
+use axum::{
+	Router,
+	extract::DefaultBodyLimit,
+	middleware,
+	routing::{delete, get, post, put},
+};
 
-use crate::CrayonServices;
+use crate::{CrayonServices, config::WebConfig};
 
+use super::limits;
+
+mod admin;
 pub mod auth;
-mod branch;
+pub(crate) mod branch;
+mod changelog;
+mod compare;
+mod conditional;
 pub mod error;
+pub(crate) mod export;
+#[cfg(feature = "graphql")]
+mod graphql;
+pub(crate) mod job;
+mod lint;
+mod mirror;
+mod namespace;
+mod operation;
+mod package;
+mod problem;
+mod security;
+pub(crate) mod status;
+mod target;
+pub mod tx;
+mod upstream;
 
-pub fn api_router() -> Router<CrayonServices> {
+pub fn api_router(web: &WebConfig) -> Router<CrayonServices> {
+	let router = Router::new()
+		.route("/", get(status::get_version))
+		.route("/version", get(status::get_version))
+		.route("/status", get(status::get_status))
+		.merge(namespaced_router())
+		.nest("/ns/{ns}", namespaced_router())
+		.route("/job/stats/latency", get(job::get_job_latency_stats))
+		.route("/operation/{id}", get(operation::get_operation))
+		.route("/target/{name}/status", get(target::get_target_status))
+		.route(
+			"/pkg/{id}/target/{target}/diff",
+			get(package::get_artifact_diff),
+		)
+		.route("/admin/queue", get(admin::get_queue_state))
+		.route("/admin/queue/pause", post(admin::pause_queue))
+		.route("/admin/queue/resume", post(admin::resume_queue))
+		.route("/admin/maintenance", get(admin::get_maintenance_state))
+		.route("/admin/maintenance/enable", post(admin::enable_maintenance))
+		.route(
+			"/admin/maintenance/disable",
+			post(admin::disable_maintenance),
+		)
+		.route("/admin/scale-hint", get(admin::get_scale_hint))
+		.route("/admin/instances", get(admin::list_instances))
+		.route("/admin/backup", post(admin::export_backup))
+		.route("/admin/security/ingest", post(security::ingest_advisories))
+		.route("/admin/namespace", get(admin::list_namespaces))
+		.route(
+			"/admin/namespace/{name}",
+			put(admin::new_namespace).delete(admin::delete_namespace),
+		)
+		.route(
+			"/admin/namespace/{name}/token",
+			post(admin::rotate_namespace_token),
+		)
+		.route("/admin/template", get(admin::list_branch_templates))
+		.route(
+			"/admin/template/{name}",
+			put(admin::put_branch_template).delete(admin::delete_branch_template),
+		);
+	#[cfg(feature = "graphql")]
+	let router = router.route("/graphql", post(graphql::graphql_handler));
+	let router = router
+		.layer(DefaultBodyLimit::max(web.max_body_size))
+		.layer(middleware::from_fn_with_state(
+			web.max_body_size,
+			limits::body_limit_layer,
+		));
+	// uploads, added after the default body limit
+	let router = router.route(
+		"/admin/import",
+		post(admin::import_branches)
+			.layer(DefaultBodyLimit::max(web.max_upload_size))
+			.layer(middleware::from_fn_with_state(
+				web.max_upload_size,
+				limits::body_limit_layer,
+			)),
+	);
+	router.layer(middleware::from_fn(tx::transaction_layer))
+}
+
+/// Routes served in namespaces.
+///
+/// Without the `/ns/{ns}` prefix, the default namespace is used.
+fn namespaced_router() -> Router<CrayonServices> {
 	Router::new()
-		.route("/", get(handler))
 		.route("/branch", get(branch::list_branches))
 		.route(
 			"/branch/{branch}",
@@ -17,8 +107,52 @@ pub fn api_router() -> Router<CrayonServices> {
 				.patch(branch::update_branch_config)
 				.delete(branch::delete_branch),
 		)
-}
-
-async fn handler() -> &'static str {
-	concat!("Fabricia Crayon ", env!("CARGO_PKG_VERSION"))
+		.route("/branch/{branch}/sync", post(branch::sync_branch))
+		.route("/branch/{branch}/pin", delete(branch::unpin_branch))
+		.route(
+			"/branch/{branch}/acl",
+			get(branch::get_branch_acl).put(branch::update_branch_acl),
+		)
+		.route(
+			"/branch/{branch}/timeline",
+			get(branch::get_branch_timeline),
+		)
+		.route(
+			"/branch/{branch}/changelog",
+			get(changelog::get_branch_changelog),
+		)
+		.route("/branch/{branch}/export", get(export::export_branch))
+		.route("/branch/{branch}/lint", post(lint::lint_branch))
+		.route("/branch/{branch}/report", get(lint::get_branch_report))
+		.route("/branch/{branch}/prefetch", post(mirror::prefetch_branch))
+		.route("/branch/{branch}/stats", get(upstream::get_branch_stats))
+		.route("/branch/{branch}/job", get(job::list_branch_jobs))
+		.route(
+			"/branch/{branch}/problems",
+			get(problem::get_branch_problems),
+		)
+		.route(
+			"/branch/{branch}/compare/{other}",
+			get(compare::compare_branches),
+		)
+		.route("/branch/{branch}/pkg", get(package::list_packages))
+		.route("/branch/{branch}/pkg/{name}", get(package::get_package))
+		.route(
+			"/branch/{branch}/pkg/{name}/events",
+			get(package::list_package_events),
+		)
+		.route(
+			"/branch/{branch}/pkg/{name}/override",
+			post(package::override_package),
+		)
+		.route(
+			"/branch/{branch}/pkg/{name}/hold",
+			put(package::hold_package).delete(package::release_package),
+		)
+		.route("/job", get(job::list_jobs))
+		.route("/job/stats", get(job::get_job_stats))
+		.route("/job/{id}", get(job::get_job))
+		.route("/history", get(job::list_history))
+		.route("/branch-graph", get(branch::get_branch_graph))
+		.route("/security", get(security::list_affected_packages))
 }
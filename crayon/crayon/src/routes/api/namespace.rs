@@ -0,0 +1,63 @@
+//! Namespaces of API routes.
+//!
+//! Branch routes are served both under `/ns/{ns}` and at the root,
+//! where the default namespace is used.
+
+use axum::{
+	extract::{FromRequestParts, RawPathParams},
+	http::{HeaderMap, StatusCode, request::Parts},
+};
+use fabricia_backend::namespace::{DEFAULT_NAMESPACE, NamespaceRef};
+
+use crate::CrayonServices;
+
+use super::{
+	auth::bearer_token,
+	error::{ApiError, ApiResult, OptionExt},
+};
+
+/// Namespace of the request, from the `ns` path parameter.
+///
+/// If the namespace has a token, the request must carry it as the bearer token.
+pub struct Namespace(pub NamespaceRef);
+
+impl FromRequestParts<CrayonServices> for Namespace {
+	type Rejection = ApiError;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		services: &CrayonServices,
+	) -> Result<Self, Self::Rejection> {
+		let params = RawPathParams::from_request_parts(parts, services)
+			.await
+			.ok();
+		let name = path_name(params.as_ref());
+		Ok(Self(authorize(services, name, &parts.headers).await?))
+	}
+}
+
+/// Returns the namespace in the `ns` path parameter, or the default namespace.
+pub fn path_name(params: Option<&RawPathParams>) -> &str {
+	params
+		.and_then(|params| params.iter().find(|(key, _)| *key == "ns"))
+		.map(|(_, value)| value)
+		.unwrap_or(DEFAULT_NAMESPACE)
+}
+
+/// Finds a namespace by name, requiring its token as the bearer token if it has one.
+pub async fn authorize(
+	services: &CrayonServices,
+	name: &str,
+	headers: &HeaderMap,
+) -> ApiResult<NamespaceRef> {
+	let namespace = services
+		.namespace
+		.get_by_name(name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "namespace not found")?;
+	if namespace.authorize(bearer_token(headers)) {
+		Ok(namespace.id)
+	} else {
+		Err(ApiError::AuthRequired)
+	}
+}
@@ -0,0 +1,76 @@
+use axum::{
+	Json,
+	extract::{Path, State},
+	http::StatusCode,
+};
+use fabricia_backend::{
+	db::BoxedSqlConn,
+	operation::{
+		OperationInfo, OperationJobState, OperationRef, OperationService, OperationStatus,
+	},
+};
+use fabricia_crayon_api_model::operation::*;
+use uuid::Uuid;
+
+use crate::CrayonServices;
+
+use super::error::{ApiResult, OptionExt};
+
+pub async fn get_operation(
+	State(services): State<CrayonServices>,
+	Path(id): Path<Uuid>,
+) -> ApiResult<Json<ApiOperation>> {
+	Ok(Json(get_api_operation(&services, id).await?))
+}
+
+pub(super) async fn get_api_operation(
+	services: &CrayonServices,
+	id: OperationRef,
+) -> ApiResult<ApiOperation> {
+	let info = services.backend()?.operation.get(id).await?;
+	into_api_operation(info)
+}
+
+/// Like [`get_api_operation`], but reads the operation in a request transaction.
+pub(super) async fn get_api_operation_in(
+	conn: &mut BoxedSqlConn,
+	id: OperationRef,
+) -> ApiResult<ApiOperation> {
+	let info = OperationService::get_in(conn, id).await?;
+	into_api_operation(info)
+}
+
+fn into_api_operation(info: Option<OperationInfo>) -> ApiResult<ApiOperation> {
+	let info = info.or_api_error(StatusCode::NOT_FOUND, "operation not found")?;
+
+	let status = match info.status() {
+		OperationStatus::Pending => ApiOperationStatus::Pending,
+		OperationStatus::Succeeded => ApiOperationStatus::Succeeded,
+		OperationStatus::Failed => ApiOperationStatus::Failed,
+	};
+	let jobs = info
+		.jobs
+		.into_iter()
+		.map(|job| {
+			let state = job.state.status();
+			let error = match job.state {
+				OperationJobState::Failed { error } => error,
+				_ => None,
+			};
+			ApiOperationJob {
+				id: job.id,
+				kind: job.kind,
+				state,
+				error,
+			}
+		})
+		.collect();
+	Ok(ApiOperation {
+		id: info.id,
+		kind: info.kind,
+		status,
+		branch: info.branch,
+		created_at: info.created_at.assume_utc(),
+		jobs,
+	})
+}
@@ -0,0 +1,292 @@
+//! Packages of branches.
+
+use axum::{
+	Json,
+	extract::{Path, Query, State},
+	http::{HeaderMap, StatusCode},
+	response::Response,
+};
+use fabricia_backend::{
+	artifact_diff,
+	namespace::NamespaceRef,
+	package::{SqlPackageStatus, StateOverride},
+	target::TargetInfo,
+};
+use fabricia_crayon_api_model::package::{
+	ApiArtifactDiff, ApiArtifactFileDelta, ApiPackageHold, ApiPackageInfo, ApiPackageOverride,
+	ApiPackageStatusEvent, ApiStateOverride,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::CrayonServices;
+
+use super::{
+	auth::{AuthRequired, Caller},
+	branch::BranchPath,
+	conditional::tagged_json,
+	error::{ApiResult, OptionExt},
+	export::packages_into_api,
+	namespace::Namespace,
+};
+
+/// Path parameters of package routes.
+///
+/// Other parameters, like the namespace, are ignored.
+#[derive(Debug, Deserialize)]
+pub struct PackagePath {
+	pub branch: String,
+	pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListPackagesQuery {
+	/// Name of the last package of the previous page.
+	after: Option<String>,
+	limit: Option<usize>,
+}
+
+/// Lists packages of a branch with their states on build targets, ordered by name.
+///
+/// Large branches are listed page by page, with the name of the last package
+/// as `after` of the next page.
+pub async fn list_packages(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	Query(query): Query<ListPackagesQuery>,
+) -> ApiResult<Json<Vec<ApiPackageInfo>>> {
+	let branch = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let packages = services
+		.package
+		.list_page(branch, query.after, query.limit.unwrap_or(100).min(1000))
+		.await?;
+	Ok(Json(packages_into_api(&services, packages, &name).await?))
+}
+
+/// Returns a package with its states on build targets.
+pub async fn get_package(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(PackagePath {
+		branch: branch_name,
+		name,
+	}): Path<PackagePath>,
+	headers: HeaderMap,
+) -> ApiResult<Response> {
+	let branch = services
+		.branch
+		.find_id(namespace, &branch_name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let package = services
+		.package
+		.find(branch, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "package not found")?;
+	let package = packages_into_api(&services, vec![package], &branch_name)
+		.await?
+		.pop()
+		.or_api_error(StatusCode::NOT_FOUND, "package not found")?;
+	tagged_json(&headers, &package)
+}
+
+/// Holds a package from automatic syncs and builds, or releases it if
+/// `reason` is [`None`].
+async fn set_hold(
+	services: &CrayonServices,
+	namespace: NamespaceRef,
+	PackagePath { branch, name }: PackagePath,
+	reason: Option<String>,
+) -> ApiResult<Json<ApiPackageInfo>> {
+	let id = services
+		.branch
+		.find_id(namespace, &branch)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let package = services
+		.package
+		.set_hold(id, &name, reason)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "package not found")?;
+	let package = packages_into_api(services, vec![package], &branch)
+		.await?
+		.pop()
+		.or_api_error(StatusCode::NOT_FOUND, "package not found")?;
+	Ok(Json(package))
+}
+
+/// Holds a package from automatic syncs and builds, e.g. while bisecting
+/// an issue manually.
+pub async fn hold_package(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(path): Path<PackagePath>,
+	Json(hold): Json<ApiPackageHold>,
+) -> ApiResult<Json<ApiPackageInfo>> {
+	set_hold(&services, namespace, path, Some(hold.reason)).await
+}
+
+/// Releases a held package, so that it is synchronized and built again.
+pub async fn release_package(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(path): Path<PackagePath>,
+) -> ApiResult<Json<ApiPackageInfo>> {
+	set_hold(&services, namespace, path, None).await
+}
+
+/// Forces a package or its state on a target into a state, e.g. marks a flaky
+/// build as ready after manual verification.
+///
+/// The override is recorded in the audit log and status events of the package.
+pub async fn override_package(
+	AuthRequired: AuthRequired,
+	Caller(principal): Caller,
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(PackagePath { branch, name }): Path<PackagePath>,
+	Json(request): Json<ApiPackageOverride>,
+) -> ApiResult<Json<ApiPackageInfo>> {
+	let id = services
+		.branch
+		.find_id(namespace, &branch)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let state = match request.state {
+		ApiStateOverride::Package { status } => {
+			let (status, message) = SqlPackageStatus::from_common(status);
+			StateOverride::Package(status, message)
+		}
+		ApiStateOverride::Target { target, status } => {
+			let target = services
+				.backend()?
+				.target
+				.find(&target)
+				.or_api_error(StatusCode::NOT_FOUND, "target not found")?;
+			StateOverride::Target(target.id, status.into())
+		}
+	};
+	let package = services
+		.package
+		.override_state(id, &name, &state, &request.reason, &principal)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "package not found on the target")?;
+	let package = packages_into_api(&services, vec![package], &branch)
+		.await?
+		.pop()
+		.or_api_error(StatusCode::NOT_FOUND, "package not found")?;
+	Ok(Json(package))
+}
+
+/// Lists status transitions of a package, oldest first.
+pub async fn list_package_events(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(PackagePath { branch, name }): Path<PackagePath>,
+	headers: HeaderMap,
+) -> ApiResult<Response> {
+	let branch = services
+		.branch
+		.find_id(namespace, &branch)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let package = services
+		.package
+		.find(branch, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "package not found")?;
+	let events = services
+		.package
+		.events(package.id.0)
+		.await?
+		.into_iter()
+		.map(|event| ApiPackageStatusEvent {
+			status: event.status.into_common(event.message),
+			actor: event.actor,
+			created_at: event.created_at.assume_utc(),
+		})
+		.collect::<Vec<ApiPackageStatusEvent>>();
+	tagged_json(&headers, &events)
+}
+
+/// Path parameters of artifact diff routes.
+#[derive(Debug, Deserialize)]
+pub struct ArtifactDiffPath {
+	/// ID of the package.
+	pub id: Uuid,
+	/// Name of the target.
+	pub target: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArtifactDiffQuery {
+	/// ID of the build job to compare against.
+	pub against: Uuid,
+}
+
+/// Compares artifacts of the last build of a package on a target against
+/// another build, see [`fabricia_backend::artifact_diff`].
+///
+/// Responds with `202 Accepted` while the comparison is running.
+pub async fn get_artifact_diff(
+	State(services): State<CrayonServices>,
+	Path(ArtifactDiffPath { id, target }): Path<ArtifactDiffPath>,
+	Query(ArtifactDiffQuery { against }): Query<ArtifactDiffQuery>,
+) -> ApiResult<(StatusCode, Json<ApiArtifactDiff>)> {
+	let target_id = TargetInfo::make_id(&target) as i64;
+	let row = services
+		.package
+		.list_targets(vec![id])
+		.await?
+		.into_iter()
+		.find(|row| row.target == target_id)
+		.or_api_error(StatusCode::NOT_FOUND, "package target not found")?;
+	let build = row
+		.target_data()?
+		.last_build
+		.or_api_error(StatusCode::NOT_FOUND, "package target has not been built")?;
+	let artifact_diff = &services.backend()?.artifact_diff;
+	if !artifact_diff.is_enabled() {
+		return Err(artifact_diff::ArtifactDiffError::Disabled.into());
+	}
+	artifact_diff
+		.artifacts(against)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "build not found")?;
+
+	let row = artifact_diff.get_or_enqueue(build, against).await?;
+	let pending = row.result.is_none() && row.error.is_none();
+	let diff = row.diff()?.unwrap_or_default();
+	let status = match pending {
+		true => StatusCode::ACCEPTED,
+		false => StatusCode::OK,
+	};
+	Ok((
+		status,
+		Json(ApiArtifactDiff {
+			build,
+			against,
+			pending,
+			error: row.error,
+			files: diff
+				.files
+				.into_iter()
+				.map(|file| ApiArtifactFileDelta {
+					path: file.path,
+					old_size: file.old_size,
+					new_size: file.new_size,
+				})
+				.collect(),
+			size_delta: diff.size_delta,
+			sonames_added: diff.sonames_added,
+			sonames_removed: diff.sonames_removed,
+		}),
+	))
+}
@@ -0,0 +1,84 @@
+//! Packages failing to build in branches.
+
+use axum::{
+	extract::{Path, State},
+	http::{HeaderMap, StatusCode},
+	response::Response,
+};
+use fabricia_backend::problem::ProblemScope;
+use fabricia_crayon_api_model::branch::{
+	ApiBranchProblems, ApiPackageProblem, ApiPackageTargetState,
+};
+
+use crate::CrayonServices;
+
+use super::{
+	branch::BranchPath,
+	conditional::tagged_json,
+	error::{ApiResult, OptionExt},
+	namespace::Namespace,
+};
+
+/// Lists packages of a branch failing on all of their targets, and those
+/// failing on some targets only.
+pub async fn get_branch_problems(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	headers: HeaderMap,
+) -> ApiResult<Response> {
+	let branch = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let targets = &services.backend()?.target;
+	let problems = services.package.problems(branch).await?;
+
+	let mut response = ApiBranchProblems {
+		branch: name,
+		everywhere: Vec::new(),
+		arch_specific: Vec::new(),
+		arch_specific_by_target: Default::default(),
+	};
+	for mut problem in problems {
+		// targets removed from the configuration are skipped
+		problem
+			.targets
+			.retain(|target, _| targets.get(*target).is_some());
+		let Some(scope) = problem.scope() else {
+			continue;
+		};
+		let target_name = |id| targets.get(id).expect("retained above").name.to_string();
+		let mut failed = problem.failed().map(target_name).collect::<Vec<_>>();
+		failed.sort();
+		let mut target_states = problem
+			.targets
+			.iter()
+			.map(|(id, state)| ApiPackageTargetState {
+				target: target_name(*id),
+				status: (*state).into(),
+			})
+			.collect::<Vec<_>>();
+		target_states.sort_by(|a, b| a.target.cmp(&b.target));
+
+		let problem = ApiPackageProblem {
+			name: problem.name,
+			failed,
+			targets: target_states,
+		};
+		match scope {
+			ProblemScope::Everywhere => response.everywhere.push(problem),
+			ProblemScope::ArchSpecific => {
+				for target in &problem.failed {
+					*response
+						.arch_specific_by_target
+						.entry(target.clone())
+						.or_default() += 1;
+				}
+				response.arch_specific.push(problem);
+			}
+		}
+	}
+	tagged_json(&headers, &response)
+}
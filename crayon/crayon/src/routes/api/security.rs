@@ -0,0 +1,69 @@
+//! Security advisories of tracked packages.
+
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{Json, extract::State, http::StatusCode};
+use diesel::{ExpressionMethods, QueryDsl};
+use fabricia_backend::{db::schema::branch::dsl, job_queue::JobCommand};
+use fabricia_crayon_api_model::security::{ApiAdvisory, ApiAffectedPackage};
+
+use crate::CrayonServices;
+
+use super::{auth::AuthRequired, error::ApiResult, namespace::Namespace};
+
+/// Lists packages in branches of the namespace affected by ingested advisories.
+pub async fn list_affected_packages(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+) -> ApiResult<Json<Vec<ApiAffectedPackage>>> {
+	let mut db = services.backend()?.database.get().await?;
+	let branches = db
+		.load::<_, (i64, String)>(
+			dsl::branch
+				.filter(dsl::namespace.eq(namespace))
+				.select((dsl::id, dsl::name)),
+		)
+		.await?;
+	drop(db);
+	let names = branches.iter().cloned().collect::<HashMap<_, _>>();
+	let ids = branches.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+
+	let mut packages = BTreeMap::new();
+	for affected in services.backend()?.security.affected(&ids).await? {
+		let Some(branch) = names.get(&affected.branch) else {
+			continue;
+		};
+		let advisory = &affected.advisory;
+		packages
+			.entry((branch.clone(), affected.name.clone()))
+			.or_insert_with(|| ApiAffectedPackage {
+				branch: branch.clone(),
+				package: affected.name,
+				version: affected.version,
+				advisories: vec![],
+			})
+			.advisories
+			.push(ApiAdvisory {
+				name: advisory.name.clone(),
+				source: advisory.source.clone(),
+				summary: advisory.summary.clone(),
+			});
+	}
+	Ok(Json(packages.into_values().collect()))
+}
+
+/// Enqueues ingestion of advisory feeds.
+///
+/// Affected packages of all branches are flagged after ingestion.
+pub async fn ingest_advisories(
+	AuthRequired: AuthRequired,
+	State(services): State<CrayonServices>,
+) -> ApiResult<(StatusCode, &'static str)> {
+	let mut db = services.backend()?.database.get().await?;
+	services
+		.backend()?
+		.job_queue
+		.enqueue(&mut db, JobCommand::IngestAdvisories)
+		.await?;
+	Ok((StatusCode::ACCEPTED, "advisory ingestion enqueued"))
+}
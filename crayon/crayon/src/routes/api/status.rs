@@ -0,0 +1,35 @@
+use axum::{Json, extract::State};
+use fabricia_backend::db;
+use fabricia_crayon_api_model::status::{ApiStatus, ApiVersion};
+
+use crate::{CrayonServices, build_info};
+
+use super::error::ApiResult;
+
+/// Returns the status of the service, including the maintenance mode.
+pub async fn get_status(State(services): State<CrayonServices>) -> ApiResult<Json<ApiStatus>> {
+	let message = services.backend()?.maintenance.message().await?;
+	Ok(Json(ApiStatus {
+		version: build_info::VERSION.to_string(),
+		maintenance: message.is_some(),
+		maintenance_message: message,
+	}))
+}
+
+/// Returns the version and build of this instance, with the migration level
+/// of the database.
+pub async fn get_version(State(services): State<CrayonServices>) -> ApiResult<Json<ApiVersion>> {
+	let mut conn = services.backend()?.database.get().await?;
+	let migration_level = db::migration_level(&mut conn).await?;
+	Ok(Json(ApiVersion {
+		version: build_info::VERSION.to_string(),
+		commit: build_info::COMMIT.map(str::to_string),
+		build_date: build_info::build_date(),
+		features: build_info::features()
+			.into_iter()
+			.map(str::to_string)
+			.collect(),
+		sql_backend: conn.backend_name().to_string(),
+		migration_level,
+	}))
+}
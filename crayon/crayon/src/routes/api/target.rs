@@ -0,0 +1,88 @@
+//! Health of build targets.
+
+use std::collections::HashMap;
+
+use axum::{
+	Json,
+	extract::{Path, Query, State},
+	http::StatusCode,
+};
+use diesel::QueryDsl;
+use fabricia_backend::db::schema::branch::dsl as branch_dsl;
+use fabricia_crayon_api_model::target::{ApiTargetFailure, ApiTargetStatus};
+use serde::Deserialize;
+
+use crate::CrayonServices;
+
+use super::error::{ApiResult, OptionExt};
+
+#[derive(Debug, Deserialize)]
+pub struct TargetStatusQuery {
+	/// The maximum count of recent failures.
+	failures: Option<usize>,
+}
+
+/// Returns the health of a target across branches of all namespaces.
+pub async fn get_target_status(
+	State(services): State<CrayonServices>,
+	Path(name): Path<String>,
+	Query(query): Query<TargetStatusQuery>,
+) -> ApiResult<Json<ApiTargetStatus>> {
+	let target = services
+		.backend()?
+		.target
+		.find(&name)
+		.or_api_error(StatusCode::NOT_FOUND, "target not found")?
+		.clone();
+	let status = services
+		.package
+		.target_status(target.id, query.failures.unwrap_or(20).min(100))
+		.await?;
+
+	let namespaces = services
+		.namespace
+		.list()
+		.await?
+		.into_iter()
+		.map(|namespace| (namespace.id, namespace.name))
+		.collect::<HashMap<_, _>>();
+	let mut db = services.backend()?.database.get().await?;
+	let branches = db
+		.load::<_, (i64, String, i64)>(branch_dsl::branch.select((
+			branch_dsl::id,
+			branch_dsl::name,
+			branch_dsl::namespace,
+		)))
+		.await?
+		.into_iter()
+		.map(|(id, name, namespace)| (id, (name, namespace)))
+		.collect::<HashMap<_, _>>();
+
+	let recent_failures = status
+		.failures
+		.into_iter()
+		// branches may be deleted concurrently
+		.filter_map(|failure| {
+			let (branch, namespace) = branches.get(&failure.branch)?;
+			Some(ApiTargetFailure {
+				namespace: namespaces.get(namespace)?.clone(),
+				branch: branch.clone(),
+				package: failure.package,
+				status: failure.status.into(),
+				last_build: failure.last_build,
+				updated_at: failure.updated_at.assume_utc(),
+			})
+		})
+		.collect();
+	Ok(Json(ApiTargetStatus {
+		target: target.as_ref().into(),
+		dirty: status.dirty,
+		ready: status.ready,
+		build_failed: status.build_failed,
+		error: status.error,
+		recent_failures,
+		avg_build_duration_secs: status
+			.avg_build_duration
+			.map(|duration| duration.whole_seconds().max(0) as u64),
+	}))
+}
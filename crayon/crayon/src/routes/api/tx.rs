@@ -0,0 +1,108 @@
+//! Per-request database transactions.
+
+use std::{
+	ops::{Deref, DerefMut},
+	sync::{Arc, Mutex},
+};
+
+use axum::{
+	extract::{FromRequestParts, Request},
+	http::{StatusCode, request::Parts},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use fabricia_backend::db::{BoxedSqlConn, service::SqlConnRef};
+use tracing::error;
+
+use crate::CrayonServices;
+
+use super::error::ApiError;
+
+/// Slot which a [`Tx`] returns its connection to when dropped.
+#[derive(Clone, Default)]
+struct TxSlot(Arc<Mutex<Option<SqlConnRef>>>);
+
+/// A database connection in a transaction spanning the whole request.
+///
+/// The transaction is committed by [`transaction_layer`] if the response is successful,
+/// and rolled back otherwise.
+pub struct Tx {
+	conn: Option<SqlConnRef>,
+	slot: TxSlot,
+}
+
+impl FromRequestParts<CrayonServices> for Tx {
+	type Rejection = ApiError;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		services: &CrayonServices,
+	) -> Result<Self, Self::Rejection> {
+		let slot = parts
+			.extensions
+			.get::<TxSlot>()
+			.cloned()
+			.ok_or(ApiError::CustomRef(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				"transaction layer is not installed",
+			))?;
+		if slot.0.lock().unwrap().is_some() {
+			return Err(ApiError::CustomRef(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				"transaction has been extracted",
+			));
+		}
+
+		let mut conn = services.backend()?.database.get().await?;
+		conn.begin_transaction().await?;
+		Ok(Self {
+			conn: Some(conn),
+			slot,
+		})
+	}
+}
+
+impl Deref for Tx {
+	type Target = BoxedSqlConn;
+
+	fn deref(&self) -> &Self::Target {
+		self.conn.as_ref().unwrap()
+	}
+}
+
+impl DerefMut for Tx {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.conn.as_mut().unwrap()
+	}
+}
+
+impl Drop for Tx {
+	fn drop(&mut self) {
+		if let Some(conn) = self.conn.take() {
+			*self.slot.0.lock().unwrap() = Some(conn);
+		}
+	}
+}
+
+/// Middleware finishing transactions extracted by [`Tx`].
+pub async fn transaction_layer(mut request: Request, next: Next) -> Response {
+	let slot = TxSlot::default();
+	request.extensions_mut().insert(slot.clone());
+
+	let response = next.run(request).await;
+
+	let conn = slot.0.lock().unwrap().take();
+	let Some(mut conn) = conn else {
+		return response;
+	};
+	let status = response.status();
+	if status.is_success() || status.is_redirection() {
+		if let Err(error) = conn.commit_transaction().await {
+			error!(?error, "failed to commit request transaction");
+			return ApiError::from(error).into_response();
+		}
+	} else if let Err(error) = conn.rollback_transaction().await {
+		error!(?error, "failed to roll back request transaction");
+	}
+	response
+}
@@ -0,0 +1,58 @@
+//! Package counts and upstream versions of branches.
+
+use axum::{
+	extract::{Path, State},
+	http::{HeaderMap, StatusCode},
+	response::Response,
+};
+use fabricia_backend::{package::SqlPackageStatus, upstream::is_outdated};
+use fabricia_crayon_api_model::branch::ApiBranchStats;
+
+use crate::CrayonServices;
+
+use super::{
+	branch::BranchPath,
+	conditional::tagged_json,
+	error::{ApiResult, OptionExt},
+	namespace::Namespace,
+};
+
+/// Returns counts of packages in a branch by status.
+pub async fn get_branch_stats(
+	State(services): State<CrayonServices>,
+	Namespace(namespace): Namespace,
+	Path(BranchPath { branch: name }): Path<BranchPath>,
+	headers: HeaderMap,
+) -> ApiResult<Response> {
+	let branch = services
+		.branch
+		.find_id(namespace, &name)
+		.await?
+		.or_api_error(StatusCode::NOT_FOUND, "branch not found")?;
+	let packages = services.package.list(branch).await?;
+	let names = packages.iter().map(|pkg| pkg.name.clone()).collect();
+	let upstream = services.backend()?.upstream.versions(names).await?;
+
+	let mut stats = ApiBranchStats {
+		branch: name,
+		packages: packages.len() as u32,
+		ready: 0,
+		dirty: 0,
+		error: 0,
+		outdated: 0,
+	};
+	for pkg in packages {
+		match pkg.status {
+			SqlPackageStatus::Dirty => stats.dirty += 1,
+			SqlPackageStatus::Ready => stats.ready += 1,
+			SqlPackageStatus::Error => stats.error += 1,
+		}
+		let Some(upstream) = upstream.get(&pkg.name) else {
+			continue;
+		};
+		if is_outdated(&pkg.pkg_data()?.version, upstream) {
+			stats.outdated += 1;
+		}
+	}
+	tagged_json(&headers, &stats)
+}
@@ -0,0 +1,58 @@
+//! Limits of durations and body sizes of requests.
+
+use std::time::Duration;
+
+use axum::{
+	extract::{Request, State},
+	http::{StatusCode, header::CONTENT_LENGTH},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+
+use super::api::error::ApiError;
+
+/// Middleware responding with 408 to requests not handled within the timeout.
+///
+/// Only the time until the response starts is limited, so that streamed
+/// bodies of large responses are not cut.
+pub async fn timeout_layer(
+	State(timeout): State<Duration>,
+	request: Request,
+	next: Next,
+) -> Response {
+	match tokio::time::timeout(timeout, next.run(request)).await {
+		Ok(response) => response,
+		Err(_) => {
+			ApiError::CustomRef(StatusCode::REQUEST_TIMEOUT, "request timed out").into_response()
+		}
+	}
+}
+
+/// Middleware responding with 413 to requests with bodies larger than the limit.
+///
+/// Requests are rejected early by `Content-Length`. Chunked bodies are limited
+/// by [`axum::extract::DefaultBodyLimit`] of the same size, whose rejections are
+/// replaced here with the same error.
+pub async fn body_limit_layer(
+	State(limit): State<usize>,
+	request: Request,
+	next: Next,
+) -> Response {
+	let length = request
+		.headers()
+		.get(CONTENT_LENGTH)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.parse::<u64>().ok());
+	if length.is_some_and(|length| length > limit as u64) {
+		return too_large();
+	}
+	let response = next.run(request).await;
+	if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+		return too_large();
+	}
+	response
+}
+
+fn too_large() -> Response {
+	ApiError::CustomRef(StatusCode::PAYLOAD_TOO_LARGE, "request body is too large").into_response()
+}
@@ -0,0 +1,66 @@
+//! Rejection of mutations in maintenance mode.
+
+use axum::{
+	extract::{Request, State},
+	http::{HeaderName, HeaderValue, Method, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use tracing::warn;
+
+use crate::CrayonServices;
+
+use super::api::error::ApiError;
+
+/// Header of API responses in maintenance mode, with the message of the mode.
+pub const MAINTENANCE_HEADER: HeaderName = HeaderName::from_static("x-fabricia-maintenance");
+
+/// Paths, relative to the API, which mutate in maintenance mode.
+///
+/// The maintenance mode itself must be switchable, and GraphQL is read-only.
+const EXEMPT_PATHS: [&str; 2] = ["/admin/maintenance", "/graphql"];
+
+/// Middleware responding with 503 to mutating requests in maintenance mode.
+///
+/// Requests with safe methods are handled as usual. All responses in
+/// maintenance mode carry [`MAINTENANCE_HEADER`]. If the mode cannot be read,
+/// requests are handled as if not in maintenance.
+pub async fn maintenance_layer(
+	State(services): State<CrayonServices>,
+	request: Request,
+	next: Next,
+) -> Response {
+	let Some(message) = maintenance_message(&services).await else {
+		return next.run(request).await;
+	};
+
+	let safe = matches!(
+		*request.method(),
+		Method::GET | Method::HEAD | Method::OPTIONS
+	);
+	let exempt = EXEMPT_PATHS
+		.iter()
+		.any(|path| request.uri().path().starts_with(path));
+	let mut response = match safe || exempt {
+		true => next.run(request).await,
+		false => {
+			ApiError::CustomString(StatusCode::SERVICE_UNAVAILABLE, message.clone()).into_response()
+		}
+	};
+	let value = HeaderValue::from_str(&message).unwrap_or_else(|_| HeaderValue::from_static("1"));
+	response.headers_mut().insert(MAINTENANCE_HEADER, value);
+	response
+}
+
+/// Returns the message of the maintenance mode, [`None`] if not in maintenance
+/// or if the mode cannot be read.
+pub async fn maintenance_message(services: &CrayonServices) -> Option<String> {
+	let backend = services.backend().ok()?;
+	match backend.maintenance.message().await {
+		Ok(message) => message,
+		Err(error) => {
+			warn!(%error, "failed to read maintenance mode");
+			None
+		}
+	}
+}
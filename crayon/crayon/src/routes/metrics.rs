@@ -0,0 +1,157 @@
+//! Prometheus metrics.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use axum::{
+	extract::State,
+	http::header,
+	response::{IntoResponse, Response},
+};
+use fabricia_backend::redis::LockStats;
+use fabricia_crayon_api_model::job::{ApiJobLatencyStats, ApiJobQueueStats, ApiPercentiles};
+
+use crate::CrayonServices;
+
+use super::api::{
+	error::ApiResult,
+	job::{job_latency_stats, job_stats},
+};
+
+/// Serves metrics in the Prometheus text format.
+pub async fn get_metrics(State(services): State<CrayonServices>) -> ApiResult<Response> {
+	let stats = job_stats(&services, None).await?;
+	let mut output = String::new();
+	write_gauge(
+		&mut output,
+		"fabricia_job_queue_pending",
+		"Pending jobs in the queue.",
+		&stats,
+		|stats| stats.pending,
+	);
+	write_gauge(
+		&mut output,
+		"fabricia_job_queue_running",
+		"Running jobs in the queue.",
+		&stats,
+		|stats| stats.running,
+	);
+	let latency = job_latency_stats(&services).await?;
+	write_latency(
+		&mut output,
+		"fabricia_job_wait_seconds",
+		"Time of recently finished jobs from enqueueing to start.",
+		&latency,
+		|stats| stats.wait,
+	);
+	write_latency(
+		&mut output,
+		"fabricia_job_run_seconds",
+		"Time of recently finished jobs from start to finish.",
+		&latency,
+		|stats| Some(stats.run),
+	);
+	if let Some(redis) = services.backend()?.redis.as_ref() {
+		write_lock_stats(&mut output, &redis.lock_stats());
+	}
+	Ok((
+		[(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+		output,
+	)
+		.into_response())
+}
+
+fn write_gauge(
+	output: &mut String,
+	name: &str,
+	help: &str,
+	stats: &[ApiJobQueueStats],
+	value: impl Fn(&ApiJobQueueStats) -> u64,
+) {
+	let _ = writeln!(output, "# HELP {name} {help}");
+	let _ = writeln!(output, "# TYPE {name} gauge");
+	for stats in stats {
+		let _ = writeln!(
+			output,
+			"{name}{{kind=\"{}\",priority_band=\"{}\",namespace=\"{}\",branch=\"{}\"}} {}",
+			escape_label(&stats.kind),
+			stats.priority_band,
+			escape_label(stats.namespace.as_deref().unwrap_or_default()),
+			escape_label(stats.branch.as_deref().unwrap_or_default()),
+			value(stats),
+		);
+	}
+}
+
+/// Writes percentiles of latencies, labelled by the windows in seconds.
+fn write_latency(
+	output: &mut String,
+	name: &str,
+	help: &str,
+	stats: &[ApiJobLatencyStats],
+	percentiles: impl Fn(&ApiJobLatencyStats) -> Option<ApiPercentiles>,
+) {
+	let _ = writeln!(output, "# HELP {name} {help}");
+	let _ = writeln!(output, "# TYPE {name} gauge");
+	for stats in stats {
+		let Some(percentiles) = percentiles(stats) else {
+			continue;
+		};
+		let quantiles = [
+			("0.5", percentiles.p50_ms),
+			("0.95", percentiles.p95_ms),
+			("0.99", percentiles.p99_ms),
+		];
+		for (quantile, ms) in quantiles {
+			let _ = writeln!(
+				output,
+				"{name}{{kind=\"{}\",window=\"{}\",quantile=\"{quantile}\"}} {}",
+				escape_label(&stats.kind),
+				stats.window_secs,
+				ms as f64 / 1000.0,
+			);
+		}
+	}
+}
+
+/// A counter of lock statistics, with its name, help text and value.
+type LockCounter = (&'static str, &'static str, fn(&LockStats) -> f64);
+
+fn write_lock_stats(output: &mut String, stats: &BTreeMap<&'static str, LockStats>) {
+	let counters: [LockCounter; 4] = [
+		(
+			"fabricia_lock_acquired_total",
+			"Distributed locks acquired by this instance.",
+			|stats| stats.acquired as f64,
+		),
+		(
+			"fabricia_lock_contended_total",
+			"Distributed locks held by others on the first attempt.",
+			|stats| stats.contended as f64,
+		),
+		(
+			"fabricia_lock_timeouts_total",
+			"Attempts of acquiring distributed locks timed out.",
+			|stats| stats.timeouts as f64,
+		),
+		(
+			"fabricia_lock_wait_seconds_total",
+			"Time spent waiting for distributed locks.",
+			|stats| stats.wait_seconds,
+		),
+	];
+	for (name, help, value) in counters {
+		let _ = writeln!(output, "# HELP {name} {help}");
+		let _ = writeln!(output, "# TYPE {name} counter");
+		for (kind, stats) in stats {
+			let _ = writeln!(output, "{name}{{kind=\"{kind}\"}} {}", value(stats));
+		}
+	}
+}
+
+/// Escapes a label value in the Prometheus text format.
+fn escape_label(value: &str) -> String {
+	value
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+}
@@ -1,19 +1,57 @@
+use std::time::Duration;
+
 use anyhow::Result;
-use axum::{Router, routing::get};
+use axum::{Router, middleware, routing::get};
+use tower_http::compression::CompressionLayer;
 
-use crate::CrayonServices;
+use crate::{CrayonServices, config::Compression};
 
-mod api;
+pub(crate) mod api;
+mod limits;
+pub(crate) mod maintenance;
+mod metrics;
+#[cfg(feature = "oidc")]
+pub(crate) mod oidc;
+mod static_files;
+mod trace;
 
 pub fn make_router(services: CrayonServices) -> Result<Router> {
-	let router = Router::new()
-		.route("/", get(handler))
-		.nest("/api/v0", api::api_router())
+	let router = if services.config.web.static_dir.is_some() {
+		Router::new().fallback(static_files::serve_static)
+	} else {
+		Router::new().route("/", get(api::status::get_version))
+	};
+	let router = router
+		.nest(
+			"/api/v0",
+			api::api_router(&services.config.web).layer(middleware::from_fn_with_state(
+				services.clone(),
+				maintenance::maintenance_layer,
+			)),
+		)
+		.route("/metrics", get(metrics::get_metrics));
+	#[cfg(feature = "oidc")]
+	let router = router.merge(oidc::router());
+	let router = match services.config.web.request_timeout {
+		0 => router,
+		timeout => router.layer(middleware::from_fn_with_state(
+			Duration::from_secs(timeout),
+			limits::timeout_layer,
+		)),
+	};
+	let compression = &services.config.web.compression;
+	let router = if compression.is_empty() {
+		router
+	} else {
+		router.layer(
+			CompressionLayer::new()
+				.gzip(compression.contains(&Compression::Gzip))
+				.br(compression.contains(&Compression::Br)),
+		)
+	};
+	let router = router
+		.layer(middleware::from_fn(trace::trace_layer))
 		.with_state(services);
 
 	Ok(router)
 }
-
-async fn handler() -> &'static str {
-	concat!("Fabricia Crayon ", env!("CARGO_PKG_VERSION"))
-}
@@ -0,0 +1,538 @@
+//! OIDC login of the web UI, enabled by the `oidc` feature.
+//!
+//! Users are redirected to the provider with the authorization code flow and
+//! PKCE. Claims are fetched from the userinfo endpoint, and mapped to roles by
+//! [`OidcRoleRule`]s. Sessions are kept in signed cookies, so that no state is
+//! shared between instances.
+
+use std::{
+	collections::HashMap,
+	sync::LazyLock,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	Json, Router,
+	extract::{Query, State},
+	http::{HeaderMap, HeaderValue, StatusCode, header},
+	response::{AppendHeaders, IntoResponse, Redirect, Response},
+	routing::{get, post},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+use tracing::info;
+
+use crate::{
+	CrayonServices,
+	config::{OidcConfig, OidcRoleRule},
+};
+
+use super::api::error::{ApiError, ApiResult, OptionExt};
+
+/// Role granting access to admin routes, like admin tokens.
+pub const ADMIN_ROLE: &str = "admin";
+
+const SESSION_COOKIE: &str = "fabricia_session";
+const LOGIN_COOKIE: &str = "fabricia_login";
+/// Lifetime of login attempts in seconds.
+const LOGIN_TTL: u64 = 600;
+
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+static PROVIDER: OnceCell<ProviderMetadata> = OnceCell::const_new();
+
+pub fn router() -> Router<CrayonServices> {
+	Router::new()
+		.route("/auth/login", get(login))
+		.route("/auth/callback", get(callback))
+		.route("/auth/logout", post(logout))
+		.route("/auth/session", get(get_session))
+}
+
+/// A logged in user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+	/// Subject identifier of the user at the provider.
+	pub sub: String,
+	pub name: Option<String>,
+	pub roles: Vec<String>,
+	/// Expiration time in seconds since the Unix epoch.
+	pub exp: u64,
+}
+
+impl Session {
+	/// Returns the valid session of a request, if any.
+	pub fn from_headers(services: &CrayonServices, headers: &HeaderMap) -> Option<Self> {
+		let config = services.config.oidc.as_ref()?;
+		let session: Session = verify(config, get_cookie(headers, SESSION_COOKIE)?)?;
+		(session.exp > now()).then_some(session)
+	}
+
+	pub fn has_role(&self, role: &str) -> bool {
+		self.roles.iter().any(|r| r == role)
+	}
+}
+
+/// State of a login attempt, kept in a cookie until the callback.
+#[derive(Debug, Serialize, Deserialize)]
+struct LoginState {
+	state: String,
+	verifier: String,
+	exp: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+	authorization_endpoint: String,
+	token_endpoint: String,
+	userinfo_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+	access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+	code: Option<String>,
+	state: Option<String>,
+	error: Option<String>,
+}
+
+fn oidc_config(services: &CrayonServices) -> ApiResult<&OidcConfig> {
+	services
+		.config
+		.oidc
+		.as_ref()
+		.or_api_error(StatusCode::NOT_FOUND, "OIDC login is not configured")
+}
+
+async fn provider(config: &OidcConfig) -> ApiResult<&'static ProviderMetadata> {
+	PROVIDER
+		.get_or_try_init(|| async {
+			let url = format!(
+				"{}/.well-known/openid-configuration",
+				config.issuer_url.trim_end_matches('/')
+			);
+			fetch_json(HTTP_CLIENT.get(url)).await
+		})
+		.await
+}
+
+async fn fetch_json<T: DeserializeOwned>(request: reqwest::RequestBuilder) -> ApiResult<T> {
+	let provider_error =
+		|error: reqwest::Error| ApiError::CustomString(StatusCode::BAD_GATEWAY, error.to_string());
+	let body = request
+		.send()
+		.await
+		.and_then(|response| response.error_for_status())
+		.map_err(provider_error)?
+		.bytes()
+		.await
+		.map_err(provider_error)?;
+	serde_json::from_slice(&body)
+		.map_err(|error| ApiError::CustomString(StatusCode::BAD_GATEWAY, error.to_string()))
+}
+
+/// Redirects to the provider to log in.
+pub async fn login(State(services): State<CrayonServices>) -> ApiResult<Response> {
+	let config = oidc_config(&services)?;
+	let provider = provider(config).await?;
+
+	let login = LoginState {
+		state: random_string(),
+		verifier: random_string(),
+		exp: now() + LOGIN_TTL,
+	};
+	let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(login.verifier.as_bytes()));
+	let mut url = reqwest::Url::parse(&provider.authorization_endpoint)
+		.map_err(|error| ApiError::CustomString(StatusCode::BAD_GATEWAY, error.to_string()))?;
+	url.query_pairs_mut()
+		.append_pair("response_type", "code")
+		.append_pair("client_id", &config.client_id)
+		.append_pair("redirect_uri", &config.redirect_url)
+		.append_pair("scope", &config.scopes.join(" "))
+		.append_pair("state", &login.state)
+		.append_pair("code_challenge", &challenge)
+		.append_pair("code_challenge_method", "S256");
+
+	let cookie = set_cookie(config, LOGIN_COOKIE, &sign(config, &login)?, LOGIN_TTL);
+	Ok(([(header::SET_COOKIE, cookie)], Redirect::to(url.as_str())).into_response())
+}
+
+/// Completes a login and starts a session.
+pub async fn callback(
+	State(services): State<CrayonServices>,
+	headers: HeaderMap,
+	Query(query): Query<CallbackQuery>,
+) -> ApiResult<Response> {
+	let config = oidc_config(&services)?;
+	if let Some(error) = query.error {
+		return Err(ApiError::CustomString(StatusCode::UNAUTHORIZED, error));
+	}
+	let login: LoginState = get_cookie(&headers, LOGIN_COOKIE)
+		.and_then(|cookie| verify(config, cookie))
+		.filter(|login: &LoginState| login.exp > now())
+		.or_api_error(StatusCode::BAD_REQUEST, "login has expired")?;
+	if query.state.as_deref() != Some(login.state.as_str()) {
+		return Err(ApiError::CustomRef(
+			StatusCode::BAD_REQUEST,
+			"login state mismatched",
+		));
+	}
+	let code = query
+		.code
+		.or_api_error(StatusCode::BAD_REQUEST, "authorization code is missing")?;
+
+	let provider = provider(config).await?;
+	let mut form = vec![
+		("grant_type", "authorization_code"),
+		("code", code.as_str()),
+		("redirect_uri", config.redirect_url.as_str()),
+		("client_id", config.client_id.as_str()),
+		("code_verifier", login.verifier.as_str()),
+	];
+	if let Some(secret) = &config.client_secret {
+		form.push(("client_secret", secret.as_str()));
+	}
+	let token: TokenResponse =
+		fetch_json(HTTP_CLIENT.post(&provider.token_endpoint).form(&form)).await?;
+	let claims: HashMap<String, serde_json::Value> = fetch_json(
+		HTTP_CLIENT
+			.get(&provider.userinfo_endpoint)
+			.bearer_auth(&token.access_token),
+	)
+	.await?;
+
+	let session = Session {
+		sub: claims
+			.get("sub")
+			.and_then(|sub| sub.as_str())
+			.or_api_error(StatusCode::BAD_GATEWAY, "userinfo has no subject")?
+			.to_string(),
+		name: ["preferred_username", "name", "email"]
+			.iter()
+			.find_map(|claim| claims.get(*claim)?.as_str())
+			.map(str::to_string),
+		roles: map_roles(&config.role, &claims),
+		exp: now() + config.session_ttl,
+	};
+	info!(sub = %session.sub, roles = ?session.roles, "user logged in");
+
+	let cookie = set_cookie(
+		config,
+		SESSION_COOKIE,
+		&sign(config, &session)?,
+		config.session_ttl,
+	);
+	Ok((
+		AppendHeaders([
+			(header::SET_COOKIE, cookie),
+			(header::SET_COOKIE, set_cookie(config, LOGIN_COOKIE, "", 0)),
+		]),
+		Redirect::to("/"),
+	)
+		.into_response())
+}
+
+/// Ends the session.
+pub async fn logout(State(services): State<CrayonServices>) -> ApiResult<Response> {
+	let config = oidc_config(&services)?;
+	let cookie = set_cookie(config, SESSION_COOKIE, "", 0);
+	Ok(([(header::SET_COOKIE, cookie)], StatusCode::NO_CONTENT).into_response())
+}
+
+/// Returns the user of the session.
+pub async fn get_session(
+	State(services): State<CrayonServices>,
+	headers: HeaderMap,
+) -> ApiResult<Json<Session>> {
+	oidc_config(&services)?;
+	Session::from_headers(&services, &headers)
+		.map(Json)
+		.ok_or(ApiError::AuthRequired)
+}
+
+fn map_roles(rules: &[OidcRoleRule], claims: &HashMap<String, serde_json::Value>) -> Vec<String> {
+	let mut roles = Vec::new();
+	for rule in rules {
+		let matched = match claims.get(&rule.claim) {
+			Some(serde_json::Value::String(value)) => *value == rule.value,
+			Some(serde_json::Value::Array(values)) => values
+				.iter()
+				.any(|value| value.as_str() == Some(rule.value.as_str())),
+			_ => false,
+		};
+		if matched && !roles.contains(&rule.role) {
+			roles.push(rule.role.clone());
+		}
+	}
+	roles
+}
+
+fn mac(config: &OidcConfig) -> ApiResult<Hmac<Sha256>> {
+	hex::decode(&config.session_secret)
+		.ok()
+		.filter(|key| key.len() >= 32)
+		.and_then(|key| Hmac::new_from_slice(&key).ok())
+		.or_api_error(
+			StatusCode::INTERNAL_SERVER_ERROR,
+			"session secret must be at least 32 hex-encoded bytes",
+		)
+}
+
+/// Encodes a value as `<payload>.<signature>`, both in unpadded base64url.
+fn sign<T: Serialize>(config: &OidcConfig, value: &T) -> ApiResult<String> {
+	let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(value).map_err(|error| {
+		ApiError::CustomString(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+	})?);
+	let mut mac = mac(config)?;
+	mac.update(payload.as_bytes());
+	let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+	Ok(format!("{payload}.{signature}"))
+}
+
+/// Decodes a value signed by [`sign`].
+fn verify<T: DeserializeOwned>(config: &OidcConfig, signed: &str) -> Option<T> {
+	let (payload, signature) = signed.split_once('.')?;
+	let mut mac = mac(config).ok()?;
+	mac.update(payload.as_bytes());
+	mac.verify_slice(&URL_SAFE_NO_PAD.decode(signature).ok()?)
+		.ok()?;
+	serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()
+}
+
+fn get_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+	headers
+		.get_all(header::COOKIE)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(';'))
+		.filter_map(|pair| pair.trim().split_once('='))
+		.find_map(|(key, value)| (key == name).then_some(value))
+}
+
+fn set_cookie(config: &OidcConfig, name: &str, value: &str, max_age: u64) -> HeaderValue {
+	let secure = if config.redirect_url.starts_with("https://") {
+		"; Secure"
+	} else {
+		""
+	};
+	HeaderValue::from_str(&format!(
+		"{name}={value}; Path=/; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}"
+	))
+	.expect("cookies are made of visible ASCII characters")
+}
+
+fn random_string() -> String {
+	URL_SAFE_NO_PAD.encode(rand::rng().random::<[u8; 32]>())
+}
+
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+	use axum::{body::Body, http::Request};
+	use serde_json::json;
+
+	use crate::{
+		routes::api::auth::AuthRequired,
+		test::{MockApis, send},
+	};
+
+	use super::*;
+
+	fn test_config() -> OidcConfig {
+		OidcConfig {
+			issuer_url: "http://127.0.0.1:1".to_string(),
+			client_id: "fabricia".to_string(),
+			client_secret: None,
+			redirect_url: "http://localhost/auth/callback".to_string(),
+			scopes: vec!["openid".to_string()],
+			session_secret: "ab".repeat(32),
+			session_ttl: 3600,
+			role: vec![OidcRoleRule {
+				role: ADMIN_ROLE.to_string(),
+				claim: "groups".to_string(),
+				value: "admins".to_string(),
+			}],
+		}
+	}
+
+	fn test_services() -> CrayonServices {
+		let mut services = MockApis::default().with_admin_token().into_services();
+		services.config.oidc = Some(test_config());
+		services
+	}
+
+	fn cookie(name: &str, value: &str) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		headers.insert(
+			header::COOKIE,
+			HeaderValue::from_str(&format!("other=1; {name}={value}")).unwrap(),
+		);
+		headers
+	}
+
+	fn test_session(roles: &[&str], exp: u64) -> Session {
+		Session {
+			sub: "alice".to_string(),
+			name: None,
+			roles: roles.iter().map(|role| role.to_string()).collect(),
+			exp,
+		}
+	}
+
+	#[test]
+	fn test_sign() {
+		let config = test_config();
+		let session = test_session(&[], now() + 60);
+		let signed = sign(&config, &session).unwrap();
+		assert_eq!(verify::<Session>(&config, &signed), Some(session.clone()));
+
+		// tampered payload
+		let (_, signature) = signed.split_once('.').unwrap();
+		let forged = test_session(&[ADMIN_ROLE], now() + 60);
+		let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+		assert_eq!(
+			verify::<Session>(&config, &format!("{payload}.{signature}")),
+			None
+		);
+		// tampered signature
+		let (payload, signature) = signed.split_once('.').unwrap();
+		let first = match signature.starts_with('A') {
+			true => 'B',
+			false => 'A',
+		};
+		let tampered = format!("{payload}.{first}{}", &signature[1..]);
+		assert_eq!(verify::<Session>(&config, &tampered), None);
+		assert_eq!(verify::<Session>(&config, "garbage"), None);
+
+		// signed with another secret
+		let other = OidcConfig {
+			session_secret: "cd".repeat(32),
+			..test_config()
+		};
+		assert_eq!(verify::<Session>(&other, &signed), None);
+		// secrets shorter than 32 bytes are refused
+		let short = OidcConfig {
+			session_secret: "ab".repeat(16),
+			..test_config()
+		};
+		assert!(sign(&short, &session).is_err());
+	}
+
+	#[test]
+	fn test_session_expired() {
+		let services = test_services();
+		let config = test_config();
+
+		let valid = sign(&config, &test_session(&[], now() + 60)).unwrap();
+		let headers = cookie(SESSION_COOKIE, &valid);
+		assert!(Session::from_headers(&services, &headers).is_some());
+
+		let expired = sign(&config, &test_session(&[], now() - 1)).unwrap();
+		let headers = cookie(SESSION_COOKIE, &expired);
+		assert_eq!(Session::from_headers(&services, &headers), None);
+	}
+
+	#[test]
+	fn test_map_roles() {
+		let rules = [
+			test_config().role,
+			vec![OidcRoleRule {
+				role: "viewer".to_string(),
+				claim: "email_verified_domain".to_string(),
+				value: "example.com".to_string(),
+			}],
+		]
+		.concat();
+		let claims = |claims: serde_json::Value| serde_json::from_value(claims).unwrap();
+
+		assert_eq!(
+			map_roles(&rules, &claims(json!({ "groups": ["users", "admins"] }))),
+			[ADMIN_ROLE]
+		);
+		assert_eq!(
+			map_roles(&rules, &claims(json!({ "groups": "admins" }))),
+			[ADMIN_ROLE]
+		);
+		assert_eq!(
+			map_roles(
+				&rules,
+				&claims(json!({ "groups": ["users"], "email_verified_domain": "example.com" }))
+			),
+			["viewer"]
+		);
+		// values are matched exactly
+		assert!(map_roles(&rules, &claims(json!({ "groups": ["Admins", "admin"] }))).is_empty());
+		assert!(map_roles(&rules, &claims(json!({ "groups": { "admins": true } }))).is_empty());
+		assert!(map_roles(&rules, &claims(json!({}))).is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_admin_session() {
+		let services = test_services();
+		let config = test_config();
+
+		let admin = sign(&config, &test_session(&[ADMIN_ROLE], now() + 60)).unwrap();
+		let headers = cookie(SESSION_COOKIE, &admin);
+		assert!(AuthRequired::authorize(&services, &headers).await.is_ok());
+
+		let user = sign(&config, &test_session(&["viewer"], now() + 60)).unwrap();
+		let headers = cookie(SESSION_COOKIE, &user);
+		assert!(AuthRequired::authorize(&services, &headers).await.is_err());
+
+		let expired = sign(&config, &test_session(&[ADMIN_ROLE], now() - 1)).unwrap();
+		let headers = cookie(SESSION_COOKIE, &expired);
+		assert!(AuthRequired::authorize(&services, &headers).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_callback_state() {
+		let config = test_config();
+		let callback = |query: &str, login: &LoginState| {
+			Request::get(format!("/auth/callback?{query}"))
+				.header(
+					header::COOKIE,
+					format!("{LOGIN_COOKIE}={}", sign(&config, login).unwrap()),
+				)
+				.body(Body::empty())
+				.unwrap()
+		};
+		let login = LoginState {
+			state: "expected".to_string(),
+			verifier: "verifier".to_string(),
+			exp: now() + 60,
+		};
+
+		// the provider is never contacted in the cases below
+		let response = send(test_services(), callback("code=c&state=other", &login)).await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+		let response = send(test_services(), callback("code=c", &login)).await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+		let response = send(test_services(), callback("state=expected", &login)).await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+		let expired = LoginState {
+			exp: now() - 1,
+			..login
+		};
+		let response = send(test_services(), callback("code=c&state=expected", &expired)).await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+		let request = Request::get("/auth/callback?code=c&state=expected")
+			.body(Body::empty())
+			.unwrap();
+		let response = send(test_services(), request).await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+	}
+}
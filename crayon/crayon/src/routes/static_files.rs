@@ -0,0 +1,252 @@
+//! Static files serving for the web UI.
+
+use std::{
+	hash::{DefaultHasher, Hash, Hasher},
+	path::{Component, Path, PathBuf},
+	time::UNIX_EPOCH,
+};
+
+use axum::{
+	body::Body,
+	extract::State,
+	http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header},
+	response::{IntoResponse, Response},
+};
+use tracing::warn;
+
+use crate::CrayonServices;
+
+const INDEX_FILE: &str = "index.html";
+
+/// Serves files in the static directory.
+///
+/// Requests to unknown paths are served with the root `index.html`,
+/// so that routing of single-page applications works.
+pub async fn serve_static(
+	State(services): State<CrayonServices>,
+	method: Method,
+	uri: Uri,
+	headers: HeaderMap,
+) -> Response {
+	if method != Method::GET && method != Method::HEAD {
+		return StatusCode::METHOD_NOT_ALLOWED.into_response();
+	}
+	let web = &services.config.web;
+	let Some(root) = &web.static_dir else {
+		return StatusCode::NOT_FOUND.into_response();
+	};
+
+	let path = uri.path().trim_start_matches('/');
+	if path.starts_with("api/") {
+		return StatusCode::NOT_FOUND.into_response();
+	}
+	let Some(path) = sanitize_path(path) else {
+		return StatusCode::BAD_REQUEST.into_response();
+	};
+
+	let mut file = root.join(&path);
+	if file.is_dir() {
+		file.push(INDEX_FILE);
+	}
+	if !file.is_file() {
+		file = root.join(INDEX_FILE);
+	}
+	let cache_control = if file.file_name().is_some_and(|name| name == INDEX_FILE) {
+		"no-cache".to_string()
+	} else {
+		format!("public, max-age={}", web.static_max_age)
+	};
+
+	match serve_file(&file, &headers, method == Method::HEAD).await {
+		Ok(mut response) => {
+			if let Ok(value) = HeaderValue::from_str(&cache_control) {
+				response.headers_mut().insert(header::CACHE_CONTROL, value);
+			}
+			response
+		}
+		Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+			StatusCode::NOT_FOUND.into_response()
+		}
+		Err(error) => {
+			warn!(?file, %error, "failed to serve static file");
+			StatusCode::INTERNAL_SERVER_ERROR.into_response()
+		}
+	}
+}
+
+/// Converts a request path into a relative path,
+/// rejecting paths escaping from the static directory.
+fn sanitize_path(path: &str) -> Option<PathBuf> {
+	let mut result = PathBuf::new();
+	for component in Path::new(path).components() {
+		match component {
+			Component::Normal(name) => result.push(name),
+			Component::CurDir => {}
+			Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+		}
+	}
+	Some(result)
+}
+
+async fn serve_file(file: &Path, headers: &HeaderMap, head: bool) -> std::io::Result<Response> {
+	let metadata = tokio::fs::metadata(file).await?;
+	let etag = make_etag(&metadata);
+	let not_modified = headers
+		.get(header::IF_NONE_MATCH)
+		.and_then(|value| value.to_str().ok())
+		.is_some_and(|value| {
+			value
+				.split(',')
+				.any(|tag| tag.trim() == etag || tag.trim() == "*")
+		});
+
+	let builder = Response::builder()
+		.header(header::ETAG, &etag)
+		.header(header::CONTENT_TYPE, content_type(file));
+	let response = if not_modified {
+		builder.status(StatusCode::NOT_MODIFIED).body(Body::empty())
+	} else if head {
+		builder
+			.header(header::CONTENT_LENGTH, metadata.len())
+			.body(Body::empty())
+	} else {
+		builder.body(Body::from(tokio::fs::read(file).await?))
+	};
+	Ok(response.unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response()))
+}
+
+/// Makes a weak entity tag from file size and modification time.
+fn make_etag(metadata: &std::fs::Metadata) -> String {
+	let mut hasher = DefaultHasher::new();
+	metadata.len().hash(&mut hasher);
+	if let Ok(modified) = metadata.modified() {
+		if let Ok(modified) = modified.duration_since(UNIX_EPOCH) {
+			modified.as_nanos().hash(&mut hasher);
+		}
+	}
+	format!("W/\"{:016x}\"", hasher.finish())
+}
+
+fn content_type(file: &Path) -> &'static str {
+	let extension = file
+		.extension()
+		.and_then(|ext| ext.to_str())
+		.unwrap_or_default()
+		.to_ascii_lowercase();
+	match extension.as_str() {
+		"html" | "htm" => "text/html; charset=utf-8",
+		"js" | "mjs" => "text/javascript; charset=utf-8",
+		"css" => "text/css; charset=utf-8",
+		"json" | "map" => "application/json",
+		"txt" => "text/plain; charset=utf-8",
+		"svg" => "image/svg+xml",
+		"png" => "image/png",
+		"jpg" | "jpeg" => "image/jpeg",
+		"gif" => "image/gif",
+		"webp" => "image/webp",
+		"ico" => "image/x-icon",
+		"woff" => "font/woff",
+		"woff2" => "font/woff2",
+		"wasm" => "application/wasm",
+		_ => "application/octet-stream",
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::env;
+
+	use axum::{
+		body::{Body, to_bytes},
+		http::Request,
+	};
+	use uuid::Uuid;
+
+	use crate::test::{MockApis, send};
+
+	use super::*;
+
+	async fn get(services: &CrayonServices, uri: &str, headers: &[(&str, &str)]) -> Response {
+		let mut request = Request::get(uri);
+		for (name, value) in headers {
+			request = request.header(*name, *value);
+		}
+		send(services.clone(), request.body(Body::empty()).unwrap()).await
+	}
+
+	async fn text(response: Response) -> String {
+		let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+		String::from_utf8(body.to_vec()).unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_serve_static() {
+		let root = env::temp_dir().join(format!("fabricia-web-{}", Uuid::now_v7()));
+		std::fs::create_dir_all(root.join("assets")).unwrap();
+		std::fs::write(root.join(INDEX_FILE), "<app>").unwrap();
+		std::fs::write(root.join("assets/app.js"), "main()").unwrap();
+		let mut services = MockApis::default().into_services();
+		services.config.web.static_dir = Some(root.clone());
+		services.config.web.static_max_age = 600;
+
+		let response = get(&services, "/assets/app.js", &[]).await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			response.headers()[header::CONTENT_TYPE],
+			"text/javascript; charset=utf-8"
+		);
+		assert_eq!(
+			response.headers()[header::CACHE_CONTROL],
+			"public, max-age=600"
+		);
+		let etag = response.headers()[header::ETAG]
+			.to_str()
+			.unwrap()
+			.to_string();
+		assert_eq!(text(response).await, "main()");
+
+		let response = get(
+			&services,
+			"/assets/app.js",
+			&[("if-none-match", etag.as_str())],
+		)
+		.await;
+		assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+		// unknown paths of the single-page application fall back to the index
+		for uri in ["/", "/assets", "/branch/main/packages"] {
+			let response = get(&services, uri, &[]).await;
+			assert_eq!(response.status(), StatusCode::OK, "{uri}");
+			assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+			assert_eq!(
+				response.headers()[header::CONTENT_TYPE],
+				"text/html; charset=utf-8"
+			);
+			assert_eq!(text(response).await, "<app>");
+		}
+
+		// but unknown API paths do not
+		let response = get(&services, "/api/v1/branch", &[]).await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+		let response = send(
+			services.clone(),
+			Request::post("/branch").body(Body::empty()).unwrap(),
+		)
+		.await;
+		assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+		std::fs::remove_dir_all(root).unwrap();
+	}
+
+	#[test]
+	fn test_sanitize_path() {
+		assert_eq!(
+			sanitize_path("assets/./app.js"),
+			Some(PathBuf::from("assets/app.js"))
+		);
+		assert_eq!(sanitize_path(""), Some(PathBuf::new()));
+		assert_eq!(sanitize_path("assets/../../secret"), None);
+		assert_eq!(sanitize_path("/etc/passwd"), None);
+	}
+}
@@ -0,0 +1,39 @@
+//! Propagation of trace contexts of requests.
+
+use axum::{
+	extract::Request,
+	http::{HeaderName, HeaderValue},
+	middleware::Next,
+	response::Response,
+};
+use fabricia_backend::trace::TraceContext;
+use tracing::{Instrument, info_span};
+
+static TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+
+/// Middleware continuing the trace in `traceparent` of a request.
+///
+/// A new trace is started if the request carries no valid `traceparent`.
+/// The context is returned in `traceparent` of the response, and recorded
+/// into jobs enqueued while handling the request.
+pub async fn trace_layer(request: Request, next: Next) -> Response {
+	let trace = request
+		.headers()
+		.get(&TRACEPARENT)
+		.and_then(|value| value.to_str().ok())
+		.and_then(TraceContext::parse)
+		.map(|trace| trace.child())
+		.unwrap_or_else(TraceContext::new_root);
+	let span = info_span!(
+		"request",
+		method = %request.method(),
+		uri = %request.uri(),
+		trace_id = trace.trace_id_hex(),
+	);
+
+	let mut response = trace.scope(next.run(request)).instrument(span).await;
+	if let Ok(value) = HeaderValue::from_str(&trace.to_string()) {
+		response.headers_mut().insert(TRACEPARENT.clone(), value);
+	}
+	response
+}
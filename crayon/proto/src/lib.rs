@@ -0,0 +1,5 @@
+//! Protobuf definitions of the Crayon gRPC API.
+//!
+//! Shared by the server in Crayon and by clients, e.g. CI integrations.
+
+tonic::include_proto!("fabricia.crayon.v0");